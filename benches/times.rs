@@ -1,7 +1,7 @@
 use std::fs::{self, File};
 use std::io::Write;
 
-use symflow::Program;
+use symflow::{fixtures, Program};
 use symflow::flow::{ControlFlowGraph, DataDependencyGraph};
 use symflow::timings;
 
@@ -14,7 +14,7 @@ fn main() {
 }
 
 fn bench(filename: &str) {
-    let path = format!("target/bin/{}", filename);
+    let path = fixtures::fixture(filename);
 
     timings::reset();
 
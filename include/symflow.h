@@ -0,0 +1,73 @@
+/* C interface of symflow, built with
+ * `cargo rustc --release --features cdylib --crate-type cdylib`.
+ *
+ * Functions filling arrays or buffers return the size of the whole result,
+ * so a capacity of zero queries it. Failing functions return NULL or
+ * SIZE_MAX and leave a message for symflow_last_error. */
+
+#ifndef SYMFLOW_H
+#define SYMFLOW_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SYMFLOW_CFG 0u
+#define SYMFLOW_DDG 1u
+#define SYMFLOW_PDG 2u
+
+#define SYMFLOW_DOT 0u
+#define SYMFLOW_JSON 1u
+
+/* The kinds of edges in SymflowEdge. */
+#define SYMFLOW_EDGE_CONTROL_FLOW 0u
+#define SYMFLOW_EDGE_DATA_DEPENDENCY 1u
+#define SYMFLOW_EDGE_CALL 2u
+#define SYMFLOW_EDGE_RETURN 3u
+#define SYMFLOW_EDGE_PARAM_IN 4u
+#define SYMFLOW_EDGE_PARAM_OUT 5u
+#define SYMFLOW_EDGE_SUMMARY 6u
+#define SYMFLOW_EDGE_INTER_THREAD 7u
+#define SYMFLOW_EDGE_SYNCHRONIZATION 8u
+#define SYMFLOW_EDGE_KINDS 9u
+
+typedef struct SymflowProgram SymflowProgram;
+typedef struct SymflowGraphs SymflowGraphs;
+
+typedef struct SymflowNode {
+    uint64_t id;
+    uint64_t addr;
+    uint32_t kind;
+} SymflowNode;
+
+typedef struct SymflowEdge {
+    uint64_t start;
+    uint64_t end;
+    uint32_t kind;
+} SymflowEdge;
+
+const char *symflow_last_error(void);
+
+SymflowProgram *symflow_program_load(const char *path);
+void symflow_program_free(SymflowProgram *program);
+
+SymflowGraphs *symflow_graphs_build(const SymflowProgram *program);
+void symflow_graphs_free(SymflowGraphs *graphs);
+
+size_t symflow_nodes(const SymflowGraphs *graphs, uint32_t graph,
+                     SymflowNode *out, size_t capacity);
+size_t symflow_edges(const SymflowGraphs *graphs, uint32_t graph,
+                     SymflowEdge *out, size_t capacity);
+size_t symflow_slice(const SymflowGraphs *graphs, uint64_t addr, int forward,
+                     uint64_t *out, size_t capacity);
+size_t symflow_export(const SymflowGraphs *graphs, uint32_t graph, uint32_t format,
+                      char *buffer, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,313 @@
+//! Analysis of a whole corpus of binaries.
+//!
+//! A batch walks a directory for ELF files and analyzes each of them with the
+//! same configuration and limits. The artifacts of a binary are written to a
+//! directory mirroring its path below the output directory:
+//! - `cfg.json`: The control flow graph in angr's model.
+//! - `pdg.json`: The nodes and edges of the program dependence graph.
+//! - `pdg.dot`: The program dependence graph for graphviz.
+//!
+//! The manifest `manifest.json` in the output directory records the seed of
+//! the analysis and lists every binary with its status, the measurements of
+//! its phases and the error if it was not analyzed. The memory of a binary is
+//! the peak resident memory while it was analyzed, which is only measured on
+//! Linux. Failing or cancelled binaries do not stop the batch.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Program;
+use crate::flow::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+use crate::flow::{AnalysisStats, CancellationToken, Monitor, Phase, Report};
+use crate::helper::{json_string, panic_message};
+use crate::sym::SymConfig;
+
+
+/// The shared settings of a batch.
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    /// The configuration of every analysis. Its progress callback receives
+    /// the reports of all binaries, but its cancellation token is replaced by
+    /// one per binary.
+    pub config: SymConfig,
+    /// The wall time after which the analysis of a binary is cancelled.
+    pub time_limit: Option<Duration>,
+    /// The number of blocks or nodes of any phase after which the analysis
+    /// of a binary is cancelled.
+    pub node_limit: Option<usize>,
+}
+
+/// The outcome of analyzing one binary.
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub path: PathBuf,
+    /// The directory with the artifacts.
+    pub artifacts: PathBuf,
+    pub status: Status,
+    /// The measurements of the phases that ran.
+    pub stats: AnalysisStats,
+    /// The wall time of the whole analysis including loading.
+    pub time: Duration,
+    /// Why the binary was not analyzed.
+    pub error: Option<String>,
+}
+
+/// Whether a binary was analyzed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Status {
+    Analyzed,
+    /// The analysis exceeded a limit.
+    Cancelled,
+    /// Loading, analyzing or writing the artifacts failed.
+    Failed,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.pad(match self {
+            Status::Analyzed => "ok",
+            Status::Cancelled => "cancelled",
+            Status::Failed => "failed",
+        })
+    }
+}
+
+impl Batch {
+    /// Create a batch with the configuration and without limits.
+    pub fn new(config: SymConfig) -> Batch {
+        Batch { config, time_limit: None, node_limit: None }
+    }
+
+    /// Analyze all ELF files below the input directory in the order of their
+    /// paths and write the artifacts and the manifest to the output
+    /// directory. Only walking the input and writing the manifest can fail.
+    pub fn run(&self, input: &Path, output: &Path) -> io::Result<Vec<BatchEntry>> {
+        let mut binaries = Vec::new();
+        find_binaries(input, &mut binaries)?;
+        binaries.sort();
+
+        let entries: Vec<BatchEntry> = binaries.iter()
+            .map(|path| {
+                let relative = path.strip_prefix(input).unwrap_or(path);
+                self.analyze(path, &output.join(relative))
+            })
+            .collect();
+
+        fs::create_dir_all(output)?;
+        let mut manifest = BufWriter::new(File::create(output.join("manifest.json"))?);
+        write_manifest(&mut manifest, self.config.seed, &entries)?;
+        manifest.flush()?;
+        Ok(entries)
+    }
+
+    /// Analyze a single binary and write its artifacts to the directory.
+    pub fn analyze(&self, path: &Path, artifacts: &Path) -> BatchEntry {
+        let started = Instant::now();
+        let mut stats = AnalysisStats::new();
+        let measured = stats.reset_memory();
+
+        // Cancel the analysis when a limit is exceeded, remembering which.
+        let reason = Arc::new(Mutex::new(None));
+        let token = CancellationToken::new();
+        let mut config = self.config.clone();
+        let forward = config.monitor.progress.take();
+        let (limit, limit_reason, limit_token) = (self.node_limit, reason.clone(), token.clone());
+        let time_limit = self.time_limit;
+        config.monitor = Monitor {
+            progress: Some(Arc::new(move |report: Report| {
+                if let Some(progress) = &forward {
+                    progress.report(report);
+                }
+                // The time limit is also checked here, so that it holds
+                // between the wake-ups of the watchdog.
+                let exceeded = match (limit, time_limit) {
+                    (Some(limit), _) if report.done > limit => {
+                        let phase = match report.phase {
+                            Phase::ControlFlow => "control flow",
+                            Phase::DataDependency => "data dependency",
+                            Phase::ProgramDependence => "program dependence",
+                        };
+                        format!("node limit of {} exceeded in {} phase", limit, phase)
+                    },
+                    (_, Some(time)) if started.elapsed() >= time => {
+                        format!("time limit of {:.2?} exceeded", time)
+                    },
+                    _ => return,
+                };
+                limit_reason.lock().unwrap().get_or_insert(exceeded);
+                limit_token.cancel();
+            })),
+            cancellation: token.clone(),
+        };
+
+        // The watchdog stops waiting when the sender is dropped.
+        let (finished, watched) = mpsc::channel::<()>();
+        if let Some(limit) = self.time_limit {
+            let (reason, token) = (reason.clone(), token.clone());
+            thread::spawn(move || {
+                if watched.recv_timeout(limit) == Err(mpsc::RecvTimeoutError::Timeout) {
+                    reason.lock().unwrap().get_or_insert_with(|| {
+                        format!("time limit of {:.2?} exceeded", limit)
+                    });
+                    token.cancel();
+                }
+            });
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), String> {
+            let program = Program::new(path);
+            let cfg = stats.measure("cfg", || ControlFlowGraph::try_with_config(&program, config))
+                .map_err(|err| err.to_string())?;
+            let ddg = stats.measure("ddg", || DataDependencyGraph::try_new(&cfg))
+                .map_err(|err| err.to_string())?;
+            let pdg = stats.measure("pdg", || ProgramDependenceGraph::try_new(&cfg, &ddg))
+                .map_err(|err| err.to_string())?;
+            write_artifacts(artifacts, &cfg, &pdg).map_err(|err| err.to_string())
+        }));
+        drop(finished);
+
+        let (status, error) = match result {
+            Ok(Ok(())) => (Status::Analyzed, None),
+            Ok(Err(err)) => match reason.lock().unwrap().take() {
+                Some(reason) => (Status::Cancelled, Some(reason)),
+                None => (Status::Failed, Some(err)),
+            },
+            Err(payload) => (Status::Failed, Some(panic_message(&*payload))),
+        };
+
+        // Without a reset, the peak would be that of the whole batch so far.
+        if !measured {
+            stats.memory = None;
+        }
+
+        BatchEntry {
+            path: path.to_path_buf(),
+            artifacts: artifacts.to_path_buf(),
+            status,
+            stats,
+            time: started.elapsed(),
+            error,
+        }
+    }
+}
+
+/// Write the manifest of the entries analyzed with the seed as JSON.
+pub fn write_manifest<W: Write>(mut f: W, seed: u64, entries: &[BatchEntry]) -> io::Result<()> {
+    let path = |path: &Path| json_string(&path.display().to_string());
+
+    writeln!(f, "{{")?;
+    writeln!(f, "  \"seed\": {},", seed)?;
+    writeln!(f, "  \"binaries\": [")?;
+    for (index, entry) in entries.iter().enumerate() {
+        let phases: Vec<String> = entry.stats.phases.iter()
+            .map(|phase| format!(
+                "{{\"name\": \"{}\", \"time\": {:.6}, \"queries\": {}, \"query_time\": {:.6}, \
+                 \"nodes\": {}, \"edges\": {}}}",
+                phase.name, phase.time.as_secs_f64(), phase.solver.queries,
+                phase.solver.time.as_secs_f64(), phase.nodes, phase.edges,
+            ))
+            .collect();
+        let memory = entry.stats.memory.map_or("null".to_string(), |bytes| bytes.to_string());
+        let error = entry.error.as_deref().map_or("null".to_string(), json_string);
+        let separator = if index + 1 < entries.len() { "," } else { "" };
+        writeln!(f, "    {{\"path\": {}, \"artifacts\": {}, \"status\": \"{}\", \"time\": {:.6}, \
+                     \"memory\": {}, \"error\": {}, \"phases\": [{}]}}{}",
+                 path(&entry.path), path(&entry.artifacts), entry.status,
+                 entry.time.as_secs_f64(), memory, error, phases.join(", "), separator)?;
+    }
+    writeln!(f, "  ]")?;
+    writeln!(f, "}}")
+}
+
+/// Write the artifacts of an analyzed binary to the directory.
+pub fn write_artifacts(
+    dir: &Path,
+    cfg: &ControlFlowGraph,
+    pdg: &ProgramDependenceGraph,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    cfg.write_angr_json(BufWriter::new(File::create(dir.join("cfg.json"))?))?;
+    pdg.write_json(BufWriter::new(File::create(dir.join("pdg.json"))?))?;
+    pdg.visualize(BufWriter::new(File::create(dir.join("pdg.dot"))?), "Program Dependence Graph")
+}
+
+/// Collect the files below the directory that start with the ELF magic.
+fn find_binaries(dir: &Path, binaries: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_binaries(&path, binaries)?;
+        } else if is_elf(&path) {
+            binaries.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the file starts with the ELF magic.
+fn is_elf(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok()
+        && &magic == b"\x7fELF"
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::json::Json;
+    use super::*;
+
+    #[test]
+    fn batch() {
+        let input = std::env::temp_dir().join(format!("symflow-batch-{}", std::process::id()));
+        let output = input.join("out");
+        fs::create_dir_all(input.join("sub")).unwrap();
+        fs::write(input.join("notes.txt"), "not a binary").unwrap();
+        fs::write(input.join("sub/broken"), b"\x7fELF\x02\x01").unwrap();
+
+        let config = SymConfig { seed: 7, .. SymConfig::default() };
+        let entries = Batch::new(config).run(&input, &output).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status, Status::Failed);
+        assert_eq!(entries[0].artifacts, output.join("sub/broken"));
+        assert!(entries[0].error.is_some());
+
+        let manifest = fs::read_to_string(output.join("manifest.json")).unwrap();
+        let manifest = Json::parse(&manifest).unwrap();
+        assert_eq!(manifest.get("seed").and_then(Json::as_u64), Some(7));
+        let binaries = manifest.get("binaries").and_then(Json::as_array).unwrap();
+        assert_eq!(binaries[0].get("status").and_then(Json::as_str), Some("failed"));
+        fs::remove_dir_all(&input).unwrap();
+    }
+
+    #[test]
+    fn limits() {
+        let dir = std::env::temp_dir().join(format!("symflow-limits-{}", std::process::id()));
+        let input = dir.join("in");
+        fs::create_dir_all(&input).unwrap();
+        fs::copy(crate::fixtures::fixture("bufs"), input.join("bufs")).unwrap();
+
+        let batch = Batch { node_limit: Some(1), .. Batch::default() };
+        let entries = batch.run(&input, &dir.join("nodes")).unwrap();
+        assert_eq!(entries[0].status, Status::Cancelled);
+        let error = entries[0].error.as_deref().unwrap();
+        assert_eq!(error, "node limit of 1 exceeded in control flow phase");
+
+        let batch = Batch { time_limit: Some(Duration::from_secs(0)), .. Batch::default() };
+        let entries = batch.run(&input, &dir.join("time")).unwrap();
+        assert_eq!(entries[0].status, Status::Cancelled);
+        assert!(entries[0].error.as_deref().unwrap().starts_with("time limit of"));
+
+        // Without limits the binary is analyzed and its memory measured.
+        let entries = Batch::default().run(&input, &dir.join("all")).unwrap();
+        assert_eq!(entries[0].status, Status::Analyzed);
+        assert_eq!(entries[0].stats.memory.is_some(), cfg!(target_os = "linux"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
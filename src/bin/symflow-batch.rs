@@ -0,0 +1,67 @@
+//! Analyze all binaries below a directory (see `symflow::batch`).
+//!
+//! Usage: `symflow-batch <input> <output> [--time-limit <seconds>]
+//! [--node-limit <count>] [--seed <seed>] [--scope <file>]`
+
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use symflow::batch::{Batch, Status};
+use symflow::flow::AnalysisScope;
+use symflow::sym::SymConfig;
+
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut paths = Vec::new();
+    let mut batch = Batch::new(SymConfig::default());
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--time-limit" => {
+                let seconds = iter.next().and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage());
+                batch.time_limit = Some(Duration::from_secs_f64(seconds));
+            },
+            "--node-limit" => {
+                let count = iter.next().and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage());
+                batch.node_limit = Some(count);
+            },
+            "--seed" => {
+                let seed = iter.next().and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage());
+                batch.config.seed = seed;
+            },
+            "--scope" => {
+                let path = iter.next().unwrap_or_else(|| usage());
+                batch.config.scope = AnalysisScope::load(path).unwrap_or_else(|err| {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                });
+            },
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        usage();
+    }
+
+    let entries = batch.run(Path::new(paths[0]), Path::new(paths[1])).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    for entry in &entries {
+        println!("{:<9} {:>10.2?}  {}", entry.status, entry.time, entry.path.display());
+    }
+    let analyzed = entries.iter().filter(|entry| entry.status == Status::Analyzed).count();
+    println!("analyzed {} of {} binaries", analyzed, entries.len());
+}
+
+fn usage() -> ! {
+    eprintln!("usage: symflow-batch <input> <output> \
+               [--time-limit <seconds>] [--node-limit <count>] [--seed <seed>] \
+               [--scope <file>]");
+    process::exit(2);
+}
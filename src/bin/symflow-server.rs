@@ -0,0 +1,29 @@
+//! Serve analyses over JSON-RPC (see `symflow::server`) on the standard
+//! streams or, given an address like `127.0.0.1:7878`, on TCP connections
+//! which are answered one after another.
+
+use std::io::{self, BufReader};
+use std::net::TcpListener;
+use symflow::server::Server;
+
+
+fn main() -> io::Result<()> {
+    let mut server = Server::new();
+    match std::env::args().nth(1) {
+        Some(address) => {
+            let listener = TcpListener::bind(&address)?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let input = BufReader::new(stream.try_clone()?);
+                if let Err(err) = server.serve(input, stream) {
+                    eprintln!("connection failed: {}", err);
+                }
+            }
+            Ok(())
+        },
+        None => {
+            let stdin = io::stdin();
+            server.serve(stdin.lock(), io::stdout())
+        },
+    }
+}
@@ -0,0 +1,59 @@
+//! Re-analyze a binary whenever it changes (see `symflow::watch`) and write
+//! the graphs of each rebuilt function like `symflow-batch` does into a
+//! directory named after the function.
+//!
+//! Usage: `symflow-watch <binary> <output> [--interval <milliseconds>]`
+
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use symflow::batch::write_artifacts;
+use symflow::watch::Watcher;
+
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (binary, output) = match (args.get(0), args.get(1)) {
+        (Some(binary), Some(output)) => (binary, Path::new(output)),
+        _ => usage(),
+    };
+
+    let mut watcher = Watcher::new(binary);
+    match args.get(2).map(String::as_str) {
+        Some("--interval") => {
+            let millis = args.get(3).and_then(|value| value.parse().ok())
+                .unwrap_or_else(|| usage());
+            watcher.interval = Duration::from_millis(millis);
+        },
+        Some(_) => usage(),
+        None => {},
+    }
+
+    println!("watching {}", binary);
+    watcher.watch(|update| match update {
+        Ok((cache, changes)) => {
+            let list = |names: &std::collections::BTreeSet<String>| {
+                names.iter().cloned().collect::<Vec<_>>().join(", ")
+            };
+            println!("changed: [{}] added: [{}] removed: [{}]",
+                     list(&changes.changed), list(&changes.added), list(&changes.removed));
+            print!("{}", cache.stats);
+            for name in &cache.rebuilt {
+                let fragment = &cache.fragments[name];
+                let dir = output.join(name);
+                if let Err(err) = write_artifacts(&dir, &fragment.cfg, &fragment.pdg) {
+                    eprintln!("error: {}", err);
+                }
+            }
+            for name in &changes.removed {
+                let _ = std::fs::remove_dir_all(output.join(name));
+            }
+        },
+        Err(err) => eprintln!("error: {}", err),
+    })
+}
+
+fn usage() -> ! {
+    eprintln!("usage: symflow-watch <binary> <output> [--interval <milliseconds>]");
+    process::exit(2);
+}
@@ -0,0 +1,769 @@
+//! Parsing of the source variables in the debugging information.
+//!
+//! Only what is needed to find the values of variables is read from the
+//! `.debug_info` section: their names, the sizes of their types and their
+//! locations. A location is either a single expression valid in the whole
+//! function or a location list, in `.debug_loc` before DWARF 5 and in
+//! `.debug_loclists` since, whose expressions are valid in address ranges.
+//! Expressions doing more than naming a single place are left out.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, LE};
+
+use crate::math::DataType;
+use crate::unwind::{read_uleb, read_sleb};
+use crate::x86_64::Register;
+
+
+/// A variable of the source program with an integer value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub data_type: DataType,
+    /// Where the variable is stored in which ranges of addresses.
+    pub locations: Vec<VariableLocation>,
+}
+
+/// Where a variable is stored while the program is in a range of addresses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct VariableLocation {
+    pub start: u64,
+    /// The address after the range.
+    pub end: u64,
+    pub place: Place,
+}
+
+/// A place storing the value of a variable.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Place {
+    /// At an offset from the canonical frame address, which is the value of
+    /// the stack pointer before the call of the function.
+    Frame(i64),
+    /// At an offset from the frame pointer, where the function sets it up.
+    FramePointer(i64),
+    /// In a register.
+    Register(Register),
+    /// At a fixed address.
+    Static(u64),
+}
+
+impl Variable {
+    /// Where the variable is stored at the address if it is in scope.
+    pub fn place_at(&self, addr: u64) -> Option<Place> {
+        self.locations.iter()
+            .find(|location| location.start <= addr && addr < location.end)
+            .map(|location| location.place)
+    }
+}
+
+/// The contents of the sections with debugging information, which are empty
+/// if the sections are missing.
+#[derive(Debug, Clone, Default)]
+pub struct DebugSections {
+    pub info: Vec<u8>,
+    pub abbrev: Vec<u8>,
+    pub str: Vec<u8>,
+    pub line_str: Vec<u8>,
+    pub addr: Vec<u8>,
+    pub loc: Vec<u8>,
+    pub loclists: Vec<u8>,
+}
+
+/// Find the variables with integer values in all compilation units.
+pub fn parse_variables(sections: &DebugSections) -> DwarfResult<Vec<Variable>> {
+    let data = sections.info.as_slice();
+    let mut cursor = Cursor::new(data);
+    let mut declared = Vec::new();
+    let mut types = HashMap::new();
+
+    while cursor.position() < data.len() as u64 {
+        let offset = cursor.position();
+        let length = cursor.read_u32::<LE>()? as u64;
+        if length >= 0xffff_fff0 {
+            return Err(DwarfError::Unsupported("64-bit format".to_string()));
+        }
+        let end = cursor.position() + length;
+
+        let version = cursor.read_u16::<LE>()?;
+        let (kind, address_size, abbrev) = match version {
+            2 ..= 4 => {
+                let abbrev = cursor.read_u32::<LE>()?;
+                (UNIT_COMPILE, cursor.read_u8()?, abbrev)
+            },
+            5 => (cursor.read_u8()?, cursor.read_u8()?, cursor.read_u32::<LE>()?),
+            _ => return Err(DwarfError::Unsupported(format!("version {}", version))),
+        };
+        if address_size != 8 {
+            return Err(DwarfError::Unsupported(format!("address size {}", address_size)));
+        }
+
+        // Type units only describe types of other units.
+        if kind == UNIT_COMPILE || kind == UNIT_PARTIAL {
+            let mut unit = Unit {
+                sections,
+                offset,
+                version,
+                abbreviations: parse_abbreviations(&sections.abbrev, abbrev as u64)?,
+                base: 0,
+                addr_base: 8,
+                loclists_base: 12,
+            };
+            unit.parse_entries(&mut cursor, end, &mut declared, &mut types)?;
+        }
+        cursor.seek(SeekFrom::Start(end))?;
+    }
+
+    Ok(declared.into_iter()
+        .filter_map(|(name, reference, locations)| Some(Variable {
+            name,
+            data_type: resolve_type(&types, reference)?,
+            locations,
+        }))
+        .filter(|variable| !variable.locations.is_empty())
+        .collect())
+}
+
+/// A variable as declared, with a reference to its type.
+type Declared = (String, Option<u64>, Vec<VariableLocation>);
+
+/// What is known about the entry of a type at an offset: the size of an
+/// integer or pointer type or the type an alias refers to.
+#[derive(Debug, Copy, Clone)]
+enum TypeEntry {
+    Sized(u64),
+    Alias(Option<u64>),
+}
+
+/// The integer type of the values of the type behind the reference,
+/// following type definitions and qualifiers.
+fn resolve_type(
+    types: &HashMap<u64, TypeEntry>,
+    mut reference: Option<u64>,
+) -> Option<DataType> {
+    for _ in 0 .. 16 {
+        match types.get(&reference?)? {
+            TypeEntry::Sized(1) => return Some(DataType::N8),
+            TypeEntry::Sized(2) => return Some(DataType::N16),
+            TypeEntry::Sized(4) => return Some(DataType::N32),
+            TypeEntry::Sized(8) => return Some(DataType::N64),
+            TypeEntry::Sized(_) => return None,
+            TypeEntry::Alias(next) => reference = *next,
+        }
+    }
+    None
+}
+
+/// An entry of the abbreviation table, describing the tag and the forms of
+/// the attributes of the entries using it.
+#[derive(Debug, Clone)]
+struct Abbreviation {
+    tag: u64,
+    children: bool,
+    /// The attributes as (name, form, implicit constant).
+    attributes: Vec<(u64, u64, i64)>,
+}
+
+/// Parse the abbreviation table at the offset.
+fn parse_abbreviations(data: &[u8], offset: u64) -> DwarfResult<HashMap<u64, Abbreviation>> {
+    let mut cursor = Cursor::new(data);
+    cursor.seek(SeekFrom::Start(offset))?;
+
+    let mut abbreviations = HashMap::new();
+    loop {
+        let code = read_uleb(&mut cursor)?;
+        if code == 0 {
+            break;
+        }
+
+        let tag = read_uleb(&mut cursor)?;
+        let children = cursor.read_u8()? != 0;
+        let mut attributes = Vec::new();
+        loop {
+            let (name, form) = (read_uleb(&mut cursor)?, read_uleb(&mut cursor)?);
+            if name == 0 && form == 0 {
+                break;
+            }
+            let constant = if form == FORM_IMPLICIT_CONST { read_sleb(&mut cursor)? } else { 0 };
+            attributes.push((name, form, constant));
+        }
+
+        abbreviations.insert(code, Abbreviation { tag, children, attributes });
+    }
+
+    Ok(abbreviations)
+}
+
+/// The value of an attribute as far as it is needed.
+#[derive(Debug, Clone)]
+enum Value {
+    /// An address, a constant or an offset into another section.
+    Number(u64),
+    /// An index into the address table of the unit.
+    AddressIndex(u64),
+    /// An index into the location list table of the unit.
+    ListIndex(u64),
+    /// Another entry by its offset in the section.
+    Reference(u64),
+    Block(Vec<u8>),
+    String(String),
+    Other,
+}
+
+/// How the frame base of a function is computed.
+#[derive(Debug, Copy, Clone)]
+enum FrameBase {
+    /// The canonical frame address.
+    Cfa,
+    /// An offset from the frame pointer.
+    FramePointer(i64),
+}
+
+/// The range and the frame base of a function.
+#[derive(Debug, Copy, Clone)]
+struct Function {
+    low: u64,
+    high: u64,
+    frame_base: Option<FrameBase>,
+}
+
+/// A compilation unit while its entries are read.
+struct Unit<'a> {
+    sections: &'a DebugSections,
+    /// The offset of the unit in the section, which references within the
+    /// unit are relative to.
+    offset: u64,
+    version: u16,
+    abbreviations: HashMap<u64, Abbreviation>,
+    /// The base address of location lists.
+    base: u64,
+    /// The offsets of the tables of the unit in `.debug_addr` and
+    /// `.debug_loclists`.
+    addr_base: u64,
+    loclists_base: u64,
+}
+
+impl Unit<'_> {
+    /// Read the entries of the unit up to the end offset and add its
+    /// variables and types.
+    fn parse_entries(
+        &mut self,
+        cursor: &mut Cursor<&[u8]>,
+        end: u64,
+        declared: &mut Vec<Declared>,
+        types: &mut HashMap<u64, TypeEntry>,
+    ) -> DwarfResult<()> {
+        // The innermost functions around the children of the open entries.
+        let mut scopes: Vec<Option<Function>> = Vec::new();
+
+        while cursor.position() < end {
+            let offset = cursor.position();
+            let code = read_uleb(cursor)?;
+            if code == 0 {
+                scopes.pop();
+                continue;
+            }
+
+            let abbreviation = self.abbreviations.get(&code)
+                .ok_or(DwarfError::Invalid("unknown abbreviation"))?
+                .clone();
+            let mut attributes = HashMap::new();
+            for &(name, form, constant) in &abbreviation.attributes {
+                let value = self.read_value(cursor, form, constant)?;
+                attributes.insert(name, value);
+            }
+
+            let number = |name: u64| match attributes.get(&name) {
+                Some(Value::Number(value)) => Some(*value),
+                _ => None,
+            };
+            let reference = match attributes.get(&AT_TYPE) {
+                Some(Value::Reference(reference)) => Some(*reference),
+                _ => None,
+            };
+
+            let mut function = scopes.last().copied().flatten();
+            match abbreviation.tag {
+                TAG_COMPILE_UNIT | TAG_PARTIAL_UNIT => {
+                    self.base = number(AT_LOW_PC).unwrap_or(0);
+                    self.addr_base = number(AT_ADDR_BASE).unwrap_or(self.addr_base);
+                    self.loclists_base = number(AT_LOCLISTS_BASE).unwrap_or(self.loclists_base);
+                },
+                TAG_BASE_TYPE | TAG_ENUMERATION_TYPE => {
+                    if let Some(size) = number(AT_BYTE_SIZE) {
+                        types.insert(offset, TypeEntry::Sized(size));
+                    }
+                },
+                TAG_POINTER_TYPE => {
+                    types.insert(offset, TypeEntry::Sized(number(AT_BYTE_SIZE).unwrap_or(8)));
+                },
+                TAG_TYPEDEF | TAG_CONST_TYPE | TAG_VOLATILE_TYPE | TAG_RESTRICT_TYPE => {
+                    types.insert(offset, TypeEntry::Alias(reference));
+                },
+                TAG_SUBPROGRAM => {
+                    let low = self.address(attributes.get(&AT_LOW_PC))?;
+                    let high = match (attributes.get(&AT_HIGH_PC), low) {
+                        (Some(Value::Number(len)), Some(low)) if self.version >= 4 => {
+                            Some(low.wrapping_add(*len))
+                        },
+                        (high, _) => self.address(high)?,
+                    };
+                    let frame_base = match attributes.get(&AT_FRAME_BASE) {
+                        Some(Value::Block(expr)) => parse_frame_base(expr),
+                        _ => None,
+                    };
+                    function = match (low, high) {
+                        (Some(low), Some(high)) => Some(Function { low, high, frame_base }),
+                        _ => None,
+                    };
+                },
+                TAG_VARIABLE | TAG_FORMAL_PARAMETER => {
+                    if let (Some(Value::String(name)), Some(location)) =
+                        (attributes.get(&AT_NAME), attributes.get(&AT_LOCATION))
+                    {
+                        let locations = self.locations(location, function)?;
+                        declared.push((name.clone(), reference, locations));
+                    }
+                },
+                _ => {},
+            }
+
+            if abbreviation.children {
+                scopes.push(function);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The locations of a variable in the function given the value of its
+    /// location attribute.
+    fn locations(&self, value: &Value, function: Option<Function>)
+    -> DwarfResult<Vec<VariableLocation>> {
+        let frame_base = function.and_then(|function| function.frame_base);
+        Ok(match value {
+            Value::Block(expr) => {
+                let (start, end) = function.map_or((0, u64::MAX), |f| (f.low, f.high));
+                parse_place(expr, frame_base).into_iter()
+                    .map(|place| VariableLocation { start, end, place })
+                    .collect()
+            },
+            Value::Number(offset) if self.version >= 5 => self.parse_loclist(*offset, frame_base)?,
+            Value::Number(offset) => self.parse_loc(*offset, frame_base)?,
+            Value::ListIndex(index) => {
+                let mut cursor = Cursor::new(self.sections.loclists.as_slice());
+                cursor.seek(SeekFrom::Start(self.loclists_base + 4 * index))?;
+                let offset = self.loclists_base + cursor.read_u32::<LE>()? as u64;
+                self.parse_loclist(offset, frame_base)?
+            },
+            _ => Vec::new(),
+        })
+    }
+
+    /// Parse a location list in `.debug_loclists`.
+    fn parse_loclist(&self, offset: u64, frame_base: Option<FrameBase>)
+    -> DwarfResult<Vec<VariableLocation>> {
+        let mut cursor = Cursor::new(self.sections.loclists.as_slice());
+        cursor.seek(SeekFrom::Start(offset))?;
+
+        let mut base = self.base;
+        let mut locations = Vec::new();
+        loop {
+            let (start, end) = match cursor.read_u8()? {
+                LLE_END_OF_LIST => break,
+                LLE_BASE_ADDRESSX => {
+                    base = self.indexed_address(read_uleb(&mut cursor)?)?;
+                    continue;
+                },
+                LLE_STARTX_ENDX => {
+                    let start = self.indexed_address(read_uleb(&mut cursor)?)?;
+                    (start, self.indexed_address(read_uleb(&mut cursor)?)?)
+                },
+                LLE_STARTX_LENGTH => {
+                    let start = self.indexed_address(read_uleb(&mut cursor)?)?;
+                    (start, start.wrapping_add(read_uleb(&mut cursor)?))
+                },
+                LLE_OFFSET_PAIR => {
+                    let start = base.wrapping_add(read_uleb(&mut cursor)?);
+                    (start, base.wrapping_add(read_uleb(&mut cursor)?))
+                },
+                LLE_DEFAULT_LOCATION => (0, u64::MAX),
+                LLE_BASE_ADDRESS => {
+                    base = cursor.read_u64::<LE>()?;
+                    continue;
+                },
+                LLE_START_END => (cursor.read_u64::<LE>()?, cursor.read_u64::<LE>()?),
+                LLE_START_LENGTH => {
+                    let start = cursor.read_u64::<LE>()?;
+                    (start, start.wrapping_add(read_uleb(&mut cursor)?))
+                },
+                kind => return Err(DwarfError::Unsupported(
+                    format!("location list entry {:#x}", kind))),
+            };
+
+            let len = read_uleb(&mut cursor)?;
+            let expr = read_block(&mut cursor, len)?;
+            if let Some(place) = parse_place(&expr, frame_base) {
+                locations.push(VariableLocation { start, end, place });
+            }
+        }
+
+        Ok(locations)
+    }
+
+    /// Parse a location list in `.debug_loc`, whose addresses are relative
+    /// to the base address unless a base address selection changes it.
+    fn parse_loc(&self, offset: u64, frame_base: Option<FrameBase>)
+    -> DwarfResult<Vec<VariableLocation>> {
+        let mut cursor = Cursor::new(self.sections.loc.as_slice());
+        cursor.seek(SeekFrom::Start(offset))?;
+
+        let mut base = self.base;
+        let mut locations = Vec::new();
+        loop {
+            let (start, end) = (cursor.read_u64::<LE>()?, cursor.read_u64::<LE>()?);
+            if start == 0 && end == 0 {
+                break;
+            } else if start == u64::MAX {
+                base = end;
+                continue;
+            }
+
+            let len = cursor.read_u16::<LE>()? as u64;
+            let expr = read_block(&mut cursor, len)?;
+            if let Some(place) = parse_place(&expr, frame_base) {
+                let (start, end) = (base.wrapping_add(start), base.wrapping_add(end));
+                locations.push(VariableLocation { start, end, place });
+            }
+        }
+
+        Ok(locations)
+    }
+
+    /// The address an attribute value stands for.
+    fn address(&self, value: Option<&Value>) -> DwarfResult<Option<u64>> {
+        match value {
+            Some(Value::Number(addr)) => Ok(Some(*addr)),
+            Some(Value::AddressIndex(index)) => self.indexed_address(*index).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The address at the index of the address table of the unit.
+    fn indexed_address(&self, index: u64) -> DwarfResult<u64> {
+        let mut cursor = Cursor::new(self.sections.addr.as_slice());
+        cursor.seek(SeekFrom::Start(self.addr_base + 8 * index))?;
+        Ok(cursor.read_u64::<LE>()?)
+    }
+
+    /// Read an attribute value of the form.
+    fn read_value(&self, cursor: &mut Cursor<&[u8]>, form: u64, constant: i64)
+    -> DwarfResult<Value> {
+        Ok(match form {
+            0x01 => Value::Number(cursor.read_u64::<LE>()?),
+            0x03 => {
+                let len = cursor.read_u16::<LE>()? as u64;
+                Value::Block(read_block(cursor, len)?)
+            },
+            0x04 => {
+                let len = cursor.read_u32::<LE>()? as u64;
+                Value::Block(read_block(cursor, len)?)
+            },
+            0x05 => Value::Number(cursor.read_u16::<LE>()? as u64),
+            0x06 | 0x17 => Value::Number(cursor.read_u32::<LE>()? as u64),
+            0x07 => Value::Number(cursor.read_u64::<LE>()?),
+            0x08 => {
+                let position = cursor.position();
+                let string = read_string(cursor.get_ref(), position)?;
+                cursor.seek(SeekFrom::Current(string.len() as i64 + 1))?;
+                Value::String(string)
+            },
+            0x09 | 0x18 => {
+                let len = read_uleb(cursor)?;
+                Value::Block(read_block(cursor, len)?)
+            },
+            0x0a => {
+                let len = cursor.read_u8()? as u64;
+                Value::Block(read_block(cursor, len)?)
+            },
+            0x0b | 0x0c => Value::Number(cursor.read_u8()? as u64),
+            0x0d => Value::Number(read_sleb(cursor)? as u64),
+            0x0e => {
+                let offset = cursor.read_u32::<LE>()? as u64;
+                Value::String(read_string(&self.sections.str, offset)?)
+            },
+            0x0f => Value::Number(read_uleb(cursor)?),
+            0x10 => Value::Reference(match self.version {
+                2 => cursor.read_u64::<LE>()?,
+                _ => cursor.read_u32::<LE>()? as u64,
+            }),
+            0x11 => Value::Reference(self.offset + cursor.read_u8()? as u64),
+            0x12 => Value::Reference(self.offset + cursor.read_u16::<LE>()? as u64),
+            0x13 => Value::Reference(self.offset + cursor.read_u32::<LE>()? as u64),
+            0x14 => Value::Reference(self.offset + cursor.read_u64::<LE>()?),
+            0x15 => Value::Reference(self.offset + read_uleb(cursor)?),
+            0x16 => {
+                let form = read_uleb(cursor)?;
+                return self.read_value(cursor, form, constant);
+            },
+            0x19 => Value::Number(1),
+            0x1a | 0x23 => {
+                read_uleb(cursor)?;
+                Value::Other
+            },
+            0x1b => Value::AddressIndex(read_uleb(cursor)?),
+            0x1c | 0x1d => {
+                cursor.read_u32::<LE>()?;
+                Value::Other
+            },
+            0x1e => {
+                read_block(cursor, 16)?;
+                Value::Other
+            },
+            0x1f => {
+                let offset = cursor.read_u32::<LE>()? as u64;
+                Value::String(read_string(&self.sections.line_str, offset)?)
+            },
+            0x20 | 0x24 => {
+                cursor.read_u64::<LE>()?;
+                Value::Other
+            },
+            FORM_IMPLICIT_CONST => Value::Number(constant as u64),
+            0x22 => Value::ListIndex(read_uleb(cursor)?),
+            0x25 ..= 0x28 => {
+                read_block(cursor, form - 0x24)?;
+                Value::Other
+            },
+            0x29 ..= 0x2c => {
+                let bytes = read_block(cursor, form - 0x28)?;
+                let index = bytes.iter().rev().fold(0, |index, &byte| index << 8 | byte as u64);
+                Value::AddressIndex(index)
+            },
+            _ => return Err(DwarfError::Unsupported(format!("attribute form {:#x}", form))),
+        })
+    }
+}
+
+/// Parse the expression computing the frame base of a function.
+fn parse_frame_base(expr: &[u8]) -> Option<FrameBase> {
+    let mut cursor = Cursor::new(expr);
+    let base = match cursor.read_u8().ok()? {
+        OP_CALL_FRAME_CFA => FrameBase::Cfa,
+        OP_REG_FRAME_POINTER => FrameBase::FramePointer(0),
+        OP_BREG_FRAME_POINTER => FrameBase::FramePointer(read_sleb(&mut cursor).ok()?),
+        _ => return None,
+    };
+    match cursor.position() == expr.len() as u64 {
+        true => Some(base),
+        false => None,
+    }
+}
+
+/// Parse a location expression if it names a single place.
+fn parse_place(expr: &[u8], frame_base: Option<FrameBase>) -> Option<Place> {
+    let mut cursor = Cursor::new(expr);
+    let place = match cursor.read_u8().ok()? {
+        OP_ADDR => Place::Static(cursor.read_u64::<LE>().ok()?),
+        OP_FBREG => {
+            let offset = read_sleb(&mut cursor).ok()?;
+            match frame_base? {
+                FrameBase::Cfa => Place::Frame(offset),
+                FrameBase::FramePointer(base) => Place::FramePointer(base + offset),
+            }
+        },
+        OP_BREG_FRAME_POINTER => Place::FramePointer(read_sleb(&mut cursor).ok()?),
+        op @ OP_REG0 ..= OP_REG15 => Place::Register(REGISTERS[(op - OP_REG0) as usize]),
+        _ => return None,
+    };
+    match cursor.position() == expr.len() as u64 {
+        true => Some(place),
+        false => None,
+    }
+}
+
+/// Read a block of bytes, which must be in the data.
+fn read_block(cursor: &mut Cursor<&[u8]>, len: u64) -> DwarfResult<Vec<u8>> {
+    let size = cursor.get_ref().len() as u64;
+    if len > size.saturating_sub(cursor.position()) {
+        return Err(DwarfError::Invalid("block beyond the end of the section"));
+    }
+    let mut bytes = vec![0; len as usize];
+    cursor.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Read the null-terminated string at the offset of the data.
+fn read_string(data: &[u8], offset: u64) -> DwarfResult<String> {
+    let bytes = data.get(offset as usize ..)
+        .and_then(|bytes| bytes.split(|&byte| byte == 0).next())
+        .filter(|bytes| (offset as usize) + bytes.len() < data.len())
+        .ok_or(DwarfError::Invalid("unterminated string"))?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The registers by their DWARF numbers.
+const REGISTERS: [Register; 16] = {
+    use Register::*;
+    [RAX, RDX, RCX, RBX, RSI, RDI, RBP, RSP, R8, R9, R10, R11, R12, R13, R14, R15]
+};
+
+const UNIT_COMPILE: u8 = 0x01;
+const UNIT_PARTIAL: u8 = 0x03;
+
+const TAG_ENUMERATION_TYPE: u64 = 0x04;
+const TAG_FORMAL_PARAMETER: u64 = 0x05;
+const TAG_POINTER_TYPE: u64 = 0x0f;
+const TAG_COMPILE_UNIT: u64 = 0x11;
+const TAG_TYPEDEF: u64 = 0x16;
+const TAG_BASE_TYPE: u64 = 0x24;
+const TAG_CONST_TYPE: u64 = 0x26;
+const TAG_SUBPROGRAM: u64 = 0x2e;
+const TAG_VARIABLE: u64 = 0x34;
+const TAG_VOLATILE_TYPE: u64 = 0x35;
+const TAG_RESTRICT_TYPE: u64 = 0x37;
+const TAG_PARTIAL_UNIT: u64 = 0x3c;
+
+const AT_LOCATION: u64 = 0x02;
+const AT_NAME: u64 = 0x03;
+const AT_BYTE_SIZE: u64 = 0x0b;
+const AT_LOW_PC: u64 = 0x11;
+const AT_HIGH_PC: u64 = 0x12;
+const AT_FRAME_BASE: u64 = 0x40;
+const AT_TYPE: u64 = 0x49;
+const AT_ADDR_BASE: u64 = 0x73;
+const AT_LOCLISTS_BASE: u64 = 0x8c;
+
+const FORM_IMPLICIT_CONST: u64 = 0x21;
+
+const OP_ADDR: u8 = 0x03;
+const OP_REG0: u8 = 0x50;
+const OP_REG15: u8 = 0x5f;
+const OP_REG_FRAME_POINTER: u8 = 0x56;
+const OP_BREG_FRAME_POINTER: u8 = 0x76;
+const OP_FBREG: u8 = 0x91;
+const OP_CALL_FRAME_CFA: u8 = 0x9c;
+
+const LLE_END_OF_LIST: u8 = 0x00;
+const LLE_BASE_ADDRESSX: u8 = 0x01;
+const LLE_STARTX_ENDX: u8 = 0x02;
+const LLE_STARTX_LENGTH: u8 = 0x03;
+const LLE_OFFSET_PAIR: u8 = 0x04;
+const LLE_DEFAULT_LOCATION: u8 = 0x05;
+const LLE_BASE_ADDRESS: u8 = 0x06;
+const LLE_START_END: u8 = 0x07;
+const LLE_START_LENGTH: u8 = 0x08;
+
+
+/// The error type for parsing debugging information.
+pub enum DwarfError {
+    Invalid(&'static str),
+    Unsupported(String),
+    Io(io::Error),
+}
+
+pub(in super) type DwarfResult<T> = Result<T, DwarfError>;
+
+impl Display for DwarfError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DwarfError::Invalid(message) => write!(f, "Invalid debugging information: {}", message),
+            DwarfError::Unsupported(what) => {
+                write!(f, "Unsupported debugging information: {}", what)
+            },
+            DwarfError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DwarfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DwarfError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+debug_display!(DwarfError);
+
+impl From<io::Error> for DwarfError {
+    fn from(err: io::Error) -> DwarfError {
+        DwarfError::Io(err)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places() {
+        let cfa = Some(FrameBase::Cfa);
+        assert_eq!(parse_place(&[0x91, 0x5c], cfa), Some(Place::Frame(-36)));
+        assert_eq!(parse_place(&[0x91, 0x5c], Some(FrameBase::FramePointer(0))),
+                   Some(Place::FramePointer(-36)));
+        assert_eq!(parse_place(&[0x76, 0x70], None), Some(Place::FramePointer(-16)));
+        assert_eq!(parse_place(&[0x55], None), Some(Place::Register(Register::RDI)));
+        assert_eq!(parse_place(&[0x03, 0x10, 0x40, 0, 0, 0, 0, 0, 0], None),
+                   Some(Place::Static(0x4010)));
+        assert_eq!(parse_place(&[0x91, 0x5c], None), None);
+        assert_eq!(parse_place(&[0x55, 0x93, 0x04], None), None);
+    }
+
+    #[test]
+    fn variables() {
+        let abbrev = vec![
+            // 1: compile unit with children and a low address.
+            1, 0x11, 1, 0x11, 0x01, 0, 0,
+            // 2: function with children, address range and frame base.
+            2, 0x2e, 1, 0x03, 0x08, 0x11, 0x01, 0x12, 0x07, 0x40, 0x18, 0, 0,
+            // 3: variable with name, type and location expression.
+            3, 0x34, 0, 0x03, 0x08, 0x49, 0x13, 0x02, 0x18, 0, 0,
+            // 4: base type with size.
+            4, 0x24, 0, 0x0b, 0x0b, 0, 0,
+            // 5: parameter with name, type and location list.
+            5, 0x05, 0, 0x03, 0x08, 0x49, 0x13, 0x02, 0x17, 0, 0,
+            0,
+        ];
+
+        let mut info = vec![
+            0, 0, 0, 0, 5, 0, 0x01, 8, 0, 0, 0, 0,
+            // Compile unit at 0x1000.
+            1, 0x00, 0x10, 0, 0, 0, 0, 0, 0,
+            // Type `int` at offset 0x15.
+            4, 4,
+            // Function `f` from 0x1000 to 0x1040 with the frame base at the
+            // canonical frame address.
+            2, b'f', 0, 0x00, 0x10, 0, 0, 0, 0, 0, 0, 0x40, 0, 0, 0, 0, 0, 0, 0, 1, 0x9c,
+            // Variable `len` of type `int` at -36.
+            3, b'l', b'e', b'n', 0, 0x15, 0, 0, 0, 2, 0x91, 0x5c,
+            // Parameter `n` of type `int` in the list at offset 0.
+            5, b'n', 0, 0x15, 0, 0, 0, 0, 0, 0, 0,
+            0, 0,
+        ];
+        let length = info.len() as u32 - 4;
+        info[.. 4].copy_from_slice(&length.to_le_bytes());
+
+        let loclists = vec![
+            // In `rdi` from 0x1000 to 0x1008 and then in the frame at -40.
+            4, 0x00, 0x08, 1, 0x55,
+            4, 0x08, 0x40, 2, 0x91, 0x58,
+            0,
+        ];
+
+        let sections = DebugSections { info, abbrev, loclists, .. DebugSections::default() };
+        let variables = parse_variables(&sections).unwrap();
+        assert_eq!(variables.len(), 2);
+
+        let len = &variables[0];
+        assert_eq!((len.name.as_str(), len.data_type), ("len", DataType::N32));
+        assert_eq!(len.place_at(0x1020), Some(Place::Frame(-36)));
+        assert_eq!(len.place_at(0x1040), None);
+
+        let n = &variables[1];
+        assert_eq!(n.place_at(0x1004), Some(Place::Register(Register::RDI)));
+        assert_eq!(n.place_at(0x1010), Some(Place::Frame(-40)));
+    }
+}
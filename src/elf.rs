@@ -2,8 +2,10 @@
 
 use std::ffi::CStr;
 use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
 use std::fs::File;
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
+#[cfg(feature = "fs")]
 use std::path::Path;
 use byteorder::{ReadBytesExt, LE};
 
@@ -58,6 +60,29 @@ pub struct SectionHeader {
     pub entry_size: u64,
 }
 
+impl SectionHeader {
+    /// Whether the section is writable at runtime.
+    pub fn is_writable(&self) -> bool {
+        self.flags & 0x1 != 0
+    }
+
+    /// Whether the section occupies memory at runtime.
+    pub fn is_allocated(&self) -> bool {
+        self.flags & 0x2 != 0
+    }
+
+    /// Whether the section contains executable instructions.
+    pub fn is_executable(&self) -> bool {
+        self.flags & 0x4 != 0
+    }
+
+    /// Whether the section has no contents in the file, like `.bss`, whose
+    /// size is only the one it occupies at runtime.
+    pub fn is_nobits(&self) -> bool {
+        self.section_type == 8
+    }
+}
+
 /// An entry in the symbol table.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SymbolTableEntry {
@@ -69,6 +94,62 @@ pub struct SymbolTableEntry {
     pub size: u64,
 }
 
+impl SymbolTableEntry {
+    /// The symbol type (e.g. 2 for functions).
+    pub fn kind(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    /// The symbol binding (e.g. 1 for global symbols).
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    /// Whether this is a function or an indirect function.
+    pub fn is_function(&self) -> bool {
+        self.kind() == 2 || self.is_ifunc()
+    }
+
+    /// Whether this is a GNU indirect function, whose value is the address of
+    /// a resolver selecting the implementation at load time.
+    pub fn is_ifunc(&self) -> bool {
+        self.kind() == 10
+    }
+
+    /// Whether this is a weak symbol, which a global one of the same name overrides.
+    pub fn is_weak(&self) -> bool {
+        self.binding() == 2
+    }
+}
+
+/// An entry in a relocation table with addend.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Relocation {
+    pub offset: u64,
+    pub info: u64,
+    pub addend: i64,
+}
+
+impl Relocation {
+    /// The relocation type.
+    pub fn kind(&self) -> u32 {
+        self.info as u32
+    }
+
+    /// Whether this is a relative relocation whose value is the load base
+    /// plus the addend.
+    pub fn is_relative(&self) -> bool {
+        self.kind() == 8
+    }
+
+    /// Whether this is an indirect relative relocation whose value is the
+    /// result of calling the resolver at the load base plus the addend.
+    pub fn is_irelative(&self) -> bool {
+        self.kind() == 37
+    }
+}
+
+#[cfg(feature = "fs")]
 impl ElfFile<File> {
     /// Load an `ELF` file from the file system.
     pub fn new<P: AsRef<Path>>(filename: P) -> ElfResult<ElfFile<File>> {
@@ -97,10 +178,7 @@ impl<R> ElfFile<R> where R: Read + Seek {
         // Build up the sections.
         let mut sections = Vec::with_capacity(self.section_headers.len());
         for header in &self.section_headers {
-            let mut data = vec![0; header.size as usize];
-            self.target.seek(SeekFrom::Start(header.offset))?;
-            self.target.read_exact(&mut data)?;
-
+            let data = read_contents(&mut self.target, header)?;
             let section = Section { header: header.clone(), data };
             sections.push(section);
         }
@@ -111,41 +189,49 @@ impl<R> ElfFile<R> where R: Read + Seek {
     /// Retrieve the section with a specific name if it is present.
     pub fn get_section(&mut self, name: &str) -> ElfResult<Section> {
         let header = self.get_section_header(name)?.clone();
-
-        let mut data = vec![0; header.size as usize];
-        self.target.seek(SeekFrom::Start(header.offset))?;
-        self.target.read_exact(&mut data)?;
-
+        let data = read_contents(&mut self.target, &header)?;
         Ok(Section { header, data })
     }
 
     /// Retrieve the symbols from the `.symtab` section if it is present.
     pub fn get_symbols(&mut self) -> ElfResult<Vec<SymbolTableEntry>> {
-        let (size, offset) = {
-            let header = self.get_section_header(".symtab")?;
-            (header.size, header.offset)
-        };
-
-        let count = (size / 24) as usize;
-        let mut symbols = Vec::with_capacity(count);
+        let data = self.get_section(".symtab")?.data;
         let symbol_strings = self.get_section(".strtab")?.data;
+        let mut cursor = Cursor::new(data.as_slice());
+        let mut symbols = Vec::with_capacity(data.len() / 24);
 
-        self.target.seek(SeekFrom::Start(offset))?;
-        for _ in 0 .. count {
-            let name_offset = self.target.read_u32::<LE>()?;
+        for _ in 0 .. data.len() / 24 {
+            let name_offset = cursor.read_u32::<LE>()?;
             symbols.push(SymbolTableEntry {
-                name: parse_string(&symbol_strings, name_offset),
-                info: self.target.read_u8()?,
-                other: self.target.read_u8()?,
-                section_table_index: self.target.read_u16::<LE>()?,
-                value: self.target.read_u64::<LE>()?,
-                size: self.target.read_u64::<LE>()?,
+                name: parse_string(&symbol_strings, name_offset)?,
+                info: cursor.read_u8()?,
+                other: cursor.read_u8()?,
+                section_table_index: cursor.read_u16::<LE>()?,
+                value: cursor.read_u64::<LE>()?,
+                size: cursor.read_u64::<LE>()?,
             })
         }
 
         Ok(symbols)
     }
 
+    /// Retrieve the relocations from a relocation table with addends.
+    pub fn get_relocations(&mut self, name: &str) -> ElfResult<Vec<Relocation>> {
+        let data = self.get_section(name)?.data;
+        let mut cursor = Cursor::new(data.as_slice());
+        let mut relocations = Vec::with_capacity(data.len() / 24);
+
+        for _ in 0 .. data.len() / 24 {
+            relocations.push(Relocation {
+                offset: cursor.read_u64::<LE>()?,
+                info: cursor.read_u64::<LE>()?,
+                addend: cursor.read_i64::<LE>()?,
+            });
+        }
+
+        Ok(relocations)
+    }
+
     fn get_section_header(&mut self, name: &str) -> ElfResult<&SectionHeader> {
         self.section_headers.iter()
             .find(|header| header.name == name)
@@ -213,30 +299,50 @@ fn parse_section_headers<R>(header: Header, target: &mut R)
 
     // Read the raw string table data.
     let string_index = header.section_name_string_table_index as usize;
-    let string_table = &headers[string_index];
-    let mut strings = vec![0; string_table.size as usize];
-    target.seek(SeekFrom::Start(string_table.offset))?;
-    target.read_exact(&mut strings)?;
+    let string_table = headers.get(string_index).ok_or(ElfError::Invalid)?;
+    let strings = read_range(target, string_table.offset, string_table.size)?;
 
     // Fill in the missing names for all sections.
     for table in headers.iter_mut() {
-        table.name = parse_string(&strings, table.name_offset);
+        table.name = parse_string(&strings, table.name_offset)?;
     }
 
     Ok(headers)
 }
 
-/// Parse a string from the string table.
-fn parse_string(strings: &[u8], offset: u32) -> String {
-    let mut zero = offset as usize;
-    while strings[zero] != 0 {
-        zero += 1;
+/// Read `size` bytes at the offset, failing before allocating anything if
+/// they extend past the end of the file.
+fn read_range<R>(target: &mut R, offset: u64, size: u64) -> ElfResult<Vec<u8>> where R: Read + Seek {
+    let len = target.seek(SeekFrom::End(0))?;
+    if offset.checked_add(size).map_or(true, |end| end > len) {
+        return Err(ElfError::Invalid);
+    }
+
+    let mut data = vec![0; size as usize];
+    target.seek(SeekFrom::Start(offset))?;
+    target.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Read the contents of the section in the file, which sections without
+/// contents in the file do not have no matter their size.
+fn read_contents<R>(target: &mut R, header: &SectionHeader) -> ElfResult<Vec<u8>>
+where R: Read + Seek {
+    match header.is_nobits() {
+        true => Ok(Vec::new()),
+        false => read_range(target, header.offset, header.size),
     }
+}
+
+/// Parse a string from the string table.
+fn parse_string(strings: &[u8], offset: u32) -> ElfResult<String> {
+    let tail = strings.get(offset as usize ..).ok_or(ElfError::Invalid)?;
+    let zero = tail.iter().position(|&byte| byte == 0).ok_or(ElfError::Invalid)?;
 
-    CStr::from_bytes_with_nul(&strings[offset as usize .. zero + 1])
-        .expect("invalid C string in elf string table")
+    Ok(CStr::from_bytes_with_nul(&tail[..= zero])
+        .map_err(|_| ElfError::Invalid)?
         .to_string_lossy()
-        .into_owned()
+        .into_owned())
 }
 
 
@@ -0,0 +1,440 @@
+//! A C interface for calling the analyses from other programs.
+//!
+//! The interface is enabled by the `cdylib` feature and declared in
+//! `include/symflow.h`. Build the shared library with
+//! `cargo rustc --release --features cdylib --crate-type cdylib`.
+//!
+//! Programs and graphs are handles created and freed by the library.
+//! Functions filling arrays or buffers of the caller return the number of
+//! elements or bytes of the whole result, so that calling them with a
+//! capacity of zero queries the size. Failing functions return null or
+//! `SIZE_MAX` and leave a message for `symflow_last_error`. Panics never
+//! unwind into the caller.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::Program;
+use crate::flow::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+use crate::flow::{DependencyNode, DependenceNode, EdgeKind, NodeId};
+use crate::helper::{export_graph, panic_message};
+
+/// The control flow graph.
+pub const SYMFLOW_CFG: u32 = 0;
+/// The data dependency graph.
+pub const SYMFLOW_DDG: u32 = 1;
+/// The program dependence graph.
+pub const SYMFLOW_PDG: u32 = 2;
+
+/// Graphviz DOT.
+pub const SYMFLOW_DOT: u32 = 0;
+/// JSON in the model of angr for the control flow graph and with the nodes
+/// and edges for the program dependence graph.
+pub const SYMFLOW_JSON: u32 = 1;
+
+/// Control flow within a function.
+pub const SYMFLOW_EDGE_CONTROL_FLOW: u32 = 0;
+/// Data flow within a function.
+pub const SYMFLOW_EDGE_DATA_DEPENDENCY: u32 = 1;
+/// Control flow from a call site into the called function.
+pub const SYMFLOW_EDGE_CALL: u32 = 2;
+/// Control flow from a function back to its caller.
+pub const SYMFLOW_EDGE_RETURN: u32 = 3;
+/// Data flow from a caller into the called function.
+pub const SYMFLOW_EDGE_PARAM_IN: u32 = 4;
+/// Data flow from a function back to its caller.
+pub const SYMFLOW_EDGE_PARAM_OUT: u32 = 5;
+/// The summarized effect of a call that is not followed into the callee.
+pub const SYMFLOW_EDGE_SUMMARY: u32 = 6;
+/// Data flow through shared memory from one thread into another.
+pub const SYMFLOW_EDGE_INTER_THREAD: u32 = 7;
+/// Data flow between threads through atomic or fenced accesses.
+pub const SYMFLOW_EDGE_SYNCHRONIZATION: u32 = 8;
+/// The number of kinds of edges.
+pub const SYMFLOW_EDGE_KINDS: u32 = 9;
+
+/// The kinds of edges by their numbers.
+const EDGE_KINDS: [(u32, EdgeKind); SYMFLOW_EDGE_KINDS as usize] = [
+    (SYMFLOW_EDGE_CONTROL_FLOW, EdgeKind::ControlFlow),
+    (SYMFLOW_EDGE_DATA_DEPENDENCY, EdgeKind::DataDependency),
+    (SYMFLOW_EDGE_CALL, EdgeKind::Call),
+    (SYMFLOW_EDGE_RETURN, EdgeKind::Return),
+    (SYMFLOW_EDGE_PARAM_IN, EdgeKind::ParamIn),
+    (SYMFLOW_EDGE_PARAM_OUT, EdgeKind::ParamOut),
+    (SYMFLOW_EDGE_SUMMARY, EdgeKind::Summary),
+    (SYMFLOW_EDGE_INTER_THREAD, EdgeKind::InterThread),
+    (SYMFLOW_EDGE_SYNCHRONIZATION, EdgeKind::Synchronization),
+];
+
+// The kinds are numbered by their indices in `EdgeKind::ALL`, so that the
+// header has to change along with the kinds.
+const _: () = {
+    assert!(EDGE_KINDS.len() == EdgeKind::ALL.len());
+    let mut index = 0;
+    while index < EDGE_KINDS.len() {
+        let (number, kind) = EDGE_KINDS[index];
+        assert!(number as usize == index && kind as usize == EdgeKind::ALL[index] as usize);
+        index += 1;
+    }
+};
+
+/// A program loaded from an ELF file.
+pub struct SymflowProgram(Program);
+
+/// The graphs of a program.
+pub struct SymflowGraphs {
+    program: Program,
+    cfg: ControlFlowGraph,
+    ddg: DataDependencyGraph,
+    pdg: ProgramDependenceGraph,
+}
+
+/// A node of a graph.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SymflowNode {
+    /// The stable identifier of the node (see `NodeId`), or its index for
+    /// the input and constant nodes of the data dependency graph.
+    pub id: u64,
+    /// The address of the block or instruction.
+    pub addr: u64,
+    /// The kind of the node: zero for blocks and locations, one for data
+    /// dependencies in the program dependence graph and for inputs and two
+    /// for constants in the data dependency graph.
+    pub kind: u32,
+}
+
+/// An edge of a graph between the nodes with the indices.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SymflowEdge {
+    pub start: u64,
+    pub end: u64,
+    /// The kind of the edge as one of the `SYMFLOW_EDGE_*` constants.
+    pub kind: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// The message of the last failed call on this thread, which stays valid
+/// until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn symflow_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+/// Load a program from the ELF file at the path, or return null.
+///
+/// # Safety
+/// The path must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_program_load(path: *const c_char) -> *mut SymflowProgram {
+    guard(ptr::null_mut(), || {
+        let path = text(path)?;
+        let program = Program::new(path);
+        Ok(Box::into_raw(Box::new(SymflowProgram(program))))
+    })
+}
+
+/// Free a program.
+///
+/// # Safety
+/// The program must come from `symflow_program_load` and not be used
+/// afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_program_free(program: *mut SymflowProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Build the control flow, data dependency and program dependence graphs of
+/// the program, or return null.
+///
+/// # Safety
+/// The program must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_graphs_build(
+    program: *const SymflowProgram,
+) -> *mut SymflowGraphs {
+    guard(ptr::null_mut(), || {
+        let program = &reference(program)?.0;
+        let cfg = ControlFlowGraph::new(program);
+        let ddg = DataDependencyGraph::new(&cfg);
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+        let graphs = SymflowGraphs { program: program.clone(), cfg, ddg, pdg };
+        Ok(Box::into_raw(Box::new(graphs)))
+    })
+}
+
+/// Free graphs.
+///
+/// # Safety
+/// The graphs must come from `symflow_graphs_build` and not be used
+/// afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_graphs_free(graphs: *mut SymflowGraphs) {
+    if !graphs.is_null() {
+        drop(Box::from_raw(graphs));
+    }
+}
+
+/// Copy the nodes of a graph into the array and return their number.
+///
+/// # Safety
+/// The graphs must be a live handle and the array must have room for
+/// `capacity` nodes.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_nodes(
+    graphs: *const SymflowGraphs,
+    graph: u32,
+    out: *mut SymflowNode,
+    capacity: usize,
+) -> usize {
+    guard(usize::MAX, || {
+        let graphs = reference(graphs)?;
+        let nodes: Vec<SymflowNode> = match graph {
+            SYMFLOW_CFG => graphs.cfg.nodes.iter()
+                .map(|node| {
+                    SymflowNode { id: NodeId::of_control(node).0, addr: node.addr, kind: 0 }
+                })
+                .collect(),
+            SYMFLOW_DDG => graphs.ddg.nodes.iter().enumerate()
+                .map(|(index, node)| match node {
+                    DependencyNode::Location(location) => SymflowNode {
+                        id: NodeId::of_location(location).0,
+                        addr: location.addr,
+                        kind: 0,
+                    },
+                    DependencyNode::Io(..) => SymflowNode { id: index as u64, addr: 0, kind: 1 },
+                    DependencyNode::Constant(..) => {
+                        SymflowNode { id: index as u64, addr: 0, kind: 2 }
+                    },
+                })
+                .collect(),
+            SYMFLOW_PDG => graphs.pdg.nodes.iter().enumerate()
+                .map(|(index, node)| SymflowNode {
+                    id: graphs.pdg.id(index).0,
+                    addr: node.addr(),
+                    kind: match node {
+                        DependenceNode::ControlFlow(_) => 0,
+                        DependenceNode::DataDependency(_) => 1,
+                    },
+                })
+                .collect(),
+            _ => return Err(format!("unknown graph {}", graph)),
+        };
+        Ok(copy(&nodes, out, capacity))
+    })
+}
+
+/// Copy the edges of a graph into the array and return their number. The
+/// edges are sorted by their nodes.
+///
+/// # Safety
+/// The graphs must be a live handle and the array must have room for
+/// `capacity` edges.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_edges(
+    graphs: *const SymflowGraphs,
+    graph: u32,
+    out: *mut SymflowEdge,
+    capacity: usize,
+) -> usize {
+    guard(usize::MAX, || {
+        let graphs = reference(graphs)?;
+        let edge = |start: usize, end: usize, kind: EdgeKind| SymflowEdge {
+            start: start as u64,
+            end: end as u64,
+            kind: EDGE_KINDS.iter().find(|&&(_, k)| k == kind).unwrap().0,
+        };
+        let mut edges: Vec<SymflowEdge> = match graph {
+            SYMFLOW_CFG => graphs.cfg.edges.keys()
+                .map(|&(start, end)| {
+                    edge(start, end, EdgeKind::of_control(&graphs.cfg, (start, end)))
+                })
+                .collect(),
+            SYMFLOW_DDG => graphs.ddg.edges.keys()
+                .map(|&(start, end)| edge(start, end, graphs.ddg.edge_kind((start, end))))
+                .collect(),
+            SYMFLOW_PDG => graphs.pdg.edges.iter()
+                .map(|((start, end), pdg_edge)| edge(start, end, pdg_edge.kind))
+                .collect(),
+            _ => return Err(format!("unknown graph {}", graph)),
+        };
+        edges.sort_by_key(|edge| (edge.start, edge.end, edge.kind));
+        Ok(copy(&edges, out, capacity))
+    })
+}
+
+/// Copy the indices of the program dependence graph nodes in the backward
+/// slice of the address, or in the forward slice if `forward` is nonzero,
+/// into the array in ascending order and return their number.
+///
+/// # Safety
+/// The graphs must be a live handle and the array must have room for
+/// `capacity` indices.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_slice(
+    graphs: *const SymflowGraphs,
+    addr: u64,
+    forward: c_int,
+    out: *mut u64,
+    capacity: usize,
+) -> usize {
+    guard(usize::MAX, || {
+        let pdg = &reference(graphs)?.pdg;
+        let slice = if forward != 0 { pdg.forward_slice(addr) } else { pdg.backward_slice(addr) };
+        let indices: Vec<u64> = slice.into_iter().map(|index| index as u64).collect();
+        Ok(copy(&indices, out, capacity))
+    })
+}
+
+/// Export a graph in the format into the buffer and return the length of the
+/// export without the terminating null byte. The export is only written,
+/// with the null byte, if the capacity is larger than its length.
+///
+/// # Safety
+/// The graphs must be a live handle and the buffer must have room for
+/// `capacity` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn symflow_export(
+    graphs: *const SymflowGraphs,
+    graph: u32,
+    format: u32,
+    buffer: *mut c_char,
+    capacity: usize,
+) -> usize {
+    guard(usize::MAX, || {
+        let graphs = reference(graphs)?;
+        let name = match graph {
+            SYMFLOW_CFG => "cfg",
+            SYMFLOW_DDG => "ddg",
+            SYMFLOW_PDG => "pdg",
+            _ => "",
+        };
+        let extension = match format {
+            SYMFLOW_DOT => "dot",
+            SYMFLOW_JSON => "json",
+            _ => "",
+        };
+        let SymflowGraphs { program, cfg, ddg, pdg } = graphs;
+        let export = export_graph(program, cfg, ddg, pdg, name, extension)
+            .ok_or_else(|| format!("no export of graph {} in format {}", graph, format))?
+            .map_err(|err| err.to_string())?;
+
+        if export.len() < capacity && !buffer.is_null() {
+            ptr::copy_nonoverlapping(export.as_ptr(), buffer as *mut u8, export.len());
+            *buffer.add(export.len()) = 0;
+        }
+        Ok(export.len())
+    })
+}
+
+/// Run the function, turning errors and panics into the fallback value and
+/// the last error.
+fn guard<T, F>(fallback: T, f: F) -> T where F: FnOnce() -> Result<T, String> {
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => message,
+        Err(payload) => panic_message(&*payload),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+    fallback
+}
+
+/// The handle behind the pointer unless it is null.
+unsafe fn reference<'a, T>(handle: *const T) -> Result<&'a T, String> {
+    handle.as_ref().ok_or_else(|| "null handle".to_string())
+}
+
+/// The UTF-8 string behind the pointer.
+unsafe fn text<'a>(string: *const c_char) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err("null string".to_string());
+    }
+    CStr::from_ptr(string).to_str().map_err(|err| err.to_string())
+}
+
+/// Copy as many items as fit into the array and return the number of items.
+unsafe fn copy<T: Copy>(items: &[T], out: *mut T, capacity: usize) -> usize {
+    if !out.is_null() {
+        ptr::copy_nonoverlapping(items.as_ptr(), out, items.len().min(capacity));
+    }
+    items.len()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let error = || unsafe { CStr::from_ptr(symflow_last_error()) }.to_str().unwrap();
+
+        assert!(unsafe { symflow_program_load(ptr::null()) }.is_null());
+        assert_eq!(error(), "null string");
+
+        let graphs = ptr::null();
+        assert_eq!(unsafe { symflow_nodes(graphs, SYMFLOW_CFG, ptr::null_mut(), 0) }, usize::MAX);
+        assert_eq!(error(), "null handle");
+
+        let items = [1u64, 2, 3];
+        let mut out = [0u64; 2];
+        assert_eq!(unsafe { copy(&items, out.as_mut_ptr(), out.len()) }, 3);
+        assert_eq!(out, [1, 2]);
+    }
+
+    #[test]
+    fn edge_kinds() {
+        let path = crate::fixtures::fixture("threads");
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let program = symflow_program_load(path.as_ptr());
+            let graphs = symflow_graphs_build(program);
+            let count = symflow_edges(graphs, SYMFLOW_DDG, ptr::null_mut(), 0);
+            let mut edges = vec![SymflowEdge { start: 0, end: 0, kind: 0 }; count];
+            assert_eq!(symflow_edges(graphs, SYMFLOW_DDG, edges.as_mut_ptr(), count), count);
+
+            // The flows between threads are told apart like in the graph.
+            let threads = edges.iter()
+                .filter(|edge| edge.kind == SYMFLOW_EDGE_INTER_THREAD)
+                .count();
+            assert!(threads > 0);
+            assert_eq!(threads, (*graphs).ddg.threads.len());
+            symflow_graphs_free(graphs);
+            symflow_program_free(program);
+        }
+    }
+
+    #[test]
+    fn header() {
+        let header = include_str!("../include/symflow.h");
+        let defines: Vec<(&str, u32)> = header.lines()
+            .filter_map(|line| line.strip_prefix("#define SYMFLOW_EDGE_"))
+            .map(|define| {
+                let (name, value) = define.split_once(' ').unwrap();
+                (name, value.trim_end_matches('u').parse().unwrap())
+            })
+            .collect();
+        assert_eq!(defines, vec![
+            ("CONTROL_FLOW", SYMFLOW_EDGE_CONTROL_FLOW),
+            ("DATA_DEPENDENCY", SYMFLOW_EDGE_DATA_DEPENDENCY),
+            ("CALL", SYMFLOW_EDGE_CALL),
+            ("RETURN", SYMFLOW_EDGE_RETURN),
+            ("PARAM_IN", SYMFLOW_EDGE_PARAM_IN),
+            ("PARAM_OUT", SYMFLOW_EDGE_PARAM_OUT),
+            ("SUMMARY", SYMFLOW_EDGE_SUMMARY),
+            ("INTER_THREAD", SYMFLOW_EDGE_INTER_THREAD),
+            ("SYNCHRONIZATION", SYMFLOW_EDGE_SYNCHRONIZATION),
+            ("KINDS", SYMFLOW_EDGE_KINDS),
+        ]);
+    }
+}
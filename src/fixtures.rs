@@ -0,0 +1,230 @@
+//! Building the programs that tests analyze on demand.
+//!
+//! The bundled fixtures are the sources in the `test` directory of the crate
+//! listed in its `Makefile`, which are compiled into `target/bin` like the
+//! `Makefile` does: C files
+//! with `gcc -nostdlib -fcf-protection=none` and assembly files with `as`
+//! followed by `strip`. A fixture is only rebuilt if its binary is missing or
+//! older than its source, so a fresh clone needs no manual build step.
+//!
+//! Downstream crates can register their own sources with a `Fixtures` set
+//! and get the path of the built binary from `path`. Builds of the same
+//! output never run at the same time, even from parallel tests, and a binary
+//! only appears once it was built completely.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+
+lazy_static! {
+    /// Serializes builds across all fixture sets.
+    static ref BUILDING: Mutex<()> = Mutex::new(());
+
+    /// The fixtures bundled with the crate.
+    static ref BUNDLED: Fixtures = Fixtures::bundled();
+}
+
+/// The path of a bundled fixture program, which is built if needed.
+///
+/// Panics if the fixture does not exist or cannot be built.
+pub fn fixture(name: &str) -> PathBuf {
+    BUNDLED.path(name).unwrap_or_else(|err| panic!("fixture {}: {}", name, err))
+}
+
+/// A set of fixture programs built from their sources into one directory.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    /// The directory of the built binaries.
+    pub output: PathBuf,
+    sources: BTreeMap<String, Source>,
+}
+
+/// How a fixture is built.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Source {
+    pub path: PathBuf,
+    /// The additional flags of the compiler or assembler.
+    pub flags: Vec<String>,
+}
+
+impl Fixtures {
+    /// An empty set building into the directory.
+    pub fn new<P: AsRef<Path>>(output: P) -> Fixtures {
+        Fixtures { output: output.as_ref().to_path_buf(), sources: BTreeMap::new() }
+    }
+
+    /// The fixtures bundled with the crate, building into its `target/bin`.
+    /// Their names and flags are the ones in the `Makefile`.
+    pub fn bundled() -> Fixtures {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let test = root.join("test");
+        let (names, flags) = parse_makefile(include_str!("../test/Makefile"));
+
+        let mut fixtures = Fixtures::new(root.join("target/bin"));
+        for name in names {
+            let source = test.join(format!("{}.s", name));
+            let source = match source.exists() {
+                true => source,
+                false => test.join(format!("{}.c", name)),
+            };
+            let flags = flags.get(name).map(Vec::as_slice).unwrap_or_default();
+            fixtures.register(name, source, flags);
+        }
+        fixtures
+    }
+
+    /// Register a C or assembly source (by its `.s` or `.S` extension) under
+    /// a name, replacing an earlier source of the same name.
+    pub fn register<P: AsRef<Path>>(&mut self, name: &str, source: P, flags: &[&str]) {
+        self.sources.insert(name.to_string(), Source {
+            path: source.as_ref().to_path_buf(),
+            flags: flags.iter().map(|flag| flag.to_string()).collect(),
+        });
+    }
+
+    /// The names of the registered fixtures.
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.sources.keys().map(String::as_str)
+    }
+
+    /// The path of the built fixture, building it first if it is missing or
+    /// older than its source.
+    pub fn path(&self, name: &str) -> io::Result<PathBuf> {
+        let source = self.sources.get(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no fixture with this name")
+        })?;
+        let binary = self.output.join(name);
+
+        let _building = BUILDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !is_fresh(&binary, &source.path) {
+            build(source, &binary)?;
+        }
+        Ok(binary)
+    }
+
+    /// Build all fixtures that are not up to date.
+    pub fn build_all(&self) -> io::Result<()> {
+        for name in self.sources.keys() {
+            self.path(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// The names of the fixtures in a `Makefile` and the flags set for some of
+/// them by lines like `$(target)/bin/guard: flags := -fstack-protector`.
+fn parse_makefile(makefile: &str) -> (Vec<&str>, HashMap<&str, Vec<&str>>) {
+    let mut names = Vec::new();
+    let mut flags = HashMap::new();
+    let mut lines = makefile.lines();
+    while let Some(line) = lines.next() {
+        if let Some(list) = line.strip_prefix("names :=") {
+            // The list continues over the lines ending in a backslash.
+            let mut line = list;
+            loop {
+                let continued = line.ends_with('\\');
+                names.extend(line.trim_end_matches('\\').split_whitespace());
+                match (continued, lines.next()) {
+                    (true, Some(next)) => line = next,
+                    _ => break,
+                }
+            }
+        } else if let Some(rule) = line.strip_prefix("$(target)/bin/") {
+            if let Some((name, list)) = rule.split_once(": flags :=") {
+                flags.insert(name, list.split_whitespace().collect());
+            }
+        }
+    }
+    (names, flags)
+}
+
+/// Whether the binary exists and is not older than the source.
+fn is_fresh(binary: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(binary), modified(source)) {
+        (Ok(binary), Ok(source)) => binary >= source,
+        _ => false,
+    }
+}
+
+/// Build the source into a temporary file next to the binary and move it in
+/// place once it is complete.
+fn build(source: &Source, binary: &Path) -> io::Result<()> {
+    if let Some(dir) = binary.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = binary.with_extension("partial");
+    let assembly = matches!(
+        source.path.extension().and_then(|ext| ext.to_str()),
+        Some("s") | Some("S")
+    );
+
+    if assembly {
+        run(Command::new("as").args(&source.flags).arg("-o").arg(&temp).arg(&source.path))?;
+        run(Command::new("strip").arg(&temp))?;
+    } else {
+        run(Command::new("gcc")
+            .args(["-nostdlib", "-fcf-protection=none"])
+            .args(&source.flags)
+            .arg("-o").arg(&temp)
+            .arg(&source.path))?;
+    }
+    fs::rename(&temp, binary)
+}
+
+/// Run a build tool and fail with its diagnostics if it fails.
+fn run(command: &mut Command) -> io::Result<()> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(format!("{:?} failed: {}", command, stderr.trim())))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let dir = std::env::temp_dir().join(format!("symflow-fixtures-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("exit.s"), ".globl _start\n_start:\n    mov $60, %eax\n    syscall\n")
+            .unwrap();
+        fs::write(dir.join("broken.c"), "int main( {").unwrap();
+
+        let mut fixtures = Fixtures::new(dir.join("bin"));
+        fixtures.register("exit", dir.join("exit.s"), &[]);
+        fixtures.register("broken", dir.join("broken.c"), &["-w"]);
+        assert_eq!(fixtures.names().collect::<Vec<_>>(), ["broken", "exit"]);
+
+        let exit = fixtures.path("exit").unwrap();
+        assert_eq!(&fs::read(&exit).unwrap()[.. 4], b"\x7fELF");
+        assert!(is_fresh(&exit, &dir.join("exit.s")));
+        assert!(fixtures.path("broken").is_err());
+        assert!(!dir.join("bin/broken").exists());
+        assert_eq!(fixtures.path("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bundled() {
+        let fixtures = Fixtures::bundled();
+        let names: Vec<&str> = fixtures.names().collect();
+        assert!(names.contains(&"block-1") && names.contains(&"init"));
+        assert!(names.iter().all(|name| fixtures.sources[*name].path.exists()));
+        assert!(fixtures.sources["min"].path.ends_with("test/min.s"));
+        assert_eq!(fixtures.sources["unwind"].flags, ["-fexceptions"]);
+        assert_eq!(fixtures.sources["guard"].flags, ["-fcf-protection=full", "-fstack-protector"]);
+        assert!(fixtures.sources["bufs"].flags.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! Compact adjacency storage for graph edges.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+
+/// Edges between numbered nodes in compressed sparse row form.
+///
+/// The edges are sorted by start and end node, so that the outgoing edges of
+/// a node are a contiguous slice and the edges between two nodes can be found
+/// by binary search. A second index over the end nodes makes the incoming
+/// edges just as cheap. Multiple edges between the same nodes are kept in the
+/// order they were added in.
+#[derive(Debug, Clone)]
+pub struct Adjacency<E> {
+    /// The outgoing edges of node `n` are `offsets[n] .. offsets[n + 1]`.
+    offsets: Vec<usize>,
+    /// The end node of each edge.
+    targets: Vec<usize>,
+    /// The data of each edge.
+    edges: Vec<E>,
+    /// The incoming edges of node `n` are `reverse_offsets[n] .. reverse_offsets[n + 1]`.
+    reverse_offsets: Vec<usize>,
+    /// The start node and edge index of each incoming edge.
+    sources: Vec<(usize, usize)>,
+}
+
+impl<E> Adjacency<E> {
+    /// Build the storage for a number of nodes from (start, end, edge) triples.
+    pub fn new(count: usize, entries: Vec<(usize, usize, E)>) -> Adjacency<E> {
+        let mut entries = entries;
+        entries.sort_by_key(|&(start, end, _)| (start, end));
+
+        let mut offsets = vec![0; count + 1];
+        let mut reverse_offsets = vec![0; count + 1];
+        for &(start, end, _) in &entries {
+            offsets[start + 1] += 1;
+            reverse_offsets[end + 1] += 1;
+        }
+        for node in 0 .. count {
+            offsets[node + 1] += offsets[node];
+            reverse_offsets[node + 1] += reverse_offsets[node];
+        }
+
+        let mut targets = Vec::with_capacity(entries.len());
+        let mut edges = Vec::with_capacity(entries.len());
+        let mut sources = vec![(0, 0); entries.len()];
+        let mut filled = reverse_offsets.clone();
+        for (index, (start, end, edge)) in entries.into_iter().enumerate() {
+            sources[filled[end]] = (start, index);
+            filled[end] += 1;
+            targets.push(end);
+            edges.push(edge);
+        }
+
+        Adjacency { offsets, targets, edges, reverse_offsets, sources }
+    }
+
+    /// Build the storage from the edge map representation.
+    pub fn from_map(count: usize, map: HashMap<(usize, usize), Vec<E>>) -> Adjacency<E> {
+        let entries = map.into_iter()
+            .flat_map(|((start, end), edges)| edges.into_iter().map(move |e| (start, end, e)))
+            .collect();
+        Adjacency::new(count, entries)
+    }
+
+    /// The number of nodes.
+    pub fn node_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// The number of edges.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Whether there are no edges.
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// The outgoing edges of the node with their end nodes.
+    pub fn successors(&self, node: usize) -> impl Iterator<Item=(usize, &E)> {
+        self.outgoing(node).map(move |index| (self.targets[index], &self.edges[index]))
+    }
+
+    /// The incoming edges of the node with their start nodes.
+    pub fn predecessors(&self, node: usize) -> impl Iterator<Item=(usize, &E)> {
+        let range = match node < self.node_count() {
+            true => self.reverse_offsets[node] .. self.reverse_offsets[node + 1],
+            false => 0 .. 0,
+        };
+        self.sources[range].iter().map(move |&(start, index)| (start, &self.edges[index]))
+    }
+
+    /// The edges from the start to the end node.
+    pub fn between(&self, start: usize, end: usize) -> &[E] {
+        let range = self.outgoing(start);
+        let targets = &self.targets[range.clone()];
+        let from = targets.partition_point(|&target| target < end);
+        let to = targets.partition_point(|&target| target <= end);
+        &self.edges[range.start + from .. range.start + to]
+    }
+
+    /// Whether there is any edge from the start to the end node.
+    pub fn contains(&self, start: usize, end: usize) -> bool {
+        !self.between(start, end).is_empty()
+    }
+
+    /// All edges as ((start, end), edge) sorted by start and end node.
+    pub fn iter(&self) -> impl Iterator<Item=((usize, usize), &E)> {
+        (0 .. self.node_count())
+            .flat_map(move |start| self.successors(start).map(move |(end, e)| ((start, end), e)))
+    }
+
+    /// The distinct (start, end) pairs that have edges, sorted.
+    pub fn pairs(&self) -> impl Iterator<Item=(usize, usize)> + '_ {
+        let mut last = None;
+        self.iter()
+            .map(|(pair, _)| pair)
+            .filter(move |&pair| last.replace(pair) != Some(pair))
+    }
+
+    /// The indices of the outgoing edges of the node.
+    fn outgoing(&self, node: usize) -> Range<usize> {
+        match node < self.node_count() {
+            true => self.offsets[node] .. self.offsets[node + 1],
+            false => 0 .. 0,
+        }
+    }
+}
+
+impl<E: Clone> Adjacency<E> {
+    /// The edges in the map representation used before this storage existed.
+    pub fn to_map(&self) -> HashMap<(usize, usize), Vec<E>> {
+        let mut map: HashMap<(usize, usize), Vec<E>> = HashMap::new();
+        for (pair, edge) in self.iter() {
+            map.entry(pair).or_default().push(edge.clone());
+        }
+        map
+    }
+}
+
+impl<E> Default for Adjacency<E> {
+    fn default() -> Adjacency<E> {
+        Adjacency::new(0, Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacency() {
+        let graph = Adjacency::new(4, vec![
+            (2, 0, 'a'), (0, 1, 'b'), (0, 3, 'c'), (0, 1, 'd'), (3, 0, 'e'),
+        ]);
+        assert_eq!(graph.len(), 5);
+        assert_eq!(graph.successors(0).collect::<Vec<_>>(), [(1, &'b'), (1, &'d'), (3, &'c')]);
+        assert_eq!(graph.predecessors(0).collect::<Vec<_>>(), [(2, &'a'), (3, &'e')]);
+        assert_eq!(graph.between(0, 1), ['b', 'd']);
+        assert!(graph.between(1, 0).is_empty() && graph.between(7, 0).is_empty());
+        assert!(graph.contains(2, 0) && !graph.contains(0, 2));
+        assert_eq!(graph.pairs().collect::<Vec<_>>(), [(0, 1), (0, 3), (2, 0), (3, 0)]);
+        assert_eq!(graph.predecessors(1).count(), 2);
+
+        let map = graph.to_map();
+        assert_eq!(map[&(0, 1)], ['b', 'd']);
+        let rebuilt = Adjacency::from_map(4, map);
+        assert_eq!(rebuilt.iter().count(), 5);
+        assert_eq!(rebuilt.between(3, 0), ['e']);
+    }
+}
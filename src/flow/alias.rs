@@ -4,9 +4,10 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display, Formatter};
 use std::rc::Rc;
 
-use crate::math::{SymExpr, SymCondition, Integer, DataType, SharedSolver, Solver};
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol, SharedSolver, Solver};
 use crate::sym::{SymState, MemoryStrategy, SymbolMap, TypedMemoryAccess};
-use super::{ControlFlowGraph, ValueSource, AbstractLocation};
+use crate::x86_64::Register;
+use super::{ControlFlowGraph, ValueSource, AbstractLocation, MemoryClass, StackVariable};
 use DataType::*;
 
 
@@ -46,7 +47,7 @@ impl<'g> AliasExplorer<'g> {
             cfg,
             target,
             map: HashMap::new(),
-            solver: Rc::new(Solver::new()),
+            solver: Rc::new(Solver::with_seed(cfg.config.seed)),
         }
     }
 
@@ -61,7 +62,7 @@ impl<'g> AliasExplorer<'g> {
         // - with no pre conditions (these are generated at conditional jumps)
         // - a blank state
         // - no address yet for the target access
-        let base_state = SymState::new(MemoryStrategy::ConditionalTrees, self.solver.clone());
+        let base_state = self.cfg.base_state(MemoryStrategy::ConditionalTrees, self.solver.clone());
 
         let mut targets = vec![ExplorationTarget {
             target: 0,
@@ -78,7 +79,7 @@ impl<'g> AliasExplorer<'g> {
             let block = &self.cfg.blocks[&node.addr];
 
             // Simulate a basic block.
-            for (addr, len, instruction, microcode) in &block.code {
+            for (addr, len, _, microcode) in &block.code {
                 let addr = *addr;
                 let next_addr = addr + len;
 
@@ -92,7 +93,7 @@ impl<'g> AliasExplorer<'g> {
 
                     exp.target_access = Some((access, exp.preconditions.len()));
                 } else {
-                    for (source, _) in instruction.flows() {
+                    for &(source, _) in &microcode.flows {
                         if let ValueSource::Storage(storage) = source {
                             if let Some(access) = exp.state.get_access_for_storage(storage) {
                                 let trace = exp.state.trace.clone();
@@ -103,7 +104,7 @@ impl<'g> AliasExplorer<'g> {
                     }
                 }
 
-                exp.state.track(&instruction, addr);
+                exp.state.track(microcode, addr);
 
                 // Execute the microcode for the instruction.
                 for op in &microcode.ops {
@@ -120,9 +121,12 @@ impl<'g> AliasExplorer<'g> {
                     let mut preconditions = exp.preconditions.clone();
                     preconditions.push(evaluated);
 
+                    let mut state = exp.state.clone();
+                    self.cfg.edge_kind((exp.target, id)).enter(&mut state);
+
                     targets.push(ExplorationTarget {
                         target: id,
-                        state: exp.state.clone(),
+                        state,
                         preconditions,
                         target_access: exp.target_access.clone(),
                     });
@@ -208,6 +212,133 @@ impl<'g> AliasExplorer<'g> {
     }
 }
 
+/// A quick, flow-insensitive answer to whether two memory accesses alias.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AliasResult {
+    /// The accesses never share a byte.
+    No,
+    /// Whether the accesses share bytes depends on the symbolic values.
+    May,
+    /// The accesses surely share some, but not all bytes of the second one.
+    Partial,
+    /// The first access surely contains all bytes of the second one.
+    Must,
+}
+
+/// A recovered object that memory accesses are attributed to.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MemoryObject {
+    /// A variable in the stack frame of a function called through the trace.
+    Stack(Vec<u64>, StackVariable),
+    /// The memory allocated at an allocation site.
+    Heap(usize),
+    /// The global data at a fixed address.
+    Global(u64),
+}
+
+/// Decides aliasing between memory accesses without the solver where possible.
+///
+/// Addresses are split into a base and a constant offset. Accesses with the
+/// same base are compared by their offsets. Different bases only rule out
+/// aliasing if they point into different regions: the stack, which is based
+/// on the initial stack pointer, the fixed addresses of global data and the
+/// heap regions of the individual allocation sites.
+#[derive(Debug, Clone)]
+pub struct AliasOracle {
+    stack: Option<SymExpr>,
+}
+
+/// A region of memory an address can point into.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Region {
+    Global,
+    Stack,
+    Heap(usize),
+    Unknown,
+}
+
+impl AliasOracle {
+    /// Create an oracle for executions starting in the state.
+    pub fn new(state: &SymState) -> AliasOracle {
+        let rsp = state.get_reg(Register::RSP);
+        AliasOracle { stack: split_address(&rsp).0.cloned() }
+    }
+
+    /// Whether and how the access `a` aliases with the access `b`.
+    pub fn check(&self, a: &TypedMemoryAccess, b: &TypedMemoryAccess) -> AliasResult {
+        let (base_a, offset_a) = split_address(&a.0);
+        let (base_b, offset_b) = split_address(&b.0);
+
+        if base_a == base_b {
+            let end_a = offset_a.wrapping_add(a.1.bytes() as i64);
+            let end_b = offset_b.wrapping_add(b.1.bytes() as i64);
+            if end_a <= offset_b || end_b <= offset_a {
+                AliasResult::No
+            } else if offset_a <= offset_b && end_b <= end_a {
+                AliasResult::Must
+            } else {
+                AliasResult::Partial
+            }
+        } else {
+            match (self.region(base_a), self.region(base_b)) {
+                (Region::Unknown, _) | (_, Region::Unknown) => AliasResult::May,
+                (x, y) if x != y => AliasResult::No,
+                _ => AliasResult::May,
+            }
+        }
+    }
+
+    /// The class of memory an address points into. Fixed addresses are
+    /// classified as global data.
+    pub fn class(&self, addr: &SymExpr) -> MemoryClass {
+        match self.region(split_address(addr).0) {
+            Region::Global => MemoryClass::Global,
+            Region::Stack => MemoryClass::Stack,
+            Region::Heap(_) => MemoryClass::Heap,
+            Region::Unknown => MemoryClass::Unknown,
+        }
+    }
+
+    /// The heap allocation or global data an address points into. Stack
+    /// addresses need the stack frames to be attributed to a variable.
+    pub fn object(&self, addr: &SymExpr) -> Option<MemoryObject> {
+        let (base, offset) = split_address(addr);
+        match self.region(base) {
+            Region::Global => Some(MemoryObject::Global(offset as u64)),
+            Region::Heap(site) => Some(MemoryObject::Heap(site)),
+            Region::Stack | Region::Unknown => None,
+        }
+    }
+
+    /// The region an address with the base points into.
+    fn region(&self, base: Option<&SymExpr>) -> Region {
+        match base {
+            None => Region::Global,
+            Some(base) if Some(base) == self.stack.as_ref() => Region::Stack,
+            Some(SymExpr::Sym(Symbol(_, "heap", site))) => Region::Heap(*site),
+            Some(_) => Region::Unknown,
+        }
+    }
+}
+
+/// Split an address into a symbolic base (if any) and a constant offset.
+fn split_address(addr: &SymExpr) -> (Option<&SymExpr>, i64) {
+    match addr {
+        SymExpr::Int(Integer(_, value)) => (None, *value as i64),
+        SymExpr::Add(base, offset) | SymExpr::Sub(base, offset) => match **offset {
+            SymExpr::Int(Integer(_, value)) => {
+                let (base, inner) = split_address(base);
+                match addr {
+                    SymExpr::Add(..) => (base, inner.wrapping_add(value as i64)),
+                    _ => (base, inner.wrapping_sub(value as i64)),
+                }
+            },
+            _ => (Some(addr), 0),
+        },
+        _ => (Some(addr), 0),
+    }
+}
+
 /// Returns the condition under which `a` contains any byte from `b`.
 pub(crate) fn determine_alias(a: &TypedMemoryAccess, b: &TypedMemoryAccess) -> SymCondition {
     if a == b { return SymCondition::TRUE; }
@@ -267,12 +398,12 @@ mod tests {
     use std::io::Write;
     use crate::Program;
     use crate::x86_64::Register;
-    use crate::math::Symbol;
+    use crate::sym::HeapRegion;
     use crate::flow::StorageLocation;
     use super::*;
 
     fn test(filename: &str, location: AbstractLocation) -> AliasMap {
-        let path = format!("target/bin/{}", filename);
+        let path = crate::fixtures::fixture(filename);
 
         let program = Program::new(path);
         let graph = ControlFlowGraph::new(&program);
@@ -318,6 +449,38 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn oracle() {
+        let stack = SymExpr::Sym(Symbol(N64, "reg", 0));
+        let pointer = SymExpr::Sym(Symbol(N64, "stdin", 0));
+        let oracle = AliasOracle { stack: Some(stack.clone()) };
+        let access = |addr: SymExpr, data_type| TypedMemoryAccess(addr, data_type);
+        let local = |offset| access(stack.clone().sub(SymExpr::from_ptr(offset)), N32);
+
+        assert_eq!(oracle.check(&local(8), &local(8)), AliasResult::Must);
+        assert_eq!(oracle.check(&local(8), &local(6)), AliasResult::Partial);
+        assert_eq!(oracle.check(&local(8), &local(4)), AliasResult::No);
+        assert_eq!(oracle.check(&local(8), &access(stack.clone().sub(SymExpr::from_ptr(6)), N8)),
+                   AliasResult::Must);
+
+        let global = access(SymExpr::from_ptr(0x4000), N64);
+        assert_eq!(oracle.check(&global, &access(SymExpr::from_ptr(0x4004), N8)), AliasResult::Must);
+        assert_eq!(oracle.check(&global, &local(8)), AliasResult::No);
+        assert_eq!(oracle.check(&global, &access(pointer.clone(), N8)), AliasResult::May);
+        let pointer_access = access(pointer, N8);
+        assert_eq!(oracle.check(&local(8), &pointer_access), AliasResult::May);
+
+        let heap = |site| access(HeapRegion::base_of(site).add(SymExpr::from_ptr(8)), N64);
+        assert_eq!(oracle.check(&heap(0x1000), &heap(0x1000)), AliasResult::Must);
+        assert_eq!(oracle.check(&heap(0x1000), &heap(0x1010)), AliasResult::No);
+        assert_eq!(oracle.check(&heap(0x1000), &global), AliasResult::No);
+
+        assert_eq!(oracle.class(&local(8).0), MemoryClass::Stack);
+        assert_eq!(oracle.class(&heap(0x1000).0), MemoryClass::Heap);
+        assert_eq!(oracle.class(&global.0), MemoryClass::Global);
+        assert_eq!(oracle.class(&pointer_access.0), MemoryClass::Unknown);
+    }
+
     #[test]
     fn alias_paths() {
         test("paths", AbstractLocation {
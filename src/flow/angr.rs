@@ -0,0 +1,273 @@
+//! Exchange of control flow graphs with angr.
+//!
+//! The JSON model follows the nodes and edges of angr's `CFGFast`: nodes are
+//! blocks with an address, a size, the containing function and the addresses
+//! of their instructions, and edges carry a VEX jump kind. A script on the
+//! angr side can build the model from `cfg.graph` or a `networkx` graph
+//! from the model.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use crate::Program;
+use crate::helper::json_string;
+use crate::json::Json;
+use crate::math::SymCondition;
+use crate::sym::SymConfig;
+use super::{ControlFlowGraph, ControlFlowNode, BasicBlock, StackFrames, EdgeKind, Literal};
+use super::interrupt_frames;
+
+
+impl ControlFlowGraph {
+    /// Write the graph as JSON in angr's `CFGFast` model.
+    ///
+    /// Nodes are identified by their index since a block may appear in
+    /// several call traces. Calls and returns have the jump kinds `Ijk_Call`
+    /// and `Ijk_Ret`, all other edges including the ones that are not
+    /// regular control flow have `Ijk_Boring`. The reconstructed switches
+    /// follow with their index expression, cases and default target, and
+    /// then the strings and constants the instructions refer to.
+    pub fn write_angr_json<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"nodes\": [")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let block = self.blocks.get(&node.addr);
+            let size = block.map_or(0, |block| block.len);
+            let instructions: Vec<String> = block.into_iter()
+                .flat_map(|block| block.code.iter())
+                .map(|(addr, ..)| addr.to_string())
+                .collect();
+            let name = self.frames.frames.get(&node.addr)
+                .map_or("null".to_string(), |frame| json_string(&frame.name));
+            let function = self.frames.functions_at(node.addr).first()
+                .map_or("null".to_string(), u64::to_string);
+            let separator = if index + 1 < self.nodes.len() { "," } else { "" };
+            writeln!(f, "    {{\"id\": {}, \"addr\": {}, \"size\": {}, \"name\": {}, \
+                         \"function_address\": {}, \"instruction_addrs\": [{}]}}{}",
+                     index, node.addr, size, name, function, instructions.join(", "), separator)?;
+        }
+        writeln!(f, "  ],")?;
+
+        let mut edges: Vec<_> = self.edges.keys().copied().collect();
+        edges.sort();
+
+        writeln!(f, "  \"edges\": [")?;
+        for (index, &(start, end)) in edges.iter().enumerate() {
+            let jumpkind = match EdgeKind::of_control(self, (start, end)) {
+                EdgeKind::Call => "Ijk_Call",
+                EdgeKind::Return => "Ijk_Ret",
+                _ => "Ijk_Boring",
+            };
+            let site = self.blocks.get(&self.nodes[start].addr)
+                .and_then(|block| block.code.last())
+                .map_or("null".to_string(), |(addr, ..)| addr.to_string());
+            let separator = if index + 1 < edges.len() { "," } else { "" };
+            writeln!(f, "    {{\"src\": {}, \"dst\": {}, \"jumpkind\": {}, \"ins_addr\": {}}}{}",
+                     start, end, json_string(jumpkind), site, separator)?;
+        }
+        writeln!(f, "  ],")?;
+
+        // The switches are no part of angr's model and ignored on import.
+        writeln!(f, "  \"switches\": [")?;
+        for (index, switch) in self.switches.iter().enumerate() {
+            let cases: Vec<String> = switch.cases.iter()
+                .map(|(value, target)| format!("{{\"value\": {}, \"target\": {}}}", value, target))
+                .collect();
+            let default = switch.default.map_or("null".to_string(), |default| default.to_string());
+            let separator = if index + 1 < self.switches.len() { "," } else { "" };
+            writeln!(f, "    {{\"node\": {}, \"ins_addr\": {}, \"index\": {}, \"cases\": [{}], \
+                         \"default\": {}}}{}",
+                     switch.node, switch.site, json_string(&switch.index.to_string()),
+                     cases.join(", "), default, separator)?;
+        }
+        writeln!(f, "  ],")?;
+
+        // And neither are the literals the instructions refer to.
+        let mut literals: Vec<_> = self.literals().into_iter().collect();
+        literals.sort_by_key(|&(site, _)| site);
+        writeln!(f, "  \"literals\": [")?;
+        for (index, (site, (addr, literal))) in literals.iter().enumerate() {
+            let (kind, value) = match literal {
+                Literal::String(string) => ("string", string.as_str()),
+                Literal::Constant(name) => ("constant", *name),
+            };
+            let separator = if index + 1 < literals.len() { "," } else { "" };
+            writeln!(f, "    {{\"ins_addr\": {}, \"addr\": {}, \"kind\": {}, \"value\": {}}}{}",
+                     site, addr, json_string(kind), json_string(value), separator)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "}}")
+    }
+
+    /// Build the graph from JSON in angr's `CFGFast` model instead of
+    /// recovering it, with the blocks decoded from the program.
+    ///
+    /// Node references of edges are the `id` of the nodes or their position
+    /// if they have none. Addresses may be numbers or hexadecimal strings.
+    /// Nodes of the same block are merged since angr's nodes have no call
+    /// traces, so all nodes of the result are in the empty trace and the
+    /// conditions of all edges are true. Fake returns, which angr adds from a
+    /// call to the instruction after it, become regular edges.
+    ///
+    /// An empty memory image in the configuration is replaced by the one of
+    /// the program, like for `with_config`.
+    pub fn from_angr_json(
+        program: &Program,
+        json: &str,
+        mut config: SymConfig,
+    ) -> Result<ControlFlowGraph, ImportError> {
+        let json = Json::parse(json).map_err(|err| ImportError::new(err.to_string()))?;
+        let field = |value: &Json, key: &str| -> Result<u64, ImportError> {
+            value.get(key).and_then(Json::as_u64)
+                .ok_or_else(|| ImportError::new(format!("missing or invalid \"{}\"", key)))
+        };
+        let array = |key: &str| {
+            json.get(key).and_then(Json::as_array)
+                .ok_or_else(|| ImportError::new(format!("missing \"{}\" array", key)))
+        };
+
+        // Decode the blocks from the code of the program.
+        let mut blocks = HashMap::new();
+        let mut ids = HashMap::new();
+        let mut order = Vec::new();
+        for (position, value) in array("nodes")?.iter().enumerate() {
+            let addr = field(value, "addr")?;
+            let len = field(value, "size")?;
+            let id = match value.get("id") {
+                Some(_) => field(value, "id")?,
+                None => position as u64,
+            };
+            ids.insert(id, addr);
+
+            if blocks.contains_key(&addr) {
+                continue;
+            }
+            let code: Vec<_> = program.code.iter()
+                .filter(|(start, ..)| addr <= *start && *start < addr + len)
+                .cloned()
+                .collect();
+            if code.first().map(|(start, ..)| *start) != Some(addr) {
+                return Err(ImportError::new(format!("no instruction at block {:#x}", addr)));
+            }
+            blocks.insert(addr, BasicBlock { addr, len, code });
+            order.push(addr);
+        }
+
+        // Place the block of the first root first.
+        let roots = program.roots();
+        let first = order.iter().position(|&addr| addr == roots[0])
+            .ok_or_else(|| ImportError::new(format!("no block at root {:#x}", roots[0])))?;
+        order[.. first + 1].rotate_right(1);
+
+        let nodes: Vec<_> = order.iter()
+            .map(|&addr| ControlFlowNode { addr, trace: Vec::new() })
+            .collect();
+        let indices: HashMap<u64, usize> = order.iter().enumerate()
+            .map(|(index, &addr)| (addr, index))
+            .collect();
+
+        let mut edges = HashMap::new();
+        for value in array("edges")? {
+            let node = |key| -> Result<usize, ImportError> {
+                let id = field(value, key)?;
+                ids.get(&id).map(|addr| indices[addr])
+                    .ok_or_else(|| ImportError::new(format!("edge to unknown node {}", id)))
+            };
+            let edge = (node("src")?, node("dst")?);
+            edges.insert(edge, SymCondition::TRUE);
+        }
+
+        if config.memory.image.is_empty() {
+            config.memory.image = Arc::new(program.image.clone());
+        }
+        let frames = StackFrames::with_threads(program, &nodes, &roots, config.threads);
+
+        Ok(ControlFlowGraph {
+            config: Arc::new(config),
+            handlers: interrupt_frames(program),
+            frames: Arc::new(frames),
+            .. ControlFlowGraph::from_parts(nodes, blocks, edges, roots)
+        })
+    }
+}
+
+/// The error of importing a graph that does not fit the program.
+#[derive(Clone, Eq, PartialEq)]
+pub struct ImportError {
+    pub message: String,
+}
+
+impl ImportError {
+    fn new(message: String) -> ImportError {
+        ImportError { message }
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Failed to import graph: {}", self.message)
+    }
+}
+
+impl std::error::Error for ImportError {}
+debug_display!(ImportError);
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use super::*;
+
+    #[test]
+    fn angr_json() {
+        let node = |addr, trace| ControlFlowNode { addr, trace };
+        let cfg = ControlFlowGraph::from_parts(
+            vec![node(0x1000, vec![]), node(0x1010, vec![(0x1004, 0x1010)])],
+            HashMap::new(),
+            vec![((0, 1), SymCondition::TRUE), ((1, 0), SymCondition::TRUE)]
+                .into_iter().collect(),
+            vec![0x1000],
+        );
+
+        let mut f = Vec::new();
+        cfg.write_angr_json(&mut f).unwrap();
+        let text = String::from_utf8(f).unwrap();
+        assert_eq!(text, "\
+            {\n  \"nodes\": [\n    \
+            {\"id\": 0, \"addr\": 4096, \"size\": 0, \"name\": null, \
+             \"function_address\": null, \"instruction_addrs\": []},\n    \
+            {\"id\": 1, \"addr\": 4112, \"size\": 0, \"name\": null, \
+             \"function_address\": null, \"instruction_addrs\": []}\n  ],\n  \
+            \"edges\": [\n    \
+            {\"src\": 0, \"dst\": 1, \"jumpkind\": \"Ijk_Call\", \"ins_addr\": null},\n    \
+            {\"src\": 1, \"dst\": 0, \"jumpkind\": \"Ijk_Ret\", \"ins_addr\": null}\n  ],\n  \
+            \"switches\": [\n  ],\n  \
+            \"literals\": [\n  ]\n}\n");
+        assert!(Json::parse(&text).is_ok());
+    }
+
+    #[test]
+    fn angr_round_trip() {
+        let program = Program::new(crate::fixtures::fixture("bufs"));
+        let cfg = ControlFlowGraph::new(&program);
+
+        let mut f = Vec::new();
+        cfg.write_angr_json(&mut f).unwrap();
+        let json = String::from_utf8(f).unwrap();
+        let imported = ControlFlowGraph::from_angr_json(&program, &json, SymConfig::default())
+            .unwrap();
+
+        let flow = |cfg: &ControlFlowGraph| cfg.edges.keys()
+            .map(|&(start, end)| (cfg.nodes[start].addr, cfg.nodes[end].addr))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(imported.nodes[0].addr, cfg.nodes[0].addr);
+        assert_eq!(imported.blocks.len(), cfg.blocks.len());
+        assert_eq!(flow(&imported), flow(&cfg));
+
+        let broken = json.replace("\"src\": 0,", "\"src\": 100000,");
+        assert!(ControlFlowGraph::from_angr_json(&program, &broken, SymConfig::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Annotations of the analysis results for disassemblers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::helper::json_string;
+use crate::x86_64::{Mnemoic, Operand};
+use super::{ControlFlowGraph, ControlKind, ProgramDependenceGraph, DependenceNode};
+
+
+/// The results of an analysis attached to the addresses of the program, in
+/// the form the scripts of disassemblers apply them.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    /// The name of the analyzed binary.
+    pub binary: String,
+    /// The names of the recovered functions by entry address.
+    pub functions: BTreeMap<u64, String>,
+    /// The resolved targets of indirect jumps and calls by the address of the
+    /// jumping instruction.
+    pub targets: BTreeMap<u64, BTreeSet<u64>>,
+    /// The data dependences of the instructions by address.
+    pub comments: BTreeMap<u64, BTreeSet<String>>,
+    /// The addresses of the instructions in a slice, which are highlighted.
+    pub slice: BTreeSet<u64>,
+    /// Labels like the taint of the data at the instructions by address.
+    pub labels: BTreeMap<u64, BTreeSet<String>>,
+}
+
+impl Annotations {
+    /// Collect the functions, indirect targets and referenced literals of the
+    /// control flow graph and the data dependences of the program dependence
+    /// graph.
+    pub fn new(
+        binary: &str,
+        cfg: &ControlFlowGraph,
+        pdg: &ProgramDependenceGraph,
+    ) -> Annotations {
+        let functions = cfg.frames.frames.values()
+            .map(|frame| (frame.function, frame.name.clone()))
+            .collect();
+
+        let mut targets: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        for &(start, end) in cfg.edges.keys() {
+            if cfg.edge_kind((start, end)) != ControlKind::Flow {
+                continue;
+            }
+            let block = &cfg.blocks[&cfg.nodes[start].addr];
+            if let Some((addr, _, instruction, _)) = block.code.last() {
+                let indirect = matches!(instruction.mnemoic, Mnemoic::Jmp | Mnemoic::Call)
+                    && instruction.operands.first().map_or(false, |op| {
+                        matches!(op, Operand::Direct(_)) || op.accesses_memory()
+                    });
+                if indirect {
+                    targets.entry(*addr).or_default().insert(cfg.nodes[end].addr);
+                }
+            }
+        }
+
+        let mut comments: BTreeMap<u64, BTreeSet<String>> = BTreeMap::new();
+        for ((start, end), _) in pdg.edges.iter() {
+            if let (DependenceNode::DataDependency(source), DependenceNode::DataDependency(sink))
+                = (&pdg.nodes[start], &pdg.nodes[end]) {
+                if source.addr != sink.addr {
+                    let sink_name = pdg.frames.describe(sink).to_string();
+                    let source_name = pdg.frames.describe(source).to_string();
+                    comments.entry(sink.addr).or_default()
+                        .insert(format!("{} depends on {}", sink_name, source_name));
+                }
+            }
+        }
+
+        // The strings and constants the instructions load are the landmarks
+        // of the listing.
+        let mut labels: BTreeMap<u64, BTreeSet<String>> = BTreeMap::new();
+        for (addr, (_, literal)) in cfg.literals() {
+            labels.entry(addr).or_default().insert(literal.annotation());
+        }
+
+        Annotations {
+            binary: binary.to_string(),
+            functions,
+            targets,
+            comments,
+            slice: BTreeSet::new(),
+            labels,
+        }
+    }
+
+    /// Add the addresses of the nodes of the program dependence graph to the
+    /// slice.
+    pub fn add_slice(&mut self, pdg: &ProgramDependenceGraph, nodes: &BTreeSet<usize>) {
+        self.slice.extend(nodes.iter().map(|&node| pdg.nodes[node].addr()));
+    }
+
+    /// Attach a label to the instruction at the address.
+    pub fn add_label(&mut self, addr: u64, label: &str) {
+        self.labels.entry(addr).or_default().insert(label.to_string());
+    }
+
+    /// Write a GhidraScript in Python that creates the functions, adds the
+    /// resolved targets as computed references with a bookmark and the data
+    /// dependences as end-of-line comments.
+    pub fn write_ghidra_script<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "# Applies the results of symflow for {}.", self.binary)?;
+        writeln!(f, "#@category symflow")?;
+        writeln!(f, "from java.awt import Color")?;
+        writeln!(f, "from ghidra.program.model.symbol import RefType, SourceType")?;
+        writeln!(f)?;
+        self.write_python_lists(&mut f)?;
+        f.write_all(GHIDRA_APPLY.as_bytes())
+    }
+
+    /// Write an IDAPython script that creates the functions, adds the
+    /// resolved targets as code cross references, colors the slice and
+    /// writes the labels and data dependences as comments.
+    pub fn write_ida_script<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "# Applies the results of symflow for {}.", self.binary)?;
+        writeln!(f, "import ida_funcs, ida_idp, ida_xref, idc")?;
+        writeln!(f)?;
+        self.write_python_lists(&mut f)?;
+        f.write_all(IDA_APPLY.as_bytes())
+    }
+
+    /// Write a Binary Ninja plugin that registers a command applying the
+    /// results to the open view: it creates the functions, sets the resolved
+    /// targets as indirect branches, highlights the slice and writes the
+    /// labels and data dependences as comments.
+    pub fn write_binja_plugin<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "# Applies the results of symflow for {}.", self.binary)?;
+        writeln!(f, "from binaryninja import HighlightStandardColor, PluginCommand")?;
+        writeln!(f)?;
+        self.write_python_lists(&mut f)?;
+        f.write_all(BINJA_APPLY.as_bytes())
+    }
+
+    /// Write a radare2 script that creates the functions, adds the resolved
+    /// targets as code references, flags the slice, writes the labels and
+    /// data dependences as comments and loads the control flow graph as a
+    /// custom graph, which `agg` shows like `agf` does for a function.
+    pub fn write_r2_script<W: Write>(&self, mut f: W, cfg: &ControlFlowGraph) -> io::Result<()> {
+        writeln!(f, "# Applies the results of symflow for {}.", r2_text(&self.binary))?;
+        for (entry, name) in &self.functions {
+            writeln!(f, "af {} @ {:#x}", r2_name(name), entry)?;
+        }
+        for (site, targets) in &self.targets {
+            for target in targets {
+                writeln!(f, "axc {:#x} @ {:#x}", target, site)?;
+            }
+        }
+        for addr in &self.slice {
+            writeln!(f, "f symflow.slice_{:x} 1 @ {:#x}", addr, addr)?;
+        }
+        for (addr, notes) in self.notes() {
+            for note in notes {
+                writeln!(f, "CC {} @ {:#x}", r2_text(&note), addr)?;
+            }
+        }
+
+        writeln!(f, "ag-")?;
+        for (index, node) in cfg.nodes.iter().enumerate() {
+            writeln!(f, "agn b{} {:#x}", index, node.addr)?;
+        }
+        let mut edges: Vec<_> = cfg.edges.keys().collect();
+        edges.sort();
+        for (start, end) in edges {
+            writeln!(f, "age b{} b{}", start, end)?;
+        }
+        Ok(())
+    }
+
+    /// The comments of the instructions with the labels first.
+    fn notes(&self) -> BTreeMap<u64, Vec<String>> {
+        let mut notes: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for (&addr, labels) in &self.labels {
+            notes.entry(addr).or_default().extend(labels.iter().cloned());
+        }
+        for (&addr, comments) in &self.comments {
+            notes.entry(addr).or_default().extend(comments.iter().cloned());
+        }
+        notes
+    }
+
+    /// Write the annotations as Python lists shared by all scripts.
+    fn write_python_lists<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "functions = [")?;
+        for (entry, name) in &self.functions {
+            writeln!(f, "    ({:#x}, {}),", entry, json_string(name))?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "targets = [")?;
+        for (site, targets) in &self.targets {
+            writeln!(f, "    ({:#x}, [{}]),", site, hex_list(targets))?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "comments = [")?;
+        for (addr, notes) in self.notes() {
+            writeln!(f, "    ({:#x}, {}),", addr, json_string(&notes.join("\n")))?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "slice = [{}]", hex_list(&self.slice))?;
+        writeln!(f)
+    }
+}
+
+/// The part of the GhidraScript applying the lists.
+const GHIDRA_APPLY: &str = "\
+for entry, name in functions:
+    function = getFunctionAt(toAddr(entry))
+    if function is None:
+        createFunction(toAddr(entry), name)
+    else:
+        function.setName(name, SourceType.ANALYSIS)
+
+for site, targets in targets:
+    instruction = getInstructionAt(toAddr(site))
+    if instruction is None:
+        continue
+    if instruction.getFlowType().isCall():
+        kind = RefType.COMPUTED_CALL
+    else:
+        kind = RefType.COMPUTED_JUMP
+    for target in targets:
+        instruction.addOperandReference(0, toAddr(target), kind, SourceType.ANALYSIS)
+    createBookmark(toAddr(site), \"symflow\", \"%d resolved targets\" % len(targets))
+
+for addr, text in comments:
+    setEOLComment(toAddr(addr), text)
+
+for addr in slice:
+    setBackgroundColor(toAddr(addr), Color(255, 204, 128))
+";
+
+/// The part of the IDAPython script applying the lists.
+const IDA_APPLY: &str = "\
+for entry, name in functions:
+    ida_funcs.add_func(entry)
+    idc.set_name(entry, name, idc.SN_NOWARN)
+
+for site, targets in targets:
+    if ida_idp.is_call_insn(site):
+        kind = ida_xref.fl_CN
+    else:
+        kind = ida_xref.fl_JN
+    for target in targets:
+        ida_xref.add_cref(site, target, kind | ida_xref.XREF_USER)
+    idc.set_cmt(site, \"%d resolved targets\" % len(targets), 0)
+
+for addr, text in comments:
+    idc.set_cmt(addr, text, 1)
+
+for addr in slice:
+    idc.set_color(addr, idc.CIC_ITEM, 0x80ccff)
+";
+
+/// The part of the Binary Ninja plugin applying the lists.
+const BINJA_APPLY: &str = "\
+def apply(bv):
+    for entry, name in functions:
+        bv.create_user_function(entry)
+        function = bv.get_function_at(entry)
+        if function is not None:
+            function.name = name
+
+    for site, targets in targets:
+        for function in bv.get_functions_containing(site):
+            branches = [(function.arch, target) for target in targets]
+            function.set_user_indirect_branches(site, branches)
+
+    for addr, text in comments:
+        bv.set_comment_at(addr, text)
+
+    for addr in slice:
+        for function in bv.get_functions_containing(addr):
+            function.set_user_instr_highlight(addr, HighlightStandardColor.OrangeHighlightColor)
+
+PluginCommand.register(\"symflow\\\\Apply results\", \"Apply the results of symflow\", apply)
+";
+
+/// Replace the characters that radare2 interprets in commands.
+fn r2_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            ';' | '|' | '>' | '<' | '~' | '`' | '#' | '"' | '\n' => ' ',
+            '@' => ':',
+            c => c,
+        })
+        .collect()
+}
+
+/// Turn a name into one radare2 accepts for functions and flags.
+fn r2_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Format addresses as a comma-separated list of hex literals.
+fn hex_list(addrs: &BTreeSet<u64>) -> String {
+    addrs.iter().map(|addr| format!("{:#x}", addr)).collect::<Vec<_>>().join(", ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghidra_script() {
+        let mut annotations = Annotations { binary: "bufs".to_string(), .. Annotations::default() };
+        annotations.functions.insert(0x1000, "main".to_string());
+        annotations.targets.insert(0x1010, vec![0x1100, 0x1200].into_iter().collect());
+        annotations.comments.entry(0x1020).or_default().insert("rax depends on rdi".to_string());
+        annotations.comments.entry(0x1020).or_default().insert("rbx depends on \"x\"".to_string());
+
+        let mut f = Vec::new();
+        annotations.write_ghidra_script(&mut f).unwrap();
+        let script = String::from_utf8(f).unwrap();
+
+        assert!(script.starts_with("# Applies the results of symflow for bufs.\n"));
+        assert!(script.contains("functions = [\n    (0x1000, \"main\"),\n]\n"));
+        assert!(script.contains("targets = [\n    (0x1010, [0x1100, 0x1200]),\n]\n"));
+        assert!(script.contains(
+            "    (0x1020, \"rax depends on rdi\\nrbx depends on \\\"x\\\"\"),\n"));
+        assert!(script.contains("slice = []\n"));
+        assert!(script.ends_with("    setBackgroundColor(toAddr(addr), Color(255, 204, 128))\n"));
+    }
+
+    #[test]
+    fn ida_and_binja_scripts() {
+        let mut annotations = Annotations { binary: "bufs".to_string(), .. Annotations::default() };
+        annotations.targets.insert(0x1010, vec![0x1100].into_iter().collect());
+        annotations.comments.entry(0x1020).or_default().insert("rax depends on rdi".to_string());
+        annotations.add_label(0x1020, "taint: stdin");
+        annotations.slice.extend(vec![0x1020, 0x1000]);
+
+        let render = |write: fn(&Annotations, &mut Vec<u8>) -> io::Result<()>| {
+            let mut f = Vec::new();
+            write(&annotations, &mut f).unwrap();
+            String::from_utf8(f).unwrap()
+        };
+
+        let ida = render(|a, f| a.write_ida_script(f));
+        assert!(ida.contains("targets = [\n    (0x1010, [0x1100]),\n]\n"));
+        assert!(ida.contains("    (0x1020, \"taint: stdin\\nrax depends on rdi\"),\n"));
+        assert!(ida.contains("slice = [0x1000, 0x1020]\n"));
+        assert!(ida.contains("ida_xref.add_cref(site, target, kind | ida_xref.XREF_USER)"));
+
+        let binja = render(|a, f| a.write_binja_plugin(f));
+        assert!(binja.contains("slice = [0x1000, 0x1020]\n"));
+        assert!(binja.contains("function.set_user_indirect_branches(site, branches)"));
+        assert!(binja.ends_with("PluginCommand.register(\"symflow\\\\Apply results\", \
+                                 \"Apply the results of symflow\", apply)\n"));
+    }
+
+    #[test]
+    fn r2_script() {
+        use std::collections::HashMap;
+        use crate::flow::ControlFlowNode;
+        use crate::math::SymCondition;
+
+        let node = |addr| ControlFlowNode { addr, trace: Vec::new() };
+        let cfg = ControlFlowGraph::from_parts(
+            vec![node(0x1000), node(0x1010)],
+            HashMap::new(),
+            vec![((0, 1), SymCondition::TRUE)].into_iter().collect(),
+            vec![0x1000],
+        );
+
+        let mut annotations = Annotations { binary: "bufs".to_string(), .. Annotations::default() };
+        annotations.functions.insert(0x1000, "operator new".to_string());
+        annotations.targets.insert(0x1008, vec![0x1010].into_iter().collect());
+        annotations.comments.entry(0x1010).or_default().insert("rax@0x1010; x | y".to_string());
+        annotations.slice.insert(0x1010);
+
+        let mut f = Vec::new();
+        annotations.write_r2_script(&mut f, &cfg).unwrap();
+        assert_eq!(String::from_utf8(f).unwrap(), "\
+            # Applies the results of symflow for bufs.\n\
+            af operator_new @ 0x1000\n\
+            axc 0x1010 @ 0x1008\n\
+            f symflow.slice_1010 1 @ 0x1010\n\
+            CC rax:0x1010  x   y @ 0x1010\n\
+            ag-\n\
+            agn b0 0x1000\n\
+            agn b1 0x1010\n\
+            age b0 b1\n");
+    }
+}
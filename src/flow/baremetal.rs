@@ -0,0 +1,358 @@
+//! Analysis of bare-metal images, which run without an operating system.
+//!
+//! Programs are otherwise loaded from ELF executables and analyzed as Linux
+//! processes, whose constructors run before the entry point and whose
+//! registers start with unknown values. A bare-metal profile describes the
+//! platform of a raw image instead. It is a JSON object with these members:
+//! - `base`: The address the image is loaded at.
+//! - `entry`: The address where execution starts, the base if it is missing.
+//! - `memory`: The memory map as an array of regions with a `name`, the
+//!   addresses `start` and `end` and a `kind`, which is `rom` for read-only
+//!   memory, `ram` for memory with unknown initial contents or `mmio` for the
+//!   registers of devices.
+//! - `registers`: The initial values of general purpose registers by name,
+//!   like the stack pointer in `{"rsp": "0x20008000"}`. The others start with
+//!   unknown values.
+//! - `vectors`: The addresses of interrupt handlers, which can run at any time
+//!   and are analyzed as extra roots before the entry point. Each returns into
+//!   the next root through an interrupt frame with `iretq` or `iret`.
+//! - `vector_table`: A table listing more interrupt handlers as an object
+//!   with its `layout`, its `address` and optionally the `count` of its
+//!   entries. Instead of the address, `"search": true` searches for the
+//!   table in the image (see `VectorTable`).
+//!
+//! Addresses are numbers or hexadecimal strings. The image is loaded at the
+//! base and is read-only where it lies in `rom` regions, or everywhere without
+//! a memory map. The regions of the memory map are the sections of the
+//! program, which scopes can select code by. As there is no operating system,
+//! system calls are not emulated and stop the paths reaching them.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+use std::ops::Range;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::Program;
+use crate::json::Json;
+use crate::sym::{MemoryImage, MmioRegion, SymConfig};
+use crate::x86_64::{Register, REGISTERS};
+use super::{VectorTable, VectorLayout};
+
+
+/// The platform a bare-metal image runs on.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct BareMetal {
+    /// The address the image is loaded at.
+    pub base: u64,
+    /// The address where execution starts, the base if there is none.
+    pub entry: Option<u64>,
+    pub memory: Vec<MapRegion>,
+    /// The initial values of general purpose registers.
+    pub registers: HashMap<Register, u64>,
+    /// The addresses of the interrupt handlers.
+    pub vectors: Vec<u64>,
+    /// The table of further interrupt handlers in the image if there is one.
+    pub vector_table: Option<VectorTable>,
+}
+
+/// A region of the memory map of a platform.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MapRegion {
+    pub name: String,
+    pub range: Range<u64>,
+    pub kind: MapKind,
+}
+
+/// What is mapped at a region of memory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MapKind {
+    /// Read-only memory like flash.
+    Rom,
+    /// Memory with unknown initial contents.
+    Ram,
+    /// The registers of a device (see `SymConfig::mmio`).
+    Mmio,
+}
+
+impl BareMetal {
+    /// Parse a profile from JSON.
+    pub fn from_json(text: &str) -> Result<BareMetal, BareMetalError> {
+        let json = Json::parse(text).map_err(|err| BareMetalError::new(err.to_string()))?;
+        let invalid = |key: &str| BareMetalError::new(format!("invalid \"{}\"", key));
+        let address = |value: &Json, key: &str| value.as_u64().ok_or_else(|| invalid(key));
+
+        let base = address(json.get("base").ok_or_else(|| invalid("base"))?, "base")?;
+        let entry = json.get("entry").map(|entry| address(entry, "entry")).transpose()?;
+
+        let mut memory = Vec::new();
+        let regions = json.get("memory").map_or(Some(&[][..]), Json::as_array);
+        for region in regions.ok_or_else(|| invalid("memory"))? {
+            memory.push(MapRegion::from_json(region)?);
+        }
+
+        let mut registers = HashMap::new();
+        match json.get("registers") {
+            Some(Json::Object(members)) => {
+                for (name, value) in members {
+                    let unknown = || BareMetalError::new(format!("unknown register \"{}\"", name));
+                    let &(register, _) = REGISTERS.iter()
+                        .find(|&&(_, other)| other == name.as_str())
+                        .ok_or_else(unknown)?;
+                    registers.insert(register, address(value, name)?);
+                }
+            },
+            Some(_) => return Err(invalid("registers")),
+            None => {},
+        }
+
+        let vectors = json.get("vectors").map_or(Some(&[][..]), Json::as_array)
+            .ok_or_else(|| invalid("vectors"))?
+            .iter()
+            .map(|vector| address(vector, "vectors"))
+            .collect::<Result<_, _>>()?;
+        let vector_table = json.get("vector_table").map(parse_table).transpose()?;
+
+        Ok(BareMetal { base, entry, memory, registers, vectors, vector_table })
+    }
+
+    /// Read a profile from a JSON file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BareMetal, BareMetalError> {
+        let text = fs::read_to_string(path).map_err(|err| BareMetalError::new(err.to_string()))?;
+        BareMetal::from_json(&text)
+    }
+
+    /// Create a program from a raw image on the platform, which is all code
+    /// and has neither symbols nor constructors. The handlers of the vector
+    /// table follow the ones of the profile.
+    pub fn program(&self, image: &[u8]) -> Program {
+        let mut memory = MemoryImage::new();
+        for (range, readonly) in self.image_parts(image.len() as u64) {
+            let start = (range.start - self.base) as usize;
+            let bytes = image[start .. start + (range.end - range.start) as usize].to_vec();
+            match readonly {
+                true => memory.insert_readonly(range.start, bytes),
+                false => memory.insert(range.start, bytes),
+            }
+        }
+        let sections = self.memory.iter()
+            .map(|region| (region.name.clone(), region.range.start, region.len()))
+            .collect();
+
+        let mut program = Program {
+            entry: self.entry.unwrap_or(self.base),
+            sections,
+            handlers: self.vectors.clone(),
+            image: memory,
+            .. Program::from_code(self.base, image, HashMap::new())
+        };
+
+        if let Some(table) = &self.vector_table {
+            for handler in table.handlers(&program) {
+                if !program.handlers.contains(&handler) {
+                    program.handlers.push(handler);
+                }
+            }
+        }
+        program
+    }
+
+    /// Split an image of the length at the base into the parts that are
+    /// read-only or writable. The image is read-only where it lies in `rom`
+    /// regions, or everywhere without a memory map.
+    fn image_parts(&self, len: u64) -> Vec<(Range<u64>, bool)> {
+        let end = self.base + len;
+        let mut bounds = vec![self.base, end];
+        for region in &self.memory {
+            for &bound in &[region.range.start, region.range.end] {
+                if self.base < bound && bound < end {
+                    bounds.push(bound);
+                }
+            }
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut parts: Vec<(Range<u64>, bool)> = Vec::new();
+        for pair in bounds.windows(2) {
+            let readonly = self.memory.is_empty() || self.memory.iter().any(|region| {
+                region.kind == MapKind::Rom && region.range.contains(&pair[0])
+            });
+            match parts.last_mut() {
+                Some((range, last)) if *last == readonly => range.end = pair[1],
+                _ => parts.push((pair[0] .. pair[1], readonly)),
+            }
+        }
+        parts
+    }
+
+    /// The configuration of the environment on the platform, which is the
+    /// given one with the device registers and the initial registers of the
+    /// profile. System calls stop the execution since there is no operating
+    /// system (see `SymConfig::bare_metal`).
+    pub fn config(&self, mut config: SymConfig) -> SymConfig {
+        let devices = self.memory.iter()
+            .filter(|region| region.kind == MapKind::Mmio)
+            .map(|region| MmioRegion::new(&region.name, region.range.clone()));
+        config.mmio.extend(devices);
+        config.registers.extend(&self.registers);
+        config.bare_metal = true;
+        config
+    }
+}
+
+/// Parse a vector table from a JSON object.
+fn parse_table(json: &Json) -> Result<VectorTable, BareMetalError> {
+    let invalid = |key: &str| BareMetalError::new(format!("invalid \"{}\" in vector table", key));
+    let layout = json.get("layout")
+        .and_then(Json::as_str)
+        .and_then(VectorLayout::from_name)
+        .ok_or_else(|| invalid("layout"))?;
+    let addr = json.get("address")
+        .map(|addr| addr.as_u64().ok_or_else(|| invalid("address")))
+        .transpose()?;
+    let count = json.get("count")
+        .map(|count| count.as_u64().map(|count| count as usize).ok_or_else(|| invalid("count")))
+        .transpose()?;
+    let search = match json.get("search") {
+        Some(Json::Bool(search)) => *search,
+        Some(_) => return Err(invalid("search")),
+        None => false,
+    };
+    if addr.is_none() && !search {
+        return Err(BareMetalError::new("vector table without \"address\"".to_string()));
+    }
+    Ok(VectorTable { layout, addr, search, count })
+}
+
+impl MapRegion {
+    /// Parse a region from a JSON object.
+    fn from_json(json: &Json) -> Result<MapRegion, BareMetalError> {
+        let member = |key: &str| json.get(key)
+            .ok_or_else(|| BareMetalError::new(format!("region without \"{}\"", key)));
+        let address = |key: &str| member(key)?.as_u64()
+            .ok_or_else(|| BareMetalError::new(format!("invalid \"{}\" in region", key)));
+
+        let name = member("name")?.as_str()
+            .ok_or_else(|| BareMetalError::new("invalid \"name\" in region".to_string()))?;
+        let (start, end) = (address("start")?, address("end")?);
+        if end < start {
+            return Err(BareMetalError::new(format!("\"end\" before \"start\" in {}", name)));
+        }
+        let kind = match member("kind")?.as_str() {
+            Some("rom") => MapKind::Rom,
+            Some("ram") => MapKind::Ram,
+            Some("mmio") => MapKind::Mmio,
+            _ => return Err(BareMetalError::new(format!("invalid \"kind\" in {}", name))),
+        };
+        Ok(MapRegion { name: name.to_string(), range: start .. end, kind })
+    }
+
+    /// The number of bytes in the region.
+    fn len(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+}
+
+/// An error reading a bare-metal profile.
+pub struct BareMetalError {
+    pub message: String,
+}
+
+impl BareMetalError {
+    fn new(message: String) -> BareMetalError {
+        BareMetalError { message }
+    }
+}
+
+impl Display for BareMetalError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid bare-metal profile: {}", self.message)
+    }
+}
+
+impl std::error::Error for BareMetalError {}
+debug_display!(BareMetalError);
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::math::{Solver, SymExpr, DataType};
+    use crate::sym::{MemoryStrategy, StdioKind, UnsupportedSyscall};
+    use super::super::{ControlFlowGraph, DataDependencyGraph, DependencyNode, MemoryClass};
+    use super::super::StopReason;
+    use super::*;
+
+    const PROFILE: &str = r#"{
+        "base": "0x10000",
+        "entry": "0x10004",
+        "memory": [
+            {"name": "flash", "start": "0x10000", "end": "0x20000", "kind": "rom"},
+            {"name": "sram", "start": "0x20000000", "end": "0x20008000", "kind": "ram"},
+            {"name": "uart", "start": "0x40000000", "end": "0x40001000", "kind": "mmio"}
+        ],
+        "registers": {"rsp": "0x20008000"},
+        "vectors": ["0x10000"]
+    }"#;
+
+    #[test]
+    fn bare_metal() {
+        let profile = BareMetal::from_json(PROFILE).unwrap();
+        assert_eq!(profile.memory[1].kind, MapKind::Ram);
+        assert_eq!(profile.registers[&Register::RSP], 0x2000_8000);
+
+        // handler: xor eax, eax; iretq
+        // entry: mov ebx, 0x40000000; mov edi, [rbx]; mov eax, 60; syscall
+        let image = [
+            0x31, 0xc0, 0x48, 0xcf,
+            0xbb, 0x00, 0x00, 0x00, 0x40, 0x8b, 0x3b, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05,
+        ];
+        let program = profile.program(&image);
+        assert_eq!(program.roots(), vec![0x10000, 0x10004]);
+        assert!(program.image.is_readonly(0x10004));
+        let mut memory = profile.memory.clone();
+        memory[0].range.start = 0x10004;
+        let name = "boot".to_string();
+        memory.push(MapRegion { name, range: 0x10000 .. 0x10004, kind: MapKind::Ram });
+        let mixed = BareMetal { memory, .. profile.clone() }.program(&image);
+        assert!(!mixed.image.is_readonly(0x10000) && mixed.image.is_readonly(0x10004));
+        assert!(program.sections.contains(&("uart".to_string(), 0x4000_0000, 0x1000)));
+        let config = profile.config(SymConfig::default());
+        assert_eq!(config.mmio, vec![MmioRegion::new("uart", 0x4000_0000 .. 0x4000_1000)]);
+
+        // The handler returns into the entry point on the configured stack
+        // through an interrupt frame.
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        assert!(cfg.addresses.contains_key(&0x10000) && cfg.addresses.contains_key(&0x10004));
+        let state = cfg.base_state(MemoryStrategy::PerfectMatches, Rc::new(Solver::new()));
+        assert_eq!(state.get_reg(Register::RSP), SymExpr::from_ptr(0x2000_7fd8));
+        let saved = state.memory[0].read_expr(SymExpr::from_ptr(0x2000_7ff0), DataType::N64);
+        assert_eq!(saved, SymExpr::from_ptr(0x2000_8000));
+
+        // The value is read from the device, and the system call stops the
+        // path since there is no system to exit to.
+        let reason = StopReason::Syscall(UnsupportedSyscall::NoSystem);
+        assert!(cfg.stops.iter().any(|stop| stop.addr == 0x10010 && stop.reason == reason));
+        let ddg = DataDependencyGraph::new(&cfg);
+        let read = ddg.nodes.iter()
+            .position(|node| matches!(node, DependencyNode::Io(StdioKind::MmioRead, _)))
+            .unwrap();
+        let register = ddg.outgoing[read][0];
+        assert_eq!(ddg.class(register), Some(MemoryClass::Device));
+        assert_eq!(ddg.describe_inputs(ddg.inputs.keys()), vec!["register 0x0 of uart"]);
+
+        let error = |text| BareMetal::from_json(text).unwrap_err().message;
+        assert_eq!(error(r#"{"entry": 1}"#), "invalid \"base\"");
+        assert_eq!(error(r#"{"base": 1, "registers": {"pc": 1}}"#), "unknown register \"pc\"");
+        assert_eq!(error(r#"{"base": 1, "vector_table": {"layout": "gdt"}}"#),
+                   "invalid \"layout\" in vector table");
+        assert_eq!(error(r#"{"base": 1, "vector_table": {"layout": "idt"}}"#),
+                   "vector table without \"address\"");
+        assert_eq!(error(r#"{"base": 1, "memory": [{"name": "io", "start": 1, "end": 2}]}"#),
+                   "region without \"kind\"");
+    }
+}
@@ -0,0 +1,179 @@
+//! Runtime checks inserted by the compiler.
+//!
+//! Stack protectors, the undefined behavior sanitizer and the address
+//! sanitizer guard operations with a branch to a call of a runtime handler,
+//! which reports the failed check. Calls of these handlers are recognized by
+//! their names and never entered: the calling block becomes a check node of
+//! the graph, after which the program aborts. Handlers of recoverable checks
+//! return through a summary instead, so that the program goes on.
+//!
+//! The inputs that can make a check fail are the ones the conditions of the
+//! edges into its node depend on, which are found through a backward slice
+//! from the deciding branches.
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+use crate::math::SymCondition;
+use super::{ControlFlowGraph, ProgramDependenceGraph};
+
+
+/// The kind of a runtime check inserted by the compiler.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum CheckKind {
+    /// A changed stack canary, reported by `__stack_chk_fail`.
+    StackProtector,
+    /// Undefined behavior, reported by the `__ubsan_handle_*` functions.
+    UndefinedBehavior,
+    /// An invalid memory access found through the shadow memory, reported by
+    /// the `__asan_report_*` functions.
+    AddressSanitizer,
+    /// An indirect call whose target is not in the jump tables of the
+    /// module, checked by `__cfi_slowpath` across modules.
+    ControlFlowIntegrity,
+}
+
+impl CheckKind {
+    /// The kind of check the handler with the symbol name reports if any.
+    pub fn from_name(name: &str) -> Option<CheckKind> {
+        match name {
+            "__stack_chk_fail" | "__stack_chk_fail_local" => Some(CheckKind::StackProtector),
+            _ if name.starts_with("__ubsan_handle_") => Some(CheckKind::UndefinedBehavior),
+            _ if name.starts_with("__asan_report_") => Some(CheckKind::AddressSanitizer),
+            "__cfi_slowpath" | "__cfi_slowpath_diag" => Some(CheckKind::ControlFlowIntegrity),
+            _ => None,
+        }
+    }
+
+    /// Whether the handler with the symbol name returns after reporting. The
+    /// handlers of the undefined behavior sanitizer do so unless they end in
+    /// `_abort` and the ones of the address sanitizer only if they end in
+    /// `_noabort`. The slow path of control flow integrity returns for valid
+    /// targets.
+    pub fn recovers(name: &str) -> bool {
+        match CheckKind::from_name(name) {
+            Some(CheckKind::ControlFlowIntegrity) => true,
+            Some(CheckKind::UndefinedBehavior) => !name.ends_with("_abort"),
+            Some(CheckKind::AddressSanitizer) => name.ends_with("_noabort"),
+            _ => false,
+        }
+    }
+}
+
+/// A call of the handler of a failed runtime check.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct RuntimeCheck {
+    /// The node of the block calling the handler.
+    pub node: usize,
+    /// The address of the call.
+    pub site: u64,
+    /// The address of the handler.
+    pub handler: u64,
+    pub kind: CheckKind,
+}
+
+impl ControlFlowGraph {
+    /// The check node with the index if it is one.
+    pub fn check_at(&self, node: usize) -> Option<&RuntimeCheck> {
+        self.checks.iter().find(|check| check.node == node)
+    }
+
+    /// The condition under which the check fails, which is the disjunction
+    /// of the conditions of the edges into its node.
+    pub fn check_condition(&self, check: &RuntimeCheck) -> SymCondition {
+        self.incoming[check.node].iter()
+            .fold(SymCondition::FALSE, |condition, &pred| {
+                condition.or(self.edges[&(pred, check.node)].clone())
+            })
+    }
+
+    /// The addresses of the conditional jumps deciding whether the check
+    /// fails, sorted.
+    pub fn check_branches(&self, check: &RuntimeCheck) -> Vec<u64> {
+        let mut branches: Vec<u64> = self.incoming[check.node].iter()
+            .filter(|&&pred| self.edges[&(pred, check.node)] != SymCondition::TRUE)
+            .filter_map(|&pred| self.blocks[&self.nodes[pred].addr].code.last())
+            .map(|&(addr, ..)| addr)
+            .collect();
+        branches.sort_unstable();
+        branches.dedup();
+        branches
+    }
+}
+
+impl ProgramDependenceGraph {
+    /// The nodes that the branches deciding whether the check fails depend
+    /// on, including the ones of the branches. The inputs among them are the
+    /// ones that can trigger the check.
+    pub fn check_slice(&self, cfg: &ControlFlowGraph, check: &RuntimeCheck) -> BTreeSet<usize> {
+        cfg.check_branches(check).into_iter()
+            .flat_map(|addr| self.backward_slice(addr))
+            .collect()
+    }
+}
+
+impl Display for CheckKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            CheckKind::StackProtector => "stack protector",
+            CheckKind::UndefinedBehavior => "undefined behavior",
+            CheckKind::AddressSanitizer => "address sanitizer",
+            CheckKind::ControlFlowIntegrity => "control flow integrity",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::{DataDependencyGraph, DependenceNode, ControlKind};
+    use super::*;
+
+    #[test]
+    fn checks() {
+        let program = Program::new(fixture("checks"));
+        let cfg = ControlFlowGraph::new(&program);
+        let mut kinds: Vec<CheckKind> = cfg.checks.iter().map(|check| check.kind).collect();
+        kinds.sort_unstable();
+        assert_eq!(kinds, vec![CheckKind::StackProtector, CheckKind::UndefinedBehavior]);
+
+        // The handlers are never entered and only the one of the overflow
+        // check returns.
+        for check in &cfg.checks {
+            assert!(cfg.nodes.iter().all(|node| node.addr != check.handler));
+            assert!(!cfg.check_branches(check).is_empty());
+            let returns = cfg.outgoing[check.node].iter().any(|&succ| {
+                matches!(cfg.edge_kind((check.node, succ)), ControlKind::Check { .. })
+            });
+            assert_eq!(returns, check.kind == CheckKind::UndefinedBehavior);
+        }
+
+        // The overflow depends on the value of the variable.
+        let ddg = DataDependencyGraph::new(&cfg);
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+        let overflow = cfg.checks.iter()
+            .find(|check| check.kind == CheckKind::UndefinedBehavior)
+            .unwrap();
+        let slice = pdg.check_slice(&cfg, overflow);
+        let locations = slice.iter().filter_map(|&node| match &pdg.nodes[node] {
+            DependenceNode::DataDependency(location) => Some(location),
+            _ => None,
+        });
+        assert_eq!(ddg.describe_inputs(locations), vec!["env LEVEL".to_string()]);
+    }
+
+    #[test]
+    fn handlers() {
+        assert_eq!(CheckKind::from_name("__stack_chk_fail"), Some(CheckKind::StackProtector));
+        assert_eq!(CheckKind::from_name("__asan_report_load8"), Some(CheckKind::AddressSanitizer));
+        assert_eq!(CheckKind::from_name("__stack_chk_guard"), None);
+        assert!(CheckKind::recovers("__ubsan_handle_add_overflow"));
+        assert!(!CheckKind::recovers("__ubsan_handle_add_overflow_abort"));
+        assert!(CheckKind::recovers("__asan_report_store4_noabort"));
+        assert!(!CheckKind::recovers("__asan_report_store4"));
+        assert!(!CheckKind::recovers("__stack_chk_fail"));
+        assert!(CheckKind::recovers("__cfi_slowpath"));
+    }
+}
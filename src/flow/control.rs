@@ -1,14 +1,23 @@
 //! Control flow graph calculation.
 
 use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 use std::io::{self, Write};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::Program;
-use crate::ir::{Microcode, MicroEncoder};
-use crate::math::{SymExpr, SymCondition, Integer, DataType, Solver};
-use crate::sym::{SymState, MemoryStrategy, Event};
-use crate::x86_64::{Instruction, Mnemoic};
+use crate::dwarf::Place;
+use crate::flow::{StackFrames, Phase, Cancelled, EdgeKind, VisualConfig, Distances};
+use crate::flow::{CallGraph, RegisterSet, Spawner, Thread, Port, THREAD_EXIT_NAMES};
+use crate::flow::{CheckKind, RuntimeCheck, Switch, switch_bound, interrupt_frames};
+use crate::ir::{Microcode, MicroEncoder, MicroOperation, Location, Transfer, MemoryMapped};
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol, Solver, SharedSolver};
+use crate::parallel::Partition;
+use crate::plugin::Decoder;
+use crate::sym::{SymState, SymConfig, MemoryStrategy, Event, Allocator, InputFunction};
+use crate::sym::{UnknownRead, UnsupportedSyscall, TypedMemoryAccess};
+use crate::x86_64::{Instruction, Register};
 
 
 /// The control flow graph representation of a program.
@@ -21,10 +30,364 @@ pub struct ControlFlowGraph {
     /// The control flow between the nodes. The key pairs are indices
     /// into the `nodes` vector.
     pub edges: HashMap<(usize, usize), SymCondition>,
+    /// The nodes of the blocks at each address in all call traces, sorted.
+    pub addresses: HashMap<u64, Vec<usize>>,
+    /// The kinds of the edges which are not regular control flow.
+    pub kinds: HashMap<(usize, usize), ControlKind>,
     /// The nodes which the node with the index has edges to.
     pub incoming: Vec<Vec<usize>>,
     /// The nodes which have edges to the node with the index.
     pub outgoing: Vec<Vec<usize>>,
+    /// The environment configuration used for all symbolic executions of the
+    /// program, including those of analyses building on this graph.
+    pub config: Arc<SymConfig>,
+    /// The writes into the code of the program.
+    pub code_writes: Vec<CodeWrite>,
+    /// The nodes of bytes that do not decode to instructions, sorted.
+    pub undecoded: Vec<UndecodedBytes>,
+    /// The reads that ended the exploration of their paths under the `Abort`
+    /// concretization policy.
+    pub aborts: Vec<AbortedRead>,
+    /// The instructions the analysis cannot follow, at which the exploration
+    /// of their paths stopped.
+    pub stops: Vec<StoppedPath>,
+    /// The calls of handlers of failed runtime checks, sorted.
+    pub checks: Vec<RuntimeCheck>,
+    /// The switches reconstructed from jumps through tables, sorted by node.
+    pub switches: Vec<Switch>,
+    /// The values of the source variables in scope at the conditional jumps
+    /// ending the nodes, if the program has debugging information.
+    pub variables: HashMap<usize, Vec<(String, SymExpr)>>,
+    /// The addresses where execution starts in order (see `Program::roots`).
+    /// The first root is the first node of the graph.
+    pub roots: Vec<u64>,
+    /// The interrupt handlers among the roots, which return into the next
+    /// root through an interrupt frame instead of a return address, with the
+    /// width of the slots of their frames.
+    pub handlers: Vec<(u64, DataType)>,
+    /// The reconstructed stack frames of the functions in the graph.
+    pub frames: Arc<StackFrames>,
+}
+
+/// A write into the code of the program, which makes the graph unreliable for
+/// the overwritten code.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CodeWrite {
+    /// The node containing the writing instruction.
+    pub node: usize,
+    /// The address of the writing instruction.
+    pub addr: u64,
+    /// The first overwritten address.
+    pub target: u64,
+    /// The number of overwritten bytes.
+    pub len: u64,
+    /// The written bytes if they are known.
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Bytes reached by the exploration that do not decode to an instruction,
+/// like data in code or junk inserted by obfuscators. Their node has a block
+/// without instructions spanning the bytes, which falls through to the next
+/// address where an instruction decodes.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct UndecodedBytes {
+    /// The node of the bytes.
+    pub node: usize,
+    /// The address of the first byte.
+    pub addr: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// A read from unknown memory at a symbolic address in the block of a node,
+/// which ended the exploration of the path under the `Abort` policy.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AbortedRead {
+    pub node: usize,
+    /// The address of the reading instruction.
+    pub addr: u64,
+    pub read: UnknownRead,
+}
+
+/// An instruction in the block of a node at which the exploration of a path
+/// stopped, because the analysis cannot follow it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StoppedPath {
+    pub node: usize,
+    /// The address of the instruction.
+    pub addr: u64,
+    pub reason: StopReason,
+}
+
+/// Why the exploration of a path stopped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StopReason {
+    /// A system call that is not emulated.
+    Syscall(UnsupportedSyscall),
+    /// A jump or return to a target that cannot be resolved.
+    UnresolvedJump(SymExpr),
+    /// A return to an unknown caller outside of the graph, like the one the
+    /// last root ends with.
+    ExternalReturn(SymExpr),
+}
+
+/// How the nodes of a function are told apart by the calls leading to them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ContextPolicy {
+    /// Every call trace without recursion is a context of its own, so that
+    /// each function is cloned for every way it is called.
+    Full,
+    /// All calls of a function share one context.
+    Insensitive,
+    /// The innermost calls up to the given number are the context.
+    CallString(usize),
+    /// Every call trace is a context of its own until a function has the
+    /// given number of contexts. Its further calls share one context.
+    Cloning(usize),
+}
+
+impl Default for ContextPolicy {
+    fn default() -> ContextPolicy {
+        ContextPolicy::Full
+    }
+}
+
+/// The kind of control transfer an edge represents.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ControlKind {
+    /// Regular control flow through jumps, calls and returns.
+    Flow,
+    /// Unwinding from a call into a landing pad of the calling function.
+    Exception,
+    /// Delivery of a signal to a handler registered at `site`, which returns
+    /// to the `resume` address afterwards.
+    Signal { site: u64, resume: u64, signal: u64, handler: u64 },
+    /// Return from a call to the `setjmp` function, which saves the execution context.
+    SetJump { function: u64 },
+    /// Non-local jump from a call to the `longjmp` function back to the return
+    /// of a `setjmp`, leaving all but `depth` call frames.
+    LongJump { function: u64, depth: usize },
+    /// Return from a call at `site` to a function managing heap memory.
+    Allocate { function: u64, site: u64, allocator: Allocator },
+    /// Return from a call at `site` to a function providing input from the
+    /// environment, from files or from sockets.
+    Input { function: u64, site: u64, input: InputFunction },
+    /// Entry into a destructor after the program exited at `site`. The
+    /// destructor returns to the null address.
+    Finalize { site: u64 },
+    /// Return from a recursive call at `site` to `function` deeper than
+    /// allowed, which changes the `clobbered` registers and may write to
+    /// memory the argument registers in `memory` point to.
+    Recursion { function: u64, site: u64, clobbered: RegisterSet, memory: RegisterSet },
+    /// Return from a call to `function` creating a thread, which succeeds.
+    Spawn { function: u64 },
+    /// Entry into the `routine` of a thread created by the call to `function`
+    /// at `site`. The routine returns to the null address.
+    Thread { function: u64, site: u64, routine: u64, spawner: Spawner },
+    /// Return from a call at `site` to the handler of a failed runtime check
+    /// that lets the program go on.
+    Check { function: u64, site: u64, kind: CheckKind },
+    /// Return from a call at `site` to a `function` with a dependence summary
+    /// or outside of the analysis scope, which changes the `clobbered`
+    /// registers and returns a value depending on the argument registers in
+    /// `result`. It may write to memory the argument registers in `memory`
+    /// point to.
+    Summary {
+        function: u64,
+        site: u64,
+        clobbered: RegisterSet,
+        result: RegisterSet,
+        memory: RegisterSet,
+    },
+}
+
+/// The layout of a `jmp_buf` as (offset, register). The stack pointer and the
+/// return address follow the callee-saved registers.
+const JUMP_BUFFER: [(u64, Register); 6] = [
+    (0, Register::RBX), (8, Register::RBP), (16, Register::R12),
+    (24, Register::R13), (32, Register::R14), (40, Register::R15),
+];
+
+/// The names of functions saving and restoring execution contexts.
+const SETJMP_NAMES: &[&str] = &["setjmp", "_setjmp", "__setjmp", "sigsetjmp", "__sigsetjmp"];
+const LONGJMP_NAMES: &[&str] = &["longjmp", "_longjmp", "siglongjmp", "__longjmp_chk"];
+
+impl ControlKind {
+    /// Whether the edge returns from a call that is summarized instead of
+    /// followed into the callee.
+    pub fn is_summary(self) -> bool {
+        use ControlKind::*;
+        matches!(self, SetJump { .. } | Allocate { .. } | Input { .. } | Recursion { .. }
+            | Spawn { .. } | Check { .. } | Summary { .. })
+    }
+
+    /// Turn the state at the end of the source of an edge of this kind into
+    /// the state the target is entered with. Returns the memory a summarized
+    /// call writes unknown values to.
+    pub fn enter(self, state: &mut SymState) -> Vec<(Symbol, TypedMemoryAccess)> {
+        use Register::*;
+        match self {
+            ControlKind::Flow => {},
+
+            // The unwinder removes the return address of the call and passes
+            // the exception object and the type selector.
+            ControlKind::Exception => {
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp);
+                state.trace.pop();
+                for &reg in &[RAX, RDX] {
+                    let value = state.unknown(DataType::N64);
+                    state.set_reg(reg, value);
+                }
+            },
+
+            // The handler is called with the signal number like a function.
+            ControlKind::Signal { site, resume, signal, handler } => {
+                let rsp = state.get_reg(RSP).sub(SymExpr::from_ptr(8));
+                state.memory[0].write_expr(rsp.clone(), SymExpr::from_ptr(resume));
+                state.set_reg(RSP, rsp);
+                let config = state.config.clone();
+                let signal = SymExpr::from_int(DataType::N64, signal);
+                config.convention(handler).set_argument(state, 0, signal);
+                state.trace.push(site);
+            },
+
+            // Save the context into the buffer (without pointer mangling) and
+            // return zero.
+            ControlKind::SetJump { function } => {
+                let config = state.config.clone();
+                let convention = config.convention(function);
+                let env = convention.argument(state, 0, DataType::N64);
+
+                let ret = state.memory[0].read_expr(state.get_reg(RSP), DataType::N64);
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp.clone());
+                state.trace.pop();
+
+                let at = |offset| env.clone().add(SymExpr::from_ptr(offset));
+                for &(offset, reg) in &JUMP_BUFFER {
+                    let value = state.get_reg(reg);
+                    state.memory[0].write_expr(at(offset), value);
+                }
+                state.memory[0].write_expr(at(48), rsp);
+                state.memory[0].write_expr(at(56), ret);
+                convention.set_result(state, SymExpr::from_int(DataType::N64, 0));
+            },
+
+            // Restore the context from the buffer and return the value passed to
+            // `longjmp` from `setjmp`, but never zero.
+            ControlKind::LongJump { function, depth } => {
+                let config = state.config.clone();
+                let convention = config.convention(function);
+                let env = convention.argument(state, 0, DataType::N64);
+                let value = convention.argument(state, 1, DataType::N32);
+                let at = |offset| env.clone().add(SymExpr::from_ptr(offset));
+                for &(offset, reg) in &JUMP_BUFFER {
+                    let saved = state.memory[0].read_expr(at(offset), DataType::N64);
+                    state.set_reg(reg, saved);
+                }
+                let rsp = state.memory[0].read_expr(at(48), DataType::N64);
+                state.set_reg(RSP, rsp);
+                state.trace.truncate(depth);
+
+                let one = SymExpr::from_int(DataType::N32, 1);
+                let zero = value.clone().equal(SymExpr::from_int(DataType::N32, 0));
+                convention.set_result(state, zero.if_then_else(one, value).cast(DataType::N64, false));
+            },
+
+            ControlKind::Allocate { function, site, allocator } => {
+                allocator.call(state, function, site);
+            },
+
+            ControlKind::Input { function, site, input } => {
+                input.call(state, function, site);
+            },
+
+            ControlKind::Finalize { site } => {
+                let rsp = state.get_reg(RSP).sub(SymExpr::from_ptr(8));
+                state.memory[0].write_expr(rsp.clone(), SymExpr::from_ptr(0));
+                state.set_reg(RSP, rsp);
+                state.trace = vec![site];
+            },
+
+            // Return with unknown values in the registers the call may change
+            // and in the memory it may write.
+            ControlKind::Recursion { site, clobbered, memory, .. } => {
+                let writes = state.havoc(site, memory);
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp);
+                state.trace.pop();
+                for reg in clobbered.iter() {
+                    let value = state.unknown(DataType::N64);
+                    state.set_reg(reg, value);
+                }
+                return writes;
+            },
+
+            ControlKind::Spawn { function } => {
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp);
+                state.trace.pop();
+                let config = state.config.clone();
+                config.convention(function).set_result(state, SymExpr::from_int(DataType::N64, 0));
+            },
+
+            // The thread starts without call frames below the stack of the
+            // creating one and gets the argument passed for it.
+            ControlKind::Thread { function, site, routine, spawner } => {
+                let config = state.config.clone();
+                let argument = spawner.argument
+                    .map(|index| config.convention(function).argument(state, index, DataType::N64));
+                let rsp = state.get_reg(RSP).sub(SymExpr::from_ptr(8));
+                state.memory[0].write_expr(rsp.clone(), SymExpr::from_ptr(0));
+                state.set_reg(RSP, rsp);
+                state.trace = vec![site];
+                if let Some(argument) = argument {
+                    config.convention(routine).set_argument(state, 0, argument);
+                }
+            },
+
+            ControlKind::Check { .. } => {
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp);
+                state.trace.pop();
+            },
+
+            // Return with unknown values in the registers the call may change
+            // like for recursion and in the memory it may write.
+            ControlKind::Summary { site, clobbered, memory, .. } => {
+                let writes = state.havoc(site, memory);
+                let rsp = state.get_reg(RSP).add(SymExpr::from_ptr(8));
+                state.set_reg(RSP, rsp);
+                state.trace.pop();
+                for reg in clobbered.iter() {
+                    let value = state.unknown(DataType::N64);
+                    state.set_reg(reg, value);
+                }
+                return writes;
+            },
+        }
+        vec![]
+    }
+}
+
+impl Display for ControlKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ControlKind::Flow => write!(f, "flow"),
+            ControlKind::Exception => write!(f, "exception"),
+            ControlKind::Signal { signal, .. } => write!(f, "signal {}", signal),
+            ControlKind::SetJump { .. } => write!(f, "setjmp"),
+            ControlKind::LongJump { .. } => write!(f, "longjmp"),
+            ControlKind::Allocate { allocator, .. } => write!(f, "{}", allocator.name()),
+            ControlKind::Input { input, .. } => write!(f, "{}", input.name()),
+            ControlKind::Finalize { .. } => write!(f, "finalize"),
+            ControlKind::Recursion { .. } => write!(f, "recursion"),
+            ControlKind::Spawn { .. } => write!(f, "spawn"),
+            ControlKind::Thread { .. } => write!(f, "thread"),
+            ControlKind::Check { kind, .. } => write!(f, "{} check", kind),
+            ControlKind::Summary { .. } => write!(f, "summary"),
+        }
+    }
 }
 
 /// A node in the control flow graph, that is a basic block in some context.
@@ -62,7 +425,255 @@ pub struct BasicBlock {
 impl ControlFlowGraph {
     /// Generate a control flow graph of a program.
     pub fn new(program: &Program) -> ControlFlowGraph {
-        crate::timings::with("control-flow-graph", || ControlFlowExplorer::new(program).run())
+        ControlFlowGraph::with_config(program, SymConfig::default())
+    }
+
+    /// Generate a control flow graph of a program executed in the configured environment.
+    ///
+    /// An empty memory image in the configuration is replaced by the one of the program.
+    ///
+    /// Panics if the analysis is cancelled through the monitor of the
+    /// configuration, use `try_with_config` for cancellable analyses.
+    pub fn with_config(program: &Program, config: SymConfig) -> ControlFlowGraph {
+        ControlFlowGraph::try_with_config(program, config)
+            .expect("control flow graph: analysis was cancelled")
+    }
+
+    /// Generate a control flow graph like `with_config` unless the analysis
+    /// is cancelled through the monitor of the configuration.
+    pub fn try_with_config(
+        program: &Program,
+        mut config: SymConfig,
+    ) -> Result<ControlFlowGraph, Cancelled> {
+        if config.memory.image.is_empty() {
+            config.memory.image = Arc::new(program.image.clone());
+        }
+
+        crate::timings::with("control-flow-graph", || {
+            let _phase = crate::trace::phase("cfg");
+            let config = Arc::new(config);
+
+            // Searches for a target and the order of the contexts cloned
+            // depend on the order of all paths, so they are not split.
+            let sequential = config.target.is_some()
+                || !config.profile.is_empty()
+                || matches!(config.context, ContextPolicy::Cloning(_));
+            let count = match sequential {
+                true => 1,
+                false => crate::parallel::threads(config.threads),
+            };
+            let partitions = Partition::all(count);
+            let explorations = crate::parallel::map(count, &partitions, |&partition| {
+                ControlFlowExplorer::new(program, config.clone(), partition).run()
+            });
+
+            let mut explorations = explorations.into_iter()
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter();
+            let mut exploration = explorations.next().unwrap_or_default();
+            if count > 1 {
+                let solver = Solver::with_seed(config.seed);
+                for other in explorations {
+                    exploration.merge(other, &solver);
+                }
+            }
+            Ok(exploration.finish(program, config))
+        })
+    }
+
+    /// Assemble a graph from its nodes, blocks and edges with the default
+    /// configuration and without any of the findings of an exploration. The
+    /// neighbors of the nodes and their addresses are derived from the edges
+    /// and the nodes.
+    pub fn from_parts(
+        nodes: Vec<ControlFlowNode>,
+        blocks: HashMap<u64, BasicBlock>,
+        edges: HashMap<(usize, usize), SymCondition>,
+        roots: Vec<u64>,
+    ) -> ControlFlowGraph {
+        let mut incoming = vec![Vec::new(); nodes.len()];
+        let mut outgoing = vec![Vec::new(); nodes.len()];
+        for &(start, end) in edges.keys() {
+            outgoing[start].push(end);
+            incoming[end].push(start);
+        }
+        for inc in &mut incoming { inc.sort(); }
+        for out in &mut outgoing { out.sort(); }
+
+        let mut addresses: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            addresses.entry(node.addr).or_default().push(index);
+        }
+
+        ControlFlowGraph {
+            nodes,
+            blocks,
+            edges,
+            addresses,
+            kinds: HashMap::new(),
+            incoming,
+            outgoing,
+            config: Arc::new(SymConfig::default()),
+            code_writes: Vec::new(),
+            undecoded: Vec::new(),
+            aborts: Vec::new(),
+            stops: Vec::new(),
+            checks: Vec::new(),
+            switches: Vec::new(),
+            variables: HashMap::new(),
+            roots,
+            handlers: Vec::new(),
+            frames: Arc::new(StackFrames::default()),
+        }
+    }
+
+    /// Create the state in which the execution of the program starts at the
+    /// first root, to which analyses building on this graph should conform.
+    pub fn base_state(&self, mem_strategy: MemoryStrategy, solver: SharedSolver) -> SymState {
+        let state = SymState::with_config(mem_strategy, solver, self.config.clone());
+        prepare_roots(state, &self.roots, &self.handlers)
+    }
+
+    /// Whether the address lies within a basic block of the graph.
+    pub fn contains_code(&self, addr: u64) -> bool {
+        self.blocks.values().any(|block| block.addr <= addr && addr < block.addr + block.len)
+    }
+
+    /// The undecodable bytes of the node if it is a node of them.
+    pub fn undecoded_at(&self, node: usize) -> Option<&UndecodedBytes> {
+        self.undecoded.iter().find(|undecoded| undecoded.node == node)
+    }
+
+    /// The kind of the edge between the nodes with the indices.
+    pub fn edge_kind(&self, edge: (usize, usize)) -> ControlKind {
+        self.kinds.get(&edge).copied().unwrap_or(ControlKind::Flow)
+    }
+
+    /// The nodes the node with the index has edges to.
+    pub fn successors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        self.outgoing[node].iter().copied()
+    }
+
+    /// The nodes which have edges to the node with the index.
+    pub fn predecessors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        self.incoming[node].iter().copied()
+    }
+
+    /// The edges leaving the node as (end, condition).
+    pub fn edges_from(&self, node: usize) -> impl Iterator<Item=(usize, &SymCondition)> {
+        self.successors(node).map(move |end| (end, &self.edges[&(node, end)]))
+    }
+
+    /// The edges entering the node as (start, condition).
+    pub fn edges_to(&self, node: usize) -> impl Iterator<Item=(usize, &SymCondition)> {
+        self.predecessors(node).map(move |start| (start, &self.edges[&(start, node)]))
+    }
+
+    /// The first node of the block at the address.
+    pub fn node_at(&self, addr: u64) -> Option<usize> {
+        self.nodes_at(addr).first().copied()
+    }
+
+    /// The nodes of the block at the address in all call traces.
+    pub fn nodes_at(&self, addr: u64) -> &[usize] {
+        self.addresses.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// The simple paths from the start to the end node with the conjunctions
+    /// of their edge conditions, up to a limit of paths.
+    ///
+    /// Paths whose condition is false are skipped. With a solver, conditions
+    /// are simplified while the paths are extended, so that unsatisfiable
+    /// prefixes are cut off early.
+    pub fn paths_between(
+        &self,
+        start: usize,
+        end: usize,
+        limit: usize,
+        solver: Option<&Solver>,
+    ) -> Vec<(Vec<usize>, SymCondition)> {
+        let mut paths = Vec::new();
+        let mut path = vec![start];
+        let mut conditions = vec![SymCondition::TRUE];
+        // The position of the next successor to try for each node on the path.
+        let mut next = vec![0];
+        let mut on_path = vec![false; self.nodes.len()];
+        on_path[start] = true;
+
+        while let Some(&node) = path.last() {
+            if paths.len() >= limit {
+                break;
+            }
+
+            let succ = match self.outgoing[node].get(next[next.len() - 1]) {
+                Some(&succ) if node != end => succ,
+                _ => {
+                    if node == end {
+                        paths.push((path.clone(), conditions[conditions.len() - 1].clone()));
+                    }
+                    path.pop();
+                    next.pop();
+                    conditions.pop();
+                    on_path[node] = false;
+                    continue;
+                }
+            };
+
+            *next.last_mut().unwrap() += 1;
+            if on_path[succ] {
+                continue;
+            }
+
+            let edge = &self.edges[&(node, succ)];
+            let mut condition = conditions[conditions.len() - 1].clone().and(edge.clone());
+            if let (Some(solver), false) = (solver, *edge == SymCondition::TRUE) {
+                condition = solver.simplify_condition(&condition);
+            }
+            if condition == SymCondition::FALSE {
+                continue;
+            }
+
+            path.push(succ);
+            next.push(0);
+            conditions.push(condition);
+            on_path[succ] = true;
+        }
+
+        paths
+    }
+
+    /// The nodes whose code is overwritten at some point.
+    pub fn modified_nodes(&self) -> Vec<usize> {
+        (0 .. self.nodes.len())
+            .filter(|&index| {
+                let block = &self.blocks[&self.nodes[index].addr];
+                self.code_writes.iter().any(|write| {
+                    write.target < block.addr + block.len && block.addr < write.target + write.len
+                })
+            })
+            .collect()
+    }
+
+    /// Pairs of addresses of explored instructions whose bytes overlap, that is,
+    /// where the same bytes were decoded starting at different offsets. Both
+    /// decodings are part of the graph as separate blocks.
+    pub fn overlapping_instructions(&self) -> Vec<(u64, u64)> {
+        find_overlaps(self.blocks.values())
+    }
+
+    /// Apply the known writes into the code to the program, so that a
+    /// second-stage graph can be generated from the modified code. Returns
+    /// `None` if there are no such writes.
+    pub fn second_stage(&self, program: &Program) -> Option<Program> {
+        let patches: Vec<_> = self.code_writes.iter()
+            .filter_map(|write| write.bytes.clone().map(|bytes| (write.target, bytes)))
+            .collect();
+
+        if patches.is_empty() {
+            None
+        } else {
+            Some(program.patched(&patches))
+        }
     }
 
     /// Visualize this flow graph in a graphviz DOT file.
@@ -72,11 +683,35 @@ impl ControlFlowGraph {
         program: &Program,
         title: &str,
         style: VisualizationStyle
+    ) -> io::Result<()> {
+        self.visualize_with(target, program, title, style, &VisualConfig::default())
+    }
+
+    /// Visualize this flow graph like `visualize` with the layout, styles and
+    /// instruction listings of the configuration.
+    pub fn visualize_with<W: Write>(
+        &self,
+        target: W,
+        program: &Program,
+        title: &str,
+        style: VisualizationStyle,
+        config: &VisualConfig,
     ) -> io::Result<()> {
         use super::visualize::*;
+        let _phase = crate::trace::phase("render");
         let mut f = target;
 
-        write_header(&mut f, &format!("Control flow graph for {}", title), 20)?;
+        write_header(&mut f, &format!("Control flow graph for {}", title), 20, config)?;
+        let modified = self.modified_nodes();
+        let stripped = match self.config.strip_instrumentation {
+            true => self.instrumentation(),
+            false => HashMap::new(),
+        };
+        let overlapping: Vec<u64> = self.overlapping_instructions().into_iter()
+            .flat_map(|(a, b)| vec![a, b])
+            .collect();
+        let literals = self.literals();
+        let solver: SharedSolver = Rc::new(Solver::with_seed(self.config.seed));
 
         // Export the blocks.
         for (index, node) in self.nodes.iter().enumerate() {
@@ -93,39 +728,131 @@ impl ControlFlowGraph {
                     write!(f, "{:x}", callsite)?;
                 }
             }
+            if let Some(check) = self.check_at(index) {
+                write!(f, " ({} check)", check.kind)?;
+            }
+            if self.undecoded_at(index).is_some() {
+                write!(f, " (undecoded)")?;
+            }
             write!(f, "</b>{}", BR)?;
 
-            if style == VisualizationStyle::Instructions || style == VisualizationStyle::Microcode {
+            if style == VisualizationStyle::Addresses {
+                // Without the instructions, the literals they refer to are
+                // listed instead.
+                let code = self.blocks.get(&node.addr).map_or(&[][..], |block| &block.code);
+                for (addr, ..) in code {
+                    if let Some((_, literal)) = literals.get(addr) {
+                        write!(f, "{}{}", escape(&config.label(literal.annotation())), BR)?;
+                    }
+                }
+            } else if style == VisualizationStyle::Effects {
+                for line in self.block_effect_with(index, &solver).lines() {
+                    write!(f, "{}{}", escape(&config.label(line)), BR)?;
+                }
+            } else {
                 // Write out the body in either micro operations or instructions.
                 let block = &self.blocks[&node.addr];
                 for (addr, _, instruction, microcode) in &block.code {
+                    if stripped.contains_key(addr) {
+                        continue;
+                    }
                     if style == VisualizationStyle::Microcode {
                         for op in &microcode.ops {
                             write!(f, "{:x}: {}{}", addr,
                                 op.to_string().replace("&", "&amp;"), BR)?;
                         }
                     } else {
-                        write!(f, "{:x}: {}{}", addr, instruction, BR)?;
+                        let mut note = match overlapping.contains(addr) {
+                            true => " (overlapping)".to_string(),
+                            false => String::new(),
+                        };
+                        if let Some((_, literal)) = literals.get(addr) {
+                            let annotation = config.label(literal.annotation());
+                            note.push_str(&format!(" ({})", escape(&annotation)));
+                        }
+                        write_instruction(&mut f, *addr, instruction, &config.listing, &note)?;
                     }
                 }
+                if let Some(undecoded) = self.undecoded_at(index) {
+                    let bytes: Vec<String> = undecoded.bytes.iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect();
+                    write!(f, "{:x}: (bad) {}{}", undecoded.addr, bytes.join(" "), BR)?;
+                }
             }
-            write!(f, ">, shape=box")?;
+            write!(f, ">, shape={}", config.block_shape())?;
 
-            // Change the background if this nodes is either a source or sink.
-            if self.outgoing[index].is_empty() || self.incoming[index].is_empty() {
+            // Change the background if this nodes is either overwritten, a
+            // check, a source or a sink.
+            if modified.contains(&index) {
+                write!(f, ", style=filled, fillcolor=\"#ffcccc\"")?;
+            } else if self.check_at(index).is_some() {
+                write!(f, ", style=filled, fillcolor=\"#ffe082\"")?;
+            } else if self.outgoing[index].is_empty() || self.incoming[index].is_empty() {
                 write!(f, ", style=filled, fillcolor=\"#dddddd\"")?;
             }
             writeln!(f, "]")?;
         }
 
-        write_edges(&mut f, &self.edges, |f, (_, condition)| {
+        let conditions = Conditions::new(config);
+        let heaviest = self.edges.keys().map(|&edge| self.edge_weight(edge)).max().unwrap_or(0);
+        write_edges(&mut f, &self.edges, |f, (edge, condition)| {
+            let kind = self.edge_kind(edge);
+            if kind != ControlKind::Flow {
+                write!(f, "label=\"{}\", style=dashed, ", kind)?;
+            } else if let Some(label) = self.case_label(edge) {
+                write!(f, "label=\"{}\", ", label)?;
+            } else if condition != &SymCondition::TRUE {
+                // Name the values of source variables where they are known.
+                let full = conditions.full(condition);
+                let full = self.name_variables(edge.0, &full).unwrap_or(full);
+                write!(f, "label=\"{}\", ", quote(&config.label(full)))?;
+            }
             if condition != &SymCondition::TRUE {
-                write!(f, "label=\"{}\", ", condition)?;
+                write!(f, "{}", conditions.tooltip(condition))?;
+            }
+            // Edges that ran more often according to the profile are wider.
+            if heaviest > 0 {
+                let weight = self.edge_weight(edge);
+                write!(f, "penwidth={:.1}, ", 1.0 + 4.0 * weight as f64 / heaviest as f64)?;
+            }
+            // Configured styles come last to take precedence.
+            if let Some(style) = config.edge_styles.get(&EdgeKind::of_control(self, edge)) {
+                write!(f, "{}, ", style)?;
             }
             Ok(())
         })?;
 
-        write_footer(&mut f)
+        let shape = config.block_shape();
+        let nodes = vec![
+            ("block".to_string(), format!("shape={}", shape)),
+            ("entry or exit".to_string(), filled(shape, "#dddddd")),
+            ("modified code".to_string(), filled(shape, "#ffcccc")),
+            ("runtime check".to_string(), filled(shape, "#ffe082")),
+        ];
+        let mut edges = vec![
+            ("flow".to_string(), String::new()),
+            ("other transfer".to_string(), "style=dashed".to_string()),
+        ];
+        edges.extend(edge_style_legend(config));
+        write_legend(&mut f, config, &nodes, &edges)?;
+
+        write_footer(&mut f, config)
+    }
+
+    /// Write the full conditions of the edges as JSON, naming the nodes like
+    /// the visualization does.
+    pub fn write_conditions<W: Write>(&self, target: W, config: &VisualConfig) -> io::Result<()> {
+        use super::visualize::{write_conditions, Conditions};
+        let mut edges: Vec<_> = self.edges.iter()
+            .filter(|(_, condition)| **condition != SymCondition::TRUE)
+            .collect();
+        edges.sort_by_key(|&(&edge, _)| edge);
+        let name = |index: usize| format!("b{}", index);
+        let edges: Vec<_> = edges.into_iter()
+            .map(|(&(start, end), condition)| (name(start), name(end), condition))
+            .collect();
+        write_conditions(target, &Conditions::new(config), &edges)
     }
 }
 
@@ -138,16 +865,66 @@ pub enum VisualizationStyle {
     Instructions,
     /// Show the whole microcode representation of the instructions.
     Microcode,
+    /// Show the net effect of each block as assignments (see `block_effect`).
+    Effects,
 }
 
 /// Constructs a control flow graph representation of a program.
 #[derive(Clone)]
 struct ControlFlowExplorer<'a> {
     program: &'a Program,
+    config: Arc<SymConfig>,
+    partition: Partition,
     stack: Vec<ExplorationTarget>,
     nodes: HashMap<ControlFlowNode, usize>,
+    /// The nodes with their full call traces, which tell the paths apart
+    /// regardless of the contexts of the graph nodes.
+    contexts: HashMap<ControlFlowNode, usize>,
+    /// The call traces that became contexts of each function with cloning.
+    clones: HashMap<u64, Vec<Vec<(u64, u64)>>>,
     blocks: HashMap<u64, BasicBlock>,
     edges: HashMap<(usize, usize), SymCondition>,
+    kinds: HashMap<(usize, usize), ControlKind>,
+    interrupts: Vec<Interrupt>,
+    setjmps: Vec<(u64, Vec<(u64, u64)>)>,
+    code_writes: Vec<CodeWrite>,
+    undecoded: Vec<UndecodedBytes>,
+    aborts: Vec<AbortedRead>,
+    stops: Vec<StoppedPath>,
+    checks: Vec<RuntimeCheck>,
+    /// The switches found so far with the node of their bounds check.
+    switches: Vec<(usize, Switch)>,
+    variables: HashMap<usize, Vec<(String, SymExpr)>>,
+    distances: Option<Distances>,
+    /// The executions of the blocks in the profile by address, computed once
+    /// they are needed.
+    heat: HashMap<u64, u64>,
+    /// The static call graph for summarizing recursive calls, built once
+    /// it is needed.
+    calls: Option<CallGraph>,
+    /// The stack frames of the functions in the call graph for finding the
+    /// stack slots of source variables, built once they are needed.
+    frames: Option<StackFrames>,
+    /// The threads started so far.
+    threads: Vec<Thread>,
+}
+
+/// The nodes, edges and findings of an explorer, which can be merged with
+/// the ones of other explorers.
+#[derive(Default)]
+struct Exploration {
+    nodes: HashMap<ControlFlowNode, usize>,
+    contexts: HashMap<ControlFlowNode, usize>,
+    blocks: HashMap<u64, BasicBlock>,
+    edges: HashMap<(usize, usize), SymCondition>,
+    kinds: HashMap<(usize, usize), ControlKind>,
+    code_writes: Vec<CodeWrite>,
+    undecoded: Vec<UndecodedBytes>,
+    aborts: Vec<AbortedRead>,
+    stops: Vec<StoppedPath>,
+    checks: Vec<RuntimeCheck>,
+    switches: Vec<(usize, Switch)>,
+    variables: HashMap<usize, Vec<(String, SymExpr)>>,
 }
 
 #[derive(Clone)]
@@ -155,59 +932,120 @@ struct ExplorationTarget {
     node: ControlFlowNode,
     state: SymState,
     path: Vec<usize>,
+    /// The number of branches taken on this path.
+    branches: usize,
+    /// The node the target was reached from and the condition of the edge.
+    entry: Option<(usize, SymCondition)>,
 }
 
 #[derive(Clone)]
 struct Exit {
     target: SymExpr,
     jumpsite: u64,
+    next: u64,
     condition: SymCondition,
     kind: ExitKind,
 }
 
+/// The entry into a signal handler from the middle of a block.
+#[derive(Clone)]
+struct Interrupt {
+    exp: ExplorationTarget,
+    handler: u64,
+    kind: ControlKind,
+}
+
 #[derive(Copy, Clone)]
 enum ExitKind {
     Call,
     Return,
     Jump,
+    /// A jump leaving all but the given number of call frames.
+    LongJump(usize),
+    /// The exit of the program.
+    Terminate,
+    /// The entry into a destructor, which leaves all call frames.
+    Finalize,
+    /// The entry into a thread, which starts without call frames.
+    Spawn,
 }
 
-impl<'a> ControlFlowExplorer<'a> {
-    fn new(program: &'a Program) -> ControlFlowExplorer<'a> {
-        ControlFlowExplorer {
-            program,
-            blocks: HashMap::new(),
-            nodes: HashMap::new(),
-            edges: HashMap::new(),
-            stack: Vec::new(),
-        }
-    }
+/// What a function does with an execution context.
+#[derive(Copy, Clone)]
+enum Context {
+    Save,
+    Restore,
+}
 
-    /// Build the control flow graph.
-    fn run(mut self) -> ControlFlowGraph {
-        let node = ControlFlowNode { addr: self.program.entry, trace: vec![], };
-        let base_state = SymState::new(MemoryStrategy::PerfectMatches, Rc::new(Solver::new()));
+impl Exploration {
+    /// Add the nodes, edges and findings of another explorer, combining the
+    /// conditions of edges found by both.
+    fn merge(&mut self, other: Exploration, solver: &Solver) {
+        let mut sorted: Vec<(ControlFlowNode, usize)> = other.nodes.into_iter().collect();
+        sorted.sort_by_key(|&(_, index)| index);
+        let mut mapping = vec![0; sorted.len()];
+        for (node, index) in sorted {
+            let new_index = self.nodes.len();
+            mapping[index] = *self.nodes.entry(node).or_insert(new_index);
+        }
 
-        self.stack.push(ExplorationTarget {
-            node,
-            state: base_state,
-            path: Vec::new(),
-        });
+        for (node, _) in other.contexts {
+            let new_index = self.contexts.len();
+            self.contexts.entry(node).or_insert(new_index);
+        }
+        for (addr, block) in other.blocks {
+            self.blocks.entry(addr).or_insert(block);
+        }
 
-        while let Some(mut exp) = self.stack.pop() {
-            self.insert_node(exp.node.decycled());
+        for ((start, end), condition) in other.edges {
+            let edge = (mapping[start], mapping[end]);
+            let condition = match self.edges.remove(&edge) {
+                Some(prev) if prev != condition => solver.simplify_condition(&prev.or(condition)),
+                _ => condition,
+            };
+            self.edges.insert(edge, condition);
+        }
+        for ((start, end), kind) in other.kinds {
+            self.kinds.insert((mapping[start], mapping[end]), kind);
+        }
 
-            // Explore this block and find all the ones reachable from this one.
-            if let Some(exit) = self.execute_block(&mut exp) {
-                self.explore_exit(&exp, exit);
+        fn add<T: PartialEq>(items: &mut Vec<T>, item: T) {
+            if !items.contains(&item) {
+                items.push(item);
+            }
+        }
+        for write in other.code_writes {
+            add(&mut self.code_writes, CodeWrite { node: mapping[write.node], .. write });
+        }
+        for undecoded in other.undecoded {
+            let node = mapping[undecoded.node];
+            add(&mut self.undecoded, UndecodedBytes { node, .. undecoded });
+        }
+        for abort in other.aborts {
+            add(&mut self.aborts, AbortedRead { node: mapping[abort.node], .. abort });
+        }
+        for stop in other.stops {
+            add(&mut self.stops, StoppedPath { node: mapping[stop.node], .. stop });
+        }
+        for check in other.checks {
+            add(&mut self.checks, RuntimeCheck { node: mapping[check.node], .. check });
+        }
+        for (branch, switch) in other.switches {
+            let switch = Switch { node: mapping[switch.node], .. switch };
+            if !self.switches.iter().any(|(_, known)| known.node == switch.node) {
+                self.switches.push((mapping[branch], switch));
+            }
+        }
+        for (node, values) in other.variables {
+            let known = self.variables.entry(mapping[node]).or_default();
+            for value in values {
+                add(known, value);
             }
         }
-
-        self.finish()
     }
 
     /// Arrange all data in the way expected for the flow graph.
-    fn finish(self) -> ControlFlowGraph {
+    fn finish(self, program: &Program, config: Arc<SymConfig>) -> ControlFlowGraph {
         // Arrange the nodes into a vector.
         let count = self.nodes.len();
         let mut nodes = vec![ControlFlowNode { addr: 0, trace: Vec::new() }; count];
@@ -227,75 +1065,396 @@ impl<'a> ControlFlowExplorer<'a> {
         for inc in &mut incoming { inc.sort(); }
         for out in &mut outgoing { out.sort(); }
 
+        let mut addresses: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            addresses.entry(node.addr).or_default().push(index);
+        }
+
+        // The frames are found through the full call traces, which the
+        // contexts of the nodes may lack.
+        let mut contexts = vec![ControlFlowNode { addr: 0, trace: Vec::new() }; self.contexts.len()];
+        for (node, index) in self.contexts.into_iter() {
+            contexts[index] = node;
+        }
+        contexts.extend(nodes.iter().cloned());
+
+        let roots = program.roots();
+        let frames = StackFrames::with_threads(program, &contexts, &roots, config.threads);
+
+        let mut checks = self.checks;
+        checks.sort_unstable();
+        let mut undecoded = self.undecoded;
+        undecoded.sort_unstable();
+
+        // Indices beyond the table of a switch go where its bounds check
+        // jumps otherwise.
+        let mut switches: Vec<Switch> = self.switches.into_iter()
+            .map(|(branch, mut switch)| {
+                let default = outgoing[branch].iter()
+                    .find(|&&succ| succ != switch.node)
+                    .map(|&succ| nodes[succ].addr);
+                switch.cases.retain(|&(_, target)| Some(target) != default);
+                switch.default = default;
+                switch
+            })
+            .collect();
+        switches.sort_by_key(|switch| switch.node);
+
         ControlFlowGraph {
             nodes,
             blocks: self.blocks,
             edges: self.edges,
+            addresses,
+            kinds: self.kinds,
             incoming,
             outgoing,
+            config,
+            code_writes: self.code_writes,
+            undecoded,
+            aborts: self.aborts,
+            stops: self.stops,
+            checks,
+            switches,
+            variables: self.variables,
+            roots,
+            handlers: interrupt_frames(program),
+            frames: Arc::new(frames),
+        }
+    }
+}
+
+impl<'a> ControlFlowExplorer<'a> {
+    fn new(
+        program: &'a Program,
+        config: Arc<SymConfig>,
+        partition: Partition,
+    ) -> ControlFlowExplorer<'a> {
+        let distances = config.target.map(|target| Distances::new(program, target));
+        ControlFlowExplorer {
+            program,
+            config,
+            partition,
+            blocks: HashMap::new(),
+            nodes: HashMap::new(),
+            contexts: HashMap::new(),
+            clones: HashMap::new(),
+            edges: HashMap::new(),
+            kinds: HashMap::new(),
+            interrupts: Vec::new(),
+            setjmps: Vec::new(),
+            code_writes: Vec::new(),
+            undecoded: Vec::new(),
+            aborts: Vec::new(),
+            stops: Vec::new(),
+            checks: Vec::new(),
+            switches: Vec::new(),
+            variables: HashMap::new(),
+            stack: Vec::new(),
+            distances,
+            heat: HashMap::new(),
+            calls: None,
+            frames: None,
+            threads: Vec::new(),
+        }
+    }
+
+    /// Explore the paths of the partition.
+    fn run(mut self) -> Result<Exploration, Cancelled> {
+        let roots = self.program.roots();
+        let node = ControlFlowNode { addr: roots[0], trace: vec![], };
+        let solver = Rc::new(Solver::with_seed(self.config.seed));
+        let state = SymState::with_config(MemoryStrategy::PerfectMatches, solver, self.config.clone());
+        let base_state = prepare_roots(state, &roots, &interrupt_frames(self.program));
+
+        self.stack.push(ExplorationTarget {
+            node,
+            state: base_state,
+            path: Vec::new(),
+            branches: 0,
+            entry: None,
+        });
+        let split = self.partition.split();
+
+        while let Some(mut exp) = self.next_target() {
+            self.config.monitor.check()?;
+            let function = exp.node.trace.last().map_or(roots[0], |&(_, function)| function);
+            let name = self.program.symbols.get(&function).map(String::as_str);
+            self.config.monitor.report(Phase::ControlFlow, self.nodes.len(), None, name);
+            if exp.node.addr == function {
+                crate::trace::function(function, name);
+            }
+            crate::trace::block(exp.node.addr, name);
+
+            let node = self.context(&exp.node);
+            let node = self.insert_node(node);
+
+            // Explore this block and find all the ones reachable from this one.
+            let pending = self.stack.len();
+            let exit = self.execute_block(&mut exp, node);
+            if self.reached_target(exp.node.addr) {
+                // The targets found before are already in the graph, so their
+                // blocks are executed as well, but not followed any further.
+                while let Some(mut exp) = self.stack.pop() {
+                    let node = self.context(&exp.node);
+                    let node = self.insert_node(node);
+                    self.execute_block(&mut exp, node);
+                }
+                break;
+            }
+            if let Some(exit) = exit {
+                if exit.condition != SymCondition::TRUE && !self.program.variables.is_empty() {
+                    self.record_variables(&exp, node, exit.jumpsite);
+                }
+                self.explore_exit(&exp, exit);
+            }
+
+            // Enter the signal handlers registered in the block.
+            for Interrupt { exp, handler, kind } in std::mem::take(&mut self.interrupts) {
+                if let ControlKind::Signal { site, .. } = kind {
+                    let condition = SymCondition::TRUE;
+                    self.explore_acyclic(&exp, handler, site, ExitKind::Call, condition, kind);
+                }
+            }
+
+            // Deal out the subtrees starting after the split.
+            let reached = self.stack.split_off(pending);
+            let branches = exp.branches + (reached.len() > 1) as usize;
+            for mut target in reached {
+                target.branches = branches;
+                if branches == split && exp.branches < split && !self.partition.takes() {
+                    continue;
+                }
+                self.stack.push(target);
+            }
+        }
+
+        Ok(Exploration {
+            nodes: self.nodes,
+            contexts: self.contexts,
+            blocks: self.blocks,
+            edges: self.edges,
+            kinds: self.kinds,
+            code_writes: self.code_writes,
+            undecoded: self.undecoded,
+            aborts: self.aborts,
+            stops: self.stops,
+            checks: self.checks,
+            switches: self.switches,
+            variables: self.variables,
+        })
+    }
+
+    /// The next target to explore. Without a target address this is the last
+    /// pushed one and otherwise the one closest to the target address, with
+    /// ties broken in favor of the last pushed one. With a profile and no
+    /// target address, it is the one that ran most often.
+    fn next_target(&mut self) -> Option<ExplorationTarget> {
+        let index = match &self.distances {
+            Some(distances) => self.stack.iter().enumerate().rev()
+                .min_by_key(|(_, exp)| distances.get(exp.node.addr).unwrap_or(usize::MAX))
+                .map(|(index, _)| index)?,
+            None if !self.config.profile.is_empty() => {
+                let (profile, heat) = (&self.config.profile, &mut self.heat);
+                let mut executions = |addr| *heat.entry(addr)
+                    .or_insert_with(|| profile.executions(addr));
+                self.stack.iter().enumerate()
+                    .max_by_key(|(_, exp)| executions(exp.node.addr))
+                    .map(|(index, _)| index)?
+            },
+            None => self.stack.len().checked_sub(1)?,
+        };
+        Some(self.stack.remove(index))
+    }
+
+    /// Whether the explored block at the address contains the target address.
+    fn reached_target(&self, addr: u64) -> bool {
+        match (&self.distances, self.blocks.get(&addr)) {
+            (Some(distances), Some(block)) => {
+                block.code.iter().any(|&(start, ..)| start == distances.target)
+            },
+            _ => false,
+        }
+    }
+
+    /// Parse and execute the basic block determined by the exploration
+    /// target, whose node in the graph has the index, and find its exit.
+    /// Undecodable bytes at the end of the code have none.
+    fn execute_block(&mut self, exp: &mut ExplorationTarget, node: usize) -> Option<Exit> {
+        // Create a new binary parser or reuse an existing block.
+        let mut parser = match self.blocks.get(&exp.node.addr) {
+            Some(block) => BlockParser::from_block(block),
+            None => {
+                let program = self.program;
+                let (binary, decoder) = (&program.binary, &program.decoder);
+                BlockParser::from_binary(binary, program.base, exp.node.addr, decoder)
+            },
+        };
+
+        // Symbolically execute the block until an exit is found.
+        loop {
+            let (addr, len, _, microcode) = match parser.next() {
+                Some(next) => next,
+                None => {
+                    if let Some(block) = parser.export() {
+                        self.blocks.insert(exp.node.addr, block);
+                    }
+                    return self.undecoded_exit(exp, node);
+                },
+            };
+
+            // Execute the microcode.
+            for op in &microcode.ops {
+                let next_addr = addr + len;
+
+                if let Some((target, len, bytes)) = self.find_code_write(&exp.state, op) {
+                    let write = CodeWrite { node, addr: *addr, target, len, bytes };
+                    if !self.code_writes.contains(&write) {
+                        self.code_writes.push(write);
+                    }
+                }
+
+                if let Some(event) = exp.state.step(next_addr, op) {
+                    // The path ends at the read, but other paths go on.
+                    if let Event::Abort(read) = event {
+                        if let Some(block) = parser.export() {
+                            self.blocks.insert(exp.node.addr, block);
+                        }
+                        self.aborts.push(AbortedRead { node, addr: *addr, read });
+                        return None;
+                    }
+                    if let Event::Unsupported(syscall) = event {
+                        if let Some(block) = parser.export() {
+                            self.blocks.insert(exp.node.addr, block);
+                        }
+                        let reason = StopReason::Syscall(syscall);
+                        self.stop(StoppedPath { node, addr: *addr, reason });
+                        return None;
+                    }
+
+                    if let Event::Signal { signal, handler } = event {
+                        if self.config.signals {
+                            let (site, resume) = (*addr, next_addr);
+                            let kind = ControlKind::Signal { site, resume, signal, handler };
+                            self.interrupts.push(Interrupt { exp: exp.clone(), handler, kind });
+                        }
+                        continue;
+                    }
+
+                    if let Some(exit) = self.find_exits(event, microcode, *addr, next_addr) {
+                        if let Some(block) = parser.export() {
+                            self.blocks.insert(exp.node.addr, block);
+                        }
+                        return Some(exit);
+                    }
+                }
+            }
         }
     }
 
-    /// Parse and execute the basic block determined by the exploration
-    /// target and find all exits. Returns `None` if there is no exit to
-    /// a new block, that is, if there was a sys_exit.
-    fn execute_block(&mut self, exp: &mut ExplorationTarget) -> Option<Exit> {
-        // Create a new binary parser or reuse an existing block.
-        let mut parser = match self.blocks.get(&exp.node.addr) {
-            Some(block) => BlockParser::from_block(block),
+    /// The exit of a block running into bytes that do not decode, which falls
+    /// through to them or, for a block of them, to the address after them.
+    fn undecoded_exit(&mut self, exp: &ExplorationTarget, node: usize) -> Option<Exit> {
+        let block = &self.blocks[&exp.node.addr];
+        let next = block.addr + block.len;
+        let jumpsite = match block.code.last() {
+            Some(&(addr, ..)) => addr,
             None => {
-                BlockParser::from_binary(&self.program.binary, self.program.base, exp.node.addr)
+                let start = (block.addr - self.program.base) as usize;
+                let bytes = self.program.binary[start .. start + block.len as usize].to_vec();
+                let undecoded = UndecodedBytes { node, addr: block.addr, bytes };
+                if !self.undecoded.contains(&undecoded) {
+                    self.undecoded.push(undecoded);
+                }
+                block.addr
             },
         };
 
-        // Symbolically execute the block until an exit is found.
-        loop {
-            let (addr, len, instruction, microcode) = parser.next();
+        if next >= self.program.base + self.program.binary.len() as u64 {
+            return None;
+        }
+        Some(Exit {
+            target: SymExpr::from_ptr(next),
+            jumpsite,
+            next,
+            condition: SymCondition::TRUE,
+            kind: ExitKind::Jump,
+        })
+    }
 
-            // Execute the microcode.
-            for op in &microcode.ops {
-                let next_addr = addr + len;
+    /// Determine whether the operation writes into the code of the program and
+    /// return the overwritten region and the written bytes if they are known.
+    fn find_code_write(
+        &self,
+        state: &SymState,
+        op: &MicroOperation,
+    ) -> Option<(u64, u64, Option<Vec<u8>>)> {
+        let concrete = |expr: SymExpr| match expr {
+            SymExpr::Int(Integer(_, value)) => Some(value),
+            _ => None,
+        };
 
-                if let Some(event) = exp.state.step(next_addr, op) {
-                    if let Some(exit) = self.find_exits(event, instruction, *addr, next_addr) {
-                        if let Some(block) = parser.export() {
-                            self.blocks.insert(exp.node.addr, block);
-                        }
-                        return exit;
-                    }
-                }
-            }
+        let (dest, len, value) = match op {
+            MicroOperation::Mov { dest: Location::Indirect(data_type, 0, temp), src } => {
+                (*temp, data_type.bytes() as u64, Some(state.read_location(*src)))
+            },
+            MicroOperation::Copy { dest, count, data_type, .. } => {
+                (*dest, concrete(state.get_temp(*count))? * data_type.bytes() as u64, None)
+            },
+            MicroOperation::Fill { dest, value, count } => {
+                let len = concrete(state.get_temp(*count))? * value.0.bytes() as u64;
+                (*dest, len, None)
+            },
+            _ => return None,
+        };
+
+        let target = concrete(state.get_temp(dest))?;
+        if !self.program.is_code(target, len) {
+            return None;
         }
+
+        let bytes = match value {
+            Some(SymExpr::Int(Integer(data_type, value))) if data_type.bytes() <= 8 => {
+                Some(value.to_le_bytes()[.. data_type.bytes()].to_vec())
+            },
+            _ => None,
+        };
+
+        Some((target, len, bytes))
     }
 
-    /// Determine the kind of exit resulting from a symbolic execution event:
-    /// - Returns Some(Some(exit)) if there are new blocks resulting.
-    /// - Returns Some(None) if it exited without new blocks (sys_exit).
-    /// - Returns None if it was no exit at all.
+    /// Determine the kind of exit resulting from a symbolic execution event
+    /// or `None` if it was no exit at all.
     fn find_exits(
         &self,
         event: Event,
-        inst: &Instruction,
+        microcode: &Microcode,
         current_addr: u64,
         next_addr: u64
-    ) -> Option<Option<Exit>> {
+    ) -> Option<Exit> {
         match event {
             // If it is a jump, add the exit to the list.
-            Event::Jump { target, condition, relative } => Some(Some(Exit {
+            Event::Jump { target, condition, relative } => Some(Exit {
                 target: if relative {
                     target.clone().add(SymExpr::Int(Integer::from_ptr(next_addr)))
                 } else {
                     target.clone()
                 },
-                kind: match inst.mnemoic {
-                    Mnemoic::Call => ExitKind::Call,
-                    Mnemoic::Ret => ExitKind::Return,
+                kind: match microcode.transfer {
+                    Transfer::Call => ExitKind::Call,
+                    Transfer::Return => ExitKind::Return,
                     _ => ExitKind::Jump,
                 },
                 jumpsite: current_addr,
+                next: next_addr,
                 condition,
-            })),
-            Event::Exit => Some(None),
+            }),
+            Event::Exit => Some(Exit {
+                target: SymExpr::from_ptr(0),
+                kind: ExitKind::Terminate,
+                jumpsite: current_addr,
+                next: next_addr,
+                condition: SymCondition::TRUE,
+            }),
             _ => None,
         }
     }
@@ -303,20 +1462,460 @@ impl<'a> ControlFlowExplorer<'a> {
     /// Add reachable blocks to the stack depending on the exit conditions
     /// of the just parsed block.
     fn explore_exit(&mut self, exp: &ExplorationTarget, exit: Exit) {
+        // After the program exits, the destructors run.
+        if let ExitKind::Terminate = exit.kind {
+            if self.destructor_of(exp).is_none() {
+                if let Some(&first) = self.program.destructors.first() {
+                    self.explore_destructor(exp, exit.jumpsite, first);
+                }
+            }
+            return;
+        }
+
         if let SymExpr::Int(Integer(DataType::N64, target)) = exit.target {
+            // Destructors return to the null address and the next one runs.
+            if let (ExitKind::Return, 0) = (exit.kind, target) {
+                if let Some((site, index)) = self.destructor_of(exp) {
+                    if let Some(&next) = self.program.destructors.get(index + 1) {
+                        self.explore_destructor(exp, site, next);
+                    }
+                    return;
+                }
+
+                // Threads end when their routine returns.
+                if self.thread_of(exp).is_some() {
+                    return;
+                }
+            }
+
+            // Calls to indirect functions and overridden weak functions land
+            // on the implementations that actually run instead.
+            if let ExitKind::Call = exit.kind {
+                if let Some(implementations) = self.program.resolutions.get(&target).cloned() {
+                    for implementation in implementations {
+                        let target = SymExpr::from_ptr(implementation);
+                        self.explore_exit(exp, Exit { target, .. exit.clone() });
+                    }
+                    return;
+                }
+            }
+
+            // Calls saving or restoring an execution context are summarized.
+            if let ExitKind::Call = exit.kind {
+                match self.context_function(target) {
+                    Some(Context::Save) => return self.explore_setjmp(exp, &exit, target),
+                    Some(Context::Restore) => return self.explore_longjmp(exp, &exit, target),
+                    None => {},
+                }
+
+                // Calls managing heap memory are summarized as well.
+                if let Some(allocator) = self.allocator(target) {
+                    let site = exit.jumpsite;
+                    let kind = ControlKind::Allocate { function: target, site, allocator };
+                    let condition = SymCondition::TRUE;
+                    return self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+                }
+
+                // And so are calls providing input.
+                if let Some(input) = self.input(target) {
+                    let site = exit.jumpsite;
+                    let kind = ControlKind::Input { function: target, site, input };
+                    let condition = SymCondition::TRUE;
+                    return self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+                }
+
+                // Calls reporting a failed runtime check make the calling
+                // block a check node and only return if the check recovers.
+                if let Some((kind, recovers)) = self.check(target) {
+                    let site = exit.jumpsite;
+                    let node = self.context(&exp.node);
+                    let node = self.insert_node(node);
+                    let check = RuntimeCheck { node, site, handler: target, kind };
+                    if !self.checks.contains(&check) {
+                        self.checks.push(check);
+                    }
+                    if recovers {
+                        let kind = ControlKind::Check { function: target, site, kind };
+                        let condition = SymCondition::TRUE;
+                        self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+                    }
+                    return;
+                }
+
+                // Calls of functions with a dependence summary are not entered
+                // and return through the summary.
+                if let Some((clobbered, result, memory)) = self.summary(target) {
+                    let site = exit.jumpsite;
+                    let kind = ControlKind::Summary {
+                        function: target, site, clobbered, result, memory,
+                    };
+                    let condition = SymCondition::TRUE;
+                    return self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+                }
+
+                // Calls creating threads return and start the thread, calls
+                // ending the thread do not return at all.
+                if let Some(spawner) = self.spawner(target) {
+                    return self.explore_spawn(exp, &exit, target, spawner);
+                }
+                if self.is_thread_exit(target) {
+                    return;
+                }
+
+                // Calls of functions outside of the scope are not entered
+                // either and return with unknown values, also in the memory
+                // their arguments may point to.
+                if !self.config.scope.contains(self.program, target) {
+                    let site = exit.jumpsite;
+                    let kind = self.excluded(target, site);
+                    let condition = SymCondition::TRUE;
+                    return self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+                }
+
+                // Recursive calls beyond the depth are not entered, but can
+                // return through a summary, which changes the memory the
+                // arguments may point to like calls outside of the scope.
+                let recursion = self.config.recursion;
+                let depth = recursion_depth(&exp.node.trace, exit.jumpsite, target);
+                if recursion.summarize && depth >= recursion.depth {
+                    let (site, clobbered) = (exit.jumpsite, self.clobbered(target));
+                    let memory = self.config.convention(target).arguments.iter().copied().collect();
+                    let kind = ControlKind::Recursion { function: target, site, clobbered, memory };
+                    let condition = SymCondition::TRUE;
+                    self.explore_acyclic(exp, exit.next, exit.jumpsite, ExitKind::Jump, condition, kind);
+                }
+            }
+
             // Try the not-jumping path if it is viable.
             if exit.condition != SymCondition::TRUE {
                 let len = self.blocks[&exp.node.addr].len;
 
                 let condition = exit.condition.clone().not();
                 let cond = exp.state.solver.simplify_condition(&condition);
-                self.explore_acyclic(&exp, exp.node.addr + len, exit.jumpsite, exit.kind, cond);
+                let addr = exp.node.addr + len;
+                self.explore_acyclic(&exp, addr, exit.jumpsite, exit.kind, cond, ControlKind::Flow);
+            }
+
+            // Calls can also unwind into a landing pad of the calling function.
+            if let ExitKind::Call = exit.kind {
+                if let Some(pad) = self.program.get_landing_pad(exit.jumpsite) {
+                    let (condition, kind) = (SymCondition::TRUE, ControlKind::Exception);
+                    self.explore_acyclic(exp, pad, exit.jumpsite, ExitKind::Jump, condition, kind);
+                }
+            }
+
+            // Tail jumps into functions outside of the scope return to the
+            // caller through a summary like calls of them.
+            let tail = self.program.symbols.contains_key(&target)
+                && !self.config.scope.contains(self.program, target);
+            if let (ExitKind::Jump, true) = (exit.kind, tail) {
+                return self.explore_tail(exp, exit, target);
             }
 
             // Try the jumping path anyways.
-            self.explore_acyclic(&exp, target, exit.jumpsite, exit.kind, exit.condition);
+            self.explore_acyclic(&exp, target, exit.jumpsite, exit.kind, exit.condition, ControlKind::Flow);
+        } else if !self.explore_table(exp, &exit) {
+            // The last root returns to a caller outside of the graph, like a
+            // function analyzed on its own does.
+            let reason = match (exit.kind, exp.node.trace.is_empty()) {
+                (ExitKind::Return, true) => StopReason::ExternalReturn(exit.target),
+                _ => StopReason::UnresolvedJump(exit.target),
+            };
+            let node = self.context(&exp.node);
+            let node = self.insert_node(node);
+            self.stop(StoppedPath { node, addr: exit.jumpsite, reason });
+        }
+    }
+
+    /// Remember where the exploration of a path stopped.
+    fn stop(&mut self, stop: StoppedPath) {
+        if !self.stops.contains(&stop) {
+            self.stops.push(stop);
+        }
+    }
+
+    /// Resolve a jump through a table, whose target selects from the entries
+    /// of the table, into the few targets it can take under the condition of
+    /// the edge into the block. If that condition bounds an index, the jump
+    /// is a switch over it and the target of each case value is recorded.
+    fn explore_table(&mut self, exp: &ExplorationTarget, exit: &Exit) -> bool {
+        let (branch, entry) = match &exp.entry {
+            Some(entry) => entry.clone(),
+            None => return false,
+        };
+        let solver = exp.state.solver.clone();
+        let limit = self.config.memory.select_limit;
+        let targets = solver.examples_where(&exit.target, &entry, limit + 1);
+        if targets.is_empty() || targets.len() > limit {
+            return false;
+        }
+
+        let node = self.context(&exp.node);
+        let node = self.insert_node(node);
+        let known = self.switches.iter().any(|(_, switch)| switch.node == node);
+        let bound = switch_bound(&entry).filter(|&(_, count)| count <= limit as u64);
+        if let (false, Some((index, count))) = (known, bound) {
+            let data_type = index.data_type();
+            let cases: Option<Vec<(u64, u64)>> = (0 .. count)
+                .map(|value| {
+                    let selected = index.clone().equal(SymExpr::from_int(data_type, value));
+                    match solver.examples_where(&exit.target, &entry.clone().and(selected), 2)[..] {
+                        [Integer(_, target)] => Some((value, target)),
+                        _ => None,
+                    }
+                })
+                .collect();
+            if let Some(cases) = cases {
+                let switch = Switch { node, site: exit.jumpsite, index, cases, default: None };
+                self.switches.push((branch, switch));
+            }
+        }
+
+        for Integer(_, target) in targets {
+            let condition = exit.target.clone().equal(SymExpr::from_ptr(target));
+            let condition = solver.simplify_condition(&condition);
+            let kind = ControlKind::Flow;
+            self.explore_acyclic(exp, target, exit.jumpsite, exit.kind, condition, kind);
+        }
+        true
+    }
+
+    /// Record the values of the source variables in scope at the conditional
+    /// jump ending the block, which can name the parts of its condition. The
+    /// values are also recorded simplified like the conditions of the edges.
+    fn record_variables(&mut self, exp: &ExplorationTarget, node: usize, jumpsite: u64) {
+        let program = self.program;
+        if self.frames.is_none() {
+            let calls = self.calls.get_or_insert_with(|| CallGraph::new(program));
+            let functions: Vec<u64> = calls.instructions.keys().copied().collect();
+            self.frames = Some(StackFrames::new(program, &[], &functions));
+        }
+        let frames = self.frames.as_ref().unwrap();
+
+        // Reads can add default values to the memory, so a copy is read.
+        let state = exp.state.clone();
+
+        // Stack slots are found relative to the stack pointer through the
+        // frame of the function, preferring the one the trace called.
+        let function = exp.node.trace.last()
+            .map(|&(_, function)| function)
+            .filter(|&function| frames.stack_pointer(function, jumpsite).is_some())
+            .or_else(|| frames.functions_at(jumpsite).first().copied());
+        let frame_pointer = function.and_then(|function| frames.frames[&function].frame_pointer);
+        let slot = |offset: i64, data_type| {
+            let offset = offset - frames.stack_pointer(function?, jumpsite)?;
+            let offset = SymExpr::from_int(DataType::N64, offset as u64);
+            let addr = state.get_reg(Register::RSP).add(offset);
+            Some(state.memory[0].read_expr(addr, data_type))
+        };
+
+        let values = self.variables.entry(node).or_default();
+        for variable in &program.variables {
+            let data_type = variable.data_type;
+            let value = match variable.place_at(jumpsite) {
+                // The canonical frame address is above the return address.
+                Some(Place::Frame(offset)) => slot(offset + 8, data_type),
+                Some(Place::FramePointer(offset)) => {
+                    frame_pointer.and_then(|base| slot(base + offset, data_type))
+                },
+                Some(Place::Register(reg)) => {
+                    Some(state.memory[1].read_direct(reg.address(), data_type))
+                },
+                Some(Place::Static(addr)) => Some(state.memory[0].read_direct(addr, data_type)),
+                None => None,
+            };
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+
+            // Constants tell nothing apart in conditions.
+            if let SymExpr::Int(_) = value {
+                continue;
+            }
+            let simplified = state.solver.simplify_expr(&value);
+            let name = &variable.name;
+            for entry in &[(name.clone(), value), (name.clone(), simplified)] {
+                if !values.contains(entry) {
+                    values.push(entry.clone());
+                }
+            }
+        }
+    }
+
+    /// The exit site and the index of the destructor the exploration target is
+    /// part of, if any.
+    fn destructor_of(&self, exp: &ExplorationTarget) -> Option<(u64, usize)> {
+        let &(site, function) = exp.node.trace.first()?;
+        self.program.destructors.iter()
+            .position(|&destructor| destructor == function)
+            .map(|index| (site, index))
+    }
+
+    /// Enter a destructor after the program exited at the site.
+    fn explore_destructor(&mut self, exp: &ExplorationTarget, site: u64, destructor: u64) {
+        let (condition, kind) = (SymCondition::TRUE, ControlKind::Finalize { site });
+        self.explore_acyclic(exp, destructor, site, ExitKind::Finalize, condition, kind);
+    }
+
+    /// Whether the function at the address saves or restores an execution context.
+    fn context_function(&self, addr: u64) -> Option<Context> {
+        let name = self.program.symbols.get(&addr).map(String::as_str);
+        let named = |names: &[&str]| name.map(|name| names.contains(&name)) == Some(true);
+        if self.config.setjmp.contains(&addr) || named(SETJMP_NAMES) {
+            Some(Context::Save)
+        } else if self.config.longjmp.contains(&addr) || named(LONGJMP_NAMES) {
+            Some(Context::Restore)
         } else {
-            panic!("handle_exit: unresolved jump target: {}", exit.target);
+            None
+        }
+    }
+
+    /// The allocator the function at the address is if any.
+    fn allocator(&self, addr: u64) -> Option<Allocator> {
+        self.config.allocators.get(&addr).copied().or_else(|| {
+            self.program.symbols.get(&addr).and_then(|name| Allocator::from_name(name))
+        })
+    }
+
+    /// The input function the function at the address is if any.
+    fn input(&self, addr: u64) -> Option<InputFunction> {
+        self.config.inputs.get(&addr).copied().or_else(|| {
+            self.program.symbols.get(&addr).and_then(|name| InputFunction::from_name(name))
+        })
+    }
+
+    /// The spawner the function at the address is if any.
+    fn spawner(&self, addr: u64) -> Option<Spawner> {
+        self.config.spawners.get(&addr).copied().or_else(|| {
+            self.program.symbols.get(&addr).and_then(|name| Spawner::from_name(name))
+        })
+    }
+
+    /// The kind of runtime check the function at the address reports and
+    /// whether it returns afterwards, if it is a check handler.
+    fn check(&self, addr: u64) -> Option<(CheckKind, bool)> {
+        let name = self.program.symbols.get(&addr)?;
+        CheckKind::from_name(name).map(|kind| (kind, CheckKind::recovers(name)))
+    }
+
+    /// The registers the function at the address changes, the ones its
+    /// return value depends on and the ones pointing to memory it may write,
+    /// if it has a dependence summary. Summaries of functions writing global
+    /// data are not used, since the call could not be replaced by them.
+    fn summary(&self, addr: u64) -> Option<(RegisterSet, RegisterSet, RegisterSet)> {
+        let name = self.program.symbols.get(&addr)?;
+        let summary = self.config.summaries.get(name)?;
+        if summary.dependences.iter().any(|d| matches!(d.output, Port::Global(_))) {
+            return None;
+        }
+
+        let convention = self.config.convention(addr);
+        let memory = match summary.dependence(&Port::Memory) {
+            Some(_) => convention.arguments.iter().copied().collect(),
+            None => RegisterSet::default(),
+        };
+        Some((summary.clobbered, summary.result_registers(convention), memory))
+    }
+
+    /// The summary of a call of the function at the address outside of the
+    /// scope, which changes all registers the calling convention does not
+    /// preserve and the memory the arguments may point to.
+    fn excluded(&self, addr: u64, site: u64) -> ControlKind {
+        let convention = self.config.convention(addr);
+        let clobbered = RegisterSet::ALL.iter()
+            .filter(|&reg| reg != Register::RSP && !convention.is_preserved(reg))
+            .collect();
+        let result = RegisterSet::default();
+        let memory = convention.arguments.iter().copied().collect();
+        ControlKind::Summary { function: addr, site, clobbered, result, memory }
+    }
+
+    /// Whether the function at the address ends the calling thread.
+    fn is_thread_exit(&self, addr: u64) -> bool {
+        let name = self.program.symbols.get(&addr).map(String::as_str);
+        name.map(|name| THREAD_EXIT_NAMES.contains(&name)) == Some(true)
+    }
+
+    /// The thread the exploration target runs in, if it is not the main thread.
+    fn thread_of(&self, exp: &ExplorationTarget) -> Option<Thread> {
+        self.threads.iter().copied().find(|thread| thread.runs(&exp.node))
+    }
+
+    /// Continue after a tail jump into a function outside of the scope at the
+    /// return address of the jumping function, where the function returns to.
+    fn explore_tail(&mut self, exp: &ExplorationTarget, exit: Exit, target: u64) {
+        let site = exit.jumpsite;
+        let rsp = exp.state.get_reg(Register::RSP);
+        let reason = match exp.state.memory[0].read_expr(rsp, DataType::N64) {
+            SymExpr::Int(Integer(DataType::N64, next)) => {
+                let (condition, kind) = (exit.condition, self.excluded(target, site));
+                return self.explore_acyclic(exp, next, site, ExitKind::Return, condition, kind);
+            },
+            // Without a caller in the graph, the function returns out of it.
+            next if exp.node.trace.is_empty() => StopReason::ExternalReturn(next),
+            next => StopReason::UnresolvedJump(next),
+        };
+        let node = self.context(&exp.node);
+        let node = self.insert_node(node);
+        self.stop(StoppedPath { node, addr: site, reason });
+    }
+
+    /// Continue after a call creating a thread and enter the routine of the
+    /// thread if it is known.
+    fn explore_spawn(
+        &mut self,
+        exp: &ExplorationTarget,
+        exit: &Exit,
+        function: u64,
+        spawner: Spawner,
+    ) {
+        let site = exit.jumpsite;
+        let (condition, kind) = (SymCondition::TRUE, ControlKind::Spawn { function });
+        self.explore_acyclic(exp, exit.next, site, ExitKind::Jump, condition, kind);
+
+        let routine = self.config.convention(function)
+            .argument(&exp.state, spawner.routine, DataType::N64);
+        if let SymExpr::Int(Integer(_, routine)) = routine {
+            let thread = Thread { site, routine };
+            if !self.threads.contains(&thread) {
+                self.threads.push(thread);
+            }
+            let kind = ControlKind::Thread { function, site, routine, spawner };
+            self.explore_acyclic(exp, routine, site, ExitKind::Spawn, SymCondition::TRUE, kind);
+        }
+    }
+
+    /// Continue after a call to `setjmp` and remember where it returns to for
+    /// later calls to `longjmp`.
+    fn explore_setjmp(&mut self, exp: &ExplorationTarget, exit: &Exit, function: u64) {
+        let setjmp = (exit.next, exp.node.trace.clone());
+        if !self.setjmps.contains(&setjmp) {
+            self.setjmps.push(setjmp);
+        }
+
+        let (condition, kind) = (SymCondition::TRUE, ControlKind::SetJump { function });
+        self.explore_acyclic(exp, exit.next, exit.jumpsite, ExitKind::Jump, condition, kind);
+    }
+
+    /// Jump from a call to `longjmp` back to the returns of all calls to `setjmp`
+    /// which could have filled the buffer and whose frames are still active.
+    fn explore_longjmp(&mut self, exp: &ExplorationTarget, exit: &Exit, function: u64) {
+        let env = self.config.convention(function).argument(&exp.state, 0, DataType::N64);
+        let saved = exp.state.memory[0].read_expr(env.add(SymExpr::from_ptr(56)), DataType::N64);
+
+        let targets: Vec<_> = self.setjmps.iter()
+            .filter(|(next, trace)| exp.node.trace.starts_with(trace) && match saved {
+                SymExpr::Int(Integer(_, addr)) => addr == *next,
+                _ => true,
+            })
+            .cloned()
+            .collect();
+
+        for (next, trace) in targets {
+            let depth = trace.len();
+            let (condition, kind) = (SymCondition::TRUE, ControlKind::LongJump { function, depth });
+            self.explore_acyclic(exp, next, exit.jumpsite, ExitKind::LongJump(depth), condition, kind);
         }
     }
 
@@ -328,39 +1927,52 @@ impl<'a> ControlFlowExplorer<'a> {
         addr: u64,
         jumpsite: u64,
         exit_kind: ExitKind,
-        condition: SymCondition
+        condition: SymCondition,
+        edge_kind: ControlKind,
     ) {
         // Assemble the new node.
         let mut target_node = ControlFlowNode { addr, trace: exp.node.trace.clone() };
         match exit_kind {
             ExitKind::Call => target_node.trace.push((jumpsite, addr)),
             ExitKind::Return => { target_node.trace.pop(); },
+            ExitKind::LongJump(depth) => target_node.trace.truncate(depth),
+            ExitKind::Finalize | ExitKind::Spawn => target_node.trace = vec![(jumpsite, addr)],
             _ => {},
         }
 
         // Insert a new edge for the jump.
-        let start = self.insert_node(exp.node.decycled());
-        let end = self.insert_node(target_node.decycled());
+        let (start, end) = (self.context(&exp.node), self.context(&target_node));
+        let (start, end) = (self.insert_node(start), self.insert_node(end));
+        let entry = Some((start, condition.clone()));
         self.edges.insert((start, end), condition);
+        if edge_kind != ControlKind::Flow {
+            self.kinds.insert((start, end), edge_kind);
+        }
 
         // Only consider the target if it is acyclic or recursing in the allowed limits.
+        let (start, end) = (self.insert_context(&exp.node), self.insert_context(&target_node));
         let looping = exp.path.contains(&end);
         if !looping {
-            // Check if we are already recursing.
-            // We allow to recursive twice because we want to capture the returns
+            // Check if we are already recursing as deep as allowed. By default,
+            // we allow to recurse twice because we want to capture the returns
             // of the recursing function to itself and the outside.
-            let fully_recursive = exp.node.trace.iter()
-                .filter(|&&jump| jump == (jumpsite, addr)).count() >= 2;
+            let depth = recursion_depth(&exp.node.trace, jumpsite, addr);
+            let fully_recursive = depth >= self.config.recursion.depth;
 
             if !fully_recursive {
                 // Add the current block to the path.
                 let mut path = exp.path.to_vec();
                 path.push(start);
 
+                let mut state = exp.state.clone();
+                edge_kind.enter(&mut state);
+
                 self.stack.push(ExplorationTarget {
                     node: target_node,
                     path,
-                    state: exp.state.clone(),
+                    state,
+                    branches: exp.branches,
+                    entry,
                 });
             }
         }
@@ -371,6 +1983,51 @@ impl<'a> ControlFlowExplorer<'a> {
         let new_index = self.nodes.len();
         *self.nodes.entry(node).or_insert(new_index)
     }
+
+    /// The node of the graph standing for the node in its full call trace
+    /// under the context policy of the configuration.
+    fn context(&mut self, node: &ControlFlowNode) -> ControlFlowNode {
+        let mut node = node.decycled();
+        match self.config.context {
+            ContextPolicy::Full => {},
+            ContextPolicy::Insensitive => node.trace.clear(),
+            ContextPolicy::CallString(k) => {
+                node.trace.drain(.. node.trace.len().saturating_sub(k));
+            },
+            ContextPolicy::Cloning(budget) => {
+                if let Some(&(_, function)) = node.trace.last() {
+                    let clones = self.clones.entry(function).or_default();
+                    if !clones.contains(&node.trace) {
+                        if clones.len() < budget {
+                            clones.push(node.trace.clone());
+                        } else {
+                            node.trace.clear();
+                        }
+                    }
+                }
+            },
+        }
+        node
+    }
+
+    /// The registers a summarized call to the function may change, which
+    /// are those it changes itself or through its callees and does not have
+    /// to preserve.
+    fn clobbered(&mut self, function: u64) -> RegisterSet {
+        let program = self.program;
+        let calls = self.calls.get_or_insert_with(|| CallGraph::new(program));
+        let convention = self.config.convention(function);
+        calls.clobbered(function).iter()
+            .filter(|&reg| reg != Register::RSP && !convention.is_preserved(reg))
+            .collect()
+    }
+
+    /// The index of the node in its full call trace, which identifies it on
+    /// the paths.
+    fn insert_context(&mut self, node: &ControlFlowNode) -> usize {
+        let new_index = self.contexts.len();
+        *self.contexts.entry(node.decycled()).or_insert(new_index)
+    }
 }
 
 /// Either reuses an existing block or parses a block from binary.
@@ -384,6 +2041,7 @@ enum BlockParser<'a> {
         entry: u64,
         index: u64,
         binary: &'a [u8],
+        decoder: &'a Decoder,
         encoder: MicroEncoder,
         code: Vec<(u64, u64, Instruction, Microcode)>,
     },
@@ -396,33 +2054,47 @@ impl<'a> BlockParser<'a> {
     }
 
     /// Create a new block parser from unparsed binary.
-    fn from_binary(binary: &'a [u8], base: u64, entry: u64) -> BlockParser {
+    fn from_binary(
+        binary: &'a [u8],
+        base: u64,
+        entry: u64,
+        decoder: &'a Decoder,
+    ) -> BlockParser<'a> {
         BlockParser::Binary {
             entry,
             index: 0,
             binary: &binary[(entry - base) as usize ..],
+            decoder,
             encoder: MicroEncoder::new(),
             code: Vec::new(),
         }
     }
 
-    /// Retrieve the next parsed element.
-    fn next(&mut self) -> &(u64, u64, Instruction, Microcode) {
+    /// Retrieve the next parsed element or `None` at bytes that do not
+    /// decode. A block starting with such bytes spans them up to the next
+    /// plausible instruction boundary.
+    fn next(&mut self) -> Option<&(u64, u64, Instruction, Microcode)> {
         match self {
             BlockParser::BasicBlock { block, index } => {
                 *index += 1;
-                &block.code[*index - 1]
+                block.code.get(*index - 1)
             },
-            BlockParser::Binary { entry, index, binary, encoder, code } => {
+            BlockParser::Binary { entry, index, binary, decoder, encoder, code } => {
                 let bytes = &binary[*index as usize ..];
 
-                let len = Instruction::length(bytes);
-                let instruction = Instruction::decode(bytes).unwrap();
-                let microcode = encoder.encode(&instruction).unwrap();
-                code.push((*index + *entry, len, instruction, microcode));
-                *index += len;
-
-                code.last().unwrap()
+                match decoder.decode(bytes, encoder) {
+                    Some((len, instruction, microcode)) => {
+                        code.push((*index + *entry, len, instruction, microcode));
+                        *index += len;
+                        code.last()
+                    },
+                    None => {
+                        if code.is_empty() {
+                            *index = decoder.undecodable(bytes);
+                        }
+                        None
+                    },
+                }
             }
         }
     }
@@ -442,6 +2114,56 @@ impl<'a> BlockParser<'a> {
     }
 }
 
+/// Prepare a state to start at the first of the roots with the configured
+/// initial registers, such that each root returns into the next one. The
+/// interrupt handlers return through an interrupt frame of slots of the given
+/// width, which restores the stack pointer from before the frame.
+fn prepare_roots(mut state: SymState, roots: &[u64], handlers: &[(u64, DataType)]) -> SymState {
+    let config = state.config.clone();
+    for (&register, &value) in &config.registers {
+        state.set_reg(register, SymExpr::from_int(register.data_type(), value));
+    }
+    for (index, &root) in roots.iter().enumerate().skip(1).rev() {
+        let handler = handlers.iter().find(|&&(handler, _)| handler == roots[index - 1]);
+        let slot = handler.map_or(DataType::N64, |&(_, slot)| slot);
+        let mut words = vec![SymExpr::from_int(slot, root)];
+        if handler.is_some() {
+            let (segment, flags) = (SymExpr::from_int(slot, 0), SymExpr::from_int(slot, 0));
+            let saved = state.get_reg(Register::RSP).cast(slot, false);
+            words.extend(vec![segment.clone(), flags, saved, segment]);
+        }
+        for word in words.into_iter().rev() {
+            let size = SymExpr::from_ptr(slot.bytes() as u64);
+            let rsp = state.get_reg(Register::RSP).sub(size);
+            state.memory[0].write_expr(rsp.clone(), word);
+            state.set_reg(Register::RSP, rsp);
+        }
+    }
+    state
+}
+
+/// Find the pairs of addresses of instructions in the blocks which overlap.
+fn find_overlaps<'a, I>(blocks: I) -> Vec<(u64, u64)> where I: Iterator<Item=&'a BasicBlock> {
+    let mut instructions: Vec<(u64, u64)> = blocks
+        .flat_map(|block| block.code.iter().map(|&(addr, len, ..)| (addr, len)))
+        .collect();
+    instructions.sort();
+    instructions.dedup();
+
+    let mut pairs = Vec::new();
+    for (i, &(addr, len)) in instructions.iter().enumerate() {
+        for &(other, _) in instructions[i + 1 ..].iter().take_while(|&&(other, _)| other < addr + len) {
+            pairs.push((addr, other));
+        }
+    }
+    pairs
+}
+
+/// How often the call from the site to the function is in the call trace.
+fn recursion_depth(trace: &[(u64, u64)], site: u64, function: u64) -> usize {
+    trace.iter().filter(|&&call| call == (site, function)).count()
+}
+
 /// Remove all cycles from a list of comparable items, where `cmp` determines
 /// if two items are equal. For example this turns 1 -> 2 -> 3 -> 2 -> 4 into
 /// 1 -> 2 -> 4.
@@ -468,7 +2190,7 @@ mod tests {
     use super::*;
 
     fn test(filename: &str) {
-        let path = format!("target/bin/{}", filename);
+        let path = crate::fixtures::fixture(filename);
 
         // Generate the flow graph.
         let program = Program::new(path);
@@ -479,6 +2201,25 @@ mod tests {
         });
     }
 
+    #[test]
+    fn paths_between() {
+        // 0 -> 1 -> 3, 0 -> 2 -> 3 and back from 3 to 0, but 2 -> 3 is never taken.
+        let mut edges: HashMap<_, _> = [(0, 1), (0, 2), (1, 3), (3, 0)].iter()
+            .map(|&edge| (edge, SymCondition::TRUE))
+            .collect();
+        edges.insert((2, 3), SymCondition::FALSE);
+        let nodes = vec![ControlFlowNode { addr: 0, trace: Vec::new() }; 4];
+        let cfg = ControlFlowGraph::from_parts(nodes, HashMap::new(), edges, vec![0]);
+
+        let paths = cfg.paths_between(0, 3, 10, None);
+        assert_eq!(paths, vec![(vec![0, 1, 3], SymCondition::TRUE)]);
+        assert_eq!(cfg.paths_between(3, 1, 10, None)[0].0, vec![3, 0, 1]);
+        assert_eq!(cfg.paths_between(2, 2, 10, None), vec![(vec![2], SymCondition::TRUE)]);
+        assert!(cfg.paths_between(0, 3, 0, None).is_empty());
+        assert_eq!(cfg.node_at(0), Some(0));
+        assert!(cfg.node_at(1).is_none());
+    }
+
     #[test]
     fn control_flow_graph() {
         test("block-1");
@@ -495,7 +2236,253 @@ mod tests {
         test("overwrite");
         test("min");
         test("custom");
-        test("checking");
+        test("checks");
+    }
+
+    #[test]
+    fn context_policies() {
+        use crate::flow::DataDependencyGraph;
+
+        // `main` calls `func` twice, which calls `helper` once.
+        let program = Program::new(crate::fixtures::fixture("twice"));
+        let function = |name: &str| program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let (func, helper) = (function("func"), function("helper"));
+
+        let build = |context| {
+            let config = SymConfig { context, .. SymConfig::default() };
+            ControlFlowGraph::with_config(&program, config)
+        };
+        let count = |cfg: &ControlFlowGraph, addr| cfg.nodes_at(addr).len();
+
+        let full = build(ContextPolicy::Full);
+        assert_eq!((count(&full, func), count(&full, helper)), (2, 2));
+
+        let insensitive = build(ContextPolicy::Insensitive);
+        assert_eq!((count(&insensitive, func), count(&insensitive, helper)), (1, 1));
+        assert!(insensitive.nodes.len() < full.nodes.len());
+
+        let call_string = build(ContextPolicy::CallString(1));
+        assert_eq!((count(&call_string, func), count(&call_string, helper)), (2, 1));
+
+        let cloning = build(ContextPolicy::Cloning(1));
+        assert_eq!((count(&cloning, func), count(&cloning, helper)), (2, 2));
+        assert!(cloning.nodes_at(func).iter().any(|&node| cloning.nodes[node].trace.is_empty()));
+
+        // Paths through merged functions still return to their own callers.
+        let ddg = DataDependencyGraph::new(&insensitive);
+        assert!(!ddg.nodes.is_empty());
+        assert_eq!(insensitive.frames.frames.len(), full.frames.frames.len());
+    }
+
+    #[test]
+    fn unwinding() {
+        let program = Program::new(crate::fixtures::fixture("unwind"));
+        let config = SymConfig { signals: true, .. SymConfig::default() };
+        let graph = ControlFlowGraph::with_config(&program, config);
+        let reaches = |addr, kind: &dyn Fn(ControlKind) -> bool| graph.kinds.iter()
+            .any(|(&(_, end), &k)| kind(k) && graph.nodes[end].addr == addr);
+
+        // The call of `emit` in `main` can unwind into the cleanup of its variable.
+        assert_eq!(program.landing_pads.len(), 1);
+        let pad = program.landing_pads[0].pad;
+        assert!(reaches(pad, &|kind| kind == ControlKind::Exception));
+
+        // The handler registered for signal 10 is entered from the registering syscall.
+        let handler = program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == "handler")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        assert!(reaches(handler, &|kind| matches!(kind, ControlKind::Signal { signal: 10, .. })));
+    }
+
+    #[test]
+    fn constructors() {
+        let program = Program::new(crate::fixtures::fixture("init"));
+        let graph = ControlFlowGraph::new(&program);
+        let function = |name: &str| program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+
+        // The constructors run before the entry in the order of their priorities.
+        let (first, second) = (function("first"), function("second"));
+        assert_eq!(graph.roots, vec![first, second, program.entry]);
+        assert!(graph.nodes_at(first).iter().all(|&node| graph.nodes[node].trace.is_empty()));
+        assert!(!graph.nodes_at(function("main")).is_empty());
+
+        // After the exit, the destructors run in reverse and each one
+        // finalizes the exit from the same site.
+        let (early, last) = (function("early"), function("last"));
+        assert_eq!(program.destructors, vec![early, last]);
+        let mut finalized: Vec<_> = graph.kinds.iter()
+            .filter_map(|(&(_, end), &kind)| match kind {
+                ControlKind::Finalize { site } => Some((graph.nodes[end].addr, site)),
+                _ => None,
+            })
+            .collect();
+        finalized.sort();
+        let site = finalized[0].1;
+        assert_eq!(finalized, vec![(last, site), (early, site)]);
+    }
+
+    #[test]
+    fn non_local_jumps() {
+        use Register::*;
+
+        let program = Program::new(crate::fixtures::fixture("jumps"));
+        let graph = ControlFlowGraph::new(&program);
+        let function = |name: &str| program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let targets = |kind: &dyn Fn(ControlKind) -> bool| graph.kinds.iter()
+            .filter(|(_, &k)| kind(k))
+            .map(|(&(_, end), _)| &graph.nodes[end])
+            .collect::<Vec<_>>();
+
+        // The `longjmp` in `check` leaves its frame for the return of `setjmp` in `main`.
+        let returns = targets(&|kind| matches!(kind, ControlKind::SetJump { .. }));
+        let jumps = targets(&|kind| matches!(kind, ControlKind::LongJump { depth: 1, .. }));
+        assert_eq!(returns.len(), 1);
+        assert_eq!(jumps, returns);
+
+        // The jump restores the saved registers and makes `setjmp` return one
+        // instead of the zero passed to `longjmp`.
+        let (setjmp, longjmp) = (function("setjmp"), function("longjmp"));
+        let mut state = SymState::new(MemoryStrategy::PerfectMatches, Rc::new(Solver::new()));
+        let env = SymExpr::from_ptr(0x4000);
+        state.memory[0].write_expr(SymExpr::from_ptr(0x7000), SymExpr::from_ptr(0x10e8));
+        for &(reg, value) in &[(RDI, 0x4000), (RSP, 0x7000), (RBX, 7), (RBP, 0x7010)] {
+            state.set_reg(reg, SymExpr::from_ptr(value));
+        }
+        ControlKind::SetJump { function: setjmp }.enter(&mut state);
+        assert_eq!(state.get_reg(RAX), SymExpr::from_ptr(0));
+
+        for &(reg, value) in &[(RSI, 0), (RSP, 0x6000), (RBX, 1), (RBP, 0x6010)] {
+            state.set_reg(reg, SymExpr::from_ptr(value));
+        }
+        state.set_reg(RDI, env);
+        ControlKind::LongJump { function: longjmp, depth: 0 }.enter(&mut state);
+        for &(reg, value) in &[(RSP, 0x7008), (RBX, 7), (RBP, 0x7010), (RAX, 1)] {
+            assert_eq!(state.get_reg(reg), SymExpr::from_ptr(value));
+        }
+    }
+
+    #[test]
+    fn overlapping() {
+        // The jump targets its own second byte, which decodes as `inc eax`.
+        let binary = [0xeb, 0xff, 0xc0, 0xc3];
+        let decoder = Decoder::default();
+        let parse = |entry, count| {
+            let mut parser = BlockParser::from_binary(&binary, 0x1000, entry, &decoder);
+            for _ in 0 .. count { parser.next(); }
+            parser.export().unwrap()
+        };
+
+        let blocks = [parse(0x1000, 1), parse(0x1001, 2)];
+        assert_eq!(blocks[1].code[0].2.to_string(), "inc eax");
+        assert_eq!(find_overlaps(blocks.iter()), vec![(0x1000, 0x1001)]);
+    }
+
+    #[test]
+    fn undecoded() {
+        // nop; two bytes that do not decode; xor edi, edi; mov eax, 60; syscall
+        let binary = vec![0x90, 0x06, 0x06, 0x31, 0xff, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05];
+        let program = Program::from_code(0x1000, &binary, HashMap::new());
+        assert_eq!(program.undecoded, vec![(0x1001, vec![0x06, 0x06])]);
+
+        // The bytes get a node of their own between the blocks around them.
+        let graph = ControlFlowGraph::new(&program);
+        let addrs: Vec<u64> = graph.nodes.iter().map(|node| node.addr).collect();
+        assert_eq!(addrs, vec![0x1000, 0x1001, 0x1003]);
+        let bytes = UndecodedBytes { node: 1, addr: 0x1001, bytes: vec![0x06, 0x06] };
+        assert_eq!(graph.undecoded, vec![bytes]);
+        assert_eq!(graph.undecoded_at(1), Some(&graph.undecoded[0]));
+        assert!(graph.blocks[&0x1001].code.is_empty() && graph.blocks[&0x1001].len == 2);
+        assert!(graph.edges.contains_key(&(0, 1)) && graph.edges.contains_key(&(1, 2)));
+
+        let mut dot = Vec::new();
+        graph.visualize(&mut dot, &program, "undecoded", VisualizationStyle::Instructions).unwrap();
+        assert!(String::from_utf8(dot).unwrap().contains("1001: (bad) 06 06"));
+    }
+
+    #[test]
+    fn code_writes() {
+        // mov byte ptr [rip+0x1], 0x0; mov edi, 0x1; mov eax, 60; syscall
+        // The first instruction overwrites the immediate of the second one.
+        let binary = vec![
+            0xc6, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00,
+            0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05,
+        ];
+        let program = Program::from_code(0x1000, &binary, HashMap::new());
+        let graph = ControlFlowGraph::new(&program);
+        let bytes = Some(vec![0]);
+        let write = CodeWrite { node: 0, addr: 0x1000, target: 0x1008, len: 1, bytes };
+        assert_eq!(graph.code_writes, vec![write]);
+        assert_eq!(graph.modified_nodes(), vec![0]);
+
+        // The second stage decodes and explores the modified instruction.
+        let second = graph.second_stage(&program).unwrap();
+        assert_eq!(second.get_instruction(0x1007).unwrap().to_string(), "mov edi, 0x0");
+        let graph = ControlFlowGraph::new(&second);
+        assert_eq!(graph.blocks[&0x1000].code[1].2.to_string(), "mov edi, 0x0");
+
+        // Without writes into the code there is no second stage.
+        let program = Program::from_code(0x1000, &binary[7 ..], HashMap::new());
+        assert!(ControlFlowGraph::new(&program).second_stage(&program).is_none());
+    }
+
+    #[test]
+    fn aborted_reads() {
+        use crate::sym::{Concretization, MemoryConfig};
+
+        // mov rax, qword ptr [rdi]; xor edi, edi; mov eax, 60; syscall
+        // The unknown value of `rdi` is a symbolic address.
+        let binary = vec![0x48, 0x8b, 0x07, 0x31, 0xff, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05];
+        let program = Program::from_code(0x1000, &binary, HashMap::new());
+        let memory = MemoryConfig { policy: Concretization::Abort, .. MemoryConfig::default() };
+        let config = SymConfig { memory, .. SymConfig::default() };
+        let graph = ControlFlowGraph::with_config(&program, config);
+
+        assert_eq!(graph.aborts.len(), 1);
+        assert_eq!((graph.aborts[0].node, graph.aborts[0].addr), (0, 0x1000));
+        assert_eq!(graph.aborts[0].read.data_type, DataType::N64);
+        assert!(graph.edges.is_empty());
+        assert!(ControlFlowGraph::new(&program).aborts.is_empty());
+    }
+
+    #[test]
+    fn stopped_paths() {
+        // The function returns to its unknown caller: xor eax, eax; ret
+        let program = Program::from_code(0x1000, &[0x31, 0xc0, 0xc3], HashMap::new());
+        let graph = ControlFlowGraph::new(&program);
+        assert_eq!(graph.stops.len(), 1);
+        assert_eq!((graph.stops[0].node, graph.stops[0].addr), (0, 0x1002));
+        assert!(matches!(graph.stops[0].reason, StopReason::ExternalReturn(_)));
+    }
+
+    #[test]
+    fn partitioned() {
+        use std::collections::HashSet;
+
+        let program = Program::new(crate::fixtures::fixture("paths"));
+        let build = |threads| {
+            ControlFlowGraph::with_config(&program, SymConfig { threads, .. SymConfig::default() })
+        };
+        let edges = |cfg: &ControlFlowGraph| -> HashSet<(ControlFlowNode, ControlFlowNode)> {
+            cfg.edges.keys()
+                .map(|&(start, end)| (cfg.nodes[start].clone(), cfg.nodes[end].clone()))
+                .collect()
+        };
+
+        let (sequential, parallel) = (build(1), build(4));
+        assert_eq!(parallel.nodes[0], sequential.nodes[0]);
+        assert_eq!(parallel.nodes.len(), sequential.nodes.len());
+        assert_eq!(edges(&parallel), edges(&sequential));
+        assert_eq!(parallel.frames.frames.len(), sequential.frames.frames.len());
     }
 
     fn test_decycle(left: Vec<&str>, right: Vec<&str>) {
@@ -0,0 +1,250 @@
+//! Coverage of the edge conditions by concrete executions.
+//!
+//! An execution trace lists the addresses a run of the program went through
+//! in order, one per line, like an instruction or basic block trace of a
+//! binary instrumentation tool. Addresses other than the starts of blocks in
+//! the control flow graph are skipped, so that instruction traces and traces
+//! through code outside of the graph work as well. Two consecutive block
+//! starts take the edges between nodes at them, in every call context.
+//!
+//! A condition is exercised true when its edge is taken and false when its
+//! node is left through another edge. Satisfiable conditions that were never
+//! exercised true come with an example of symbol values taking their edge.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+use std::io::{self, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::helper::json_string;
+use crate::math::{Integer, Solver, SymCondition, Symbol};
+use super::ControlFlowGraph;
+use super::profile::number;
+
+
+/// The addresses a concrete execution went through in order.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ExecutionTrace {
+    pub addrs: Vec<u64>,
+}
+
+/// How the concrete executions exercised the conditions of the edges.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    /// The number of executions.
+    pub executions: usize,
+    /// The conditional edges sorted by their nodes.
+    pub conditions: Vec<ConditionCoverage>,
+}
+
+/// How the concrete executions exercised the condition of an edge.
+#[derive(Debug, Clone)]
+pub struct ConditionCoverage {
+    pub edge: (usize, usize),
+    pub condition: SymCondition,
+    /// How often the edge was taken.
+    pub taken: u64,
+    /// How often the start of the edge was left through another edge.
+    pub not_taken: u64,
+    /// Whether some values of the symbols fulfill the condition.
+    pub satisfiable: bool,
+    /// Values of the symbols taking the edge if it is satisfiable but was
+    /// never taken.
+    pub example: Option<HashMap<Symbol, Integer>>,
+}
+
+impl ExecutionTrace {
+    /// Parse a trace with one address per line, which is the first token of
+    /// the line. Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<ExecutionTrace, TraceError> {
+        let mut addrs = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let token = line.split_whitespace().next().unwrap_or_default();
+            let addr = number(token.trim_end_matches(':')).ok_or_else(|| {
+                TraceError::new(format!("invalid address in line {}", index + 1))
+            })?;
+            addrs.push(addr);
+        }
+        Ok(ExecutionTrace { addrs })
+    }
+
+    /// Read a trace from a file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ExecutionTrace, TraceError> {
+        let text = fs::read_to_string(path).map_err(|err| TraceError::new(err.to_string()))?;
+        ExecutionTrace::parse(&text)
+    }
+}
+
+impl ControlFlowGraph {
+    /// How the executions exercised the conditions of the edges.
+    pub fn condition_coverage(&self, traces: &[ExecutionTrace]) -> CoverageReport {
+        let mut taken: HashMap<(usize, usize), u64> = HashMap::new();
+        let mut left: HashMap<usize, u64> = HashMap::new();
+        for trace in traces {
+            let blocks: Vec<u64> = trace.addrs.iter()
+                .copied()
+                .filter(|addr| self.addresses.contains_key(addr))
+                .collect();
+            for pair in blocks.windows(2) {
+                for &start in &self.addresses[&pair[0]] {
+                    let ends = self.outgoing[start].iter()
+                        .filter(|&&end| self.nodes[end].addr == pair[1]);
+                    let mut any = false;
+                    for &end in ends {
+                        *taken.entry((start, end)).or_default() += 1;
+                        any = true;
+                    }
+                    if any {
+                        *left.entry(start).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        let solver = Solver::with_seed(self.config.seed);
+        let mut edges: Vec<(&(usize, usize), &SymCondition)> = self.edges.iter()
+            .filter(|(_, condition)| **condition != SymCondition::TRUE)
+            .collect();
+        edges.sort_by_key(|&(&edge, _)| edge);
+
+        let conditions = edges.into_iter()
+            .map(|(&edge, condition)| {
+                let count = taken.get(&edge).copied().unwrap_or(0);
+                let model = solver.model(condition);
+                ConditionCoverage {
+                    edge,
+                    condition: condition.clone(),
+                    taken: count,
+                    not_taken: left.get(&edge.0).copied().unwrap_or(0).saturating_sub(count),
+                    satisfiable: model.is_some(),
+                    example: model.filter(|_| count == 0),
+                }
+            })
+            .collect();
+
+        CoverageReport { executions: traces.len(), conditions }
+    }
+}
+
+impl CoverageReport {
+    /// The satisfiable conditions that no execution exercised true.
+    pub fn unexercised(&self) -> impl Iterator<Item=&ConditionCoverage> {
+        self.conditions.iter().filter(|coverage| coverage.satisfiable && coverage.taken == 0)
+    }
+
+    /// Write the report as JSON. Edges name their nodes like the
+    /// visualization of the graph and have the addresses of their blocks.
+    pub fn write_json<W: Write>(&self, mut f: W, cfg: &ControlFlowGraph) -> io::Result<()> {
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"executions\": {},", self.executions)?;
+        writeln!(f, "  \"conditions\": {},", self.conditions.len())?;
+        writeln!(f, "  \"unexercised\": {},", self.unexercised().count())?;
+        writeln!(f, "  \"edges\": [")?;
+        for (index, coverage) in self.conditions.iter().enumerate() {
+            let (start, end) = coverage.edge;
+            let example = match &coverage.example {
+                Some(model) => {
+                    let mut values: Vec<_> = model.iter().collect();
+                    values.sort_by_key(|&(symbol, _)| symbol);
+                    let values: Vec<String> = values.iter()
+                        .map(|(symbol, value)| format!(
+                            "{}: {}",
+                            json_string(&symbol.to_string()),
+                            json_string(&value.to_string()),
+                        ))
+                        .collect();
+                    format!("{{{}}}", values.join(", "))
+                },
+                None => "null".to_string(),
+            };
+            let separator = if index + 1 < self.conditions.len() { "," } else { "" };
+            writeln!(f, "    {{\"start\": \"b{}\", \"end\": \"b{}\", \"from\": {}, \"to\": {}, \
+                         \"condition\": {}, \"true\": {}, \"false\": {}, \"satisfiable\": {}, \
+                         \"example\": {}}}{}",
+                     start, end, cfg.nodes[start].addr, cfg.nodes[end].addr,
+                     json_string(&coverage.condition.to_string()), coverage.taken,
+                     coverage.not_taken, coverage.satisfiable, example, separator)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "}}")
+    }
+}
+
+/// An error reading an execution trace.
+pub struct TraceError {
+    pub message: String,
+}
+
+impl TraceError {
+    fn new(message: String) -> TraceError {
+        TraceError { message }
+    }
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid execution trace: {}", self.message)
+    }
+}
+
+impl std::error::Error for TraceError {}
+debug_display!(TraceError);
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::json::Json;
+    use super::*;
+
+    #[test]
+    fn coverage() {
+        let program = Program::new(fixture("variables"));
+        let cfg = ControlFlowGraph::new(&program);
+        let main = program.symbols.iter()
+            .find(|(_, name)| name.as_str() == "main")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let branch = (0 .. cfg.nodes.len())
+            .find(|&node| cfg.nodes[node].addr >= main && cfg.outgoing[node].len() == 2)
+            .unwrap();
+        let (first, second) = (cfg.outgoing[branch][0], cfg.outgoing[branch][1]);
+
+        // One run takes the first edge, passing an address inside of the
+        // block that is not the start of one.
+        let text = format!("# run\n{:#x}\n{:#x}: jb\n\n{}\n",
+                           cfg.nodes[branch].addr, cfg.nodes[branch].addr + 1,
+                           cfg.nodes[first].addr);
+        let trace = ExecutionTrace::parse(&text).unwrap();
+        assert_eq!(trace.addrs.len(), 3);
+
+        let report = cfg.condition_coverage(&[trace]);
+        let coverage = |edge| report.conditions.iter().find(|c| c.edge == edge).unwrap();
+        assert_eq!(report.executions, 1);
+        let counts = |edge| (coverage(edge).taken, coverage(edge).not_taken);
+        assert_eq!(counts((branch, first)), (1, 0));
+        assert_eq!(counts((branch, second)), (0, 1));
+        assert!(coverage((branch, first)).example.is_none());
+        assert!(coverage((branch, second)).example.is_some());
+        assert!(report.unexercised().any(|c| c.edge == (branch, second)));
+
+        let mut json = Vec::new();
+        report.write_json(&mut json, &cfg).unwrap();
+        let json = Json::parse(&String::from_utf8(json).unwrap()).unwrap();
+        assert_eq!(json.get("executions").and_then(Json::as_u64), Some(1));
+        let edges = json.get("edges").and_then(Json::as_array).unwrap();
+        assert_eq!(edges.len(), report.conditions.len());
+
+        let error = ExecutionTrace::parse("0x10\nmain\n").unwrap_err();
+        assert_eq!(error.message, "invalid address in line 2");
+    }
+}
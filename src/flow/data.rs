@@ -1,12 +1,17 @@
 //! Data flow analysis.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::x86_64::Register;
-use crate::math::{SymCondition, Integer, Symbol, SharedSolver, Solver};
+use crate::ir::{Microcode, Transfer};
+use crate::math::{SymExpr, SymCondition, Integer, Symbol, SharedSolver, Solver};
+use crate::parallel::Partition;
 use crate::sym::{SymState, Event, MemoryStrategy, TypedMemoryAccess, SymbolMap, StdioKind};
+use crate::sym::{Input, describe_inputs};
 use super::*;
 
 
@@ -18,6 +23,28 @@ pub struct DataDependencyGraph {
     /// The conditions for data flow between the abstract locations.
     /// The key pairs are indices into the `nodes` vector.
     pub edges: HashMap<(usize, usize), (SymCondition, SymbolMap)>,
+    /// The stack frames used for naming locations (shared with the control flow graph).
+    pub frames: Arc<StackFrames>,
+    /// The memory classes of the location nodes by index.
+    pub classes: HashMap<usize, MemoryClass>,
+    /// The edges linking a definition to a use it reaches, as opposed to the
+    /// flows within single instructions. Their conditions are the conditions
+    /// of the paths along which the definition reaches the use.
+    pub chains: HashSet<(usize, usize)>,
+    /// The edges from writes to global memory in one thread to the reads of
+    /// it in other threads. They are unconditional, as the threads may run
+    /// in any interleaving.
+    pub threads: HashSet<(usize, usize)>,
+    /// The edges between threads from atomic or fenced writes to atomic or
+    /// fenced reads, if the configuration asks for them.
+    pub synchronizations: HashSet<(usize, usize)>,
+    /// The inputs from the environment and from files that were written to
+    /// the locations.
+    pub inputs: HashMap<AbstractLocation, Input>,
+    /// The nodes which have edges to the node with the index, sorted.
+    pub incoming: Vec<Vec<usize>>,
+    /// The nodes which the node with the index has edges to, sorted.
+    pub outgoing: Vec<Vec<usize>>,
 }
 
 /// A node in the data dependency graph, describing some kind of value.
@@ -28,10 +55,268 @@ pub enum DependencyNode {
     Constant(usize, Integer),
 }
 
+/// How finely memory is divided into the locations whose data flow is tracked.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Granularity {
+    /// Every byte of a memory access is a location of its own, so that
+    /// partial overwrites are tracked exactly. This multiplies the nodes and
+    /// solver queries for memory.
+    Byte,
+    /// Every memory access is one location of its width.
+    Access,
+    /// Memory accesses are attributed to the stack variables, heap
+    /// allocations and global data they belong to. Accesses to the same
+    /// object that may overlap are linked unconditionally and accesses to
+    /// different objects are assumed not to alias, which skips the solver
+    /// for them.
+    Object,
+}
+
+impl Default for Granularity {
+    fn default() -> Granularity {
+        Granularity::Access
+    }
+}
+
 impl DataDependencyGraph {
     /// Create a new data dependency graph graph for all abstract locations.
+    ///
+    /// With the `parallel` feature, the paths are split among the configured
+    /// number of threads, each with its own solver, and the partial graphs
+    /// are merged.
+    ///
+    /// Panics if the analysis is cancelled through the monitor of the
+    /// configuration of the control flow graph, use `try_new` for cancellable
+    /// analyses.
     pub fn new(cfg: &ControlFlowGraph) -> DataDependencyGraph {
-        crate::timings::with("data-dependency-graph", || DataFlowExplorer::new(cfg).run())
+        DataDependencyGraph::try_new(cfg).expect("data dependency graph: analysis was cancelled")
+    }
+
+    /// Create a new data dependency graph like `new` unless the analysis is
+    /// cancelled.
+    pub fn try_new(cfg: &ControlFlowGraph) -> Result<DataDependencyGraph, Cancelled> {
+        DataDependencyGraph::build(cfg, None)
+    }
+
+    /// Create a data dependency graph with only the flows of the instructions
+    /// at the addresses.
+    ///
+    /// The paths are still executed completely, but the flows elsewhere are
+    /// not recorded, which skips most of the solver queries. The definitions
+    /// elsewhere that reach uses at the addresses are kept as nodes without
+    /// incoming edges, which mark where the graph is incomplete.
+    pub fn with_scope(cfg: &ControlFlowGraph, scope: &HashSet<u64>) -> DataDependencyGraph {
+        DataDependencyGraph::build(cfg, Some(scope))
+            .expect("data dependency graph: analysis was cancelled")
+    }
+
+    /// Build the graph with the flows of all instructions or only those in
+    /// the scope.
+    fn build(
+        cfg: &ControlFlowGraph,
+        scope: Option<&HashSet<u64>>,
+    ) -> Result<DataDependencyGraph, Cancelled> {
+        crate::timings::with("data-dependency-graph", || {
+            let _phase = crate::trace::phase("ddg");
+            let count = crate::parallel::threads(cfg.config.threads);
+            let partitions = Partition::all(count);
+            let loops = if cfg.config.summarize_loops { cfg.loops() } else { Vec::new() };
+            let values = match cfg.config.value_sets {
+                true => Some(ValueSetAnalysis::new(cfg)),
+                false => None,
+            };
+            let flows = crate::parallel::map(count, &partitions, |&partition| {
+                DataFlowExplorer::new(cfg, partition, scope, &loops, values.as_ref()).run()
+            });
+
+            let mut flows = flows.into_iter().collect::<Result<Vec<_>, _>>()?.into_iter();
+            let mut flow = flows.next().unwrap_or_default();
+            if count > 1 {
+                let solver = Solver::with_seed(cfg.config.seed);
+                for other in flows {
+                    flow.merge(other, &solver);
+                }
+            }
+            flow.link_threads();
+            if let Some(scope) = scope {
+                flow.retain_connected(scope);
+            }
+            let graph = flow.finish(cfg.frames.clone());
+            Ok(match cfg.config.prune_dead {
+                true => graph.pruned(&Liveness::new(cfg)),
+                false => graph,
+            })
+        })
+    }
+
+    /// The kind of the edge between the nodes with the indices.
+    pub fn edge_kind(&self, (start, end): (usize, usize)) -> EdgeKind {
+        match (&self.nodes[start], &self.nodes[end]) {
+            _ if self.synchronizations.contains(&(start, end)) => EdgeKind::Synchronization,
+            _ if self.threads.contains(&(start, end)) => EdgeKind::InterThread,
+            (DependencyNode::Location(first), DependencyNode::Location(second)) => {
+                EdgeKind::of_data(first, second)
+            }
+            _ => EdgeKind::DataDependency,
+        }
+    }
+
+    /// The memory class of the node with the index if it is a location.
+    pub fn class(&self, index: usize) -> Option<MemoryClass> {
+        self.classes.get(&index).copied()
+    }
+
+    /// Describe the inputs from the environment and from files written to
+    /// the locations, like `env THEME` or `bytes 4..8 of config.bin`. The
+    /// locations can be the ones of a slice of the program dependence graph.
+    pub fn describe_inputs<'a, I>(&self, locations: I) -> Vec<String>
+    where I: IntoIterator<Item=&'a AbstractLocation> {
+        describe_inputs(locations.into_iter().filter_map(|location| self.inputs.get(location)))
+    }
+
+    /// A copy of this graph where the data only flows through locations of
+    /// the kept memory classes, like only through globals. The other nodes
+    /// stay in place without edges, so that indices remain valid.
+    pub fn restricted<F>(&self, keep: F) -> DataDependencyGraph
+    where F: Fn(MemoryClass) -> bool {
+        let kept = |index| self.class(index).map(&keep) != Some(false);
+        let mut restricted = DataDependencyGraph {
+            edges: self.edges.iter()
+                .filter(|(&(start, end), _)| kept(start) && kept(end))
+                .map(|(&edge, data)| (edge, data.clone()))
+                .collect(),
+            chains: self.chains.iter()
+                .copied()
+                .filter(|&(start, end)| kept(start) && kept(end))
+                .collect(),
+            threads: self.threads.iter()
+                .copied()
+                .filter(|&(start, end)| kept(start) && kept(end))
+                .collect(),
+            synchronizations: self.synchronizations.iter()
+                .copied()
+                .filter(|&(start, end)| kept(start) && kept(end))
+                .collect(),
+            .. self.clone()
+        };
+        restricted.reindex();
+        restricted
+    }
+
+    /// Add the nodes and edges of another graph of the same control flow
+    /// graph, like one with the flows of other instructions, combining the
+    /// conditions of edges in both.
+    pub fn extend(&mut self, other: DataDependencyGraph, solver: &Solver) {
+        let mut indices: HashMap<DependencyNode, usize> = self.nodes.iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, node)| (node, index))
+            .collect();
+
+        // Constants refer to their sink, which comes before them.
+        let mut mapping = Vec::with_capacity(other.nodes.len());
+        for node in other.nodes {
+            let node = match node {
+                DependencyNode::Constant(sink, int) => DependencyNode::Constant(mapping[sink], int),
+                node => node,
+            };
+            let index = match indices.get(&node) {
+                Some(&index) => index,
+                None => {
+                    indices.insert(node.clone(), self.nodes.len());
+                    self.nodes.push(node);
+                    self.nodes.len() - 1
+                }
+            };
+            mapping.push(index);
+        }
+
+        for ((start, end), data) in other.edges {
+            join_edge(&mut self.edges, (mapping[start], mapping[end]), data, solver);
+        }
+        for (index, class) in other.classes {
+            let joined = match self.classes.get(&mapping[index]) {
+                Some(&previous) => previous.join(class),
+                None => class,
+            };
+            self.classes.insert(mapping[index], joined);
+        }
+
+        let remap = |(start, end): (usize, usize)| (mapping[start], mapping[end]);
+        self.chains.extend(other.chains.into_iter().map(remap));
+        self.threads.extend(other.threads.into_iter().map(remap));
+        self.synchronizations.extend(other.synchronizations.into_iter().map(remap));
+        self.inputs.extend(other.inputs);
+        self.reindex();
+    }
+
+    /// Rebuild the `incoming` and `outgoing` indices after modifying the edges.
+    pub fn reindex(&mut self) {
+        self.incoming = vec![Vec::new(); self.nodes.len()];
+        self.outgoing = vec![Vec::new(); self.nodes.len()];
+        for &(start, end) in self.edges.keys() {
+            self.outgoing[start].push(end);
+            self.incoming[end].push(start);
+        }
+        for list in self.incoming.iter_mut().chain(&mut self.outgoing) {
+            list.sort_unstable();
+        }
+    }
+
+    /// The nodes the node with the index has edges to.
+    pub fn successors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        self.outgoing[node].iter().copied()
+    }
+
+    /// The nodes which have edges to the node with the index.
+    pub fn predecessors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        self.incoming[node].iter().copied()
+    }
+
+    /// The edges leaving the node as (end, condition).
+    pub fn edges_from(&self, node: usize) -> impl Iterator<Item=(usize, &SymCondition)> {
+        self.successors(node).map(move |end| (end, &self.edges[&(node, end)].0))
+    }
+
+    /// The edges entering the node as (start, condition).
+    pub fn edges_to(&self, node: usize) -> impl Iterator<Item=(usize, &SymCondition)> {
+        self.predecessors(node).map(move |start| (start, &self.edges[&(start, node)].0))
+    }
+
+    /// The definitions reaching a use of the storage at the address in any
+    /// call trace, as indices of their location nodes.
+    pub fn defs_reaching(&self, addr: u64, storage: StorageLocation) -> Vec<usize> {
+        let storage = storage.normalized();
+        let mut defs: Vec<usize> = self.chains.iter()
+            .filter(|&&(_, end)| match &self.nodes[end] {
+                DependencyNode::Location(location) => {
+                    location.addr == addr && location.storage.normalized() == storage
+                },
+                _ => false,
+            })
+            .map(|&(start, _)| start)
+            .collect();
+        defs.sort_unstable();
+        defs.dedup();
+        defs
+    }
+
+    /// The uses reached by the definition with the node index.
+    pub fn uses_of(&self, def: usize) -> Vec<usize> {
+        let mut uses: Vec<usize> = self.chains.iter()
+            .filter(|&&(start, _)| start == def)
+            .map(|&(_, end)| end)
+            .collect();
+        uses.sort_unstable();
+        uses
+    }
+
+    /// The def-use chains as (definition, use, path condition) ordered by
+    /// the definitions.
+    pub fn def_use_chains(&self) -> impl Iterator<Item=(usize, usize, &SymCondition)> {
+        let mut chains: Vec<(usize, usize)> = self.chains.iter().copied().collect();
+        chains.sort_unstable();
+        chains.into_iter().map(move |edge| (edge.0, edge.1, &self.edges[&edge].0))
     }
 
     /// Visualize this flow graph in a graphviz DOT file.
@@ -39,25 +324,42 @@ impl DataDependencyGraph {
         &self,
         target: W,
         title: &str,
+    ) -> io::Result<()> {
+        self.visualize_with(target, title, &VisualConfig::default())
+    }
+
+    /// Visualize this flow graph like `visualize` with the layout and styles
+    /// of the configuration.
+    pub fn visualize_with<W: Write>(
+        &self,
+        target: W,
+        title: &str,
+        config: &VisualConfig,
     ) -> io::Result<()> {
         use super::visualize::*;
+        let _phase = crate::trace::phase("render");
         let mut f = target;
 
-        write_header(&mut f, &format!("Data dependency graph for {}", title), 40)?;
+        write_header(&mut f, &format!("Data dependency graph for {}", title), 40, config)?;
 
         for (index, node) in self.nodes.iter().enumerate() {
             match node {
                 DependencyNode::Location(location) => {
-                    let fmt = location.to_string().replace(">", "&gt;");
+                    let fmt = self.frames.describe(location).to_string().replace(">", "&gt;");
                     let mut splitter = fmt.splitn(2, ' ');
-                    writeln!(f, "b{} [label=<<b>{}</b> {}>,shape=box]", index,
-                                splitter.next().unwrap(), splitter.next().unwrap())?;
+                    let class = self.class(index).unwrap_or(MemoryClass::Unknown);
+                    writeln!(f, "b{} [label=<<b>{}</b> {}>,shape={},style=filled,fillcolor=\"{}\"]",
+                                index, splitter.next().unwrap(), splitter.next().unwrap(),
+                                config.location_shape("box"), class.color())?;
                 },
 
                 DependencyNode::Io(kind, symbol) => {
                     let color = match kind {
                         StdioKind::Stdin => "#4caf50",
                         StdioKind::Stdout => "#03a9f4",
+                        StdioKind::MmioRead => "#8bc34a",
+                        StdioKind::MmioWrite => "#00bcd4",
+                        StdioKind::Timestamp | StdioKind::Random | StdioKind::Cpuid => "#cddc39",
                     };
 
                     writeln!(f, "b{} [label=<<b>{}</b>>,shape=box,style=filled,fillcolor=\"{}\"]",
@@ -71,10 +373,11 @@ impl DataDependencyGraph {
             }
         }
 
+        let conditions = Conditions::new(config);
         write_edges(&mut f, &self.edges, |f, ((start, end), (condition, _))| {
             if condition != &SymCondition::TRUE {
                 write!(f, "label=< ")?;
-                let fmt = condition.to_string().replace("<", "&lt;").replace(">", "&gt;");
+                let fmt = escape(&conditions.label(condition));
                 let mut len = 0;
                 for part in fmt.split(" ") {
                     write!(f, "{} ", part)?;
@@ -84,22 +387,58 @@ impl DataDependencyGraph {
                         len = 0;
                     }
                 }
-                write!(f, ">, ")?;
+                write!(f, ">, {}", conditions.tooltip(condition))?;
             }
 
             if let DependencyNode::Location(first) = &self.nodes[start] {
                 if let DependencyNode::Location(second) = &self.nodes[end] {
                     if !first.storage.accesses_memory() &&
                         first.storage.normalized() == second.storage.normalized() {
-                        write!(f, "style=dashed, color=\"#ababab\"")?;
+                        write!(f, "style=dashed, color=\"#ababab\", ")?;
                     }
                 }
             }
 
+            // Configured styles come last to take precedence.
+            if let Some(style) = config.edge_styles.get(&self.edge_kind((start, end))) {
+                write!(f, "{}", style)?;
+            }
+
             Ok(())
         })?;
 
-        write_footer(&mut f)
+        let mut nodes = class_legend(config.location_shape("box"));
+        let io = [
+            ("stdin", "#4caf50"), ("stdout", "#03a9f4"),
+            ("device read", "#8bc34a"), ("device write", "#00bcd4"),
+            ("constant", "#f0ce24"),
+        ];
+        for &(label, color) in &io {
+            nodes.push((label.to_string(), filled("box", color)));
+        }
+        let mut edges = vec![
+            ("data flow".to_string(), String::new()),
+            ("same register".to_string(), "style=dashed, color=\"#ababab\"".to_string()),
+        ];
+        edges.extend(edge_style_legend(config));
+        write_legend(&mut f, config, &nodes, &edges)?;
+
+        write_footer(&mut f, config)
+    }
+
+    /// Write the full conditions of the edges as JSON, naming the nodes like
+    /// the visualization does.
+    pub fn write_conditions<W: Write>(&self, target: W, config: &VisualConfig) -> io::Result<()> {
+        use super::visualize::{write_conditions, Conditions};
+        let mut edges: Vec<_> = self.edges.iter()
+            .filter(|(_, (condition, _))| *condition != SymCondition::TRUE)
+            .collect();
+        edges.sort_by_key(|&(&edge, _)| edge);
+        let name = |index: usize| format!("b{}", index);
+        let edges: Vec<_> = edges.into_iter()
+            .map(|(&(start, end), (condition, _))| (name(start), name(end), condition))
+            .collect();
+        write_conditions(target, &Conditions::new(config), &edges)
     }
 }
 
@@ -107,8 +446,55 @@ impl DataDependencyGraph {
 struct DataFlowExplorer<'g> {
     cfg: &'g ControlFlowGraph,
     solver: SharedSolver,
+    oracle: AliasOracle,
+    partition: Partition,
+    /// The addresses of the instructions whose flows are recorded, or `None`
+    /// for all of them.
+    scope: Option<&'g HashSet<u64>>,
+    /// The loops that are summarized and their indices by header.
+    loops: &'g [Loop],
+    headers: HashMap<usize, usize>,
+    /// The value sets that rule out aliasing of the memory operands of
+    /// instructions, whose locations are kept by node index.
+    values: Option<&'g ValueSetAnalysis>,
+    operands: HashMap<usize, AbstractLocation>,
+    nodes: HashMap<DependencyNode, usize>,
+    edges: HashMap<(usize, usize), (SymCondition, SymbolMap)>,
+    classes: HashMap<usize, MemoryClass>,
+    chains: HashSet<(usize, usize)>,
+    /// The call sites creating the threads of the program.
+    spawns: HashSet<u64>,
+    shared: HashSet<SharedAccess>,
+    inputs: HashMap<AbstractLocation, Input>,
+    /// The instrumentation instructions whose flows are not recorded.
+    stripped: HashSet<u64>,
+}
+
+/// The nodes and edges found by an explorer, which can be merged with the
+/// ones of other explorers.
+#[derive(Debug, Default)]
+struct DataFlow {
     nodes: HashMap<DependencyNode, usize>,
     edges: HashMap<(usize, usize), (SymCondition, SymbolMap)>,
+    classes: HashMap<usize, MemoryClass>,
+    chains: HashSet<(usize, usize)>,
+    shared: HashSet<SharedAccess>,
+    threads: HashSet<(usize, usize)>,
+    synchronizations: HashSet<(usize, usize)>,
+    inputs: HashMap<AbstractLocation, Input>,
+}
+
+/// An access to global memory at a concrete address by the location node
+/// with the index, in the thread created at the call site or the main thread.
+/// Atomic accesses and those right before or after a fence synchronize.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct SharedAccess {
+    node: usize,
+    thread: Option<u64>,
+    addr: u64,
+    len: u64,
+    write: bool,
+    synchronizing: bool,
 }
 
 #[derive(Clone)]
@@ -116,6 +502,13 @@ struct ExplorationTarget {
     target: usize,
     state: SymState,
 
+    /// The number of branches taken on this path.
+    branches: usize,
+
+    /// The call sites of the context the locations in the target node are
+    /// attributed to.
+    context: Vec<u64>,
+
     /// The set of all conditions (through ifs) met on this path.
     preconditions: Vec<SymCondition>,
 
@@ -125,20 +518,59 @@ struct ExplorationTarget {
     /// we with the write accesses.
     location_links: HashMap<StorageLocation, (usize, usize)>,
 
-    /// All past writing memory accesses with their abstract location index (node id).
+    /// The summarized loops this path is in from the outermost to the
+    /// innermost one, and whether it already went around them once more.
+    loops: Vec<(usize, bool)>,
+
+    /// Whether a fence executed since the last access to global memory.
+    fenced: bool,
+    /// The last access to global memory, which synchronizes if a fence follows it.
+    last_shared: Option<SharedAccess>,
+
+    /// All past writing memory accesses with their abstract location index (node id)
+    /// and the object they belong to with object granularity.
     /// The last usize holds the number of preconditions that were already active
     /// when this access happened. This allows us to discern the new preconditions
     /// for a read access from those that already were before.
-    write_accesses: Vec<(usize, TypedMemoryAccess, usize)>
+    write_accesses: Vec<(usize, TypedMemoryAccess, Option<MemoryObject>, usize)>
 }
 
 impl<'g> DataFlowExplorer<'g> {
-    fn new(cfg: &'g ControlFlowGraph) -> DataFlowExplorer<'g> {
+    fn new(
+        cfg: &'g ControlFlowGraph,
+        partition: Partition,
+        scope: Option<&'g HashSet<u64>>,
+        loops: &'g [Loop],
+        values: Option<&'g ValueSetAnalysis>,
+    ) -> DataFlowExplorer<'g> {
+        let solver: SharedSolver = Rc::new(Solver::with_seed(cfg.config.seed));
+        let base_state = cfg.base_state(MemoryStrategy::ConditionalTrees, solver.clone());
+        let oracle = AliasOracle::new(&base_state);
+
         DataFlowExplorer {
             cfg,
-            solver: Rc::new(Solver::new()),
+            solver,
+            oracle,
+            partition,
+            scope,
+            loops,
+            headers: loops.iter()
+                .enumerate()
+                .map(|(index, summarized)| (summarized.header, index))
+                .collect(),
+            values,
+            operands: HashMap::new(),
             nodes: HashMap::new(),
             edges: HashMap::new(),
+            classes: HashMap::new(),
+            chains: HashSet::new(),
+            spawns: cfg.threads().iter().map(|thread| thread.site).collect(),
+            shared: HashSet::new(),
+            inputs: HashMap::new(),
+            stripped: match cfg.config.strip_instrumentation {
+                true => cfg.instrumentation().keys().copied().collect(),
+                false => HashSet::new(),
+            },
         }
     }
 
@@ -150,30 +582,62 @@ impl<'g> DataFlowExplorer<'g> {
     /// All direct flows are translated into edges with condition _True_ in the
     /// graph. Indirect flows through memory can have more complex conditions
     /// associated with them.
-    fn run(mut self) -> DataDependencyGraph {
-        let base_state = SymState::new(MemoryStrategy::ConditionalTrees, self.solver.clone());
+    fn run(mut self) -> Result<DataFlow, Cancelled> {
+        let base_state = self.cfg.base_state(MemoryStrategy::ConditionalTrees, self.solver.clone());
+
+        let split = self.partition.split();
 
         let mut targets = vec![ExplorationTarget {
             target: 0,
+            context: self.context(0, &base_state),
             state: base_state,
+            branches: 0,
             preconditions: Vec::new(),
             location_links: HashMap::new(),
+            loops: Vec::new(),
+            fenced: false,
+            last_shared: None,
             write_accesses: Vec::new(),
         }];
 
+        let monitor = &self.cfg.config.monitor;
+        let mut done = 0;
         while let Some(mut exp) = targets.pop() {
             let node = &self.cfg.nodes.get(exp.target)
                 .expect("data flow explorer: expected node in control flow graph");
 
+            monitor.check()?;
+            let frames = &self.cfg.frames;
+            let name = frames.functions_at(node.addr).first()
+                .and_then(|function| frames.frames.get(function))
+                .map(|frame| frame.name.as_str());
+            monitor.report(Phase::DataDependency, done, None, name);
+            crate::trace::block(node.addr, name);
+            done += 1;
+
             let block = &self.cfg.blocks[&node.addr];
+            let mut returned = None;
 
             // Simulate a basic block.
             for (addr, len, instruction, microcode) in &block.code {
                 let addr = *addr;
                 let next_addr = addr + len;
+                let local = self.in_scope(addr);
+                let stripped = self.stripped.contains(&addr);
+                if instruction.is_fence() {
+                    self.fence(&mut exp);
+                }
+
+                // The locations accessing memory-mapped I/O by address.
+                let mut devices = Vec::new();
+                for &(source, sink) in self.flows(microcode).iter().filter(|_| !stripped) {
+                    if !local {
+                        self.pass_through(&mut exp, addr, sink);
+                        continue;
+                    }
 
-                for (source, sink) in instruction.flows() {
-                    let sink_index = self.insert_loc(addr, &exp.state.trace, sink);
+                    let sink_index = self.insert_loc(addr, &exp.context, sink);
+                    self.classify(&exp.state, sink_index, sink);
 
                     // The source may be a constant or a storage location.
                     let source_data = match source {
@@ -188,7 +652,8 @@ impl<'g> DataFlowExplorer<'g> {
                                 Some((sink, sink_index))
 
                             } else {
-                                let source_index = self.insert_loc(addr, &exp.state.trace, source);
+                                let source_index = self.insert_loc(addr, &exp.context, source);
+                                self.classify(&exp.state, source_index, source);
                                 self.link_location(&mut exp, source, source_index, false);
 
                                 // For flows inherent to an instruction the condition is
@@ -213,26 +678,50 @@ impl<'g> DataFlowExplorer<'g> {
 
                     if let Some((source, source_index)) = source_data {
                         // For reading memory accesses we need to check if they alias
-                        // with any of the previous writing accesses.
+                        // with any of the previous writing accesses. Device registers
+                        // are volatile, so reads of them only depend on the device.
                         if let Some(access) = exp.state.get_access_for_storage(source) {
-                            self.handle_read_access(&exp, access, source_index);
+                            if let Some(device) = exp.state.device(&access.0) {
+                                devices.push((device, source_index, source));
+                            } else {
+                                let object = self.object(addr, &exp.context, source, &access);
+                                self.operand(source_index, addr, &exp.context, source);
+                                let atomic = microcode.atomic;
+                                self.share(&mut exp, source_index, &access, false, atomic);
+                                self.handle_read_access(&exp, access, object, source_index);
+                            }
                         }
                     }
 
                     // Writing memory accesses are stored in the `write_accesses` list
                     // so we can check aliasing with reading accesses later on.
                     if let Some(access) = exp.state.get_access_for_storage(sink) {
-                        exp.write_accesses.push((sink_index, access, exp.preconditions.len()));
+                        if let Some(device) = exp.state.device(&access.0) {
+                            devices.push((device, sink_index, sink));
+                            continue;
+                        }
+                        let object = self.object(addr, &exp.context, sink, &access);
+                        self.operand(sink_index, addr, &exp.context, sink);
+                        self.share(&mut exp, sink_index, &access, true, microcode.atomic);
+                        exp.write_accesses.push((sink_index, access, object, exp.preconditions.len()));
                     }
                 }
 
-                exp.state.track(&instruction, addr);
+                exp.state.track(microcode, addr);
 
                 // Execute the instruction.
                 for op in &microcode.ops {
                     if let Some(event) = exp.state.step(next_addr, op) {
                         match event {
-                            Event::Stdio(kind, ios) => self.handle_io(&mut exp, kind, ios),
+                            Event::Stdio(kind, ios) => self.handle_io(&mut exp, kind, ios, local),
+                            Event::Device(kind, symbol, access) => {
+                                self.handle_device(&exp, addr, kind, symbol, access, &devices);
+                            },
+                            Event::Query(kind, symbol) if !stripped => {
+                                self.handle_query(&mut exp, addr, kind, symbol, local);
+                            },
+                            Event::Jump { target: SymExpr::Int(Integer(_, target)), .. }
+                                if microcode.transfer == Transfer::Return => returned = Some(target),
                             _ => {},
                         }
                     }
@@ -240,7 +729,27 @@ impl<'g> DataFlowExplorer<'g> {
             }
 
             // Add all nodes reachable from that one as targets.
-            for &id in &self.cfg.outgoing[exp.target] {
+            let outgoing = &self.cfg.outgoing[exp.target];
+            let branches = exp.branches + (outgoing.len() > 1) as usize;
+            for &id in outgoing {
+                // Functions whose contexts are merged return to all of their
+                // callers in the graph, but a path only to its own one.
+                if let Some(target) = returned {
+                    let plain = self.cfg.edge_kind((exp.target, id)) == ControlKind::Flow;
+                    if plain && self.cfg.nodes[id].addr != target {
+                        continue;
+                    }
+                }
+
+                if branches == split && exp.branches < split && !self.partition.takes() {
+                    continue;
+                }
+
+                let (loops, entered) = match self.enter_loops(&exp.loops, id) {
+                    Some(entered) => entered,
+                    None => continue,
+                };
+
                 let condition = &self.cfg.edges[&(exp.target, id)];
 
                 // If the arrow to the next basic block has a condition, we
@@ -250,46 +759,113 @@ impl<'g> DataFlowExplorer<'g> {
                 let mut preconditions = exp.preconditions.clone();
                 preconditions.push(evaluated);
 
-                targets.push(ExplorationTarget {
+                // Calls providing input write the bytes like reads from stdin.
+                // Summarized calls and recursions may write memory, which is
+                // remembered like writes outside of the scope.
+                let mut state = exp.state.clone();
+                let kind = self.cfg.edge_kind((exp.target, id));
+                let (inputs, writes) = match kind {
+                    ControlKind::Input { function, site, input } => {
+                        (Some((site, input.call(&mut state, function, site))), vec![])
+                    },
+                    kind => (None, kind.enter(&mut state)),
+                };
+
+                // A summarized loop is entered with its counters standing for
+                // their values in any iteration.
+                if let Some(index) = entered {
+                    let reachable = self.loops[index].widen(&mut state);
+                    preconditions.push(self.solver.simplify_condition(&reachable));
+                }
+
+                let mut next = ExplorationTarget {
                     target: id,
-                    state: exp.state.clone(),
+                    context: self.context(id, &state),
+                    state,
+                    branches,
                     preconditions,
                     location_links: exp.location_links.clone(),
+                    loops,
+                    fenced: exp.fenced,
+                    last_shared: exp.last_shared,
                     write_accesses: exp.write_accesses.clone(),
-                });
+                };
+                if let Some((site, ios)) = inputs {
+                    let local = self.in_scope(site);
+                    self.handle_io(&mut next, StdioKind::Stdin, ios, local);
+                }
+                self.handle_io(&mut next, StdioKind::Stdin, writes, false);
+                if let ControlKind::Summary { function, site, result, .. } = kind {
+                    self.summarize(&mut next, function, site, result);
+                }
+                targets.push(next);
             }
         }
 
-        self.finish()
+        Ok(DataFlow {
+            nodes: self.nodes,
+            edges: self.edges,
+            classes: self.classes,
+            chains: self.chains,
+            shared: self.shared,
+            threads: HashSet::new(),
+            synchronizations: HashSet::new(),
+            inputs: self.inputs,
+        })
     }
 
-    /// Arrange all data in the way expected for the flow graph.
-    fn finish(self) -> DataDependencyGraph {
-        // Arrange the nodes into a vector.
-        let default = DependencyNode::Constant(0, Integer::from_ptr(0));
-        let mut nodes = vec![default; self.nodes.len()];
-        for (node, index) in self.nodes.into_iter() {
-            nodes[index] = node;
+    /// The summarized loops a path is in after moving to the node and the
+    /// loop it enters there, if any. Returns `None` if the node is the header
+    /// of a loop the path already went around twice, so that every loop is
+    /// followed through two iterations: the first with the widened counters
+    /// and the second for the dependencies carried between iterations.
+    fn enter_loops(&self, loops: &[(usize, bool)], node: usize) -> Option<(Vec<(usize, bool)>, Option<usize>)> {
+        let mut loops = loops.to_vec();
+        while let Some(&(index, _)) = loops.last() {
+            if self.loops[index].body.binary_search(&node).is_ok() {
+                break;
+            }
+            loops.pop();
         }
 
-        DataDependencyGraph {
-            nodes,
-            edges: self.edges,
+        let index = match self.headers.get(&node) {
+            Some(&index) => index,
+            None => return Some((loops, None)),
+        };
+
+        match loops.last_mut() {
+            Some((active, repeated)) if *active == index => {
+                if *repeated {
+                    return None;
+                }
+                *repeated = true;
+                Some((loops, None))
+            },
+            _ => {
+                loops.push((index, false));
+                Some((loops, Some(index)))
+            },
         }
     }
 
-    /// Add I/O nodes and abstract locations for reads and writes.
+    /// Add I/O nodes and abstract locations for reads and writes. Outside of
+    /// the scope only the written locations are remembered.
     fn handle_io(
         &mut self,
         exp: &mut ExplorationTarget,
         kind: StdioKind,
-        ios: Vec<(Symbol, TypedMemoryAccess)>
+        ios: Vec<(Symbol, TypedMemoryAccess)>,
+        local: bool,
     ) {
         for (symbol, access) in ios {
             // Add to the previous links list.
-            let location = exp.state.symbol_map[&symbol].clone();
+            let mut location = exp.state.symbol_map[&symbol].clone();
+            location.trace = exp.context.clone();
             let location_index = self.insert_node(DependencyNode::Location(location.clone()));
-            let index = self.insert_node(DependencyNode::Io(kind, symbol));
+            self.classify(&exp.state, location_index, location.storage);
+            if let Some(input) = exp.state.inputs.get(&symbol) {
+                self.inputs.insert(location.clone(), input.clone());
+            }
 
             // Store the location node so it can be backlinked.
             exp.location_links.insert(
@@ -299,17 +875,214 @@ impl<'g> DataFlowExplorer<'g> {
 
             // If it is a stdin read, that is, a memory write, add it
             // to the write access list.
+            let io = DependencyNode::Io(kind, symbol);
+            let object = self.object(location.addr, &location.trace, location.storage, &access);
             match kind {
                 StdioKind::Stdin => {
-                    exp.write_accesses.push((location_index, access, exp.preconditions.len()));
-                    self.insert_pre_edge(&exp, 0, index, location_index);
+                    exp.write_accesses.push((location_index, access, object, exp.preconditions.len()));
+                    if local {
+                        let index = self.insert_node(io);
+                        self.insert_pre_edge(&exp, 0, index, location_index);
+                    }
                 },
-                StdioKind::Stdout => {
-                    self.handle_read_access(exp, access, location_index);
+                StdioKind::Stdout if local => {
+                    let index = self.insert_node(io);
+                    self.handle_read_access(exp, access, object, location_index);
                     self.insert_pre_edge(&exp, 0, location_index, index);
                 },
+                _ => {},
+            }
+        }
+    }
+
+    /// Add an I/O node for an access to memory-mapped I/O by the instruction
+    /// at the address, which is linked to the location of the register. Reads
+    /// are inputs written to the location, writes outputs read from it.
+    fn handle_device(
+        &mut self,
+        exp: &ExplorationTarget,
+        addr: u64,
+        kind: StdioKind,
+        symbol: Symbol,
+        access: TypedMemoryAccess,
+        devices: &[(u64, usize, StorageLocation)],
+    ) {
+        let device = exp.state.device(&access.0);
+        let (location_index, storage) = match devices.iter().find(|d| Some(d.0) == device) {
+            Some(&(_, index, storage)) => (index, storage),
+            None => return,
+        };
+
+        let index = self.insert_node(DependencyNode::Io(kind, symbol));
+        if kind == StdioKind::MmioRead {
+            if let Some(input) = exp.state.inputs.get(&symbol) {
+                let location = AbstractLocation::new(addr, exp.context.clone(), storage);
+                self.inputs.insert(location, input.clone());
+            }
+            self.insert_pre_edge(exp, 0, index, location_index);
+        } else {
+            self.insert_pre_edge(exp, 0, location_index, index);
+        }
+    }
+
+    /// Let the registers written with a value from the processor depend on
+    /// an I/O node for its symbol, like memory written by reads from stdin.
+    fn handle_query(
+        &mut self,
+        exp: &mut ExplorationTarget,
+        addr: u64,
+        kind: StdioKind,
+        symbol: Symbol,
+        local: bool,
+    ) {
+        let register = match exp.state.symbol_map[&symbol].storage {
+            StorageLocation::Direct(register) => register,
+            _ => return,
+        };
+        // The timestamp counter is split into edx:eax.
+        let registers = match kind {
+            StdioKind::Timestamp => vec![Register::EAX, Register::EDX],
+            _ => vec![register],
+        };
+
+        for register in registers {
+            let sink = StorageLocation::Direct(register);
+            if !local {
+                self.pass_through(exp, addr, sink);
+                continue;
+            }
+            let sink_index = self.insert_loc(addr, &exp.context, sink);
+            self.classify(&exp.state, sink_index, sink);
+            self.link_location(exp, sink, sink_index, true);
+            let index = self.insert_node(DependencyNode::Io(kind, symbol));
+            self.insert_pre_edge(exp, 0, index, sink_index);
+        }
+    }
+
+    /// Let the return value of a summarized call at the site depend on the
+    /// definitions of the argument registers reaching the call.
+    fn summarize(
+        &mut self,
+        exp: &mut ExplorationTarget,
+        function: u64,
+        site: u64,
+        result: RegisterSet,
+    ) {
+        let storage = StorageLocation::Direct(self.cfg.config.convention(function).result);
+        if !self.in_scope(site) {
+            return self.pass_through(exp, site, storage);
+        }
+
+        let index = self.insert_loc(site, &exp.context, storage);
+        self.classify(&exp.state, index, storage);
+        for reg in result.iter() {
+            let argument = StorageLocation::Direct(reg).normalized();
+            if let Some(&(def, num_preconditions)) = exp.location_links.get(&argument) {
+                self.insert_pre_edge(exp, num_preconditions, def, index);
+                self.record_chain(def, index);
+            }
+        }
+        self.link_location(exp, storage, index, true);
+    }
+
+    /// The call sites of the context of the locations in the node, which is
+    /// the trace of the state if the graph is fully context-sensitive and the
+    /// context of the node otherwise.
+    fn context(&self, node: usize, state: &SymState) -> Vec<u64> {
+        match self.cfg.config.context {
+            ContextPolicy::Full => state.trace.clone(),
+            _ => self.cfg.nodes[node].trace.iter().map(|&(site, _)| site).collect(),
+        }
+    }
+
+    /// Whether the flows of the instruction at the address are recorded.
+    fn in_scope(&self, addr: u64) -> bool {
+        match self.scope {
+            Some(scope) => scope.contains(&addr),
+            None => true,
+        }
+    }
+
+    /// Remember a definition outside of the scope, so that uses in the scope
+    /// are linked to it, without recording any flows.
+    fn pass_through(&mut self, exp: &mut ExplorationTarget, addr: u64, sink: StorageLocation) {
+        let sink_index = self.insert_loc(addr, &exp.context, sink);
+        self.classify(&exp.state, sink_index, sink);
+        self.link_location(exp, sink, sink_index, true);
+        if let Some(access) = exp.state.get_access_for_storage(sink) {
+            let object = self.object(addr, &exp.context, sink, &access);
+            exp.write_accesses.push((sink_index, access, object, exp.preconditions.len()));
+        }
+    }
+
+    /// The flows of the microcode between locations of the configured
+    /// granularity. With byte granularity, memory locations are split into
+    /// their bytes, which flow into each other pairwise between accesses of
+    /// the same width and all into all otherwise.
+    fn flows<'m>(&self, microcode: &'m Microcode) -> Cow<'m, [(ValueSource, StorageLocation)]> {
+        if self.cfg.config.granularity != Granularity::Byte {
+            return Cow::Borrowed(&microcode.flows);
+        }
+
+        let mut flows = Vec::new();
+        for &(source, sink) in &microcode.flows {
+            let sinks = sink.bytes();
+            match source {
+                ValueSource::Storage(source) => {
+                    let sources = source.bytes();
+                    if sources.len() == sinks.len() {
+                        flows.extend(sources.into_iter().map(ValueSource::Storage).zip(sinks));
+                    } else {
+                        for &source in &sources {
+                            flows.extend(sinks.iter().map(|&sink| (ValueSource::Storage(source), sink)));
+                        }
+                    }
+                },
+                ValueSource::Const(_) => flows.extend(sinks.into_iter().map(|sink| (source, sink))),
             }
         }
+        Cow::Owned(flows)
+    }
+
+    /// The object a memory access at the instruction belongs to with object
+    /// granularity.
+    fn object(
+        &self,
+        addr: u64,
+        trace: &[u64],
+        storage: StorageLocation,
+        access: &TypedMemoryAccess,
+    ) -> Option<MemoryObject> {
+        if self.cfg.config.granularity != Granularity::Object {
+            return None;
+        }
+
+        let location = AbstractLocation::new(addr, trace.to_vec(), storage);
+        match self.cfg.frames.variable(&location) {
+            Some(variable) => Some(MemoryObject::Stack(location.trace, variable)),
+            None => self.oracle.object(&access.0),
+        }
+    }
+
+    /// Remember the location of a memory operand of an instruction if value
+    /// sets are used.
+    fn operand(&mut self, index: usize, addr: u64, trace: &[u64], storage: StorageLocation) {
+        if self.values.is_some() {
+            self.operands.insert(index, AbstractLocation::new(addr, trace.to_vec(), storage));
+        }
+    }
+
+    /// Whether the memory operands of the nodes may alias by their value sets.
+    /// Accesses that are no operands or whose value sets are not known may.
+    fn may_alias(&self, a: usize, b: usize) -> bool {
+        let (values, a, b) = match (self.values, self.operands.get(&a), self.operands.get(&b)) {
+            (Some(values), Some(a), Some(b)) => (values, a, b),
+            _ => return true,
+        };
+        match (values.address(a), values.address(b)) {
+            (Some(_), Some(_)) => values.may_alias(a, b),
+            _ => true,
+        }
     }
 
     /// Check all previous write accesses for aliasing with the current read access and add
@@ -318,12 +1091,30 @@ impl<'g> DataFlowExplorer<'g> {
         &mut self,
         exp: &ExplorationTarget,
         read: TypedMemoryAccess,
+        object: Option<MemoryObject>,
         location_index: usize
     ) {
         let mut overwritten = SymCondition::FALSE;
 
-        for (prev_index, prev, num_preconditions) in exp.write_accesses.iter().rev() {
-            let mut alias = determine_alias(prev, &read);
+        for (prev_index, prev, prev_object, num_preconditions) in exp.write_accesses.iter().rev() {
+            // Ask the oracle first and only build conditions if it cannot decide.
+            // Undecided accesses to objects alias exactly if the objects are the same.
+            let result = match (self.oracle.check(prev, &read), prev_object, &object) {
+                (AliasResult::May, Some(a), Some(b)) if a == b => AliasResult::Partial,
+                (AliasResult::May, Some(_), Some(_)) => AliasResult::No,
+                (AliasResult::May, ..) if !self.may_alias(*prev_index, location_index) => {
+                    AliasResult::No
+                },
+                (result, ..) => result,
+            };
+            let (mut alias, full) = match result {
+                AliasResult::No => continue,
+                AliasResult::Must => (SymCondition::TRUE, SymCondition::TRUE),
+                AliasResult::Partial => (SymCondition::TRUE, SymCondition::FALSE),
+                AliasResult::May => {
+                    (determine_alias(prev, &read), determine_full_alias(prev, &read))
+                },
+            };
 
             // Any condition that has to be met on this path *additionally* to those
             // already active for the current write have to be included in the conditions.
@@ -341,10 +1132,9 @@ impl<'g> DataFlowExplorer<'g> {
             // If this cannot match with the neccessary preconditions, we can skip this, too.
             if alias != SymCondition::FALSE {
                 self.insert_edge(exp, *prev_index, location_index, alias);
+                self.record_chain(*prev_index, location_index);
 
-                // Determine the condition that the read access was overwritten by the
-                // write one.
-                let full = determine_full_alias(prev, &read);
+                // The read access is overwritten where the write one contains it fully.
                 overwritten = overwritten.or(full);
 
                 crate::timings::with("simplify-overwrite", || {
@@ -386,13 +1176,84 @@ impl<'g> DataFlowExplorer<'g> {
         // storage location if it was used before.
         if !overwritten {
             if let Some((prev, num_preconditions)) = exp.location_links.get(&location) {
-                self.insert_pre_edge(&exp, *num_preconditions, *prev, location_index);
+                let prev = *prev;
+                self.insert_pre_edge(&exp, *num_preconditions, prev, location_index);
+                self.record_chain(prev, location_index);
             }
         }
 
         exp.location_links.insert(location, (location_index, exp.preconditions.len()));
     }
 
+    /// Record the memory class of a location node at its current address,
+    /// joining it with the classes found on other paths.
+    fn classify(&mut self, state: &SymState, index: usize, storage: StorageLocation) {
+        let class = match state.get_access_for_storage(storage) {
+            Some(access) => match (self.oracle.class(&access.0), &access.0) {
+                (_, addr) if state.device(addr).is_some() => MemoryClass::Device,
+                (MemoryClass::Global, SymExpr::Int(Integer(_, addr)))
+                    if self.cfg.contains_code(*addr) => MemoryClass::Code,
+                (class, _) => class,
+            },
+            None => storage.memory_class(),
+        };
+
+        let joined = match self.classes.get(&index) {
+            Some(&previous) => previous.join(class),
+            None => class,
+        };
+        self.classes.insert(index, joined);
+    }
+
+    /// Remember an access to global memory at a concrete address in a
+    /// program with threads, which the other threads share.
+    fn share(
+        &mut self,
+        exp: &mut ExplorationTarget,
+        index: usize,
+        access: &TypedMemoryAccess,
+        write: bool,
+        atomic: bool,
+    ) {
+        if self.spawns.is_empty() {
+            return;
+        }
+        if let SymExpr::Int(Integer(_, addr)) = access.0 {
+            if self.oracle.class(&access.0) == MemoryClass::Global {
+                // Threads start without call frames, so the first call site
+                // of their traces is the one creating them.
+                let thread = exp.state.trace.first().copied()
+                    .filter(|site| self.spawns.contains(site));
+                let len = access.1.bytes() as u64;
+                let synchronizing = self.cfg.config.happens_before && (atomic || exp.fenced);
+                let shared = SharedAccess { node: index, thread, addr, len, write, synchronizing };
+                self.shared.insert(shared);
+                exp.fenced = false;
+                exp.last_shared = Some(shared);
+            }
+        }
+    }
+
+    /// Let the last access to global memory and the next one synchronize
+    /// after a fence.
+    fn fence(&mut self, exp: &mut ExplorationTarget) {
+        if self.spawns.is_empty() || !self.cfg.config.happens_before {
+            return;
+        }
+        exp.fenced = true;
+        if let Some(last) = exp.last_shared {
+            self.shared.insert(SharedAccess { synchronizing: true, .. last });
+        }
+    }
+
+    /// Remember that the edge links a definition to a use it reaches unless it
+    /// was dropped because its condition is unsatisfiable.
+    fn record_chain(&mut self, def: usize, usage: usize) {
+        if self.edges.contains_key(&(def, usage)) {
+            self.chains.insert((def, usage));
+        }
+    }
+
     /// Insert a new abstract location node for a storage location in a context.
     fn insert_loc(&mut self, addr: u64, trace: &[u64], storage: StorageLocation) -> usize {
         let location = AbstractLocation::new(addr, trace.to_vec(), storage);
@@ -455,20 +1316,191 @@ impl<'g> DataFlowExplorer<'g> {
     }
 }
 
+/// Add an edge, combining its condition with the one of the same edge if
+/// there is one already.
+fn join_edge(
+    edges: &mut HashMap<(usize, usize), (SymCondition, SymbolMap)>,
+    edge: (usize, usize),
+    (condition, symbols): (SymCondition, SymbolMap),
+    solver: &Solver,
+) {
+    match edges.remove(&edge) {
+        Some((prev, mut prev_symbols)) => {
+            let condition = match prev == condition {
+                true => prev,
+                false => solver.simplify_condition(&prev.or(condition)),
+            };
+            if condition != SymCondition::FALSE {
+                prev_symbols.extend(symbols);
+                edges.insert(edge, (condition, prev_symbols));
+            }
+        }
+        None => { edges.insert(edge, (condition, symbols)); }
+    }
+}
+
+impl DataFlow {
+    /// Add the nodes and edges found by another explorer, combining the
+    /// conditions of edges found by both.
+    fn merge(&mut self, other: DataFlow, solver: &Solver) {
+        let mut sorted: Vec<(DependencyNode, usize)> = other.nodes.into_iter().collect();
+        sorted.sort_by_key(|&(_, index)| index);
+
+        // Constants refer to their sink, which was inserted before them.
+        let mut mapping = HashMap::new();
+        for (node, index) in sorted {
+            let node = match node {
+                DependencyNode::Constant(sink, int) => {
+                    DependencyNode::Constant(mapping[&sink], int)
+                }
+                node => node,
+            };
+            let new_index = self.nodes.len();
+            mapping.insert(index, *self.nodes.entry(node).or_insert(new_index));
+        }
+
+        for ((start, end), data) in other.edges {
+            join_edge(&mut self.edges, (mapping[&start], mapping[&end]), data, solver);
+        }
+
+        for (index, class) in other.classes {
+            let joined = match self.classes.get(&mapping[&index]) {
+                Some(&previous) => previous.join(class),
+                None => class,
+            };
+            self.classes.insert(mapping[&index], joined);
+        }
+
+        self.chains.extend(other.chains.into_iter()
+            .map(|(def, usage)| (mapping[&def], mapping[&usage])));
+        self.shared.extend(other.shared.into_iter()
+            .map(|access| SharedAccess { node: mapping[&access.node], .. access }));
+        self.inputs.extend(other.inputs);
+    }
+
+    /// Link the writes to global memory in each thread to the overlapping
+    /// reads in other threads.
+    fn link_threads(&mut self) {
+        for write in self.shared.iter().filter(|access| access.write) {
+            for read in self.shared.iter().filter(|access| !access.write) {
+                let overlapping = write.addr < read.addr + read.len
+                    && read.addr < write.addr + write.len;
+                if write.thread != read.thread && overlapping {
+                    let edge = (write.node, read.node);
+                    self.edges.entry(edge).or_insert_with(|| (SymCondition::TRUE, SymbolMap::new()));
+                    self.threads.insert(edge);
+                    if write.synchronizing && read.synchronizing {
+                        self.synchronizations.insert(edge);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove the locations outside of the scope that no recorded flow
+    /// reaches, renumbering the other nodes.
+    fn retain_connected(&mut self, scope: &HashSet<u64>) {
+        let connected: HashSet<usize> = self.edges.keys()
+            .flat_map(|&(start, end)| vec![start, end])
+            .collect();
+
+        let mut sorted: Vec<(DependencyNode, usize)> = self.nodes.drain().collect();
+        sorted.sort_by_key(|&(_, index)| index);
+
+        let mut mapping = HashMap::new();
+        for (node, index) in sorted {
+            let kept = match &node {
+                DependencyNode::Location(location) => {
+                    scope.contains(&location.addr) || connected.contains(&index)
+                }
+                _ => true,
+            };
+            if kept {
+                let node = match node {
+                    DependencyNode::Constant(sink, int) => {
+                        DependencyNode::Constant(mapping[&sink], int)
+                    }
+                    node => node,
+                };
+                mapping.insert(index, self.nodes.len());
+                self.nodes.insert(node, mapping[&index]);
+            }
+        }
+
+        self.edges = self.edges.drain()
+            .map(|((start, end), data)| ((mapping[&start], mapping[&end]), data))
+            .collect();
+        self.classes = self.classes.drain()
+            .filter_map(|(index, class)| Some((*mapping.get(&index)?, class)))
+            .collect();
+        self.chains = self.chains.drain()
+            .map(|(def, usage)| (mapping[&def], mapping[&usage]))
+            .collect();
+        self.threads = self.threads.drain()
+            .map(|(write, read)| (mapping[&write], mapping[&read]))
+            .collect();
+        self.synchronizations = self.synchronizations.drain()
+            .map(|(write, read)| (mapping[&write], mapping[&read]))
+            .collect();
+    }
+
+    /// Arrange all data in the way expected for the flow graph.
+    fn finish(self, frames: Arc<StackFrames>) -> DataDependencyGraph {
+        // Arrange the nodes into a vector.
+        let default = DependencyNode::Constant(0, Integer::from_ptr(0));
+        let mut nodes = vec![default; self.nodes.len()];
+        for (node, index) in self.nodes.into_iter() {
+            nodes[index] = node;
+        }
+
+        let mut graph = DataDependencyGraph {
+            nodes,
+            edges: self.edges,
+            frames,
+            classes: self.classes,
+            chains: self.chains,
+            threads: self.threads,
+            synchronizations: self.synchronizations,
+            inputs: self.inputs,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        };
+        graph.reindex();
+        graph
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use crate::Program;
     use crate::flow::visualize::test::compile;
+    use crate::sym::SymConfig;
     use super::*;
 
     fn test(filename: &str) {
-        let path = format!("target/bin/{}", filename);
+        let path = crate::fixtures::fixture(filename);
 
         let program = Program::new(path);
         let cfg = ControlFlowGraph::new(&program);
         let ddg = DataDependencyGraph::new(&cfg);
 
+        // Every chain is an edge and leads from a definition to its uses.
+        for (def, usage, _) in ddg.def_use_chains() {
+            assert!(ddg.uses_of(def).contains(&usage));
+            if let DependencyNode::Location(location) = &ddg.nodes[usage] {
+                assert!(ddg.defs_reaching(location.addr, location.storage).contains(&def));
+            }
+        }
+
+        // The neighbor indices agree with the edges in both directions.
+        for &(start, end) in ddg.edges.keys() {
+            assert!(ddg.successors(start).any(|node| node == end));
+            assert!(ddg.edges_to(end).any(|(node, _)| node == start));
+        }
+        let count: usize = (0 .. ddg.nodes.len()).map(|node| ddg.edges_from(node).count()).sum();
+        assert_eq!(count, ddg.edges.len());
+
         compile("data", filename, |file| {
             ddg.visualize(file, filename)
         });
@@ -481,5 +1513,155 @@ mod tests {
     #[test] fn data_min() { test("min") }
     #[test] fn data_block1() { test("block-1") }
     #[test] fn data_custom() { test("custom") } 
-    #[test] fn data_checking() { test("checking") }    
+    #[test] fn data_checks() { test("checks") }
+
+    #[test]
+    fn summarized_loops() {
+        for name in &["count", "loop"] {
+            let program = Program::new(crate::fixtures::fixture(name));
+            let config = SymConfig { summarize_loops: true, .. SymConfig::default() };
+            let cfg = ControlFlowGraph::with_config(&program, config);
+            let ddg = DataDependencyGraph::new(&cfg);
+            assert!(!ddg.nodes.is_empty());
+        }
+
+        // Both the first and the second iteration write a byte.
+        let program = Program::new(crate::fixtures::fixture("count"));
+        let config = SymConfig { summarize_loops: true, .. SymConfig::default() };
+        let ddg = DataDependencyGraph::new(&ControlFlowGraph::with_config(&program, config));
+        let writes = ddg.nodes.iter()
+            .filter(|node| matches!(node, DependencyNode::Io(StdioKind::Stdout, _)))
+            .count();
+        assert_eq!(writes, 2);
+    }
+
+    #[test]
+    fn granularity() {
+        let program = Program::new(crate::fixtures::fixture("bufs"));
+        let build = |granularity| {
+            let config = SymConfig { granularity, .. SymConfig::default() };
+            DataDependencyGraph::new(&ControlFlowGraph::with_config(&program, config))
+        };
+        let memory = |ddg: &DataDependencyGraph| -> Vec<StorageLocation> {
+            ddg.nodes.iter()
+                .filter_map(|node| match node {
+                    DependencyNode::Location(location) if location.storage.accesses_memory() => {
+                        Some(location.storage)
+                    },
+                    _ => None,
+                })
+                .collect()
+        };
+        let outputs = |ddg: &DataDependencyGraph| (0 .. ddg.nodes.len())
+            .filter(|&node| matches!(ddg.nodes[node], DependencyNode::Io(StdioKind::Stdout, _)))
+            .map(|node| ddg.predecessors(node).count())
+            .collect::<Vec<_>>();
+
+        let access = build(Granularity::Access);
+        let bytes = build(Granularity::Byte);
+        let objects = build(Granularity::Object);
+        assert!(memory(&bytes).iter().all(|storage| storage.data_type() == DataType::N8));
+        assert!(memory(&bytes).len() >= memory(&access).len());
+        assert_eq!(outputs(&bytes), outputs(&access));
+        assert_eq!(outputs(&objects).len(), outputs(&access).len());
+
+        let location = StorageLocation::indirect_reg(DataType::N32, Register::RSP);
+        assert_eq!(location.bytes().len(), 4);
+        assert_eq!(location.bytes()[0], StorageLocation::indirect_reg(DataType::N8, Register::RSP));
+        assert_eq!(StorageLocation::Direct(Register::EAX).bytes().len(), 1);
+    }
+
+    #[test]
+    fn value_sets() {
+        // The buffer accesses may overlap, so the value sets keep their flow.
+        let program = Program::new(crate::fixtures::fixture("bufs"));
+        let build = |value_sets| {
+            let config = SymConfig { value_sets, .. SymConfig::default() };
+            DataDependencyGraph::new(&ControlFlowGraph::with_config(&program, config))
+        };
+        let edges = |ddg: &DataDependencyGraph| ddg.edges.keys()
+            .map(|&(start, end)| (ddg.nodes[start].clone(), ddg.nodes[end].clone()))
+            .collect::<HashSet<_>>();
+        assert_eq!(edges(&build(true)), edges(&build(false)));
+    }
+
+    #[test]
+    fn processor_values() {
+        // rdtsc; mov ecx, edx; rdrand eax; add ecx, eax; mov eax, 60; syscall
+        let binary = [
+            0x0f, 0x31, 0x89, 0xd1, 0x0f, 0xc7, 0xf0, 0x01, 0xc1,
+            0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05,
+        ];
+        let program = Program::from_code(0x1000, &binary, HashMap::new());
+        let ddg = DataDependencyGraph::new(&ControlFlowGraph::new(&program));
+
+        // The sum slices back to both the timestamp counter and the random number.
+        let ecx = StorageLocation::Direct(Register::ECX);
+        let sum = DependencyNode::Location(AbstractLocation::new(0x1007, vec![], ecx));
+        let mut pending = vec![ddg.nodes.iter().position(|node| *node == sum).unwrap()];
+        let mut sources = Vec::new();
+        while let Some(node) = pending.pop() {
+            if let DependencyNode::Io(kind, _) = ddg.nodes[node] {
+                sources.push(kind);
+            }
+            pending.extend(ddg.predecessors(node));
+        }
+        sources.sort();
+        sources.dedup();
+        assert_eq!(sources, [StdioKind::Timestamp, StdioKind::Random]);
+    }
+
+    #[test]
+    fn merge_flows() {
+        let eax = StorageLocation::Direct(Register::EAX);
+        let location = |addr| DependencyNode::Location(AbstractLocation::new(addr, vec![], eax));
+        let constant = |sink| DependencyNode::Constant(sink, Integer::from_ptr(5));
+        let flow = |nodes: Vec<DependencyNode>, edges: Vec<(usize, usize)>, class| DataFlow {
+            nodes: nodes.into_iter().enumerate().map(|(index, node)| (node, index)).collect(),
+            edges: edges.iter()
+                .map(|&edge| (edge, (SymCondition::TRUE, SymbolMap::new())))
+                .collect(),
+            classes: vec![(0, class)].into_iter().collect(),
+            chains: edges.into_iter().collect(),
+            .. DataFlow::default()
+        };
+
+        let mut merged = flow(vec![location(1), location(2)], vec![(0, 1)], MemoryClass::Register);
+        let other = flow(
+            vec![location(3), location(1), constant(0)],
+            vec![(2, 0), (0, 1)],
+            MemoryClass::Stack,
+        );
+        merged.merge(other, &Solver::new());
+
+        let ddg = merged.finish(Arc::new(StackFrames::default()));
+        assert_eq!(ddg.nodes, vec![location(1), location(2), location(3), constant(2)]);
+        let edges: HashSet<(usize, usize)> = ddg.edges.keys().copied().collect();
+        assert_eq!(edges, vec![(0, 1), (3, 2), (2, 0)].into_iter().collect());
+        assert_eq!(ddg.chains, edges);
+        assert_eq!(ddg.class(0), Some(MemoryClass::Register));
+        assert_eq!(ddg.class(2), Some(MemoryClass::Stack));
+    }
+
+    #[test]
+    fn retain_connected() {
+        let eax = StorageLocation::Direct(Register::EAX);
+        let location = |addr| DependencyNode::Location(AbstractLocation::new(addr, vec![], eax));
+        let nodes = vec![location(1), location(2), location(3), location(4)];
+        let mut flow = DataFlow {
+            nodes: nodes.into_iter().enumerate().map(|(index, node)| (node, index)).collect(),
+            edges: vec![((1, 3), (SymCondition::TRUE, SymbolMap::new()))].into_iter().collect(),
+            classes: vec![(0, MemoryClass::Stack), (3, MemoryClass::Register)]
+                .into_iter().collect(),
+            chains: vec![(1, 3)].into_iter().collect(),
+            .. DataFlow::default()
+        };
+
+        // The location at 2 is outside of the scope, but reaches the one at 4.
+        flow.retain_connected(&vec![4].into_iter().collect());
+        let ddg = flow.finish(Arc::new(StackFrames::default()));
+        assert_eq!(ddg.nodes, vec![location(2), location(4)]);
+        assert_eq!(ddg.chains, vec![(0, 1)].into_iter().collect());
+        assert_eq!(ddg.classes, vec![(1, MemoryClass::Register)].into_iter().collect());
+    }
 }
@@ -0,0 +1,276 @@
+//! Differences between the dependence graphs of two builds of a program.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+use crate::math::SymCondition;
+use super::{ProgramDependenceGraph, DependenceNode, EdgeKind, StackFrames, VisualConfig};
+use super::AbstractLocation;
+use super::visualize::{write_header, write_footer, write_legend};
+
+
+/// The position of a dependence node in a form that can be compared across
+/// builds: addresses are replaced by function symbols and offsets into them.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NodeKey {
+    /// The call sites of the location as positions, outermost first.
+    pub context: Vec<Position>,
+    /// The position of the block or instruction.
+    pub position: Position,
+    /// The storage of a location or `None` for a block.
+    pub storage: Option<String>,
+}
+
+/// An address as function symbol and offset into the function.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Position {
+    pub function: String,
+    pub offset: u64,
+}
+
+/// An edge between matched nodes.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DiffEdge {
+    pub start: NodeKey,
+    pub end: NodeKey,
+    pub kind: EdgeKind,
+}
+
+/// The dependence edges added, removed and changed from one build to another.
+#[derive(Debug, Clone)]
+pub struct PdgDiff {
+    /// The edges only in the new graph with their conditions.
+    pub added: Vec<(DiffEdge, SymCondition)>,
+    /// The edges only in the old graph with their conditions.
+    pub removed: Vec<(DiffEdge, SymCondition)>,
+    /// The edges in both graphs whose conditions differ as (old, new).
+    pub changed: Vec<(DiffEdge, SymCondition, SymCondition)>,
+}
+
+impl ProgramDependenceGraph {
+    /// The differences from this graph to the graph of another build.
+    ///
+    /// Nodes are matched by their `NodeKey`. Parallel edges of the same kind
+    /// between nodes with the same key are merged by disjunction.
+    pub fn diff(&self, other: &ProgramDependenceGraph) -> PdgDiff {
+        let old = self.keyed_edges();
+        let mut new = other.keyed_edges();
+
+        let mut diff = PdgDiff { added: Vec::new(), removed: Vec::new(), changed: Vec::new() };
+        for (edge, condition) in old {
+            match new.remove(&edge) {
+                Some(other) if other != condition => diff.changed.push((edge, condition, other)),
+                Some(_) => {}
+                None => diff.removed.push((edge, condition)),
+            }
+        }
+        diff.added.extend(new);
+        diff
+    }
+
+    /// The key of the node with the index.
+    pub fn key(&self, index: usize) -> NodeKey {
+        match &self.nodes[index] {
+            DependenceNode::ControlFlow(addr) => NodeKey::of_block(&self.frames, *addr, &[]),
+            DependenceNode::DataDependency(location) => {
+                NodeKey::of_location(&self.frames, location)
+            },
+        }
+    }
+
+    /// The edges between node keys with merged conditions.
+    fn keyed_edges(&self) -> BTreeMap<DiffEdge, SymCondition> {
+        let keys: Vec<NodeKey> = (0 .. self.nodes.len()).map(|index| self.key(index)).collect();
+        let mut edges: BTreeMap<DiffEdge, SymCondition> = BTreeMap::new();
+        for ((start, end), edge) in self.edges.iter() {
+            let key = DiffEdge {
+                start: keys[start].clone(),
+                end: keys[end].clone(),
+                kind: edge.kind,
+            };
+            let condition = match edges.remove(&key) {
+                Some(prev) if prev != edge.condition => prev.or(edge.condition.clone()),
+                _ => edge.condition.clone(),
+            };
+            edges.insert(key, condition);
+        }
+        edges
+    }
+}
+
+impl NodeKey {
+    /// The key of the block at the address entered through the call sites.
+    pub fn of_block(frames: &StackFrames, addr: u64, sites: &[u64]) -> NodeKey {
+        NodeKey {
+            context: sites.iter().map(|&site| block_position(frames, site)).collect(),
+            position: block_position(frames, addr),
+            storage: None,
+        }
+    }
+
+    /// The key of the location.
+    pub fn of_location(frames: &StackFrames, location: &AbstractLocation) -> NodeKey {
+        NodeKey {
+            context: location.trace.iter().map(|&site| block_position(frames, site)).collect(),
+            position: position(frames, frames.function_of(location), location.addr),
+            storage: Some(location.storage.to_string()),
+        }
+    }
+}
+
+impl PdgDiff {
+    /// Whether the graphs have the same edges and conditions.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Visualize the changed edges and their endpoints in a graphviz DOT file.
+    /// Added edges are green, removed ones red and changed ones orange.
+    pub fn visualize<W: Write>(&self, target: W, title: &str) -> io::Result<()> {
+        self.visualize_with(target, title, &VisualConfig::default())
+    }
+
+    /// Visualize the changes like `visualize` with the layout and node shapes
+    /// of the configuration.
+    pub fn visualize_with<W: Write>(
+        &self,
+        target: W,
+        title: &str,
+        config: &VisualConfig,
+    ) -> io::Result<()> {
+        let _phase = crate::trace::phase("render");
+        let mut f = target;
+        write_header(&mut f, &format!("Dependence Delta for {}", title), 40, config)?;
+
+        let edges = self.added.iter().map(|(edge, _)| (edge, "darkgreen"))
+            .chain(self.removed.iter().map(|(edge, _)| (edge, "red")))
+            .chain(self.changed.iter().map(|(edge, _, _)| (edge, "darkorange")));
+
+        let mut nodes = BTreeSet::new();
+        let mut lines = Vec::new();
+        for (edge, color) in edges {
+            nodes.insert(&edge.start);
+            nodes.insert(&edge.end);
+            lines.push((edge, color));
+        }
+
+        let names: BTreeMap<&NodeKey, usize> = nodes.iter()
+            .enumerate()
+            .map(|(index, &key)| (key, index))
+            .collect();
+        for (key, index) in &names {
+            let shape = match key.storage {
+                Some(_) => config.location_shape("ellipse"),
+                None => config.block_shape(),
+            };
+            writeln!(f, "k{} [label=\"{}\", shape={}]", index, key, shape)?;
+        }
+
+        lines.sort();
+        for (edge, color) in lines {
+            writeln!(f, "k{} -> k{} [label=\"{}\", color={}, penwidth=2]",
+                     names[&edge.start], names[&edge.end], edge.kind.name(), color)?;
+        }
+
+        let nodes = vec![
+            ("block".to_string(), format!("shape={}", config.block_shape())),
+            ("location".to_string(), format!("shape={}", config.location_shape("ellipse"))),
+        ];
+        let colors = [("added", "darkgreen"), ("removed", "red"), ("changed", "darkorange")];
+        let edges: Vec<(String, String)> = colors.iter()
+            .map(|&(label, color)| (label.to_string(), format!("color={}, penwidth=2", color)))
+            .collect();
+        write_legend(&mut f, config, &nodes, &edges)?;
+
+        write_footer(&mut f, config)
+    }
+}
+
+impl Display for NodeKey {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for site in &self.context {
+            write!(f, "{} > ", site)?;
+        }
+        write!(f, "{}", self.position)?;
+        match &self.storage {
+            Some(storage) => write!(f, " {}", storage),
+            None => write!(f, " block"),
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.function, self.offset)
+    }
+}
+
+/// The position of the address in the first function containing it.
+pub(super) fn block_position(frames: &StackFrames, addr: u64) -> Position {
+    position(frames, frames.functions_at(addr).first().copied(), addr)
+}
+
+/// The position of the address in the function if it is known or relative
+/// to nothing otherwise.
+fn position(frames: &StackFrames, function: Option<u64>, addr: u64) -> Position {
+    match function {
+        Some(function) => Position {
+            function: frames.frames.get(&function)
+                .map(|frame| frame.name.clone())
+                .unwrap_or_else(|| format!("fn_{:x}", function)),
+            offset: addr.wrapping_sub(function),
+        },
+        None => Position { function: String::new(), offset: addr },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::math::{SymExpr, Symbol, DataType};
+    use crate::x86_64::Register;
+    use super::*;
+    use super::super::{AbstractLocation, StorageLocation, PDGEdge};
+
+    fn graph(edges: Vec<(usize, usize, SymCondition)>) -> ProgramDependenceGraph {
+        let location = |reg| {
+            AbstractLocation::new(0x10, vec![], StorageLocation::Direct(reg))
+        };
+        let nodes = vec![
+            DependenceNode::DataDependency(location(Register::EAX)),
+            DependenceNode::DataDependency(location(Register::ECX)),
+            DependenceNode::DataDependency(location(Register::EDX)),
+        ];
+        let edges = edges.into_iter()
+            .map(|(start, end, condition)| {
+                (start, end, PDGEdge { kind: EdgeKind::DataDependency, condition })
+            })
+            .collect();
+        let frames = Arc::new(StackFrames::default());
+        ProgramDependenceGraph::from_parts(nodes, edges, frames, HashMap::new())
+    }
+
+    #[test]
+    fn diff() {
+        let symbol = SymExpr::Sym(Symbol(DataType::N8, "stdin", 0));
+        let one = SymExpr::from_int(DataType::N8, 1);
+        let cond = SymCondition::Equal(Box::new(symbol), Box::new(one));
+        let old = graph(vec![(0, 1, SymCondition::TRUE), (1, 2, SymCondition::TRUE)]);
+        let new = graph(vec![(1, 2, cond.clone()), (2, 0, SymCondition::TRUE)]);
+
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].0.start.to_string(), "+0x10 eax");
+        assert_eq!(diff.added[0].0.end.storage.as_deref(), Some("eax"));
+        assert_eq!(diff.changed, vec![(diff.changed[0].0.clone(), SymCondition::TRUE, cond)]);
+
+        let mut dot = Vec::new();
+        diff.visualize(&mut dot, "test").unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("k1 -> k2 [label=\"data-dependency\", color=darkorange"));
+    }
+}
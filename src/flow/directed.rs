@@ -0,0 +1,164 @@
+//! Static distances to a target address for directed exploration.
+//!
+//! The distances are measured in instructions on an interprocedural graph
+//! built from the decoded code: instructions flow into their successors and
+//! direct jump targets, calls flow into the called function and into their
+//! return site, and returns flow back to the return sites of all calls to
+//! their function. Indirect jumps and calls have no static successors.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::Program;
+use crate::x86_64::{Instruction, Mnemoic, Operand};
+
+
+/// The shortest static distances from the instructions of a program to a
+/// target address.
+#[derive(Debug, Clone)]
+pub struct Distances {
+    /// The address the distances lead to.
+    pub target: u64,
+    distances: HashMap<u64, usize>,
+}
+
+impl Distances {
+    /// Compute the distances of all instructions to the target.
+    pub fn new(program: &Program, target: u64) -> Distances {
+        let instructions: HashMap<u64, (u64, &Instruction)> = program.code.iter()
+            .map(|(addr, len, instruction, _)| (*addr, (*len, instruction)))
+            .collect();
+
+        // The functions called directly together with their return sites.
+        let mut return_sites: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut predecessors: HashMap<u64, Vec<u64>> = HashMap::new();
+        for (addr, len, instruction, _) in &program.code {
+            let (local, callee) = successors(*addr, *len, instruction);
+            for next in local {
+                predecessors.entry(next).or_default().push(*addr);
+            }
+            for function in callee.into_iter().flat_map(|callee| implementations(program, callee)) {
+                predecessors.entry(function).or_default().push(*addr);
+                return_sites.entry(function).or_default().push(addr + len);
+            }
+        }
+
+        // Returns lead back to the return sites of their function.
+        for (&function, sites) in &return_sites {
+            for ret in returns(&instructions, function) {
+                for &site in sites {
+                    predecessors.entry(site).or_default().push(ret);
+                }
+            }
+        }
+
+        // Search backwards from the target.
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(target, 0);
+        queue.push_back(target);
+
+        while let Some(addr) = queue.pop_front() {
+            let distance = distances[&addr];
+            for &pred in predecessors.get(&addr).into_iter().flatten() {
+                if !distances.contains_key(&pred) {
+                    distances.insert(pred, distance + 1);
+                    queue.push_back(pred);
+                }
+            }
+        }
+
+        Distances { target, distances }
+    }
+
+    /// The distance from the instruction at the address to the target or
+    /// `None` if the target is not statically reachable from it.
+    pub fn get(&self, addr: u64) -> Option<usize> {
+        self.distances.get(&addr).copied()
+    }
+}
+
+/// The successors within the function and the directly called function of
+/// an instruction.
+pub(super) fn successors(addr: u64, len: u64, instruction: &Instruction) -> (Vec<u64>, Option<u64>) {
+    let next = addr + len;
+    let target = match instruction.operands.first() {
+        Some(Operand::Offset(offset)) => Some(next.wrapping_add(*offset as u64)),
+        _ => None,
+    };
+
+    match instruction.mnemoic {
+        Mnemoic::Ret | Mnemoic::Iret | Mnemoic::Iretq => (vec![], None),
+        Mnemoic::Jmp => (target.into_iter().collect(), None),
+        Mnemoic::Call => (vec![next], target),
+        _ => (Some(next).into_iter().chain(target).collect(), None),
+    }
+}
+
+/// The function itself and the implementations it resolves to.
+pub(super) fn implementations(program: &Program, function: u64) -> Vec<u64> {
+    let mut functions = vec![function];
+    if let Some(resolved) = program.resolutions.get(&function) {
+        functions.extend(resolved);
+    }
+    functions
+}
+
+/// The addresses of the return instructions reachable within the function.
+pub(super) fn returns(instructions: &HashMap<u64, (u64, &Instruction)>, function: u64) -> Vec<u64> {
+    let mut visited = HashSet::new();
+    let mut stack = vec![function];
+    let mut rets = Vec::new();
+
+    while let Some(addr) = stack.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+
+        if let Some(&(len, instruction)) = instructions.get(&addr) {
+            if matches!(instruction.mnemoic, Mnemoic::Ret | Mnemoic::Iret | Mnemoic::Iretq) {
+                rets.push(addr);
+            }
+            stack.extend(successors(addr, len, instruction).0);
+        }
+    }
+
+    rets
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::fixture;
+    use crate::flow::{ControlFlowGraph, DataDependencyGraph};
+    use crate::math::SymCondition;
+    use crate::sym::SymConfig;
+    use super::*;
+
+    #[test]
+    fn directed() {
+        let program = Program::new(fixture("case"));
+        let cfg = ControlFlowGraph::new(&program);
+        let target = cfg.nodes[cfg.nodes.len() - 1].addr;
+
+        let distances = Distances::new(&program, target);
+        assert_eq!(distances.get(target), Some(0));
+        assert!(distances.get(program.entry).is_some());
+
+        let config = SymConfig { target: Some(target), .. SymConfig::default() };
+        let directed = ControlFlowGraph::with_config(&program, config);
+        assert!(!directed.nodes_containing(target).is_empty());
+        assert!(directed.nodes.len() <= cfg.nodes.len());
+
+        // The other side of the branch before the target is in the graph
+        // with its block, so that the data flow can be built.
+        let (branch, target) = cfg.edges.iter()
+            .find(|(_, condition)| **condition != SymCondition::TRUE)
+            .map(|(&(branch, target), _)| (cfg.nodes[branch].addr, cfg.nodes[target].addr))
+            .unwrap();
+        let config = SymConfig { target: Some(target), .. SymConfig::default() };
+        let directed = ControlFlowGraph::with_config(&program, config);
+        assert!(!directed.nodes_containing(branch).is_empty());
+        assert!(directed.nodes.iter().all(|node| directed.blocks.contains_key(&node.addr)));
+        assert!(!DataDependencyGraph::new(&directed).nodes.is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+//! The net effects of basic blocks as C-like assignments.
+//!
+//! A block is executed symbolically from a state in which every general
+//! purpose register holds a symbol named after it, like a gadget. Its effect
+//! is the registers and memory it leaves with different values, which are
+//! simplified and written in terms of the values at the start of the block.
+//! All assignments therefore happen at once, at the end of the block. Memory
+//! is named after the stack variable at the address where the frame is known
+//! and values read from memory are named after where they were read, so that
+//! a block reads like
+//!
+//! ```text
+//! rax = (local_8 + 0x1:n64)
+//! local_8 = (local_8 + 0x1:n64)
+//! ```
+//!
+//! System calls are not executed. The register holding their result gets the
+//! `syscall` symbol instead.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::helper::replace_tokens;
+use crate::ir::{Location, MicroOperation};
+use crate::math::{DataType, SharedSolver, Solver, SymCondition, SymExpr, Symbol};
+use crate::sym::{Event, MemoryStrategy, SymState};
+use crate::x86_64::{Register, initial, offset_from, REGISTERS};
+use super::{AbstractLocation, ControlFlowGraph, StorageLocation};
+
+
+/// The net effect of a basic block.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct BlockEffect {
+    /// The registers and memory the block changes with their values at its
+    /// end in terms of the values at its start.
+    pub assignments: Vec<(EffectTarget, SymExpr)>,
+    /// The symbols standing for the values read from memory before the block
+    /// wrote to it, with where they were read.
+    pub loads: Vec<(Symbol, EffectTarget)>,
+    /// The condition under which the block jumps and the jump target if it
+    /// ends with a conditional jump.
+    pub branch: Option<(SymCondition, SymExpr)>,
+    /// Whether the block makes a system call.
+    pub syscall: bool,
+}
+
+/// Something a block assigns to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EffectTarget {
+    Register(Register),
+    /// A variable in the stack frame of the function with its name.
+    Local(String, DataType),
+    /// The memory at an address.
+    Memory(SymExpr, DataType),
+}
+
+impl ControlFlowGraph {
+    /// The net effect of the block of the node.
+    pub fn block_effect(&self, node: usize) -> BlockEffect {
+        let solver: SharedSolver = Rc::new(Solver::with_seed(self.config.seed));
+        self.block_effect_with(node, &solver)
+    }
+
+    /// Write the net effects of all blocks, one assignment per line below the
+    /// index and address of each node.
+    pub fn write_effects<W: Write>(&self, mut f: W) -> io::Result<()> {
+        let solver: SharedSolver = Rc::new(Solver::with_seed(self.config.seed));
+        for (index, node) in self.nodes.iter().enumerate() {
+            writeln!(f, "b{} {:x}:", index, node.addr)?;
+            for line in self.block_effect_with(index, &solver).lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The net effect of the block of the node, simplified with the solver,
+    /// which is empty if the node has no block.
+    pub(super) fn block_effect_with(&self, node: usize, solver: &SharedSolver) -> BlockEffect {
+        let node = &self.nodes[node];
+        let block = match self.blocks.get(&node.addr) {
+            Some(block) => block,
+            None => return BlockEffect::default(),
+        };
+        let trace: Vec<u64> = node.trace.iter().map(|&(site, _)| site).collect();
+
+        // Addresses relative to the stack or frame pointer at the start of
+        // the block refer to the stack variables at the offsets.
+        let target = |addr: SymExpr, data_type: DataType| {
+            let name = [Register::RSP, Register::RBP].iter().find_map(|&base| {
+                let displacement = offset_from(&addr, base)?;
+                let storage = StorageLocation::Indirect {
+                    data_type,
+                    base,
+                    scaled_offset: None,
+                    displacement: Some(displacement),
+                };
+                let location = AbstractLocation::new(block.addr, trace.clone(), storage);
+                let variable = self.frames.variable(&location)?;
+                Some(self.frames.frames[&variable.function].variable_name(variable.offset))
+            });
+            match name {
+                Some(name) => EffectTarget::Local(name, data_type),
+                None => EffectTarget::Memory(addr, data_type),
+            }
+        };
+
+        let mut state = SymState::with_config(
+            MemoryStrategy::PerfectMatches,
+            solver.clone(),
+            self.config.clone(),
+        );
+        for &(register, _) in &REGISTERS {
+            state.set_reg(register, initial(register));
+        }
+
+        let mut stored: Vec<(SymExpr, DataType)> = Vec::new();
+        let mut loads: Vec<(Symbol, EffectTarget)> = Vec::new();
+        let mut branch = None;
+        let mut syscall = false;
+        for (addr, len, _, microcode) in &block.code {
+            for op in &microcode.ops {
+                let mut load = None;
+                match *op {
+                    MicroOperation::Mov { dest: Location::Indirect(data_type, 0, temp), .. } => {
+                        let addr = state.get_temp(temp);
+                        if !stored.contains(&(addr.clone(), data_type)) {
+                            stored.push((addr, data_type));
+                        }
+                    },
+                    MicroOperation::Mov { dest, src: Location::Indirect(data_type, 0, temp) } => {
+                        load = Some((state.get_temp(temp), data_type, dest));
+                    },
+                    MicroOperation::Syscall => {
+                        syscall = true;
+                        let result = SymExpr::Sym(Symbol(DataType::N64, "syscall", 0));
+                        state.set_reg(Register::RAX, result);
+                        continue;
+                    },
+                    _ => {},
+                }
+
+                let next = addr + len;
+                if let Some(Event::Jump { target, condition, relative }) = state.step(next, op) {
+                    if condition != SymCondition::TRUE {
+                        let condition = state.evaluate_condition(&condition);
+                        let target = match relative {
+                            true => target.add(SymExpr::from_ptr(next)),
+                            false => target,
+                        };
+                        branch = Some((
+                            solver.simplify_condition(&condition),
+                            solver.simplify_expr(&target),
+                        ));
+                    }
+                }
+
+                // Fresh symbols read from memory stand for its value at the
+                // start of the block.
+                if let Some((addr, data_type, dest)) = load {
+                    if let SymExpr::Sym(symbol) = state.read_location(dest) {
+                        let known = loads.iter().any(|&(other, _)| other == symbol)
+                            || REGISTERS.iter().any(|&(register, _)| {
+                                initial(register) == SymExpr::Sym(symbol)
+                            });
+                        if !known {
+                            loads.push((symbol, target(addr, data_type)));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut assignments: Vec<(EffectTarget, SymExpr)> = REGISTERS.iter()
+            .map(|&(register, _)| (register, state.get_reg(register)))
+            .filter(|&(register, ref value)| *value != initial(register))
+            .map(|(register, value)| (EffectTarget::Register(register), value))
+            .collect();
+        for (addr, data_type) in stored {
+            let value = state.memory[0].read_expr(addr.clone(), data_type);
+            assignments.push((target(addr, data_type), value));
+        }
+        for (_, value) in &mut assignments {
+            *value = solver.simplify_expr(value);
+        }
+
+        BlockEffect { assignments, loads, branch, syscall }
+    }
+}
+
+impl BlockEffect {
+    /// The assignments, system call and branch as lines with the symbols of
+    /// the values at the start of the block replaced by the names of the
+    /// registers and memory holding them.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.assignments.iter()
+            .map(|(target, value)| format!("{} = {}", target, value))
+            .collect();
+        if self.syscall {
+            lines.push("syscall".to_string());
+        }
+        if let Some((condition, target)) = &self.branch {
+            lines.push(format!("if {} goto {}", condition, target));
+        }
+
+        // Symbols are replaced as whole tokens. The names of values read from
+        // memory are themselves in terms of the registers and earlier reads.
+        let part = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+        let mut names: HashMap<String, String> = REGISTERS.iter()
+            .map(|&(register, _)| (initial(register).to_string(), register.to_string()))
+            .collect();
+        for (symbol, target) in &self.loads {
+            let name = replace_tokens(&target.to_string(), part, &names);
+            names.insert(symbol.to_string(), name);
+        }
+        lines.iter().map(|line| replace_tokens(line, part, &names)).collect()
+    }
+}
+
+impl Display for BlockEffect {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for EffectTarget {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EffectTarget::Register(register) => write!(f, "{}", register),
+            EffectTarget::Local(name, _) => write!(f, "{}", name),
+            EffectTarget::Memory(addr, data_type) => write!(f, "[{}]:{}", addr, data_type),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::ssa::tests::graph;
+    use super::*;
+
+    #[test]
+    fn effects() {
+        // mov eax, [rdi+0x10]; add eax, 1; mov [rdi+0x10], eax; cmp eax, 5; jne 0x1020
+        let cfg = graph(&[
+            (0x1000, &[
+                &[0x8b, 0x47, 0x10],
+                &[0x83, 0xc0, 0x01],
+                &[0x89, 0x47, 0x10],
+                &[0x83, 0xf8, 0x05],
+                &[0x75, 0x12],
+            ]),
+            (0x1010, &[&[0x90]]),
+            (0x1020, &[&[0x90]]),
+        ], &[(0, 1), (0, 2)]);
+
+        let effect = cfg.block_effect(0);
+        assert_eq!(effect.loads.len(), 1);
+        assert!(!effect.syscall && effect.branch.is_some());
+
+        // The counter in memory is named after its address in the value it
+        // is incremented from.
+        let lines = effect.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("rax = "));
+        let counter = effect.loads[0].1.to_string();
+        assert!(counter.starts_with('[') && counter.ends_with("]:n32"));
+        assert!(lines[1].starts_with(&format!("{} = ", counter)));
+        assert!(lines[1][counter.len() ..].contains(&counter));
+        assert!(lines[2].starts_with("if ") && lines[2].ends_with(" goto 0x1020:n64"));
+        assert!(lines.iter().all(|line| line.contains("rdi") && !line.contains("rdi0")));
+
+        let mut export = Vec::new();
+        cfg.write_effects(&mut export).unwrap();
+        let export = String::from_utf8(export).unwrap();
+        assert!(export.starts_with("b0 1000:\n    rax = "));
+        assert!(export.contains("b1 1010:\nb2 1020:\n"));
+
+        // Nodes without a block have no effect.
+        let mut cfg = cfg;
+        cfg.blocks.remove(&0x1020);
+        assert_eq!(cfg.block_effect(2), BlockEffect::default());
+
+        // Symbols are only replaced as a whole.
+        let read = Symbol(DataType::N64, "mem", 1);
+        let effect = BlockEffect {
+            assignments: vec![(
+                EffectTarget::Register(Register::RAX),
+                SymExpr::Sym(Symbol(DataType::N64, "xmem", 1)).add(SymExpr::Sym(read)),
+            )],
+            loads: vec![(read, EffectTarget::Memory(initial(Register::RDI), DataType::N64))],
+            .. BlockEffect::default()
+        };
+        assert_eq!(effect.lines(), vec!["rax = (xmem1:n64 + [rdi]:n64)"]);
+    }
+}
@@ -0,0 +1,659 @@
+//! Semantic equivalence of small functions in two binaries.
+//!
+//! Both functions are executed symbolically along all of their paths from
+//! the entry to a return. They start from the same state in which every
+//! general purpose register holds a symbol named after it and memory that is
+//! read before it is written holds a symbol for its address, so that the
+//! inputs of both functions are the same symbols. The outputs are the result
+//! register of the calling convention and the memory outside of the stack
+//! frame that either function writes. For every pair of paths the solver
+//! looks for inputs taking both paths for which some output differs, which
+//! are then a distinguishing input.
+//!
+//! Memory is matched by the expressions of the addresses like the perfect
+//! matches strategy does, which only holds if all accesses outside of the
+//! stack frame go through the same pointer at constant offsets and do not
+//! partially overlap. Functions that access memory through different
+//! pointers, which may alias, or in overlapping parts, call others, make
+//! system calls, access memory-mapped I/O, jump indirectly, compute values
+//! the lifter cannot, have too many paths or run too long on a path cannot
+//! be compared and are reported as inconclusive.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::Program;
+use crate::ir::{Location, MicroOperation, Transfer};
+use crate::math::{DataType, Integer, SharedSolver, Solver, SymCondition, SymExpr, Symbol};
+use crate::math::Traversed;
+use crate::sym::{CallingConvention, Event, MemoryStrategy, SymState};
+use crate::x86_64::{Register, initial, offset_from, REGISTERS};
+
+
+/// Limits and conventions for equivalence checks.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EquivalenceConfig {
+    /// The maximum number of paths through each function.
+    pub max_paths: usize,
+    /// The maximum number of instructions executed on a path.
+    pub max_instructions: usize,
+    /// The convention whose result register is compared.
+    pub convention: CallingConvention,
+    /// The type of the result, so that garbage in the upper bits of the
+    /// result register of functions returning smaller values is ignored.
+    pub result: DataType,
+    /// The seed of the solver finding counterexamples, like `SymConfig::seed`.
+    pub seed: u64,
+}
+
+impl Default for EquivalenceConfig {
+    fn default() -> EquivalenceConfig {
+        EquivalenceConfig {
+            max_paths: 64,
+            max_instructions: 1000,
+            convention: CallingConvention::sysv(),
+            result: DataType::N64,
+            seed: 0,
+        }
+    }
+}
+
+/// The outcome of an equivalence check.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Equivalence {
+    /// The functions compute the same outputs for all inputs.
+    Equivalent,
+    /// Some input makes the functions compute different outputs.
+    Different(Counterexample),
+    /// The functions could not be compared for the reason.
+    Inconclusive(String),
+}
+
+/// An input distinguishing two functions.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counterexample {
+    /// The values of the registers at the entry and of the memory read before
+    /// it is written by name, sorted by name. Inputs left out do not matter.
+    pub inputs: Vec<(String, Integer)>,
+    /// The differing outputs by name with their values in the first and the
+    /// second function.
+    pub outputs: Vec<(String, Integer, Integer)>,
+}
+
+/// Check whether the functions at the addresses of the programs are
+/// equivalent.
+pub fn check_equivalence(
+    first: &Program,
+    first_function: u64,
+    second: &Program,
+    second_function: u64,
+    config: &EquivalenceConfig,
+) -> Equivalence {
+    let solver: SharedSolver = Rc::new(Solver::with_seed(config.seed));
+    let mut inputs = Inputs::default();
+    let paths = explore(first, first_function, config, &solver, &mut inputs)
+        .and_then(|first| {
+            explore(second, second_function, config, &solver, &mut inputs)
+                .map(|second| (first, second))
+        });
+    let (first, second) = match paths {
+        Ok(paths) => paths,
+        Err(reason) => return Equivalence::Inconclusive(reason),
+    };
+
+    // Memory is only matched correctly if no two accesses may alias or
+    // partially overlap.
+    let stores = first.iter().chain(&second)
+        .flat_map(|outcome| outcome.stores.iter().map(|(addr, data_type, _)| (addr, *data_type)));
+    let accesses: Vec<(&SymExpr, DataType)> = inputs.memory.iter()
+        .map(|(addr, data_type)| (addr, *data_type))
+        .chain(stores)
+        .filter(|(addr, _)| offset_from(addr, Register::RSP).is_none())
+        .collect();
+    for (index, &(a, a_type)) in accesses.iter().enumerate() {
+        for &(b, b_type) in &accesses[index + 1 ..] {
+            let same = a == b && a_type == b_type;
+            if !same && base(a) == base(b) && overlap(a, a_type, b, b_type) {
+                return Equivalence::Inconclusive(format!(
+                    "the accesses to {} and {} overlap",
+                    inputs.describe_memory(a, a_type), inputs.describe_memory(b, b_type),
+                ));
+            }
+        }
+    }
+    let mut bases: Vec<Option<&SymExpr>> = Vec::new();
+    for &(addr, _) in &accesses {
+        if !bases.contains(&base(addr)) {
+            bases.push(base(addr));
+        }
+    }
+    if let [a, b, ..] = bases[..] {
+        let describe = |base: Option<&SymExpr>| match base {
+            Some(base) => inputs.describe(base),
+            None => "constant addresses".to_string(),
+        };
+        return Equivalence::Inconclusive(format!(
+            "the accesses through {} and {} may alias", describe(a), describe(b),
+        ));
+    }
+
+    let result = config.convention.result.to_string();
+    for a in &first {
+        for b in &second {
+            // Memory written by only one of the functions keeps its initial
+            // value in the other one.
+            let mut outputs = vec![(result.clone(), a.result.clone(), b.result.clone())];
+            for &(ref addr, data_type, ref value) in &a.stores {
+                let other = b.stored(addr, data_type)
+                    .unwrap_or_else(|| inputs.memory(addr, data_type));
+                outputs.push((inputs.describe_memory(addr, data_type), value.clone(), other));
+            }
+            for &(ref addr, data_type, ref value) in &b.stores {
+                if a.stored(addr, data_type).is_none() {
+                    let other = inputs.memory(addr, data_type);
+                    outputs.push((inputs.describe_memory(addr, data_type), other, value.clone()));
+                }
+            }
+
+            let same = outputs.iter().fold(SymCondition::TRUE, |all, (_, x, y)| {
+                all.and(x.clone().equal(y.clone()))
+            });
+            let condition = a.condition.clone().and(b.condition.clone()).and(same.not());
+            if let Some(model) = solver.model(&condition) {
+                return Equivalence::Different(inputs.counterexample(&model, outputs));
+            }
+        }
+    }
+    Equivalence::Equivalent
+}
+
+/// Check whether the functions with the name in the programs are equivalent.
+pub fn check_equivalence_named(
+    first: &Program,
+    second: &Program,
+    name: &str,
+    config: &EquivalenceConfig,
+) -> Result<Equivalence, EquivalenceError> {
+    let function = |program: &Program, which: &str| {
+        program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .ok_or_else(|| EquivalenceError::new(
+                format!("no function named {} in the {} binary", name, which),
+            ))
+    };
+    let first_function = function(first, "first")?;
+    let second_function = function(second, "second")?;
+    Ok(check_equivalence(first, first_function, second, second_function, config))
+}
+
+/// The pointer the address is at a constant offset from, or `None` for a
+/// constant address.
+fn base(addr: &SymExpr) -> Option<&SymExpr> {
+    let int = |expr: &SymExpr| matches!(expr, SymExpr::Int(_));
+    match addr {
+        SymExpr::Int(_) => None,
+        SymExpr::Add(a, b) | SymExpr::Sub(a, b) if int(b) => Some(&**a),
+        SymExpr::Add(a, b) if int(a) => Some(&**b),
+        _ => Some(addr),
+    }
+}
+
+/// The constant offset of the address from its base.
+fn offset(addr: &SymExpr) -> u64 {
+    let int = |expr: &SymExpr| match *expr {
+        SymExpr::Int(Integer(_, value)) => Some(value),
+        _ => None,
+    };
+    match addr {
+        SymExpr::Int(Integer(_, value)) => *value,
+        SymExpr::Add(_, b) if int(b).is_some() => int(b).unwrap(),
+        SymExpr::Sub(_, b) if int(b).is_some() => int(b).unwrap().wrapping_neg(),
+        SymExpr::Add(a, _) => int(a).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Whether two accesses at addresses with the same base share some bytes.
+fn overlap(a: &SymExpr, a_type: DataType, b: &SymExpr, b_type: DataType) -> bool {
+    let distance = offset(b).wrapping_sub(offset(a));
+    match (distance as i64) < 0 {
+        false => distance < a_type.bytes() as u64,
+        true => distance.wrapping_neg() < b_type.bytes() as u64,
+    }
+}
+
+/// A path through a function from the entry to a return.
+#[derive(Debug, Clone)]
+struct Outcome {
+    /// The condition under which the path is taken.
+    condition: SymCondition,
+    /// The value of the result register.
+    result: SymExpr,
+    /// The memory outside of the stack the path writes to as (address, type,
+    /// value) triples.
+    stores: Vec<(SymExpr, DataType, SymExpr)>,
+}
+
+impl Outcome {
+    /// The value the path writes to the memory.
+    fn stored(&self, addr: &SymExpr, data_type: DataType) -> Option<SymExpr> {
+        self.stores.iter()
+            .find(|&&(ref other, other_type, _)| other == addr && other_type == data_type)
+            .map(|(_, _, value)| value.clone())
+    }
+}
+
+/// A path through a function that is being executed.
+#[derive(Debug, Clone)]
+struct Path {
+    state: SymState,
+    addr: u64,
+    condition: SymCondition,
+    steps: usize,
+    /// The fresh symbols read from memory with the address they were read
+    /// from in the order they were read.
+    loads: Vec<(Symbol, SymExpr)>,
+    /// Where the path writes to memory.
+    stores: Vec<(SymExpr, DataType)>,
+}
+
+/// Execute all paths through the function at the address.
+fn explore(
+    program: &Program,
+    function: u64,
+    config: &EquivalenceConfig,
+    solver: &SharedSolver,
+    inputs: &mut Inputs,
+) -> Result<Vec<Outcome>, String> {
+    let code: HashMap<u64, usize> = program.code.iter()
+        .enumerate()
+        .map(|(index, &(addr, ..))| (addr, index))
+        .collect();
+
+    let mut state = SymState::new(MemoryStrategy::PerfectMatches, solver.clone());
+    for &(register, _) in &REGISTERS {
+        state.set_reg(register, initial(register));
+    }
+    let start = Path {
+        state,
+        addr: function,
+        condition: SymCondition::TRUE,
+        steps: 0,
+        loads: Vec::new(),
+        stores: Vec::new(),
+    };
+
+    let mut outcomes = Vec::new();
+    let mut pending = vec![start];
+    let mut count = 1;
+    while let Some(mut path) = pending.pop() {
+        let feasible = loop {
+            path.steps += 1;
+            if path.steps > config.max_instructions {
+                return Err(format!("a path through {:#x} runs too long", function));
+            }
+            let &(addr, len, _, ref microcode) = code.get(&path.addr)
+                .map(|&index| &program.code[index])
+                .ok_or_else(|| format!("no instruction at {:#x}", path.addr))?;
+            match microcode.transfer {
+                Transfer::Return => break true,
+                Transfer::Call => return Err(format!("call at {:#x}", addr)),
+                _ => {},
+            }
+
+            let next = addr + len;
+            let mut target = next;
+            let mut feasible = true;
+            for op in &microcode.ops {
+                let mut load = None;
+                match *op {
+                    MicroOperation::Mov { dest: Location::Indirect(data_type, 0, temp), .. } => {
+                        let addr = path.state.get_temp(temp);
+                        if !path.stores.contains(&(addr.clone(), data_type)) {
+                            path.stores.push((addr, data_type));
+                        }
+                    },
+                    MicroOperation::Mov { dest, src: Location::Indirect(_, 0, temp) } => {
+                        load = Some((path.state.get_temp(temp), dest));
+                    },
+                    MicroOperation::Syscall => return Err(format!("system call at {:#x}", addr)),
+                    _ => {},
+                }
+
+                match path.state.step(addr, op) {
+                    Some(Event::Jump { target: jump, condition, relative }) => {
+                        let jump = match relative {
+                            true => jump.add(SymExpr::from_ptr(next)),
+                            false => jump,
+                        };
+                        let jump = match solver.simplify_expr(&jump) {
+                            SymExpr::Int(Integer(_, jump)) => jump,
+                            _ => return Err(format!("indirect jump at {:#x}", addr)),
+                        };
+                        if condition == SymCondition::TRUE {
+                            target = jump;
+                            break;
+                        }
+
+                        let condition = path.state.evaluate_condition(&condition);
+                        let condition = solver.simplify_condition(&condition);
+                        let taken = path.condition.clone().and(condition.clone());
+                        if solver.model(&taken).is_some() {
+                            count += 1;
+                            if count > config.max_paths {
+                                return Err(format!("too many paths through {:#x}", function));
+                            }
+                            let mut fork = path.clone();
+                            fork.addr = jump;
+                            fork.condition = taken;
+                            pending.push(fork);
+                        }
+                        path.condition = path.condition.clone().and(condition.not());
+                        if solver.model(&path.condition).is_none() {
+                            feasible = false;
+                            break;
+                        }
+                    },
+                    Some(Event::Device(..)) => {
+                        return Err(format!("memory-mapped I/O at {:#x}", addr));
+                    },
+                    Some(Event::Query(..)) | None => {},
+                    Some(_) => return Err(format!("system call at {:#x}", addr)),
+                }
+
+                // Fresh symbols read from memory stand for its value at the
+                // entry of the function.
+                if let Some((addr, dest)) = load {
+                    let mut fresh = Vec::new();
+                    path.state.read_location(dest).traverse(&mut |node| {
+                        if let Traversed::Expr(&SymExpr::Sym(symbol @ Symbol(_, "mem", _))) = node {
+                            fresh.push(symbol);
+                        }
+                    });
+                    for symbol in fresh {
+                        if !path.loads.iter().any(|&(other, _)| other == symbol) {
+                            path.loads.push((symbol, addr.clone()));
+                        }
+                    }
+                }
+            }
+
+            if !feasible {
+                break false;
+            }
+            path.addr = target;
+        };
+
+        if feasible {
+            outcomes.push(inputs.outcome(path, config, solver)?);
+        }
+    }
+    Ok(outcomes)
+}
+
+/// The memory inputs shared by both functions.
+#[derive(Debug, Clone, Default)]
+struct Inputs {
+    /// The addresses and types of memory read before it is written. The
+    /// symbol for the value at the index `i` is `memory{i}`.
+    memory: Vec<(SymExpr, DataType)>,
+}
+
+impl Inputs {
+    /// The symbol for the initial value of the memory at the address.
+    fn memory(&mut self, addr: &SymExpr, data_type: DataType) -> SymExpr {
+        let index = match self.memory.iter().position(|(other, other_type)| {
+            other == addr && *other_type == data_type
+        }) {
+            Some(index) => index,
+            None => {
+                self.memory.push((addr.clone(), data_type));
+                self.memory.len() - 1
+            },
+        };
+        SymExpr::Sym(Symbol(data_type, "memory", index))
+    }
+
+    /// Describe the outcome of an executed path in terms of the inputs.
+    fn outcome(&mut self, path: Path, config: &EquivalenceConfig, solver: &SharedSolver)
+    -> Result<Outcome, String> {
+        // Addresses only depend on values read before, so the loads are
+        // renamed in order.
+        let mut renames: HashMap<Symbol, SymExpr> = HashMap::new();
+        let rename = |renames: &HashMap<Symbol, SymExpr>, expr: &SymExpr| {
+            let mut expr = expr.clone();
+            expr.replace_symbols(&|symbol| {
+                renames.get(&symbol).cloned().unwrap_or(SymExpr::Sym(symbol))
+            });
+            solver.simplify_expr(&expr)
+        };
+        for (symbol, addr) in &path.loads {
+            let addr = rename(&renames, addr);
+            renames.insert(*symbol, self.memory(&addr, symbol.0));
+        }
+
+        let mut condition = path.condition.clone();
+        condition.replace_symbols(&|symbol| {
+            renames.get(&symbol).cloned().unwrap_or(SymExpr::Sym(symbol))
+        });
+        let register = config.convention.result.address();
+        let result = path.state.memory[1].read_direct(register, config.result);
+        let result = rename(&renames, &result);
+
+        let mut stores: Vec<(SymExpr, DataType, SymExpr)> = Vec::new();
+        for (raw, data_type) in &path.stores {
+            let addr = rename(&renames, raw);
+            if offset_from(&addr, Register::RSP).is_some() {
+                continue;
+            }
+            let value = path.state.memory[0].read_expr(raw.clone(), *data_type);
+            let value = rename(&renames, &value);
+            stores.retain(|&(ref other, other_type, _)| *other != addr || other_type != *data_type);
+            stores.push((addr, *data_type, value));
+        }
+
+        // Values the lifter cannot compute are fresh symbols of one function
+        // only and cannot be compared.
+        let mut opaque = None;
+        let mut check = |node: Traversed| {
+            if let Traversed::Expr(&SymExpr::Sym(symbol)) = node {
+                let input = symbol.1 == "memory"
+                    || REGISTERS.iter().any(|&(register, _)| {
+                        initial(register) == SymExpr::Sym(symbol)
+                    });
+                if !input {
+                    opaque = Some(symbol);
+                }
+            }
+        };
+        condition.traverse(&mut check);
+        result.traverse(&mut check);
+        for (addr, _, value) in &stores {
+            addr.traverse(&mut check);
+            value.traverse(&mut check);
+        }
+        if let Some(symbol) = opaque {
+            return Err(format!("the value {} cannot be computed", symbol));
+        }
+
+        Ok(Outcome { condition, result, stores })
+    }
+
+    /// The name of the memory at the address.
+    fn describe_memory(&self, addr: &SymExpr, data_type: DataType) -> String {
+        format!("[{}]:{}", self.describe(addr), data_type)
+    }
+
+    /// The expression with the input symbols replaced by their names.
+    fn describe(&self, expr: &SymExpr) -> String {
+        // Longer symbols go first, so that symbols containing others are
+        // replaced as a whole.
+        let mut replacements: Vec<(String, String)> = REGISTERS.iter()
+            .map(|&(register, _)| (initial(register).to_string(), register.to_string()))
+            .collect();
+        for (index, (addr, data_type)) in self.memory.iter().enumerate() {
+            let symbol = Symbol(*data_type, "memory", index).to_string();
+            let mut name = format!("[{}]:{}", addr, data_type);
+            for (symbol, replacement) in &replacements {
+                name = name.replace(symbol.as_str(), replacement);
+            }
+            replacements.push((symbol, name));
+        }
+        replacements.sort_by_key(|(symbol, _)| std::cmp::Reverse(symbol.len()));
+
+        let mut description = expr.to_string();
+        for (symbol, name) in &replacements {
+            description = description.replace(symbol.as_str(), name);
+        }
+        description
+    }
+
+    /// The counterexample given by the model for the outputs.
+    fn counterexample(
+        &self,
+        model: &HashMap<Symbol, Integer>,
+        outputs: Vec<(String, SymExpr, SymExpr)>,
+    ) -> Counterexample {
+        let mut inputs: Vec<(String, Integer)> = model.iter()
+            .map(|(&symbol, &value)| (self.describe(&SymExpr::Sym(symbol)), value))
+            .collect();
+        inputs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let value = |symbol: Symbol| {
+            Some(model.get(&symbol).copied().unwrap_or(Integer(symbol.0, 0)))
+        };
+        let outputs = outputs.into_iter()
+            .map(|(name, a, b)| (name, a.evaluate(&value), b.evaluate(&value)))
+            .filter(|(_, a, b)| a != b)
+            .collect();
+        Counterexample { inputs, outputs }
+    }
+}
+
+impl Display for Equivalence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Equivalence::Equivalent => write!(f, "equivalent"),
+            Equivalence::Different(counterexample) => {
+                write!(f, "different for {}", counterexample)
+            },
+            Equivalence::Inconclusive(reason) => write!(f, "inconclusive: {}", reason),
+        }
+    }
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let inputs: Vec<String> = self.inputs.iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        write!(f, "{}", inputs.join(", "))?;
+        for (name, a, b) in &self.outputs {
+            write!(f, "; {} is {} instead of {}", name, b, a)?;
+        }
+        Ok(())
+    }
+}
+
+/// An error finding the functions to compare.
+pub struct EquivalenceError {
+    pub message: String,
+}
+
+impl EquivalenceError {
+    fn new(message: String) -> EquivalenceError {
+        EquivalenceError { message }
+    }
+}
+
+impl Display for EquivalenceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Failed to check equivalence: {}", self.message)
+    }
+}
+
+impl std::error::Error for EquivalenceError {}
+debug_display!(EquivalenceError);
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    /// A program with a single function `f` made of the code.
+    fn program(binary: &[u8]) -> Program {
+        let base = 0x1000;
+        let mut symbols = HashMap::new();
+        symbols.insert(base, "f".to_string());
+        Program::from_code(base, binary, symbols)
+    }
+
+    #[test]
+    fn equivalence() {
+        let config = EquivalenceConfig::default();
+        let check = |a: &Program, b: &Program| check_equivalence_named(a, b, "f", &config).unwrap();
+
+        // lea eax, [rdi+rdi]; ret
+        let double = program(&[0x8d, 0x04, 0x3f, 0xc3]);
+        // mov eax, edi; add eax, eax; ret
+        let add = program(&[0x89, 0xf8, 0x01, 0xc0, 0xc3]);
+        // lea eax, [rdi+rdi]; cmp edi, 0x64; jne +2; xor eax, eax; ret
+        let patched = program(&[0x8d, 0x04, 0x3f, 0x83, 0xff, 0x64, 0x75, 0x02, 0x31, 0xc0, 0xc3]);
+        assert_eq!(check(&double, &add), Equivalence::Equivalent);
+
+        // Only an argument of 100 tells the patched function apart.
+        let counterexample = match check(&double, &patched) {
+            Equivalence::Different(counterexample) => counterexample,
+            other => panic!("expected a difference, found {}", other),
+        };
+        let rdi = counterexample.inputs.iter().find(|(name, _)| name == "rdi").unwrap().1;
+        let other = EquivalenceConfig { seed: 7, .. EquivalenceConfig::default() };
+        let seeded = |a: &Program, b: &Program| check_equivalence_named(a, b, "f", &other).unwrap();
+        assert_eq!(seeded(&double, &patched), seeded(&double, &patched));
+        assert_eq!(rdi.1 & 0xffff_ffff, 0x64);
+        assert_eq!(counterexample.outputs.len(), 1);
+        let (name, a, b) = &counterexample.outputs[0];
+        assert_eq!((name.as_str(), a.1, b.1), ("rax", 0xc8, 0));
+
+        // mov [rdi], esi; ret and mov dword [rdi], 0; ret
+        let store = program(&[0x89, 0x37, 0xc3]);
+        let clear = program(&[0xc7, 0x07, 0x00, 0x00, 0x00, 0x00, 0xc3]);
+        match check(&store, &clear) {
+            Equivalence::Different(counterexample) => {
+                assert!(counterexample.outputs.iter().any(|(name, ..)| name == "[rdi]:n32"));
+            },
+            other => panic!("expected a difference, found {}", other),
+        }
+
+        // The stores may write the same memory, which is not modeled.
+        // mov [rdi], esi; mov dword [rdx], 0; ret
+        let twice = program(&[0x89, 0x37, 0xc7, 0x02, 0x00, 0x00, 0x00, 0x00, 0xc3]);
+        match check(&store, &twice) {
+            Equivalence::Inconclusive(reason) => {
+                assert_eq!(reason, "the accesses through rdi and rdx may alias");
+            },
+            other => panic!("expected an inconclusive check, found {}", other),
+        }
+
+        // Stores at the same pointer overlap if their offsets or widths differ.
+        // mov [rdi], rsi; ret and mov [rdi], esi; mov dword [rdi+4], 0; ret
+        let wide = program(&[0x48, 0x89, 0x37, 0xc3]);
+        let halves = program(&[0x89, 0x37, 0xc7, 0x47, 0x04, 0x00, 0x00, 0x00, 0x00, 0xc3]);
+        match check(&wide, &halves) {
+            Equivalence::Inconclusive(reason) => {
+                assert_eq!(reason, "the accesses to [rdi]:n64 and [rdi]:n32 overlap");
+            },
+            other => panic!("expected an inconclusive check, found {}", other),
+        }
+
+        // call 0; ret
+        let call = program(&[0xe8, 0xfb, 0xef, 0xff, 0xff, 0xc3]);
+        assert!(matches!(check(&double, &call), Equivalence::Inconclusive(_)));
+
+        let error = check_equivalence_named(&double, &add, "g", &config).unwrap_err();
+        assert_eq!(error.message, "no function named g in the first binary");
+    }
+}
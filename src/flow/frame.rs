@@ -0,0 +1,351 @@
+//! Reconstruction of stack frames and naming of stack variables.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::Program;
+use crate::math::{DataType, Integer};
+use crate::x86_64::{Instruction, Mnemoic, Operand, Register};
+use super::{AbstractLocation, ControlFlowNode, StorageLocation, write_trace};
+
+
+/// The layout of the stack frame of a function.
+///
+/// All offsets are relative to the stack pointer at the entry of the function,
+/// which points to the return address. Locals have negative offsets and
+/// arguments passed on the stack positive ones.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StackFrame {
+    /// The address of the function.
+    pub function: u64,
+    /// The symbol name of the function or a name derived from its address.
+    pub name: String,
+    /// The offset `rbp` points to if it is set up as frame pointer.
+    pub frame_pointer: Option<i64>,
+    /// The registers saved by the prologue and the offsets they are saved at.
+    pub saved: Vec<(Register, i64)>,
+    /// The maximum number of bytes the frame extends below the return address.
+    pub size: u64,
+    /// The offsets and data types of the accessed stack variables.
+    pub variables: BTreeMap<i64, DataType>,
+}
+
+impl StackFrame {
+    /// The name of the stack variable at the offset.
+    pub fn variable_name(&self, offset: i64) -> String {
+        if let Some((reg, _)) = self.saved.iter().find(|saved| saved.1 == offset) {
+            format!("saved_{}", reg)
+        } else if offset < 0 {
+            format!("local_{:x}", -offset)
+        } else if offset == 0 {
+            "return_address".to_string()
+        } else {
+            format!("arg_{:x}", offset)
+        }
+    }
+}
+
+/// A variable in the stack frame of a function.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct StackVariable {
+    /// The function owning the frame.
+    pub function: u64,
+    /// The offset in the frame.
+    pub offset: i64,
+}
+
+/// The stack frames of all functions in a control flow graph.
+#[derive(Debug, Clone, Default)]
+pub struct StackFrames {
+    /// The frames by function address.
+    pub frames: HashMap<u64, StackFrame>,
+    /// The stack pointer offsets before the instructions by (function, address).
+    offsets: HashMap<(u64, u64), i64>,
+    /// The functions containing the instruction at an address.
+    functions: HashMap<u64, Vec<u64>>,
+    /// The functions called at the call sites.
+    callees: HashMap<u64, u64>,
+}
+
+impl StackFrames {
+    /// Reconstruct the frames of the functions entered by the nodes of a
+    /// control flow graph and the roots.
+    pub fn new(program: &Program, nodes: &[ControlFlowNode], roots: &[u64]) -> StackFrames {
+        StackFrames::with_threads(program, nodes, roots, 1)
+    }
+
+    /// Reconstruct the frames like `new`, analyzing the functions on the
+    /// number of threads with the `parallel` feature (0 for one per core).
+    pub fn with_threads(
+        program: &Program,
+        nodes: &[ControlFlowNode],
+        roots: &[u64],
+        threads: usize,
+    ) -> StackFrames {
+        let code: HashMap<u64, (u64, &Instruction)> = program.code.iter()
+            .map(|(addr, len, instruction, _)| (*addr, (*len, instruction)))
+            .collect();
+
+        let mut frames = StackFrames::default();
+        let mut entries: HashSet<u64> = roots.iter().copied().collect();
+        for node in nodes {
+            for &(site, function) in &node.trace {
+                frames.callees.insert(site, function);
+                entries.insert(function);
+            }
+        }
+
+        // The functions are analyzed independently of each other, so that they
+        // can be analyzed in parallel, and merged in order.
+        let mut sorted: Vec<u64> = entries.iter().copied().collect();
+        sorted.sort_unstable();
+        let analyzed = crate::parallel::map(threads, &sorted, |&function| {
+            let name = program.symbols.get(&function)
+                .cloned()
+                .unwrap_or_else(|| format!("fn_{:x}", function));
+            analyze(function, name, &code, &entries)
+        });
+
+        for (frame, visited) in analyzed {
+            for (addr, offset) in visited {
+                frames.offsets.insert((frame.function, addr), offset);
+                frames.functions.entry(addr).or_default().push(frame.function);
+            }
+            frames.frames.insert(frame.function, frame);
+        }
+
+        frames
+    }
+
+    /// The function the instruction of a location belongs to, preferring the
+    /// one called at the innermost call site of its trace.
+    pub fn function_of(&self, location: &AbstractLocation) -> Option<u64> {
+        let callee = location.trace.last().and_then(|site| self.callees.get(site));
+        if let Some(&callee) = callee {
+            if self.offsets.contains_key(&(callee, location.addr)) {
+                return Some(callee);
+            }
+        }
+        self.functions.get(&location.addr)?.first().copied()
+    }
+
+    /// The offset of the stack pointer before the instruction at the address
+    /// in the function from its value at the entry of the function.
+    pub fn stack_pointer(&self, function: u64, addr: u64) -> Option<i64> {
+        self.offsets.get(&(function, addr)).copied()
+    }
+
+    /// The functions containing the instruction at the address.
+    pub fn functions_at(&self, addr: u64) -> &[u64] {
+        self.functions.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// The stack variable a location refers to if any.
+    pub fn variable(&self, location: &AbstractLocation) -> Option<StackVariable> {
+        let function = self.function_of(location)?;
+        let offset = self.offsets[&(function, location.addr)];
+        let frame_pointer = self.frames[&function].frame_pointer;
+        let offset = stack_offset(location.storage, offset, frame_pointer)?;
+        Some(StackVariable { function, offset })
+    }
+
+    /// The name of the stack variable a location refers to like `local_8 of foo`.
+    pub fn name(&self, location: &AbstractLocation) -> Option<String> {
+        let variable = self.variable(location)?;
+        let frame = &self.frames[&variable.function];
+        Some(format!("{} of {}", frame.variable_name(variable.offset), frame.name))
+    }
+
+    /// Display a location with the name of its stack variable if it has one.
+    pub fn describe<'a>(&'a self, location: &'a AbstractLocation) -> NamedLocation<'a> {
+        NamedLocation { frames: self, location }
+    }
+}
+
+/// Follow the stack pointer through the function starting at the entry and
+/// return its frame with the visited instructions and offsets before them.
+fn analyze(
+    function: u64,
+    name: String,
+    code: &HashMap<u64, (u64, &Instruction)>,
+    entries: &HashSet<u64>,
+) -> (StackFrame, Vec<(u64, i64)>) {
+    use Mnemoic::*;
+
+    let mut frame = StackFrame {
+        function,
+        name,
+        frame_pointer: None,
+        saved: Vec::new(),
+        size: 0,
+        variables: BTreeMap::new(),
+    };
+
+    let rsp = Operand::Direct(Register::RSP);
+    let rbp = Operand::Direct(Register::RBP);
+    let mut prologue = true;
+    let mut stack = vec![(function, 0)];
+    let mut offsets = HashMap::new();
+    let mut visited = Vec::new();
+
+    while let Some((addr, offset)) = stack.pop() {
+        if offsets.contains_key(&addr) {
+            continue;
+        }
+        let (len, instruction) = match code.get(&addr) {
+            Some(&entry) => entry,
+            None => continue,
+        };
+
+        offsets.insert(addr, offset);
+        visited.push((addr, offset));
+
+        for &operand in &instruction.operands {
+            if let Some(storage) = StorageLocation::from_operand(operand) {
+                if let Some(variable) = stack_offset(storage, offset, frame.frame_pointer) {
+                    frame.variables.entry(variable).or_insert_with(|| storage.data_type());
+                }
+            }
+        }
+
+        // Find the stack pointer offset after the instruction.
+        let operands = instruction.operands.as_slice();
+        let after = match (instruction.mnemoic, operands) {
+            (Push, [Operand::Direct(reg)]) if prologue => {
+                frame.saved.push((*reg, offset - 8));
+                Some(offset - 8)
+            },
+            (Push, _) => Some(offset - 8),
+            (Pop, _) => Some(offset + 8),
+            (Sub, [target, Operand::Immediate(int)]) if *target == rsp => Some(offset - signed(*int)),
+            (Add, [target, Operand::Immediate(int)]) if *target == rsp => Some(offset + signed(*int)),
+            (Mov, [target, source]) if *target == rbp && *source == rsp => {
+                frame.frame_pointer = Some(offset);
+                Some(offset)
+            },
+            (Mov, [target, source]) if *target == rsp && *source == rbp => frame.frame_pointer,
+            (Leave, _) => frame.frame_pointer.map(|base| base + 8),
+            (Lea, [target, source]) if *target == rsp => StorageLocation::from_operand(*source)
+                .and_then(|storage| stack_offset(storage, offset, frame.frame_pointer)),
+            // Any other write to the stack pointer loses track of it.
+            (Call, _) | (Cmp, _) | (Test, _) => Some(offset),
+            (_, [target, ..]) if *target == rsp => None,
+            _ => Some(offset),
+        };
+
+        prologue &= match (instruction.mnemoic, operands) {
+            (Push, _) => true,
+            (Mov, [target, source]) => *target == rbp && *source == rsp,
+            (Sub, [target, _]) => *target == rsp,
+            _ => false,
+        };
+
+        let after = match after {
+            Some(after) => after,
+            None => continue,
+        };
+        frame.size = frame.size.max((-after).max(0) as u64);
+
+        // Follow the direct control flow within the function. Jumps to
+        // other functions are tail calls.
+        let next = addr + len;
+        let target = match operands.first() {
+            Some(Operand::Offset(offset)) => Some(next.wrapping_add(*offset as u64)),
+            _ => None,
+        };
+
+        match instruction.mnemoic {
+            Ret | Iret | Iretq => {},
+            Jmp => if let Some(target) = target.filter(|target| !entries.contains(target)) {
+                stack.push((target, after));
+            },
+            Call => stack.push((next, after)),
+            _ => {
+                stack.push((next, after));
+                if let Some(target) = target {
+                    stack.push((target, after));
+                }
+            },
+        }
+    }
+
+    (frame, visited)
+}
+
+/// A location displayed with the name of its stack variable.
+pub struct NamedLocation<'a> {
+    frames: &'a StackFrames,
+    location: &'a AbstractLocation,
+}
+
+impl Display for NamedLocation<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.frames.name(self.location) {
+            Some(name) => {
+                write!(f, "{} at {:x}", name, self.location.addr)?;
+                write_trace(f, &self.location.trace)
+            },
+            None => write!(f, "{}", self.location),
+        }
+    }
+}
+
+/// The frame offset a storage location refers to given the stack pointer
+/// offset before the instruction.
+fn stack_offset(storage: StorageLocation, offset: i64, frame_pointer: Option<i64>) -> Option<i64> {
+    match storage {
+        StorageLocation::Indirect { base: Register::RSP, scaled_offset: None, displacement, .. } => {
+            Some(offset + displacement.unwrap_or(0))
+        },
+        StorageLocation::Indirect { base: Register::RBP, scaled_offset: None, displacement, .. } => {
+            frame_pointer.map(|base| base + displacement.unwrap_or(0))
+        },
+        _ => None,
+    }
+}
+
+/// The signed value of an immediate.
+fn signed(int: Integer) -> i64 {
+    int.cast(DataType::N64, true).1 as i64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        // push rbp; mov rbp, rsp; sub rsp, 0x10; mov dword ptr [rbp-0x4], 0;
+        // mov eax, dword ptr [rsp+0x8]; leave; ret
+        let bytes: &[&[u8]] = &[
+            &[0x55], &[0x48, 0x89, 0xe5], &[0x48, 0x83, 0xec, 0x10],
+            &[0xc7, 0x45, 0xfc, 0x00, 0x00, 0x00, 0x00],
+            &[0x8b, 0x44, 0x24, 0x08], &[0xc9], &[0xc3],
+        ];
+
+        let mut symbols = HashMap::new();
+        symbols.insert(0x1000, "foo".to_string());
+        let program = Program::from_code(0x1000, &bytes.concat(), symbols);
+
+        let frames = StackFrames::new(&program, &[], &[0x1000]);
+        let frame = &frames.frames[&0x1000];
+        assert_eq!(frame.frame_pointer, Some(-8));
+        assert_eq!(frame.saved, vec![(Register::RBP, -8)]);
+        assert_eq!(frame.size, 0x18);
+        assert_eq!(frame.variables.keys().copied().collect::<Vec<_>>(), vec![-0x10, -0xc]);
+
+        let location = |addr, storage| AbstractLocation::new(addr, vec![], storage);
+        let local = location(0x1008, StorageLocation::Indirect {
+            data_type: DataType::N32,
+            base: Register::RBP,
+            scaled_offset: None,
+            displacement: Some(-4),
+        });
+        assert_eq!(frames.name(&local).unwrap(), "local_c of foo");
+        assert_eq!(frames.describe(&local).to_string(), "local_c of foo at 1008");
+
+        let register = location(0x1008, StorageLocation::Direct(Register::EAX));
+        assert_eq!(frames.name(&register), None);
+    }
+}
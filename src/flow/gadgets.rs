@@ -0,0 +1,371 @@
+//! Discovery of code-reuse gadgets.
+//!
+//! A gadget is a short sequence of instructions ending in a return or an
+//! indirect jump or call. The code is scanned at every byte offset, so that
+//! sequences starting in the middle of regular instructions are found too.
+//! Each gadget is executed symbolically from a state in which every general
+//! purpose register holds a symbol named after it, which yields its effect on
+//! the registers and the stack and classifies it.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::Program;
+use crate::ir::{Location, MicroEncoder, MicroOperation, Microcode, Transfer};
+use crate::math::{DataType, Solver, SymExpr, Symbol};
+use crate::sym::{Event, MemoryStrategy, SymState};
+use crate::x86_64::{Instruction, Mnemoic, Operand, Register, initial, offset_from, REGISTERS};
+
+
+/// Limits for the gadget search.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GadgetConfig {
+    /// The maximum number of instructions including the final one.
+    pub max_instructions: usize,
+    /// The maximum number of bytes before the final instruction.
+    pub max_bytes: usize,
+    /// The seed of the solver, like `SymConfig::seed`.
+    pub seed: u64,
+}
+
+impl Default for GadgetConfig {
+    fn default() -> GadgetConfig {
+        GadgetConfig { max_instructions: 5, max_bytes: 20, seed: 0 }
+    }
+}
+
+/// A gadget with its symbolic effect.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Gadget {
+    /// The address of the first instruction.
+    pub addr: u64,
+    /// The addresses and lengths of the instructions alongside them.
+    pub code: Vec<(u64, u64, Instruction)>,
+    /// Whether the gadget starts at the boundary of a regularly decoded
+    /// instruction.
+    pub aligned: bool,
+    pub end: GadgetEnd,
+    pub kind: GadgetKind,
+    /// The general purpose registers whose values are changed with their
+    /// final values in terms of the initial register symbols.
+    pub effects: Vec<(Register, SymExpr)>,
+    /// How far the stack pointer moves if by a constant.
+    pub stack_offset: Option<i64>,
+    /// The values read from memory as (address, value) pairs. Memory which
+    /// was not written before holds fresh symbols.
+    pub loads: Vec<(SymExpr, SymExpr)>,
+    /// The values written to memory as (address, value) pairs, not counting
+    /// the return address pushed by a final call.
+    pub stores: Vec<(SymExpr, SymExpr)>,
+    /// Where execution continues after the gadget.
+    pub target: SymExpr,
+}
+
+/// The instruction ending a gadget.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum GadgetEnd {
+    Return,
+    Jump,
+    Call,
+}
+
+/// What a gadget is useful for, by the most notable of its effects.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum GadgetKind {
+    /// Moves the stack pointer by a non-constant amount.
+    StackPivot,
+    /// Writes to memory.
+    Store,
+    /// Loads a register from memory other than the stack.
+    Load,
+    /// Loads a register from the stack.
+    Pop,
+    /// Copies a register into another one.
+    Move,
+    /// Computes a register from others.
+    Arithmetic,
+    /// Has no effect on the general purpose registers besides the stack pointer.
+    Nop,
+}
+
+/// Find the gadgets in the code of the program.
+pub fn find_gadgets(program: &Program, config: &GadgetConfig) -> Vec<Gadget> {
+    let boundaries: HashSet<u64> = program.code.iter().map(|&(addr, ..)| addr).collect();
+    let binary = &program.binary;
+    let solver = Rc::new(Solver::with_seed(config.seed));
+
+    let mut gadgets = Vec::new();
+    for end in 0 .. binary.len() {
+        let (_, instruction) = match decode_at(binary, end) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        if gadget_end(&instruction).is_none() {
+            continue;
+        }
+
+        for start in end.saturating_sub(config.max_bytes) ..= end {
+            let code = match sequence(binary, start, end, config.max_instructions) {
+                Some(code) => code,
+                None => continue,
+            };
+
+            let code = code.into_iter()
+                .map(|(offset, len, instruction)| (program.base + offset as u64, len, instruction))
+                .collect();
+            let state = SymState::new(MemoryStrategy::PerfectMatches, solver.clone());
+            if let Some(mut gadget) = execute(code, state) {
+                gadget.aligned = boundaries.contains(&gadget.addr);
+                gadgets.push(gadget);
+            }
+        }
+    }
+
+    gadgets.sort_by_key(|gadget| (gadget.addr, gadget.code.len()));
+    gadgets
+}
+
+/// Decode the instruction at the offset.
+fn decode_at(binary: &[u8], offset: usize) -> Option<(u64, Instruction)> {
+    let rest = &binary[offset ..];
+    let len = Instruction::length(rest);
+    let bytes = rest.get(.. len as usize).filter(|bytes| !bytes.is_empty())?;
+    Instruction::decode(bytes).ok().map(|instruction| (len, instruction))
+}
+
+/// How the instruction ends a gadget if it can.
+fn gadget_end(instruction: &Instruction) -> Option<GadgetEnd> {
+    let indirect = !matches!(instruction.operands.first(), Some(Operand::Offset(_)));
+    match instruction.mnemoic {
+        Mnemoic::Ret => Some(GadgetEnd::Return),
+        Mnemoic::Jmp if indirect => Some(GadgetEnd::Jump),
+        Mnemoic::Call if indirect => Some(GadgetEnd::Call),
+        _ => None,
+    }
+}
+
+/// Decode the instructions from the start offset if they end exactly with
+/// the one at the end offset and are not too many.
+fn sequence(binary: &[u8], start: usize, end: usize, max: usize)
+-> Option<Vec<(usize, u64, Instruction)>> {
+    let mut code = Vec::new();
+    let mut offset = start;
+    while offset <= end && code.len() < max {
+        let (len, instruction) = decode_at(binary, offset)?;
+        if offset < end && gadget_end(&instruction).is_some() {
+            return None;
+        }
+        code.push((offset, len, instruction));
+        if offset == end {
+            return Some(code);
+        }
+        offset += len as usize;
+    }
+    None
+}
+
+/// Execute the instructions symbolically and describe their effect. Fails
+/// if an instruction cannot be lifted or one before the last one transfers
+/// control or makes a system call.
+fn execute(code: Vec<(u64, u64, Instruction)>, mut state: SymState) -> Option<Gadget> {
+    let mut encoder = MicroEncoder::new();
+    let microcode: Vec<Microcode> = code.iter()
+        .map(|(_, _, instruction)| encoder.encode(instruction).ok())
+        .collect::<Option<_>>()?;
+
+    let last = code.len() - 1;
+    let leaves = |microcode: &Microcode| microcode.ops.iter().any(|op| {
+        matches!(op, MicroOperation::Jump { .. } | MicroOperation::Syscall)
+    });
+    if microcode[.. last].iter().any(|mc| mc.transfer != Transfer::Next || leaves(mc)) {
+        return None;
+    }
+
+    for &(register, name) in &REGISTERS {
+        state.set_reg(register, SymExpr::Sym(Symbol(DataType::N64, name, 0)));
+    }
+
+    let mut loads = Vec::new();
+    let mut stores = Vec::new();
+    let mut target = None;
+
+    for (index, ((addr, _, _), microcode)) in code.iter().zip(&microcode).enumerate() {
+        for op in &microcode.ops {
+            let store = match *op {
+                MicroOperation::Mov { dest: Location::Indirect(_, 0, temp), src } if index < last => {
+                    Some((state.get_temp(temp), state.read_location(src)))
+                },
+                _ => None,
+            };
+            let load = match *op {
+                MicroOperation::Mov { dest, src: Location::Indirect(_, 0, temp) } => {
+                    Some((state.get_temp(temp), dest))
+                },
+                _ => None,
+            };
+
+            let event = state.step(*addr, op);
+            stores.extend(store);
+            loads.extend(load.map(|(addr, dest)| (addr, state.read_location(dest))));
+
+            if let Some(Event::Jump { target: jump, .. }) = event {
+                target = Some(jump);
+            }
+        }
+    }
+
+    let effects: Vec<(Register, SymExpr)> = REGISTERS.iter()
+        .map(|&(register, _)| (register, state.get_reg(register)))
+        .filter(|&(register, ref value)| *value != initial(register))
+        .collect();
+    let stack_offset = stack_offset(&state.get_reg(Register::RSP));
+
+    let addr = code[0].0;
+    let end = gadget_end(&code[last].2)?;
+    let kind = classify(&effects, stack_offset, &loads, &stores);
+
+    Some(Gadget {
+        addr,
+        code,
+        aligned: false,
+        end,
+        kind,
+        effects,
+        stack_offset,
+        loads,
+        stores,
+        target: target?,
+    })
+}
+
+/// Find the most notable effect of a gadget.
+fn classify(
+    effects: &[(Register, SymExpr)],
+    offset: Option<i64>,
+    loads: &[(SymExpr, SymExpr)],
+    stores: &[(SymExpr, SymExpr)],
+) -> GadgetKind {
+    if offset.is_none() {
+        return GadgetKind::StackPivot;
+    } else if !stores.is_empty() {
+        return GadgetKind::Store;
+    }
+
+    let mut kind = GadgetKind::Nop;
+    for (register, value) in effects {
+        if *register == Register::RSP {
+            continue;
+        }
+
+        let loaded = loads.iter().find(|(_, loaded)| loaded == value);
+        let current = match loaded {
+            Some((addr, _)) if stack_offset(addr).is_some() => GadgetKind::Pop,
+            Some(_) => GadgetKind::Load,
+            None if REGISTERS.iter().any(|&(other, _)| *value == initial(other)) => GadgetKind::Move,
+            None => GadgetKind::Arithmetic,
+        };
+        kind = kind.min(current);
+    }
+    kind
+}
+
+/// The constant offset of an expression from the initial stack pointer.
+fn stack_offset(expr: &SymExpr) -> Option<i64> {
+    offset_from(expr, Register::RSP)
+}
+
+impl Display for Gadget {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:#x}: ", self.addr)?;
+        for (i, (_, _, instruction)) in self.code.iter().enumerate() {
+            if i > 0 { write!(f, "; ")?; }
+            write!(f, "{}", instruction)?;
+        }
+        write!(f, " [{}]", self.kind)?;
+
+        for (register, value) in &self.effects {
+            if *register != Register::RSP || self.stack_offset.is_none() {
+                write!(f, " {} = {}", register, value)?;
+            }
+        }
+        if let Some(offset) = self.stack_offset {
+            write!(f, " rsp ")?;
+            crate::helper::write_signed_hex(f, offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GadgetKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            GadgetKind::StackPivot => "stack pivot",
+            GadgetKind::Store => "store",
+            GadgetKind::Load => "load",
+            GadgetKind::Pop => "pop",
+            GadgetKind::Move => "move",
+            GadgetKind::Arithmetic => "arithmetic",
+            GadgetKind::Nop => "nop",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn gadgets(binary: &[u8]) -> Vec<Gadget> {
+        let program = Program::from_code(0x1000, binary, HashMap::new());
+        find_gadgets(&program, &GadgetConfig::default())
+    }
+
+    #[test]
+    fn pop() {
+        // pop rdi; pop rsi; ret
+        let found = gadgets(&[0x5f, 0x5e, 0xc3]);
+        assert_eq!(found.len(), 3);
+
+        let gadget = &found[0];
+        assert_eq!((gadget.addr, gadget.end, gadget.kind), (0x1000, GadgetEnd::Return, GadgetKind::Pop));
+        assert_eq!(gadget.stack_offset, Some(24));
+        assert_eq!(gadget.effects.iter().map(|&(reg, _)| reg).collect::<Vec<_>>(),
+                   vec![Register::RSP, Register::RSI, Register::RDI]);
+        assert_eq!(gadget.loads.len(), 3);
+        assert_eq!(gadget.target, gadget.loads[2].1);
+
+        assert_eq!(found[2].kind, GadgetKind::Nop);
+        assert_eq!(found[2].to_string(), "0x1002: ret [nop] rsp +0x8");
+    }
+
+    #[test]
+    fn kinds() {
+        // mov rdi, rax; call rdx
+        let found = gadgets(&[0x48, 0x89, 0xc7, 0xff, 0xd2]);
+        assert_eq!((found[0].kind, found[0].end), (GadgetKind::Move, GadgetEnd::Call));
+        assert!(found[0].stores.is_empty());
+        assert_eq!(found[0].target, initial(Register::RDX));
+
+        // mov qword ptr [rdi], rax; jmp rcx
+        let found = gadgets(&[0x48, 0x89, 0x07, 0xff, 0xe1]);
+        assert_eq!((found[0].kind, found[0].end), (GadgetKind::Store, GadgetEnd::Jump));
+        assert_eq!(found[0].stores, vec![(initial(Register::RDI), initial(Register::RAX))]);
+
+        // mov rsp, rax; ret
+        let found = gadgets(&[0x48, 0x89, 0xc4, 0xc3]);
+        assert_eq!(found[0].kind, GadgetKind::StackPivot);
+
+        // A direct call ends no gadget.
+        assert!(gadgets(&[0x90, 0xe8, 0x00, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn seeded() {
+        // mov qword ptr [rdi], rax; pop rsi; ret
+        let program = Program::from_code(0x1000, &[0x48, 0x89, 0x07, 0x5e, 0xc3], HashMap::new());
+        let config = GadgetConfig { seed: 7, .. GadgetConfig::default() };
+        assert_eq!(find_gadgets(&program, &config), find_gadgets(&program, &config));
+    }
+}
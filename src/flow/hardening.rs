@@ -0,0 +1,148 @@
+//! Instrumentation inserted by the compiler for hardening.
+//!
+//! Functions protected by a stack canary copy the guard from `fs:0x28` into
+//! their frame on entry and compare it with the guard again before they
+//! return. Every protected function thus reads the same location, which
+//! links all of them in the data dependency graph without telling anything
+//! about the program. With control flow enforcement, indirect branch targets
+//! start with an `endbr64` marker. Calls of the slow path of control flow
+//! integrity checks are runtime checks (see `CheckKind`).
+//!
+//! The instructions of the instrumentation are found in the explored blocks.
+//! With `strip_instrumentation` in the configuration, the data flow analysis
+//! records no flows for them and the visualizations leave them out.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::ir::Microcode;
+use crate::x86_64::{Instruction, Mnemoic, Operand};
+use super::{ControlFlowGraph, StorageLocation, TlsSlot};
+
+
+/// What an instruction of the instrumentation is part of.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Instrumentation {
+    /// The copy of the stack canary into the frame or the comparison with it.
+    StackGuard,
+    /// The marker of an indirect branch target.
+    BranchTarget,
+}
+
+impl ControlFlowGraph {
+    /// The instrumentation instructions in the explored code by address.
+    pub fn instrumentation(&self) -> HashMap<u64, Instrumentation> {
+        let mut found = HashMap::new();
+        for block in self.blocks.values() {
+            find_instrumentation(&block.code, &mut found);
+        }
+        found
+    }
+}
+
+/// Find the instrumentation in the code of a block.
+fn find_instrumentation(
+    code: &[(u64, u64, Instruction, Microcode)],
+    found: &mut HashMap<u64, Instrumentation>,
+) {
+    let register = |operand: Option<&Operand>| match operand {
+        Some(Operand::Direct(reg)) => Some(reg.base()),
+        _ => None,
+    };
+
+    for (index, (addr, _, instruction, _)) in code.iter().enumerate() {
+        if instruction.mnemoic == Mnemoic::Endbr64 {
+            found.insert(*addr, Instrumentation::BranchTarget);
+            continue;
+        }
+
+        let guarded = instruction.operands.iter().any(|&operand| {
+            StorageLocation::from_operand(operand).and_then(|storage| storage.tls_slot())
+                == Some(TlsSlot::StackGuard)
+        });
+        let reg = match register(instruction.operands.first()) {
+            Some(reg) if guarded => reg,
+            _ => continue,
+        };
+        found.insert(*addr, Instrumentation::StackGuard);
+
+        if instruction.mnemoic == Mnemoic::Mov {
+            // The loaded canary is stored into the frame and cleared from the
+            // register right after.
+            for (addr, _, next, _) in &code[index + 1 ..] {
+                let operands = (register(next.operands.get(0)), register(next.operands.get(1)));
+                match (next.mnemoic, operands) {
+                    (Mnemoic::Mov, (None, Some(source))) if source == reg => {
+                        found.insert(*addr, Instrumentation::StackGuard);
+                    },
+                    (Mnemoic::Xor, (Some(a), Some(b))) if a == reg && b == reg => {
+                        found.insert(*addr, Instrumentation::StackGuard);
+                        break;
+                    },
+                    _ => break,
+                }
+            }
+        } else {
+            // The copy in the frame is loaded right before the comparison,
+            // whose result only decides the following jump.
+            if let Some((addr, _, previous, _)) = code[.. index].last() {
+                let frame = matches!(previous.operands.get(1), Some(Operand::Indirect { .. }));
+                let loaded = register(previous.operands.first());
+                if previous.mnemoic == Mnemoic::Mov && frame && loaded == Some(reg) {
+                    found.insert(*addr, Instrumentation::StackGuard);
+                }
+            }
+            if let Some((addr, _, next, _)) = code.get(index + 1) {
+                if !next.mnemoic.flags_read().is_empty() {
+                    found.insert(*addr, Instrumentation::StackGuard);
+                }
+            }
+        }
+    }
+}
+
+impl Display for Instrumentation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Instrumentation::StackGuard => "stack guard",
+            Instrumentation::BranchTarget => "branch target",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::{DataDependencyGraph, DependencyNode};
+    use crate::sym::SymConfig;
+    use super::*;
+
+    #[test]
+    fn instrumentation() {
+        let program = Program::new(fixture("guard"));
+        let cfg = ControlFlowGraph::new(&program);
+        let found = cfg.instrumentation();
+        let count = |kind| found.values().filter(|&&found| found == kind).count();
+
+        // The canary is loaded, stored, cleared, loaded again, compared and
+        // jumped on in main. The other functions only start with markers.
+        assert_eq!(count(Instrumentation::StackGuard), 6);
+        assert_eq!(count(Instrumentation::BranchTarget), 4);
+
+        // Without the instrumentation, no location is at the canary code.
+        let guarded = |ddg: &DataDependencyGraph| ddg.nodes.iter().any(|node| match node {
+            DependencyNode::Location(location) => found.contains_key(&location.addr),
+            _ => false,
+        });
+        let ddg = DataDependencyGraph::new(&cfg);
+        assert!(guarded(&ddg));
+
+        let config = SymConfig { strip_instrumentation: true, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        let stripped = DataDependencyGraph::new(&cfg);
+        assert!(!guarded(&stripped));
+        assert!(stripped.nodes.len() < ddg.nodes.len());
+    }
+}
@@ -0,0 +1,391 @@
+//! Reuse of analysis results when a slightly changed binary is analyzed again.
+//!
+//! Functions are compared by fingerprints of their code. The graphs are kept
+//! as fragments, one per function, which explore the function on its own
+//! from its entry with unknown arguments. Calls of functions analyzed before
+//! return through the summaries of their fragments, so the functions are
+//! analyzed callees first. When the program changes, only the fragments of
+//! functions whose code, address or callees changed are built again, along
+//! with the ones of all functions calling them, since they depend on the
+//! summaries of their callees. Changed data sections rebuild all fragments.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::Program;
+use crate::sym::SymConfig;
+use crate::x86_64::{Instruction, Operand, Register};
+use super::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph, AnalysisStats};
+use super::{CallGraph, Summaries, FunctionSummary};
+use super::pdg::Digest;
+
+
+/// Hashes of the code of a function.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Fingerprint {
+    /// The hash of the raw instruction bytes.
+    pub bytes: u64,
+    /// The hash of the instructions with relative branch targets within the
+    /// function replaced by offsets into it and targets outside of it and
+    /// `rip`-relative displacements left out, which stays the same if only
+    /// the function or its callees moved.
+    pub structure: u64,
+}
+
+/// The fingerprints of the functions of a program by symbol name.
+pub type Fingerprints = BTreeMap<String, Fingerprint>;
+
+/// The differences between the functions of two builds by symbol name.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FunctionChanges {
+    /// The functions only in the new build.
+    pub added: BTreeSet<String>,
+    /// The functions only in the old build.
+    pub removed: BTreeSet<String>,
+    /// The functions whose structure changed.
+    pub changed: BTreeSet<String>,
+    /// The functions with the same structure, which may have moved.
+    pub unchanged: BTreeSet<String>,
+}
+
+impl FunctionChanges {
+    /// Compare the fingerprints of an old and a new build.
+    pub fn new(old: &Fingerprints, new: &Fingerprints) -> FunctionChanges {
+        let mut changes = FunctionChanges::default();
+        for (name, fingerprint) in new {
+            match old.get(name) {
+                Some(prev) if prev.structure == fingerprint.structure => {
+                    changes.unchanged.insert(name.clone());
+                }
+                Some(_) => { changes.changed.insert(name.clone()); }
+                None => { changes.added.insert(name.clone()); }
+            }
+        }
+        for name in old.keys() {
+            if !new.contains_key(name) {
+                changes.removed.insert(name.clone());
+            }
+        }
+        changes
+    }
+
+    /// Whether all functions stayed the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The fingerprints of the functions reached from the roots of the program.
+pub fn fingerprints(program: &Program) -> Fingerprints {
+    let calls = CallGraph::new(program);
+    let code = instructions(program);
+    calls.instructions.keys()
+        .map(|&function| (name(program, function), fingerprint(&code, &calls, function)))
+        .collect()
+}
+
+/// The lengths and instructions of the program by address.
+fn instructions(program: &Program) -> HashMap<u64, (u64, &Instruction)> {
+    program.code.iter()
+        .map(|(addr, len, instruction, _)| (*addr, (*len, instruction)))
+        .collect()
+}
+
+/// The symbol name of the function at the address.
+fn name(program: &Program, function: u64) -> String {
+    program.symbols.get(&function)
+        .cloned()
+        .unwrap_or_else(|| format!("fn_{:x}", function))
+}
+
+/// The fingerprint of the instructions reachable within the function.
+fn fingerprint(
+    code: &HashMap<u64, (u64, &Instruction)>,
+    calls: &CallGraph,
+    function: u64,
+) -> Fingerprint {
+    let instructions: BTreeMap<u64, (u64, &Instruction)> = calls.instructions[&function].iter()
+        .filter_map(|addr| code.get(addr).map(|&instruction| (*addr, instruction)))
+        .collect();
+
+    let mut bytes = Digest::new(b'b');
+    let mut structure = Digest::new(b's');
+    for (&addr, &(len, instruction)) in &instructions {
+        bytes.bytes(&instruction.bytes);
+        structure.word(addr - function);
+        structure.bytes(format!("{:?}{:?}", instruction.prefixes, instruction.mnemoic)
+            .as_bytes());
+        for operand in &instruction.operands {
+            let normalized = match *operand {
+                Operand::Offset(offset) => {
+                    let target = (addr + len).wrapping_add(offset as u64);
+                    match instructions.contains_key(&target) {
+                        true => format!("local {:#x}", target - function),
+                        false => "external".to_string(),
+                    }
+                }
+                Operand::Indirect { data_type, base: Register::RIP, .. } => {
+                    format!("rip-relative {}", data_type)
+                }
+                ref operand => format!("{:?}", operand),
+            };
+            structure.bytes(normalized.as_bytes());
+        }
+    }
+
+    Fingerprint { bytes: bytes.0, structure: structure.0 }
+}
+
+/// The graphs of a single function explored on its own.
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    /// The address of the function.
+    pub function: u64,
+    pub fingerprint: Fingerprint,
+    /// The names of the functions it calls directly.
+    pub callees: BTreeSet<String>,
+    pub cfg: ControlFlowGraph,
+    pub ddg: DataDependencyGraph,
+    pub pdg: ProgramDependenceGraph,
+    /// The summary through which the calls of the function return in the
+    /// fragments of its callers.
+    pub summary: Option<FunctionSummary>,
+}
+
+impl Fragment {
+    /// Explore the function from its entry, where calls of the summarized
+    /// functions are not entered.
+    fn build(
+        program: &Program,
+        function: u64,
+        fingerprint: Fingerprint,
+        callees: BTreeSet<String>,
+        summaries: &Summaries,
+        stats: &mut AnalysisStats,
+    ) -> Fragment {
+        let program = Program {
+            entry: function,
+            constructors: Vec::new(),
+            destructors: Vec::new(),
+            handlers: Vec::new(),
+            .. program.clone()
+        };
+        let config = SymConfig { summaries: summaries.clone(), .. SymConfig::default() };
+
+        let cfg = stats.measure("cfg", || ControlFlowGraph::with_config(&program, config));
+        let ddg = stats.measure("ddg", || DataDependencyGraph::new(&cfg));
+        let pdg = stats.measure("pdg", || ProgramDependenceGraph::new(&cfg, &ddg));
+        let summary = Summaries::compute(&program, &cfg, &ddg).functions
+            .remove(&name(&program, function));
+        Fragment { function, fingerprint, callees, cfg, ddg, pdg, summary }
+    }
+}
+
+/// The graphs of an analyzed program, which are reused when the same program
+/// or a new build of it is analyzed again.
+#[derive(Debug, Clone)]
+pub struct AnalysisCache {
+    pub program: Program,
+    /// The graphs of the functions by symbol name.
+    pub fragments: BTreeMap<String, Arc<Fragment>>,
+    pub fingerprints: Fingerprints,
+    /// The functions whose fragments were built by the last analysis.
+    pub rebuilt: BTreeSet<String>,
+    /// The measurements of the last analysis that was not skipped.
+    pub stats: AnalysisStats,
+}
+
+impl AnalysisCache {
+    /// Analyze a program from scratch.
+    pub fn new(program: &Program) -> AnalysisCache {
+        let mut cache = AnalysisCache {
+            program: program.clone(),
+            fragments: BTreeMap::new(),
+            fingerprints: Fingerprints::new(),
+            rebuilt: BTreeSet::new(),
+            stats: AnalysisStats::new(),
+        };
+        cache.analyze(program, true);
+        cache
+    }
+
+    /// Bring the graphs up to date with a new build of the program and return
+    /// the function changes. If the program is the same, nothing is analyzed
+    /// and the changes are empty. Otherwise only the fragments of changed
+    /// functions and their callers are built again.
+    pub fn update(&mut self, program: &Program) -> FunctionChanges {
+        if *program == self.program {
+            self.rebuilt.clear();
+            return FunctionChanges {
+                unchanged: self.fingerprints.keys().cloned().collect(),
+                .. FunctionChanges::default()
+            };
+        }
+
+        let old = std::mem::take(&mut self.fingerprints);
+        self.analyze(program, program.image != self.program.image);
+        self.program = program.clone();
+        FunctionChanges::new(&old, &self.fingerprints)
+    }
+
+    /// Build the fragments of the program which are missing or outdated, or
+    /// all of them.
+    fn analyze(&mut self, program: &Program, all: bool) {
+        let calls = CallGraph::new(program);
+        let code = instructions(program);
+        let mut functions: Vec<u64> = calls.callees.keys().copied().collect();
+        functions.sort_unstable();
+
+        let names: HashMap<u64, String> = functions.iter()
+            .map(|&function| (function, name(program, function)))
+            .collect();
+        let prints: HashMap<u64, Fingerprint> = functions.iter()
+            .map(|&function| (function, fingerprint(&code, &calls, function)))
+            .collect();
+        let callees = |function: u64| -> BTreeSet<String> {
+            calls.callees[&function].iter().map(|callee| names[callee].clone()).collect()
+        };
+
+        // A fragment is outdated if anything it was built from changed,
+        // which includes the summaries of the functions it calls.
+        let mut outdated: Vec<u64> = functions.iter().copied()
+            .filter(|&function| all || match self.fragments.get(&names[&function]) {
+                Some(fragment) => fragment.function != function
+                    || fragment.fingerprint != prints[&function]
+                    || fragment.callees != callees(function),
+                None => true,
+            })
+            .collect();
+        let mut callers: HashMap<u64, Vec<u64>> = HashMap::new();
+        for (&function, called) in &calls.callees {
+            for &callee in called {
+                callers.entry(callee).or_default().push(function);
+            }
+        }
+        let mut dirty: HashSet<u64> = outdated.iter().copied().collect();
+        while let Some(function) = outdated.pop() {
+            for &caller in callers.get(&function).into_iter().flatten() {
+                if dirty.insert(caller) {
+                    outdated.push(caller);
+                }
+            }
+        }
+
+        // Analyze the callees before their callers.
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        for &root in &functions {
+            if !visited.insert(root) {
+                continue;
+            }
+            let mut stack = vec![(root, 0)];
+            while let Some((function, next)) = stack.pop() {
+                match calls.callees[&function].get(next) {
+                    Some(&callee) => {
+                        stack.push((function, next + 1));
+                        if visited.insert(callee) {
+                            stack.push((callee, 0));
+                        }
+                    },
+                    None => order.push(function),
+                }
+            }
+        }
+
+        let mut old = std::mem::take(&mut self.fragments);
+        let mut summaries = Summaries::default();
+        self.rebuilt.clear();
+        self.stats = AnalysisStats::new();
+        for function in order {
+            let name = &names[&function];
+            let fragment = match old.remove(name) {
+                Some(fragment) if !dirty.contains(&function) => fragment,
+                _ => {
+                    self.rebuilt.insert(name.clone());
+                    Arc::new(Fragment::build(
+                        program,
+                        function,
+                        prints[&function],
+                        callees(function),
+                        &summaries,
+                        &mut self.stats,
+                    ))
+                },
+            };
+            if let Some(summary) = &fragment.summary {
+                summaries.functions.insert(name.clone(), summary.clone());
+            }
+            self.fragments.insert(name.clone(), fragment);
+        }
+
+        self.fingerprints = functions.iter()
+            .map(|function| (names[function].clone(), prints[function]))
+            .collect();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::fixture;
+    use crate::flow::ControlKind;
+    use super::*;
+
+    #[test]
+    fn function_changes() {
+        let print = |bytes, structure| Fingerprint { bytes, structure };
+        let old: Fingerprints = vec![
+            ("main".to_string(), print(1, 10)),
+            ("parse".to_string(), print(2, 20)),
+            ("gone".to_string(), print(3, 30)),
+        ].into_iter().collect();
+        let new: Fingerprints = vec![
+            ("main".to_string(), print(4, 10)),
+            ("parse".to_string(), print(5, 21)),
+            ("fresh".to_string(), print(6, 60)),
+        ].into_iter().collect();
+
+        let changes = FunctionChanges::new(&old, &new);
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(changes.unchanged, names(&["main"]));
+        assert_eq!(changes.changed, names(&["parse"]));
+        assert_eq!(changes.added, names(&["fresh"]));
+        assert_eq!(changes.removed, names(&["gone"]));
+        assert!(!changes.is_empty());
+        assert!(FunctionChanges::new(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn fragments() {
+        let program = Program::new(fixture("summary"));
+        let mut cache = AnalysisCache::new(&program);
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let all: BTreeSet<String> = names(&[
+            "_start", "accumulate", "main", "read_one_byte", "write_one_byte",
+        ]);
+        assert_eq!(cache.rebuilt, all);
+
+        // The caller returns from the callees through their summaries.
+        let main = &cache.fragments["main"].cfg;
+        assert!(main.edges.keys().any(|&edge| {
+            matches!(main.edge_kind(edge), ControlKind::Summary { .. })
+        }));
+
+        // Return the doubled total instead of the doubled value.
+        let accumulate = program.symbols.iter()
+            .find(|(_, name)| name.as_str() == "accumulate")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let patched = program.patched(&[(accumulate + 0x25, vec![0xd0])]);
+        let reused = cache.fragments["read_one_byte"].clone();
+
+        let changes = cache.update(&patched);
+        assert_eq!(changes.changed, names(&["accumulate"]));
+        assert_eq!(cache.rebuilt, names(&["_start", "accumulate", "main"]));
+        assert!(Arc::ptr_eq(&reused, &cache.fragments["read_one_byte"]));
+        assert_eq!(cache.fragments.keys().cloned().collect::<BTreeSet<_>>(), all);
+
+        cache.update(&patched);
+        assert!(cache.rebuilt.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Data dependency graphs built only for the parts of a program that queries
+//! actually touch.
+//!
+//! When a query requires more instructions, only the flows of the new ones
+//! are explored and added to the graph, so each instruction is analyzed once
+//! no matter how often the slice grows.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::math::Solver;
+use super::{ControlFlowGraph, DataDependencyGraph, DependencyNode};
+
+
+/// A data dependency graph that only contains the flows of the functions
+/// required so far and is extended when more are required.
+#[derive(Debug, Clone)]
+pub struct LazyDataDependencyGraph<'g> {
+    cfg: &'g ControlFlowGraph,
+    /// The addresses of the instructions of each function.
+    functions: HashMap<u64, Vec<u64>>,
+    /// The addresses of the instructions whose flows are in the graph.
+    scope: HashSet<u64>,
+    graph: DataDependencyGraph,
+}
+
+impl<'g> LazyDataDependencyGraph<'g> {
+    /// Prepare a graph for the control flow graph without building anything.
+    pub fn new(cfg: &'g ControlFlowGraph) -> LazyDataDependencyGraph<'g> {
+        let mut functions: HashMap<u64, Vec<u64>> = HashMap::new();
+        for block in cfg.blocks.values() {
+            for &(addr, ..) in &block.code {
+                for &function in cfg.frames.functions_at(addr) {
+                    functions.entry(function).or_default().push(addr);
+                }
+            }
+        }
+
+        let graph = DataDependencyGraph {
+            nodes: Vec::new(),
+            edges: HashMap::new(),
+            frames: cfg.frames.clone(),
+            classes: HashMap::new(),
+            chains: HashSet::new(),
+            threads: HashSet::new(),
+            synchronizations: HashSet::new(),
+            inputs: HashMap::new(),
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        };
+
+        LazyDataDependencyGraph { cfg, functions, scope: HashSet::new(), graph }
+    }
+
+    /// The graph with the flows required so far. Its indices stay valid when
+    /// more flows are required.
+    pub fn graph(&self) -> &DataDependencyGraph {
+        &self.graph
+    }
+
+    /// The addresses of the instructions whose flows are in the graph.
+    pub fn scope(&self) -> &HashSet<u64> {
+        &self.scope
+    }
+
+    /// Whether all flows into the node are in the graph, which is not the case
+    /// for locations outside of the scope.
+    pub fn is_complete(&self, index: usize) -> bool {
+        match &self.graph.nodes[index] {
+            DependencyNode::Location(location) => self.scope.contains(&location.addr),
+            _ => true,
+        }
+    }
+
+    /// Require the flows of the functions and return whether the graph was
+    /// extended for them.
+    pub fn require_functions(&mut self, functions: &[u64]) -> bool {
+        let addrs: Vec<u64> = functions.iter()
+            .filter_map(|function| self.functions.get(function))
+            .flatten()
+            .copied()
+            .collect();
+        self.require(addrs)
+    }
+
+    /// Require the flows of the functions containing the address, or only of
+    /// the address if it is in no function, and return whether the graph was
+    /// extended for them.
+    pub fn require_address(&mut self, addr: u64) -> bool {
+        self.require_addresses(&[addr])
+    }
+
+    /// The nodes that the locations at the address depend on, including
+    /// themselves. The functions are required as the slice reaches them.
+    pub fn backward_slice(&mut self, addr: u64) -> BTreeSet<usize> {
+        self.require_address(addr);
+        loop {
+            let mut slice = BTreeSet::new();
+            let mut pending: Vec<usize> = self.graph.nodes.iter()
+                .enumerate()
+                .filter(|(_, node)| match node {
+                    DependencyNode::Location(location) => location.addr == addr,
+                    _ => false,
+                })
+                .map(|(index, _)| index)
+                .collect();
+
+            while let Some(node) = pending.pop() {
+                if slice.insert(node) {
+                    pending.extend(self.graph.predecessors(node));
+                }
+            }
+
+            let missing: Vec<u64> = slice.iter()
+                .filter(|&&node| !self.is_complete(node))
+                .filter_map(|&node| match &self.graph.nodes[node] {
+                    DependencyNode::Location(location) => Some(location.addr),
+                    _ => None,
+                })
+                .collect();
+
+            if !self.require_addresses(&missing) {
+                return slice;
+            }
+        }
+    }
+
+    /// Require the functions containing each of the addresses.
+    fn require_addresses(&mut self, addrs: &[u64]) -> bool {
+        let mut required = Vec::new();
+        for &addr in addrs {
+            match self.cfg.frames.functions_at(addr) {
+                [] => required.push(addr),
+                functions => {
+                    for function in functions {
+                        required.extend(self.functions.get(function).into_iter().flatten());
+                    }
+                }
+            }
+        }
+        self.require(required)
+    }
+
+    /// Add the addresses to the scope and the flows of the new ones to the
+    /// graph.
+    fn require(&mut self, addrs: Vec<u64>) -> bool {
+        let added: HashSet<u64> = addrs.into_iter()
+            .filter(|addr| !self.scope.contains(addr))
+            .collect();
+        if added.is_empty() {
+            return false;
+        }
+
+        let flows = DataDependencyGraph::with_scope(self.cfg, &added);
+        self.graph.extend(flows, &Solver::with_seed(self.cfg.config.seed));
+        self.scope.extend(added);
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use super::*;
+
+    fn test(filename: &str) {
+        let program = Program::new(crate::fixtures::fixture(filename));
+        let cfg = ControlFlowGraph::new(&program);
+        let ddg = DataDependencyGraph::new(&cfg);
+
+        let mut lazy = LazyDataDependencyGraph::new(&cfg);
+        assert!(lazy.graph().nodes.is_empty());
+
+        // A slice in the lazy graph has the same nodes as in the full one.
+        let addr = (0 .. ddg.nodes.len())
+            .filter(|&index| ddg.predecessors(index).next().is_some())
+            .filter_map(|index| match &ddg.nodes[index] {
+                DependencyNode::Location(location) => Some(location.addr),
+                _ => None,
+            })
+            .max()
+            .unwrap();
+        assert!(lazy.require_address(addr));
+        let required = lazy.graph().nodes.clone();
+        let slice = lazy.backward_slice(addr);
+        assert!(lazy.scope().contains(&addr));
+        assert_eq!(lazy.graph().nodes[.. required.len()], required[..]);
+        assert!(slice.iter().all(|&node| lazy.is_complete(node)));
+
+        let mut full = HashSet::new();
+        let mut pending: Vec<usize> = (0 .. ddg.nodes.len())
+            .filter(|&index| match &ddg.nodes[index] {
+                DependencyNode::Location(location) => location.addr == addr,
+                _ => false,
+            })
+            .collect();
+        while let Some(node) = pending.pop() {
+            if full.insert(ddg.nodes[node].clone()) {
+                pending.extend(ddg.predecessors(node));
+            }
+        }
+        let lazy_slice: HashSet<_> = slice.iter()
+            .map(|&node| lazy.graph().nodes[node].clone())
+            .collect();
+        assert_eq!(lazy_slice, full);
+        assert!(!lazy.require_address(addr));
+    }
+
+    #[test] fn lazy_bufs() { test("bufs") }
+    #[test] fn lazy_paths() { test("paths") }
+}
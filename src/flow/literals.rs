@@ -0,0 +1,145 @@
+//! String literals and well-known constants referenced by the code.
+//!
+//! Strings in read-only data are the landmarks a reader finds their way
+//! through a binary by. Instructions refer to them through a rip-relative
+//! address or, in position-dependent code, an immediate one. Any such address
+//! in a read-only region of the memory image is looked at: tables of well-known
+//! constants, like the S-box of AES, are recognized by their first bytes and
+//! other data by being a null-terminated string of printable characters.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::math::Integer;
+use crate::sym::MemoryImage;
+use crate::x86_64::{Operand, Register};
+use super::ControlFlowGraph;
+
+
+/// Read-only data that instructions refer to.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Literal {
+    /// A null-terminated string of printable characters without the null.
+    String(String),
+    /// A well-known table of constants by its name.
+    Constant(&'static str),
+}
+
+impl Literal {
+    /// The annotation of an instruction referring to the literal, like
+    /// `loads "Usage: %s"`.
+    pub fn annotation(&self) -> String {
+        format!("loads {}", self)
+    }
+}
+
+impl ControlFlowGraph {
+    /// The literals referred to by the instructions in the explored code by
+    /// the address of the instruction, with the address of the literal.
+    pub fn literals(&self) -> HashMap<u64, (u64, Literal)> {
+        let image = &*self.config.memory.image;
+        let mut found = HashMap::new();
+        for block in self.blocks.values() {
+            for &(addr, len, ref instruction, _) in &block.code {
+                let literal = instruction.operands.iter()
+                    .filter_map(|&operand| match operand {
+                        Operand::Indirect {
+                            base: Register::RIP,
+                            scaled_offset: None,
+                            displacement,
+                            ..
+                        } => Some((addr + len).wrapping_add(displacement.unwrap_or(0) as u64)),
+                        Operand::Immediate(Integer(_, value)) => Some(value),
+                        _ => None,
+                    })
+                    .find_map(|target| Some((target, literal_at(image, target)?)));
+                if let Some(literal) = literal {
+                    found.insert(addr, literal);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// The literal at the address if it is in a read-only region.
+pub fn literal_at(image: &MemoryImage, addr: u64) -> Option<Literal> {
+    if !image.is_readonly(addr) {
+        return None;
+    }
+    let (start, size) = image.region(addr)?;
+    let bytes = image.read(addr, start + size - addr)?;
+
+    let known = KNOWN_CONSTANTS.iter().find(|(_, prefix)| bytes.starts_with(prefix));
+    if let Some(&(name, _)) = known {
+        return Some(Literal::Constant(name));
+    }
+
+    let end = bytes.iter().position(|&byte| byte == 0)?;
+    let string = std::str::from_utf8(&bytes[.. end]).ok()?;
+    let printable = string.chars().all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'));
+    match printable && string.chars().count() >= MIN_STRING_LEN {
+        true => Some(Literal::String(string.to_string())),
+        false => None,
+    }
+}
+
+/// The minimum number of characters of a string, so that short runs of
+/// bytes in other data are not taken for one.
+const MIN_STRING_LEN: usize = 2;
+
+/// The well-known tables of constants by their first bytes.
+const KNOWN_CONSTANTS: &[(&str, &[u8])] = &[
+    ("AES S-box", b"\x63\x7c\x77\x7b\xf2\x6b\x6f\xc5\x30\x01\x67\x2b\xfe\xd7\xab\x76"),
+    ("AES inverse S-box", b"\x52\x09\x6a\xd5\x30\x36\xa5\x38\xbf\x40\xa3\x9e\x81\xf3\xd7\xfb"),
+    ("CRC-32 table", b"\x00\x00\x00\x00\x96\x30\x07\x77\x2c\x61\x0e\xee\xba\x51\x09\x99"),
+    ("MD5 sine table", b"\x78\xa4\x6a\xd7\x56\xb7\xc7\xe8\xdb\x70\x20\x24\xee\xce\xbd\xc1"),
+    ("SHA-256 initial hash", b"\x67\xe6\x09\x6a\x85\xae\x67\xbb\x72\xf3\x6e\x3c\x3a\xf5\x4f\xa5"),
+    ("SHA-256 constants", b"\x98\x2f\x8a\x42\x91\x44\x37\x71\xcf\xfb\xc0\xb5\xa5\xdb\xb5\xe9"),
+    ("SHA-512 constants", b"\x22\xae\x28\xd7\x98\x2f\x8a\x42\xcd\x65\xef\x23\x91\x44\x37\x71"),
+    ("Base64 alphabet", b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"),
+    ("ChaCha constant", b"expand 32-byte k"),
+];
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Literal::String(string) => write!(f, "{:?}", string),
+            Literal::Constant(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use super::*;
+
+    #[test]
+    fn literals() {
+        let program = Program::new(fixture("inputs"));
+        let cfg = ControlFlowGraph::new(&program);
+        let mut annotations: Vec<String> = cfg.literals().values()
+            .map(|(_, literal)| literal.annotation())
+            .collect();
+        annotations.sort();
+        assert_eq!(annotations, vec!["loads \"THEME\"", "loads \"config.bin\""]);
+    }
+
+    #[test]
+    fn constants() {
+        let mut image = MemoryImage::new();
+        let mut table = KNOWN_CONSTANTS[0].1.to_vec();
+        table.extend(b"\x01\x02usage\0\x7f\x00");
+        image.insert_readonly(0x2000, table);
+        image.insert(0x3000, b"writable\0".to_vec());
+
+        assert_eq!(literal_at(&image, 0x2000), Some(Literal::Constant("AES S-box")));
+        assert_eq!(literal_at(&image, 0x2012), Some(Literal::String("usage".to_string())));
+        assert_eq!(literal_at(&image, 0x2010), None);
+        assert_eq!(literal_at(&image, 0x2018), None);
+        assert_eq!(literal_at(&image, 0x3000), None);
+    }
+}
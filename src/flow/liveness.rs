@@ -0,0 +1,291 @@
+//! Liveness of registers and stack variables.
+//!
+//! A variable is live at a program point if some path from there reads it
+//! before writing it. The analysis works on the same variables as the SSA
+//! form, so reads of stack variables through pointers other than the stack
+//! and frame pointer are not visible to it.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::x86_64::Register;
+use super::{ControlFlowGraph, ControlKind, AbstractLocation, DataDependencyGraph, DependencyNode};
+use super::{SsaVariable, StackFrames};
+use super::ssa::{Access, node_accesses, edge_defs};
+
+
+/// The live variables at all program points of a control flow graph.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    /// The variables live at the start of each node.
+    pub live_in: Vec<BTreeSet<SsaVariable>>,
+    /// The variables live at the end of each node.
+    pub live_out: Vec<BTreeSet<SsaVariable>>,
+    /// The variables accessed by the instructions of each node.
+    accesses: Vec<Vec<Access>>,
+    /// The nodes containing an instruction by its address and call sites.
+    contexts: HashMap<(u64, Vec<u64>), Vec<usize>>,
+}
+
+impl Liveness {
+    /// Run the analysis on the graph.
+    pub fn new(cfg: &ControlFlowGraph) -> Liveness {
+        crate::timings::with("liveness", || Liveness::run(cfg))
+    }
+
+    /// The variables live before the instruction at the address in the node.
+    pub fn live_before(&self, node: usize, addr: u64) -> Option<BTreeSet<SsaVariable>> {
+        let mut live = self.live_out.get(node)?.clone();
+        for (at, uses, defs) in self.accesses[node].iter().rev() {
+            transfer(&mut live, uses, defs);
+            if *at == addr {
+                return Some(live);
+            }
+        }
+        None
+    }
+
+    /// The variables live after the instruction at the address in the node.
+    pub fn live_after(&self, node: usize, addr: u64) -> Option<BTreeSet<SsaVariable>> {
+        let mut live = self.live_out.get(node)?.clone();
+        for (at, uses, defs) in self.accesses[node].iter().rev() {
+            if *at == addr {
+                return Some(live);
+            }
+            transfer(&mut live, uses, defs);
+        }
+        None
+    }
+
+    /// The instructions as (node, address) before which the variable is live,
+    /// which make up its live range.
+    pub fn live_range(&self, variable: SsaVariable) -> Vec<(usize, u64)> {
+        let mut range = Vec::new();
+        for (node, accesses) in self.accesses.iter().enumerate() {
+            let mut live = self.live_out[node].clone();
+            for (addr, uses, defs) in accesses.iter().rev() {
+                transfer(&mut live, uses, defs);
+                if live.contains(&variable) {
+                    range.push((node, *addr));
+                }
+            }
+        }
+        range.sort_unstable();
+        range
+    }
+
+    /// The definitions as (node, address, variable) whose values are never
+    /// read, like dead stores to stack variables.
+    pub fn dead_definitions(&self) -> Vec<(usize, u64, SsaVariable)> {
+        let mut dead = Vec::new();
+        for (node, accesses) in self.accesses.iter().enumerate() {
+            let mut live = self.live_out[node].clone();
+            for (addr, uses, defs) in accesses.iter().rev() {
+                for &variable in defs {
+                    if !live.contains(&variable) {
+                        dead.push((node, *addr, variable));
+                    }
+                }
+                transfer(&mut live, uses, defs);
+            }
+        }
+        dead.sort_unstable();
+        dead
+    }
+
+    /// Whether the value written to the location is never read in any node
+    /// containing its instruction. Locations that are neither registers nor
+    /// stack variables are never dead.
+    pub fn is_dead(&self, frames: &StackFrames, location: &AbstractLocation) -> bool {
+        let variable = match SsaVariable::from_location(frames, location) {
+            Some(variable) => variable,
+            None => return false,
+        };
+        let nodes = match self.contexts.get(&(location.addr, location.trace.clone())) {
+            Some(nodes) => nodes,
+            None => return false,
+        };
+        nodes.iter().all(|&node| {
+            let written = self.accesses[node].iter()
+                .any(|(addr, _, defs)| *addr == location.addr && defs.contains(&variable));
+            written && matches!(self.live_after(node, location.addr),
+                Some(live) if !live.contains(&variable))
+        })
+    }
+
+    /// Find the fixpoint of the live variables at the ends of the nodes.
+    fn run(cfg: &ControlFlowGraph) -> Liveness {
+        let count = cfg.nodes.len();
+        let accesses: Vec<Vec<Access>> = (0 .. count)
+            .map(|node| node_accesses(cfg, node))
+            .collect();
+
+        let mut contexts: HashMap<(u64, Vec<u64>), Vec<usize>> = HashMap::new();
+        for (node, block) in cfg.nodes.iter().enumerate() {
+            let trace: Vec<u64> = block.trace.iter().map(|&(site, _)| site).collect();
+            for (addr, _, _) in &accesses[node] {
+                contexts.entry((*addr, trace.clone())).or_default().push(node);
+            }
+        }
+
+        let mut liveness = Liveness {
+            live_in: vec![BTreeSet::new(); count],
+            live_out: vec![BTreeSet::new(); count],
+            accesses,
+            contexts,
+        };
+
+        let mut worklist: Vec<usize> = (0 .. count).collect();
+        while let Some(node) = worklist.pop() {
+            let mut live = BTreeSet::new();
+            for &succ in &cfg.outgoing[node] {
+                let kind = cfg.edge_kind((node, succ));
+                let mut incoming = liveness.live_in[succ].clone();
+                for reg in edge_defs(kind) {
+                    incoming.remove(&SsaVariable::Register(reg));
+                }
+                incoming.extend(edge_uses(cfg, kind).into_iter().map(SsaVariable::Register));
+                live.extend(incoming);
+            }
+            liveness.live_out[node] = live.clone();
+
+            for (_, uses, defs) in liveness.accesses[node].iter().rev() {
+                transfer(&mut live, uses, defs);
+            }
+
+            if live != liveness.live_in[node] {
+                liveness.live_in[node] = live;
+                for &pred in &cfg.incoming[node] {
+                    if !worklist.contains(&pred) {
+                        worklist.push(pred);
+                    }
+                }
+            }
+        }
+
+        liveness
+    }
+}
+
+impl DataDependencyGraph {
+    /// A copy of this graph without the edges of dead definitions whose values
+    /// flow nowhere, repeated until no more such definitions remain. The nodes
+    /// stay in place, so that indices remain valid.
+    pub fn pruned(&self, liveness: &Liveness) -> DataDependencyGraph {
+        let mut pruned = self.clone();
+        loop {
+            let sources: BTreeSet<usize> = pruned.edges.keys()
+                .chain(&pruned.chains)
+                .map(|&(start, _)| start)
+                .collect();
+
+            let dead: BTreeSet<usize> = pruned.edges.keys()
+                .map(|&(_, end)| end)
+                .filter(|end| !sources.contains(end))
+                .filter(|&end| match &pruned.nodes[end] {
+                    DependencyNode::Location(location) => liveness.is_dead(&self.frames, location),
+                    _ => false,
+                })
+                .collect();
+
+            if dead.is_empty() {
+                pruned.reindex();
+                return pruned;
+            }
+            pruned.edges.retain(|(_, end), _| !dead.contains(end));
+            pruned.chains.retain(|(_, end)| !dead.contains(end));
+            pruned.threads.retain(|(_, end)| !dead.contains(end));
+            pruned.synchronizations.retain(|(_, end)| !dead.contains(end));
+        }
+    }
+}
+
+/// Turn the variables live after an instruction into those live before it.
+fn transfer(live: &mut BTreeSet<SsaVariable>, uses: &[SsaVariable], defs: &[SsaVariable]) {
+    for variable in defs {
+        live.remove(variable);
+    }
+    live.extend(uses.iter().copied());
+}
+
+/// The registers read by the summarized effect of an edge kind.
+fn edge_uses(cfg: &ControlFlowGraph, kind: ControlKind) -> Vec<Register> {
+    let mut uses = vec![Register::RSP];
+    match kind {
+        ControlKind::Flow => uses.clear(),
+        ControlKind::Allocate { function, .. }
+        | ControlKind::Input { function, .. }
+        | ControlKind::LongJump { function, .. }
+        | ControlKind::Recursion { function, .. }
+        | ControlKind::Thread { function, .. }
+        | ControlKind::Check { function, .. }
+        | ControlKind::Summary { function, .. } => {
+            uses.extend(&cfg.config.convention(function).arguments);
+        },
+        ControlKind::SetJump { function } => {
+            let convention = cfg.config.convention(function);
+            uses.extend(&convention.arguments);
+            uses.extend(&convention.preserved);
+        },
+        ControlKind::Exception
+        | ControlKind::Signal { .. }
+        | ControlKind::Finalize { .. }
+        | ControlKind::Spawn { .. } => {},
+    }
+    uses
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::flow::ssa::tests::graph;
+    use crate::math::SymCondition;
+    use crate::sym::SymConfig;
+    use super::*;
+    use super::super::StorageLocation;
+
+    #[test]
+    fn liveness() {
+        // b0: mov eax, edi; mov ecx, 1    b1: add eax, ecx; mov ecx, 2; syscall
+        let blocks: &[(u64, &[&[u8]])] = &[
+            (0x1000, &[&[0x89, 0xf8], &[0xb9, 0x01, 0x00, 0x00, 0x00]]),
+            (0x1010, &[&[0x01, 0xc8], &[0xb9, 0x02, 0x00, 0x00, 0x00], &[0x0f, 0x05]]),
+        ];
+
+        let cfg = graph(blocks, &[(0, 1)]);
+
+        assert_eq!(cfg.successors(0).collect::<Vec<_>>(), [1]);
+        assert_eq!(cfg.edges_to(1).collect::<Vec<_>>(), [(0, &SymCondition::TRUE)]);
+        assert_eq!(cfg.predecessors(0).count(), 0);
+
+        let liveness = Liveness::new(&cfg);
+        let reg = SsaVariable::Register;
+        assert!(liveness.live_in[1].contains(&reg(Register::RAX)));
+        assert!(liveness.live_in[1].contains(&reg(Register::RCX)));
+        assert!(liveness.live_in[0].contains(&reg(Register::RDI)));
+        assert!(!liveness.live_in[0].contains(&reg(Register::RAX)));
+
+        // The second write to ecx is clobbered by the system call.
+        let dead = liveness.dead_definitions();
+        assert!(dead.contains(&(1, 0x1012, reg(Register::RCX))));
+        assert!(dead.iter().all(|&(node, addr, _)| node == 1 && addr != 0x1010));
+        let after = liveness.live_after(0, 0x1000).unwrap();
+        assert!(after.contains(&reg(Register::RAX)) && !after.contains(&reg(Register::RCX)));
+        assert_eq!(liveness.live_range(reg(Register::RCX)), vec![(1, 0x1010)]);
+
+        let ecx = StorageLocation::Direct(Register::ECX);
+        let location = |addr| AbstractLocation::new(addr, vec![], ecx);
+        assert!(liveness.is_dead(&cfg.frames, &location(0x1012)));
+        assert!(!liveness.is_dead(&cfg.frames, &location(0x1002)));
+
+        // Data dependency graphs can leave out the flow into the dead write.
+        let written = |ddg: &DataDependencyGraph| ddg.nodes.iter()
+            .position(|node| *node == DependencyNode::Location(location(0x1012)))
+            .map_or(false, |node| ddg.predecessors(node).count() > 0);
+        let ddg = DataDependencyGraph::new(&cfg);
+        assert!(written(&ddg) && !written(&ddg.pruned(&liveness)));
+        let mut cfg = cfg;
+        cfg.config = Arc::new(SymConfig { prune_dead: true, .. SymConfig::default() });
+        assert!(!written(&DataDependencyGraph::new(&cfg)));
+    }
+}
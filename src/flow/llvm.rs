@@ -0,0 +1,832 @@
+//! Lowering of the recovered functions into LLVM IR.
+//!
+//! This backend is enabled by the `llvm` feature. Every function of the
+//! control flow graph becomes an LLVM function without parameters, whose
+//! basic blocks are the blocks of the graph and whose branches are its edges.
+//! The microcode of the instructions is lowered operation by operation:
+//! - The registers live in the global `@symflow.regs`, laid out like the
+//!   register memory space of the microcode.
+//! - Main memory is accessed through the addresses as pointers.
+//! - Temporaries are stack slots of the function, which `mem2reg` promotes.
+//! - Values the microcode does not model, like unknown values and system
+//!   calls, come from external functions prefixed with `symflow.` for an
+//!   instrumentation to define.
+//!
+//! Pointers are opaque, which LLVM 15 and later expect.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+
+use crate::ir::{MicroOperation, Location, Temporary, Transfer, BitOp, PackedOp, FloatOp, Query};
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol};
+use super::{ControlFlowGraph, BasicBlock, EdgeKind};
+use DataType::*;
+
+/// The size of the register memory space, which ends after the last vector
+/// register.
+const REGISTER_SPACE: u64 = 0x300;
+
+
+impl ControlFlowGraph {
+    /// Write the functions of the graph as a module in LLVM IR text.
+    ///
+    /// The functions start at the roots and the targets of calls. A function
+    /// consists of the blocks reachable from its start through control flow
+    /// edges and returns from calls, jumps to the start of another function
+    /// become tail calls. Calls and jumps with several targets in the graph
+    /// switch over the computed target.
+    pub fn write_llvm_ir<W: Write>(&self, mut f: W) -> io::Result<()> {
+        let mut flow: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        let mut calls: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        for &(start, end) in self.edges.keys() {
+            let edge = (self.nodes[start].addr, self.nodes[end].addr);
+            match EdgeKind::of_control(self, (start, end)) {
+                EdgeKind::ControlFlow => flow.entry(edge.0).or_default().insert(edge.1),
+                EdgeKind::Call => calls.entry(edge.0).or_default().insert(edge.1),
+                _ => continue,
+            };
+        }
+
+        // Name the functions uniquely.
+        let mut entries: BTreeSet<u64> = self.roots.iter().copied().collect();
+        entries.extend(self.nodes.first().map(|node| node.addr));
+        entries.extend(calls.values().flatten());
+        let mut names = BTreeMap::new();
+        let mut used = BTreeSet::new();
+        for &entry in &entries {
+            let name = self.frames.frames.get(&entry)
+                .map_or_else(|| format!("sub_{:x}", entry), |frame| frame.name.clone());
+            let name = if used.insert(name.clone()) {
+                name
+            } else {
+                format!("{}.{:x}", name, entry)
+            };
+            names.insert(entry, global_name(&name));
+        }
+
+        writeln!(f, "; ModuleID = 'symflow'")?;
+        writeln!(f, "target triple = \"x86_64-unknown-linux-gnu\"")?;
+        writeln!(f)?;
+        writeln!(f, "@symflow.regs = global [{} x i8] zeroinitializer", REGISTER_SPACE)?;
+
+        let mut declarations = BTreeSet::new();
+        for &entry in &entries {
+            let function = Function {
+                blocks: self.function_blocks(entry, &entries, &flow),
+                flow: &flow,
+                calls: &calls,
+                names: &names,
+            };
+            writeln!(f)?;
+            if function.blocks.is_empty() {
+                writeln!(f, "declare void {}()", names[&entry])?;
+                continue;
+            }
+
+            let mut lowering = Lowering {
+                body: Vec::new(),
+                values: 0,
+                slots: BTreeSet::new(),
+                declarations: &mut declarations,
+            };
+            function.lower(&mut lowering);
+
+            writeln!(f, "define void {}() {{", names[&entry])?;
+            writeln!(f, "entry:")?;
+            for &(index, data_type) in &lowering.slots {
+                writeln!(f, "  %t{}.{} = alloca {}", index, data_type, ty(data_type))?;
+            }
+            writeln!(f, "  br label %b{:x}", entry)?;
+            for line in &lowering.body {
+                writeln!(f, "{}", line)?;
+            }
+            writeln!(f, "}}")?;
+        }
+
+        if !declarations.is_empty() {
+            writeln!(f)?;
+        }
+        for declaration in &declarations {
+            writeln!(f, "{}", declaration)?;
+        }
+        Ok(())
+    }
+
+    /// The blocks of the function at the entry in address order.
+    fn function_blocks(
+        &self,
+        entry: u64,
+        entries: &BTreeSet<u64>,
+        flow: &BTreeMap<u64, BTreeSet<u64>>,
+    ) -> BTreeMap<u64, &BasicBlock> {
+        let mut blocks = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(entry);
+
+        while let Some(addr) = queue.pop_front() {
+            let block = match self.blocks.get(&addr) {
+                Some(block) if !blocks.contains_key(&addr) => block,
+                _ => continue,
+            };
+            blocks.insert(addr, block);
+
+            let transfer = block.code.last().map(|(_, _, _, microcode)| microcode.transfer);
+            if transfer == Some(Transfer::Call) {
+                queue.push_back(block.addr + block.len);
+            }
+            for &target in flow.get(&addr).into_iter().flatten() {
+                if !entries.contains(&target) {
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        blocks
+    }
+}
+
+/// A function to be lowered with the edges of the whole graph.
+struct Function<'a> {
+    blocks: BTreeMap<u64, &'a BasicBlock>,
+    flow: &'a BTreeMap<u64, BTreeSet<u64>>,
+    calls: &'a BTreeMap<u64, BTreeSet<u64>>,
+    names: &'a BTreeMap<u64, String>,
+}
+
+impl Function<'_> {
+    fn lower(&self, lowering: &mut Lowering) {
+        for (&addr, block) in &self.blocks {
+            lowering.label(format!("b{:x}", addr));
+            for (addr, _, instruction, microcode) in &block.code {
+                lowering.emit(format!("; {:#x}: {}", addr, instruction));
+                for operation in &microcode.ops {
+                    lowering.operation(operation);
+                }
+            }
+            self.terminate(lowering, block);
+        }
+    }
+
+    /// Branch to the successors of the block.
+    fn terminate(&self, lowering: &mut Lowering, block: &BasicBlock) {
+        let (addr, len, _, microcode) = match block.code.last() {
+            Some(last) => last,
+            None => return lowering.emit("unreachable".to_string()),
+        };
+        let next = addr + len;
+        let jump = microcode.ops.iter().rev().find_map(|op| match op {
+            MicroOperation::Jump { target, condition, relative } => {
+                Some((*target, condition, *relative))
+            },
+            _ => None,
+        });
+        let target = |lowering: &mut Lowering| match jump {
+            Some((target, _, relative)) => lowering.jump_target(target, relative, next),
+            None => lowering.unknown(N64),
+        };
+        let label = |suffix: &str| format!("b{:x}.{}", block.addr, suffix);
+
+        match microcode.transfer {
+            Transfer::Return => lowering.emit("ret void".to_string()),
+
+            Transfer::Call => {
+                let callees: Vec<u64> = self.calls.get(&block.addr).into_iter()
+                    .flatten().copied().collect();
+                match callees.as_slice() {
+                    [] => lowering.emit("; unresolved call".to_string()),
+                    [callee] => lowering.emit(format!("call void {}()", self.names[callee])),
+                    _ => {
+                        let target = target(lowering);
+                        let cases: Vec<String> = (0 .. callees.len())
+                            .map(|index| label(&format!("call{}", index)))
+                            .collect();
+                        lowering.switch(&target, &label("ret"), &callees, &cases);
+                        for (callee, case) in callees.iter().zip(cases) {
+                            lowering.label(case);
+                            lowering.emit(format!("call void {}()", self.names[callee]));
+                            lowering.emit(format!("br label %{}", label("ret")));
+                        }
+                        lowering.label(label("ret"));
+                    },
+                }
+                if self.blocks.contains_key(&next) {
+                    lowering.emit(format!("br label %b{:x}", next));
+                } else {
+                    lowering.emit("unreachable".to_string());
+                }
+            },
+
+            Transfer::Jump | Transfer::Next => {
+                let successors: Vec<u64> = self.flow.get(&block.addr).into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(|s| self.blocks.contains_key(s) || self.names.contains_key(s))
+                    .collect();
+
+                // Jumps out of the function call the other function.
+                let mut tails = Vec::new();
+                let labels: Vec<String> = successors.iter().map(|&successor| {
+                    if self.blocks.contains_key(&successor) {
+                        format!("b{:x}", successor)
+                    } else {
+                        tails.push(successor);
+                        label(&format!("tail{:x}", successor))
+                    }
+                }).collect();
+
+                let condition = jump.map(|(_, condition, _)| condition)
+                    .filter(|&condition| condition != &SymCondition::TRUE);
+                match (labels.as_slice(), condition) {
+                    ([], _) => lowering.emit("unreachable".to_string()),
+                    ([single], _) => lowering.emit(format!("br label %{}", single)),
+                    ([first, second], Some(condition)) if successors.contains(&next) => {
+                        let (taken, fallthrough) = if successors[0] == next {
+                            (second, first)
+                        } else {
+                            (first, second)
+                        };
+                        let condition = lowering.condition(condition);
+                        lowering.emit(format!("br i1 {}, label %{}, label %{}",
+                                              condition, taken, fallthrough));
+                    },
+                    _ => {
+                        let target = target(lowering);
+                        lowering.switch(&target, &label("default"), &successors, &labels);
+                        lowering.label(label("default"));
+                        lowering.emit("unreachable".to_string());
+                    },
+                }
+
+                for successor in tails {
+                    lowering.label(label(&format!("tail{:x}", successor)));
+                    lowering.emit(format!("call void {}()", self.names[&successor]));
+                    lowering.emit("ret void".to_string());
+                }
+            },
+        }
+    }
+}
+
+/// The lowering of the microcode of one function.
+struct Lowering<'a> {
+    /// The lines of the function after the entry block.
+    body: Vec<String>,
+    /// The number of numbered values so far.
+    values: usize,
+    /// The stack slots of the temporaries by index and data type.
+    slots: BTreeSet<(usize, DataType)>,
+    /// The declarations of the external functions for the whole module.
+    declarations: &'a mut BTreeSet<String>,
+}
+
+impl Lowering<'_> {
+    fn operation(&mut self, operation: &MicroOperation) {
+        use MicroOperation::*;
+
+        match *operation {
+            Mov { dest, src } => {
+                let value = self.load(src);
+                let value = self.resize(value, src.data_type(), dest.data_type(), false);
+                self.store(dest, &value);
+            },
+            Const { dest, constant } => self.store_temp(dest, &constant_text(constant)),
+            Cast { target, new, signed } => {
+                let value = self.load_temp(target);
+                let value = self.resize(value, target.0, new, signed);
+                self.store_temp(Temporary(new, target.1), &value);
+            },
+
+            Add { sum, a, b } => self.binary("add", sum, a, b),
+            Sub { diff, a, b } => self.binary("sub", diff, a, b),
+            Mul { prod, a, b } => self.binary("mul", prod, a, b),
+            And { and, a, b } => self.binary("and", and, a, b),
+            Or { or, a, b } => self.binary("or", or, a, b),
+            Xor { xor, a, b } => self.binary("xor", xor, a, b),
+            Not { not, a } => {
+                let value = self.load_temp(a);
+                let value = self.value(format!("xor {} {}, -1", ty(a.0), value));
+                self.store_temp(not, &value);
+            },
+
+            Bits { op, target, a, b } => {
+                let value = self.bits(op, a, b);
+                let value = self.resize(value, a.0, target.0, false);
+                self.store_temp(target, &value);
+            },
+            Packed { op, lane, target, a, b } => {
+                let value = self.packed(op, lane, target, a, b);
+                self.store_temp(target, &value);
+            },
+            Float { op, target, a, b } => {
+                let value = self.float(op, target, a, b);
+                self.store_temp(target, &value);
+            },
+
+            Copy { dest, src, count, data_type } => {
+                let dest = self.address(dest);
+                let src = self.address(src);
+                let length = self.load_temp(count);
+                let length = self.resize(length, count.0, N64, false);
+                let bytes = self.value(format!("mul i64 {}, {}", length, data_type.bytes()));
+                self.declare("declare void @llvm.memmove.p0.p0.i64(ptr, ptr, i64, i1)");
+                self.emit(format!("call void @llvm.memmove.p0.p0.i64(ptr {}, ptr {}, i64 {}, \
+                                   i1 false)", dest, src, bytes));
+            },
+            Fill { dest, value, count } => {
+                let dest = self.address(dest);
+                let data = self.load_temp(value);
+                let length = self.load_temp(count);
+                let length = self.resize(length, count.0, N64, false);
+                if value.0 == N8 {
+                    self.declare("declare void @llvm.memset.p0.i64(ptr, i8, i64, i1)");
+                    self.emit(format!("call void @llvm.memset.p0.i64(ptr {}, i8 {}, i64 {}, \
+                                       i1 false)", dest, data, length));
+                } else {
+                    let t = ty(value.0);
+                    self.declare(&format!("declare void @symflow.fill.{}(ptr, {}, i64)", t, t));
+                    self.emit(format!("call void @symflow.fill.{}(ptr {}, {} {}, i64 {})",
+                                      t, dest, t, data, length));
+                }
+            },
+            Unknown { target } => {
+                let value = self.unknown(target.0);
+                self.store_temp(target, &value);
+            },
+            Query { query, target, .. } => {
+                let value = self.query(query, target.0);
+                self.store_temp(target, &value);
+            },
+
+            Set { target, ref condition } => {
+                let condition = self.condition(condition);
+                let value = self.value(format!("zext i1 {} to {}", condition, ty(target.0)));
+                self.store_temp(target, &value);
+            },
+            Select { target, ref condition, a, b } => {
+                let condition = self.condition(condition);
+                let a = self.load_temp(a);
+                let b = self.load_temp(b);
+                let t = ty(target.0);
+                let value = self.value(format!("select i1 {}, {} {}, {} {}",
+                                               condition, t, a, t, b));
+                self.store_temp(target, &value);
+            },
+            // The terminator of the block does the jump.
+            Jump { .. } => {},
+
+            Syscall => {
+                self.declare("declare void @symflow.syscall()");
+                self.emit("call void @symflow.syscall()".to_string());
+            },
+        }
+    }
+
+    /// Apply an arithmetic or logic instruction to two temporaries.
+    fn binary(&mut self, instruction: &str, target: Temporary, a: Temporary, b: Temporary) {
+        let x = self.load_temp(a);
+        let y = self.load_temp(b);
+        let y = self.resize(y, b.0, a.0, false);
+        let value = self.value(format!("{} {} {}, {}", instruction, ty(a.0), x, y));
+        let value = self.resize(value, a.0, target.0, false);
+        self.store_temp(target, &value);
+    }
+
+    /// The result of a bit manipulation, in the type of `a`.
+    fn bits(&mut self, op: BitOp, a: Temporary, b: Temporary) -> String {
+        let t = ty(a.0);
+        let x = self.load_temp(a);
+        let y = self.load_temp(b);
+        let y = self.resize(y, b.0, a.0, false);
+        match op {
+            BitOp::Shl => self.value(format!("shl {} {}, {}", t, x, y)),
+            BitOp::Shr => self.value(format!("lshr {} {}, {}", t, x, y)),
+            BitOp::TrailingZeros | BitOp::LeadingZeros => {
+                let intrinsic = if op == BitOp::TrailingZeros { "cttz" } else { "ctlz" };
+                self.declare(&format!("declare {} @llvm.{}.{}({}, i1)", t, intrinsic, t, t));
+                self.value(format!("call {} @llvm.{}.{}({} {}, i1 false)", t, intrinsic, t, t, x))
+            },
+            BitOp::Extract | BitOp::Deposit if a.0 == N32 || a.0 == N64 => {
+                let intrinsic = if op == BitOp::Extract { "pext" } else { "pdep" };
+                let bits = a.0.bits();
+                self.declare(&format!("declare {} @llvm.x86.bmi.{}.{}({}, {})",
+                                      t, intrinsic, bits, t, t));
+                self.value(format!("call {} @llvm.x86.bmi.{}.{}({} {}, {} {})",
+                                   t, intrinsic, bits, t, x, t, y))
+            },
+            _ => self.unknown(a.0),
+        }
+    }
+
+    /// The result of a lane-wise operation, in the type of the target.
+    fn packed(
+        &mut self,
+        op: PackedOp,
+        lane: DataType,
+        target: Temporary,
+        a: Temporary,
+        b: Temporary,
+    ) -> String {
+        let lanes = a.0.bits() / lane.bits();
+        let vector = format!("<{} x {}>", lanes, ty(lane));
+        let x = self.load_temp(a);
+        let x = self.value(format!("bitcast {} {} to {}", ty(a.0), x, vector));
+
+        let result = match op {
+            PackedOp::MoveMask => {
+                let signs = self.value(format!("icmp slt {} {}, zeroinitializer", vector, x));
+                let mask = self.value(format!("bitcast <{} x i1> {} to i{}", lanes, signs, lanes));
+                let t = ty(target.0);
+                return match lanes.cmp(&target.0.bits()) {
+                    Ordering::Less => self.value(format!("zext i{} {} to {}",
+                                                                   lanes, mask, t)),
+                    Ordering::Equal => mask,
+                    Ordering::Greater => self.value(format!("trunc i{} {} to {}",
+                                                                      lanes, mask, t)),
+                };
+            },
+            PackedOp::Shuffle if lane == N8 && (a.0 == N128 || a.0 == N256) => {
+                let y = self.load_temp(b);
+                let y = self.value(format!("bitcast {} {} to {}", ty(b.0), y, vector));
+                let intrinsic = if a.0 == N128 { "ssse3.pshuf.b.128" } else { "avx2.pshuf.b" };
+                self.declare(&format!("declare {} @llvm.x86.{}({}, {})",
+                                      vector, intrinsic, vector, vector));
+                self.value(format!("call {} @llvm.x86.{}({} {}, {} {})",
+                                   vector, intrinsic, vector, x, vector, y))
+            },
+            PackedOp::Shuffle => return self.unknown(target.0),
+            _ => {
+                let y = self.load_temp(b);
+                let y = self.value(format!("bitcast {} {} to {}", ty(b.0), y, vector));
+                match op {
+                    PackedOp::Add => self.value(format!("add {} {}, {}", vector, x, y)),
+                    PackedOp::Sub => self.value(format!("sub {} {}, {}", vector, x, y)),
+                    _ => {
+                        let predicate = if op == PackedOp::Equal { "eq" } else { "sgt" };
+                        let flags = self.value(format!("icmp {} {} {}, {}",
+                                                       predicate, vector, x, y));
+                        self.value(format!("sext <{} x i1> {} to {}", lanes, flags, vector))
+                    },
+                }
+            },
+        };
+
+        let value = self.value(format!("bitcast {} {} to {}", vector, result, ty(a.0)));
+        self.resize(value, a.0, target.0, false)
+    }
+
+    /// The result of a floating-point operation, in the type of the target.
+    fn float(&mut self, op: FloatOp, target: Temporary, a: Temporary, b: Temporary) -> String {
+        let (from, to) = (float_type(a.0), float_type(target.0));
+        let x = self.load_temp(a);
+        let result = match (op, from, to) {
+            (FloatOp::Convert, Some(from), Some(to)) => {
+                let instruction = match a.0.bits().cmp(&target.0.bits()) {
+                    Ordering::Less => "fpext",
+                    Ordering::Equal => return x,
+                    Ordering::Greater => "fptrunc",
+                };
+                let x = self.value(format!("bitcast {} {} to {}", ty(a.0), x, from));
+                self.value(format!("{} {} {} to {}", instruction, from, x, to))
+            },
+            (FloatOp::Convert, None, Some(to)) => {
+                self.value(format!("sitofp {} {} to {}", ty(a.0), x, to))
+            },
+            (FloatOp::Convert, Some(from), None) => {
+                let x = self.value(format!("bitcast {} {} to {}", ty(a.0), x, from));
+                return self.value(format!("fptosi {} {} to {}", from, x, ty(target.0)));
+            },
+            (_, Some(from), Some(_)) if op != FloatOp::Convert && a.0 == target.0 => {
+                let instruction = match op {
+                    FloatOp::Add => "fadd",
+                    FloatOp::Sub => "fsub",
+                    FloatOp::Mul => "fmul",
+                    _ => "fdiv",
+                };
+                let y = self.load_temp(b);
+                let y = self.resize(y, b.0, a.0, false);
+                let x = self.value(format!("bitcast {} {} to {}", ty(a.0), x, from));
+                let y = self.value(format!("bitcast {} {} to {}", ty(a.0), y, from));
+                self.value(format!("{} {} {}, {}", instruction, from, x, y))
+            },
+            _ => return self.unknown(target.0),
+        };
+        let to = to.unwrap_or("double");
+        self.value(format!("bitcast {} {} to {}", to, result, ty(target.0)))
+    }
+
+    /// A value provided by the processor.
+    fn query(&mut self, query: Query, data_type: DataType) -> String {
+        let t = ty(data_type);
+        match query {
+            Query::Timestamp => {
+                self.declare("declare i64 @llvm.readcyclecounter()");
+                let value = self.value("call i64 @llvm.readcyclecounter()".to_string());
+                self.resize(value, N64, data_type, false)
+            },
+            Query::Random => {
+                self.declare(&format!("declare {} @symflow.random.{}()", t, t));
+                self.value(format!("call {} @symflow.random.{}()", t, t))
+            },
+            Query::Cpuid { leaf, subleaf, index } => {
+                let leaf_value = self.load_temp(leaf);
+                let leaf_value = self.resize(leaf_value, leaf.0, N32, false);
+                let subleaf_value = self.load_temp(subleaf);
+                let subleaf_value = self.resize(subleaf_value, subleaf.0, N32, false);
+                self.declare("declare i32 @symflow.cpuid(i32, i32, i32)");
+                let value = self.value(format!("call i32 @symflow.cpuid(i32 {}, i32 {}, i32 {})",
+                                               leaf_value, subleaf_value, index));
+                self.resize(value, N32, data_type, false)
+            },
+        }
+    }
+
+    /// The `i1` value of a condition.
+    fn condition(&mut self, condition: &SymCondition) -> String {
+        use SymCondition::*;
+
+        let (predicate, a, b) = match condition {
+            Bool(value) => return value.to_string(),
+            And(a, b) | Or(a, b) => {
+                let x = self.condition(a);
+                let y = self.condition(b);
+                let instruction = if matches!(condition, And(..)) { "and" } else { "or" };
+                return self.value(format!("{} i1 {}, {}", instruction, x, y));
+            },
+            Not(a) => {
+                let x = self.condition(a);
+                return self.value(format!("xor i1 {}, true", x));
+            },
+            Equal(a, b) => ("eq", a, b),
+            LessThan(a, b, signed) => (if *signed { "slt" } else { "ult" }, a, b),
+            LessEqual(a, b, signed) => (if *signed { "sle" } else { "ule" }, a, b),
+            GreaterThan(a, b, signed) => (if *signed { "sgt" } else { "ugt" }, a, b),
+            GreaterEqual(a, b, signed) => (if *signed { "sge" } else { "uge" }, a, b),
+        };
+
+        let data_type = a.data_type();
+        let x = self.expr(a);
+        let y = self.expr(b);
+        self.value(format!("icmp {} {} {}, {}", predicate, ty(data_type), x, y))
+    }
+
+    /// The value of an expression over temporaries.
+    fn expr(&mut self, expr: &SymExpr) -> String {
+        use SymExpr::*;
+
+        let data_type = expr.data_type();
+        let t = ty(data_type);
+        let (instruction, a, b) = match expr {
+            Int(int) => return constant_text(*int),
+            Sym(Symbol(data_type, "T", index)) => {
+                return self.load_temp(Temporary(*data_type, *index));
+            },
+            Sym(Symbol(data_type, ..)) => return self.unknown(*data_type),
+            Add(a, b) => ("add", a, b),
+            Sub(a, b) => ("sub", a, b),
+            Mul(a, b) => ("mul", a, b),
+            BitAnd(a, b) => ("and", a, b),
+            BitOr(a, b) => ("or", a, b),
+            BitXor(a, b) => ("xor", a, b),
+            Shl(a, b) => ("shl", a, b),
+            Shr(a, b, arithmetic) => (if *arithmetic { "ashr" } else { "lshr" }, a, b),
+            BitNot(a) => {
+                let x = self.expr(a);
+                return self.value(format!("xor {} {}, -1", t, x));
+            },
+            Cast(a, new, signed) => {
+                let x = self.expr(a);
+                return self.resize(x, a.data_type(), *new, *signed);
+            },
+            AsExpr(condition, _) => {
+                let condition = self.condition(condition);
+                return self.value(format!("zext i1 {} to {}", condition, t));
+            },
+            IfThenElse(condition, a, b) => {
+                let condition = self.condition(condition);
+                let x = self.expr(a);
+                let y = self.expr(b);
+                return self.value(format!("select i1 {}, {} {}, {} {}", condition, t, x, t, y));
+            },
+        };
+
+        let x = self.expr(a);
+        let y = self.expr(b);
+        let y = self.resize(y, b.data_type(), data_type, false);
+        self.value(format!("{} {} {}, {}", instruction, t, x, y))
+    }
+
+    /// The absolute target of a jump.
+    fn jump_target(&mut self, target: Temporary, relative: bool, next: u64) -> String {
+        let value = self.load_temp(target);
+        let value = self.resize(value, target.0, N64, false);
+        if relative {
+            self.value(format!("add i64 {}, {}", value, next as i64))
+        } else {
+            value
+        }
+    }
+
+    /// Switch over the target address to the labels of the addresses.
+    fn switch(&mut self, target: &str, default: &str, addrs: &[u64], labels: &[String]) {
+        let cases: Vec<String> = addrs.iter().zip(labels)
+            .map(|(&addr, label)| format!("i64 {}, label %{}", addr as i64, label))
+            .collect();
+        self.emit(format!("switch i64 {}, label %{} [ {} ]", target, default, cases.join(" ")));
+    }
+
+    /// A pointer to the location.
+    fn pointer(&mut self, location: Location) -> String {
+        match location {
+            Location::Temp(temp) => self.slot(temp),
+            Location::Direct(_, 1, offset) => {
+                self.value(format!("getelementptr i8, ptr @symflow.regs, i64 {}", offset))
+            },
+            Location::Direct(_, _, addr) => {
+                self.value(format!("inttoptr i64 {} to ptr", addr as i64))
+            },
+            Location::Indirect(_, 1, temp) => {
+                let offset = self.load_temp(temp);
+                let offset = self.resize(offset, temp.0, N64, false);
+                self.value(format!("getelementptr i8, ptr @symflow.regs, i64 {}", offset))
+            },
+            Location::Indirect(_, _, temp) => self.address(temp),
+        }
+    }
+
+    /// The temporary as a pointer into main memory.
+    fn address(&mut self, temp: Temporary) -> String {
+        let addr = self.load_temp(temp);
+        let addr = self.resize(addr, temp.0, N64, false);
+        self.value(format!("inttoptr i64 {} to ptr", addr))
+    }
+
+    fn load(&mut self, location: Location) -> String {
+        let pointer = self.pointer(location);
+        self.value(format!("load {}, ptr {}", ty(location.data_type()), pointer))
+    }
+
+    fn store(&mut self, location: Location, value: &str) {
+        let pointer = self.pointer(location);
+        self.emit(format!("store {} {}, ptr {}", ty(location.data_type()), value, pointer));
+    }
+
+    fn load_temp(&mut self, temp: Temporary) -> String {
+        self.load(Location::Temp(temp))
+    }
+
+    fn store_temp(&mut self, temp: Temporary, value: &str) {
+        self.store(Location::Temp(temp), value);
+    }
+
+    /// The stack slot of the temporary.
+    fn slot(&mut self, temp: Temporary) -> String {
+        self.slots.insert((temp.1, temp.0));
+        format!("%t{}.{}", temp.1, temp.0)
+    }
+
+    /// Truncate or extend the value to the other data type.
+    fn resize(&mut self, value: String, from: DataType, to: DataType, signed: bool) -> String {
+        let instruction = match from.bits().cmp(&to.bits()) {
+            Ordering::Equal => return value,
+            Ordering::Greater => "trunc",
+            Ordering::Less if signed => "sext",
+            Ordering::Less => "zext",
+        };
+        self.value(format!("{} {} {} to {}", instruction, ty(from), value, ty(to)))
+    }
+
+    /// A value the microcode does not model.
+    fn unknown(&mut self, data_type: DataType) -> String {
+        let t = ty(data_type);
+        self.declare(&format!("declare {} @symflow.unknown.{}()", t, t));
+        self.value(format!("call {} @symflow.unknown.{}()", t, t))
+    }
+
+    /// Emit the instruction as a new numbered value.
+    fn value(&mut self, instruction: String) -> String {
+        let name = format!("%v{}", self.values);
+        self.values += 1;
+        self.emit(format!("{} = {}", name, instruction));
+        name
+    }
+
+    fn emit(&mut self, line: String) {
+        self.body.push(format!("  {}", line));
+    }
+
+    fn label(&mut self, label: String) {
+        self.body.push(format!("{}:", label));
+    }
+
+    fn declare(&mut self, declaration: &str) {
+        self.declarations.insert(declaration.to_string());
+    }
+}
+
+/// The LLVM type holding values of the data type. Floats are held as their
+/// bit patterns like in the microcode.
+fn ty(data_type: DataType) -> &'static str {
+    match data_type {
+        N8 => "i8",
+        N16 => "i16",
+        N32 | F32 => "i32",
+        N64 | F64 => "i64",
+        N128 => "i128",
+        N256 => "i256",
+    }
+}
+
+/// The LLVM floating-point type of a float data type.
+fn float_type(data_type: DataType) -> Option<&'static str> {
+    match data_type {
+        F32 => Some("float"),
+        F64 => Some("double"),
+        _ => None,
+    }
+}
+
+/// An integer constant, written signed for types up to 64 bits.
+fn constant_text(int: Integer) -> String {
+    let bits = int.0.bits();
+    if bits <= 64 {
+        let shift = 64 - bits;
+        (((int.1 << shift) as i64) >> shift).to_string()
+    } else {
+        int.1.to_string()
+    }
+}
+
+/// The name of a global, quoted unless it is a plain identifier.
+fn global_name(name: &str) -> String {
+    let plain = name.chars().all(|c| c.is_ascii_alphanumeric() || "_.$".contains(c))
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if plain {
+        return format!("@{}", name);
+    }
+
+    let mut quoted = "@\"".to_string();
+    for c in name.chars() {
+        match c {
+            '"' | '\\' => quoted.push_str(&format!("\\{:02X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::flow::ControlFlowNode;
+    use crate::ir::MicroEncoder;
+    use crate::x86_64::Instruction;
+    use super::*;
+
+    #[test]
+    fn llvm_ir() {
+        // cmp rdi, rsi; jl 0x1008 | mov rax, rdi | ret
+        let block = |addr, instructions: &[&[u8]]| {
+            let mut encoder = MicroEncoder::new();
+            let mut code = Vec::new();
+            let mut end = addr;
+            for bytes in instructions {
+                let instruction = Instruction::decode(bytes).unwrap();
+                let microcode = encoder.encode(&instruction).unwrap();
+                code.push((end, bytes.len() as u64, instruction, microcode));
+                end += bytes.len() as u64;
+            }
+            (addr, BasicBlock { addr, len: end - addr, code })
+        };
+        let blocks = vec![
+            block(0x1000, &[&[0x48, 0x39, 0xf7], &[0x7c, 0x03]]),
+            block(0x1005, &[&[0x48, 0x89, 0xf8]]),
+            block(0x1008, &[&[0xc3]]),
+        ];
+
+        let node = |addr| ControlFlowNode { addr, trace: Vec::new() };
+        let edges = [(0, 1), (0, 2), (1, 2)];
+        let cfg = ControlFlowGraph::from_parts(
+            vec![node(0x1000), node(0x1005), node(0x1008)],
+            blocks.into_iter().collect(),
+            edges.iter().map(|&edge| (edge, SymCondition::TRUE)).collect(),
+            vec![0x1000],
+        );
+
+        let mut f = Vec::new();
+        cfg.write_llvm_ir(&mut f).unwrap();
+        let ir = String::from_utf8(f).unwrap();
+
+        assert!(ir.starts_with("; ModuleID = 'symflow'\n"));
+        assert!(ir.contains("@symflow.regs = global [768 x i8] zeroinitializer\n"));
+        assert!(ir.contains("define void @sub_1000() {\nentry:\n"));
+        assert!(ir.contains("  br label %b1000\nb1000:\n  ; 0x1000: cmp rdi, rsi\n"));
+        assert!(ir.contains("getelementptr i8, ptr @symflow.regs, i64 56\n"));
+        assert!(ir.contains(", label %b1008, label %b1005\nb1005:\n"));
+        assert!(ir.contains("  br label %b1008\nb1008:\n"));
+        assert!(ir.ends_with("  ret void\n}\n"));
+        assert_eq!(ir.matches("icmp slt i64").count(), 1);
+    }
+}
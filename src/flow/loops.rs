@@ -0,0 +1,651 @@
+//! Natural loops with their invariants, counters and bounds.
+//!
+//! A natural loop belongs to every back edge, an edge to a node dominating
+//! its source, and consists of the nodes reaching the source without passing
+//! the header. The paths through the body are executed symbolically from a
+//! state in which the registers hold symbols named after them and memory that
+//! is read before it is written holds `loop` symbols. Comparing the values at
+//! the end of every iteration with these symbols yields the values no
+//! iteration changes and the counters changing by a constant step. An exit
+//! whose condition only depends on one counter and invariant values bounds
+//! the number of iterations.
+//!
+//! Like the perfect matches memory strategy, this treats memory at different
+//! address expressions as different, so a store through a pointer that
+//! happens to alias a counter is missed.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::ir::{Location, MicroOperation};
+use crate::math::{DataType, Integer, SharedSolver, Solver, SymCondition, SymExpr, Symbol, Traversed};
+use crate::sym::{MemoryStrategy, SymState};
+use crate::x86_64::{Register, initial, REGISTERS};
+use super::ControlFlowGraph;
+use super::ssa::dominators;
+
+
+/// The maximum number of paths through the body of a loop, counting both
+/// complete iterations and paths to exits, that are executed to infer its
+/// counters and bound.
+pub const MAX_LOOP_PATHS: usize = 64;
+
+/// A natural loop of a control flow graph.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Loop {
+    /// The node every iteration starts at.
+    pub header: usize,
+    /// The nodes with edges back to the header.
+    pub latches: Vec<usize>,
+    /// The nodes of the loop including the header, sorted.
+    pub body: Vec<usize>,
+    /// The edges from the body to nodes outside of it.
+    pub exits: Vec<(usize, usize)>,
+    /// The values no iteration changes with the symbols standing for them.
+    pub invariants: Vec<(Symbol, LoopVariable)>,
+    /// The values every iteration changes by the same constant.
+    pub counters: Vec<Counter>,
+    /// An exit that bounds the number of iterations if one was found.
+    pub bound: Option<LoopBound>,
+}
+
+/// A value read by a loop.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum LoopVariable {
+    Register(Register),
+    /// The memory at an address in terms of the register values at the header.
+    Memory(SymExpr, DataType),
+}
+
+/// A value changing by a constant step in every iteration.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counter {
+    /// The symbol standing for the value at the header.
+    pub symbol: Symbol,
+    pub variable: LoopVariable,
+    pub step: i64,
+}
+
+/// An exit taken once a counter reaches a limit.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LoopBound {
+    /// The edge leaving the loop. Its source lies on every iteration.
+    pub exit: (usize, usize),
+    /// The index of the counter in the counters of the loop.
+    pub counter: usize,
+    /// The condition under which the exit is taken in terms of the values at
+    /// the header of the iteration taking it.
+    pub condition: SymCondition,
+}
+
+impl ControlFlowGraph {
+    /// The natural loops of the graph sorted by their headers. Loops sharing
+    /// a header are merged.
+    pub fn loops(&self) -> Vec<Loop> {
+        let (order, idom) = dominators(self);
+        let dominates = |dominator: usize, mut node: usize| loop {
+            if node == dominator {
+                return true;
+            }
+            match idom[node] {
+                Some(next) if next != node => node = next,
+                _ => return false,
+            }
+        };
+
+        let mut latches: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &node in &order {
+            for &succ in &self.outgoing[node] {
+                if dominates(succ, node) {
+                    latches.entry(succ).or_default().push(node);
+                }
+            }
+        }
+
+        let solver = Rc::new(Solver::with_seed(self.config.seed));
+        latches.into_iter()
+            .map(|(header, mut latches)| {
+                latches.sort();
+                let body = self.loop_body(header, &latches);
+                let exits = body.iter()
+                    .flat_map(|&node| self.outgoing[node].iter().map(move |&succ| (node, succ)))
+                    .filter(|(_, succ)| body.binary_search(succ).is_err())
+                    .collect();
+
+                let mut result = Loop {
+                    header,
+                    latches,
+                    body,
+                    exits,
+                    invariants: Vec::new(),
+                    counters: Vec::new(),
+                    bound: None,
+                };
+                LoopExecutor::new(self, &result, solver.clone()).infer(&mut result);
+                result
+            })
+            .collect()
+    }
+
+    /// The nodes reaching the latches without passing the header, sorted.
+    fn loop_body(&self, header: usize, latches: &[usize]) -> Vec<usize> {
+        let mut body = HashSet::new();
+        body.insert(header);
+
+        let mut stack = latches.to_vec();
+        while let Some(node) = stack.pop() {
+            if body.insert(node) {
+                stack.extend(&self.incoming[node]);
+            }
+        }
+
+        let mut body: Vec<usize> = body.into_iter().collect();
+        body.sort();
+        body
+    }
+}
+
+impl Loop {
+    /// The number of times the header is entered when the loop is entered
+    /// with the state. Returns `None` if the loop has no bound, the values of
+    /// its bound are not concrete in the state or it runs more than `limit`
+    /// iterations.
+    pub fn trips(&self, state: &SymState, limit: u64) -> Option<u64> {
+        let bound = self.bound.as_ref()?;
+        let counter = &self.counters[bound.counter];
+
+        let mut values = HashMap::new();
+        let variables = self.invariants.iter()
+            .map(|(symbol, variable)| (*symbol, variable))
+            .chain(std::iter::once((counter.symbol, &counter.variable)));
+        for (symbol, variable) in variables {
+            if let SymExpr::Int(value) = variable.value(state) {
+                values.insert(symbol, value);
+            }
+        }
+
+        if !used_symbols(&bound.condition).iter().all(|symbol| values.contains_key(symbol)) {
+            return None;
+        }
+
+        let start = values[&counter.symbol];
+        for iteration in 0 .. limit {
+            let offset = Integer::from_ptr((counter.step as u64).wrapping_mul(iteration));
+            values.insert(counter.symbol, start.add(offset.cast(start.0, false)));
+            if bound.condition.evaluate(&|symbol| values.get(&symbol).copied()) {
+                return Some(iteration + 1);
+            }
+        }
+        None
+    }
+
+    /// Replace the counters in a state at the header by fresh symbols standing
+    /// for their values in any iteration and return the condition under which
+    /// they take these values. Unless the value is the initial one, the
+    /// condition requires that the previous iteration did not take the exit of
+    /// the bound, so it also covers limits that are not concrete.
+    pub fn widen(&self, state: &mut SymState) -> SymCondition {
+        let values: HashMap<Symbol, SymExpr> = self.invariants.iter()
+            .map(|(symbol, variable)| (*symbol, variable.value(state)))
+            .collect();
+
+        let mut reachable = SymCondition::TRUE;
+        for (index, counter) in self.counters.iter().enumerate() {
+            let start = counter.variable.value(state);
+            let data_type = start.data_type();
+            let value = state.unknown(data_type);
+            counter.variable.set_value(state, value.clone());
+
+            let step = SymExpr::Int(Integer::from_ptr(counter.step.unsigned_abs()).cast(data_type, false));
+            let (later, previous) = if counter.step > 0 {
+                (value.clone().greater_than(start.clone(), true), value.clone().sub(step))
+            } else {
+                (value.clone().less_than(start.clone(), true), value.clone().add(step))
+            };
+
+            let mut reached = later;
+            if let Some(bound) = self.bound.as_ref().filter(|bound| bound.counter == index) {
+                let mut exited = bound.condition.clone();
+                exited.replace_symbols(&|symbol| if symbol == counter.symbol {
+                    previous.clone()
+                } else {
+                    values.get(&symbol).cloned().unwrap_or(SymExpr::Sym(symbol))
+                });
+                reached = reached.and(exited.not());
+            }
+
+            reachable = reachable.and(value.equal(start).or(reached));
+        }
+        reachable
+    }
+}
+
+impl LoopVariable {
+    /// The value of the variable in a state at the header.
+    pub fn value(&self, state: &SymState) -> SymExpr {
+        match self {
+            LoopVariable::Register(register) => state.get_reg(*register),
+            LoopVariable::Memory(addr, data_type) => {
+                state.memory[0].read_expr(address(addr, state), *data_type)
+            },
+        }
+    }
+
+    /// Overwrite the variable in a state at the header.
+    pub fn set_value(&self, state: &mut SymState, value: SymExpr) {
+        match self {
+            LoopVariable::Register(register) => state.set_reg(*register, value),
+            LoopVariable::Memory(addr, _) => {
+                let addr = address(addr, state);
+                state.memory[0].write_expr(addr, value);
+            },
+        }
+    }
+}
+
+/// The address in terms of the register values at the header evaluated in
+/// a state.
+fn address(addr: &SymExpr, state: &SymState) -> SymExpr {
+    let mut addr = addr.clone();
+    addr.replace_symbols(&|symbol| {
+        match REGISTERS.iter().find(|&&(register, _)| initial(register) == SymExpr::Sym(symbol)) {
+            Some(&(register, _)) => state.get_reg(register),
+            None => SymExpr::Sym(symbol),
+        }
+    });
+    state.solver.simplify_expr(&addr)
+}
+
+/// A path through the body of a loop that is being executed.
+#[derive(Clone)]
+struct Walk {
+    path: Vec<usize>,
+    state: SymState,
+    /// The fresh symbols of values read before they were written by the
+    /// `loop` symbols standing for them.
+    renamed: HashMap<Symbol, SymExpr>,
+    /// The addresses written so far with the types of the writes.
+    stored: Vec<(SymExpr, DataType)>,
+    /// Whether memory was written at an unknown extent.
+    clobbered: bool,
+}
+
+/// The values at the end of a complete iteration.
+struct Iteration {
+    path: Vec<usize>,
+    /// The values of the registers in the order of `REGISTERS`.
+    registers: Vec<SymExpr>,
+    /// The written addresses and types with the values there.
+    stores: Vec<(SymExpr, DataType, SymExpr)>,
+    clobbered: bool,
+}
+
+/// Executes the paths through the body of a loop.
+struct LoopExecutor<'g> {
+    cfg: &'g ControlFlowGraph,
+    header: usize,
+    body: HashSet<usize>,
+    solver: SharedSolver,
+    /// The memory read before it is written, indexed by `loop` symbol.
+    variables: Vec<(SymExpr, DataType)>,
+    iterations: Vec<Iteration>,
+    /// The exits reached with the paths to them and their conditions.
+    exits: Vec<((usize, usize), Vec<usize>, SymCondition)>,
+    /// Whether a path ran into a cycle other than the loop itself.
+    nested: bool,
+}
+
+impl<'g> LoopExecutor<'g> {
+    fn new(cfg: &'g ControlFlowGraph, result: &Loop, solver: SharedSolver) -> LoopExecutor<'g> {
+        LoopExecutor {
+            cfg,
+            header: result.header,
+            body: result.body.iter().copied().collect(),
+            solver,
+            variables: Vec::new(),
+            iterations: Vec::new(),
+            exits: Vec::new(),
+            nested: false,
+        }
+    }
+
+    /// Fill in the invariants, counters and bound of the loop if all paths
+    /// could be executed.
+    fn infer(mut self, result: &mut Loop) {
+        if !self.run() || self.iterations.is_empty() {
+            return;
+        }
+
+        let mut invariant = HashSet::new();
+        for (index, &(register, name)) in REGISTERS.iter().enumerate() {
+            let start = initial(register);
+            let steps = self.iterations.iter()
+                .map(|iteration| self.step(&iteration.registers[index], &start))
+                .collect();
+            let symbol = Symbol(DataType::N64, name, 0);
+            self.classify(result, symbol, LoopVariable::Register(register), steps, &mut invariant);
+        }
+
+        for index in 0 .. self.variables.len() {
+            let (addr, data_type) = self.variables[index].clone();
+            let symbol = Symbol(data_type, "loop", index);
+            if !expr_symbols(&addr).iter().all(|used| invariant.contains(used)) {
+                continue;
+            }
+
+            let steps = self.iterations.iter()
+                .map(|iteration| match iteration.stores.iter().find(|(stored, ..)| *stored == addr) {
+                    Some((_, stored_type, value)) if *stored_type == data_type => {
+                        self.step(value, &SymExpr::Sym(symbol))
+                    },
+                    Some(_) => None,
+                    None if iteration.clobbered => None,
+                    None => Some(0),
+                })
+                .collect();
+            let variable = LoopVariable::Memory(addr, data_type);
+            self.classify(result, symbol, variable, steps, &mut invariant);
+        }
+
+        if !self.nested {
+            result.bound = self.bound(result, &invariant);
+        }
+    }
+
+    /// Execute all paths from the header through the body. Fails if there
+    /// are too many of them.
+    fn run(&mut self) -> bool {
+        let mut state = SymState::new(MemoryStrategy::PerfectMatches, self.solver.clone());
+        for &(register, _) in &REGISTERS {
+            state.set_reg(register, initial(register));
+        }
+
+        let mut walks = vec![Walk {
+            path: vec![self.header],
+            state,
+            renamed: HashMap::new(),
+            stored: Vec::new(),
+            clobbered: false,
+        }];
+
+        while let Some(mut walk) = walks.pop() {
+            if self.iterations.len() + self.exits.len() > MAX_LOOP_PATHS {
+                return false;
+            }
+
+            let cfg = self.cfg;
+            let node = *walk.path.last().unwrap();
+            self.execute(&mut walk, node);
+
+            for &succ in &cfg.outgoing[node] {
+                let edge = (node, succ);
+                if succ == self.header {
+                    let iteration = self.finish(&walk);
+                    self.iterations.push(iteration);
+                } else if !self.body.contains(&succ) {
+                    let condition = walk.state.evaluate_condition(&cfg.edges[&edge]);
+                    self.exits.push((edge, walk.path.clone(), rename_condition(condition, &walk.renamed)));
+                } else if walk.path.contains(&succ) {
+                    self.nested = true;
+                } else {
+                    let mut next = walk.clone();
+                    next.path.push(succ);
+                    cfg.edge_kind(edge).enter(&mut next.state);
+                    walks.push(next);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Execute the block of the node and keep track of the memory accesses.
+    fn execute(&mut self, walk: &mut Walk, node: usize) {
+        let cfg = self.cfg;
+        let block = &cfg.blocks[&cfg.nodes[node].addr];
+        for (addr, len, _, microcode) in &block.code {
+            for op in &microcode.ops {
+                let mut load = None;
+                match *op {
+                    MicroOperation::Mov { dest: Location::Indirect(data_type, 0, temp), .. } => {
+                        let addr = rename(walk.state.get_temp(temp), &walk.renamed);
+                        if !walk.stored.contains(&(addr.clone(), data_type)) {
+                            walk.stored.push((addr, data_type));
+                        }
+                    },
+                    MicroOperation::Mov { dest, src: Location::Indirect(data_type, 0, temp) } => {
+                        load = Some((walk.state.get_temp(temp), data_type, dest));
+                    },
+                    MicroOperation::Copy { .. } | MicroOperation::Fill { .. } => {
+                        walk.clobbered = true;
+                    },
+                    _ => {},
+                }
+
+                walk.state.step(addr + len, op);
+
+                // Values read from memory not written in this iteration stand
+                // for the values at the header.
+                if let Some((addr, data_type, dest)) = load {
+                    let addr = rename(addr, &walk.renamed);
+                    if let SymExpr::Sym(symbol) = walk.state.read_location(dest) {
+                        let fresh = !walk.renamed.contains_key(&symbol)
+                            && !walk.stored.iter().any(|(stored, _)| *stored == addr)
+                            && !REGISTERS.iter().any(|&(register, _)| initial(register) == SymExpr::Sym(symbol));
+                        if fresh {
+                            let index = self.variable(addr, data_type);
+                            walk.renamed.insert(symbol, SymExpr::Sym(Symbol(data_type, "loop", index)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The values at the end of a complete iteration.
+    fn finish(&self, walk: &Walk) -> Iteration {
+        let registers = REGISTERS.iter()
+            .map(|&(register, _)| rename(walk.state.get_reg(register), &walk.renamed))
+            .collect();
+
+        let stores = walk.stored.iter()
+            .map(|(addr, data_type)| {
+                let value = walk.state.memory[0].read_expr(addr.clone(), *data_type);
+                (addr.clone(), *data_type, rename(value, &walk.renamed))
+            })
+            .collect();
+
+        Iteration { path: walk.path.clone(), registers, stores, clobbered: walk.clobbered }
+    }
+
+    /// The index of the `loop` symbol for the memory.
+    fn variable(&mut self, addr: SymExpr, data_type: DataType) -> usize {
+        let key = (addr, data_type);
+        match self.variables.iter().position(|variable| *variable == key) {
+            Some(index) => index,
+            None => {
+                self.variables.push(key);
+                self.variables.len() - 1
+            },
+        }
+    }
+
+    /// How much the value changed from the start if by a constant.
+    fn step(&self, value: &SymExpr, start: &SymExpr) -> Option<i64> {
+        if value == start {
+            return Some(0);
+        }
+        match self.solver.simplify_expr(&value.clone().sub(start.clone())) {
+            SymExpr::Int(diff) => Some(diff.cast(DataType::N64, true).1 as i64),
+            _ => None,
+        }
+    }
+
+    /// Record the variable as invariant or counter depending on its steps in
+    /// the iterations.
+    fn classify(
+        &self,
+        result: &mut Loop,
+        symbol: Symbol,
+        variable: LoopVariable,
+        steps: Option<Vec<i64>>,
+        invariant: &mut HashSet<Symbol>,
+    ) {
+        let steps = match steps {
+            Some(steps) => steps,
+            None => return,
+        };
+
+        let step = steps[0];
+        if steps.iter().any(|&other| other != step) {
+            return;
+        }
+
+        if step == 0 {
+            invariant.insert(symbol);
+            result.invariants.push((symbol, variable));
+        } else if !self.nested {
+            result.counters.push(Counter { symbol, variable, step });
+        }
+    }
+
+    /// Find an exit lying on every iteration whose condition only depends on
+    /// one counter and invariants.
+    fn bound(&self, result: &Loop, invariant: &HashSet<Symbol>) -> Option<LoopBound> {
+        for &exit in &result.exits {
+            let conditions: Vec<&SymCondition> = self.exits.iter()
+                .filter(|(edge, ..)| *edge == exit)
+                .map(|(_, _, condition)| condition)
+                .collect();
+
+            let always = self.iterations.iter().all(|iteration| iteration.path.contains(&exit.0));
+            let condition = match conditions.first() {
+                Some(&first) if always && conditions.iter().all(|&other| other == first) => first,
+                _ => continue,
+            };
+
+            let used = used_symbols(condition);
+            let counters: Vec<usize> = result.counters.iter()
+                .enumerate()
+                .filter(|(_, counter)| used.contains(&counter.symbol))
+                .map(|(index, _)| index)
+                .collect();
+
+            let closed = used.iter().all(|symbol| {
+                invariant.contains(symbol) || counters.iter().any(|&index| result.counters[index].symbol == *symbol)
+            });
+
+            if let ([counter], true) = (counters.as_slice(), closed) {
+                return Some(LoopBound { exit, counter: *counter, condition: condition.clone() });
+            }
+        }
+        None
+    }
+}
+
+/// Replace the fresh symbols by the `loop` symbols standing for them.
+fn rename(mut expr: SymExpr, renamed: &HashMap<Symbol, SymExpr>) -> SymExpr {
+    expr.replace_symbols(&|symbol| renamed.get(&symbol).cloned().unwrap_or(SymExpr::Sym(symbol)));
+    expr
+}
+
+/// Replace the fresh symbols of a condition by the `loop` symbols.
+fn rename_condition(mut condition: SymCondition, renamed: &HashMap<Symbol, SymExpr>) -> SymCondition {
+    condition.replace_symbols(&|symbol| renamed.get(&symbol).cloned().unwrap_or(SymExpr::Sym(symbol)));
+    condition
+}
+
+/// The symbols occurring in a condition.
+fn used_symbols(condition: &SymCondition) -> HashSet<Symbol> {
+    let mut symbols = HashSet::new();
+    condition.traverse(&mut |node| {
+        if let Traversed::Expr(&SymExpr::Sym(symbol)) = node {
+            symbols.insert(symbol);
+        }
+    });
+    symbols
+}
+
+/// The symbols occurring in an expression.
+fn expr_symbols(expr: &SymExpr) -> HashSet<Symbol> {
+    let mut symbols = HashSet::new();
+    expr.traverse(&mut |node| {
+        if let Traversed::Expr(&SymExpr::Sym(symbol)) = node {
+            symbols.insert(symbol);
+        }
+    });
+    symbols
+}
+
+impl Display for Loop {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "loop at node {} with {} nodes", self.header, self.body.len())?;
+        for (symbol, variable) in &self.invariants {
+            write!(f, "\n  invariant {} = {}", variable, symbol)?;
+        }
+        for counter in &self.counters {
+            write!(f, "\n  counter {} = {} {:+} per iteration", counter.variable, counter.symbol, counter.step)?;
+        }
+        if let Some(bound) = &self.bound {
+            write!(f, "\n  exits {} -> {} if {}", bound.exit.0, bound.exit.1, bound.condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for LoopVariable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LoopVariable::Register(register) => write!(f, "{}", register),
+            LoopVariable::Memory(addr, data_type) => write!(f, "{} [{}]", data_type.name(), addr),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use super::*;
+
+    #[test]
+    fn counted() {
+        let program = Program::new(fixture("count"));
+        let cfg = ControlFlowGraph::new(&program);
+        let loops = cfg.loops();
+        assert_eq!(loops.len(), 1);
+
+        let counted = &loops[0];
+        let bound = counted.bound.as_ref().unwrap();
+        let counter = &counted.counters[bound.counter];
+        assert_eq!(counter.step, 1);
+        assert!(matches!(counter.variable, LoopVariable::Memory(_, DataType::N32)));
+        assert!(counted.invariants.iter().any(|(_, variable)| *variable == LoopVariable::Register(Register::RBP)));
+
+        // Entering the loop with the counter at zero runs three iterations.
+        let mut state = SymState::new(MemoryStrategy::PerfectMatches, Rc::new(Solver::new()));
+        state.set_reg(Register::RBP, SymExpr::from_ptr(0x1000));
+        state.memory[0].write_expr(SymExpr::from_ptr(0xffc), SymExpr::from_int(DataType::N32, 0));
+        assert_eq!(counted.trips(&state, 100), Some(3));
+        assert_eq!(counted.trips(&state, 2), None);
+
+        // After widening, the counter can hold any value up to the last one.
+        let reachable = counted.widen(&mut state);
+        let value = counter.variable.value(&state);
+        assert!(matches!(value, SymExpr::Sym(Symbol(DataType::N32, "unknown", _))));
+        let holds = |n: u64| reachable.evaluate(&|_| Some(Integer(DataType::N32, n)));
+        assert!(holds(0) && holds(2));
+        assert!(!holds(3));
+    }
+
+    #[test]
+    fn endless() {
+        let program = Program::new(fixture("loop"));
+        let cfg = ControlFlowGraph::new(&program);
+        let loops = cfg.loops();
+        assert!(!loops.is_empty());
+        assert!(loops.iter().all(|endless| endless.bound.is_none() && endless.exits.is_empty()));
+    }
+}
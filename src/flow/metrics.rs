@@ -0,0 +1,324 @@
+//! Size and complexity metrics of the analyzed functions and graphs.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+
+use crate::helper::json_string;
+use super::{ControlFlowGraph, ProgramDependenceGraph, DependenceNode, EdgeKind};
+
+
+/// Metrics of a whole program computed from its control flow and program
+/// dependence graphs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphMetrics {
+    /// The metrics of each function sorted by address.
+    pub functions: Vec<FunctionMetrics>,
+    /// The number of dependence nodes by kind.
+    pub nodes: BTreeMap<&'static str, usize>,
+    /// The number of dependence edges by kind.
+    pub edges: BTreeMap<&'static str, usize>,
+    /// The number of locations on the longest chain of data dependences,
+    /// ignoring the edges that close cycles.
+    pub max_depth: usize,
+    /// The distribution of the number of data dependences into locations.
+    pub fan_in: Distribution,
+    /// The distribution of the number of data dependences out of locations.
+    pub fan_out: Distribution,
+}
+
+/// Metrics of a single function, summed over all contexts it was reached in.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FunctionMetrics {
+    /// The address of the function.
+    pub function: u64,
+    /// The symbol name or a name derived from the address.
+    pub name: String,
+    /// The number of distinct basic blocks.
+    pub blocks: usize,
+    /// The number of distinct control flow edges within the function.
+    pub edges: usize,
+    /// The cyclomatic complexity, i.e. one more than the number of decisions.
+    pub cyclomatic: usize,
+    /// The number of locations accessed in the function.
+    pub locations: usize,
+    /// The number of distinct functions calling this one.
+    pub callers: usize,
+    /// The number of distinct functions called by this one.
+    pub callees: usize,
+}
+
+/// The maximum and mean of a count over nodes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Distribution {
+    pub max: usize,
+    pub mean: f64,
+}
+
+impl Distribution {
+    fn new(counts: &[usize]) -> Distribution {
+        Distribution {
+            max: counts.iter().copied().max().unwrap_or(0),
+            mean: match counts.len() {
+                0 => 0.0,
+                len => counts.iter().sum::<usize>() as f64 / len as f64,
+            },
+        }
+    }
+}
+
+impl GraphMetrics {
+    /// Compute the metrics of the graphs of a program.
+    pub fn new(cfg: &ControlFlowGraph, pdg: &ProgramDependenceGraph) -> GraphMetrics {
+        crate::timings::with("metrics", || GraphMetrics::compute(cfg, pdg))
+    }
+
+    fn compute(cfg: &ControlFlowGraph, pdg: &ProgramDependenceGraph) -> GraphMetrics {
+        // Collapse the contexts of each function into its distinct blocks and
+        // the control flow between them.
+        let functions: Vec<u64> = (0 .. cfg.nodes.len())
+            .map(|node| function_of(cfg, node))
+            .collect();
+        let mut blocks: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        let mut flows: HashMap<u64, BTreeSet<(u64, u64)>> = HashMap::new();
+        let mut callers: HashMap<u64, BTreeSet<u64>> = HashMap::new();
+        let mut callees: HashMap<u64, BTreeSet<u64>> = HashMap::new();
+        for (node, block) in cfg.nodes.iter().enumerate() {
+            blocks.entry(functions[node]).or_default().insert(block.addr);
+            for end in cfg.successors(node) {
+                match EdgeKind::of_control(cfg, (node, end)) {
+                    EdgeKind::ControlFlow => {
+                        let edge = (block.addr, cfg.nodes[end].addr);
+                        flows.entry(functions[node]).or_default().insert(edge);
+                    }
+                    EdgeKind::Call => {
+                        callees.entry(functions[node]).or_default().insert(functions[end]);
+                        callers.entry(functions[end]).or_default().insert(functions[node]);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut locations: HashMap<u64, usize> = HashMap::new();
+        for node in &pdg.nodes {
+            if let DependenceNode::DataDependency(location) = node {
+                if let Some(function) = cfg.frames.function_of(location) {
+                    *locations.entry(function).or_default() += 1;
+                }
+            }
+        }
+
+        let functions = blocks.into_iter()
+            .map(|(function, blocks)| {
+                let edges = flows.remove(&function).unwrap_or_default();
+                let mut successors: HashMap<u64, usize> = HashMap::new();
+                for &(start, _) in &edges {
+                    *successors.entry(start).or_default() += 1;
+                }
+                let decisions: usize = successors.values().map(|&count| count - 1).sum();
+                FunctionMetrics {
+                    function,
+                    name: cfg.frames.frames.get(&function)
+                        .map(|frame| frame.name.clone())
+                        .unwrap_or_else(|| format!("fn_{:x}", function)),
+                    blocks: blocks.len(),
+                    edges: edges.len(),
+                    cyclomatic: decisions + 1,
+                    locations: locations.get(&function).copied().unwrap_or(0),
+                    callers: callers.get(&function).map_or(0, BTreeSet::len),
+                    callees: callees.get(&function).map_or(0, BTreeSet::len),
+                }
+            })
+            .collect();
+
+        let mut nodes = BTreeMap::new();
+        for node in &pdg.nodes {
+            let kind = match node {
+                DependenceNode::ControlFlow(_) => "control-flow",
+                DependenceNode::DataDependency(_) => "data-dependency",
+            };
+            *nodes.entry(kind).or_default() += 1;
+        }
+
+        let mut edges = BTreeMap::new();
+        for (_, edge) in pdg.edges.iter() {
+            *edges.entry(edge.kind.name()).or_default() += 1;
+        }
+
+        let data: Vec<usize> = (0 .. pdg.nodes.len())
+            .filter(|&node| matches!(pdg.nodes[node], DependenceNode::DataDependency(_)))
+            .collect();
+        let fan_in: Vec<usize> = data.iter()
+            .map(|&node| pdg.edges_to(node).filter(|(_, edge)| !edge.kind.is_control()).count())
+            .collect();
+        let fan_out: Vec<usize> = data.iter()
+            .map(|&node| pdg.edges_from(node).filter(|(_, edge)| !edge.kind.is_control()).count())
+            .collect();
+
+        GraphMetrics {
+            functions,
+            nodes,
+            edges,
+            max_depth: max_depth(pdg),
+            fan_in: Distribution::new(&fan_in),
+            fan_out: Distribution::new(&fan_out),
+        }
+    }
+
+    /// Write the metrics as a JSON object.
+    pub fn write_json<W: Write>(&self, mut f: W) -> io::Result<()> {
+        let counts = |map: &BTreeMap<&str, usize>| map.iter()
+            .map(|(kind, count)| format!("{}: {}", json_string(kind), count))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"nodes\": {{{}}},", counts(&self.nodes))?;
+        writeln!(f, "  \"edges\": {{{}}},", counts(&self.edges))?;
+        writeln!(f, "  \"max_depth\": {},", self.max_depth)?;
+        writeln!(f, "  \"fan_in\": {{\"max\": {}, \"mean\": {:.3}}},",
+                 self.fan_in.max, self.fan_in.mean)?;
+        writeln!(f, "  \"fan_out\": {{\"max\": {}, \"mean\": {:.3}}},",
+                 self.fan_out.max, self.fan_out.mean)?;
+        writeln!(f, "  \"functions\": [")?;
+        for (i, function) in self.functions.iter().enumerate() {
+            let separator = if i + 1 < self.functions.len() { "," } else { "" };
+            writeln!(f, "    {{\"address\": \"{:#x}\", \"name\": {}, \"blocks\": {}, \
+                         \"edges\": {}, \"cyclomatic\": {}, \"locations\": {}, \
+                         \"callers\": {}, \"callees\": {}}}{}",
+                     function.function, json_string(&function.name), function.blocks,
+                     function.edges, function.cyclomatic, function.locations,
+                     function.callers, function.callees, separator)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "}}")
+    }
+
+    /// Write the metrics of the functions as CSV with a header row.
+    pub fn write_csv<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "address,name,blocks,edges,cyclomatic,locations,callers,callees")?;
+        for function in &self.functions {
+            writeln!(f, "{:#x},\"{}\",{},{},{},{},{},{}",
+                     function.function, function.name.replace('"', "\"\""), function.blocks,
+                     function.edges, function.cyclomatic, function.locations,
+                     function.callers, function.callees)?;
+        }
+        Ok(())
+    }
+}
+
+/// The function the node's block is executed in: the one entered at the
+/// innermost call site or, outside of calls, the root the block belongs to.
+fn function_of(cfg: &ControlFlowGraph, node: usize) -> u64 {
+    let node = &cfg.nodes[node];
+    if let Some(&(_, function)) = node.trace.last() {
+        return function;
+    }
+    let functions = cfg.frames.functions_at(node.addr);
+    functions.iter()
+        .find(|function| cfg.roots.contains(function))
+        .or_else(|| functions.first())
+        .or_else(|| cfg.roots.first())
+        .copied()
+        .unwrap_or(node.addr)
+}
+
+/// The number of locations on the longest chain of data dependences. Edges
+/// closing cycles in a depth-first search from each node are ignored.
+fn max_depth(pdg: &ProgramDependenceGraph) -> usize {
+    let count = pdg.nodes.len();
+    let mut depth: Vec<Option<usize>> = vec![None; count];
+    let mut on_stack = vec![false; count];
+
+    for root in 0 .. count {
+        let data = matches!(pdg.nodes[root], DependenceNode::DataDependency(_));
+        if depth[root].is_some() || !data {
+            continue;
+        }
+
+        // Explicit stack of (node, whether its successors were pushed).
+        let mut stack = vec![(root, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                on_stack[node] = false;
+                let deepest = pdg.edges_from(node)
+                    .filter(|(_, edge)| !edge.kind.is_control())
+                    .filter_map(|(end, _)| depth[end])
+                    .max()
+                    .unwrap_or(0);
+                depth[node] = Some(deepest + 1);
+                continue;
+            }
+            if depth[node].is_some() || on_stack[node] {
+                continue;
+            }
+            on_stack[node] = true;
+            stack.push((node, true));
+            for (end, edge) in pdg.edges_from(node) {
+                if !edge.kind.is_control() && depth[end].is_none() && !on_stack[end] {
+                    stack.push((end, false));
+                }
+            }
+        }
+    }
+
+    depth.into_iter().flatten().max().unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::math::SymCondition;
+    use super::*;
+    use super::super::ControlFlowNode;
+
+    #[test]
+    fn metrics() {
+        // main: 0x10 -> 0x20 | 0x30, 0x20 -> 0x30, 0x30 calls f at 0x40.
+        let node = |addr, trace| ControlFlowNode { addr, trace };
+        let nodes = vec![
+            node(0x10, vec![]),
+            node(0x20, vec![]),
+            node(0x30, vec![]),
+            node(0x40, vec![(0x35, 0x40)]),
+        ];
+        let edges = [(0, 1), (0, 2), (1, 2), (2, 3)].iter()
+            .map(|&edge| (edge, SymCondition::TRUE))
+            .collect();
+        let cfg = ControlFlowGraph::from_parts(nodes, HashMap::new(), edges, vec![0x10]);
+
+        let pdg = ProgramDependenceGraph::new(&cfg, &crate::flow::DataDependencyGraph {
+            nodes: Vec::new(),
+            edges: HashMap::new(),
+            frames: cfg.frames.clone(),
+            classes: HashMap::new(),
+            chains: Default::default(),
+            threads: Default::default(),
+            synchronizations: Default::default(),
+            inputs: Default::default(),
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        });
+
+        let metrics = GraphMetrics::new(&cfg, &pdg);
+        assert_eq!(metrics.functions.len(), 2);
+        let main = &metrics.functions[0];
+        assert_eq!((main.function, main.blocks, main.edges), (0x10, 3, 3));
+        assert_eq!((main.cyclomatic, main.callers, main.callees), (2, 0, 1));
+        assert_eq!(metrics.functions[1].callers, 1);
+        assert_eq!(metrics.nodes["control-flow"], 4);
+        assert_eq!(metrics.edges["call"], 1);
+        assert_eq!(metrics.max_depth, 0);
+
+        let mut csv = Vec::new();
+        metrics.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(1), Some("0x10,\"fn_10\",3,3,2,0,0,1"));
+
+        let mut json = Vec::new();
+        metrics.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"edges\": {\"call\": 1, \"control-flow\": 3},"));
+        assert!(json.contains("{\"address\": \"0x40\", \"name\": \"fn_40\""));
+    }
+}
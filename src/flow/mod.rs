@@ -1,19 +1,106 @@
 //! Control and data flow models.
+//!
+//! All graphs are deterministic: analyzing the same program with the same
+//! configuration yields the same nodes in the same order, the same symbol
+//! names and byte-identical output from every exporter. Node indices follow
+//! the order in which the explorations find the nodes, symbols are numbered
+//! per path in the order they are created, and everything kept in hash maps
+//! is sorted before it is iterated for output.
 
 use std::fmt::{self, Display, Formatter};
 use crate::math::{Integer, DataType};
-use crate::x86_64::{Register, Operand};
+use crate::x86_64::{Register, Flag, Operand};
 
 mod control;
 mod alias;
 mod data;
 mod visualize;
 mod pdg;
+mod adjacency;
+mod frame;
+mod vsa;
+mod ssa;
+mod liveness;
+mod validate;
+mod metrics;
+mod diff;
+mod incremental;
+mod lazy;
+mod progress;
+mod stats;
+mod annotate;
+mod angr;
+mod semantics;
+mod snapshot;
+mod gadgets;
+mod synthesis;
+mod directed;
+mod loops;
+mod recursion;
+mod threads;
+mod taint;
+mod checks;
+mod hardening;
+mod literals;
+mod switches;
+mod variables;
+mod summary;
+mod profile;
+mod report;
+mod regions;
+mod effects;
+mod coverage;
+mod equivalence;
+mod patch;
+mod scope;
+mod baremetal;
+mod vectors;
+#[cfg(feature = "llvm")]
+mod llvm;
 
 pub use control::*;
 pub use alias::*;
 pub use data::*;
 pub use pdg::*;
+pub use adjacency::*;
+pub use frame::*;
+pub use vsa::*;
+pub use ssa::*;
+pub use liveness::*;
+pub use validate::*;
+pub use metrics::*;
+pub use diff::*;
+pub use incremental::*;
+pub use lazy::*;
+pub use progress::*;
+pub use stats::*;
+pub use annotate::*;
+pub use angr::*;
+pub use snapshot::*;
+pub use gadgets::*;
+pub use synthesis::*;
+pub use directed::*;
+pub use loops::*;
+pub use recursion::*;
+pub use threads::*;
+pub use taint::*;
+pub use checks::*;
+pub use hardening::*;
+pub use literals::*;
+pub use switches::*;
+pub use variables::*;
+pub use summary::*;
+pub use profile::*;
+pub use report::*;
+pub use regions::*;
+pub use effects::*;
+pub use coverage::*;
+pub use equivalence::*;
+pub use patch::*;
+pub use scope::*;
+pub use baremetal::*;
+pub use vectors::*;
+pub use visualize::{VisualConfig, Listing, Metadata, Elision};
 
 
 /// A storage location within the context in which it is valid.
@@ -47,6 +134,9 @@ pub enum StorageLocation {
         scaled_offset: Option<(Register, u8)>,
         displacement: Option<i64>,
     },
+    /// A status flag, which is written by comparisons and arithmetic and read
+    /// by conditional jumps, sets and moves.
+    Flag(Flag),
 }
 
 impl StorageLocation {
@@ -82,19 +172,145 @@ impl StorageLocation {
         }
     }
 
+    /// The single bytes of a memory location in order, or just the location
+    /// if it is a register or flag.
+    pub fn bytes(self) -> Vec<StorageLocation> {
+        match self {
+            StorageLocation::Indirect { data_type, base, scaled_offset, displacement } => {
+                (0 .. data_type.bytes() as i64)
+                    .map(|index| StorageLocation::Indirect {
+                        data_type: DataType::N8,
+                        base,
+                        scaled_offset,
+                        displacement: match (displacement, index) {
+                            (None, 0) => None,
+                            (displacement, index) => Some(displacement.unwrap_or(0) + index),
+                        },
+                    })
+                    .collect()
+            },
+            _ => vec![self],
+        }
+    }
+
     /// Whether this in an indirect access.
     pub fn accesses_memory(&self) -> bool {
         match self {
-            StorageLocation::Direct(_) => false,
+            StorageLocation::Direct(_) | StorageLocation::Flag(_) => false,
             StorageLocation::Indirect { .. } => true,
         }
     }
 
-    /// The underlying data type of the value at the location.
+    /// The underlying data type of the value at the location. Flags are
+    /// treated like bytes holding zero or one.
     pub fn data_type(&self) -> DataType {
         match *self {
             StorageLocation::Direct(reg) => reg.data_type(),
             StorageLocation::Indirect { data_type, .. } => data_type,
+            StorageLocation::Flag(_) => DataType::N8,
+        }
+    }
+
+    /// The memory class of the location as far as it can be told without
+    /// knowing the values of its registers.
+    pub fn memory_class(&self) -> MemoryClass {
+        match *self {
+            StorageLocation::Direct(_) | StorageLocation::Flag(_) => MemoryClass::Register,
+            StorageLocation::Indirect { base: Register::RSP, .. }
+            | StorageLocation::Indirect { base: Register::RBP, .. } => MemoryClass::Stack,
+            StorageLocation::Indirect { base: Register::RIP, scaled_offset: None, .. } => {
+                MemoryClass::Global
+            },
+            StorageLocation::Indirect { .. } => MemoryClass::Unknown,
+        }
+    }
+
+    /// The thread-local slot if this is a fixed offset from the `fs` segment base.
+    pub fn tls_slot(&self) -> Option<TlsSlot> {
+        match *self {
+            StorageLocation::Indirect { base: Register::FS, scaled_offset: None, displacement, .. } => {
+                Some(TlsSlot::from_offset(displacement.unwrap_or(0)))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The kind of memory a storage location refers to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MemoryClass {
+    /// A register instead of memory.
+    Register,
+    /// The stack frame of some function.
+    Stack,
+    /// A region returned by a heap allocator.
+    Heap,
+    /// Data at a fixed address.
+    Global,
+    /// The code of the program.
+    Code,
+    /// The registers of a device mapped into memory.
+    Device,
+    /// Memory whose kind could not be determined.
+    Unknown,
+}
+
+impl MemoryClass {
+    /// All classes in order.
+    pub const ALL: [MemoryClass; 7] = [
+        MemoryClass::Register,
+        MemoryClass::Stack,
+        MemoryClass::Heap,
+        MemoryClass::Global,
+        MemoryClass::Code,
+        MemoryClass::Device,
+        MemoryClass::Unknown,
+    ];
+
+    /// Combine the classes of the same location observed on different paths.
+    pub fn join(self, other: MemoryClass) -> MemoryClass {
+        if self == other { self } else { MemoryClass::Unknown }
+    }
+
+    /// The fill color of locations of this class in visualizations.
+    pub fn color(self) -> &'static str {
+        match self {
+            MemoryClass::Register => "#ffffff",
+            MemoryClass::Stack => "#ffe0b2",
+            MemoryClass::Heap => "#f8bbd0",
+            MemoryClass::Global => "#c8e6c9",
+            MemoryClass::Code => "#d1c4e9",
+            MemoryClass::Device => "#b2ebf2",
+            MemoryClass::Unknown => "#e0e0e0",
+        }
+    }
+}
+
+/// A location relative to the thread pointer in `fs` following the x86-64
+/// TLS layout of glibc.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TlsSlot {
+    /// The pointer of the thread control block to itself at `fs:0x0`.
+    ThreadPointer,
+    /// The stack protector canary at `fs:0x28`.
+    StackGuard,
+    /// The pointer mangling guard at `fs:0x30`.
+    PointerGuard,
+    /// Another field of the thread control block.
+    Control(i64),
+    /// A variable in the static TLS block below the thread pointer.
+    Variable(i64),
+}
+
+impl TlsSlot {
+    /// The slot at an offset from the thread pointer.
+    pub fn from_offset(offset: i64) -> TlsSlot {
+        match offset {
+            0x0 => TlsSlot::ThreadPointer,
+            0x28 => TlsSlot::StackGuard,
+            0x30 => TlsSlot::PointerGuard,
+            x if x < 0 => TlsSlot::Variable(x),
+            x => TlsSlot::Control(x),
         }
     }
 }
@@ -109,16 +325,21 @@ pub enum ValueSource {
 impl Display for AbstractLocation {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{} at {:x}", self.storage, self.addr)?;
-        if !self.trace.is_empty() {
-            write!(f, " by ")?;
-        }
-        let mut first = true;
-        for &addr in &self.trace {
-            if !first { write!(f, " -> ")?; } first = false;
-            write!(f, "{:x}", addr)?;
-        }
-        Ok(())
+        write_trace(f, &self.trace)
+    }
+}
+
+/// Write the call trace of an abstract location.
+fn write_trace(f: &mut Formatter, trace: &[u64]) -> fmt::Result {
+    if !trace.is_empty() {
+        write!(f, " by ")?;
     }
+    let mut first = true;
+    for &addr in trace {
+        if !first { write!(f, " -> ")?; } first = false;
+        write!(f, "{:x}", addr)?;
+    }
+    Ok(())
 }
 
 impl Display for StorageLocation {
@@ -128,6 +349,7 @@ impl Display for StorageLocation {
 
         match *self {
             Direct(reg) => write!(f, "{}", reg),
+            Flag(flag) => write!(f, "{}", flag),
             Indirect { data_type, base, scaled_offset, displacement } => {
                 write!(f, "[{}", base)?;
                 if let Some((index, scale)) = scaled_offset {
@@ -142,6 +364,34 @@ impl Display for StorageLocation {
     }
 }
 
+impl Display for TlsSlot {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use crate::helper::write_signed_hex;
+
+        match *self {
+            TlsSlot::ThreadPointer => write!(f, "thread pointer"),
+            TlsSlot::StackGuard => write!(f, "stack guard"),
+            TlsSlot::PointerGuard => write!(f, "pointer guard"),
+            TlsSlot::Control(offset) => { write!(f, "tcb")?; write_signed_hex(f, offset) },
+            TlsSlot::Variable(offset) => { write!(f, "tls")?; write_signed_hex(f, offset) },
+        }
+    }
+}
+
+impl Display for MemoryClass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            MemoryClass::Register => "register",
+            MemoryClass::Stack => "stack",
+            MemoryClass::Heap => "heap",
+            MemoryClass::Global => "global",
+            MemoryClass::Code => "code",
+            MemoryClass::Device => "device",
+            MemoryClass::Unknown => "unknown",
+        })
+    }
+}
+
 impl Display for ValueSource {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -150,3 +400,50 @@ impl Display for ValueSource {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x86_64::Instruction;
+
+    fn slot(bytes: &[u8]) -> Option<TlsSlot> {
+        let instruction = Instruction::decode(bytes).unwrap();
+        StorageLocation::from_operand(instruction.operands[1]).and_then(|s| s.tls_slot())
+    }
+
+    #[test]
+    fn tls() {
+        // Instruction: mov rax, qword ptr fs:[0x28]
+        assert_eq!(slot(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00]),
+                   Some(TlsSlot::StackGuard));
+
+        // Instruction: mov eax, dword ptr fs:[0xfffffffffffffffc]
+        assert_eq!(slot(&[0x64, 0x8b, 0x04, 0x25, 0xfc, 0xff, 0xff, 0xff]),
+                   Some(TlsSlot::Variable(-4)));
+
+        // Instruction: mov eax, dword ptr [rbp-0x4]
+        assert_eq!(slot(&[0x8b, 0x45, 0xfc]), None);
+        assert_eq!(TlsSlot::Variable(-4).to_string(), "tls-0x4");
+    }
+
+    #[test]
+    fn memory_classes() {
+        let class = |bytes: &[u8]| {
+            let instruction = Instruction::decode(bytes).unwrap();
+            StorageLocation::from_operand(instruction.operands[1]).unwrap().memory_class()
+        };
+
+        // Instruction: mov eax, dword ptr [rbp-0x4]
+        assert_eq!(class(&[0x8b, 0x45, 0xfc]), MemoryClass::Stack);
+        // Instruction: mov eax, dword ptr [rip+0x10]
+        assert_eq!(class(&[0x8b, 0x05, 0x10, 0x00, 0x00, 0x00]), MemoryClass::Global);
+        // Instruction: mov eax, dword ptr [rdi]
+        assert_eq!(class(&[0x8b, 0x07]), MemoryClass::Unknown);
+        // Instruction: mov eax, edi
+        assert_eq!(class(&[0x89, 0xf8]), MemoryClass::Register);
+
+        assert_eq!(MemoryClass::Heap.join(MemoryClass::Heap), MemoryClass::Heap);
+        assert_eq!(MemoryClass::Heap.join(MemoryClass::Stack), MemoryClass::Unknown);
+    }
+}
@@ -0,0 +1,329 @@
+//! The impact of a patch between two builds of a program.
+//!
+//! The instructions of both builds are grouped by the functions containing
+//! them, which are matched by name, and the sequences of each function are
+//! matched by a longest common subsequence. Instructions are compared in a
+//! form that does not depend on where code and data are placed: branch
+//! targets are replaced by their positions in functions and data referenced
+//! relative to the instruction pointer by its symbol. The instructions of the
+//! new build left unmatched are the changed ones. Branches over inserted or
+//! removed code of their function count as changed as well.
+//!
+//! The impact of the patch is the forward slice of the changed instructions
+//! and their blocks in the program dependence graph of the new build, with
+//! the functions it reaches and the outputs of functions it flows into.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::Program;
+use crate::helper::json_string;
+use crate::x86_64::{Instruction, Operand, Register};
+use super::{ControlFlowGraph, DataDependencyGraph, DependencyNode, DependenceNode};
+use super::{Port, ProgramDependenceGraph, StackFrames};
+use super::diff::block_position;
+use super::summary::output_locations;
+
+
+/// What a patch can influence in the new build.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PatchImpact {
+    /// The addresses of the instructions of the new build that are changed
+    /// or added, sorted.
+    pub changed: Vec<u64>,
+    /// The nodes of the program dependence graph of the new build in the
+    /// forward slice of the changed instructions.
+    pub slice: BTreeSet<usize>,
+    /// The names of the functions with nodes in the slice, sorted.
+    pub functions: Vec<String>,
+    /// The outputs of functions written by locations in the slice by the name
+    /// of the function, sorted.
+    pub outputs: Vec<(String, Port)>,
+}
+
+impl PatchImpact {
+    /// Compute the impact of the patch from the old to the new build.
+    pub fn compute(
+        old: &Program,
+        old_cfg: &ControlFlowGraph,
+        new: &Program,
+        new_cfg: &ControlFlowGraph,
+        new_ddg: &DataDependencyGraph,
+        new_pdg: &ProgramDependenceGraph,
+    ) -> PatchImpact {
+        let changed = changed_instructions(old, old_cfg, new, new_cfg);
+
+        let mut slice = BTreeSet::new();
+        for &addr in &changed {
+            slice.extend(new_pdg.forward_slice(addr));
+            for node in new_cfg.nodes_containing(addr) {
+                slice.extend(new_pdg.forward_slice(new_cfg.nodes[node].addr));
+            }
+        }
+
+        let frames = &new_cfg.frames;
+        let name = |function: u64| frames.frames[&function].name.clone();
+        let functions: BTreeSet<String> = slice.iter()
+            .flat_map(|&index| match &new_pdg.nodes[index] {
+                DependenceNode::ControlFlow(addr) => frames.functions_at(*addr).to_vec(),
+                DependenceNode::DataDependency(location) => {
+                    frames.function_of(location).into_iter().collect()
+                },
+            })
+            .map(&name)
+            .collect();
+
+        let mut outputs = Vec::new();
+        for (function, ports) in output_locations(new, new_cfg, new_ddg) {
+            for (port, locations) in ports {
+                let influenced = locations.iter().any(|&index| match &new_ddg.nodes[index] {
+                    DependencyNode::Location(location) => new_pdg.node_for(location)
+                        .map_or(false, |node| slice.contains(&node)),
+                    _ => false,
+                });
+                if influenced {
+                    outputs.push((name(function), port));
+                }
+            }
+        }
+        outputs.sort();
+
+        PatchImpact {
+            changed,
+            slice,
+            functions: functions.into_iter().collect(),
+            outputs,
+        }
+    }
+
+    /// Write the impact as JSON with the changed addresses, the influenced
+    /// functions and outputs and the size of the slice.
+    pub fn write_json<W: Write>(&self, mut f: W) -> io::Result<()> {
+        let changed: Vec<String> = self.changed.iter().map(u64::to_string).collect();
+        let functions: Vec<String> = self.functions.iter().map(|name| json_string(name)).collect();
+        let outputs: Vec<String> = self.outputs.iter()
+            .map(|(function, port)| format!(
+                "{{\"function\": {}, \"output\": {}}}",
+                json_string(function), json_string(&port.to_string()),
+            ))
+            .collect();
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"changed\": [{}],", changed.join(", "))?;
+        writeln!(f, "  \"functions\": [{}],", functions.join(", "))?;
+        writeln!(f, "  \"outputs\": [{}],", outputs.join(", "))?;
+        writeln!(f, "  \"slice\": {}", self.slice.len())?;
+        writeln!(f, "}}")
+    }
+}
+
+/// The addresses of the instructions of the new build that are changed or
+/// added compared to the old one, sorted.
+pub fn changed_instructions(
+    old: &Program,
+    old_cfg: &ControlFlowGraph,
+    new: &Program,
+    new_cfg: &ControlFlowGraph,
+) -> Vec<u64> {
+    let old = functions(old, &old_cfg.frames);
+    let new = functions(new, &new_cfg.frames);
+
+    let mut changed = BTreeSet::new();
+    for (name, instructions) in &new {
+        let keys: Vec<&str> = instructions.iter().map(|(_, key)| key.as_str()).collect();
+        let before: Vec<&str> = old.get(name)
+            .map(|instructions| instructions.iter().map(|(_, key)| key.as_str()).collect())
+            .unwrap_or_default();
+        changed.extend(unmatched(&before, &keys).into_iter().map(|index| instructions[index].0));
+    }
+    changed.into_iter().collect()
+}
+
+/// The instructions of the functions with their addresses and normalized
+/// forms by the names of the functions. Instructions outside of functions
+/// are listed under the empty name.
+fn functions(program: &Program, frames: &StackFrames) -> BTreeMap<String, Vec<(u64, String)>> {
+    let mut functions: BTreeMap<String, Vec<(u64, String)>> = BTreeMap::new();
+    for (addr, len, instruction, _) in &program.code {
+        let key = normalized(program, frames, *addr, *len, instruction);
+        let containing = frames.functions_at(*addr);
+        if containing.is_empty() {
+            functions.entry(String::new()).or_default().push((*addr, key));
+        }
+        for function in containing {
+            let name = frames.frames[function].name.clone();
+            functions.entry(name).or_default().push((*addr, key.clone()));
+        }
+    }
+    for instructions in functions.values_mut() {
+        instructions.sort_by_key(|&(addr, _)| addr);
+    }
+    functions
+}
+
+/// The instruction with its branch targets and data relative to the
+/// instruction pointer replaced by where they are in the program.
+fn normalized(
+    program: &Program,
+    frames: &StackFrames,
+    addr: u64,
+    len: u64,
+    instruction: &Instruction,
+) -> String {
+    let next = addr + len;
+    let mut instruction = instruction.clone();
+    let mut targets = Vec::new();
+    for operand in &mut instruction.operands {
+        match operand {
+            Operand::Offset(offset) => {
+                let target = next.wrapping_add(*offset as u64);
+                targets.push(match program.symbols.get(&target) {
+                    Some(name) => name.clone(),
+                    None => block_position(frames, target).to_string(),
+                });
+                *offset = 0;
+            },
+            Operand::Indirect { base: Register::RIP, displacement, .. } => {
+                let target = next.wrapping_add(displacement.unwrap_or(0) as u64);
+                targets.push(program.symbols.get(&target)
+                    .cloned()
+                    .unwrap_or_else(|| "data".to_string()));
+                *displacement = None;
+            },
+            _ => {},
+        }
+    }
+
+    match targets.is_empty() {
+        true => instruction.to_string(),
+        false => format!("{} -> {}", instruction, targets.join(", ")),
+    }
+}
+
+/// The indices of the new elements that are not part of a longest common
+/// subsequence with the old ones.
+fn unmatched(old: &[&str], new: &[&str]) -> Vec<usize> {
+    let mut matched = common_subsequence(old, new).into_iter().map(|(_, j)| j).peekable();
+    (0 .. new.len()).filter(|&j| matched.next_if_eq(&j).is_none()).collect()
+}
+
+/// The indices of the old and new elements that make up a longest common
+/// subsequence of both, in order.
+pub(super) fn common_subsequence(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    // Patches tend to be small, so the common start and end are skipped
+    // before the quadratic part.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix ..].iter().rev()
+        .zip(new[prefix ..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut common: Vec<(usize, usize)> = (0 .. prefix).map(|i| (i, i)).collect();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    collect_common(&old[prefix .. old_end], &new[prefix .. new_end], (prefix, prefix), &mut common);
+    common.extend((0 .. suffix).map(|k| (old_end + k, new_end + k)));
+    common
+}
+
+/// Collect the indices of the elements of a longest common subsequence,
+/// counted from the start. The old elements are split in half at a point
+/// where the longest subsequences of both halves with the new ones add up to
+/// the longest one of the whole, so that the space stays linear
+/// (Hirschberg's algorithm).
+fn collect_common(
+    old: &[&str],
+    new: &[&str],
+    start: (usize, usize),
+    common: &mut Vec<(usize, usize)>,
+) {
+    if old.len() <= 1 || new.is_empty() {
+        if let Some(j) = new.iter().position(|element| old.contains(element)) {
+            common.push((start.0, start.1 + j));
+        }
+        return;
+    }
+
+    let half = old.len() / 2;
+    let forward = subsequence_lengths(&old[.. half], new);
+    let old_back: Vec<&str> = old[half ..].iter().rev().copied().collect();
+    let new_back: Vec<&str> = new.iter().rev().copied().collect();
+    let backward = subsequence_lengths(&old_back, &new_back);
+    let m = new.len();
+    let split = (0 ..= m).rev()
+        .max_by_key(|&j| forward[j] + backward[m - j])
+        .unwrap_or(0);
+
+    collect_common(&old[.. half], &new[.. split], start, common);
+    collect_common(&old[half ..], &new[split ..], (start.0 + half, start.1 + split), common);
+}
+
+/// The lengths of the longest common subsequences of the old elements with
+/// each prefix of the new ones.
+fn subsequence_lengths(old: &[&str], new: &[&str]) -> Vec<usize> {
+    let mut lengths = vec![0; new.len() + 1];
+    for a in old {
+        let mut diagonal = 0;
+        for (j, b) in new.iter().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = match a == b {
+                true => diagonal + 1,
+                false => above.max(lengths[j]),
+            };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::fixture;
+    use crate::json::Json;
+    use super::*;
+
+    #[test]
+    fn impact() {
+        let old = Program::new(fixture("block-1"));
+        let new = Program::new(fixture("block-2"));
+        let old_cfg = ControlFlowGraph::new(&old);
+        let new_cfg = ControlFlowGraph::new(&new);
+        let new_ddg = DataDependencyGraph::new(&new_cfg);
+        let new_pdg = ProgramDependenceGraph::new(&new_cfg, &new_ddg);
+
+        // Only the initial value of `a` in main changes, which flows into
+        // the comparison.
+        assert!(changed_instructions(&old, &old_cfg, &old, &old_cfg).is_empty());
+        let impact = PatchImpact::compute(&old, &old_cfg, &new, &new_cfg, &new_ddg, &new_pdg);
+        assert_eq!(impact.changed.len(), 1);
+        let (_, _, instruction, _) = new.code.iter()
+            .find(|&&(addr, ..)| addr == impact.changed[0])
+            .unwrap();
+        assert!(instruction.to_string().contains("0x7"));
+        assert!(impact.functions.iter().any(|name| name == "main"));
+        assert!(impact.functions.iter().any(|name| name == "compare"));
+        assert!(impact.outputs.contains(&("compare".to_string(), Port::Result)));
+
+        let mut json = Vec::new();
+        impact.write_json(&mut json).unwrap();
+        let json = Json::parse(&String::from_utf8(json).unwrap()).unwrap();
+        assert_eq!(json.get("slice").and_then(Json::as_u64), Some(impact.slice.len() as u64));
+        assert_eq!(json.get("changed").and_then(Json::as_array).map(<[Json]>::len), Some(1));
+    }
+
+    #[test]
+    fn subsequences() {
+        let old = ["a", "b", "c", "d", "e"];
+        assert_eq!(unmatched(&old, &old), Vec::<usize>::new());
+        assert_eq!(unmatched(&old, &["a", "x", "c", "d", "y", "e"]), vec![1, 4]);
+        assert_eq!(unmatched(&[], &["a", "b"]), vec![0, 1]);
+        assert_eq!(unmatched(&old, &["e"]), Vec::<usize>::new());
+        assert_eq!(unmatched(&["b", "a", "b"], &["a", "b", "a"]), vec![2]);
+
+        // Long functions are matched without a table of all pairs.
+        let numbers: Vec<String> = (0 .. 3000).map(|number| number.to_string()).collect();
+        let old: Vec<&str> = numbers.iter().map(String::as_str).collect();
+        let mut new = old.clone();
+        new[1] = "x";
+        new.remove(2998);
+        assert_eq!(unmatched(&old[1 ..], &new), vec![0, 1]);
+    }
+}
@@ -1,16 +1,103 @@
 // Program Dependence Graph (PDG) generation
 
-use std::collections::{HashMap};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
 use std::io::{self, Write};
+use std::str::FromStr;
+#[cfg(feature = "fs")]
 use std::fs;
+use std::ops::Range;
+use std::sync::Arc;
+use crate::helper::json_string;
 use crate::math::SymCondition;
-use crate::flow::{ControlFlowGraph, DataDependencyGraph, AbstractLocation, DependencyNode};
-use crate::flow::visualize::{write_header, write_edges, write_footer};
+use crate::flow::{ControlFlowGraph, DataDependencyGraph, AbstractLocation, DependencyNode, StackFrames};
+use crate::flow::ControlFlowNode;
+use crate::flow::{MemoryClass, Adjacency, Phase, Cancelled, VisualConfig};
+use crate::flow::visualize::{write_header, write_footer, write_legend, write_instruction, BR};
+use crate::flow::visualize::{class_legend, escape, quote, write_conditions, Conditions};
 
 #[derive(Debug, Clone)]
 pub struct ProgramDependenceGraph {
     pub nodes: Vec<DependenceNode>,
-    pub edges: HashMap<(usize, usize), Vec<PDGEdge>>,
+    pub edges: Adjacency<PDGEdge>,
+    pub frames: Arc<StackFrames>,
+    pub classes: HashMap<usize, MemoryClass>,
+    /// The stable identifier of each node.
+    ids: Vec<NodeId>,
+    /// The index of the node with each identifier.
+    indices: HashMap<NodeId, usize>,
+    /// The control flow nodes of the blocks at each address.
+    blocks: HashMap<u64, Vec<usize>>,
+    /// The data dependency node of each location.
+    locations: HashMap<AbstractLocation, usize>,
+}
+
+/// An identifier of a dependence node that is derived from its address and
+/// location and therefore stays the same across runs and construction orders.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct NodeId(pub u64);
+
+impl NodeId {
+    /// The identifier of a control flow node in its call trace.
+    pub fn of_control(node: &ControlFlowNode) -> NodeId {
+        let mut digest = Digest::new(b'c');
+        digest.word(node.addr);
+        for &(site, target) in &node.trace {
+            digest.word(site);
+            digest.word(target);
+        }
+        NodeId(digest.0)
+    }
+
+    /// The identifier of a data dependency node.
+    pub fn of_location(location: &AbstractLocation) -> NodeId {
+        let mut digest = Digest::new(b'd');
+        digest.word(location.addr);
+        digest.word(location.trace.len() as u64);
+        for &site in &location.trace {
+            digest.word(site);
+        }
+        digest.bytes(location.storage.to_string().as_bytes());
+        NodeId(digest.0)
+    }
+}
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "n{:016x}", self.0)
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NodeId, String> {
+        s.strip_prefix('n')
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .map(NodeId)
+            .ok_or_else(|| format!("invalid node id: {}", s))
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the standard hasher is fixed across releases.
+pub(super) struct Digest(pub u64);
+
+impl Digest {
+    pub(super) fn new(tag: u8) -> Digest {
+        let mut digest = Digest(0xcbf2_9ce4_8422_2325);
+        digest.bytes(&[tag]);
+        digest
+    }
+
+    pub(super) fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub(super) fn word(&mut self, word: u64) {
+        self.bytes(&word.to_le_bytes());
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -19,89 +106,698 @@ pub enum DependenceNode {
     DataDependency(AbstractLocation),
 }
 
+impl DependenceNode {
+    /// The address of the block or of the instruction of the location.
+    pub fn addr(&self) -> u64 {
+        match self {
+            DependenceNode::ControlFlow(addr) => *addr,
+            DependenceNode::DataDependency(location) => location.addr,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PDGEdge {
     pub kind: EdgeKind,
     pub condition: SymCondition,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum EdgeKind {
+    /// Control flow within a function.
     ControlFlow,
+    /// Data flow within a function.
     DataDependency,
+    /// Control flow from a call site into the called function.
+    Call,
+    /// Control flow from a function back to its caller.
+    Return,
+    /// Data flow from a caller into the called function.
+    ParamIn,
+    /// Data flow from a function back to its caller.
+    ParamOut,
+    /// The summarized effect of a call that is not followed into the callee.
+    Summary,
+    /// Data flow through shared memory from one thread into another.
+    InterThread,
+    /// Data flow between threads through atomic or fenced accesses, along
+    /// which the write happens before the reads observing it.
+    Synchronization,
+}
+
+impl EdgeKind {
+    /// The kind of the control flow edge between two nodes of the graph.
+    /// Other transfers than summarized calls, like the entry of a signal
+    /// handler or a `longjmp`, are told apart by the call traces like
+    /// regular control flow.
+    pub fn of_control(cfg: &ControlFlowGraph, edge: (usize, usize)) -> EdgeKind {
+        if cfg.edge_kind(edge).is_summary() {
+            return EdgeKind::Summary;
+        }
+        let (start, end) = (&cfg.nodes[edge.0].trace, &cfg.nodes[edge.1].trace);
+        if end.len() > start.len() && end.starts_with(start) {
+            EdgeKind::Call
+        } else if start.len() > end.len() && start.starts_with(end) {
+            EdgeKind::Return
+        } else {
+            EdgeKind::ControlFlow
+        }
+    }
+
+    /// The kind of the data flow edge between two locations.
+    pub fn of_data(start: &AbstractLocation, end: &AbstractLocation) -> EdgeKind {
+        let (from, to) = (&start.trace, &end.trace);
+        if to.len() > from.len() && to.starts_with(from) {
+            EdgeKind::ParamIn
+        } else if from.len() > to.len() && from.starts_with(to) {
+            EdgeKind::ParamOut
+        } else {
+            EdgeKind::DataDependency
+        }
+    }
+
+    /// All kinds in order.
+    pub const ALL: [EdgeKind; 9] = [
+        EdgeKind::ControlFlow,
+        EdgeKind::DataDependency,
+        EdgeKind::Call,
+        EdgeKind::Return,
+        EdgeKind::ParamIn,
+        EdgeKind::ParamOut,
+        EdgeKind::Summary,
+        EdgeKind::InterThread,
+        EdgeKind::Synchronization,
+    ];
+
+    /// Whether the edge is a control flow edge of any kind.
+    pub fn is_control(self) -> bool {
+        matches!(self, EdgeKind::ControlFlow | EdgeKind::Call
+            | EdgeKind::Return | EdgeKind::Summary)
+    }
+
+    /// Whether the edge crosses a function boundary.
+    pub fn is_interprocedural(self) -> bool {
+        !matches!(self, EdgeKind::ControlFlow | EdgeKind::DataDependency)
+    }
+
+    /// The name of the kind in exports.
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::ControlFlow => "control-flow",
+            EdgeKind::DataDependency => "data-dependency",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+            EdgeKind::ParamIn => "param-in",
+            EdgeKind::ParamOut => "param-out",
+            EdgeKind::Summary => "summary",
+            EdgeKind::InterThread => "inter-thread",
+            EdgeKind::Synchronization => "synchronization",
+        }
+    }
+
+    /// The graphviz attributes with which edges of this kind are drawn.
+    pub fn style(self) -> &'static str {
+        match self {
+            EdgeKind::ControlFlow => "style=solid, color=black",
+            EdgeKind::DataDependency => "style=dashed, color=blue",
+            EdgeKind::Call => "style=bold, color=darkgreen",
+            EdgeKind::Return => "style=bold, color=darkorange",
+            EdgeKind::ParamIn => "style=dotted, color=darkgreen",
+            EdgeKind::ParamOut => "style=dotted, color=darkorange",
+            EdgeKind::Summary => "style=tapered, color=purple",
+            EdgeKind::InterThread => "style=dashed, color=red",
+            EdgeKind::Synchronization => "style=bold, color=red",
+        }
+    }
 }
 
 impl ProgramDependenceGraph {
+    /// Create a program dependence graph from the control flow and data
+    /// dependency graphs.
+    ///
+    /// Panics if the analysis is cancelled through the monitor of the
+    /// configuration of the control flow graph, use `try_new` for cancellable
+    /// analyses.
     pub fn new(cfg: &ControlFlowGraph, ddg: &DataDependencyGraph) -> ProgramDependenceGraph {
+        ProgramDependenceGraph::try_new(cfg, ddg)
+            .expect("program dependence graph: analysis was cancelled")
+    }
+
+    /// Create a program dependence graph like `new` unless the analysis is
+    /// cancelled.
+    pub fn try_new(
+        cfg: &ControlFlowGraph,
+        ddg: &DataDependencyGraph,
+    ) -> Result<ProgramDependenceGraph, Cancelled> {
+        let _phase = crate::trace::phase("pdg");
+        let monitor = &cfg.config.monitor;
+        let total = Some(cfg.nodes.len() + ddg.nodes.len());
+        let name = |function: Option<u64>| {
+            cfg.frames.frames.get(&function?).map(|frame| frame.name.as_str())
+        };
+
         let mut nodes = Vec::with_capacity(cfg.nodes.len() + ddg.nodes.len());
-        let mut edges: HashMap<(usize, usize), Vec<PDGEdge>> = HashMap::new();
+        let mut edges = Vec::new();
         let mut ddg_node_mapping = HashMap::new();
+        let mut classes = HashMap::new();
+        let mut ids = Vec::with_capacity(nodes.capacity());
 
         for (index, node) in cfg.nodes.iter().enumerate() {
+            monitor.check()?;
+            let function = cfg.frames.functions_at(node.addr).first().copied();
+            monitor.report(Phase::ProgramDependence, index, total, name(function));
+
             let pdg_index = nodes.len();
             nodes.push(DependenceNode::ControlFlow(node.addr));
+            ids.push(NodeId::of_control(node));
 
             for &out_index in &cfg.outgoing[index] {
-                edges.entry((pdg_index, out_index))
-                    .or_insert(vec![])
-                    .push(PDGEdge {
-                        kind: EdgeKind::ControlFlow,
-                        condition: cfg.edges[&(index, out_index)].clone(),
-                    });
+                edges.push((pdg_index, out_index, PDGEdge {
+                    kind: EdgeKind::of_control(cfg, (index, out_index)),
+                    condition: cfg.edges[&(index, out_index)].clone(),
+                }));
             }
         }
 
         for (index, node) in ddg.nodes.iter().enumerate() {
+            monitor.check()?;
             if let DependencyNode::Location(location) = node {
+                let function = cfg.frames.function_of(location);
+                let done = cfg.nodes.len() + index;
+                monitor.report(Phase::ProgramDependence, done, total, name(function));
+
                 let pdg_index = nodes.len();
                 nodes.push(DependenceNode::DataDependency(location.clone()));
+                ids.push(NodeId::of_location(location));
                 ddg_node_mapping.insert(index, pdg_index);
+                if let Some(class) = ddg.class(index) {
+                    classes.insert(pdg_index, class);
+                }
             }
         }
 
-        for &(start, end) in ddg.edges.keys() {
+        let mut ddg_edges: Vec<(usize, usize)> = ddg.edges.keys().copied().collect();
+        ddg_edges.sort_unstable();
+        for (start, end) in ddg_edges {
             if let (Some(&pdg_start), Some(&pdg_end)) = (ddg_node_mapping.get(&start), ddg_node_mapping.get(&end)) {
-                edges.entry((pdg_start, pdg_end))
-                    .or_insert(vec![])
-                    .push(PDGEdge {
-                        kind: EdgeKind::DataDependency,
-                        condition: ddg.edges[&(start, end)].0.clone(),
-                    });
+                edges.push((pdg_start, pdg_end, PDGEdge {
+                    kind: ddg.edge_kind((start, end)),
+                    condition: ddg.edges[&(start, end)].0.clone(),
+                }));
             }
         }
 
-        ProgramDependenceGraph { nodes, edges }
+        Ok(ProgramDependenceGraph::assemble(nodes, ids, edges, cfg.frames.clone(), classes))
     }
 
-    pub fn visualize<W: Write>(&self, target: W, title: &str) -> io::Result<()> {
-        let mut f = target;
+    /// Build a graph from nodes and (start, end, edge) triples, deriving the
+    /// identifiers of control flow nodes without their call traces.
+    pub fn from_parts(
+        nodes: Vec<DependenceNode>,
+        edges: Vec<(usize, usize, PDGEdge)>,
+        frames: Arc<StackFrames>,
+        classes: HashMap<usize, MemoryClass>,
+    ) -> ProgramDependenceGraph {
+        let ids = nodes.iter()
+            .map(|node| match node {
+                DependenceNode::ControlFlow(addr) => {
+                    NodeId::of_control(&ControlFlowNode { addr: *addr, trace: Vec::new() })
+                }
+                DependenceNode::DataDependency(location) => NodeId::of_location(location),
+            })
+            .collect();
+        ProgramDependenceGraph::assemble(nodes, ids, edges, frames, classes)
+    }
+
+    /// Build the graph from its parts and index the nodes.
+    fn assemble(
+        nodes: Vec<DependenceNode>,
+        ids: Vec<NodeId>,
+        edges: Vec<(usize, usize, PDGEdge)>,
+        frames: Arc<StackFrames>,
+        classes: HashMap<usize, MemoryClass>,
+    ) -> ProgramDependenceGraph {
+        let indices = ids.iter().enumerate().map(|(index, &id)| (id, index)).collect();
+        let mut blocks: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut locations = HashMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            match node {
+                DependenceNode::ControlFlow(addr) => blocks.entry(*addr).or_default().push(index),
+                DependenceNode::DataDependency(location) => {
+                    locations.insert(location.clone(), index);
+                }
+            }
+        }
 
-        write_header(&mut f, &format!("Program Dependence Graph for {}", title), 40)?;
+        ProgramDependenceGraph {
+            edges: Adjacency::new(nodes.len(), edges),
+            nodes,
+            frames,
+            classes,
+            ids,
+            indices,
+            blocks,
+            locations,
+        }
+    }
+
+    /// The first control flow node of the block at the address.
+    pub fn node_at(&self, addr: u64) -> Option<usize> {
+        self.nodes_at(addr).first().copied()
+    }
 
+    /// The control flow nodes of the block at the address in all call traces.
+    pub fn nodes_at(&self, addr: u64) -> &[usize] {
+        self.blocks.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// The data dependency node of the location.
+    pub fn node_for(&self, location: &AbstractLocation) -> Option<usize> {
+        self.locations.get(location).copied()
+    }
+
+    /// The distinct nodes the node with the index has edges to.
+    pub fn successors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        let mut last = None;
+        self.edges.successors(node)
+            .map(|(end, _)| end)
+            .filter(move |&end| last.replace(end) != Some(end))
+    }
+
+    /// The distinct nodes which have edges to the node with the index.
+    pub fn predecessors(&self, node: usize) -> impl Iterator<Item=usize> + '_ {
+        let mut last = None;
+        self.edges.predecessors(node)
+            .map(|(start, _)| start)
+            .filter(move |&start| last.replace(start) != Some(start))
+    }
+
+    /// The edges leaving the node as (end, edge).
+    pub fn edges_from(&self, node: usize) -> impl Iterator<Item=(usize, &PDGEdge)> {
+        self.edges.successors(node)
+    }
+
+    /// The edges entering the node as (start, edge).
+    pub fn edges_to(&self, node: usize) -> impl Iterator<Item=(usize, &PDGEdge)> {
+        self.edges.predecessors(node)
+    }
+
+    /// Whether the sink depends on the source through a chain of edges and
+    /// under which condition, which is the disjunction of the conjoined edge
+    /// conditions of all chains. A node depends on itself unconditionally.
+    ///
+    /// Chains are tracked by the sets of distinct conditions along them and a
+    /// chain is dropped if another one needs only a subset of its conditions.
+    /// Going around a cycle never adds a new condition set, so that the query
+    /// terminates without a solver.
+    pub fn depends_on(&self, sink: NodeId, source: NodeId) -> Option<SymCondition> {
+        let (sink, source) = (self.index(sink)?, self.index(source)?);
+
+        // Only nodes from which the sink can be reached are relevant.
+        let mut relevant = vec![false; self.nodes.len()];
+        let mut stack = vec![sink];
+        relevant[sink] = true;
+        while let Some(node) = stack.pop() {
+            for pred in self.predecessors(node) {
+                if !relevant[pred] {
+                    relevant[pred] = true;
+                    stack.push(pred);
+                }
+            }
+        }
+        if !relevant[source] {
+            return None;
+        }
+
+        let mut atoms: Vec<&SymCondition> = Vec::new();
+        let mut reaching: Vec<Vec<BTreeSet<usize>>> = vec![Vec::new(); self.nodes.len()];
+        reaching[source].push(BTreeSet::new());
+        let mut worklist = vec![(source, BTreeSet::new())];
+
+        while let Some((node, set)) = worklist.pop() {
+            if node == sink {
+                continue;
+            }
+            for (succ, edge) in self.edges_from(node) {
+                if !relevant[succ] || edge.condition == SymCondition::FALSE {
+                    continue;
+                }
+                let mut extended = set.clone();
+                if edge.condition != SymCondition::TRUE {
+                    let atom = match atoms.iter().position(|&atom| *atom == edge.condition) {
+                        Some(atom) => atom,
+                        None => {
+                            atoms.push(&edge.condition);
+                            atoms.len() - 1
+                        }
+                    };
+                    extended.insert(atom);
+                }
+
+                let sets = &mut reaching[succ];
+                if sets.iter().any(|existing| existing.is_subset(&extended)) {
+                    continue;
+                }
+                sets.retain(|existing| !extended.is_subset(existing));
+                sets.push(extended.clone());
+                worklist.push((succ, extended));
+            }
+        }
+
+        let mut sets = std::mem::take(&mut reaching[sink]);
+        if sets.is_empty() {
+            return None;
+        }
+        sets.sort();
+        Some(sets.into_iter()
+            .map(|set| set.into_iter()
+                .map(|atom| atoms[atom].clone())
+                .fold(SymCondition::TRUE, SymCondition::and))
+            .fold(SymCondition::FALSE, SymCondition::or))
+    }
+
+    /// The edges keyed by (start, end) node as they were stored before the
+    /// compact representation.
+    pub fn edge_map(&self) -> HashMap<(usize, usize), Vec<PDGEdge>> {
+        self.edges.to_map()
+    }
+
+    /// A new graph with only the nodes matching the predicate and the edges
+    /// between them. Nodes are renumbered, but keep their identifiers.
+    pub fn subgraph<F>(&self, keep: F) -> ProgramDependenceGraph
+    where F: Fn(&DependenceNode) -> bool {
+        let mut mapping = vec![None; self.nodes.len()];
+        let mut nodes = Vec::new();
+        let mut ids = Vec::new();
+        let mut classes = HashMap::new();
         for (index, node) in self.nodes.iter().enumerate() {
-            match node {
-                DependenceNode::ControlFlow(addr) => {
-                    writeln!(f, "b{} [label=\"ControlFlow: 0x{:x}\", shape=box]", index, addr)?;
+            if keep(node) {
+                mapping[index] = Some(nodes.len());
+                if let Some(class) = self.class(index) {
+                    classes.insert(nodes.len(), class);
                 }
+                nodes.push(node.clone());
+                ids.push(self.ids[index]);
+            }
+        }
+
+        let edges = self.edges.iter()
+            .filter_map(|((start, end), edge)| match (mapping[start], mapping[end]) {
+                (Some(start), Some(end)) => Some((start, end, edge.clone())),
+                _ => None,
+            })
+            .collect();
+
+        ProgramDependenceGraph::assemble(nodes, ids, edges, self.frames.clone(), classes)
+    }
+
+    /// The subgraph of the blocks and locations of the function at the address.
+    pub fn function_subgraph(&self, function: u64) -> ProgramDependenceGraph {
+        self.subgraph(|node| match node {
+            DependenceNode::ControlFlow(addr) => {
+                self.frames.functions_at(*addr).contains(&function)
+            }
+            DependenceNode::DataDependency(location) => {
+                self.frames.function_of(location) == Some(function)
+            }
+        })
+    }
+
+    /// The subgraph of the blocks and locations with addresses in the range.
+    pub fn address_subgraph(&self, range: Range<u64>) -> ProgramDependenceGraph {
+        self.subgraph(|node| range.contains(&node.addr()))
+    }
+
+    /// A new graph without the edges that are implied by other edges.
+    ///
+    /// An edge is dropped if it duplicates another one or if its nodes are
+    /// also connected by a chain of at least two remaining edges of the same
+    /// class (control or data) whose conditions hold whenever its own holds,
+    /// that is, are true or the same. The dependencies through the edge are
+    /// then already covered by the chain, so that the slices and the
+    /// conditions of `depends_on` stay the same.
+    pub fn reduce(&self) -> ProgramDependenceGraph {
+        let edges: Vec<(usize, usize, &PDGEdge)> = self.edges.iter()
+            .map(|((start, end), edge)| (start, end, edge))
+            .collect();
+
+        let mut outgoing = vec![Vec::new(); self.nodes.len()];
+        for (index, &(start, ..)) in edges.iter().enumerate() {
+            outgoing[start].push(index);
+        }
+
+        let mut removed = vec![false; edges.len()];
+        for index in 0 .. edges.len() {
+            let (start, end, edge) = edges[index];
+            let duplicate = outgoing[start].iter().any(|&other| {
+                let (_, other_end, other_edge) = edges[other];
+                other < index && !removed[other] && other_end == end
+                    && other_edge.kind == edge.kind && other_edge.condition == edge.condition
+            });
+
+            // Search a chain of implied edges that does not start with a
+            // direct edge to the end.
+            let implies = |other: &PDGEdge| {
+                other.kind.is_control() == edge.kind.is_control()
+                    && (edge.condition == SymCondition::FALSE
+                        || other.condition == SymCondition::TRUE
+                        || other.condition == edge.condition)
+            };
+            let mut visited = vec![false; self.nodes.len()];
+            let mut stack = vec![start];
+            let mut chained = false;
+            visited[start] = true;
+            while let Some(node) = stack.pop() {
+                for &other in &outgoing[node] {
+                    let (_, next, other_edge) = edges[other];
+                    if other == index || removed[other] || !implies(other_edge) {
+                        continue;
+                    }
+                    if next == end && node != start {
+                        chained = true;
+                    } else if next != end && !visited[next] {
+                        visited[next] = true;
+                        stack.push(next);
+                    }
+                }
+                if chained {
+                    break;
+                }
+            }
+
+            removed[index] = duplicate || chained;
+        }
+
+        let edges = edges.into_iter()
+            .zip(removed)
+            .filter(|(_, removed)| !removed)
+            .map(|((start, end, edge), _)| (start, end, edge.clone()))
+            .collect();
+
+        ProgramDependenceGraph::assemble(
+            self.nodes.clone(),
+            self.ids.clone(),
+            edges,
+            self.frames.clone(),
+            self.classes.clone(),
+        )
+    }
+
+    /// The nodes that the block and the locations at the address depend on
+    /// through chains of edges, including themselves.
+    pub fn backward_slice(&self, addr: u64) -> BTreeSet<usize> {
+        self.slice(addr, |node| self.predecessors(node).collect())
+    }
+
+    /// The nodes that depend on the block and the locations at the address
+    /// through chains of edges, including themselves.
+    pub fn forward_slice(&self, addr: u64) -> BTreeSet<usize> {
+        self.slice(addr, |node| self.successors(node).collect())
+    }
+
+    /// The nodes reachable from the ones at the address through the neighbours.
+    fn slice<F>(&self, addr: u64, neighbours: F) -> BTreeSet<usize>
+    where F: Fn(usize) -> Vec<usize> {
+        let mut slice = BTreeSet::new();
+        let mut pending: Vec<usize> = (0 .. self.nodes.len())
+            .filter(|&index| self.nodes[index].addr() == addr)
+            .collect();
+        while let Some(node) = pending.pop() {
+            if slice.insert(node) {
+                pending.extend(neighbours(node));
+            }
+        }
+        slice
+    }
+
+    /// The stable identifier of the node with the index.
+    pub fn id(&self, index: usize) -> NodeId {
+        self.ids[index]
+    }
+
+    /// The index of the node with the identifier in this graph.
+    pub fn index(&self, id: NodeId) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+
+    /// The node with the identifier.
+    pub fn node(&self, id: NodeId) -> Option<&DependenceNode> {
+        self.index(id).map(|index| &self.nodes[index])
+    }
+
+    /// The edges between two nodes given by their identifiers.
+    pub fn edges_between(&self, start: NodeId, end: NodeId) -> &[PDGEdge] {
+        match (self.index(start), self.index(end)) {
+            (Some(start), Some(end)) => self.edges.between(start, end),
+            _ => &[],
+        }
+    }
+
+    /// The memory class of the node with the index if it is a data dependency.
+    pub fn class(&self, index: usize) -> Option<MemoryClass> {
+        self.classes.get(&index).copied()
+    }
+
+    pub fn visualize<W: Write>(&self, target: W, title: &str) -> io::Result<()> {
+        self.visualize_with(target, title, None, &VisualConfig::default())
+    }
+
+    /// Visualize the graph like `visualize` with the layout and styles of the
+    /// configuration. With the control flow graph, the control flow nodes
+    /// show the disassembled instructions of their blocks as described by
+    /// the listing of the configuration.
+    pub fn visualize_with<W: Write>(
+        &self,
+        target: W,
+        title: &str,
+        cfg: Option<&ControlFlowGraph>,
+        config: &VisualConfig,
+    ) -> io::Result<()> {
+        let _phase = crate::trace::phase("render");
+        let mut f = target;
+
+        write_header(&mut f, &format!("Program Dependence Graph for {}", title), 40, config)?;
+
+        // Nodes and edges are named and sorted by their identifiers, so that
+        // the output does not depend on the construction order.
+        let mut order: Vec<usize> = (0 .. self.nodes.len()).collect();
+        order.sort_by_key(|&index| self.ids[index]);
+        let literals = cfg.map(ControlFlowGraph::literals).unwrap_or_default();
+        let annotation = |addr: u64| literals.get(&addr).map(|(_, literal)| {
+            format!(" ({})", config.label(literal.annotation()))
+        });
+        for index in order {
+            let id = self.ids[index];
+            match &self.nodes[index] {
+                DependenceNode::ControlFlow(addr) => match cfg {
+                    Some(cfg) => {
+                        write!(f, "{} [label=<<b>ControlFlow: 0x{:x}</b>{}", id, addr, BR)?;
+                        let code = cfg.blocks.get(addr).map_or(&[][..], |block| &block.code);
+                        for (addr, _, instruction, _) in code {
+                            let note = annotation(*addr).map_or(String::new(), |n| escape(&n));
+                            write_instruction(&mut f, *addr, instruction, &config.listing, &note)?;
+                        }
+                        writeln!(f, ">, shape={}]", config.block_shape())?;
+                    }
+                    None => {
+                        writeln!(f, "{} [label=\"ControlFlow: 0x{:x}\", shape={}]",
+                                 id, addr, config.block_shape())?;
+                    }
+                },
                 DependenceNode::DataDependency(location) => {
-                    writeln!(f, "b{} [label=\"DataDependency: {}\", shape=ellipse]", index, location)?;
+                    let note = annotation(location.addr).unwrap_or_default();
+                    let location = self.frames.describe(location);
+                    let color = self.class(index).unwrap_or(MemoryClass::Unknown).color();
+                    writeln!(f, "{} [label=\"DataDependency: {}{}\", shape={}, style=filled, \
+                                 fillcolor=\"{}\"]",
+                             id, location, quote(&note), config.location_shape("ellipse"), color)?;
                 }
             }
         }
 
-        write_edges(&mut f, &self.edges, |f, ((_, _), edge_list)| {
+        let conditions = Conditions::new(config);
+        let mut edges: Vec<_> = self.edges.pairs()
+            .map(|(start, end)| ((self.ids[start], self.ids[end]), self.edges.between(start, end)))
+            .collect();
+        edges.sort_by_key(|&(key, _)| key);
+        for ((start, end), edge_list) in edges {
+            write!(f, "{} -> {} [", start, end)?;
+            let guards: Vec<String> = edge_list.iter()
+                .filter(|edge| edge.condition != SymCondition::TRUE)
+                .map(|edge| conditions.full(&edge.condition))
+                .collect();
+            if config.tooltips && !guards.is_empty() {
+                writeln!(f, "tooltip=\"{}\"", quote(&guards.join("; ")))?;
+            }
             for edge in edge_list {
-                match edge.kind {
-                    EdgeKind::ControlFlow => writeln!(f, "style=solid, color=black")?,
-                    EdgeKind::DataDependency => writeln!(f, "style=dashed, color=blue")?,
-                }
+                writeln!(f, "{}", config.edge_style(edge.kind))?;
             }
-            Ok(())
-        })?;
+            writeln!(f, "]")?;
+        }
 
-        write_footer(&mut f)
+        let mut nodes = vec![("block".to_string(), format!("shape={}", config.block_shape()))];
+        nodes.extend(class_legend(config.location_shape("ellipse")));
+        let edges: Vec<(String, String)> = EdgeKind::ALL.iter()
+            .map(|&kind| (kind.name().to_string(), config.edge_style(kind).to_string()))
+            .collect();
+        write_legend(&mut f, config, &nodes, &edges)?;
+
+        write_footer(&mut f, config)
     }
 
+    /// Write the full conditions of the edges as JSON, naming the nodes by
+    /// their identifiers like the visualization does.
+    pub fn write_conditions<W: Write>(&self, target: W, config: &VisualConfig) -> io::Result<()> {
+        let mut edges: Vec<_> = self.edges.iter()
+            .filter(|(_, edge)| edge.condition != SymCondition::TRUE)
+            .map(|((start, end), edge)| (self.ids[start], self.ids[end], &edge.condition))
+            .collect();
+        edges.sort_by_key(|&(start, end, _)| (start, end));
+        let edges: Vec<_> = edges.into_iter()
+            .map(|(start, end, condition)| (start.to_string(), end.to_string(), condition))
+            .collect();
+        write_conditions(target, &Conditions::new(config), &edges)
+    }
+
+    /// Write the nodes and edges as JSON. Nodes have their identifier, kind,
+    /// address and for data dependencies the described location; edges
+    /// refer to the node indices and have their kind and condition.
+    pub fn write_json<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"nodes\": [")?;
+        for index in 0 .. self.nodes.len() {
+            let separator = if index + 1 < self.nodes.len() { "," } else { "" };
+            writeln!(f, "    {}{}", self.node_json(index), separator)?;
+        }
+        writeln!(f, "  ],")?;
+
+        let edges: Vec<_> = self.edges.iter().collect();
+        writeln!(f, "  \"edges\": [")?;
+        for (index, ((start, end), edge)) in edges.iter().enumerate() {
+            let separator = if index + 1 < edges.len() { "," } else { "" };
+            writeln!(f, "    {{\"start\": {}, \"end\": {}, \"kind\": \"{}\", \
+                         \"condition\": {}}}{}",
+                     start, end, edge.kind.name(), json_string(&edge.condition.to_string()),
+                     separator)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "}}")
+    }
+
+    /// The node with the index as a JSON object like in `write_json`.
+    pub(crate) fn node_json(&self, index: usize) -> String {
+        let node = &self.nodes[index];
+        let (kind, location) = match node {
+            DependenceNode::ControlFlow(_) => ("control", "null".to_string()),
+            DependenceNode::DataDependency(location) => {
+                ("data", json_string(&self.frames.describe(location).to_string()))
+            },
+        };
+        format!("{{\"id\": \"{}\", \"kind\": \"{}\", \"addr\": {}, \"location\": {}}}",
+                self.ids[index], kind, node.addr(), location)
+    }
+
+    #[cfg(feature = "fs")]
     pub fn save_as_pdf(&self, filename: &str, title: &str) -> io::Result<()> {
         let path = format!("target/out/pdg/{}.dot", filename);
 
@@ -117,6 +813,7 @@ impl ProgramDependenceGraph {
     }
 }
 
+#[cfg(feature = "fs")]
 pub fn generate_and_save_pdg(cfg: &ControlFlowGraph, ddg: &DataDependencyGraph, filename: &str) {
     let pdg = ProgramDependenceGraph::new(cfg, ddg);
     pdg.save_as_pdf(filename, "Program Dependence Graph").expect("Failed to save PDG");
@@ -129,7 +826,7 @@ mod tests {
     use crate::flow::{ControlFlowGraph, DataDependencyGraph};
 
     fn run_test_pdg(filename: &str) {
-        let path = format!("target/bin/{}", filename);
+        let path = crate::fixtures::fixture(filename);
 
         let program = Program::new(path);
 
@@ -142,6 +839,190 @@ mod tests {
         pdg.save_as_pdf(filename, "Program Dependence Graph").unwrap();
     }
 
+    #[test]
+    fn edge_kinds() {
+        use crate::flow::StorageLocation;
+        use crate::x86_64::Register;
+
+        let edi = StorageLocation::Direct(Register::EDI);
+        let location = |trace| AbstractLocation::new(0, trace, edi);
+        let kind = |from, to| EdgeKind::of_data(&location(from), &location(to));
+        assert_eq!(kind(vec![], vec![0x10]), EdgeKind::ParamIn);
+        assert_eq!(kind(vec![0x10, 0x20], vec![0x10]), EdgeKind::ParamOut);
+        assert_eq!(kind(vec![0x10], vec![0x10]), EdgeKind::DataDependency);
+        assert_eq!(kind(vec![0x10], vec![0x20]), EdgeKind::DataDependency);
+        assert!(EdgeKind::Summary.is_control() && EdgeKind::Summary.is_interprocedural());
+        assert!(!EdgeKind::ParamIn.is_control() && !EdgeKind::DataDependency.is_interprocedural());
+
+        // A signal handler is entered like a call and a `longjmp` leaves
+        // frames like a return, while only summarized calls are summaries.
+        use crate::flow::ControlKind;
+        let node = |addr, trace| ControlFlowNode { addr, trace };
+        let nodes = vec![node(0, vec![]), node(1, vec![(0, 1)]), node(2, vec![]), node(3, vec![])];
+        let edges = [(0, 1), (1, 0), (0, 2), (2, 3), (3, 0)].iter()
+            .map(|&edge| (edge, SymCondition::TRUE))
+            .collect();
+        let mut cfg = ControlFlowGraph::from_parts(nodes, HashMap::new(), edges, vec![0]);
+        let signal = ControlKind::Signal { site: 0, resume: 0, signal: 10, handler: 1 };
+        cfg.kinds.insert((0, 1), signal);
+        cfg.kinds.insert((1, 0), ControlKind::LongJump { function: 0x10, depth: 0 });
+        cfg.kinds.insert((0, 2), ControlKind::Exception);
+        cfg.kinds.insert((2, 3), ControlKind::Finalize { site: 0 });
+        cfg.kinds.insert((3, 0), ControlKind::SetJump { function: 0x20 });
+        let kinds: Vec<_> = [(0, 1), (1, 0), (0, 2), (2, 3), (3, 0)].iter()
+            .map(|&edge| EdgeKind::of_control(&cfg, edge))
+            .collect();
+        assert_eq!(kinds, vec![
+            EdgeKind::Call, EdgeKind::Return, EdgeKind::ControlFlow,
+            EdgeKind::ControlFlow, EdgeKind::Summary,
+        ]);
+    }
+
+    #[test]
+    fn node_ids() {
+        use crate::flow::StorageLocation;
+        use crate::x86_64::Register;
+
+        let node = ControlFlowNode { addr: 0x1000, trace: vec![(0x40, 0x1000)] };
+        let id = NodeId::of_control(&node);
+        assert_eq!(id, NodeId::of_control(&node.clone()));
+        assert_ne!(id, NodeId::of_control(&ControlFlowNode { addr: 0x1000, trace: vec![] }));
+        assert_eq!(id.to_string().parse::<NodeId>(), Ok(id));
+        assert!("x12".parse::<NodeId>().is_err());
+
+        let location = |reg| AbstractLocation::new(0x1000, vec![], StorageLocation::Direct(reg));
+        let eax = NodeId::of_location(&location(Register::EAX));
+        assert_ne!(eax, NodeId::of_location(&location(Register::ECX)));
+        assert_ne!(eax, NodeId::of_control(&ControlFlowNode { addr: 0x1000, trace: vec![] }));
+    }
+
+    #[test]
+    fn subgraph() {
+        use crate::flow::StorageLocation;
+        use crate::x86_64::Register;
+
+        let eax = StorageLocation::Direct(Register::EAX);
+        let location = |addr| AbstractLocation::new(addr, vec![], eax);
+        let nodes = vec![
+            DependenceNode::ControlFlow(0x1000),
+            DependenceNode::DataDependency(location(0x1000)),
+            DependenceNode::DataDependency(location(0x2000)),
+            DependenceNode::DataDependency(location(0x1004)),
+        ];
+        let ids: Vec<NodeId> = (0 .. 4).map(|index| NodeId(index as u64)).collect();
+        let edge = |kind| PDGEdge { kind, condition: SymCondition::TRUE };
+        let edges = vec![
+            (1, 2, edge(EdgeKind::DataDependency)),
+            (2, 3, edge(EdgeKind::DataDependency)),
+            (1, 3, edge(EdgeKind::DataDependency)),
+            (0, 0, edge(EdgeKind::ControlFlow)),
+        ];
+        let classes = vec![(3, MemoryClass::Register)].into_iter().collect();
+        let frames = Arc::new(StackFrames::default());
+        let pdg = ProgramDependenceGraph::assemble(nodes, ids, edges, frames, classes);
+        assert_eq!(pdg.node_at(0x1000), Some(0));
+        assert_eq!(pdg.nodes_at(0x2000), &[] as &[usize]);
+        assert_eq!(pdg.node_for(&location(0x1004)), Some(3));
+        assert_eq!(pdg.node_for(&location(0x1008)), None);
+
+        let sub = pdg.address_subgraph(0x1000 .. 0x2000);
+        assert_eq!(sub.nodes.len(), 3);
+        assert_eq!(sub.edges.len(), 2);
+        assert_eq!(sub.index(NodeId(3)), Some(2));
+        assert_eq!(sub.class(2), Some(MemoryClass::Register));
+        assert_eq!(sub.node_for(&location(0x1004)), Some(2));
+        assert_eq!(sub.edges_between(NodeId(1), NodeId(3)).len(), 1);
+        assert!(sub.node(NodeId(2)).is_none());
+        assert!(pdg.function_subgraph(0x1000).nodes.is_empty());
+    }
+
+    #[test]
+    fn depends_on() {
+        use crate::flow::StorageLocation;
+        use crate::math::{SymExpr, Symbol, DataType};
+        use crate::x86_64::Register;
+
+        // 0 -> 1 -> 3 under a, 0 -> 2 -> 3 under b and a cycle 3 -> 1 under c.
+        let eax = StorageLocation::Direct(Register::EAX);
+        let nodes: Vec<_> = (0 .. 5)
+            .map(|addr| DependenceNode::DataDependency(AbstractLocation::new(addr, vec![], eax)))
+            .collect();
+        let ids: Vec<NodeId> = (0 .. 5).map(|index| NodeId(index as u64)).collect();
+        let symbol = |index| SymExpr::Sym(Symbol(DataType::N8, "stdin", index));
+        let cond = |index| SymCondition::Equal(Box::new(symbol(index)), Box::new(symbol(9)));
+        let edge = |condition| PDGEdge { kind: EdgeKind::DataDependency, condition };
+        let edges = vec![
+            (0, 1, edge(cond(0))),
+            (1, 3, edge(SymCondition::TRUE)),
+            (0, 2, edge(cond(1))),
+            (2, 3, edge(SymCondition::TRUE)),
+            (3, 1, edge(cond(2))),
+        ];
+        let frames = Arc::new(StackFrames::default());
+        let pdg = ProgramDependenceGraph::assemble(nodes, ids, edges, frames, HashMap::new());
+
+        let id = |index| NodeId(index);
+        assert_eq!(pdg.depends_on(id(3), id(0)), Some(cond(0).or(cond(1))));
+        assert_eq!(pdg.depends_on(id(1), id(0)), Some(cond(0).or(cond(1).and(cond(2)))));
+        assert_eq!(pdg.depends_on(id(3), id(3)), Some(SymCondition::TRUE));
+        assert_eq!(pdg.depends_on(id(0), id(3)), None);
+        assert_eq!(pdg.depends_on(id(4), id(0)), None);
+        assert_eq!(pdg.depends_on(id(9), id(0)), None);
+    }
+
+    #[test]
+    fn reduce() {
+        use crate::flow::StorageLocation;
+        use crate::math::{SymExpr, Symbol, DataType};
+        use crate::x86_64::Register;
+
+        let eax = StorageLocation::Direct(Register::EAX);
+        let nodes: Vec<_> = (0 .. 5)
+            .map(|addr| DependenceNode::DataDependency(AbstractLocation::new(addr, vec![], eax)))
+            .collect();
+        let symbol = |index| SymExpr::Sym(Symbol(DataType::N8, "stdin", index));
+        let cond = |index| SymCondition::Equal(Box::new(symbol(index)), Box::new(symbol(9)));
+        let edge = |kind, condition| PDGEdge { kind, condition };
+        let data = |condition| edge(EdgeKind::DataDependency, condition);
+        let edges = vec![
+            (0, 1, data(SymCondition::TRUE)),
+            (1, 2, data(SymCondition::TRUE)),
+            (1, 2, data(SymCondition::TRUE)),
+            (0, 2, data(SymCondition::TRUE)),
+            (0, 2, data(cond(0))),
+            (0, 2, edge(EdgeKind::ControlFlow, SymCondition::TRUE)),
+            (2, 3, data(cond(0))),
+            (3, 4, data(cond(1))),
+            (2, 4, data(cond(0))),
+        ];
+        let frames = Arc::new(StackFrames::default());
+        let pdg = ProgramDependenceGraph::from_parts(nodes, edges, frames, HashMap::new());
+
+        // The duplicate and both data edges from 0 to 2 are implied, the
+        // control edge and the edge from 2 to 4 under a weaker chain are not.
+        let reduced = pdg.reduce();
+        assert_eq!(reduced.edges.len(), 6);
+        assert_eq!(reduced.edges.between(0, 2).len(), 1);
+        assert_eq!(reduced.edges.between(1, 2).len(), 1);
+        assert_eq!(reduced.edges.between(2, 4).len(), 1);
+        for (sink, source) in (0 .. 5).flat_map(|sink| (0 .. 5).map(move |source| (sink, source))) {
+            let (sink, source) = (pdg.id(sink), pdg.id(source));
+            assert_eq!(reduced.depends_on(sink, source), pdg.depends_on(sink, source));
+        }
+
+        // The slices of a real program are preserved.
+        let program = Program::new(crate::fixtures::fixture("paths"));
+        let cfg = ControlFlowGraph::new(&program);
+        let ddg = DataDependencyGraph::new(&cfg);
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+        let reduced = pdg.reduce();
+        assert!(reduced.edges.len() <= pdg.edges.len());
+        for node in &pdg.nodes {
+            assert_eq!(reduced.backward_slice(node.addr()), pdg.backward_slice(node.addr()));
+            assert_eq!(reduced.forward_slice(node.addr()), pdg.forward_slice(node.addr()));
+        }
+    }
+
     #[test]
     fn pdg_example() {
         run_test_pdg("block-1");
@@ -151,6 +1032,30 @@ mod tests {
         run_test_pdg("overwrite");
         run_test_pdg("min");
         run_test_pdg("custom");
-        run_test_pdg("checking");
+        run_test_pdg("checks");
+    }
+
+    #[test]
+    fn deterministic() {
+        use crate::flow::VisualizationStyle;
+
+        // Two analyses of the same program render the same bytes.
+        let render = |filename: &str| {
+            let program = Program::new(crate::fixtures::fixture(filename));
+            let cfg = ControlFlowGraph::new(&program);
+            let ddg = DataDependencyGraph::new(&cfg);
+            let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+
+            let mut output = Vec::new();
+            let style = VisualizationStyle::Instructions;
+            cfg.visualize(&mut output, &program, filename, style).unwrap();
+            ddg.visualize(&mut output, filename).unwrap();
+            pdg.visualize(&mut output, filename).unwrap();
+            (ddg.nodes, pdg.nodes, output)
+        };
+
+        for filename in &["bufs", "paths"] {
+            assert_eq!(render(filename), render(filename));
+        }
     }
 }
@@ -0,0 +1,234 @@
+//! Execution profiles weighting the edges of the control flow graph.
+//!
+//! A profile counts how often branches were taken in real runs of the
+//! program, like the last branch records sampled by `perf record -b`. The
+//! output of `perf script -F brstack` lists the records of a sample as
+//! entries `from/to/flags/...`, most recent first. Between two consecutive
+//! records the code ran straight from the target of the older branch to the
+//! source of the newer one, so that the conditional branches in between fell
+//! through. Profiles can also list one branch per line as `from to count`.
+//!
+//! Addresses are the ones in the binary, so profiles of position-independent
+//! executables have to be relocated to the image base of the binary first.
+//!
+//! The weight of an edge is how often its branch was taken or, for edges to
+//! the next block, fell through. Exploration prefers the blocks that ran most
+//! often and visualizations draw edges with a width growing with their weight.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use super::ControlFlowGraph;
+
+
+/// Execution counts of the branches and straight-line code of a program.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Profile {
+    /// How often the branch at the first address went to the second one.
+    pub branches: HashMap<(u64, u64), u64>,
+    /// How often the code from the first address up to the instruction at
+    /// the second one ran without branching.
+    pub ranges: HashMap<(u64, u64), u64>,
+}
+
+impl Profile {
+    /// Parse a profile from the output of `perf script -F brstack` or from
+    /// lines of `from to count`. Empty lines and lines starting with `#` are
+    /// skipped.
+    pub fn parse(text: &str) -> Result<Profile, ProfileError> {
+        let mut profile = Profile::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: &str| {
+                ProfileError::new(format!("{} in line {}", message, index + 1))
+            };
+
+            if line.contains('/') {
+                let records = line.split_whitespace()
+                    .filter(|token| token.contains('/'))
+                    .map(|token| {
+                        let mut fields = token.split('/');
+                        let from = fields.next().and_then(number);
+                        let to = fields.next().and_then(number);
+                        from.zip(to).ok_or_else(|| invalid("invalid branch record"))
+                    })
+                    .collect::<Result<Vec<(u64, u64)>, _>>()?;
+
+                for &branch in &records {
+                    *profile.branches.entry(branch).or_default() += 1;
+                }
+                for pair in records.windows(2) {
+                    let (start, end) = (pair[1].1, pair[0].0);
+                    if start <= end {
+                        *profile.ranges.entry((start, end)).or_default() += 1;
+                    }
+                }
+            } else {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                match fields[..] {
+                    [from, to, count] => {
+                        let branch = number(from).zip(number(to))
+                            .ok_or_else(|| invalid("invalid address"))?;
+                        let count = count.parse::<u64>().map_err(|_| invalid("invalid count"))?;
+                        *profile.branches.entry(branch).or_default() += count;
+                    },
+                    _ => return Err(invalid("expected `from to count`")),
+                }
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Read a profile from a file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Profile, ProfileError> {
+        let text = fs::read_to_string(path).map_err(|err| ProfileError::new(err.to_string()))?;
+        Profile::parse(&text)
+    }
+
+    /// Whether the profile has no counts at all.
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty() && self.ranges.is_empty()
+    }
+
+    /// How often the branch at the address went to the target.
+    pub fn taken(&self, from: u64, to: u64) -> u64 {
+        self.branches.get(&(from, to)).copied().unwrap_or(0)
+    }
+
+    /// How often the instruction at the address ran without branching away.
+    pub fn fell_through(&self, addr: u64) -> u64 {
+        self.ranges.iter()
+            .filter(|(&(start, end), _)| start <= addr && addr < end)
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    /// How often the instruction at the address ran, which is estimated from
+    /// the straight-line code covering it or from the branches from and to
+    /// it, whichever count is higher.
+    pub fn executions(&self, addr: u64) -> u64 {
+        self.ranges.iter()
+            .filter(|(&(start, end), _)| start <= addr && addr <= end)
+            .map(|(_, &count)| count)
+            .sum::<u64>()
+            .max(self.branches.iter()
+                .filter(|(&(from, to), _)| from == addr || to == addr)
+                .map(|(_, &count)| count)
+                .sum())
+    }
+}
+
+impl ControlFlowGraph {
+    /// How often the edge ran according to the profile of the configuration.
+    pub fn edge_weight(&self, (start, end): (usize, usize)) -> u64 {
+        let profile = &self.config.profile;
+        let target = self.nodes[end].addr;
+        let last = self.blocks.get(&self.nodes[start].addr)
+            .and_then(|block| block.code.last());
+        match last {
+            Some(&(addr, len, ..)) => {
+                let mut weight = profile.taken(addr, target);
+                if addr + len == target {
+                    weight += profile.fell_through(addr);
+                }
+                weight
+            },
+            None => 0,
+        }
+    }
+}
+
+/// Parse an address, which is hexadecimal with a `0x` prefix and decimal
+/// otherwise.
+pub(super) fn number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// An error reading a profile.
+pub struct ProfileError {
+    pub message: String,
+}
+
+impl ProfileError {
+    fn new(message: String) -> ProfileError {
+        ProfileError { message }
+    }
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid profile: {}", self.message)
+    }
+}
+
+impl std::error::Error for ProfileError {}
+debug_display!(ProfileError);
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::VisualizationStyle;
+    use crate::sym::SymConfig;
+    use super::*;
+
+    #[test]
+    fn weights() {
+        // Find the conditional branch comparing the variable.
+        let program = Program::new(fixture("variables"));
+        let cfg = ControlFlowGraph::new(&program);
+        let branch = (0 .. cfg.nodes.len())
+            .find(|&node| cfg.outgoing[node].len() == 2)
+            .unwrap();
+        let start = cfg.nodes[branch].addr;
+        let &(addr, len, ..) = cfg.blocks[&start].code.last().unwrap();
+        let (taken, next) = match cfg.outgoing[branch][..] {
+            [a, b] if cfg.nodes[a].addr == addr + len => (cfg.nodes[b].addr, addr + len),
+            [a, _] => (cfg.nodes[a].addr, addr + len),
+            _ => unreachable!(),
+        };
+
+        // The branch was taken three times and fell through once in a run
+        // from the start of its block to after it.
+        let text = format!(
+            "# branch counts\n{:#x} {:#x} 3\n{:#x}/0x1/P/-/-/0 0x2/{:#x}/P/-/-/0\n",
+            addr, taken, addr + len, start,
+        );
+        let profile = Profile::parse(&text).unwrap();
+        assert_eq!(profile.fell_through(addr), 1);
+        assert_eq!(profile.executions(start), 1);
+
+        let config = SymConfig { profile, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        let branch = cfg.nodes.iter().position(|node| node.addr == start).unwrap();
+        let edge = |addr| (branch, cfg.nodes.iter().position(|node| node.addr == addr).unwrap());
+        assert_eq!(cfg.edge_weight(edge(taken)), 3);
+        assert_eq!(cfg.edge_weight(edge(next)), 1);
+
+        let mut dot = Vec::new();
+        cfg.visualize(&mut dot, &program, "weights", VisualizationStyle::Addresses).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("penwidth=5.0"));
+        assert!(dot.contains("penwidth=2.3"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text| Profile::parse(text).unwrap_err().message;
+        assert_eq!(error("0x10 0x20"), "expected `from to count` in line 1");
+        assert_eq!(error("\n0x10/zz/P"), "invalid branch record in line 2");
+        assert_eq!(error("0x10 0x20 many"), "invalid count in line 1");
+    }
+}
@@ -0,0 +1,139 @@
+//! Progress reports and cancellation of long analyses.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// The phases of building the flow graphs.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Phase {
+    ControlFlow,
+    DataDependency,
+    ProgramDependence,
+}
+
+/// The state of a phase when a report is made.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Report<'a> {
+    pub phase: Phase,
+    /// The number of items (blocks or nodes) done so far.
+    pub done: usize,
+    /// The total number of items if it is known in advance, which is not the
+    /// case while paths are explored.
+    pub total: Option<usize>,
+    /// The name of the function that is currently analyzed if there is one.
+    pub function: Option<&'a str>,
+}
+
+/// Receives the reports of an analysis. With the `parallel` feature, reports
+/// may come from multiple threads, each counting its own items.
+pub trait Progress: Send + Sync {
+    fn report(&self, report: Report);
+}
+
+impl<F> Progress for F where F: Fn(Report) + Send + Sync {
+    fn report(&self, report: Report) {
+        self(report)
+    }
+}
+
+/// A token through which an analysis can be cancelled from another thread.
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Request the analyses using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The progress callback and cancellation token of an analysis.
+#[derive(Clone, Default)]
+pub struct Monitor {
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancellation: CancellationToken,
+}
+
+impl Monitor {
+    /// Pass a report to the callback if there is one.
+    pub fn report(&self, phase: Phase, done: usize, total: Option<usize>, function: Option<&str>) {
+        if let Some(progress) = &self.progress {
+            progress.report(Report { phase, done, total, function });
+        }
+    }
+
+    /// Fail if the analysis was cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.cancellation.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Debug for Monitor {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Monitor")
+            .field("progress", &self.progress.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+/// The error of an analysis that was cancelled through its token.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Analysis was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+debug_display!(Cancelled);
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use super::*;
+
+    #[test]
+    fn monitor() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let monitor = Monitor {
+            progress: Some(Arc::new(move |report: Report| {
+                let function = report.function.map(str::to_string);
+                sink.lock().unwrap().push((report.phase, report.done, report.total, function));
+            })),
+            cancellation: CancellationToken::new(),
+        };
+
+        monitor.report(Phase::ControlFlow, 3, None, Some("main"));
+        monitor.report(Phase::ProgramDependence, 1, Some(2), None);
+        assert_eq!(*reports.lock().unwrap(), vec![
+            (Phase::ControlFlow, 3, None, Some("main".to_string())),
+            (Phase::ProgramDependence, 1, Some(2), None),
+        ]);
+
+        let token = monitor.cancellation.clone();
+        assert_eq!(monitor.check(), Ok(()));
+        token.cancel();
+        assert_eq!(monitor.check(), Err(Cancelled));
+        assert_eq!(Cancelled.to_string(), "Analysis was cancelled");
+    }
+}
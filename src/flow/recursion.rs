@@ -0,0 +1,314 @@
+//! Recursive calls and their summaries.
+//!
+//! The exploration follows recursive calls only up to a configured depth.
+//! Deeper calls are summarized: the call returns right away with unknown
+//! values in the registers the called function may change. These registers
+//! are found on the static call graph, where each function changes the
+//! registers written by its own instructions and by the functions it calls,
+//! which is iterated to a fixed point through the recursive cycles. Like a
+//! call outside of the analysis scope, the summarized call also writes
+//! unknown values to the memory its arguments may point to.
+
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+
+use crate::Program;
+use crate::ir::Microcode;
+use crate::x86_64::{Instruction, Register, REGISTERS};
+use super::StorageLocation;
+use super::directed::{implementations, successors};
+
+
+/// How recursive calls are explored.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RecursionConfig {
+    /// How often the same call may already be in the call trace of a path
+    /// when it is entered again.
+    pub depth: usize,
+    /// Whether calls beyond the depth return through a summary instead of
+    /// ending the path.
+    pub summarize: bool,
+}
+
+impl Default for RecursionConfig {
+    fn default() -> RecursionConfig {
+        RecursionConfig { depth: 2, summarize: true }
+    }
+}
+
+/// A set of general purpose registers.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct RegisterSet(u16);
+
+impl RegisterSet {
+    /// The set of all general purpose registers.
+    pub const ALL: RegisterSet = RegisterSet(u16::MAX);
+
+    /// Add the general purpose register containing the register. Other
+    /// registers are ignored.
+    pub fn insert(&mut self, reg: Register) {
+        if let Some(index) = index(reg) {
+            self.0 |= 1 << index;
+        }
+    }
+
+    /// Whether the general purpose register containing the register is in the set.
+    pub fn contains(self, reg: Register) -> bool {
+        index(reg).map_or(false, |index| self.0 & (1 << index) != 0)
+    }
+
+    /// The registers in either set.
+    pub fn union(self, other: RegisterSet) -> RegisterSet {
+        RegisterSet(self.0 | other.0)
+    }
+
+    /// The 64-bit registers in the set.
+    pub fn iter(self) -> impl Iterator<Item=Register> {
+        REGISTERS.iter()
+            .map(|&(reg, _)| reg)
+            .filter(move |&reg| self.contains(reg))
+    }
+}
+
+impl FromIterator<Register> for RegisterSet {
+    fn from_iter<I: IntoIterator<Item=Register>>(iter: I) -> RegisterSet {
+        let mut set = RegisterSet::default();
+        for reg in iter {
+            set.insert(reg);
+        }
+        set
+    }
+}
+
+/// The position of the base of the register among the general purpose ones.
+fn index(reg: Register) -> Option<usize> {
+    let base = reg.base();
+    REGISTERS.iter().position(|&(gpr, _)| gpr == base)
+}
+
+/// The functions of a program called directly from each other.
+#[derive(Debug, Clone)]
+pub struct CallGraph {
+    /// The functions called by each function.
+    pub callees: HashMap<u64, Vec<u64>>,
+    /// The addresses of the instructions reachable within each function, sorted.
+    pub instructions: HashMap<u64, Vec<u64>>,
+    /// The registers each function may change including its callees.
+    clobbered: HashMap<u64, RegisterSet>,
+}
+
+impl CallGraph {
+    /// Build the call graph of the roots and of all directly called functions.
+    pub fn new(program: &Program) -> CallGraph {
+        let instructions: HashMap<u64, (u64, &Instruction, &Microcode)> = program.code.iter()
+            .map(|(addr, len, instruction, microcode)| (*addr, (*len, instruction, microcode)))
+            .collect();
+
+        let mut pending = program.roots();
+        for (addr, len, instruction, _) in &program.code {
+            pending.extend(successors(*addr, *len, instruction).1);
+        }
+
+        let mut callees = HashMap::new();
+        let mut bodies = HashMap::new();
+        let mut clobbered = HashMap::new();
+        while let Some(function) = pending.pop() {
+            if callees.contains_key(&function) {
+                continue;
+            }
+            let (calls, code, written) = body(program, &instructions, function);
+            pending.extend(calls.iter().copied());
+            callees.insert(function, calls);
+            bodies.insert(function, code);
+            clobbered.insert(function, written);
+        }
+
+        // A function changes everything its callees change.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (function, calls) in &callees {
+                let before = clobbered[function];
+                let after = calls.iter()
+                    .fold(before, |set, callee| set.union(clobbered[callee]));
+                if after != before {
+                    clobbered.insert(*function, after);
+                    changed = true;
+                }
+            }
+        }
+
+        CallGraph { callees, instructions: bodies, clobbered }
+    }
+
+    /// The registers the function may change, which are all of them for
+    /// functions outside of the graph.
+    pub fn clobbered(&self, function: u64) -> RegisterSet {
+        self.clobbered.get(&function).copied().unwrap_or(RegisterSet::ALL)
+    }
+
+    /// The sets of functions calling each other recursively, each sorted
+    /// and in the order of their first functions.
+    pub fn cycles(&self) -> Vec<Vec<u64>> {
+        let mut functions: Vec<u64> = self.callees.keys().copied().collect();
+        functions.sort_unstable();
+
+        // Order the functions by when they are finished in a depth-first
+        // search and collect the components in reverse on the reversed graph.
+        let mut callers: HashMap<u64, Vec<u64>> = HashMap::new();
+        for (&function, calls) in &self.callees {
+            for &callee in calls {
+                callers.entry(callee).or_default().push(function);
+            }
+        }
+
+        let mut finished = Vec::new();
+        let mut visited = HashSet::new();
+        for &root in &functions {
+            if !visited.insert(root) {
+                continue;
+            }
+            let mut stack = vec![(root, 0)];
+            while let Some((function, next)) = stack.pop() {
+                match self.callees[&function].get(next) {
+                    Some(&callee) => {
+                        stack.push((function, next + 1));
+                        if visited.insert(callee) {
+                            stack.push((callee, 0));
+                        }
+                    },
+                    None => finished.push(function),
+                }
+            }
+        }
+
+        let mut assigned = HashSet::new();
+        let mut cycles = Vec::new();
+        for &root in finished.iter().rev() {
+            if !assigned.insert(root) {
+                continue;
+            }
+            let mut component = vec![root];
+            let mut stack = vec![root];
+            while let Some(function) = stack.pop() {
+                for &caller in callers.get(&function).into_iter().flatten() {
+                    if assigned.insert(caller) {
+                        component.push(caller);
+                        stack.push(caller);
+                    }
+                }
+            }
+
+            let recursive = component.len() > 1 || self.callees[&root].contains(&root);
+            if recursive {
+                component.sort_unstable();
+                cycles.push(component);
+            }
+        }
+
+        cycles.sort();
+        cycles
+    }
+
+    /// Whether the function calls itself directly or through other functions.
+    pub fn is_recursive(&self, function: u64) -> bool {
+        self.cycles().iter().any(|cycle| cycle.contains(&function))
+    }
+}
+
+/// The functions called, the addresses of the instructions reachable within
+/// the function and the registers written by them.
+fn body(
+    program: &Program,
+    instructions: &HashMap<u64, (u64, &Instruction, &Microcode)>,
+    function: u64,
+) -> (Vec<u64>, Vec<u64>, RegisterSet) {
+    let mut visited = HashSet::new();
+    let mut stack = vec![function];
+    let mut calls = Vec::new();
+    let mut code = Vec::new();
+    let mut written = RegisterSet::default();
+
+    while let Some(addr) = stack.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+
+        if let Some(&(len, instruction, microcode)) = instructions.get(&addr) {
+            code.push(addr);
+            for &(_, sink) in &microcode.flows {
+                if let StorageLocation::Direct(reg) = sink {
+                    written.insert(reg);
+                }
+            }
+
+            let (local, callee) = successors(addr, len, instruction);
+            stack.extend(local);
+            for callee in callee.into_iter().flat_map(|callee| implementations(program, callee)) {
+                if !calls.contains(&callee) {
+                    calls.push(callee);
+                }
+            }
+        }
+    }
+
+    calls.sort_unstable();
+    code.sort_unstable();
+    (calls, code, written)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::fixtures::fixture;
+    use crate::flow::{ControlFlowGraph, ControlKind};
+    use crate::math::{Solver, SymExpr, DataType};
+    use crate::sym::{SymConfig, SymState, MemoryStrategy};
+    use super::*;
+
+    #[test]
+    fn recursion() {
+        let program = Program::new(fixture("recursive-1"));
+        let function = |name: &str| program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let fac = function("fac");
+
+        let calls = CallGraph::new(&program);
+        assert_eq!(calls.cycles(), vec![vec![fac]]);
+        assert!(calls.is_recursive(fac) && !calls.is_recursive(function("main")));
+        assert!(calls.clobbered(fac).contains(Register::EAX));
+
+        let mutual = CallGraph::new(&Program::new(fixture("recursive-2")));
+        assert_eq!(mutual.cycles().iter().map(Vec::len).collect::<Vec<_>>(), vec![2]);
+
+        // Deeper calls than allowed are summarized.
+        let summarized = |recursion| {
+            let config = SymConfig { recursion, .. SymConfig::default() };
+            let cfg = ControlFlowGraph::with_config(&program, config);
+            cfg.edges.keys()
+                .filter(|&&edge| matches!(cfg.edge_kind(edge), ControlKind::Recursion { .. }))
+                .count()
+        };
+        assert!(summarized(RecursionConfig::default()) > 0);
+        assert_eq!(summarized(RecursionConfig { summarize: false, .. RecursionConfig::default() }), 0);
+
+        // Memory behind the pointer arguments may be written by the call.
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+        state.set_reg(Register::RDI, SymExpr::from_ptr(0x4000));
+        let byte = SymExpr::from_int(DataType::N8, 7);
+        state.memory[0].write_expr(SymExpr::from_ptr(0x4003), byte.clone());
+        let memory = vec![Register::RDI].into_iter().collect();
+        let clobbered = calls.clobbered(fac);
+        let kind = ControlKind::Recursion { function: fac, site: 0x1000, clobbered, memory };
+        assert_eq!(kind.enter(&mut state).len(), 1);
+        assert_ne!(state.memory[0].read_expr(SymExpr::from_ptr(0x4003), DataType::N8), byte);
+
+        let set: RegisterSet = vec![Register::EAX, Register::R12].into_iter().collect();
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![Register::RAX, Register::R12]);
+        assert!(!set.contains(Register::RBX));
+    }
+}
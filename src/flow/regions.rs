@@ -0,0 +1,191 @@
+//! Dominance frontiers and single-entry single-exit regions.
+//!
+//! A region is entered only through its entry node and left only to its exit
+//! node, which lies outside of it. Its nodes are the ones dominated by the
+//! entry but not by the exit. Regions are found like LLVM does: the exit of a
+//! region post-dominates its entry, so the candidates for each entry are its
+//! post-dominators from the nearest upwards. A candidate is an exit if the
+//! dominance frontier of the entry has no nodes outside of the exit besides
+//! the ones the exit leads to and if no edge from outside leads into the
+//! nodes between them. Regions of a single node falling through to its exit
+//! are left out. Regions either nest or are disjoint, so they form a tree.
+
+use std::collections::BTreeSet;
+
+use super::ControlFlowGraph;
+use super::ssa::{dominators, dominator_tree, frontiers};
+
+
+/// A single-entry single-exit region of a control flow graph.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Region {
+    /// The node through which every path enters the region.
+    pub entry: usize,
+    /// The node outside of the region that every path leaving it goes to.
+    pub exit: usize,
+    /// The nodes of the region including the entry, sorted.
+    pub nodes: Vec<usize>,
+    /// The index of the smallest region containing this one.
+    pub parent: Option<usize>,
+}
+
+impl Region {
+    /// Whether the node is part of the region.
+    pub fn contains(&self, node: usize) -> bool {
+        self.nodes.binary_search(&node).is_ok()
+    }
+}
+
+impl ControlFlowGraph {
+    /// The immediate dominator of each node, which is the node itself for
+    /// the entry and `None` for nodes unreachable from it.
+    pub fn immediate_dominators(&self) -> Vec<Option<usize>> {
+        dominators(self).1
+    }
+
+    /// The dominance frontier of each node: the nodes with a predecessor it
+    /// dominates which it does not strictly dominate itself.
+    pub fn dominance_frontiers(&self) -> Vec<BTreeSet<usize>> {
+        let (order, idom) = dominators(self);
+        frontiers(self, &order, &idom)
+    }
+
+    /// The immediate post-dominator of each node. It is `None` for nodes
+    /// without successors, for nodes reaching different ones of them on
+    /// different paths and for nodes in loops that are never left.
+    pub fn immediate_post_dominators(&self) -> Vec<Option<usize>> {
+        // The reverse graph is rooted at a virtual node after all nodes
+        // without successors.
+        let count = self.nodes.len();
+        let mut successors = self.incoming.clone();
+        let mut predecessors = self.outgoing.clone();
+        let exits: Vec<usize> = (0 .. count)
+            .filter(|&node| self.outgoing[node].is_empty())
+            .collect();
+        for &exit in &exits {
+            predecessors[exit].push(count);
+        }
+        successors.push(exits);
+        predecessors.push(Vec::new());
+
+        let (_, mut ipdom) = dominator_tree(count, &successors, &predecessors);
+        ipdom.truncate(count);
+        ipdom.into_iter()
+            .map(|node| node.filter(|&node| node != count))
+            .collect()
+    }
+
+    /// The non-trivial single-entry single-exit regions ordered by the
+    /// reverse postorder of their entries and, for the same entry, from the
+    /// innermost outwards.
+    pub fn regions(&self) -> Vec<Region> {
+        let (order, idom) = dominators(self);
+        let frontiers = frontiers(self, &order, &idom);
+        let ipdom = self.immediate_post_dominators();
+
+        let dominates = |dominator: usize, mut node: usize| loop {
+            if node == dominator {
+                return true;
+            }
+            match idom[node] {
+                Some(next) if next != node => node = next,
+                _ => return false,
+            }
+        };
+
+        // Whether every predecessor of the node that is inside of the region
+        // is also inside of the part the exit dominates.
+        let common_frontier = |node: usize, entry: usize, exit: usize| {
+            self.incoming[node].iter()
+                .all(|&pred| !dominates(entry, pred) || dominates(exit, pred))
+        };
+
+        let is_region = |entry: usize, exit: usize| {
+            if !dominates(entry, exit) {
+                // The exit is the header of a loop containing the entry.
+                return frontiers[entry].iter().all(|&node| node == exit || node == entry);
+            }
+            let leaves = frontiers[entry].iter()
+                .filter(|&&node| node != exit && node != entry)
+                .all(|&node| frontiers[exit].contains(&node) && common_frontier(node, entry, exit));
+            let entered = frontiers[exit].iter()
+                .any(|&node| node != exit && node != entry && dominates(entry, node));
+            leaves && !entered
+        };
+
+        let mut regions = Vec::new();
+        for &entry in &order {
+            let mut candidate = ipdom[entry];
+            while let Some(exit) = candidate {
+                let trivial = self.outgoing[entry] == [exit];
+                if !trivial && is_region(entry, exit) {
+                    let mut nodes: Vec<usize> = order.iter()
+                        .copied()
+                        .filter(|&node| dominates(entry, node) && !dominates(exit, node))
+                        .collect();
+                    nodes.sort_unstable();
+                    regions.push(Region { entry, exit, nodes, parent: None });
+                }
+                if !dominates(entry, exit) {
+                    break;
+                }
+                candidate = ipdom[exit];
+            }
+        }
+
+        let parents: Vec<Option<usize>> = regions.iter()
+            .map(|region| (0 .. regions.len())
+                .filter(|&other| {
+                    let outer = &regions[other];
+                    outer.nodes.len() > region.nodes.len()
+                        && region.nodes.iter().all(|&node| outer.contains(node))
+                })
+                .min_by_key(|&other| regions[other].nodes.len()))
+            .collect();
+        for (region, parent) in regions.iter_mut().zip(parents) {
+            region.parent = parent;
+        }
+        regions
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::ssa::tests::graph;
+    use super::*;
+
+    #[test]
+    fn regions() {
+        // A diamond b0 to b3 followed by the loop b4 and the exit b5.
+        let nop: &[&[u8]] = &[&[0x90]];
+        let blocks: Vec<(u64, &[&[u8]])> = (0 .. 6)
+            .map(|index| (0x1000 + 0x10 * index, nop))
+            .collect();
+        let cfg = graph(&blocks, &[(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (4, 4), (4, 5)]);
+
+        let idom = cfg.immediate_dominators();
+        assert_eq!(idom, vec![Some(0), Some(0), Some(0), Some(0), Some(3), Some(4)]);
+        let ipdom = cfg.immediate_post_dominators();
+        assert_eq!(ipdom, vec![Some(3), Some(3), Some(3), Some(4), Some(5), None]);
+
+        let frontiers = cfg.dominance_frontiers();
+        let set = |nodes: &[usize]| nodes.iter().copied().collect::<BTreeSet<usize>>();
+        assert_eq!(frontiers[1], set(&[3]));
+        assert_eq!(frontiers[2], set(&[3]));
+        assert_eq!(frontiers[4], set(&[4]));
+        assert!(frontiers[0].is_empty() && frontiers[3].is_empty());
+
+        let regions = cfg.regions();
+        let found: Vec<(usize, usize, &[usize], Option<usize>)> = regions.iter()
+            .map(|region| (region.entry, region.exit, region.nodes.as_slice(), region.parent))
+            .collect();
+        assert_eq!(found, vec![
+            (0, 3, &[0, 1, 2][..], Some(1)),
+            (0, 4, &[0, 1, 2, 3][..], Some(2)),
+            (0, 5, &[0, 1, 2, 3, 4][..], None),
+            (3, 5, &[3, 4][..], Some(2)),
+            (4, 5, &[4][..], Some(3)),
+        ]);
+    }
+}
@@ -0,0 +1,405 @@
+//! Standalone reports of program slices.
+//!
+//! A report bundles what is known about a slice of the program dependence
+//! graph into a single HTML or Markdown document:
+//! - The origin of the analysis if the visual configuration has metadata.
+//! - The sliced instructions in address order with their disassembly and,
+//!   from the listing of the visual configuration, their source lines.
+//! - The conditions of the dependences within the slice and, if asked for,
+//!   values of the symbols under which each of them holds.
+//! - The sub-graph of the slice. HTML reports inline it as SVG when graphviz
+//!   can render it and fall back to its graphviz source like Markdown does.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, Write};
+
+use crate::math::{SymCondition, Solver};
+use super::{ControlFlowGraph, DependenceNode, ProgramDependenceGraph, VisualConfig};
+use super::visualize::{escape, Conditions};
+
+
+/// The document format of a report.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+/// What a report contains and how it is written.
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    pub format: ReportFormat,
+    /// Whether to solve for values of the symbols under which each condition
+    /// holds.
+    pub model: bool,
+    /// How the sub-graph and conditions are rendered. Source lines come from
+    /// its listing and the origin from its metadata.
+    pub visual: VisualConfig,
+}
+
+impl Default for ReportConfig {
+    fn default() -> ReportConfig {
+        ReportConfig {
+            format: ReportFormat::Html,
+            model: false,
+            visual: VisualConfig::default(),
+        }
+    }
+}
+
+impl ProgramDependenceGraph {
+    /// Write a report of the slice, given as node indices like the ones of
+    /// `backward_slice`, with the blocks and instructions of the control flow
+    /// graph this graph was built from.
+    pub fn write_report<W: Write>(
+        &self,
+        target: W,
+        title: &str,
+        slice: &BTreeSet<usize>,
+        cfg: &ControlFlowGraph,
+        config: &ReportConfig,
+    ) -> io::Result<()> {
+        let _phase = crate::trace::phase("render");
+        let mut doc = Document { f: target, format: config.format };
+        doc.start(&format!("Slice report: {}", title))?;
+
+        if let Some(metadata) = &config.visual.metadata {
+            doc.heading("Origin")?;
+            let mut items = vec![
+                format!("binary: {}", metadata.binary),
+                format!("hash: {:016x}", metadata.hash),
+                format!("symflow {}", metadata.version),
+            ];
+            items.extend(metadata.options.iter().map(|(name, value)| {
+                format!("{}: {}", name, value)
+            }));
+            doc.list(&items)?;
+        }
+
+        // The whole blocks the slice depends on and the single instructions
+        // of its locations.
+        let mut addrs = BTreeSet::new();
+        for &index in slice {
+            match &self.nodes[index] {
+                DependenceNode::ControlFlow(addr) => if let Some(block) = cfg.blocks.get(addr) {
+                    addrs.extend(block.code.iter().map(|&(addr, ..)| addr));
+                },
+                DependenceNode::DataDependency(location) => {
+                    addrs.insert(location.addr);
+                }
+            }
+        }
+        let code: BTreeMap<u64, _> = cfg.blocks.values()
+            .flat_map(|block| &block.code)
+            .filter(|(addr, ..)| addrs.contains(addr))
+            .map(|(addr, _, instruction, _)| (*addr, instruction))
+            .collect();
+        let literals = cfg.literals();
+
+        let mut listing = String::new();
+        let mut last_line = None;
+        for (&addr, instruction) in &code {
+            if let Some(line) = config.visual.listing.lines.get(&addr) {
+                if last_line != Some(line) {
+                    listing.push_str(&format!("// {}\n", line));
+                    last_line = Some(line);
+                }
+            }
+            listing.push_str(&format!("{:x}: {}", addr, instruction));
+            if let Some((_, literal)) = literals.get(&addr) {
+                listing.push_str(&format!("  ; {}", literal.annotation()));
+            }
+            listing.push('\n');
+        }
+
+        doc.heading("Instructions")?;
+        doc.paragraph(&format!("{} nodes of the dependence graph cover {} instructions.",
+                               slice.len(), code.len()))?;
+        doc.code("asm", &listing)?;
+
+        // Each distinct condition is listed once, with the first dependence
+        // guarded by it.
+        let conditions = Conditions::new(&config.visual);
+        let solver = match config.model {
+            true => Some(Solver::with_seed(cfg.config.seed)),
+            false => None,
+        };
+        let mut edges: Vec<_> = self.edges.iter()
+            .filter(|&((start, end), edge)| {
+                slice.contains(&start) && slice.contains(&end)
+                    && edge.condition != SymCondition::TRUE
+            })
+            .map(|((start, end), edge)| (self.id(start), self.id(end), start, end, edge))
+            .collect();
+        edges.sort_by_key(|&(start, end, ..)| (start, end));
+
+        let mut seen = HashSet::new();
+        let mut rows = Vec::new();
+        for (_, _, start, end, edge) in edges {
+            if !seen.insert(&edge.condition) {
+                continue;
+            }
+            let mut row = vec![
+                self.describe_node(start),
+                self.describe_node(end),
+                edge.kind.name().to_string(),
+                conditions.full(&edge.condition),
+            ];
+            if let Some(solver) = &solver {
+                row.push(match solver.model(&edge.condition) {
+                    Some(model) => {
+                        let mut values: Vec<_> = model.into_iter().collect();
+                        values.sort_by_key(|&(symbol, _)| symbol);
+                        let values: Vec<String> = values.iter()
+                            .map(|(symbol, value)| format!("{} = {}", symbol, value))
+                            .collect();
+                        values.join(", ")
+                    },
+                    None => "unsatisfiable".to_string(),
+                });
+            }
+            rows.push(row);
+        }
+
+        doc.heading("Conditions")?;
+        if rows.is_empty() {
+            doc.paragraph("All dependences in the slice hold unconditionally.")?;
+        } else {
+            let mut header = vec!["From", "To", "Kind", "Condition"];
+            if solver.is_some() {
+                header.push("Model");
+            }
+            doc.table(&header, &rows)?;
+        }
+
+        let keep: HashSet<&DependenceNode> = slice.iter()
+            .map(|&index| &self.nodes[index])
+            .collect();
+        let mut graph = Vec::new();
+        self.subgraph(|node| keep.contains(node))
+            .visualize_with(&mut graph, title, Some(cfg), &config.visual)?;
+
+        doc.heading("Graph")?;
+        match config.format {
+            ReportFormat::Html => match render_svg(&graph) {
+                Some(svg) => doc.raw(&svg)?,
+                None => doc.code("dot", &String::from_utf8_lossy(&graph))?,
+            },
+            ReportFormat::Markdown => doc.code("dot", &String::from_utf8_lossy(&graph))?,
+        }
+
+        doc.end()
+    }
+
+    /// A node as it is named in reports.
+    fn describe_node(&self, index: usize) -> String {
+        match &self.nodes[index] {
+            DependenceNode::ControlFlow(addr) => format!("block 0x{:x}", addr),
+            DependenceNode::DataDependency(location) => self.frames.describe(location).to_string(),
+        }
+    }
+}
+
+/// Writes the parts of a report in its format.
+struct Document<W: Write> {
+    f: W,
+    format: ReportFormat,
+}
+
+impl<W: Write> Document<W> {
+    fn start(&mut self, title: &str) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => {
+                writeln!(self.f, "<!DOCTYPE html>")?;
+                writeln!(self.f, "<html>")?;
+                writeln!(self.f, "<head>")?;
+                writeln!(self.f, "<meta charset=\"utf-8\">")?;
+                writeln!(self.f, "<title>{}</title>", escape(title))?;
+                writeln!(self.f, "<style>{}</style>", STYLE)?;
+                writeln!(self.f, "</head>")?;
+                writeln!(self.f, "<body>")?;
+                writeln!(self.f, "<h1>{}</h1>", escape(title))
+            },
+            ReportFormat::Markdown => writeln!(self.f, "# {}", title),
+        }
+    }
+
+    fn heading(&mut self, text: &str) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => writeln!(self.f, "<h2>{}</h2>", escape(text)),
+            ReportFormat::Markdown => writeln!(self.f, "\n## {}\n", text),
+        }
+    }
+
+    fn paragraph(&mut self, text: &str) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => writeln!(self.f, "<p>{}</p>", escape(text)),
+            ReportFormat::Markdown => writeln!(self.f, "{}\n", text),
+        }
+    }
+
+    fn list(&mut self, items: &[String]) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => {
+                writeln!(self.f, "<ul>")?;
+                for item in items {
+                    writeln!(self.f, "<li>{}</li>", escape(item))?;
+                }
+                writeln!(self.f, "</ul>")
+            },
+            ReportFormat::Markdown => {
+                for item in items {
+                    writeln!(self.f, "- {}", item)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Preformatted text in the language, which Markdown viewers may
+    /// highlight or, for graphviz, render.
+    fn code(&mut self, language: &str, text: &str) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => writeln!(self.f, "<pre>{}</pre>", escape(text)),
+            ReportFormat::Markdown => writeln!(self.f, "```{}\n{}```", language, text),
+        }
+    }
+
+    fn table(&mut self, header: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => {
+                writeln!(self.f, "<table>")?;
+                let cells: Vec<String> = header.iter()
+                    .map(|cell| format!("<th>{}</th>", escape(cell)))
+                    .collect();
+                writeln!(self.f, "<tr>{}</tr>", cells.concat())?;
+                for row in rows {
+                    let cells: Vec<String> = row.iter()
+                        .map(|cell| format!("<td><code>{}</code></td>", escape(cell)))
+                        .collect();
+                    writeln!(self.f, "<tr>{}</tr>", cells.concat())?;
+                }
+                writeln!(self.f, "</table>")
+            },
+            ReportFormat::Markdown => {
+                writeln!(self.f, "| {} |", header.join(" | "))?;
+                writeln!(self.f, "|{}", " --- |".repeat(header.len()))?;
+                for row in rows {
+                    let cells: Vec<String> = row.iter()
+                        .map(|cell| format!("`{}`", cell.replace('|', "\\|")))
+                        .collect();
+                    writeln!(self.f, "| {} |", cells.join(" | "))?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Markup written as it is.
+    fn raw(&mut self, markup: &str) -> io::Result<()> {
+        writeln!(self.f, "{}", markup)
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Html => {
+                writeln!(self.f, "</body>")?;
+                writeln!(self.f, "</html>")
+            },
+            ReportFormat::Markdown => Ok(()),
+        }
+    }
+}
+
+/// The style sheet of HTML reports.
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; } \
+                     pre, code { font-family: \"Source Code Pro\", monospace; } \
+                     table { border-collapse: collapse; } \
+                     th, td { border: 1px solid #ccc; padding: 0.3em; text-align: left; }";
+
+/// Render the graphviz source as SVG that can be inlined into HTML, if
+/// graphviz is installed.
+#[cfg(feature = "fs")]
+fn render_svg(dot: &[u8]) -> Option<String> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(dot).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // The XML declaration and doctype are only allowed in standalone files.
+    let svg = String::from_utf8(output.stdout).ok()?;
+    svg.find("<svg").map(|start| svg[start ..].to_string())
+}
+
+/// Without the file system there is no graphviz to run.
+#[cfg(not(feature = "fs"))]
+fn render_svg(_: &[u8]) -> Option<String> {
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::DataDependencyGraph;
+    use super::*;
+
+    #[test]
+    fn report() {
+        let program = Program::new(fixture("variables"));
+        let cfg = ControlFlowGraph::new(&program);
+        let ddg = DataDependencyGraph::new(&cfg);
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+
+        // The slice of a block writing the answer depends on the comparison
+        // of the byte read from standard input.
+        let main = program.symbols.iter()
+            .find(|(_, name)| name.as_str() == "main")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let branch = (0 .. cfg.nodes.len())
+            .find(|&node| cfg.nodes[node].addr >= main && cfg.outgoing[node].len() == 2)
+            .unwrap();
+        let target = cfg.nodes[cfg.outgoing[branch][0]].addr;
+        let slice = pdg.backward_slice(target);
+
+        let mut config = ReportConfig {
+            format: ReportFormat::Markdown,
+            model: true,
+            .. ReportConfig::default()
+        };
+        let &(addr, ..) = cfg.blocks[&target].code.first().unwrap();
+        config.visual.listing.lines.insert(addr, "write_one_byte('s');".to_string());
+
+        let mut markdown = Vec::new();
+        pdg.write_report(&mut markdown, "answer", &slice, &cfg, &config).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.starts_with("# Slice report: answer\n"));
+        assert!(markdown.contains(&format!("// write_one_byte('s');\n{:x}: ", addr)));
+        assert!(markdown.contains("| From | To | Kind | Condition | Model |"));
+        assert!(markdown.contains("stdin"));
+        assert!(markdown.contains("```dot\ndigraph Flow {"));
+
+        config.format = ReportFormat::Html;
+        config.model = false;
+        let mut html = Vec::new();
+        pdg.write_report(&mut html, "answer", &slice, &cfg, &config).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<th>Condition</th></tr>"));
+        assert!(!html.contains("<th>Model</th>"));
+        assert!(html.contains("<svg") || html.contains("digraph Flow {"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,285 @@
+//! The part of a program the analysis descends into.
+//!
+//! A scope file is a JSON object with the arrays `include` and `exclude`,
+//! whose filters select code by one of these members:
+//! - `start` and `end`: The addresses of the range from the start up to the
+//!   end, as numbers or hexadecimal strings.
+//! - `section`: The name of the section containing the code, like `.plt`.
+//! - `symbol`: A pattern for the symbol name of a function, in which `*`
+//!   matches any text.
+//!
+//! A function is in the scope if any include filter selects it, or if there
+//! are none, and no exclude filter does. Ranges and sections select the
+//! functions starting in them. The scope is checked at calls: calls of
+//! functions outside of it are not entered and return with unknown values
+//! in all registers the calling convention does not preserve and in the
+//! memory the argument registers may point to. Tail jumps into them return
+//! to the caller the same way. Functions creating or ending threads are
+//! still recognized outside of the scope. For example,
+//! `{"exclude": [{"section": ".plt"}, {"symbol": "inflate*"}]}` leaves out
+//! the library stubs and the decompressor.
+
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+use std::ops::Range;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::Program;
+use crate::json::Json;
+use super::taint::glob;
+
+
+/// The functions the analysis enters.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AnalysisScope {
+    pub include: Vec<ScopeFilter>,
+    pub exclude: Vec<ScopeFilter>,
+}
+
+/// A selection of functions of a scope.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScopeFilter {
+    /// The functions starting in the address range.
+    Range(Range<u64>),
+    /// The functions starting in the section with the name.
+    Section(String),
+    /// The functions with a symbol name matching the pattern.
+    Symbol(String),
+}
+
+impl AnalysisScope {
+    /// Parse a scope from JSON.
+    pub fn from_json(text: &str) -> Result<AnalysisScope, ScopeError> {
+        let json = Json::parse(text).map_err(|err| ScopeError::new(err.to_string()))?;
+        let filters = |key: &str| -> Result<Vec<ScopeFilter>, ScopeError> {
+            match json.get(key) {
+                Some(value) => value.as_array()
+                    .ok_or_else(|| ScopeError::new(format!("\"{}\" is not an array", key)))?
+                    .iter()
+                    .map(ScopeFilter::from_json)
+                    .collect(),
+                None => Ok(Vec::new()),
+            }
+        };
+        Ok(AnalysisScope { include: filters("include")?, exclude: filters("exclude")? })
+    }
+
+    /// Read a scope from a JSON file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AnalysisScope, ScopeError> {
+        let text = fs::read_to_string(path).map_err(|err| ScopeError::new(err.to_string()))?;
+        AnalysisScope::from_json(&text)
+    }
+
+    /// Whether the scope contains the whole program.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether the function at the address is in the scope.
+    pub fn contains(&self, program: &Program, addr: u64) -> bool {
+        let included = self.include.is_empty()
+            || self.include.iter().any(|filter| filter.selects(program, addr));
+        included && !self.exclude.iter().any(|filter| filter.selects(program, addr))
+    }
+}
+
+impl ScopeFilter {
+    /// Parse a filter from a JSON object.
+    fn from_json(json: &Json) -> Result<ScopeFilter, ScopeError> {
+        let members = match json {
+            Json::Object(members) => members,
+            _ => return Err(ScopeError::new("filter is not an object".to_string())),
+        };
+
+        let (mut start, mut end, mut section, mut symbol) = (None, None, None, None);
+        for (key, value) in members {
+            let invalid = || ScopeError::new(format!("invalid \"{}\" in filter", key));
+            match key.as_str() {
+                "start" => start = Some(value.as_u64().ok_or_else(invalid)?),
+                "end" => end = Some(value.as_u64().ok_or_else(invalid)?),
+                "section" => section = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                "symbol" => symbol = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                _ => return Err(ScopeError::new(format!("unknown member \"{}\" in filter", key))),
+            }
+        }
+
+        match (start, end, section, symbol) {
+            (Some(start), Some(end), None, None) if start <= end => {
+                Ok(ScopeFilter::Range(start .. end))
+            },
+            (Some(_), Some(_), None, None) => {
+                Err(ScopeError::new("\"end\" before \"start\" in filter".to_string()))
+            },
+            (None, None, Some(section), None) => Ok(ScopeFilter::Section(section)),
+            (None, None, None, Some(symbol)) => Ok(ScopeFilter::Symbol(symbol)),
+            (None, None, None, None) => Err(ScopeError::new("empty filter".to_string())),
+            _ => Err(ScopeError::new("ambiguous filter".to_string())),
+        }
+    }
+
+    /// Whether the filter selects the function at the address.
+    fn selects(&self, program: &Program, addr: u64) -> bool {
+        match self {
+            ScopeFilter::Range(range) => range.contains(&addr),
+            ScopeFilter::Section(name) => program.sections.iter().any(|(section, start, size)| {
+                section == name && *start <= addr && addr - start < *size
+            }),
+            ScopeFilter::Symbol(pattern) => program.symbols.get(&addr)
+                .map_or(false, |name| glob(pattern, name)),
+        }
+    }
+}
+
+impl Display for AnalysisScope {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "all");
+        }
+        let list = |filters: &[ScopeFilter]| filters.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut parts = Vec::new();
+        if !self.include.is_empty() {
+            parts.push(format!("include {}", list(&self.include)));
+        }
+        if !self.exclude.is_empty() {
+            parts.push(format!("exclude {}", list(&self.exclude)));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl Display for ScopeFilter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ScopeFilter::Range(range) => write!(f, "{:#x}..{:#x}", range.start, range.end),
+            ScopeFilter::Section(name) => write!(f, "{}", name),
+            ScopeFilter::Symbol(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+/// An error reading an analysis scope.
+pub struct ScopeError {
+    pub message: String,
+}
+
+impl ScopeError {
+    fn new(message: String) -> ScopeError {
+        ScopeError { message }
+    }
+}
+
+impl Display for ScopeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid analysis scope: {}", self.message)
+    }
+}
+
+impl std::error::Error for ScopeError {}
+debug_display!(ScopeError);
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::fixture;
+    use crate::sym::SymConfig;
+    use super::super::{ControlFlowGraph, ControlKind};
+    use super::*;
+
+    #[test]
+    fn scope() {
+        let program = Program::new(fixture("block-1"));
+        let symbol = |wanted: &str| program.symbols.iter()
+            .find(|(_, name)| name.as_str() == wanted)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let (main, compare) = (symbol("main"), symbol("compare"));
+
+        let scope = AnalysisScope::from_json(r#"{"exclude": [{"symbol": "comp*"}]}"#).unwrap();
+        assert!(scope.contains(&program, main) && !scope.contains(&program, compare));
+        let text = format!(r#"{{"include": [{{"start": "{:#x}", "end": "{:#x}"}}]}}"#,
+                           main, main + 1);
+        let range = AnalysisScope::from_json(&text).unwrap();
+        assert!(range.contains(&program, main) && !range.contains(&program, compare));
+        let text = program.sections.iter()
+            .find(|(_, start, size)| *start <= main && main - start < *size)
+            .map(|(name, ..)| format!(r#"{{"include": [{{"section": "{}"}}]}}"#, name))
+            .unwrap();
+        assert!(AnalysisScope::from_json(&text).unwrap().contains(&program, compare));
+        assert_eq!(scope.to_string(), "exclude comp*");
+        assert_eq!(AnalysisScope::default().to_string(), "all");
+
+        // The excluded function is not entered and its call returns through
+        // a summary changing the registers that are not preserved and the
+        // memory the arguments point to.
+        let config = SymConfig { scope, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        assert!(cfg.nodes.iter().all(|node| node.addr != compare));
+        let (clobbered, memory) = cfg.edges.keys()
+            .find_map(|&edge| match cfg.edge_kind(edge) {
+                ControlKind::Summary { function, clobbered, memory, .. } if function == compare => {
+                    Some((clobbered, memory))
+                },
+                _ => None,
+            })
+            .unwrap();
+        assert!(clobbered.contains(crate::x86_64::Register::RAX));
+        assert!(!clobbered.contains(crate::x86_64::Register::RBX));
+        assert!(memory.contains(crate::x86_64::Register::RDI));
+
+        let error = |text| AnalysisScope::from_json(text).unwrap_err().message;
+        assert_eq!(error(r#"{"exclude": [{}]}"#), "empty filter");
+        assert_eq!(error(r#"{"exclude": [{"start": 1}]}"#), "ambiguous filter");
+        assert_eq!(error(r#"{"include": [{"start": 2, "end": 1}]}"#),
+                   "\"end\" before \"start\" in filter");
+        assert_eq!(error(r#"{"exclude": [{"name": "x"}]}"#), "unknown member \"name\" in filter");
+    }
+
+    #[test]
+    fn excluded_calls() {
+        // Calls creating threads still start them when the function creating
+        // the thread is outside of the scope.
+        let program = Program::new(fixture("threads"));
+        let symbol = |wanted: &str| program.symbols.iter()
+            .find(|(_, name)| name.as_str() == wanted)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let (spawner, worker) = (symbol("pthread_create"), symbol("worker"));
+        let scope = AnalysisScope {
+            exclude: vec![ScopeFilter::Range(spawner .. spawner + 1)],
+            .. AnalysisScope::default()
+        };
+        let config = SymConfig { scope, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        assert!(cfg.nodes.iter().any(|node| node.addr == worker));
+
+        // A tail jump into an excluded function returns to the caller of the
+        // jumping function through a summary.
+        //   0x1000: call 0x1010;  ret
+        //   0x1010: jmp 0x1020
+        //   0x1020: mov [rdi], rax;  ret
+        let binary = [
+            &[0xe8, 0x0b, 0, 0, 0, 0xc3][..], &[0x90; 10],
+            &[0xe9, 0x0b, 0, 0, 0], &[0x90; 11],
+            &[0x48, 0x89, 0x07, 0xc3],
+        ].concat();
+        let symbols = vec![(0x1010, "jumping".to_string()), (0x1020, "excluded".to_string())];
+        let program = Program::from_code(0x1000, &binary, symbols.into_iter().collect());
+        let scope = AnalysisScope::from_json(r#"{"exclude": [{"symbol": "excluded"}]}"#).unwrap();
+        let config = SymConfig { scope, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        assert!(cfg.nodes.iter().all(|node| node.addr != 0x1020));
+        let summary = cfg.edges.keys().find(|&&edge| match cfg.edge_kind(edge) {
+            ControlKind::Summary { function, .. } => function == 0x1020,
+            _ => false,
+        });
+        let (_, end) = *summary.unwrap();
+        assert_eq!(cfg.nodes[end].addr, 0x1005);
+        assert!(cfg.nodes[end].trace.is_empty());
+    }
+}
@@ -0,0 +1,809 @@
+//! Serialization of the microcode as Ghidra P-code and BAP BIL.
+//!
+//! Both listings show the instructions of the graph in address order with
+//! the semantics of their microcode, so that it can be compared with the
+//! lifters of Ghidra and BAP. The microcode is translated operation by
+//! operation, temporaries keep their index and the conditions of jumps,
+//! which refer to the operands of the last flag-modifying operation, are
+//! computed right before the branch.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::ir::{Microcode, MicroOperation, Location, Temporary, Transfer, BitOp};
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol};
+use crate::x86_64::Instruction;
+use super::ControlFlowGraph;
+use DataType::*;
+
+
+impl ControlFlowGraph {
+    /// Write the semantics of the instructions as raw P-code in the format
+    /// of Ghidra's `PcodeOp.toString`.
+    ///
+    /// Registers are varnodes at their offsets in Ghidra's `x86:LE:64:default`
+    /// language and temporaries are unique varnodes 0x20 bytes apart by index.
+    /// The space operand of loads and stores and the operation of user-defined
+    /// operations are written as names instead of identifiers. Branches with
+    /// a target computed from a constant offset branch directly, conditional
+    /// indirect branches skip over the branch if the condition is false.
+    pub fn write_pcode<W: Write>(&self, mut f: W) -> io::Result<()> {
+        for (addr, len, instruction, microcode) in self.instructions() {
+            writeln!(f, "{:#x}: {}", addr, instruction)?;
+            let mut pcode = Pcode { ops: Vec::new(), uniques: 0, constants: HashMap::new() };
+            pcode.microcode(microcode, addr + len);
+            for op in &pcode.ops {
+                writeln!(f, "  {}", op)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the semantics of the instructions as statements in the concrete
+    /// syntax of BAP's BIL.
+    ///
+    /// Registers are the 64-bit registers and the 256-bit `YMM` registers,
+    /// narrower parts are extracted and concatenated. Temporaries are the
+    /// virtual variables `#t<index>_<bits>`. Operations BIL has no operator
+    /// for produce unknown values named after the operation.
+    pub fn write_bil<W: Write>(&self, mut f: W) -> io::Result<()> {
+        for (addr, len, instruction, microcode) in self.instructions() {
+            writeln!(f, "{:#x}: {}", addr, instruction)?;
+            let mut bil = Bil { statements: Vec::new(), constants: HashMap::new(), loops: 0 };
+            bil.microcode(microcode, addr + len);
+            writeln!(f, "{{")?;
+            for statement in &bil.statements {
+                writeln!(f, "  {}", statement)?;
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+
+    /// The instructions of all blocks in address order.
+    fn instructions(&self) -> Vec<(u64, u64, &Instruction, &Microcode)> {
+        let mut blocks: Vec<_> = self.blocks.values().collect();
+        blocks.sort_by_key(|block| block.addr);
+        blocks.iter()
+            .flat_map(|block| block.code.iter())
+            .map(|(addr, len, instruction, microcode)| (*addr, *len, instruction, microcode))
+            .collect()
+    }
+}
+
+/// The jump of microcode with its target resolved if it is a constant.
+enum Target {
+    Direct(u64),
+    Computed(Temporary, bool),
+}
+
+/// Resolve the target of a jump from the constants of the instruction.
+fn target(constants: &HashMap<usize, u64>, temp: Temporary, relative: bool, next: u64) -> Target {
+    match constants.get(&temp.1) {
+        Some(&offset) if relative => Target::Direct(next.wrapping_add(offset)),
+        Some(&addr) => Target::Direct(addr),
+        None => Target::Computed(temp, relative),
+    }
+}
+
+/// The sign-extended value of a constant of a type up to 64 bits.
+fn sign_extend(int: Integer) -> u64 {
+    let shift = 64 - int.0.bits().min(64);
+    (((int.1 << shift) as i64) >> shift) as u64
+}
+
+/// The P-code of one instruction.
+struct Pcode {
+    ops: Vec<String>,
+    /// The number of fresh unique varnodes.
+    uniques: u64,
+    /// The constants stored in temporaries by index.
+    constants: HashMap<usize, u64>,
+}
+
+impl Pcode {
+    fn microcode(&mut self, microcode: &Microcode, next: u64) {
+        for operation in &microcode.ops {
+            self.operation(operation, microcode.transfer, next);
+        }
+    }
+
+    fn operation(&mut self, operation: &MicroOperation, transfer: Transfer, next: u64) {
+        use MicroOperation::*;
+
+        match *operation {
+            Mov { dest, src } => {
+                let value = self.read(src);
+                self.write(dest, value);
+            },
+            Const { dest, constant } => {
+                self.constants.insert(dest.1, sign_extend(constant));
+                let value = self.constant(constant.1, dest.0);
+                self.op(&temp(dest), "COPY", &[value]);
+            },
+            Cast { target, new, signed } => {
+                let output = temp(Temporary(new, target.1));
+                if new.bytes() < target.0.bytes() {
+                    self.op(&output, "SUBPIECE", &[temp(target), self.constant(0, N32)]);
+                } else if new.bytes() > target.0.bytes() {
+                    let opcode = if signed { "INT_SEXT" } else { "INT_ZEXT" };
+                    self.op(&output, opcode, &[temp(target)]);
+                } else {
+                    self.op(&output, "COPY", &[temp(target)]);
+                }
+            },
+
+            Add { sum, a, b } => self.op(&temp(sum), "INT_ADD", &[temp(a), temp(b)]),
+            Sub { diff, a, b } => self.op(&temp(diff), "INT_SUB", &[temp(a), temp(b)]),
+            Mul { prod, a, b } => self.op(&temp(prod), "INT_MULT", &[temp(a), temp(b)]),
+            And { and, a, b } => self.op(&temp(and), "INT_AND", &[temp(a), temp(b)]),
+            Or { or, a, b } => self.op(&temp(or), "INT_OR", &[temp(a), temp(b)]),
+            Xor { xor, a, b } => self.op(&temp(xor), "INT_XOR", &[temp(a), temp(b)]),
+            Not { not, a } => self.op(&temp(not), "INT_NEGATE", &[temp(a)]),
+
+            Bits { op, target, a, b } => match op {
+                BitOp::Shl => self.op(&temp(target), "INT_LEFT", &[temp(a), temp(b)]),
+                BitOp::Shr => self.op(&temp(target), "INT_RIGHT", &[temp(a), temp(b)]),
+                BitOp::LeadingZeros => self.op(&temp(target), "LZCOUNT", &[temp(a)]),
+                BitOp::TrailingZeros => self.user(Some(target), "tzcnt", &[temp(a)]),
+                BitOp::Extract => self.user(Some(target), "pext", &[temp(a), temp(b)]),
+                BitOp::Deposit => self.user(Some(target), "pdep", &[temp(a), temp(b)]),
+            },
+            Packed { op, lane, target, a, b } => {
+                let name = format!("packed_{:?}_{}", op, lane).to_lowercase();
+                self.user(Some(target), &name, &[temp(a), temp(b)]);
+            },
+            Float { op, target, a, b } => {
+                use crate::ir::FloatOp;
+                let float = |data_type| matches!(data_type, F32 | F64);
+                let opcode = match op {
+                    FloatOp::Add => "FLOAT_ADD",
+                    FloatOp::Sub => "FLOAT_SUB",
+                    FloatOp::Mul => "FLOAT_MULT",
+                    FloatOp::Div => "FLOAT_DIV",
+                    FloatOp::Convert => match (float(a.0), float(target.0)) {
+                        (true, true) => "FLOAT_FLOAT2FLOAT",
+                        (false, true) => "FLOAT_INT2FLOAT",
+                        _ => "FLOAT_TRUNC",
+                    },
+                };
+                if op == FloatOp::Convert {
+                    self.op(&temp(target), opcode, &[temp(a)]);
+                } else {
+                    self.op(&temp(target), opcode, &[temp(a), temp(b)]);
+                }
+            },
+
+            Copy { dest, src, count, data_type } => {
+                let size = data_type.bytes() as u64;
+                self.repeat(count, size, |pcode, offset| {
+                    let from = pcode.unique(N64);
+                    pcode.op(&from, "INT_ADD", &[temp(src), offset.to_string()]);
+                    let value = pcode.unique(data_type);
+                    pcode.op(&value, "LOAD", &["ram".to_string(), from]);
+                    let to = pcode.unique(N64);
+                    pcode.op(&to, "INT_ADD", &[temp(dest), offset.to_string()]);
+                    pcode.op("---", "STORE", &["ram".to_string(), to, value]);
+                });
+            },
+            Fill { dest, value, count } => {
+                self.repeat(count, value.0.bytes() as u64, |pcode, offset| {
+                    let to = pcode.unique(N64);
+                    pcode.op(&to, "INT_ADD", &[temp(dest), offset.to_string()]);
+                    pcode.op("---", "STORE", &["ram".to_string(), to, temp(value)]);
+                });
+            },
+            Unknown { target } => self.user(Some(target), "unknown", &[]),
+            Query { query, target, .. } => match query {
+                crate::ir::Query::Timestamp => self.user(Some(target), "rdtsc", &[]),
+                crate::ir::Query::Random => self.user(Some(target), "rdrand", &[]),
+                crate::ir::Query::Cpuid { leaf, subleaf, index } => {
+                    let index = self.constant(index as u64, N32);
+                    self.user(Some(target), "cpuid", &[temp(leaf), temp(subleaf), index]);
+                },
+            },
+
+            Set { target, ref condition } => {
+                let condition = self.condition(condition);
+                self.op(&temp(target), "INT_ZEXT", &[condition]);
+            },
+            Select { target, ref condition, a, b } => {
+                let condition = self.condition(condition);
+                self.op(&temp(target), "COPY", &[temp(b)]);
+                let negated = self.unique(N8);
+                self.op(&negated, "BOOL_NEGATE", &[condition]);
+                self.op("---", "CBRANCH", &[self.constant(2, N32), negated]);
+                self.op(&temp(target), "COPY", &[temp(a)]);
+            },
+            Jump { target: temporary, ref condition, relative } => {
+                let target = target(&self.constants, temporary, relative, next);
+                let (direct_opcode, indirect_opcode) = match transfer {
+                    Transfer::Call => ("CALL", "CALLIND"),
+                    Transfer::Return => ("BRANCH", "RETURN"),
+                    _ => ("BRANCH", "BRANCHIND"),
+                };
+                let destination = match target {
+                    Target::Direct(addr) => format!("(ram, {:#x}, 8)", addr),
+                    Target::Computed(temporary, relative) => {
+                        if relative {
+                            let addr = self.unique(N64);
+                            let next = self.constant(next, N64);
+                            self.op(&addr, "INT_ADD", &[temp(temporary), next]);
+                            addr
+                        } else {
+                            temp(temporary)
+                        }
+                    },
+                };
+
+                let direct = matches!(target, Target::Direct(_)) && transfer != Transfer::Return;
+                let opcode = if direct { direct_opcode } else { indirect_opcode };
+                if condition == &SymCondition::TRUE {
+                    self.op("---", opcode, &[destination]);
+                } else if direct && opcode == "BRANCH" {
+                    let condition = self.condition(condition);
+                    self.op("---", "CBRANCH", &[destination, condition]);
+                } else {
+                    let condition = self.condition(condition);
+                    let negated = self.unique(N8);
+                    self.op(&negated, "BOOL_NEGATE", &[condition]);
+                    self.op("---", "CBRANCH", &[self.constant(2, N32), negated]);
+                    self.op("---", opcode, &[destination]);
+                }
+            },
+
+            Syscall => self.user(None, "syscall", &[]),
+        }
+    }
+
+    /// Repeat the operations of the body for `count` elements of the size,
+    /// which receives a varnode holding the byte offset of the element.
+    fn repeat<F>(&mut self, count: Temporary, size: u64, body: F)
+    where F: FnOnce(&mut Pcode, &str) {
+        let index = self.unique(N64);
+        let offset = self.unique(N64);
+        let done = self.unique(N8);
+        let wide = self.unique(N64);
+        if count.0 == N64 {
+            self.op(&wide, "COPY", &[temp(count)]);
+        } else {
+            self.op(&wide, "INT_ZEXT", &[temp(count)]);
+        }
+        self.op(&index, "COPY", &[self.constant(0, N64)]);
+
+        let start = self.ops.len();
+        self.op(&done, "INT_LESSEQUAL", &[wide.clone(), index.clone()]);
+        let exit = self.ops.len();
+        self.ops.push(String::new());
+        self.op(&offset, "INT_MULT", &[index.clone(), self.constant(size, N64)]);
+        body(self, &offset);
+        self.op(&index, "INT_ADD", &[index.clone(), self.constant(1, N64)]);
+
+        let back = -((self.ops.len() - start) as i64) as u64;
+        self.op("---", "BRANCH", &[format!("(const, {:#x}, 4)", back & 0xffff_ffff)]);
+        let forward = (self.ops.len() - exit) as u64;
+        self.ops[exit] = format!("--- CBRANCH {} , {}", self.constant(forward, N32), done);
+    }
+
+    /// The boolean varnode of a condition.
+    fn condition(&mut self, condition: &SymCondition) -> String {
+        use SymCondition::*;
+
+        let (opcode, a, b) = match condition {
+            Bool(value) => return self.constant(*value as u64, N8),
+            And(a, b) | Or(a, b) => {
+                let x = self.condition(a);
+                let y = self.condition(b);
+                let opcode = if matches!(condition, And(..)) { "BOOL_AND" } else { "BOOL_OR" };
+                let output = self.unique(N8);
+                self.op(&output, opcode, &[x, y]);
+                return output;
+            },
+            Not(a) => {
+                let x = self.condition(a);
+                let output = self.unique(N8);
+                self.op(&output, "BOOL_NEGATE", &[x]);
+                return output;
+            },
+            Equal(a, b) => ("INT_EQUAL", a, b),
+            LessThan(a, b, signed) => (if *signed { "INT_SLESS" } else { "INT_LESS" }, a, b),
+            LessEqual(a, b, signed) => {
+                (if *signed { "INT_SLESSEQUAL" } else { "INT_LESSEQUAL" }, a, b)
+            },
+            GreaterThan(a, b, signed) => (if *signed { "INT_SLESS" } else { "INT_LESS" }, b, a),
+            GreaterEqual(a, b, signed) => {
+                (if *signed { "INT_SLESSEQUAL" } else { "INT_LESSEQUAL" }, b, a)
+            },
+        };
+
+        let x = self.expr(a);
+        let y = self.expr(b);
+        let output = self.unique(N8);
+        self.op(&output, opcode, &[x, y]);
+        output
+    }
+
+    /// The varnode of the value of an expression.
+    fn expr(&mut self, expr: &SymExpr) -> String {
+        use SymExpr::*;
+
+        let data_type = expr.data_type();
+        let (opcode, a, b) = match expr {
+            Int(int) => return self.constant(int.1, int.0),
+            Sym(Symbol(data_type, "T", index)) => return temp(Temporary(*data_type, *index)),
+            Sym(Symbol(data_type, ..)) => {
+                let output = self.unique(*data_type);
+                self.op(&output, "CALLOTHER", &["\"unknown\"".to_string()]);
+                return output;
+            },
+            Add(a, b) => ("INT_ADD", a, b),
+            Sub(a, b) => ("INT_SUB", a, b),
+            Mul(a, b) => ("INT_MULT", a, b),
+            BitAnd(a, b) => ("INT_AND", a, b),
+            BitOr(a, b) => ("INT_OR", a, b),
+            BitXor(a, b) => ("INT_XOR", a, b),
+            Shl(a, b) => ("INT_LEFT", a, b),
+            Shr(a, b, arithmetic) => (if *arithmetic { "INT_SRIGHT" } else { "INT_RIGHT" }, a, b),
+            BitNot(a) => {
+                let x = self.expr(a);
+                let output = self.unique(data_type);
+                self.op(&output, "INT_NEGATE", &[x]);
+                return output;
+            },
+            Cast(a, new, signed) => {
+                let x = self.expr(a);
+                let output = self.unique(*new);
+                let old = a.data_type().bytes();
+                if new.bytes() < old {
+                    self.op(&output, "SUBPIECE", &[x, self.constant(0, N32)]);
+                } else if new.bytes() > old {
+                    self.op(&output, if *signed { "INT_SEXT" } else { "INT_ZEXT" }, &[x]);
+                } else {
+                    self.op(&output, "COPY", &[x]);
+                }
+                return output;
+            },
+            AsExpr(condition, _) => {
+                let condition = self.condition(condition);
+                let output = self.unique(data_type);
+                self.op(&output, "INT_ZEXT", &[condition]);
+                return output;
+            },
+            IfThenElse(condition, a, b) => {
+                let condition = self.condition(condition);
+                let x = self.expr(a);
+                let y = self.expr(b);
+                let output = self.unique(data_type);
+                self.op(&output, "COPY", &[y]);
+                let negated = self.unique(N8);
+                self.op(&negated, "BOOL_NEGATE", &[condition]);
+                self.op("---", "CBRANCH", &[self.constant(2, N32), negated]);
+                self.op(&output, "COPY", &[x]);
+                return output;
+            },
+        };
+
+        let x = self.expr(a);
+        let y = self.expr(b);
+        let output = self.unique(data_type);
+        self.op(&output, opcode, &[x, y]);
+        output
+    }
+
+    /// The varnode holding the value at the location, loading it if needed.
+    fn read(&mut self, location: Location) -> String {
+        match location {
+            Location::Indirect(data_type, space, pointer) => {
+                let output = self.unique(data_type);
+                self.op(&output, "LOAD", &[space_name(space).to_string(), temp(pointer)]);
+                output
+            },
+            location => varnode(location),
+        }
+    }
+
+    /// Store the value of the varnode at the location.
+    fn write(&mut self, location: Location, value: String) {
+        match location {
+            Location::Indirect(_, space, pointer) => {
+                self.op("---", "STORE", &[space_name(space).to_string(), temp(pointer), value]);
+            },
+            location => self.op(&varnode(location), "COPY", &[value]),
+        }
+    }
+
+    /// Apply a user-defined operation.
+    fn user(&mut self, output: Option<Temporary>, name: &str, inputs: &[String]) {
+        let output = output.map_or("---".to_string(), temp);
+        let mut operands = vec![format!("\"{}\"", name)];
+        operands.extend_from_slice(inputs);
+        self.op(&output, "CALLOTHER", &operands);
+    }
+
+    fn op(&mut self, output: &str, opcode: &str, inputs: &[String]) {
+        let op = format!("{} {} {}", output, opcode, inputs.join(" , "));
+        self.ops.push(op.trim_end().to_string());
+    }
+
+    /// A fresh unique varnode after the ones of the temporaries.
+    fn unique(&mut self, data_type: DataType) -> String {
+        self.uniques += 1;
+        format!("(unique, {:#x}, {})", 0x10_0000 + 0x20 * self.uniques, data_type.bytes())
+    }
+
+    fn constant(&self, value: u64, data_type: DataType) -> String {
+        format!("(const, {:#x}, {})", value, data_type.bytes())
+    }
+}
+
+/// The unique varnode of a temporary.
+fn temp(temp: Temporary) -> String {
+    format!("(unique, {:#x}, {})", 0x20 * temp.1, temp.0.bytes())
+}
+
+/// The varnode of a direct location.
+fn varnode(location: Location) -> String {
+    match location {
+        Location::Temp(t) => temp(t),
+        Location::Direct(data_type, 1, offset) => {
+            format!("(register, {:#x}, {})", ghidra_register(offset), data_type.bytes())
+        },
+        Location::Direct(data_type, _, addr) => {
+            format!("(ram, {:#x}, {})", addr, data_type.bytes())
+        },
+        Location::Indirect(..) => unreachable!("varnode: indirect location"),
+    }
+}
+
+fn space_name(space: usize) -> &'static str {
+    if space == 1 { "register" } else { "ram" }
+}
+
+/// The offset in Ghidra's register space of an offset in the register memory
+/// space of the microcode.
+fn ghidra_register(offset: u64) -> u64 {
+    match offset {
+        0x00 ..= 0x3f => offset,
+        0x40 ..= 0x7f => offset + 0x40,
+        0x80 ..= 0x87 => 0x288 + (offset - 0x80),
+        0x88 ..= 0x8f => 0x1106 + (offset - 0x88),
+        0x90 ..= 0x97 => 0x110 + (offset - 0x90),
+        0x98 ..= 0x9f => 0x118 + (offset - 0x98),
+        _ => 0x1200 + (offset - 0x100),
+    }
+}
+
+/// The BIL statements of one instruction.
+struct Bil {
+    statements: Vec<String>,
+    /// The constants stored in temporaries by index.
+    constants: HashMap<usize, u64>,
+    /// The number of loop counters.
+    loops: usize,
+}
+
+impl Bil {
+    fn microcode(&mut self, microcode: &Microcode, next: u64) {
+        for operation in &microcode.ops {
+            self.operation(operation, next);
+        }
+    }
+
+    fn operation(&mut self, operation: &MicroOperation, next: u64) {
+        use MicroOperation::*;
+
+        match *operation {
+            Mov { dest, src } => {
+                let value = self.read(src);
+                self.write(dest, value);
+            },
+            Const { dest, constant } => {
+                self.constants.insert(dest.1, sign_extend(constant));
+                self.assign(dest, bil_constant(constant.1, dest.0));
+            },
+            Cast { target, new, signed } => {
+                let value = bil_cast(bil_temp(target), target.0, new, signed);
+                self.assign(Temporary(new, target.1), value);
+            },
+
+            Add { sum, a, b } => self.binary(sum, a, "+", b),
+            Sub { diff, a, b } => self.binary(diff, a, "-", b),
+            Mul { prod, a, b } => self.binary(prod, a, "*", b),
+            And { and, a, b } => self.binary(and, a, "&", b),
+            Or { or, a, b } => self.binary(or, a, "|", b),
+            Xor { xor, a, b } => self.binary(xor, a, "^", b),
+            Not { not, a } => self.assign(not, format!("~{}", bil_temp(a))),
+
+            Bits { op: BitOp::Shl, target, a, b } => self.binary(target, a, "<<", b),
+            Bits { op: BitOp::Shr, target, a, b } => self.binary(target, a, ">>", b),
+            Bits { op, target, .. } => {
+                let name = format!("{:?}", op).to_lowercase();
+                self.assign(target, bil_unknown(&name, target.0));
+            },
+            Packed { op, lane, target, .. } => {
+                let name = format!("packed_{:?}_{}", op, lane).to_lowercase();
+                self.assign(target, bil_unknown(&name, target.0));
+            },
+            Float { op, target, .. } => {
+                let name = format!("float_{:?}", op).to_lowercase();
+                self.assign(target, bil_unknown(&name, target.0));
+            },
+
+            Copy { dest, src, count, data_type } => {
+                let size = data_type.bytes() as u64;
+                self.repeat(count, size, |offset| {
+                    let from = format!("{} + {}", bil_temp(src), offset);
+                    let to = format!("{} + {}", bil_temp(dest), offset);
+                    let value = bil_load(&from, data_type);
+                    format!("mem := mem with [{}, el]:u{} <- {}", to, data_type.bits(), value)
+                });
+            },
+            Fill { dest, value, count } => {
+                self.repeat(count, value.0.bytes() as u64, |offset| {
+                    format!("mem := mem with [{} + {}, el]:u{} <- {}",
+                            bil_temp(dest), offset, value.0.bits(), bil_temp(value))
+                });
+            },
+            Unknown { target } => self.assign(target, bil_unknown("unknown", target.0)),
+            Query { query, target, .. } => {
+                let name = match query {
+                    crate::ir::Query::Timestamp => "rdtsc",
+                    crate::ir::Query::Random => "rdrand",
+                    crate::ir::Query::Cpuid { .. } => "cpuid",
+                };
+                self.assign(target, bil_unknown(name, target.0));
+            },
+
+            Set { target, ref condition } => {
+                let value = format!("pad:{}[{}]", target.0.bits(), bil_condition(condition));
+                self.assign(target, value);
+            },
+            Select { target, ref condition, a, b } => {
+                let value = format!("if {} then {} else {}",
+                                    bil_condition(condition), bil_temp(a), bil_temp(b));
+                self.assign(target, value);
+            },
+            Jump { target: temporary, ref condition, relative } => {
+                let destination = match target(&self.constants, temporary, relative, next) {
+                    Target::Direct(addr) => bil_constant(addr, N64),
+                    Target::Computed(temporary, true) => {
+                        format!("{} + {}", bil_temp(temporary), bil_constant(next, N64))
+                    },
+                    Target::Computed(temporary, false) => bil_temp(temporary),
+                };
+                if condition == &SymCondition::TRUE {
+                    self.statements.push(format!("jmp {}", destination));
+                } else {
+                    let mut condition = bil_condition(condition);
+                    if !condition.starts_with('(') {
+                        condition = format!("({})", condition);
+                    }
+                    self.statements.push(format!("if {} {{ jmp {} }}", condition, destination));
+                }
+            },
+
+            Syscall => self.statements.push("special \"syscall\"".to_string()),
+        }
+    }
+
+    /// Repeat the statement built from an expression of the byte offset for
+    /// `count` elements of the size in a loop.
+    fn repeat<F>(&mut self, count: Temporary, size: u64, body: F) where F: FnOnce(&str) -> String {
+        let index = format!("#i{}", self.loops);
+        self.loops += 1;
+        let count = bil_cast(bil_temp(count), count.0, N64, false);
+        let offset = format!("{} * {}", index, bil_constant(size, N64));
+        self.statements.push(format!("{} := {}", index, bil_constant(0, N64)));
+        self.statements.push(format!("while ({} < {}) {{ {}; {} := {} + {} }}",
+                                     index, count, body(&offset),
+                                     index, index, bil_constant(1, N64)));
+    }
+
+    fn binary(&mut self, target: Temporary, a: Temporary, operator: &str, b: Temporary) {
+        self.assign(target, format!("{} {} {}", bil_temp(a), operator, bil_temp(b)));
+    }
+
+    fn assign(&mut self, target: Temporary, value: String) {
+        self.statements.push(format!("{} := {}", bil_temp(target), value));
+    }
+
+    /// The expression of the value at the location.
+    fn read(&mut self, location: Location) -> String {
+        match location {
+            Location::Temp(t) => bil_temp(t),
+            Location::Direct(data_type, 1, offset) => {
+                let (name, low, width) = bil_register(offset);
+                let bits = data_type.bits() as u64;
+                if low == 0 && bits == width {
+                    name
+                } else if low == 0 {
+                    format!("low:{}[{}]", bits, name)
+                } else {
+                    format!("extract:{}:{}[{}]", low + bits - 1, low, name)
+                }
+            },
+            Location::Direct(data_type, _, addr) => {
+                bil_load(&bil_constant(addr, N64), data_type)
+            },
+            Location::Indirect(data_type, _, pointer) => bil_load(&bil_temp(pointer), data_type),
+        }
+    }
+
+    /// Store the value of the expression at the location.
+    fn write(&mut self, location: Location, value: String) {
+        let statement = match location {
+            Location::Temp(t) => format!("{} := {}", bil_temp(t), value),
+            Location::Direct(data_type, 1, offset) => {
+                let (name, low, width) = bil_register(offset);
+                let high = low + data_type.bits() as u64;
+                let mut parts = Vec::new();
+                if high < width {
+                    parts.push(format!("extract:{}:{}[{}]", width - 1, high, name));
+                }
+                parts.push(value);
+                if low > 0 {
+                    parts.push(format!("extract:{}:0[{}]", low - 1, name));
+                }
+                format!("{} := {}", name, parts.join("."))
+            },
+            Location::Direct(data_type, _, addr) => {
+                format!("mem := mem with [{}, el]:u{} <- {}",
+                        bil_constant(addr, N64), data_type.bits(), value)
+            },
+            Location::Indirect(data_type, _, pointer) => {
+                format!("mem := mem with [{}, el]:u{} <- {}",
+                        bil_temp(pointer), data_type.bits(), value)
+            },
+        };
+        self.statements.push(statement);
+    }
+}
+
+/// The virtual variable of a temporary.
+fn bil_temp(temp: Temporary) -> String {
+    format!("#t{}_{}", temp.1, temp.0.bits())
+}
+
+fn bil_constant(value: u64, data_type: DataType) -> String {
+    format!("{:#x}:{}", value, data_type.bits())
+}
+
+fn bil_unknown(name: &str, data_type: DataType) -> String {
+    format!("unknown[{}]:u{}", name, data_type.bits())
+}
+
+fn bil_load(addr: &str, data_type: DataType) -> String {
+    format!("mem[{}, el]:u{}", addr, data_type.bits())
+}
+
+fn bil_cast(value: String, from: DataType, to: DataType, signed: bool) -> String {
+    if from.bits() == to.bits() {
+        value
+    } else if to.bits() < from.bits() {
+        format!("low:{}[{}]", to.bits(), value)
+    } else if signed {
+        format!("extend:{}[{}]", to.bits(), value)
+    } else {
+        format!("pad:{}[{}]", to.bits(), value)
+    }
+}
+
+/// The name, lowest bit and width of the register containing an offset in the
+/// register memory space of the microcode.
+fn bil_register(offset: u64) -> (String, u64, u64) {
+    const NAMES: [&str; 20] = [
+        "RAX", "RCX", "RDX", "RBX", "RSP", "RBP", "RSI", "RDI",
+        "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15",
+        "RIP", "ST0", "FS_BASE", "GS_BASE",
+    ];
+    if offset >= 0x100 {
+        let index = (offset - 0x100) / 0x20;
+        (format!("YMM{}", index), (offset - 0x100 - 0x20 * index) * 8, 256)
+    } else {
+        (NAMES[(offset / 8) as usize].to_string(), (offset % 8) * 8, 64)
+    }
+}
+
+/// The one-bit expression of a condition.
+fn bil_condition(condition: &SymCondition) -> String {
+    use SymCondition::*;
+
+    let (operator, a, b) = match condition {
+        Bool(value) => return format!("{}:1", *value as u8),
+        And(a, b) => return format!("({} & {})", bil_condition(a), bil_condition(b)),
+        Or(a, b) => return format!("({} | {})", bil_condition(a), bil_condition(b)),
+        Not(a) => return format!("~{}", bil_condition(a)),
+        Equal(a, b) => ("=", a, b),
+        LessThan(a, b, signed) => (if *signed { "<$" } else { "<" }, a, b),
+        LessEqual(a, b, signed) => (if *signed { "<=$" } else { "<=" }, a, b),
+        GreaterThan(a, b, signed) => (if *signed { "<$" } else { "<" }, b, a),
+        GreaterEqual(a, b, signed) => (if *signed { "<=$" } else { "<=" }, b, a),
+    };
+    format!("({} {} {})", bil_expr(a), operator, bil_expr(b))
+}
+
+/// The expression of a symbolic expression over temporaries.
+fn bil_expr(expr: &SymExpr) -> String {
+    use SymExpr::*;
+
+    let (operator, a, b) = match expr {
+        Int(int) => return bil_constant(int.1, int.0),
+        Sym(Symbol(data_type, "T", index)) => return bil_temp(Temporary(*data_type, *index)),
+        Sym(Symbol(data_type, ..)) => return bil_unknown("symbol", *data_type),
+        Add(a, b) => ("+", a, b),
+        Sub(a, b) => ("-", a, b),
+        Mul(a, b) => ("*", a, b),
+        BitAnd(a, b) => ("&", a, b),
+        BitOr(a, b) => ("|", a, b),
+        BitXor(a, b) => ("^", a, b),
+        Shl(a, b) => ("<<", a, b),
+        Shr(a, b, arithmetic) => (if *arithmetic { "~>>" } else { ">>" }, a, b),
+        BitNot(a) => return format!("~{}", bil_expr(a)),
+        Cast(a, new, signed) => return bil_cast(bil_expr(a), a.data_type(), *new, *signed),
+        AsExpr(condition, data_type) => {
+            return format!("pad:{}[{}]", data_type.bits(), bil_condition(condition));
+        },
+        IfThenElse(condition, a, b) => {
+            return format!("(if {} then {} else {})",
+                           bil_condition(condition), bil_expr(a), bil_expr(b));
+        },
+    };
+    format!("({} {} {})", bil_expr(a), operator, bil_expr(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::flow::{BasicBlock, ControlFlowNode};
+    use crate::ir::MicroEncoder;
+    use super::*;
+
+    #[test]
+    fn pcode_and_bil() {
+        // mov eax, edi; cmp rdi, rsi; jl 0x1008
+        let mut encoder = MicroEncoder::new();
+        let mut code = Vec::new();
+        let mut addr = 0x1000;
+        for bytes in [&[0x89, 0xf8][..], &[0x48, 0x39, 0xf7], &[0x7c, 0x01]] {
+            let instruction = Instruction::decode(bytes).unwrap();
+            let microcode = encoder.encode(&instruction).unwrap();
+            code.push((addr, bytes.len() as u64, instruction, microcode));
+            addr += bytes.len() as u64;
+        }
+        let block = BasicBlock { addr: 0x1000, len: addr - 0x1000, code };
+        let cfg = ControlFlowGraph::from_parts(
+            vec![ControlFlowNode { addr: 0x1000, trace: Vec::new() }],
+            vec![(0x1000, block)].into_iter().collect(),
+            HashMap::new(),
+            vec![0x1000],
+        );
+
+        let render = |write: &dyn Fn(&mut Vec<u8>) -> io::Result<()>| {
+            let mut f = Vec::new();
+            write(&mut f).unwrap();
+            String::from_utf8(f).unwrap()
+        };
+
+        assert_eq!(render(&|f| cfg.write_pcode(f)), "\
+            0x1000: mov eax, edi\n  \
+            (register, 0x0, 4) COPY (register, 0x38, 4)\n\
+            0x1002: cmp rdi, rsi\n  \
+            (unique, 0x0, 8) COPY (register, 0x38, 8)\n  \
+            (unique, 0x20, 8) COPY (register, 0x30, 8)\n\
+            0x1005: jl +0x1\n  \
+            (unique, 0x40, 8) COPY (const, 0x1, 8)\n  \
+            (unique, 0x100020, 1) INT_SLESS (unique, 0x0, 8) , (unique, 0x20, 8)\n  \
+            --- CBRANCH (ram, 0x1008, 8) , (unique, 0x100020, 1)\n");
+
+        assert_eq!(render(&|f| cfg.write_bil(f)), "\
+            0x1000: mov eax, edi\n{\n  \
+            RAX := extract:63:32[RAX].low:32[RDI]\n}\n\
+            0x1002: cmp rdi, rsi\n{\n  \
+            #t0_64 := RDI\n  \
+            #t1_64 := RSI\n}\n\
+            0x1005: jl +0x1\n{\n  \
+            #t2_64 := 0x1:64\n  \
+            if (#t0_64 <$ #t1_64) { jmp 0x1008:64 }\n}\n");
+    }
+}
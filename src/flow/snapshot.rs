@@ -0,0 +1,283 @@
+//! Canonical text forms of the graphs for comparisons against snapshots.
+//!
+//! Nodes are named by their `NodeKey`, so that the text stays the same when
+//! code moves, and all lines are sorted, so that it does not depend on the
+//! order in which the graph was built. A snapshot lists the nodes and then
+//! the edges with their kinds and, unless they are true, their conditions.
+//!
+//! With the `fs` feature, `check_snapshot` compares a text against a file and
+//! shows the differing lines on a mismatch. Missing snapshot files are a
+//! mismatch as well, unless `SYMFLOW_UPDATE_SNAPSHOTS` is set, which creates
+//! them and rewrites the existing ones.
+
+#[cfg(feature = "fs")]
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+use crate::math::SymCondition;
+use super::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+use super::{DependencyNode, EdgeKind, NodeKey, common_subsequence};
+
+
+impl ControlFlowGraph {
+    /// The graph in canonical text form. Blocks are named by their position
+    /// in the call trace.
+    pub fn snapshot(&self) -> String {
+        let names: Vec<String> = self.nodes.iter()
+            .map(|node| {
+                let sites: Vec<u64> = node.trace.iter().map(|&(site, _)| site).collect();
+                NodeKey::of_block(&self.frames, node.addr, &sites).to_string()
+            })
+            .collect();
+        let edges = self.edges.iter().map(|(&(start, end), condition)| {
+            (start, end, EdgeKind::of_control(self, (start, end)), condition)
+        });
+        write_snapshot(&names, edges)
+    }
+}
+
+impl DataDependencyGraph {
+    /// The graph in canonical text form. Inputs and outputs are named by
+    /// their symbol and constants by their value and the node they flow to.
+    pub fn snapshot(&self) -> String {
+        let mut names: Vec<String> = self.nodes.iter()
+            .map(|node| match node {
+                DependencyNode::Location(location) => {
+                    NodeKey::of_location(&self.frames, location).to_string()
+                },
+                DependencyNode::Io(_, symbol) => symbol.to_string(),
+                DependencyNode::Constant(..) => String::new(),
+            })
+            .collect();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let DependencyNode::Constant(sink, int) = node {
+                names[index] = format!("constant {} for {}", int, names[*sink]);
+            }
+        }
+
+        let edges = self.edges.iter().map(|(&(start, end), (condition, _))| {
+            (start, end, self.edge_kind((start, end)), condition)
+        });
+        write_snapshot(&names, edges)
+    }
+}
+
+impl ProgramDependenceGraph {
+    /// The graph in canonical text form.
+    pub fn snapshot(&self) -> String {
+        let names: Vec<String> = (0 .. self.nodes.len())
+            .map(|index| self.key(index).to_string())
+            .collect();
+        let edges = self.edges.iter()
+            .map(|((start, end), edge)| (start, end, edge.kind, &edge.condition));
+        write_snapshot(&names, edges)
+    }
+}
+
+/// Write the sorted node names and edges. Nodes with the same name, like a
+/// block in different call traces of the program dependence graph, appear
+/// once and so do equal edges between them.
+fn write_snapshot<'a, I>(names: &[String], edges: I) -> String
+where I: Iterator<Item=(usize, usize, EdgeKind, &'a SymCondition)> {
+    let mut nodes: Vec<&str> = names.iter().map(String::as_str).collect();
+    nodes.sort();
+    nodes.dedup();
+
+    let mut lines: Vec<String> = edges
+        .map(|(start, end, kind, condition)| match condition {
+            SymCondition::Bool(true) => {
+                format!("{} -> {} [{}]", names[start], names[end], kind.name())
+            },
+            condition => {
+                format!("{} -> {} [{}] if {}", names[start], names[end], kind.name(), condition)
+            },
+        })
+        .collect();
+    lines.sort();
+    lines.dedup();
+
+    let mut snapshot = String::from("nodes\n");
+    for node in nodes {
+        snapshot.push_str(&format!("  {}\n", node));
+    }
+    snapshot.push_str("edges\n");
+    for line in lines {
+        snapshot.push_str(&format!("  {}\n", line));
+    }
+    snapshot
+}
+
+/// The lines of two texts with the unchanged ones prefixed by a space, the
+/// removed ones by `-` and the added ones by `+`. Runs of unchanged lines
+/// are shortened to the two lines around every change.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // The lines of a longest common subsequence are unchanged and the ones
+    // between them removed or added.
+    let ends = std::iter::once((old.len(), new.len()));
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (a, b) in common_subsequence(&old, &new).into_iter().chain(ends) {
+        lines.extend(old[i .. a].iter().map(|&line| ('-', line)));
+        lines.extend(new[j .. b].iter().map(|&line| ('+', line)));
+        if a < old.len() {
+            lines.push((' ', old[a]));
+        }
+        i = a + 1;
+        j = b + 1;
+    }
+
+    const CONTEXT: usize = 2;
+    let near_change = |index: usize| {
+        let range = index.saturating_sub(CONTEXT) ..= (index + CONTEXT).min(lines.len() - 1);
+        lines[range].iter().any(|&(marker, _)| marker != ' ')
+    };
+    let mut diff = String::new();
+    let mut skipped = false;
+    for (index, &(marker, line)) in lines.iter().enumerate() {
+        if near_change(index) {
+            if skipped {
+                diff.push_str("...\n");
+                skipped = false;
+            }
+            diff.push_str(&format!("{} {}\n", marker, line));
+        } else {
+            skipped = true;
+        }
+    }
+    if skipped {
+        diff.push_str("...\n");
+    }
+    diff
+}
+
+/// Compare a text against the snapshot file, which is written instead if
+/// `SYMFLOW_UPDATE_SNAPSHOTS` is set.
+#[cfg(feature = "fs")]
+pub fn check_snapshot<P: AsRef<Path>>(path: P, actual: &str) -> Result<(), SnapshotMismatch> {
+    let path = path.as_ref();
+    let update = std::env::var_os("SYMFLOW_UPDATE_SNAPSHOTS").is_some();
+    match fs::read_to_string(path) {
+        Ok(expected) if !update => match expected == actual {
+            true => Ok(()),
+            false => {
+                let mut diff = line_diff(&expected, actual);
+                if diff.lines().all(|line| line == "...") {
+                    diff = "only the line endings differ\n".to_string();
+                }
+                Err(SnapshotMismatch { path: path.to_path_buf(), diff })
+            },
+        },
+        Err(err) if !update => Err(SnapshotMismatch {
+            path: path.to_path_buf(),
+            diff: format!("failed to read snapshot: {}\n", err),
+        }),
+        _ => {
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, actual));
+            written.map_err(|err| SnapshotMismatch {
+                path: path.to_path_buf(),
+                diff: format!("failed to write snapshot: {}", err),
+            })
+        },
+    }
+}
+
+/// Compare a text against the snapshot file like `check_snapshot` and panic
+/// with the differences if they do not match.
+#[cfg(feature = "fs")]
+pub fn assert_snapshot<P: AsRef<Path>>(path: P, actual: &str) {
+    if let Err(mismatch) = check_snapshot(path, actual) {
+        panic!("{}", mismatch);
+    }
+}
+
+/// A text that differs from its snapshot.
+#[cfg(feature = "fs")]
+#[derive(Clone, Eq, PartialEq)]
+pub struct SnapshotMismatch {
+    pub path: PathBuf,
+    /// The differences from the snapshot to the text.
+    pub diff: String,
+}
+
+#[cfg(feature = "fs")]
+impl Display for SnapshotMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Snapshot {} differs (set SYMFLOW_UPDATE_SNAPSHOTS to accept):\n{}",
+               self.path.display(), self.diff)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl std::error::Error for SnapshotMismatch {}
+#[cfg(feature = "fs")]
+debug_display!(SnapshotMismatch);
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::math::{SymExpr, Symbol, DataType};
+    use crate::x86_64::Register;
+    use super::*;
+    use super::super::{AbstractLocation, StorageLocation, DependenceNode, PDGEdge, StackFrames};
+
+    #[test]
+    fn snapshot() {
+        let location = |addr, reg| {
+            AbstractLocation::new(addr, vec![], StorageLocation::Direct(reg))
+        };
+        let nodes = vec![
+            DependenceNode::DataDependency(location(0x20, Register::ECX)),
+            DependenceNode::DataDependency(location(0x10, Register::EAX)),
+            DependenceNode::ControlFlow(0x10),
+        ];
+        let input = SymExpr::Sym(Symbol(DataType::N8, "stdin", 0));
+        let one = SymExpr::from_int(DataType::N8, 1);
+        let condition = SymCondition::Equal(Box::new(input), Box::new(one));
+        let edge = |kind, condition| PDGEdge { kind, condition };
+        let edges = vec![
+            (1, 0, edge(EdgeKind::DataDependency, condition)),
+            (2, 1, edge(EdgeKind::ControlFlow, SymCondition::TRUE)),
+        ];
+        let frames = Arc::new(StackFrames::default());
+        let pdg = ProgramDependenceGraph::from_parts(nodes, edges, frames, HashMap::new());
+
+        assert_eq!(pdg.snapshot(), "\
+            nodes\n  +0x10 block\n  +0x10 eax\n  +0x20 ecx\n\
+            edges\n  +0x10 block -> +0x10 eax [control-flow]\n  \
+            +0x10 eax -> +0x20 ecx [data-dependency] if (stdin0:n8 == 0x1:n8)\n");
+    }
+
+    #[test]
+    fn diff() {
+        let expected = "a\nb\nc\nd\ne\nf\ng\n";
+        let actual = "a\nb\nc\nd\nE\nf\ng\nh\n";
+        assert_eq!(line_diff(expected, actual), "...\n  c\n  d\n- e\n+ E\n  f\n  g\n+ h\n");
+        assert_eq!(line_diff("a\n", "a\n"), "...\n");
+
+        #[cfg(feature = "fs")] {
+            let dir = std::env::temp_dir().join(format!("symflow-snap-{}", std::process::id()));
+            let path = dir.join("graph.txt");
+            if std::env::var_os("SYMFLOW_UPDATE_SNAPSHOTS").is_none() {
+                assert!(check_snapshot(&path, expected).is_err());
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, expected).unwrap();
+                assert_eq!(check_snapshot(&path, expected), Ok(()));
+                let mismatch = check_snapshot(&path, actual).unwrap_err();
+                assert_eq!(mismatch.diff, line_diff(expected, actual));
+            } else {
+                assert_eq!(check_snapshot(&path, expected), Ok(()));
+                assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+            }
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
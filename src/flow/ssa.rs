@@ -0,0 +1,642 @@
+//! Static single assignment form of the registers and stack variables.
+//!
+//! The form is built over the nodes of the control flow graph, so every call
+//! context has its own versions of the variables. Each instruction reads and
+//! writes the variables its data flows mention. Memory apart from the
+//! recovered stack variables is not converted and stays in the data
+//! dependency graph.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+
+use crate::ir::MicroOperation;
+use crate::math::DataType;
+use crate::x86_64::Register;
+use super::{ControlFlowGraph, ControlKind, AbstractLocation, StorageLocation, StackVariable};
+use super::{ValueSource, StackFrames};
+
+
+/// A variable in SSA form.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SsaVariable {
+    /// A full (64-bit or vector) register. Writes to parts of it define a new
+    /// version that also uses the previous one.
+    Register(Register),
+    /// A variable in the stack frame of a function.
+    Local(StackVariable),
+}
+
+/// A version of a variable. Version zero is the value at the entry of the
+/// program.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SsaValue {
+    pub variable: SsaVariable,
+    pub version: usize,
+}
+
+/// Where a value is defined.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SsaDefinition {
+    /// The value the variable has at the entry of the program.
+    Entry,
+    /// A phi node at the start of the node joining the values flowing in
+    /// from the predecessors, in the order of the incoming edges of the graph.
+    Phi { node: usize, operands: Vec<SsaValue> },
+    /// An instruction in the node.
+    Instruction { node: usize, addr: u64 },
+    /// The summarized effect of an edge that is not regular control flow,
+    /// like the return value of an allocator or the registers restored by
+    /// `longjmp`.
+    Edge { from: usize, to: usize },
+}
+
+/// A place where a value is used.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SsaUse {
+    /// An operand of the phi node at the start of the node defining `result`.
+    Phi { node: usize, result: SsaValue },
+    /// An instruction in the node.
+    Instruction { node: usize, addr: u64 },
+}
+
+/// The values an instruction reads and writes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SsaInstruction {
+    /// The address of the instruction.
+    pub addr: u64,
+    /// The values read by the instruction.
+    pub uses: Vec<SsaValue>,
+    /// The values written by the instruction.
+    pub defs: Vec<SsaValue>,
+}
+
+/// The SSA form of a control flow graph.
+#[derive(Debug, Clone, Default)]
+pub struct SsaForm {
+    /// The values defined by phi nodes at the start of the nodes.
+    pub phis: Vec<Vec<SsaValue>>,
+    /// The instructions of the nodes. Nodes unreachable from the entry have none.
+    pub instructions: Vec<Vec<SsaInstruction>>,
+    /// The definitions of all values.
+    definitions: HashMap<SsaValue, SsaDefinition>,
+    /// The places where the values are used.
+    uses: HashMap<SsaValue, Vec<SsaUse>>,
+}
+
+impl SsaForm {
+    /// Build the SSA form of the graph.
+    pub fn new(cfg: &ControlFlowGraph) -> SsaForm {
+        crate::timings::with("ssa", || SsaBuilder::new(cfg).run())
+    }
+
+    /// Where the value is defined.
+    pub fn definition(&self, value: SsaValue) -> Option<&SsaDefinition> {
+        match value.version {
+            0 => Some(&SsaDefinition::Entry),
+            _ => self.definitions.get(&value),
+        }
+    }
+
+    /// The places where the value is used.
+    pub fn uses(&self, value: SsaValue) -> &[SsaUse] {
+        self.uses.get(&value).map(|uses| uses.as_slice()).unwrap_or(&[])
+    }
+
+    /// All def-use chains as pairs of a value and its uses, ordered by value.
+    pub fn chains(&self) -> impl Iterator<Item=(SsaValue, &[SsaUse])> {
+        let mut values: Vec<SsaValue> = self.uses.keys().copied().collect();
+        values.sort_unstable();
+        values.into_iter().map(move |value| (value, self.uses[&value].as_slice()))
+    }
+
+    /// The instruction at the address in the node if the node is reachable.
+    pub fn instruction(&self, node: usize, addr: u64) -> Option<&SsaInstruction> {
+        self.instructions.get(node)?.iter().find(|instruction| instruction.addr == addr)
+    }
+
+    /// The operands of the phi node defining the value if it is one.
+    pub fn phi_operands(&self, value: SsaValue) -> Option<&[SsaValue]> {
+        match self.definitions.get(&value) {
+            Some(SsaDefinition::Phi { operands, .. }) => Some(operands),
+            _ => None,
+        }
+    }
+}
+
+impl SsaVariable {
+    /// The variable a storage location refers to if it is a register or a
+    /// stack variable, where `location` is the abstract location of the
+    /// storage in its context.
+    pub fn from_location(frames: &StackFrames, location: &AbstractLocation) -> Option<SsaVariable> {
+        match location.storage {
+            StorageLocation::Direct(Register::RIP) | StorageLocation::Flag(_) => None,
+            StorageLocation::Direct(reg) => Some(SsaVariable::Register(reg.base())),
+            StorageLocation::Indirect { .. } => frames.variable(location).map(SsaVariable::Local),
+        }
+    }
+}
+
+/// The variables read and written by an instruction as (address, uses, defs).
+pub(super) type Access = (u64, Vec<SsaVariable>, Vec<SsaVariable>);
+
+/// Builds the SSA form with the algorithm of Cytron et al.
+struct SsaBuilder<'g> {
+    cfg: &'g ControlFlowGraph,
+    /// The variables accessed by the instructions of each node.
+    accesses: Vec<Vec<Access>>,
+    /// The immediate dominators of the reachable nodes.
+    idom: Vec<Option<usize>>,
+    /// The reachable nodes in reverse postorder.
+    order: Vec<usize>,
+    form: SsaForm,
+    /// The variables with phi nodes at the start of each node.
+    phi_variables: Vec<BTreeSet<SsaVariable>>,
+    /// The last version of each variable.
+    versions: HashMap<SsaVariable, usize>,
+}
+
+impl<'g> SsaBuilder<'g> {
+    fn new(cfg: &'g ControlFlowGraph) -> SsaBuilder<'g> {
+        let count = cfg.nodes.len();
+        let accesses = (0 .. count).map(|node| node_accesses(cfg, node)).collect();
+        let (order, idom) = dominators(cfg);
+        SsaBuilder {
+            cfg,
+            accesses,
+            idom,
+            order,
+            form: SsaForm {
+                phis: vec![Vec::new(); count],
+                instructions: vec![Vec::new(); count],
+                .. SsaForm::default()
+            },
+            phi_variables: vec![BTreeSet::new(); count],
+            versions: HashMap::new(),
+        }
+    }
+
+    fn run(mut self) -> SsaForm {
+        if !self.order.is_empty() {
+            self.place_phis();
+            self.rename();
+        }
+        self.form
+    }
+
+    /// Insert phi nodes at the iterated dominance frontiers of the definitions.
+    fn place_phis(&mut self) {
+        let frontiers = frontiers(self.cfg, &self.order, &self.idom);
+
+        let mut defining: HashMap<SsaVariable, BTreeSet<usize>> = HashMap::new();
+        for &node in &self.order {
+            for (_, _, defs) in &self.accesses[node] {
+                for &variable in defs {
+                    defining.entry(variable).or_default().insert(node);
+                }
+            }
+
+            // Values defined by summarized edges are joined at their targets.
+            for &pred in &self.cfg.incoming[node] {
+                for reg in edge_defs(self.cfg.edge_kind((pred, node))) {
+                    let variable = SsaVariable::Register(reg);
+                    self.phi_variables[node].insert(variable);
+                    defining.entry(variable).or_default().insert(node);
+                }
+            }
+        }
+
+        for (variable, nodes) in defining {
+            let mut worklist: Vec<usize> = nodes.iter().copied().collect();
+            let mut placed = BTreeSet::new();
+            while let Some(node) = worklist.pop() {
+                for &frontier in &frontiers[node] {
+                    if placed.insert(frontier) {
+                        self.phi_variables[frontier].insert(variable);
+                        if !nodes.contains(&frontier) {
+                            worklist.push(frontier);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number the definitions in a walk over the dominator tree.
+    fn rename(&mut self) {
+        let count = self.cfg.nodes.len();
+        let mut children = vec![Vec::new(); count];
+        for &node in self.order.iter().skip(1) {
+            if let Some(idom) = self.idom[node] {
+                children[idom].push(node);
+            }
+        }
+        for nodes in &mut children {
+            nodes.sort_unstable();
+        }
+
+        let mut phi_operands: HashMap<(usize, SsaVariable), Vec<SsaValue>> = HashMap::new();
+        for &node in &self.order {
+            for &variable in &self.phi_variables[node] {
+                let entry = SsaValue { variable, version: 0 };
+                let operands = vec![entry; self.cfg.incoming[node].len()];
+                phi_operands.insert((node, variable), operands);
+            }
+        }
+
+        // The current values of the variables and, per visited node, the
+        // values to restore when leaving its subtree.
+        let mut current: HashMap<SsaVariable, SsaValue> = HashMap::new();
+        let mut stack = vec![(self.order[0], None)];
+        while let Some((node, restore)) = stack.pop() {
+            if let Some(restore) = restore {
+                current = restore;
+                continue;
+            }
+            stack.push((node, Some(current.clone())));
+
+            for &variable in &self.phi_variables[node].clone() {
+                let value = self.define(variable, SsaDefinition::Phi { node, operands: vec![] });
+                self.form.phis[node].push(value);
+                current.insert(variable, value);
+            }
+
+            for (addr, uses, defs) in self.accesses[node].clone() {
+                let uses: Vec<SsaValue> = uses.iter()
+                    .map(|&variable| value_of(&current, variable))
+                    .collect();
+                for &value in &uses {
+                    self.form.uses.entry(value).or_default()
+                        .push(SsaUse::Instruction { node, addr });
+                }
+
+                let defs: Vec<SsaValue> = defs.iter().map(|&variable| {
+                    let value = self.define(variable, SsaDefinition::Instruction { node, addr });
+                    current.insert(variable, value);
+                    value
+                }).collect();
+
+                self.form.instructions[node].push(SsaInstruction { addr, uses, defs });
+            }
+
+            // Fill in the operands of the phi nodes of the successors.
+            for &succ in &self.cfg.outgoing[node] {
+                let index = match self.cfg.incoming[succ].iter().position(|&pred| pred == node) {
+                    Some(index) => index,
+                    None => continue,
+                };
+
+                let mut flowing = current.clone();
+                for reg in edge_defs(self.cfg.edge_kind((node, succ))) {
+                    let variable = SsaVariable::Register(reg);
+                    let value = self.define(variable, SsaDefinition::Edge { from: node, to: succ });
+                    flowing.insert(variable, value);
+                }
+
+                for &variable in &self.phi_variables[succ] {
+                    let value = value_of(&flowing, variable);
+                    if let Some(operands) = phi_operands.get_mut(&(succ, variable)) {
+                        operands[index] = value;
+                    }
+                }
+            }
+
+            for &child in children[node].iter().rev() {
+                stack.push((child, None));
+            }
+        }
+
+        // Record the operands of the phi nodes and their uses.
+        for node in 0 .. count {
+            for &result in &self.form.phis[node] {
+                let operands = phi_operands.remove(&(node, result.variable)).unwrap_or_default();
+                for &operand in &operands {
+                    self.form.uses.entry(operand).or_default()
+                        .push(SsaUse::Phi { node, result });
+                }
+                self.form.definitions.insert(result, SsaDefinition::Phi { node, operands });
+            }
+        }
+    }
+
+    /// Create a new version of the variable with the definition.
+    fn define(&mut self, variable: SsaVariable, definition: SsaDefinition) -> SsaValue {
+        let version = self.versions.entry(variable).or_insert(0);
+        *version += 1;
+        let value = SsaValue { variable, version: *version };
+        self.form.definitions.insert(value, definition);
+        value
+    }
+}
+
+/// The current value of a variable or its entry value.
+fn value_of(current: &HashMap<SsaVariable, SsaValue>, variable: SsaVariable) -> SsaValue {
+    current.get(&variable).copied().unwrap_or(SsaValue { variable, version: 0 })
+}
+
+/// The variables read and written by the instructions of the node.
+pub(super) fn node_accesses(cfg: &ControlFlowGraph, node: usize) -> Vec<Access> {
+    let trace: Vec<u64> = cfg.nodes[node].trace.iter().map(|&(site, _)| site).collect();
+    let block = &cfg.blocks[&cfg.nodes[node].addr];
+
+    block.code.iter().map(|(addr, _, _, microcode)| {
+        let variable = |storage| {
+            let location = AbstractLocation::new(*addr, trace.clone(), storage);
+            SsaVariable::from_location(&cfg.frames, &location)
+        };
+
+        let mut uses = Vec::new();
+        let mut defs = Vec::new();
+        for &(source, sink) in &microcode.flows {
+            let source = match source {
+                ValueSource::Storage(source) => Some(source),
+                ValueSource::Const(_) => None,
+            };
+
+            // Memory accesses read the registers of their address.
+            for storage in source.iter().chain(Some(&sink)) {
+                if let StorageLocation::Indirect { base, scaled_offset, .. } = *storage {
+                    add_variable(&mut uses, variable(StorageLocation::Direct(base)));
+                    if let Some((index, _)) = scaled_offset {
+                        add_variable(&mut uses, variable(StorageLocation::Direct(index)));
+                    }
+                }
+            }
+
+            if let Some(source) = source {
+                add_variable(&mut uses, variable(source));
+            }
+
+            // Writing part of a register keeps the rest of it.
+            if let StorageLocation::Direct(reg) = sink {
+                if reg != reg.base() && reg.data_type() != DataType::N32 {
+                    add_variable(&mut uses, variable(sink));
+                }
+            }
+            add_variable(&mut defs, variable(sink));
+        }
+
+        // System calls take their arguments in registers and clobber some.
+        if microcode.ops.contains(&MicroOperation::Syscall) {
+            use Register::*;
+            for &reg in &[RAX, RDI, RSI, RDX, R10, R8, R9] {
+                add_variable(&mut uses, Some(SsaVariable::Register(reg)));
+            }
+            for &reg in &[RAX, RCX, R11] {
+                add_variable(&mut defs, Some(SsaVariable::Register(reg)));
+            }
+        }
+
+        (*addr, uses, defs)
+    }).collect()
+}
+
+/// Add the variable to the list if it is one and not in there yet.
+fn add_variable(list: &mut Vec<SsaVariable>, variable: Option<SsaVariable>) {
+    if let Some(variable) = variable {
+        if !list.contains(&variable) {
+            list.push(variable);
+        }
+    }
+}
+
+/// The registers written by the summarized effect of an edge kind.
+pub(super) fn edge_defs(kind: ControlKind) -> Vec<Register> {
+    use Register::*;
+    match kind {
+        ControlKind::Flow => vec![],
+        ControlKind::Exception => vec![RSP, RAX, RDX],
+        ControlKind::Signal { .. } | ControlKind::Thread { .. } => vec![RSP, RDI],
+        ControlKind::SetJump { .. }
+        | ControlKind::Allocate { .. }
+        | ControlKind::Input { .. }
+        | ControlKind::Spawn { .. } => vec![RSP, RAX],
+        ControlKind::LongJump { .. } => vec![RBX, RBP, R12, R13, R14, R15, RSP, RAX],
+        ControlKind::Finalize { .. } | ControlKind::Check { .. } => vec![RSP],
+        ControlKind::Recursion { clobbered, .. } | ControlKind::Summary { clobbered, .. } => {
+            std::iter::once(RSP).chain(clobbered.iter()).collect()
+        },
+    }
+}
+
+/// The nodes reachable from the entry in reverse postorder and their
+/// immediate dominators, computed with the algorithm of Cooper, Harvey and
+/// Kennedy. The entry is its own dominator.
+pub(super) fn dominators(cfg: &ControlFlowGraph) -> (Vec<usize>, Vec<Option<usize>>) {
+    if cfg.nodes.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    dominator_tree(0, &cfg.outgoing, &cfg.incoming)
+}
+
+/// The nodes reachable from the root in reverse postorder and their
+/// immediate dominators in the graph given by the successors and
+/// predecessors of each node.
+pub(super) fn dominator_tree(
+    root: usize,
+    successors: &[Vec<usize>],
+    predecessors: &[Vec<usize>],
+) -> (Vec<usize>, Vec<Option<usize>>) {
+    let count = successors.len();
+    let mut postorder = Vec::new();
+    let mut visited = vec![false; count];
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        match successors[node].get(*next) {
+            Some(&succ) => {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            },
+            None => {
+                postorder.push(node);
+                stack.pop();
+            },
+        }
+    }
+
+    let mut rank = vec![usize::MAX; count];
+    for (index, &node) in postorder.iter().enumerate() {
+        rank[node] = index;
+    }
+
+    let mut idom = vec![None; count];
+    idom[root] = Some(root);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in postorder.iter().rev().skip(1) {
+            let mut new_idom = None;
+            for &pred in &predecessors[node] {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(other) => intersect(&idom, &rank, pred, other),
+                });
+            }
+            if new_idom.is_some() && idom[node] != new_idom {
+                idom[node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    postorder.reverse();
+    (postorder, idom)
+}
+
+/// The dominance frontiers of the nodes in the order, given their immediate
+/// dominators.
+pub(super) fn frontiers(
+    cfg: &ControlFlowGraph,
+    order: &[usize],
+    idom: &[Option<usize>],
+) -> Vec<BTreeSet<usize>> {
+    let mut frontiers = vec![BTreeSet::new(); cfg.nodes.len()];
+    for &node in order {
+        let preds: Vec<usize> = cfg.incoming[node].iter()
+            .copied()
+            .filter(|&pred| idom[pred].is_some())
+            .collect();
+        if preds.len() < 2 {
+            continue;
+        }
+        for pred in preds {
+            let mut runner = pred;
+            while Some(runner) != idom[node] {
+                frontiers[runner].insert(node);
+                match idom[runner] {
+                    Some(next) if next != runner => runner = next,
+                    _ => break,
+                }
+            }
+        }
+    }
+    frontiers
+}
+
+/// The nearest common dominator of two nodes.
+fn intersect(idom: &[Option<usize>], rank: &[usize], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while rank[a] < rank[b] {
+            a = idom[a].unwrap();
+        }
+        while rank[b] < rank[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+impl Display for SsaVariable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SsaVariable::Register(reg) => write!(f, "{}", reg),
+            SsaVariable::Local(variable) if variable.offset < 0 => {
+                write!(f, "[{:x}-{:#x}]", variable.function, -variable.offset)
+            },
+            SsaVariable::Local(variable) => {
+                write!(f, "[{:x}+{:#x}]", variable.function, variable.offset)
+            },
+        }
+    }
+}
+
+impl Display for SsaValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.variable, self.version)
+    }
+}
+
+impl Display for SsaForm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let list = |values: &[SsaValue]| values.iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        for (node, instructions) in self.instructions.iter().enumerate() {
+            if instructions.is_empty() && self.phis[node].is_empty() {
+                continue;
+            }
+            writeln!(f, "b{}:", node)?;
+            for &value in &self.phis[node] {
+                let operands = self.phi_operands(value).unwrap_or(&[]);
+                writeln!(f, "    {} = phi({})", value, list(operands))?;
+            }
+            for instruction in instructions {
+                writeln!(f, "    {:x}: {} <- {}", instruction.addr,
+                    list(&instruction.defs), list(&instruction.uses))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+pub(super) mod tests {
+    use crate::ir::MicroEncoder;
+    use crate::math::SymCondition;
+    use crate::x86_64::Instruction;
+    use super::*;
+    use super::super::{BasicBlock, ControlFlowNode};
+
+    /// A graph of the blocks given as (address, instruction bytes) with the edges.
+    pub(in crate::flow) fn graph(
+        blocks: &[(u64, &[&[u8]])],
+        edges: &[(usize, usize)],
+    ) -> ControlFlowGraph {
+        let mut encoder = MicroEncoder::new();
+        let mut nodes = Vec::new();
+        let mut parsed = HashMap::new();
+        for &(addr, instructions) in blocks {
+            let mut code = Vec::new();
+            let mut next = addr;
+            for bytes in instructions {
+                let instruction = Instruction::decode(bytes).unwrap();
+                let microcode = encoder.encode(&instruction).unwrap();
+                code.push((next, bytes.len() as u64, instruction, microcode));
+                next += bytes.len() as u64;
+            }
+            parsed.insert(addr, BasicBlock { addr, len: next - addr, code });
+            nodes.push(ControlFlowNode { addr, trace: Vec::new() });
+        }
+
+        let edges = edges.iter().map(|&edge| (edge, SymCondition::TRUE)).collect();
+        ControlFlowGraph::from_parts(nodes, parsed, edges, vec![blocks[0].0])
+    }
+
+    #[test]
+    fn diamond() {
+        // b0: mov eax, edi    b1: add eax, 1    b2: mov eax, 2    b3: mov edx, eax
+        let cfg = graph(&[
+            (0x1000, &[&[0x89, 0xf8]]),
+            (0x1010, &[&[0x83, 0xc0, 0x01]]),
+            (0x1020, &[&[0xb8, 0x02, 0x00, 0x00, 0x00]]),
+            (0x1030, &[&[0x89, 0xc2]]),
+        ], &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+
+        let ssa = SsaForm::new(&cfg);
+        let rax = |version| SsaValue { variable: SsaVariable::Register(Register::RAX), version };
+        let rdi = SsaValue { variable: SsaVariable::Register(Register::RDI), version: 0 };
+
+        assert_eq!(ssa.instruction(0, 0x1000).unwrap().uses, vec![rdi]);
+        assert_eq!(ssa.instruction(0, 0x1000).unwrap().defs, vec![rax(1)]);
+        assert_eq!(ssa.instruction(1, 0x1010).unwrap().defs, vec![rax(2)]);
+        assert_eq!(ssa.phis[3], vec![rax(4)]);
+        assert_eq!(ssa.phi_operands(rax(4)).unwrap(), &[rax(2), rax(3)]);
+        assert_eq!(ssa.instruction(3, 0x1030).unwrap().uses, vec![rax(4)]);
+
+        let definition = SsaDefinition::Instruction { node: 2, addr: 0x1020 };
+        assert_eq!(ssa.definition(rax(3)), Some(&definition));
+        assert_eq!(ssa.uses(rax(1)), &[SsaUse::Instruction { node: 1, addr: 0x1010 }]);
+        assert_eq!(ssa.uses(rax(2)), &[SsaUse::Phi { node: 3, result: rax(4) }]);
+        assert!(ssa.phis[1].is_empty() && ssa.phis[2].is_empty());
+        assert!(ssa.to_string().contains("rax.4 = phi(rax.2, rax.3)"));
+    }
+}
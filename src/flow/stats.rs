@@ -0,0 +1,238 @@
+//! Statistics about the construction of the flow graphs.
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+#[cfg(feature = "fs")]
+use std::time::Instant;
+
+use crate::math::SolverStats;
+use super::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+
+
+/// The measurements of the phases of an analysis.
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisStats {
+    /// The phases in the order they ran.
+    pub phases: Vec<PhaseStats>,
+    /// The peak resident memory of the process in bytes if it is known,
+    /// since the last `reset_memory` if there was one.
+    pub memory: Option<u64>,
+}
+
+/// The measurements of a single phase.
+#[derive(Debug, Clone)]
+pub struct PhaseStats {
+    pub name: &'static str,
+    /// The wall time of the phase, which is zero without the `fs` feature.
+    pub time: Duration,
+    /// The solver queries made during the phase.
+    pub solver: SolverStats,
+    /// The number of nodes of the graph built by the phase.
+    pub nodes: usize,
+    /// The number of edges of the graph built by the phase.
+    pub edges: usize,
+}
+
+/// A graph whose size is recorded in the statistics.
+pub trait GraphSize {
+    /// The number of nodes and edges.
+    fn size(&self) -> (usize, usize);
+}
+
+impl AnalysisStats {
+    pub fn new() -> AnalysisStats {
+        AnalysisStats::default()
+    }
+
+    /// Run a phase building a graph and record its measurements.
+    ///
+    /// The solver queries are counted for the whole process, so they include
+    /// the queries of other analyses running at the same time.
+    pub fn measure<T, F>(&mut self, name: &'static str, f: F) -> T
+    where T: GraphSize, F: FnOnce() -> T {
+        let solver = SolverStats::total();
+        let (graph, time) = timed(f);
+        let (nodes, edges) = graph.size();
+
+        self.phases.push(PhaseStats {
+            name,
+            time,
+            solver: SolverStats::total().since(solver),
+            nodes,
+            edges,
+        });
+        self.memory = memory_high_water().or(self.memory);
+        graph
+    }
+
+    /// Reset the peak resident memory of the process to the current one, so
+    /// that the measured peak only covers the phases that follow. Returns
+    /// whether the peak could be reset, which is only possible on Linux.
+    pub fn reset_memory(&mut self) -> bool {
+        self.memory = None;
+        reset_memory_high_water()
+    }
+
+    /// The total wall time of all phases.
+    pub fn time(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.time).sum()
+    }
+
+    /// The solver queries of all phases.
+    pub fn solver(&self) -> SolverStats {
+        SolverStats {
+            queries: self.phases.iter().map(|phase| phase.solver.queries).sum(),
+            time: self.phases.iter().map(|phase| phase.solver.time).sum(),
+        }
+    }
+
+    /// The highest node count of any phase.
+    pub fn peak_nodes(&self) -> usize {
+        self.phases.iter().map(|phase| phase.nodes).max().unwrap_or(0)
+    }
+
+    /// The highest edge count of any phase.
+    pub fn peak_edges(&self) -> usize {
+        self.phases.iter().map(|phase| phase.edges).max().unwrap_or(0)
+    }
+}
+
+impl Display for AnalysisStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{:<8} {:>12} {:>8} {:>12} {:>8} {:>8}",
+                 "phase", "time", "queries", "query time", "nodes", "edges")?;
+        for phase in &self.phases {
+            writeln!(f, "{:<8} {:>12} {:>8} {:>12} {:>8} {:>8}",
+                     phase.name,
+                     format!("{:.2?}", phase.time),
+                     phase.solver.queries,
+                     format!("{:.2?}", phase.solver.time),
+                     phase.nodes,
+                     phase.edges)?;
+        }
+
+        let solver = self.solver();
+        writeln!(f, "{:<8} {:>12} {:>8} {:>12} {:>8} {:>8}",
+                 "total",
+                 format!("{:.2?}", self.time()),
+                 solver.queries,
+                 format!("{:.2?}", solver.time),
+                 self.peak_nodes(),
+                 self.peak_edges())?;
+
+        match self.memory {
+            Some(bytes) => writeln!(f, "peak memory: {:.1} MiB", bytes as f64 / (1 << 20) as f64),
+            None => writeln!(f, "peak memory: unknown"),
+        }
+    }
+}
+
+impl GraphSize for ControlFlowGraph {
+    fn size(&self) -> (usize, usize) {
+        (self.nodes.len(), self.edges.len())
+    }
+}
+
+impl GraphSize for DataDependencyGraph {
+    fn size(&self) -> (usize, usize) {
+        (self.nodes.len(), self.edges.len())
+    }
+}
+
+impl GraphSize for ProgramDependenceGraph {
+    fn size(&self) -> (usize, usize) {
+        (self.nodes.len(), self.edges.len())
+    }
+}
+
+/// A phase that failed is recorded without nodes and edges.
+impl<T: GraphSize, E> GraphSize for Result<T, E> {
+    fn size(&self) -> (usize, usize) {
+        self.as_ref().map_or((0, 0), GraphSize::size)
+    }
+}
+
+/// Run the function and measure its wall time. Without the `fs` feature,
+/// which WebAssembly builds lack, there is no clock and the time is zero.
+#[cfg(feature = "fs")]
+fn timed<T, F: FnOnce() -> T>(f: F) -> (T, Duration) {
+    let started = Instant::now();
+    let value = f();
+    (value, started.elapsed())
+}
+
+#[cfg(not(feature = "fs"))]
+fn timed<T, F: FnOnce() -> T>(f: F) -> (T, Duration) {
+    (f(), Duration::default())
+}
+
+/// The peak resident memory of the process in bytes, which is only known on
+/// Linux.
+#[cfg(feature = "fs")]
+fn memory_high_water() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_high_water(&status)
+}
+
+#[cfg(not(feature = "fs"))]
+fn memory_high_water() -> Option<u64> {
+    None
+}
+
+/// Reset the peak resident memory of the process, which is only possible on
+/// Linux.
+#[cfg(feature = "fs")]
+fn reset_memory_high_water() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+#[cfg(not(feature = "fs"))]
+fn reset_memory_high_water() -> bool {
+    false
+}
+
+/// Find the peak resident memory in the contents of `/proc/self/status`.
+#[cfg(any(feature = "fs", test))]
+fn parse_high_water(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line["VmHWM:".len() ..].trim().trim_end_matches("kB").trim();
+    kilobytes.parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Graph(usize, usize);
+
+    impl GraphSize for Graph {
+        fn size(&self) -> (usize, usize) {
+            (self.0, self.1)
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = AnalysisStats::new();
+        stats.measure("cfg", || Graph(10, 12));
+        stats.measure("ddg", || Graph(30, 8));
+        stats.memory = Some(3 << 20);
+
+        assert_eq!(stats.phases.len(), 2);
+        assert_eq!(stats.peak_nodes(), 30);
+        assert_eq!(stats.peak_edges(), 12);
+        assert_eq!(stats.time(), stats.phases[0].time + stats.phases[1].time);
+
+        let report = stats.to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].starts_with("ddg "));
+        assert!(lines[3].starts_with("total ") && lines[3].ends_with("      30       12"));
+        assert_eq!(lines[4], "peak memory: 3.0 MiB");
+
+        let status = "Name:\tcargo\nVmPeak:\t  1000 kB\nVmHWM:\t    2048 kB\n";
+        assert_eq!(parse_high_water(status), Some(2 << 20));
+        assert_eq!(parse_high_water("Name:\tcargo\n"), None);
+    }
+}
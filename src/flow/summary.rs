@@ -0,0 +1,575 @@
+//! Dependence summaries of functions.
+//!
+//! A summary tells which outputs of a function depend on which of its inputs:
+//! the return value, global data and the memory the caller passes pointers
+//! to on the one side and the arguments, global data, caller memory and
+//! standard input on the other. They are computed from the data dependency
+//! graph of a program, where the inputs of a function are the locations in
+//! it with data flowing in from outside and the outputs the ones with data
+//! flowing out.
+//!
+//! Summaries are written to JSON files, which later runs load through the
+//! configuration, like summaries published for common libraries. Calls of a
+//! summarized function are not entered: they return through the summary with
+//! unknown values in the clobbered registers and a return value depending on
+//! the register arguments it depends on. Writes to memory are not modeled.
+//!
+//! A file is a JSON object with the array `functions` of objects like
+//! `{"name": "add", "clobbered": ["rax"], "dependences": [{"output":
+//! "result", "inputs": ["arg0", "arg1"], "conditional": false}]}`. Inputs
+//! and outputs are named `arg0`, `result`, `global <name>`, `memory`,
+//! `stdin` and `stdout`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+use std::io::{self, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::Program;
+use crate::helper::json_string;
+use crate::json::Json;
+use crate::math::SymCondition;
+use crate::sym::{CallingConvention, StdioKind};
+use crate::x86_64::{Register, REGISTERS};
+use super::{ControlFlowGraph, DataDependencyGraph, DependencyNode, AbstractLocation};
+use super::{StorageLocation, CallGraph, RegisterSet};
+
+
+/// The summaries of functions by their symbol names.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Summaries {
+    pub functions: BTreeMap<String, FunctionSummary>,
+}
+
+/// What a function changes and how its outputs depend on its inputs.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FunctionSummary {
+    /// The registers the function may change, except for the stack pointer.
+    pub clobbered: RegisterSet,
+    /// The dependences of the outputs, one per output and sorted by it.
+    pub dependences: Vec<Dependence>,
+}
+
+/// The inputs an output of a function depends on.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Dependence {
+    pub output: Port,
+    pub inputs: BTreeSet<Port>,
+    /// Whether the data only flows under conditions decided in the function.
+    pub conditional: bool,
+}
+
+/// An input or output of a function.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Port {
+    /// The argument with the index in the calling convention of the function.
+    Argument(usize),
+    /// The return value.
+    Result,
+    /// Global data by its symbol name or hexadecimal address.
+    Global(String),
+    /// Memory outside of the frame of the function, which the caller passes
+    /// pointers to.
+    Memory,
+    Stdin,
+    Stdout,
+}
+
+impl Summaries {
+    /// Compute the summaries of the functions entered in the graphs.
+    pub fn compute(
+        program: &Program,
+        cfg: &ControlFlowGraph,
+        ddg: &DataDependencyGraph,
+    ) -> Summaries {
+        let lengths: HashMap<u64, u64> = program.code.iter()
+            .map(|&(addr, len, ..)| (addr, len))
+            .collect();
+        let calls = CallGraph::new(program);
+
+        let mut functions = BTreeMap::new();
+        for (&function, frame) in &cfg.frames.frames {
+            let convention = cfg.config.convention(function);
+            let clobbered = calls.clobbered(function).iter()
+                .filter(|&reg| reg != Register::RSP && !convention.is_preserved(reg))
+                .collect();
+            let boundary = Boundary { program, cfg, ddg, function, convention, lengths: &lengths };
+            let summary = FunctionSummary { clobbered, dependences: boundary.dependences() };
+            functions.insert(frame.name.clone(), summary);
+        }
+        Summaries { functions }
+    }
+
+    /// The summary of the function with the symbol name if there is one.
+    pub fn get(&self, name: &str) -> Option<&FunctionSummary> {
+        self.functions.get(name)
+    }
+
+    /// Parse summaries from JSON.
+    pub fn from_json(text: &str) -> Result<Summaries, SummaryError> {
+        let json = Json::parse(text).map_err(|err| SummaryError::new(err.to_string()))?;
+        let functions = json.get("functions")
+            .and_then(Json::as_array)
+            .ok_or_else(|| SummaryError::new("\"functions\" is not an array".to_string()))?;
+
+        let mut summaries = Summaries::default();
+        for function in functions {
+            let name = function.get("name")
+                .and_then(Json::as_str)
+                .ok_or_else(|| SummaryError::new("function without \"name\"".to_string()))?;
+            let summary = FunctionSummary::from_json(function)
+                .map_err(|err| SummaryError::new(format!("{} in {}", err.message, name)))?;
+            summaries.functions.insert(name.to_string(), summary);
+        }
+        Ok(summaries)
+    }
+
+    /// Read summaries from a JSON file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Summaries, SummaryError> {
+        let text = fs::read_to_string(path).map_err(|err| SummaryError::new(err.to_string()))?;
+        Summaries::from_json(&text)
+    }
+
+    /// Write the summaries as JSON in the format read by `from_json`.
+    pub fn write_json<W: Write>(&self, mut f: W) -> io::Result<()> {
+        writeln!(f, "{{\"functions\": [")?;
+        for (index, (name, summary)) in self.functions.iter().enumerate() {
+            let clobbered: Vec<String> = summary.clobbered.iter()
+                .map(|reg| json_string(&reg.to_string()))
+                .collect();
+            write!(f, "  {{\"name\": {}, \"clobbered\": [{}], \"dependences\": [",
+                   json_string(name), clobbered.join(", "))?;
+            for (position, dependence) in summary.dependences.iter().enumerate() {
+                let inputs: Vec<String> = dependence.inputs.iter()
+                    .map(|input| json_string(&input.to_string()))
+                    .collect();
+                write!(f, "{}\n    {{\"output\": {}, \"inputs\": [{}], \"conditional\": {}}}",
+                       if position > 0 { "," } else { "" },
+                       json_string(&dependence.output.to_string()),
+                       inputs.join(", "),
+                       dependence.conditional)?;
+            }
+            let last = index + 1 == self.functions.len();
+            writeln!(f, "]}}{}", if last { "" } else { "," })?;
+        }
+        writeln!(f, "]}}")
+    }
+}
+
+impl FunctionSummary {
+    /// Parse the summary of a function from a JSON object.
+    fn from_json(json: &Json) -> Result<FunctionSummary, SummaryError> {
+        let strings = |value: Option<&Json>, key: &str| -> Result<Vec<String>, SummaryError> {
+            let invalid = || SummaryError::new(format!("invalid \"{}\"", key));
+            match value {
+                Some(value) => value.as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|element| element.as_str().map(str::to_string).ok_or_else(invalid))
+                    .collect(),
+                None => Ok(Vec::new()),
+            }
+        };
+
+        let mut summary = FunctionSummary::default();
+        for name in strings(json.get("clobbered"), "clobbered")? {
+            let reg = REGISTERS.iter()
+                .find(|&&(_, other)| other == name)
+                .ok_or_else(|| SummaryError::new(format!("unknown register \"{}\"", name)))?;
+            summary.clobbered.insert(reg.0);
+        }
+
+        let dependences = json.get("dependences").and_then(Json::as_array).unwrap_or(&[]);
+        for dependence in dependences {
+            let port = |name: &str| Port::from_name(name)
+                .ok_or_else(|| SummaryError::new(format!("unknown port \"{}\"", name)));
+            let output = dependence.get("output")
+                .and_then(Json::as_str)
+                .ok_or_else(|| SummaryError::new("dependence without \"output\"".to_string()))?;
+            let inputs = strings(dependence.get("inputs"), "inputs")?.iter()
+                .map(|name| port(name.as_str()))
+                .collect::<Result<_, _>>()?;
+            let conditional = match dependence.get("conditional") {
+                Some(Json::Bool(conditional)) => *conditional,
+                None => false,
+                Some(_) => return Err(SummaryError::new("invalid \"conditional\"".to_string())),
+            };
+            summary.dependences.push(Dependence { output: port(output)?, inputs, conditional });
+        }
+        summary.dependences.sort_by(|a, b| a.output.cmp(&b.output));
+        Ok(summary)
+    }
+
+    /// The dependence of the output if the function has one.
+    pub fn dependence(&self, output: &Port) -> Option<&Dependence> {
+        self.dependences.iter().find(|dependence| &dependence.output == output)
+    }
+
+    /// The argument registers the return value depends on in the convention.
+    pub fn result_registers(&self, convention: &CallingConvention) -> RegisterSet {
+        self.dependence(&Port::Result)
+            .map(|dependence| dependence.inputs.iter()
+                .filter_map(|input| match *input {
+                    Port::Argument(index) => convention.arguments.get(index).copied(),
+                    _ => None,
+                })
+                .collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Port {
+    /// The port with the name it is displayed with, like `arg0`.
+    pub fn from_name(name: &str) -> Option<Port> {
+        match name {
+            "result" => Some(Port::Result),
+            "memory" => Some(Port::Memory),
+            "stdin" => Some(Port::Stdin),
+            "stdout" => Some(Port::Stdout),
+            _ => {
+                if let Some(global) = name.strip_prefix("global ") {
+                    return Some(Port::Global(global.to_string()));
+                }
+                name.strip_prefix("arg")?.parse().ok().map(Port::Argument)
+            },
+        }
+    }
+}
+
+/// The boundary of a function in the data dependency graph. Locations in
+/// functions it calls belong to it as well.
+struct Boundary<'a> {
+    program: &'a Program,
+    cfg: &'a ControlFlowGraph,
+    ddg: &'a DataDependencyGraph,
+    function: u64,
+    convention: &'a CallingConvention,
+    /// The lengths of the instructions by address.
+    lengths: &'a HashMap<u64, u64>,
+}
+
+/// The locations writing the outputs of the functions entered in the graphs
+/// as indices into the data dependency graph, by function and output.
+pub(super) fn output_locations(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    ddg: &DataDependencyGraph,
+) -> BTreeMap<u64, BTreeMap<Port, Vec<usize>>> {
+    let lengths: HashMap<u64, u64> = program.code.iter()
+        .map(|&(addr, len, ..)| (addr, len))
+        .collect();
+    cfg.frames.frames.keys()
+        .map(|&function| {
+            let convention = cfg.config.convention(function);
+            let boundary = Boundary { program, cfg, ddg, function, convention, lengths: &lengths };
+            (function, boundary.outputs(&boundary.within()))
+        })
+        .collect()
+}
+
+impl Boundary<'_> {
+    /// The dependences of the outputs of the function, sorted by output.
+    fn dependences(&self) -> Vec<Dependence> {
+        let nodes = &self.ddg.nodes;
+        let within = self.within();
+        let outputs = self.outputs(&within);
+
+        // The inputs are where data flows in on the way to an output or where
+        // a location inside is read without being written before.
+        let mut dependences = Vec::new();
+        for (output, starts) in outputs {
+            let mut inputs = BTreeSet::new();
+            let mut conditional = false;
+            let mut visited = BTreeSet::new();
+            let mut pending = starts;
+            while let Some(index) = pending.pop() {
+                if !visited.insert(index) {
+                    continue;
+                }
+                let preds = &self.ddg.incoming[index];
+                let mut entered = preds.is_empty();
+                for &pred in preds {
+                    match &nodes[pred] {
+                        DependencyNode::Io(StdioKind::Stdin, _) => {
+                            inputs.insert(Port::Stdin);
+                        },
+                        DependencyNode::Location(_) if within[pred] => {
+                            conditional |= self.ddg.edges[&(pred, index)].0 != SymCondition::TRUE;
+                            pending.push(pred);
+                        },
+                        DependencyNode::Location(_) => entered = true,
+                        _ => {},
+                    }
+                }
+                if entered {
+                    inputs.extend(self.input(index));
+                }
+            }
+            dependences.push(Dependence { output, inputs, conditional });
+        }
+        dependences
+    }
+
+    /// Whether each node of the data dependency graph is a location inside.
+    fn within(&self) -> Vec<bool> {
+        self.ddg.nodes.iter()
+            .map(|node| match node {
+                DependencyNode::Location(location) => self.contains(location),
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// The locations with data flowing out of the function by output.
+    fn outputs(&self, within: &[bool]) -> BTreeMap<Port, Vec<usize>> {
+        let nodes = &self.ddg.nodes;
+        let mut outputs: BTreeMap<Port, Vec<usize>> = BTreeMap::new();
+        for index in (0 .. nodes.len()).filter(|&index| within[index]) {
+            for &succ in &self.ddg.outgoing[index] {
+                let port = match &nodes[succ] {
+                    DependencyNode::Io(StdioKind::Stdout, _) => Some(Port::Stdout),
+                    DependencyNode::Location(_) if !within[succ] => self.output(index),
+                    _ => None,
+                };
+                if let Some(port) = port {
+                    outputs.entry(port).or_default().push(index);
+                }
+            }
+        }
+        outputs
+    }
+
+    /// Whether the location is in the function or in one it calls.
+    fn contains(&self, location: &AbstractLocation) -> bool {
+        let frames = &self.cfg.frames;
+        frames.function_of(location) == Some(self.function)
+            || location.trace.iter()
+                .any(|&site| frames.functions_at(site).contains(&self.function))
+    }
+
+    /// The output the location in the function writes if data flows out of it.
+    fn output(&self, index: usize) -> Option<Port> {
+        match self.location(index).storage {
+            StorageLocation::Direct(reg) if reg.base() == self.convention.result.base() => {
+                Some(Port::Result)
+            },
+            StorageLocation::Indirect { .. } => match self.memory(index)? {
+                Port::Argument(_) => None,
+                port => Some(port),
+            },
+            _ => None,
+        }
+    }
+
+    /// The input the location in the function reads if data flows into it.
+    fn input(&self, index: usize) -> Option<Port> {
+        match self.location(index).storage {
+            StorageLocation::Direct(reg) => self.convention.arguments.iter()
+                .position(|&argument| argument.base() == reg.base())
+                .map(Port::Argument),
+            StorageLocation::Indirect { .. } => self.memory(index),
+            StorageLocation::Flag(_) => None,
+        }
+    }
+
+    /// The port of memory accessed by the location: a stack argument, global
+    /// data at a static address or other memory outside of the frame. Locals
+    /// of the function and read-only data are none.
+    fn memory(&self, index: usize) -> Option<Port> {
+        let location = self.location(index);
+        if let StorageLocation::Indirect {
+            base: Register::RIP,
+            scaled_offset: None,
+            displacement,
+            ..
+        } = location.storage {
+            let len = self.lengths.get(&location.addr)?;
+            let addr = (location.addr + len).wrapping_add(displacement.unwrap_or(0) as u64);
+            if self.cfg.config.memory.image.is_readonly(addr) {
+                return None;
+            }
+            let name = self.program.symbols.get(&addr)
+                .cloned()
+                .unwrap_or_else(|| format!("{:#x}", addr));
+            return Some(Port::Global(name));
+        }
+
+        match self.cfg.frames.variable(location) {
+            Some(variable) if variable.function == self.function => {
+                // Arguments on the stack follow the return address and the
+                // shadow space.
+                let first = 8 + self.convention.shadow_space as i64;
+                match variable.offset >= first {
+                    true => {
+                        let slot = ((variable.offset - first) / 8) as usize;
+                        Some(Port::Argument(self.convention.arguments.len() + slot))
+                    },
+                    false => None,
+                }
+            },
+            _ => Some(Port::Memory),
+        }
+    }
+
+    /// The location of the node with the index.
+    fn location(&self, index: usize) -> &AbstractLocation {
+        match &self.ddg.nodes[index] {
+            DependencyNode::Location(location) => location,
+            _ => unreachable!("summary: expected a location node"),
+        }
+    }
+}
+
+impl Display for Port {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Port::Argument(index) => write!(f, "arg{}", index),
+            Port::Result => write!(f, "result"),
+            Port::Global(name) => write!(f, "global {}", name),
+            Port::Memory => write!(f, "memory"),
+            Port::Stdin => write!(f, "stdin"),
+            Port::Stdout => write!(f, "stdout"),
+        }
+    }
+}
+
+impl Display for Dependence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let inputs: Vec<String> = self.inputs.iter().map(Port::to_string).collect();
+        write!(f, "{} <- {}", self.output, inputs.join(", "))?;
+        if self.conditional {
+            write!(f, " (conditional)")?;
+        }
+        Ok(())
+    }
+}
+
+/// An error reading summaries.
+pub struct SummaryError {
+    pub message: String,
+}
+
+impl SummaryError {
+    fn new(message: String) -> SummaryError {
+        SummaryError { message }
+    }
+}
+
+impl Display for SummaryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid summaries: {}", self.message)
+    }
+}
+
+impl std::error::Error for SummaryError {}
+debug_display!(SummaryError);
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::fixtures::fixture;
+    use crate::math::{Solver, SymExpr, DataType};
+    use crate::sym::{SymConfig, SymState, MemoryStrategy};
+    use crate::flow::ControlKind;
+    use super::*;
+
+    #[test]
+    fn summaries() {
+        let program = Program::new(fixture("summary"));
+        let cfg = ControlFlowGraph::new(&program);
+        let ddg = DataDependencyGraph::new(&cfg);
+        let summaries = Summaries::compute(&program, &cfg, &ddg);
+
+        // The result and the total depend on the value but not on the
+        // weight, and the total only changes for positive values.
+        let accumulate = summaries.get("accumulate").unwrap();
+        let total = Port::Global("total".to_string());
+        let outputs: Vec<&Port> = accumulate.dependences.iter()
+            .map(|dependence| &dependence.output)
+            .collect();
+        assert_eq!(outputs, vec![&Port::Result, &total]);
+        let result = accumulate.dependence(&Port::Result).unwrap();
+        assert_eq!(result.inputs, vec![Port::Argument(0)].into_iter().collect());
+        let global = accumulate.dependence(&total).unwrap();
+        assert_eq!(global.inputs, vec![Port::Argument(0), total.clone()].into_iter().collect());
+        assert!(global.conditional);
+        assert!(accumulate.clobbered.contains(Register::RAX));
+        assert!(!accumulate.clobbered.contains(Register::RBP));
+
+        // The output of the program comes from the input.
+        let main = summaries.get("main").unwrap();
+        assert!(main.dependence(&Port::Stdout).unwrap().inputs.contains(&Port::Stdin));
+
+        let mut json = Vec::new();
+        summaries.write_json(&mut json).unwrap();
+        assert_eq!(Summaries::from_json(std::str::from_utf8(&json).unwrap()).unwrap(), summaries);
+    }
+
+    #[test]
+    fn summarized_calls() {
+        let program = Program::new(fixture("summary"));
+        let text = r#"{"functions": [{"name": "accumulate", "clobbered": ["rax", "rdx"],
+            "dependences": [{"output": "result", "inputs": ["arg0"]}]}]}"#;
+        let summaries = Summaries::from_json(text).unwrap();
+        let config = SymConfig { summaries, .. SymConfig::default() };
+
+        // The summarized function is not entered and its result depends on
+        // the argument passed to it.
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        let accumulate = program.symbols.iter()
+            .find(|(_, name)| name.as_str() == "accumulate")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        assert!(cfg.nodes.iter().all(|node| node.addr != accumulate));
+        let site = cfg.edges.keys()
+            .find_map(|&edge| match cfg.edge_kind(edge) {
+                ControlKind::Summary { site, .. } => Some(site),
+                _ => None,
+            })
+            .unwrap();
+
+        let ddg = DataDependencyGraph::new(&cfg);
+        let result = ddg.nodes.iter()
+            .position(|node| match node {
+                DependencyNode::Location(location) => {
+                    let rax = StorageLocation::Direct(Register::RAX);
+                    location.addr == site && location.storage == rax
+                },
+                _ => false,
+            })
+            .unwrap();
+        assert!(!ddg.incoming[result].is_empty());
+
+        // Summaries writing global data are not used.
+        let text = r#"{"functions": [{"name": "accumulate",
+            "dependences": [{"output": "global total", "inputs": ["arg0"]}]}]}"#;
+        let summaries = Summaries::from_json(text).unwrap();
+        let config = SymConfig { summaries, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        assert!(cfg.nodes.iter().any(|node| node.addr == accumulate));
+
+        // Memory the arguments point to may be written by the call.
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+        state.set_reg(Register::RDI, SymExpr::from_ptr(0x4000));
+        let byte = SymExpr::from_int(DataType::N8, 7);
+        state.memory[0].write_expr(SymExpr::from_ptr(0x4003), byte.clone());
+        let memory = vec![Register::RDI].into_iter().collect();
+        let kind = ControlKind::Summary {
+            function: accumulate, site, clobbered: RegisterSet::default(),
+            result: RegisterSet::default(), memory,
+        };
+        assert_eq!(kind.enter(&mut state).len(), 1);
+        assert_ne!(state.memory[0].read_expr(SymExpr::from_ptr(0x4003), DataType::N8), byte);
+
+        let error = |text| Summaries::from_json(text).unwrap_err().message;
+        let unknown = r#"{"functions": [{"name": "f", "dependences": [{"output": "arg"}]}]}"#;
+        assert_eq!(error(unknown), "unknown port \"arg\" in f");
+        assert_eq!(Port::from_name("global errno"), Some(Port::Global("errno".to_string())));
+    }
+}
@@ -0,0 +1,149 @@
+//! Switch statements reconstructed from jumps through tables.
+//!
+//! Compilers lower dense switch statements into a bounds check of the index
+//! followed by a jump through a table of targets in read-only data. The
+//! target of such a jump selects from the entries of the table, so it only
+//! takes a few values while the bounds check holds, which resolves the jump.
+//! The bounds check also tells the index and the number of entries, whose
+//! targets are then found one by one. Indices beyond the table go to the
+//! default target, which is where the bounds check jumps otherwise.
+
+use crate::math::{SymExpr, SymCondition, Integer};
+use super::ControlFlowGraph;
+
+
+/// A switch statement reconstructed from a jump through a table.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Switch {
+    /// The node of the block ending with the jump through the table.
+    pub node: usize,
+    /// The address of the jump.
+    pub site: u64,
+    /// The expression the table is indexed with.
+    pub index: SymExpr,
+    /// The case values of the index with their targets, sorted by value.
+    /// Entries leading to the default target are left out.
+    pub cases: Vec<(u64, u64)>,
+    /// The target of the indices beyond the table if known.
+    pub default: Option<u64>,
+}
+
+impl Switch {
+    /// The case values leading to the target.
+    pub fn values_of(&self, target: u64) -> Vec<u64> {
+        self.cases.iter()
+            .filter(|&&(_, case)| case == target)
+            .map(|&(value, _)| value)
+            .collect()
+    }
+}
+
+impl ControlFlowGraph {
+    /// The switch ending in the node with the index if there is one.
+    pub fn switch_at(&self, node: usize) -> Option<&Switch> {
+        self.switches.iter().find(|switch| switch.node == node)
+    }
+
+    /// The label of an edge out of a switch naming the case values it is
+    /// taken for, like `case 0, 2`, or `default`.
+    pub fn case_label(&self, (start, end): (usize, usize)) -> Option<String> {
+        let switch = self.switch_at(start)?;
+        let target = self.nodes[end].addr;
+        let values: Vec<String> = switch.values_of(target).iter().map(u64::to_string).collect();
+
+        let mut labels = Vec::new();
+        if !values.is_empty() {
+            labels.push(format!("case {}", values.join(", ")));
+        }
+        if switch.default == Some(target) {
+            labels.push("default".to_string());
+        }
+        match labels.is_empty() {
+            true => None,
+            false => Some(labels.join(", ")),
+        }
+    }
+}
+
+/// The index and the number of table entries if the condition is a bounds
+/// check, which holds if the index is unsigned below the number.
+pub(super) fn switch_bound(condition: &SymCondition) -> Option<(SymExpr, u64)> {
+    bound(condition, true)
+}
+
+/// Find a bounds check in a condition, which is negated unless it `holds`.
+fn bound(condition: &SymCondition, holds: bool) -> Option<(SymExpr, u64)> {
+    use SymCondition::*;
+    let constant = |expr: &SymExpr| match expr {
+        SymExpr::Int(Integer(_, value)) => Some(*value),
+        _ => None,
+    };
+
+    let (index, count) = match (condition, holds) {
+        (Not(inner), _) => return bound(inner, !holds),
+        (And(a, b), true) | (Or(a, b), false) => {
+            return bound(a, holds).or_else(|| bound(b, holds));
+        },
+        (LessThan(index, n, false), true) |
+        (GreaterEqual(index, n, false), false) => (index, constant(n)?),
+        (LessEqual(index, n, false), true) |
+        (GreaterThan(index, n, false), false) => (index, constant(n)?.checked_add(1)?),
+        (GreaterThan(n, index, false), true) |
+        (LessEqual(n, index, false), false) => (index, constant(n)?),
+        (GreaterEqual(n, index, false), true) |
+        (LessThan(n, index, false), false) => (index, constant(n)?.checked_add(1)?),
+        _ => return None,
+    };
+
+    match (&**index, count) {
+        (SymExpr::Int(_), _) | (_, 0) => None,
+        (index, count) => Some((index.clone(), count)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::math::{DataType, Symbol};
+    use super::*;
+
+    #[test]
+    fn switches() {
+        let program = Program::new(fixture("switch"));
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.switches.len(), 1);
+
+        // The cases 'a' to 'f' are the indices 0 to 5 and 'd' has no case.
+        let switch = &cfg.switches[0];
+        let values: Vec<u64> = switch.cases.iter().map(|&(value, _)| value).collect();
+        assert_eq!(values, vec![0, 1, 2, 4, 5]);
+        let default = switch.default.unwrap();
+        assert!(switch.cases.iter().all(|&(_, target)| target != default));
+
+        // Every case has its own target and the default is reached through
+        // both the table and the bounds check.
+        let labels: Vec<String> = cfg.outgoing[switch.node].iter()
+            .filter_map(|&succ| cfg.case_label((switch.node, succ)))
+            .collect();
+        assert_eq!(labels.len(), 6);
+        assert!(labels.contains(&"case 0".to_string()));
+        assert!(labels.contains(&"default".to_string()));
+    }
+
+    #[test]
+    fn bounds() {
+        let index = SymExpr::Sym(Symbol(DataType::N32, "stdin", 0));
+        let n = SymExpr::from_int(DataType::N32, 5);
+        let at_most = SymCondition::LessEqual(Box::new(index.clone()), Box::new(n.clone()), false);
+        assert_eq!(switch_bound(&at_most), Some((index.clone(), 6)));
+
+        let beyond = SymCondition::LessEqual(Box::new(n.clone()), Box::new(index.clone()), false);
+        assert_eq!(switch_bound(&beyond.clone().not()), Some((index.clone(), 5)));
+        assert_eq!(switch_bound(&beyond), None);
+
+        let signed = SymCondition::LessEqual(Box::new(index), Box::new(n), true);
+        assert_eq!(switch_bound(&signed), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! Synthesis of inputs that reach a part of the program.
+//!
+//! The conditions of the edges along a path from the entry to the target are
+//! conjoined and solved, and the values of the input symbols in the model
+//! become the bytes of the input. Only the standard input is modeled, so the
+//! synthesized input is the content to pipe into the program.
+
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::math::{Integer, Solver, SymCondition, Symbol};
+use super::{ControlFlowGraph, DependenceNode, ProgramDependenceGraph};
+
+
+/// An input together with the path it makes the program take.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TestCase {
+    /// The control flow node that is reached.
+    pub node: usize,
+    /// The control flow nodes from the entry to the reached node.
+    pub path: Vec<usize>,
+    /// The conjunction of the edge conditions along the path.
+    pub condition: SymCondition,
+    /// The bytes to read from the standard input. They end with the last
+    /// byte the path depends on.
+    pub stdin: Vec<u8>,
+}
+
+impl TestCase {
+    /// Write the standard input into a file.
+    #[cfg(feature = "fs")]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, &self.stdin)
+    }
+}
+
+impl ControlFlowGraph {
+    /// The nodes whose blocks contain the instruction at the address.
+    pub fn nodes_containing(&self, addr: u64) -> Vec<usize> {
+        (0 .. self.nodes.len())
+            .filter(|&index| {
+                let block = &self.blocks[&self.nodes[index].addr];
+                block.code.iter().any(|&(start, ..)| start == addr)
+            })
+            .collect()
+    }
+
+    /// Find an input reaching any of the target nodes.
+    ///
+    /// The targets are tried in order and for each at most `limit` paths from
+    /// the entry. Returns `None` if none of these paths is satisfiable.
+    pub fn synthesize_input(&self, targets: &[usize], solver: &Solver, limit: usize) -> Option<TestCase> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        for &node in targets {
+            for (path, condition) in self.paths_between(0, node, limit, Some(solver)) {
+                if let Some(model) = solver.model(&condition) {
+                    return Some(TestCase { node, path, condition, stdin: stdin_bytes(&model) });
+                }
+            }
+        }
+        None
+    }
+
+    /// Find an input reaching the instruction at the address.
+    pub fn synthesize_input_at(&self, addr: u64, solver: &Solver, limit: usize) -> Option<TestCase> {
+        self.synthesize_input(&self.nodes_containing(addr), solver, limit)
+    }
+
+    /// Find an input reaching a node of the program dependence graph built
+    /// from this graph. Locations are only reached in their call trace.
+    pub fn synthesize_input_for(
+        &self,
+        pdg: &ProgramDependenceGraph,
+        node: usize,
+        solver: &Solver,
+        limit: usize,
+    ) -> Option<TestCase> {
+        let targets: Vec<usize> = match &pdg.nodes[node] {
+            DependenceNode::ControlFlow(addr) => self.nodes_at(*addr).to_vec(),
+            DependenceNode::DataDependency(location) => {
+                self.nodes_containing(location.addr).into_iter()
+                    .filter(|&index| {
+                        let sites = self.nodes[index].trace.iter().map(|&(site, _)| site);
+                        sites.eq(location.trace.iter().copied())
+                    })
+                    .collect()
+            },
+        };
+        self.synthesize_input(&targets, solver, limit)
+    }
+}
+
+/// The bytes of the standard input with the values of the model.
+fn stdin_bytes(model: &HashMap<Symbol, Integer>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (symbol, value) in model {
+        if let Symbol(_, "stdin", index) = *symbol {
+            if bytes.len() <= index {
+                bytes.resize(index + 1, 0);
+            }
+            bytes[index] = value.1 as u8;
+        }
+    }
+    bytes
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use super::*;
+
+    #[test]
+    fn synthesize() {
+        let program = Program::new(fixture("case"));
+        let cfg = ControlFlowGraph::new(&program);
+        let solver = Solver::new();
+
+        let nodes: Vec<usize> = (0 .. cfg.nodes.len()).collect();
+        let cases: Vec<TestCase> = nodes.iter()
+            .map(|&node| cfg.synthesize_input(&[node], &solver, 10).unwrap())
+            .collect();
+
+        let lower = |case: &TestCase| matches!(case.stdin.first(), Some(byte) if byte.is_ascii_lowercase());
+        assert!(cases.iter().any(lower));
+        assert!(cases.iter().any(|case| !lower(case)));
+        assert!(cases.iter().all(|case| case.path.last() == Some(&case.node)));
+
+        let entry = cfg.synthesize_input_at(program.entry, &solver, 10).unwrap();
+        assert_eq!((entry.node, entry.stdin.len()), (0, 0));
+        assert!(cfg.synthesize_input(&[], &solver, 10).is_none());
+    }
+}
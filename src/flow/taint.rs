@@ -0,0 +1,377 @@
+//! Taint tracking with sources and sinks declared in rule files.
+//!
+//! A rule file is a JSON object with the arrays `sources` and `sinks`, whose
+//! rules select locations of the data dependency graph by any combination
+//! of these members, all of which have to match:
+//! - `function`: The symbol name of a called function. Sources are the
+//!   locations a summarized call writes at its site and the reads of the
+//!   result right after the call. Sinks are the last definitions of the
+//!   register arguments before the call in its block.
+//! - `argument`: The index of an argument of the function. Sources are then
+//!   only the memory the argument points to and sinks only the argument.
+//! - `address`: The address of the instruction of the location, as a number
+//!   or a hexadecimal string.
+//! - `location`: A pattern for the storage of the location like
+//!   `[rbp-0x8:n64]`, in which `*` matches any text.
+//!
+//! For example, `{"sources": [{"function": "recv", "argument": 1}], "sinks":
+//! [{"function": "system", "argument": 0}]}` finds the received data that
+//! ends up in a command. The data depending on a source through the edges of
+//! the graph is tainted, and a flow is reported for every sink it reaches.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::Program;
+use crate::json::Json;
+use crate::sym::ArgumentSlot;
+use crate::x86_64::Register;
+use super::{ControlFlowGraph, DataDependencyGraph, DependencyNode, StorageLocation, ValueSource};
+use super::directed::successors;
+
+
+/// The taint sources and sinks of an analysis.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TaintRules {
+    pub sources: Vec<TaintRule>,
+    pub sinks: Vec<TaintRule>,
+}
+
+/// A selection of locations as a source or sink. Only the given criteria
+/// have to match.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TaintRule {
+    /// The symbol name of a called function.
+    pub function: Option<String>,
+    /// The index of an argument of the function.
+    pub argument: Option<usize>,
+    /// The address of the instruction of the location.
+    pub address: Option<u64>,
+    /// A pattern for the storage of the location, in which `*` matches any text.
+    pub location: Option<String>,
+}
+
+/// Tainted data reaching a sink.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TaintFlow {
+    /// The index of the source rule.
+    pub source_rule: usize,
+    /// The index of the sink rule.
+    pub sink_rule: usize,
+    /// The location node of the data dependency graph the data comes from.
+    pub source: usize,
+    /// The location node the data reaches.
+    pub sink: usize,
+}
+
+/// The locations at an address selected by a rule for a function.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Selected {
+    All,
+    PointedBy(Register),
+    Register(Register),
+}
+
+impl TaintRules {
+    /// Parse rules from JSON.
+    pub fn from_json(text: &str) -> Result<TaintRules, RuleError> {
+        let json = Json::parse(text).map_err(|err| RuleError::new(err.to_string()))?;
+        let rules = |key: &str| -> Result<Vec<TaintRule>, RuleError> {
+            match json.get(key) {
+                Some(value) => value.as_array()
+                    .ok_or_else(|| RuleError::new(format!("\"{}\" is not an array", key)))?
+                    .iter()
+                    .map(TaintRule::from_json)
+                    .collect(),
+                None => Ok(Vec::new()),
+            }
+        };
+        Ok(TaintRules { sources: rules("sources")?, sinks: rules("sinks")? })
+    }
+
+    /// Read rules from a JSON file.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TaintRules, RuleError> {
+        let text = fs::read_to_string(path).map_err(|err| RuleError::new(err.to_string()))?;
+        TaintRules::from_json(&text)
+    }
+
+    /// Find the flows from the sources to the sinks, sorted. Each sink a
+    /// source rule reaches is reported once with one of the source locations.
+    pub fn analyze(
+        &self,
+        program: &Program,
+        cfg: &ControlFlowGraph,
+        ddg: &DataDependencyGraph,
+    ) -> Vec<TaintFlow> {
+        let sinks: Vec<Vec<usize>> = self.sinks.iter()
+            .map(|rule| rule.select(program, cfg, ddg, false))
+            .collect();
+
+        let mut flows = BTreeSet::new();
+        for (source_rule, rule) in self.sources.iter().enumerate() {
+            // Remember the source each tainted node was first reached from.
+            let mut origins = vec![None; ddg.nodes.len()];
+            let mut pending = Vec::new();
+            for node in rule.select(program, cfg, ddg, true) {
+                origins[node] = Some(node);
+                pending.push(node);
+            }
+            while let Some(node) = pending.pop() {
+                for next in ddg.successors(node) {
+                    if origins[next].is_none() {
+                        origins[next] = origins[node];
+                        pending.push(next);
+                    }
+                }
+            }
+
+            for (sink_rule, nodes) in sinks.iter().enumerate() {
+                for &sink in nodes {
+                    if let Some(source) = origins[sink] {
+                        flows.insert(TaintFlow { source_rule, sink_rule, source, sink });
+                    }
+                }
+            }
+        }
+        flows.into_iter().collect()
+    }
+}
+
+impl TaintRule {
+    /// Parse a rule from a JSON object.
+    fn from_json(json: &Json) -> Result<TaintRule, RuleError> {
+        let members = match json {
+            Json::Object(members) => members,
+            _ => return Err(RuleError::new("rule is not an object".to_string())),
+        };
+
+        let mut rule = TaintRule::default();
+        for (key, value) in members {
+            let invalid = || RuleError::new(format!("invalid \"{}\" in rule", key));
+            match key.as_str() {
+                "function" => rule.function = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                "argument" => rule.argument = Some(value.as_u64().ok_or_else(invalid)? as usize),
+                "address" => rule.address = Some(value.as_u64().ok_or_else(invalid)?),
+                "location" => rule.location = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                _ => return Err(RuleError::new(format!("unknown member \"{}\" in rule", key))),
+            }
+        }
+
+        if rule == TaintRule::default() {
+            return Err(RuleError::new("empty rule".to_string()));
+        }
+        if rule.argument.is_some() && rule.function.is_none() {
+            return Err(RuleError::new("\"argument\" without \"function\" in rule".to_string()));
+        }
+        Ok(rule)
+    }
+
+    /// The location nodes of the graph the rule selects as a source or sink.
+    fn select(
+        &self,
+        program: &Program,
+        cfg: &ControlFlowGraph,
+        ddg: &DataDependencyGraph,
+        source: bool,
+    ) -> Vec<usize> {
+        let selected = self.function.as_ref()
+            .map(|name| self.calls(program, cfg, name, source));
+
+        (0 .. ddg.nodes.len())
+            .filter(|&index| {
+                let location = match &ddg.nodes[index] {
+                    DependencyNode::Location(location) => location,
+                    _ => return false,
+                };
+                let storage = location.storage;
+                self.address.map_or(true, |addr| location.addr == addr)
+                    && self.location.as_ref().map_or(true, |pattern| {
+                        glob(pattern, &storage.to_string())
+                    })
+                    && selected.as_ref().map_or(true, |selected| {
+                        selected.iter().any(|&(addr, part)| {
+                            location.addr == addr && part.contains(storage)
+                        })
+                    })
+            })
+            .collect()
+    }
+
+    /// The parts of the locations at each address that the calls to the
+    /// function with the name provide as a source or use as a sink.
+    fn calls(
+        &self,
+        program: &Program,
+        cfg: &ControlFlowGraph,
+        name: &str,
+        source: bool,
+    ) -> HashSet<(u64, Selected)> {
+        let functions: HashSet<u64> = program.symbols.iter()
+            .filter(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .collect();
+
+        let mut selected = HashSet::new();
+        for block in cfg.blocks.values() {
+            for (position, (addr, len, instruction, _)) in block.code.iter().enumerate() {
+                let callee = match successors(*addr, *len, instruction).1 {
+                    Some(callee) if functions.contains(&callee) => callee,
+                    _ => continue,
+                };
+                let convention = cfg.config.convention(callee);
+                let register = |index| match convention.argument_slot(index) {
+                    ArgumentSlot::Register(reg) => Some(reg),
+                    ArgumentSlot::Stack(_) => None,
+                };
+
+                if source {
+                    if let Some(index) = self.argument {
+                        if let Some(reg) = register(index) {
+                            selected.insert((*addr, Selected::PointedBy(reg)));
+                        }
+                        continue;
+                    }
+
+                    selected.insert((*addr, Selected::All));
+                    let next = cfg.blocks.get(&(*addr + *len)).and_then(|block| block.code.first());
+                    if let Some((next, _, _, microcode)) = next {
+                        if reads(&microcode.flows, convention.result) {
+                            selected.insert((*next, Selected::Register(convention.result)));
+                        }
+                    }
+                } else {
+                    let indices: Vec<usize> = match self.argument {
+                        Some(index) => vec![index],
+                        None => (0 .. convention.arguments.len()).collect(),
+                    };
+                    for reg in indices.into_iter().filter_map(register) {
+                        let definition = block.code[.. position].iter().rev()
+                            .find(|(.., microcode)| writes(&microcode.flows, reg));
+                        if let Some((def, ..)) = definition {
+                            selected.insert((*def, Selected::Register(reg)));
+                        }
+                    }
+                }
+            }
+        }
+        selected
+    }
+}
+
+impl Selected {
+    /// Whether the storage is part of the selection.
+    fn contains(self, storage: StorageLocation) -> bool {
+        match (self, storage) {
+            (Selected::All, _) => true,
+            (Selected::PointedBy(reg), StorageLocation::Indirect { base, .. }) => {
+                base == reg.base()
+            },
+            (Selected::Register(reg), StorageLocation::Direct(other)) => {
+                other.base() == reg.base()
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Whether the flows of an instruction read the register.
+fn reads(flows: &[(ValueSource, StorageLocation)], reg: Register) -> bool {
+    flows.iter().any(|(source, _)| match source {
+        ValueSource::Storage(StorageLocation::Direct(other)) => other.base() == reg.base(),
+        _ => false,
+    })
+}
+
+/// Whether the flows of an instruction write the register.
+fn writes(flows: &[(ValueSource, StorageLocation)], reg: Register) -> bool {
+    flows.iter().any(|(_, sink)| match sink {
+        StorageLocation::Direct(other) => other.base() == reg.base(),
+        _ => false,
+    })
+}
+
+/// Whether the text matches the pattern, in which `*` matches any text.
+pub(super) fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        if index + 1 == parts.len() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len() ..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+/// An error reading taint rules.
+pub struct RuleError {
+    pub message: String,
+}
+
+impl RuleError {
+    fn new(message: String) -> RuleError {
+        RuleError { message }
+    }
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid taint rules: {}", self.message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+debug_display!(RuleError);
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::fixture;
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let program = Program::new(fixture("inputs"));
+        let cfg = ControlFlowGraph::new(&program);
+        let ddg = DataDependencyGraph::new(&cfg);
+        let rules = TaintRules::from_json(r#"{
+            "sources": [{"function": "read", "argument": 1}, {"function": "getenv"}],
+            "sinks": [{"function": "write_one_byte", "argument": 0, "location": "*di"}]
+        }"#).unwrap();
+
+        // Both the file contents and the variable end up in the output.
+        let flows = rules.analyze(&program, &cfg, &ddg);
+        let pairs: BTreeSet<(usize, usize)> = flows.iter()
+            .map(|flow| (flow.source_rule, flow.sink_rule))
+            .collect();
+        assert_eq!(pairs, vec![(0, 0), (1, 0)].into_iter().collect());
+        let source = |flow: &TaintFlow| match &ddg.nodes[flow.source] {
+            DependencyNode::Location(location) => ddg.inputs.contains_key(location),
+            _ => false,
+        };
+        assert!(source(&flows[0]));
+
+        let error = |text| TaintRules::from_json(text).unwrap_err().message;
+        assert_eq!(error(r#"{"sinks": [{}]}"#), "empty rule");
+        let unbound = "\"argument\" without \"function\" in rule";
+        assert_eq!(error(r#"{"sinks": [{"argument": 0}]}"#), unbound);
+        assert_eq!(error(r#"{"sources": [{"name": "read"}]}"#), "unknown member \"name\" in rule");
+
+        assert!(glob("[rbp-*:n8]", "[rbp-0x14:n8]"));
+        assert!(glob("*", "rax") && !glob("r*x*", "rdi"));
+    }
+}
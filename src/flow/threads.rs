@@ -0,0 +1,147 @@
+//! Threads started by the program.
+//!
+//! Calls to functions creating threads like `pthread_create` are summarized:
+//! they return zero right away and the thread starts at its routine in a
+//! separate path, with the state of the creating thread at the call. The
+//! routine is called with the argument passed for it and returns to the null
+//! address, which ends the thread. Threads of Rust programs are found the same
+//! way through the call of `pthread_create` within `std::thread::spawn`, whose
+//! start routine then calls the closure.
+//!
+//! Since every thread runs on its own paths, the data dependency graph links
+//! the writes to global memory in one thread to the overlapping reads in all
+//! other threads, as the threads may run in any interleaving. With
+//! `happens_before` in the configuration, the links between accesses that are
+//! atomic or right next to a fence are marked as synchronizations, like the
+//! release and acquire of a flag guarding other data.
+
+use super::{ControlFlowGraph, ControlFlowNode, ControlKind};
+
+
+/// A function creating a thread, described by the positions of its arguments.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Spawner {
+    /// The argument with the address of the routine the thread starts at.
+    pub routine: usize,
+    /// The argument passed on to the routine if there is one.
+    pub argument: Option<usize>,
+}
+
+/// The names of functions ending the calling thread.
+pub(super) const THREAD_EXIT_NAMES: &[&str] = &["pthread_exit", "thrd_exit"];
+
+impl Spawner {
+    /// `pthread_create(thread, attr, routine, arg)`.
+    pub const PTHREAD_CREATE: Spawner = Spawner { routine: 2, argument: Some(3) };
+    /// `thrd_create(thread, routine, arg)` of C11.
+    pub const THRD_CREATE: Spawner = Spawner { routine: 1, argument: Some(2) };
+
+    /// The spawner with the symbol name if there is one.
+    pub fn from_name(name: &str) -> Option<Spawner> {
+        match name {
+            "pthread_create" | "__pthread_create_2_1" => Some(Spawner::PTHREAD_CREATE),
+            "thrd_create" => Some(Spawner::THRD_CREATE),
+            _ => None,
+        }
+    }
+}
+
+/// A thread started by the program.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Thread {
+    /// The address of the call creating the thread.
+    pub site: u64,
+    /// The function the thread starts at.
+    pub routine: u64,
+}
+
+impl Thread {
+    /// Whether the node runs in this thread, that is its trace starts with
+    /// the entry into the routine.
+    pub fn runs(&self, node: &ControlFlowNode) -> bool {
+        node.trace.first() == Some(&(self.site, self.routine))
+    }
+}
+
+impl ControlFlowGraph {
+    /// The threads started by the program, whose routines are roots of the
+    /// analysis in addition to the ones of the program, sorted.
+    pub fn threads(&self) -> Vec<Thread> {
+        let mut threads: Vec<Thread> = self.kinds.values()
+            .filter_map(|kind| match *kind {
+                ControlKind::Thread { site, routine, .. } => Some(Thread { site, routine }),
+                _ => None,
+            })
+            .collect();
+        threads.sort_unstable();
+        threads.dedup();
+        threads
+    }
+
+    /// The thread the node with the index runs in, or `None` for the main thread.
+    pub fn thread_of(&self, node: usize) -> Option<Thread> {
+        self.threads().into_iter().find(|thread| thread.runs(&self.nodes[node]))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::{DataDependencyGraph, DependencyNode, ProgramDependenceGraph, EdgeKind};
+    use crate::sym::SymConfig;
+    use super::*;
+
+    #[test]
+    fn threads() {
+        let program = Program::new(fixture("threads"));
+        let function = |name: &str| program.symbols.iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&addr, _)| addr)
+            .unwrap();
+
+        // The worker is only reachable through the thread.
+        let cfg = ControlFlowGraph::new(&program);
+        let threads = cfg.threads();
+        let routines: Vec<u64> = threads.iter().map(|thread| thread.routine).collect();
+        assert_eq!(routines, vec![function("worker")]);
+        let worker: Vec<usize> = (0 .. cfg.nodes.len())
+            .filter(|&node| cfg.nodes[node].addr == function("worker"))
+            .collect();
+        assert!(!worker.is_empty());
+        assert!(worker.iter().all(|&node| cfg.thread_of(node) == Some(threads[0])));
+        assert!(cfg.frames.frames.contains_key(&function("worker")));
+
+        // The write after the thread was created still reaches its read.
+        let ddg = DataDependencyGraph::new(&cfg);
+        assert!(!ddg.threads.is_empty());
+        let edges: HashSet<(usize, usize)> = ddg.edges.keys().copied().collect();
+        assert!(ddg.threads.is_subset(&edges));
+
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+        let count = pdg.edges.iter().filter(|(_, edge)| edge.kind == EdgeKind::InterThread).count();
+        assert_eq!(count, ddg.threads.len());
+        assert!(ddg.synchronizations.is_empty());
+    }
+
+    #[test]
+    fn synchronization() {
+        let program = Program::new(fixture("threads"));
+        let config = SymConfig { happens_before: true, .. SymConfig::default() };
+        let cfg = ControlFlowGraph::with_config(&program, config);
+        let ddg = DataDependencyGraph::new(&cfg);
+        assert!(!ddg.synchronizations.is_empty());
+        assert!(ddg.synchronizations.is_subset(&ddg.threads));
+
+        // The flag is exchanged atomically, while the data written before the
+        // fence is read without one.
+        let atomic = |node: usize| match &ddg.nodes[node] {
+            DependencyNode::Location(location) => program.code.iter()
+                .any(|(addr, _, _, microcode)| *addr == location.addr && microcode.atomic),
+            _ => false,
+        };
+        assert!(ddg.synchronizations.iter().all(|&(_, read)| atomic(read)));
+    }
+}
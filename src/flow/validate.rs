@@ -0,0 +1,308 @@
+//! Integrity checks for graphs that were built or modified programmatically.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::math::{SymCondition, SymExpr, Symbol, Traversed};
+use super::{ControlFlowGraph, DataDependencyGraph, DependencyNode};
+use super::{ProgramDependenceGraph, DependenceNode, NodeId};
+
+
+/// A violated invariant of a flow graph. Edges are given as (start, end)
+/// node indices.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Diagnostic {
+    /// An edge has an endpoint that is not a node of the graph.
+    EdgeOutOfRange(usize, usize),
+    /// An edge is missing from the neighbor lists of its endpoints.
+    MissingNeighbor(usize, usize),
+    /// The neighbor lists contain an edge that does not exist.
+    ExtraNeighbor(usize, usize),
+    /// An edge is stored although its condition is false.
+    FalseEdge(usize, usize),
+    /// A control flow node has no basic block.
+    MissingBlock(usize),
+    /// The first root is not the address of the first node.
+    MisplacedRoot(u64),
+    /// An edge kind is recorded for an edge that does not exist.
+    DanglingKind(usize, usize),
+    /// A def-use chain has no corresponding edge.
+    DanglingChain(usize, usize),
+    /// A memory class is recorded for a node that is not a location.
+    DanglingClass(usize),
+    /// The symbol map of an edge names a symbol the condition does not use.
+    DanglingSymbol(usize, usize, Symbol),
+    /// The condition of an edge uses an I/O symbol that is neither in the
+    /// symbol map of the edge nor a node of the graph.
+    UndeclaredSymbol(usize, usize, Symbol),
+    /// Two nodes have the same identifier.
+    DuplicateId(NodeId),
+    /// A lookup index disagrees with the node it points to.
+    StaleIndex(usize),
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Diagnostic::*;
+        match self {
+            EdgeOutOfRange(a, b) => write!(f, "edge {} -> {} has endpoint out of range", a, b),
+            MissingNeighbor(a, b) => write!(f, "edge {} -> {} missing from neighbors", a, b),
+            ExtraNeighbor(a, b) => write!(f, "neighbors list nonexistent edge {} -> {}", a, b),
+            FalseEdge(a, b) => write!(f, "edge {} -> {} has false condition", a, b),
+            MissingBlock(node) => write!(f, "node {} has no basic block", node),
+            MisplacedRoot(addr) => write!(f, "root {:#x} is not the first node", addr),
+            DanglingKind(a, b) => write!(f, "kind of nonexistent edge {} -> {}", a, b),
+            DanglingChain(a, b) => write!(f, "chain {} -> {} is not an edge", a, b),
+            DanglingClass(node) => write!(f, "memory class of non-location node {}", node),
+            DanglingSymbol(a, b, symbol) => {
+                write!(f, "edge {} -> {} maps unused symbol {}", a, b, symbol)
+            }
+            UndeclaredSymbol(a, b, symbol) => {
+                write!(f, "edge {} -> {} uses undeclared symbol {}", a, b, symbol)
+            }
+            DuplicateId(id) => write!(f, "identifier {} is not unique", id),
+            StaleIndex(node) => write!(f, "lookup index of node {} is stale", node),
+        }
+    }
+}
+
+impl ControlFlowGraph {
+    /// Check the invariants of this graph and return the violations.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut issues = BTreeSet::new();
+        let count = self.nodes.len();
+
+        let edges: BTreeSet<(usize, usize)> = self.edges.keys().copied().collect();
+        check_edges(&mut issues, count, &edges, &self.incoming, &self.outgoing);
+        for (&(start, end), condition) in &self.edges {
+            if *condition == SymCondition::FALSE {
+                issues.insert(Diagnostic::FalseEdge(start, end));
+            }
+        }
+
+        for &edge in self.kinds.keys() {
+            if !edges.contains(&edge) {
+                issues.insert(Diagnostic::DanglingKind(edge.0, edge.1));
+            }
+        }
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            if !self.blocks.contains_key(&node.addr) {
+                issues.insert(Diagnostic::MissingBlock(index));
+            }
+            if !self.nodes_at(node.addr).contains(&index) {
+                issues.insert(Diagnostic::StaleIndex(index));
+            }
+        }
+
+        if let (Some(&root), Some(first)) = (self.roots.first(), self.nodes.first()) {
+            if root != first.addr {
+                issues.insert(Diagnostic::MisplacedRoot(root));
+            }
+        }
+
+        issues.into_iter().collect()
+    }
+}
+
+impl DataDependencyGraph {
+    /// Check the invariants of this graph and return the violations.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut issues = BTreeSet::new();
+        let count = self.nodes.len();
+
+        let edges: BTreeSet<(usize, usize)> = self.edges.keys().copied().collect();
+        check_edges(&mut issues, count, &edges, &self.incoming, &self.outgoing);
+
+        for &(start, end) in &self.chains {
+            if !edges.contains(&(start, end)) {
+                issues.insert(Diagnostic::DanglingChain(start, end));
+            }
+        }
+
+        for &index in self.classes.keys() {
+            if !matches!(self.nodes.get(index), Some(DependencyNode::Location(_))) {
+                issues.insert(Diagnostic::DanglingClass(index));
+            }
+        }
+
+        let io: HashSet<Symbol> = self.nodes.iter()
+            .filter_map(|node| match node {
+                DependencyNode::Io(_, symbol) => Some(*symbol),
+                _ => None,
+            })
+            .collect();
+
+        for (&(start, end), (condition, symbols)) in &self.edges {
+            if *condition == SymCondition::FALSE {
+                issues.insert(Diagnostic::FalseEdge(start, end));
+            }
+
+            let used = used_symbols(condition);
+            for symbol in symbols.keys() {
+                if !used.contains(symbol) {
+                    issues.insert(Diagnostic::DanglingSymbol(start, end, *symbol));
+                }
+            }
+            for symbol in used {
+                let declared = symbols.contains_key(&symbol) || io.contains(&symbol);
+                if matches!(symbol.1, "stdin" | "stdout") && !declared {
+                    issues.insert(Diagnostic::UndeclaredSymbol(start, end, symbol));
+                }
+            }
+        }
+
+        issues.into_iter().collect()
+    }
+}
+
+impl ProgramDependenceGraph {
+    /// Check the invariants of this graph and return the violations.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut issues = BTreeSet::new();
+
+        for ((start, end), edge) in self.edges.iter() {
+            if edge.condition == SymCondition::FALSE {
+                issues.insert(Diagnostic::FalseEdge(start, end));
+            }
+        }
+        if self.edges.node_count() != self.nodes.len() {
+            issues.insert(Diagnostic::StaleIndex(self.edges.node_count()));
+        }
+
+        for &index in self.classes.keys() {
+            if !matches!(self.nodes.get(index), Some(DependenceNode::DataDependency(_))) {
+                issues.insert(Diagnostic::DanglingClass(index));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let id = self.id(index);
+            if !seen.insert(id) {
+                issues.insert(Diagnostic::DuplicateId(id));
+            } else if self.index(id) != Some(index) {
+                issues.insert(Diagnostic::StaleIndex(index));
+            }
+
+            let indexed = match node {
+                DependenceNode::ControlFlow(addr) => self.nodes_at(*addr).contains(&index),
+                DependenceNode::DataDependency(location) => self.node_for(location) == Some(index),
+            };
+            if !indexed {
+                issues.insert(Diagnostic::StaleIndex(index));
+            }
+        }
+
+        issues.into_iter().collect()
+    }
+}
+
+/// Check that the edges are in range and agree with the neighbor lists.
+fn check_edges(
+    issues: &mut BTreeSet<Diagnostic>,
+    count: usize,
+    edges: &BTreeSet<(usize, usize)>,
+    incoming: &[Vec<usize>],
+    outgoing: &[Vec<usize>],
+) {
+    for &(start, end) in edges {
+        if start >= count || end >= count {
+            issues.insert(Diagnostic::EdgeOutOfRange(start, end));
+        } else if outgoing.get(start).map(|list| list.contains(&end)) != Some(true)
+            || incoming.get(end).map(|list| list.contains(&start)) != Some(true) {
+            issues.insert(Diagnostic::MissingNeighbor(start, end));
+        }
+    }
+
+    let listed = outgoing.iter().enumerate()
+        .flat_map(|(start, list)| list.iter().map(move |&end| (start, end)))
+        .chain(incoming.iter().enumerate()
+            .flat_map(|(end, list)| list.iter().map(move |&start| (start, end))));
+    for edge in listed {
+        if !edges.contains(&edge) {
+            issues.insert(Diagnostic::ExtraNeighbor(edge.0, edge.1));
+        }
+    }
+}
+
+/// The symbols occurring in a condition.
+fn used_symbols(condition: &SymCondition) -> HashSet<Symbol> {
+    let mut symbols = HashSet::new();
+    condition.traverse(&mut |node| {
+        if let Traversed::Expr(&SymExpr::Sym(symbol)) = node {
+            symbols.insert(symbol);
+        }
+    });
+    symbols
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::math::DataType;
+    use crate::sym::SymbolMap;
+    use crate::x86_64::Register;
+    use super::*;
+    use super::super::{AbstractLocation, StorageLocation, StackFrames, MemoryClass};
+
+    #[test]
+    fn validate() {
+        let eax = StorageLocation::Direct(Register::EAX);
+        let location = |addr| AbstractLocation::new(addr, vec![], eax);
+        let stdin = Symbol(DataType::N8, "stdin", 0);
+        let condition = SymCondition::Equal(
+            Box::new(SymExpr::Sym(stdin)),
+            Box::new(SymExpr::from_int(DataType::N8, 1)),
+        );
+
+        let mut ddg = DataDependencyGraph {
+            nodes: (0 .. 2).map(|addr| DependencyNode::Location(location(addr))).collect(),
+            edges: HashMap::new(),
+            frames: Arc::new(StackFrames::default()),
+            classes: vec![(1, MemoryClass::Register)].into_iter().collect(),
+            chains: vec![(0, 1)].into_iter().collect(),
+            threads: HashSet::new(),
+            synchronizations: HashSet::new(),
+            inputs: HashMap::new(),
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        };
+        ddg.edges.insert((0, 1), (SymCondition::TRUE, SymbolMap::new()));
+        ddg.reindex();
+        assert!(ddg.validate().is_empty());
+
+        let mut symbols = SymbolMap::new();
+        symbols.insert(stdin, location(0));
+        ddg.edges.insert((1, 0), (condition.clone(), symbols));
+        ddg.edges.insert((1, 5), (condition, SymbolMap::new()));
+        ddg.chains.insert((1, 1));
+        ddg.classes.insert(4, MemoryClass::Stack);
+        let issues = ddg.validate();
+        assert_eq!(issues, vec![
+            Diagnostic::EdgeOutOfRange(1, 5),
+            Diagnostic::MissingNeighbor(1, 0),
+            Diagnostic::DanglingChain(1, 1),
+            Diagnostic::DanglingClass(4),
+            Diagnostic::UndeclaredSymbol(1, 5, stdin),
+        ]);
+        assert_eq!(issues[0].to_string(), "edge 1 -> 5 has endpoint out of range");
+
+        let mut cfg = ControlFlowGraph::from_parts(
+            vec![super::super::ControlFlowNode { addr: 0x10, trace: vec![] }],
+            HashMap::new(),
+            HashMap::new(),
+            vec![0x20],
+        );
+        cfg.edges.insert((0, 0), SymCondition::FALSE);
+        cfg.incoming[0].push(0);
+        assert_eq!(cfg.validate(), vec![
+            Diagnostic::MissingNeighbor(0, 0),
+            Diagnostic::FalseEdge(0, 0),
+            Diagnostic::MissingBlock(0),
+            Diagnostic::MisplacedRoot(0x20),
+        ]);
+    }
+}
@@ -0,0 +1,83 @@
+//! Conditions in terms of the variables of the source program.
+//!
+//! With debugging information, the explorer records the values of the source
+//! variables in scope at each conditional jump (see `Program::variables`).
+//! Where such a value appears in the condition of an edge leaving the jump,
+//! it is replaced by the name of the variable, so that a condition reads like
+//! `(len < 0x40:n8 signed)` instead of naming the symbol the value came from.
+
+use crate::helper::replace_whole;
+use super::ControlFlowGraph;
+
+
+impl ControlFlowGraph {
+    /// The rendered condition of an edge leaving the node with the values of
+    /// source variables replaced by their names, if any of them appear.
+    pub fn name_variables(&self, node: usize, rendered: &str) -> Option<String> {
+        let values = self.variables.get(&node)?;
+
+        // Longer values go first, so that values containing others are
+        // replaced as a whole. Values only match whole symbols and registers,
+        // so that `stdin1:n8` does not match within `stdin12:n8`.
+        let mut replacements: Vec<(String, &str)> = values.iter()
+            .map(|(name, value)| (value.to_string(), name.as_str()))
+            .collect();
+        replacements.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+
+        let part = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+        let mut named = rendered.to_string();
+        for (value, name) in replacements {
+            named = replace_whole(&named, part, &value, name);
+        }
+        match named != rendered {
+            true => Some(named),
+            false => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::math::SymCondition;
+    use super::*;
+
+    #[test]
+    fn variables() {
+        let program = Program::new(fixture("variables"));
+        assert!(program.variables.iter().any(|variable| variable.name == "len"));
+
+        // Both edges of the comparison in main are conditioned on the
+        // variable, which is the byte read from standard input.
+        let cfg = ControlFlowGraph::new(&program);
+        let main = program.symbols.iter()
+            .find(|(_, name)| name.as_str() == "main")
+            .map(|(&addr, _)| addr)
+            .unwrap();
+        let branch = (0 .. cfg.nodes.len())
+            .find(|&node| cfg.nodes[node].addr >= main && cfg.outgoing[node].len() == 2)
+            .unwrap();
+        for (_, condition) in cfg.edges_from(branch) {
+            assert_ne!(condition, &SymCondition::TRUE);
+            let named = cfg.name_variables(branch, &condition.to_string()).unwrap();
+            assert!(named.contains("len"));
+            assert!(!named.contains("stdin"));
+        }
+    }
+
+    #[test]
+    fn whole_values() {
+        use crate::flow::ssa::tests::graph;
+        use crate::math::{DataType, SymExpr, Symbol};
+
+        // nop
+        let mut cfg = graph(&[(0x1000, &[&[0x90]])], &[]);
+        let value = SymExpr::Sym(Symbol(DataType::N8, "stdin", 1));
+        cfg.variables.insert(0, vec![("n".to_string(), value)]);
+        let named = cfg.name_variables(0, "(stdin12:n8 + stdin1:n8)");
+        assert_eq!(named.as_deref(), Some("(stdin12:n8 + n)"));
+        assert_eq!(cfg.name_variables(0, "stdin12:n8"), None);
+    }
+}
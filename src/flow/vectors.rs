@@ -0,0 +1,266 @@
+//! Interrupt handlers from the vector tables of firmware images.
+//!
+//! A vector table lists the handlers of the interrupts and exceptions, which
+//! the processor calls at any time. These layouts are supported:
+//! - `idt`: The interrupt descriptor table of x86-64, whose 16-byte gates
+//!   hold the address of the handler split into three parts. Gates that are
+//!   not present or neither interrupt nor trap gates are unused.
+//! - `pointers`: The 64-bit little-endian addresses of the handlers.
+//! - `pointers32`: The 32-bit little-endian addresses of the handlers, like
+//!   in the tables of firmware starting in protected mode.
+//!
+//! Entries of zero are unused. The table of a bare-metal profile is either
+//! at a given address, where its handlers are the ones in the image, or
+//! found in the image as the longest run of at least `MIN_VECTORS` aligned
+//! entries that all point to decoded instructions. Since other data can
+//! point to instructions as well, the search only runs when asked for.
+//!
+//! Handlers return into the interrupted code through an interrupt frame of
+//! 64-bit slots with `iretq` or of 32-bit slots with `iret`.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::Program;
+use crate::math::DataType;
+use crate::x86_64::{Instruction, Mnemoic};
+use super::directed::returns;
+
+
+/// A table of interrupt handlers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VectorTable {
+    pub layout: VectorLayout,
+    /// The address of the table.
+    pub addr: Option<u64>,
+    /// Whether the table is searched for in the image if it has no address.
+    pub search: bool,
+    /// The number of entries, by default all up to the end of the image or
+    /// of the table that was found.
+    pub count: Option<usize>,
+}
+
+/// How the entries of a vector table store the handlers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum VectorLayout {
+    Idt,
+    Pointers,
+    Pointers32,
+}
+
+/// The minimum number of entries of a table found in an image.
+pub const MIN_VECTORS: usize = 8;
+
+impl VectorTable {
+    /// The handlers of the table in the image of the program in the order of
+    /// their entries and without duplicates.
+    pub fn handlers(&self, program: &Program) -> Vec<u64> {
+        let (addr, found) = match self.addr {
+            Some(addr) => (addr, usize::MAX),
+            None if self.search => match self.layout.find(program) {
+                Some(table) => table,
+                None => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+
+        let end = program.base + program.binary.len() as u64;
+        let offset = match addr.checked_sub(program.base) {
+            Some(offset) if addr < end => offset as usize,
+            _ => return Vec::new(),
+        };
+
+        let mut handlers = Vec::new();
+        let entries = program.binary[offset ..].chunks_exact(self.layout.entry_size());
+        for entry in entries.take(self.count.unwrap_or(found)) {
+            match self.layout.handler(entry) {
+                Some(handler) if (program.base .. end).contains(&handler) => {
+                    if !handlers.contains(&handler) {
+                        handlers.push(handler);
+                    }
+                },
+                _ => {},
+            }
+        }
+        handlers
+    }
+}
+
+impl VectorLayout {
+    /// The layout with the name if there is one.
+    pub fn from_name(name: &str) -> Option<VectorLayout> {
+        Some(match name {
+            "idt" => VectorLayout::Idt,
+            "pointers" => VectorLayout::Pointers,
+            "pointers32" => VectorLayout::Pointers32,
+            _ => return None,
+        })
+    }
+
+    /// The number of bytes of an entry.
+    pub fn entry_size(self) -> usize {
+        match self {
+            VectorLayout::Idt => 16,
+            VectorLayout::Pointers => 8,
+            VectorLayout::Pointers32 => 4,
+        }
+    }
+
+    /// The handler of an entry unless it is unused or too short.
+    fn handler(self, entry: &[u8]) -> Option<u64> {
+        if entry.len() < self.entry_size() {
+            return None;
+        }
+        let word = |range: Range<usize>| entry[range].iter().rev()
+            .fold(0, |value, &byte| value << 8 | byte as u64);
+        let handler = match self {
+            VectorLayout::Idt => {
+                let (present, gate) = (entry[5] & 0x80 != 0, entry[5] & 0x0f);
+                if !present || (gate != 0xe && gate != 0xf) {
+                    return None;
+                }
+                word(0 .. 2) | word(6 .. 8) << 16 | word(8 .. 12) << 32
+            },
+            VectorLayout::Pointers => word(0 .. 8),
+            VectorLayout::Pointers32 => word(0 .. 4),
+        };
+        Some(handler).filter(|&handler| handler != 0)
+    }
+
+    /// The address and number of entries of the longest run of at least
+    /// `MIN_VECTORS` entries in the image that all point to instructions.
+    fn find(self, program: &Program) -> Option<(u64, usize)> {
+        let starts: HashSet<u64> = program.code.iter().map(|&(addr, ..)| addr).collect();
+        let size = self.entry_size();
+        let first = (size as u64 - program.base % size as u64) as usize % size;
+
+        let mut best: Option<(usize, usize)> = None;
+        let mut run: Option<(usize, usize)> = None;
+        for offset in (first .. program.binary.len()).step_by(size) {
+            let valid = program.binary.get(offset .. offset + size)
+                .and_then(|entry| self.handler(entry))
+                .map_or(false, |handler| starts.contains(&handler));
+            run = match (valid, run) {
+                (false, _) => None,
+                (true, Some((start, count))) => Some((start, count + 1)),
+                (true, None) => Some((offset, 1)),
+            };
+            if let Some((start, count)) = run {
+                if best.map_or(true, |(_, longest)| count > longest) {
+                    best = Some((start, count));
+                }
+            }
+        }
+
+        best.filter(|&(_, count)| count >= MIN_VECTORS)
+            .map(|(start, count)| (program.base + start as u64, count))
+    }
+}
+
+/// The interrupt handlers of the program with the width of the slots of the
+/// interrupt frames they return through, which is 32 bits for handlers
+/// returning with `iret` and 64 bits otherwise.
+pub(super) fn interrupt_frames(program: &Program) -> Vec<(u64, DataType)> {
+    let instructions: HashMap<u64, (u64, &Instruction)> = program.code.iter()
+        .map(|(addr, len, instruction, _)| (*addr, (*len, instruction)))
+        .collect();
+    program.handlers.iter()
+        .map(|&handler| {
+            let iret = returns(&instructions, handler).iter()
+                .filter_map(|addr| instructions.get(addr))
+                .any(|(_, instruction)| instruction.mnemoic == Mnemoic::Iret);
+            (handler, if iret { DataType::N32 } else { DataType::N64 })
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::sym::SymConfig;
+    use super::super::{BareMetal, CallGraph, ControlFlowGraph};
+    use super::*;
+
+    /// A gate of the interrupt descriptor table for the handler.
+    fn gate(handler: u64) -> Vec<u8> {
+        let mut gate = vec![0; 16];
+        gate[0 .. 2].copy_from_slice(&(handler as u16).to_le_bytes());
+        gate[2 .. 4].copy_from_slice(&8u16.to_le_bytes());
+        gate[5] = 0x8e;
+        gate[6 .. 8].copy_from_slice(&((handler >> 16) as u16).to_le_bytes());
+        gate[8 .. 12].copy_from_slice(&((handler >> 32) as u32).to_le_bytes());
+        gate
+    }
+
+    #[test]
+    fn vectors() {
+        // Eight handlers `xor eax, eax; ret` and the entry `mov eax, 60;
+        // syscall`, followed by a table at 0x10020.
+        let mut image = Vec::new();
+        for _ in 0 .. 8 {
+            image.extend(&[0x31, 0xc0, 0xc3]);
+        }
+        image.extend(&[0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x90]);
+        let handlers: Vec<u64> = (0 .. 8).map(|index| 0x10000 + 3 * index).collect();
+        let mut table = Vec::new();
+        for &handler in &handlers {
+            table.extend(gate(handler));
+        }
+        let mut unused = gate(0x10000);
+        unused[5] = 0x0e;
+        table.extend(&unused);
+        image.extend(&table);
+
+        let profile = |table: &str| BareMetal::from_json(&format!(
+            r#"{{"base": "0x10000", "entry": "0x10018", "vector_table": {}}}"#, table,
+        )).unwrap();
+
+        // The table is found, and all of its handlers are roots.
+        let program = profile(r#"{"layout": "idt", "search": true}"#).program(&image);
+        assert_eq!(program.handlers, handlers);
+        let mut roots = handlers.clone();
+        roots.push(0x10018);
+        assert_eq!(program.roots(), roots);
+        let graph = CallGraph::new(&program);
+        assert!(handlers.iter().all(|handler| graph.callees.contains_key(handler)));
+
+        let program = profile(r#"{"layout": "idt", "address": "0x10020", "count": 2}"#)
+            .program(&image);
+        assert_eq!(program.handlers, handlers[.. 2]);
+        assert_eq!(VectorLayout::Idt.handler(&unused), None);
+
+        // Pointer tables list the addresses directly.
+        let pointers: Vec<u8> = [0x10003u32, 0, 0x10000, 0x10003].iter()
+            .flat_map(|pointer| pointer.to_le_bytes().to_vec())
+            .collect();
+        let mut image = image[.. 0x20].to_vec();
+        image.extend(&pointers);
+        let program = profile(r#"{"layout": "pointers32", "address": "0x10020"}"#)
+            .program(&image);
+        assert_eq!(program.handlers, vec![0x10003, 0x10000]);
+        let search = profile(r#"{"layout": "pointers32", "search": true}"#);
+        assert!(search.program(&image).handlers.is_empty());
+        assert_eq!(VectorLayout::Pointers.handler(&pointers[.. 4]), None);
+    }
+
+    #[test]
+    fn interrupt_returns() {
+        // Two handlers `xor eax, eax; iretq` and `iret` and the entry `mov
+        // eax, 60; syscall`, followed by a table at 0x10010.
+        let mut image = vec![0x31, 0xc0, 0x48, 0xcf, 0xcf, 0x90, 0x90, 0x90];
+        image.extend(&[0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x90]);
+        image.extend(gate(0x10000));
+        image.extend(gate(0x10004));
+        let profile = BareMetal::from_json(r#"{"base": "0x10000", "entry": "0x10008",
+            "registers": {"rsp": "0x8000"},
+            "vector_table": {"layout": "idt", "address": "0x10010", "count": 2}}"#).unwrap();
+        let program = profile.program(&image);
+        assert_eq!(program.roots(), vec![0x10000, 0x10004, 0x10008]);
+
+        // Each handler returns into the next root through its interrupt frame,
+        // whose slots are as wide as its interrupt return reads them.
+        let cfg = ControlFlowGraph::with_config(&program, profile.config(SymConfig::default()));
+        assert_eq!(cfg.handlers, vec![(0x10000, DataType::N64), (0x10004, DataType::N32)]);
+        assert!(program.roots().iter().all(|root| cfg.addresses.contains_key(root)));
+    }
+}
@@ -3,16 +3,237 @@
 use std::collections::HashMap;
 use std::io::{Result, Write};
 
+use crate::Program;
+use crate::helper::json_string;
+use crate::math::{SymCondition, Solver};
+use crate::sym::{SymConfig, CallingConvention};
+use crate::x86_64::Instruction;
+use super::{EdgeKind, MemoryClass};
+use super::pdg::Digest;
+
 pub const BR: &str = "<br align=\"left\"/>";
 
 
+/// How rendered graphs are laid out and styled. The defaults reproduce the
+/// built-in styles of each graph.
+#[derive(Debug, Clone)]
+pub struct VisualConfig {
+    /// The direction of the ranks like `LR`, graphviz lays out top to bottom
+    /// if it is not set.
+    pub rankdir: Option<String>,
+    /// How edges are drawn like `ortho` or `polyline`.
+    pub splines: Option<String>,
+    /// The font of all text.
+    pub font: String,
+    /// The font size of the title instead of the one of each graph.
+    pub title_size: Option<u32>,
+    /// Graphviz attributes replacing the default style of edges of a kind.
+    pub edge_styles: HashMap<EdgeKind, String>,
+    /// The shape of block nodes instead of a box.
+    pub block_shape: Option<String>,
+    /// The shape of location nodes instead of the one of each graph.
+    pub location_shape: Option<String>,
+    /// The maximum number of characters of condition labels, longer ones
+    /// are shortened as the elision says.
+    pub max_label: Option<usize>,
+    /// How labels longer than the maximum are shortened.
+    pub elision: Elision,
+    /// Whether to simplify conditions with the solver before they are written.
+    pub simplify: bool,
+    /// Whether to attach the full conditions to edges as tooltips.
+    pub tooltips: bool,
+    /// What the disassembly listings of blocks show.
+    pub listing: Listing,
+    /// Whether to add a legend explaining the node shapes and edge styles.
+    pub legend: bool,
+    /// The origin of the graph written below it.
+    pub metadata: Option<Metadata>,
+}
+
+/// How labels longer than the maximum length are shortened.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Elision {
+    /// Keep the start and cut off the end.
+    End,
+    /// Keep the start and the end and cut out the middle.
+    Middle,
+}
+
+/// Formats the conditions of edges as configured, simplifying them with its
+/// own solver if asked to.
+pub struct Conditions<'c> {
+    config: &'c VisualConfig,
+    solver: Option<Solver>,
+}
+
+/// What the disassembly listings of blocks show besides the instructions.
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    /// Show the encoded bytes of each instruction.
+    pub bytes: bool,
+    /// Source lines shown above the instructions at their addresses.
+    pub lines: HashMap<u64, String>,
+}
+
+/// Where a rendered graph comes from.
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    /// The name of the analyzed binary.
+    pub binary: String,
+    /// The hash of the code of the binary.
+    pub hash: u64,
+    /// The version of this crate.
+    pub version: String,
+    /// The options of the analysis as (name, value) pairs.
+    pub options: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// The metadata of an analysis of the program in the environment.
+    pub fn new(binary: &str, program: &Program, config: &SymConfig) -> Metadata {
+        let mut digest = Digest::new(b'p');
+        digest.bytes(&program.binary);
+
+        let convention = if config.convention == CallingConvention::sysv() {
+            "sysv"
+        } else if config.convention == CallingConvention::microsoft() {
+            "microsoft"
+        } else {
+            "custom"
+        };
+        let mut registers: Vec<_> = config.registers.iter().collect();
+        registers.sort();
+        let registers: Vec<String> = registers.into_iter()
+            .map(|(register, value)| format!("{}={:#x}", register, value))
+            .collect();
+        let options = vec![
+            ("convention", convention.to_string()),
+            ("signals", config.signals.to_string()),
+            ("policy", format!("{:?}", config.memory.policy)),
+            ("select limit", config.memory.select_limit.to_string()),
+            ("threads", config.threads.to_string()),
+            ("seed", config.seed.to_string()),
+            ("scope", config.scope.to_string()),
+            ("mmio", config.mmio.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")),
+            ("registers", registers.join(", ")),
+        ];
+
+        Metadata {
+            binary: binary.to_string(),
+            hash: digest.0,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            options: options.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        }
+    }
+}
+
+impl VisualConfig {
+    /// The graphviz attributes of edges of the kind.
+    pub fn edge_style(&self, kind: EdgeKind) -> &str {
+        self.edge_styles.get(&kind).map_or(kind.style(), String::as_str)
+    }
+
+    /// The shape of block nodes.
+    pub fn block_shape(&self) -> &str {
+        self.block_shape.as_deref().unwrap_or("box")
+    }
+
+    /// The shape of location nodes, which is `default` unless configured.
+    pub fn location_shape<'a>(&'a self, default: &'a str) -> &'a str {
+        self.location_shape.as_deref().unwrap_or(default)
+    }
+
+    /// Shorten the label to the maximum number of characters.
+    pub fn label(&self, label: String) -> String {
+        let count = label.chars().count();
+        match self.max_label {
+            Some(max) if count > max => match self.elision {
+                Elision::End => {
+                    let mut cut: String = label.chars().take(max).collect();
+                    cut.push_str("...");
+                    cut
+                }
+                Elision::Middle => {
+                    let mut cut: String = label.chars().take(max - max / 2).collect();
+                    cut.push_str("...");
+                    cut.extend(label.chars().skip(count - max / 2));
+                    cut
+                }
+            },
+            _ => label,
+        }
+    }
+}
+
+impl<'c> Conditions<'c> {
+    pub fn new(config: &'c VisualConfig) -> Conditions<'c> {
+        let solver = if config.simplify { Some(Solver::new()) } else { None };
+        Conditions { config, solver }
+    }
+
+    /// The condition as it is rendered, simplified if configured.
+    pub fn full(&self, condition: &SymCondition) -> String {
+        match &self.solver {
+            Some(solver) => solver.simplify_condition(condition).to_string(),
+            None => condition.to_string(),
+        }
+    }
+
+    /// The condition for a label, shortened to the maximum length.
+    pub fn label(&self, condition: &SymCondition) -> String {
+        self.config.label(self.full(condition))
+    }
+
+    /// The tooltip attribute with the full condition if tooltips are
+    /// configured.
+    pub fn tooltip(&self, condition: &SymCondition) -> String {
+        match self.config.tooltips {
+            true => format!("tooltip=\"{}\", ", quote(&self.full(condition))),
+            false => String::new(),
+        }
+    }
+}
+
+impl Default for VisualConfig {
+    fn default() -> VisualConfig {
+        VisualConfig {
+            rankdir: None,
+            splines: None,
+            font: "Source Code Pro".to_string(),
+            title_size: None,
+            edge_styles: HashMap::new(),
+            block_shape: None,
+            location_shape: None,
+            max_label: None,
+            elision: Elision::End,
+            simplify: false,
+            tooltips: false,
+            listing: Listing::default(),
+            legend: false,
+            metadata: None,
+        }
+    }
+}
+
 /// Write the preamble of the graphviz file.
-pub fn write_header<W: Write>(mut f: W, title: &str, fontsize: u32) -> Result<()> {
+pub fn write_header<W: Write>(
+    mut f: W,
+    title: &str,
+    fontsize: u32,
+    config: &VisualConfig,
+) -> Result<()> {
+    let fontsize = config.title_size.unwrap_or(fontsize);
     writeln!(f, "digraph Flow {{")?;
     write!(f, "graph [label=\"{}\", labelloc=\"t\", fontsize={}, ", title, fontsize)?;
-    writeln!(f, "fontname=\"Source Code Pro\"]")?;
-    writeln!(f, "node [fontname=\"Source Code Pro\"]")?;
-    writeln!(f, "edge [fontname=\"Source Code Pro\"]")
+    if let Some(rankdir) = &config.rankdir {
+        write!(f, "rankdir=\"{}\", ", rankdir)?;
+    }
+    if let Some(splines) = &config.splines {
+        write!(f, "splines=\"{}\", ", splines)?;
+    }
+    writeln!(f, "fontname=\"{}\"]", config.font)?;
+    writeln!(f, "node [fontname=\"{}\"]", config.font)?;
+    writeln!(f, "edge [fontname=\"{}\"]", config.font)
 }
 
 /// Write condition edges.
@@ -33,8 +254,111 @@ pub fn write_edges<W: Write, F, T>(
     Ok(())
 }
 
-/// Write the closing of the file.
-pub fn write_footer<W: Write>(mut f: W) -> Result<()> {
+/// Write an instruction as a left-aligned line of an HTML-like label,
+/// preceded by its source line if the listing has one.
+pub fn write_instruction<W: Write>(
+    mut f: W,
+    addr: u64,
+    instruction: &Instruction,
+    listing: &Listing,
+    note: &str,
+) -> Result<()> {
+    if let Some(line) = listing.lines.get(&addr) {
+        write!(f, "<i>{}</i>{}", escape(line), BR)?;
+    }
+    write!(f, "{:x}: ", addr)?;
+    if listing.bytes {
+        let bytes: Vec<String> = instruction.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{:<21} ", bytes.join(" "))?;
+    }
+    write!(f, "{}{}{}", escape(&instruction.to_string()), note, BR)
+}
+
+/// Write the full conditions of edges between named nodes as a JSON array,
+/// which can accompany a graph whose labels are shortened.
+pub fn write_conditions<W: Write>(
+    mut f: W,
+    conditions: &Conditions,
+    edges: &[(String, String, &SymCondition)],
+) -> Result<()> {
+    writeln!(f, "[")?;
+    for (i, (start, end, condition)) in edges.iter().enumerate() {
+        let separator = if i + 1 < edges.len() { "," } else { "" };
+        writeln!(f, "  {{\"start\": {}, \"end\": {}, \"condition\": {}}}{}",
+                 json_string(start), json_string(end),
+                 json_string(&conditions.full(condition)), separator)?;
+    }
+    writeln!(f, "]")
+}
+
+/// Escape text for use in a quoted string.
+pub fn quote(text: &str) -> String {
+    text.replace("\\", "\\\\").replace("\"", "\\\"")
+}
+
+/// Escape text for use in an HTML-like label.
+pub fn escape(text: &str) -> String {
+    text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
+}
+
+/// Write a legend cluster explaining the nodes and edges, given as labels
+/// and graphviz attributes, if the configuration asks for one.
+pub fn write_legend<W: Write>(
+    mut f: W,
+    config: &VisualConfig,
+    nodes: &[(String, String)],
+    edges: &[(String, String)],
+) -> Result<()> {
+    if !config.legend {
+        return Ok(());
+    }
+
+    writeln!(f, "subgraph cluster_legend {{")?;
+    writeln!(f, "label=\"Legend\"")?;
+    for (index, (label, attributes)) in nodes.iter().enumerate() {
+        writeln!(f, "legend_n{} [label=\"{}\", {}]", index, label, attributes)?;
+    }
+    for (index, (label, attributes)) in edges.iter().enumerate() {
+        writeln!(f, "legend_s{} [shape=point, width=0.05]", index)?;
+        writeln!(f, "legend_e{} [shape=point, width=0.05]", index)?;
+        writeln!(f, "legend_s{} -> legend_e{} [label=\"{}\", {}]",
+                 index, index, label, attributes)?;
+    }
+    writeln!(f, "}}")
+}
+
+/// Legend entries for locations of each memory class in the shape.
+pub fn class_legend(shape: &str) -> Vec<(String, String)> {
+    MemoryClass::ALL.iter()
+        .map(|class| (class.to_string(), filled(shape, class.color())))
+        .collect()
+}
+
+/// Legend entries for the configured edge styles.
+pub fn edge_style_legend(config: &VisualConfig) -> Vec<(String, String)> {
+    EdgeKind::ALL.iter()
+        .filter_map(|kind| Some((kind.name().to_string(), config.edge_styles.get(kind)?.clone())))
+        .collect()
+}
+
+/// The attributes of a node of the shape filled with the color.
+pub fn filled(shape: &str, color: &str) -> String {
+    format!("shape={}, style=filled, fillcolor=\"{}\"", shape, color)
+}
+
+/// Write the metadata if the configuration has some and the closing of the
+/// file.
+pub fn write_footer<W: Write>(mut f: W, config: &VisualConfig) -> Result<()> {
+    if let Some(metadata) = &config.metadata {
+        write!(f, "metadata [shape=plaintext, fontsize=10, label=<")?;
+        write!(f, "binary: {}{}", escape(&metadata.binary), BR)?;
+        write!(f, "hash: {:016x}{}", metadata.hash, BR)?;
+        write!(f, "symflow {}{}", escape(&metadata.version), BR)?;
+        for (name, value) in &metadata.options {
+            write!(f, "{}: {}{}", escape(name), escape(value), BR)?;
+        }
+        writeln!(f, ">]")?;
+    }
     writeln!(f, "}}")
 }
 
@@ -87,4 +411,110 @@ where
     Ok(())
 }
 
+    #[test]
+    fn config() {
+        let mut config = VisualConfig::default();
+        let header = |config: &VisualConfig| {
+            let mut f = Vec::new();
+            write_header(&mut f, "cfg", 20, config).unwrap();
+            String::from_utf8(f).unwrap()
+        };
+        assert!(header(&config).contains("fontsize=20, fontname=\"Source Code Pro\"]"));
+        assert_eq!(config.edge_style(EdgeKind::Call), EdgeKind::Call.style());
+        assert_eq!(config.label("a < b".to_string()), "a < b");
+
+        config.rankdir = Some("LR".to_string());
+        config.splines = Some("ortho".to_string());
+        config.font = "Iosevka".to_string();
+        config.title_size = Some(30);
+        config.edge_styles.insert(EdgeKind::Call, "color=red".to_string());
+        config.max_label = Some(3);
+        let header = header(&config);
+        assert!(header.contains("fontsize=30, rankdir=\"LR\", splines=\"ortho\", "));
+        assert!(header.contains("edge [fontname=\"Iosevka\"]"));
+        assert_eq!(config.edge_style(EdgeKind::Call), "color=red");
+        assert_eq!(config.edge_style(EdgeKind::Return), EdgeKind::Return.style());
+        assert_eq!(config.label("a < b".to_string()), "a <...");
+        assert_eq!(config.location_shape("ellipse"), "ellipse");
+
+        config.elision = Elision::Middle;
+        assert_eq!(config.label("a < b".to_string()), "a ...b");
+    }
+
+    #[test]
+    fn conditions() {
+        use crate::math::{SymExpr, Symbol, DataType};
+
+        let symbol = |index| SymExpr::Sym(Symbol(DataType::N8, "stdin", index));
+        let condition = SymCondition::Equal(Box::new(symbol(0)), Box::new(symbol(1)));
+        let full = condition.to_string();
+
+        let mut config = VisualConfig { max_label: Some(4), .. VisualConfig::default() };
+        assert_eq!(Conditions::new(&config).tooltip(&condition), "");
+
+        config.tooltips = true;
+        let conditions = Conditions::new(&config);
+        assert_eq!(conditions.full(&condition), full);
+        assert_eq!(conditions.label(&condition), format!("{}...", &full[.. 4]));
+        assert_eq!(conditions.tooltip(&condition), format!("tooltip=\"{}\", ", full));
+
+        let mut f = Vec::new();
+        let edges = vec![("b0".to_string(), "b1".to_string(), &condition)];
+        write_conditions(&mut f, &conditions, &edges).unwrap();
+        assert_eq!(String::from_utf8(f).unwrap(), format!(
+            "[\n  {{\"start\": \"b0\", \"end\": \"b1\", \"condition\": {}}}\n]\n",
+            json_string(&full),
+        ));
+        assert_eq!(quote("say \"hi\""), "say \\\"hi\\\"");
+    }
+
+    #[test]
+    fn legend_and_metadata() {
+        let render = |config: &VisualConfig| {
+            let mut f = Vec::new();
+            let nodes = vec![("block".to_string(), "shape=box".to_string())];
+            let edges = vec![("call".to_string(), EdgeKind::Call.style().to_string())];
+            write_legend(&mut f, config, &nodes, &edges).unwrap();
+            write_footer(&mut f, config).unwrap();
+            String::from_utf8(f).unwrap()
+        };
+
+        let mut config = VisualConfig::default();
+        assert_eq!(render(&config), "}\n");
+
+        config.legend = true;
+        config.metadata = Some(Metadata {
+            binary: "bufs".to_string(),
+            hash: 0xabc,
+            version: "0.1.0".to_string(),
+            options: vec![("threads".to_string(), "4".to_string())],
+        });
+        let output = render(&config);
+        assert!(output.starts_with("subgraph cluster_legend {\nlabel=\"Legend\"\n"));
+        assert!(output.contains("legend_n0 [label=\"block\", shape=box]"));
+        let edge = "legend_s0 -> legend_e0 [label=\"call\", style=bold, color=darkgreen]";
+        assert!(output.contains(edge));
+        assert!(output.contains(&format!("binary: bufs{}hash: 0000000000000abc{}", BR, BR)));
+        assert!(output.ends_with(&format!("threads: 4{}>]\n}}\n", BR)));
+    }
+
+    #[test]
+    fn listing() {
+        let instruction = Instruction::decode(&[0x3c, 0x40]).unwrap();
+        let render = |listing: &Listing, note: &str| {
+            let mut f = Vec::new();
+            write_instruction(&mut f, 0x1000, &instruction, listing, note).unwrap();
+            String::from_utf8(f).unwrap()
+        };
+
+        let mut listing = Listing::default();
+        assert_eq!(render(&listing, ""), format!("1000: cmp al, 0x40{}", BR));
+
+        listing.bytes = true;
+        listing.lines.insert(0x1000, "if (c < '@')".to_string());
+        assert_eq!(render(&listing, " (overlapping)"), format!(
+            "<i>if (c &lt; '@')</i>{}1000: 3c 40                 cmp al, 0x40 (overlapping){}",
+            BR, BR,
+        ));
+    }
 }
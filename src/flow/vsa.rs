@@ -0,0 +1,1286 @@
+//! Value-set analysis over the control flow graph.
+//!
+//! This is a cheap and sound companion to the symbolic execution: every
+//! abstract location (registers and memory cells at known offsets) holds a set
+//! of strided intervals, one per memory region it may point into. The analysis
+//! interprets the microcode of the blocks and joins the states of all paths
+//! reaching a node until a fixpoint is found. With `SymConfig::value_sets`,
+//! the data dependency graph uses it to rule out aliasing of memory operands
+//! before asking the solver.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::ops::Bound::Included;
+
+use crate::ir::{Microcode, MicroOperation, BitOp, Location, Temporary, MemoryMapped};
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol};
+use crate::sym::{Allocator, InputFunction, MemoryImage};
+use crate::x86_64::Register;
+use super::{ControlFlowGraph, ControlKind, AbstractLocation, StorageLocation};
+use DataType::*;
+
+
+/// The set of integers `lower, lower + stride, ..., upper`.
+///
+/// Values of 64-bit locations are their two's complement interpretation,
+/// values of smaller ones are unsigned.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct StridedInterval {
+    /// The distance between neighbouring values, zero for a single value.
+    pub stride: u64,
+    pub lower: i64,
+    pub upper: i64,
+}
+
+/// A memory region the values of pointers are relative to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MemoryRegion {
+    /// Absolute addresses and plain numbers.
+    Global,
+    /// Offsets from the stack pointer at the start of the program.
+    Stack,
+    /// Offsets from the base of the heap region of the allocation site.
+    Heap(u64),
+}
+
+/// The possible values of a location as strided intervals by region.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ValueSet {
+    /// Any value at all.
+    Top,
+    /// The union of the offsets in the regions. No regions means no value.
+    Values(BTreeMap<MemoryRegion, StridedInterval>),
+}
+
+/// The values of all abstract locations at one program point.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct AbstractState {
+    /// The values of the locations written so far with their data types.
+    /// Missing registers and stack or heap cells hold any value.
+    values: BTreeMap<(Space, i64), (DataType, ValueSet)>,
+    /// Whether global memory was written at unknown addresses, so that missing
+    /// global cells do not hold their initial values anymore.
+    havocked: bool,
+}
+
+/// The results of the value-set analysis of a control flow graph.
+#[derive(Debug, Clone)]
+pub struct ValueSetAnalysis {
+    /// The states before the instructions by address and call trace, joined
+    /// over all nodes with the instruction in that context.
+    states: HashMap<(u64, Vec<u64>), AbstractState>,
+    /// The targets of the jumps ending blocks by the address of the jumping
+    /// instruction and call trace.
+    jumps: HashMap<(u64, Vec<u64>), ValueSet>,
+}
+
+/// Where an abstract location lives.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+enum Space {
+    Register,
+    Memory(MemoryRegion),
+}
+
+/// How often a node is joined with new states before widening kicks in.
+const WIDENING_DELAY: usize = 3;
+/// The maximum number of addresses an access is split into before it is
+/// treated as an access to the whole region.
+const ACCESS_LIMIT: u64 = 64;
+
+impl ValueSetAnalysis {
+    /// Run the analysis on the graph.
+    pub fn new(cfg: &ControlFlowGraph) -> ValueSetAnalysis {
+        crate::timings::with("value-set-analysis", || ValueSetAnalysis::run(cfg))
+    }
+
+    /// The state before the instruction at the address in the call trace if the
+    /// instruction is reachable in that context.
+    pub fn state_before(&self, addr: u64, trace: &[u64]) -> Option<&AbstractState> {
+        self.states.get(&(addr, trace.to_vec()))
+    }
+
+    /// The possible addresses of the memory accessed by a location, or `None`
+    /// if it is a register or unreachable.
+    pub fn address(&self, location: &AbstractLocation) -> Option<ValueSet> {
+        self.state_before(location.addr, &location.trace)?.address(location.storage)
+    }
+
+    /// Whether the locations can refer to overlapping storage.
+    pub fn may_alias(&self, a: &AbstractLocation, b: &AbstractLocation) -> bool {
+        match (a.storage.accesses_memory(), b.storage.accesses_memory()) {
+            (false, false) => a.storage.normalized() == b.storage.normalized(),
+            (true, true) => match (self.address(a), self.address(b)) {
+                (Some(x), Some(y)) => {
+                    let size = |loc: &AbstractLocation| loc.storage.data_type().bytes() as u64;
+                    x.may_overlap(size(a), &y, size(b))
+                },
+                // Unreachable locations never access anything.
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The absolute targets of the jump at the address in the call trace if
+    /// there are at most `limit` of them.
+    pub fn jump_targets(&self, addr: u64, trace: &[u64], limit: usize) -> Option<Vec<u64>> {
+        let target = self.jumps.get(&(addr, trace.to_vec()))?;
+        let values = target.global()?.values(limit as u64)?;
+        Some(values.into_iter().map(|value| value as u64).collect())
+    }
+
+    /// Find the fixpoint of the states at the entries of the nodes and record
+    /// the states before all instructions.
+    fn run(cfg: &ControlFlowGraph) -> ValueSetAnalysis {
+        let image = &*cfg.config.memory.image;
+        let count = cfg.nodes.len();
+
+        let mut entries: Vec<Option<AbstractState>> = vec![None; count];
+        let mut visits = vec![0; count];
+        let mut worklist = Vec::new();
+        if count > 0 {
+            entries[0] = Some(AbstractState::initial(&cfg.roots, &cfg.handlers));
+            worklist.push(0);
+        }
+
+        while let Some(node) = worklist.pop() {
+            let state = entries[node].clone().expect("value-set analysis: missing entry state");
+            let mut interpreter = Interpreter::new(state, image);
+            interpreter.run(cfg, node, |_, _, _| {});
+
+            for &succ in &cfg.outgoing[node] {
+                let state = match interpreter.successor(cfg, node, succ) {
+                    Some(state) => state,
+                    None => continue,
+                };
+
+                let merged = match &entries[succ] {
+                    Some(old) if visits[succ] >= WIDENING_DELAY => old.widen(&state),
+                    Some(old) => old.join(&state),
+                    None => state,
+                };
+
+                if entries[succ].as_ref() != Some(&merged) {
+                    entries[succ] = Some(merged);
+                    visits[succ] += 1;
+                    if !worklist.contains(&succ) {
+                        worklist.push(succ);
+                    }
+                }
+            }
+        }
+
+        // Replay every block once from its final entry state.
+        let mut analysis = ValueSetAnalysis { states: HashMap::new(), jumps: HashMap::new() };
+        for (node, entry) in entries.into_iter().enumerate() {
+            let entry = match entry {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            let trace: Vec<u64> = cfg.nodes[node].trace.iter().map(|&(site, _)| site).collect();
+            let mut interpreter = Interpreter::new(entry, image);
+            let states = &mut analysis.states;
+            interpreter.run(cfg, node, |addr, state, _| {
+                let key = (addr, trace.clone());
+                let joined = match states.get(&key) {
+                    Some(previous) => previous.join(state),
+                    None => state.clone(),
+                };
+                states.insert(key, joined);
+            });
+
+            if let Some((addr, target)) = interpreter.jump {
+                let key = (addr, trace);
+                let joined = match analysis.jumps.get(&key) {
+                    Some(previous) => previous.join(&target),
+                    None => target,
+                };
+                analysis.jumps.insert(key, joined);
+            }
+        }
+
+        analysis
+    }
+}
+
+impl AbstractState {
+    /// The state at the start of the program, where the stack pointer points
+    /// to the return addresses of the roots after the first one, which are
+    /// interrupt frames after the interrupt handlers.
+    fn initial(roots: &[u64], handlers: &[(u64, DataType)]) -> AbstractState {
+        let mut state = AbstractState::default();
+        let mut rsp = 0;
+        for (index, &root) in roots.iter().enumerate().skip(1).rev() {
+            let handler = handlers.iter().find(|&&(handler, _)| handler == roots[index - 1]);
+            let slot = handler.map_or(N64, |&(_, slot)| slot);
+            let mut words = vec![ValueSet::constant(root as i64)];
+            if handler.is_some() {
+                let saved = ValueSet::pointer(MemoryRegion::Stack, rsp);
+                let zero = ValueSet::constant(0);
+                words.extend(vec![zero.clone(), zero.clone(), saved, zero]);
+            }
+            for word in words.into_iter().rev() {
+                rsp -= slot.bytes() as i64;
+                state.write((Space::Memory(MemoryRegion::Stack), rsp), slot, word);
+            }
+        }
+        state.set_reg(Register::RSP, ValueSet::pointer(MemoryRegion::Stack, rsp));
+        state
+    }
+
+    /// The values of the register.
+    pub fn register(&self, reg: Register) -> ValueSet {
+        self.read((Space::Register, reg.address() as i64), reg.data_type(), None)
+    }
+
+    /// The values of the memory cell of the data type at an offset in the
+    /// region, without the initial contents of global memory.
+    pub fn memory(&self, region: MemoryRegion, offset: i64, data_type: DataType) -> ValueSet {
+        self.read((Space::Memory(region), offset), data_type, None)
+    }
+
+    /// The possible addresses accessed by the storage location if it is in memory.
+    pub fn address(&self, storage: StorageLocation) -> Option<ValueSet> {
+        match storage {
+            StorageLocation::Direct(_) | StorageLocation::Flag(_) => None,
+            StorageLocation::Indirect { base, scaled_offset, displacement, .. } => {
+                // Segment bases are not tracked.
+                if let Register::FS | Register::GS = base {
+                    return Some(ValueSet::Top);
+                }
+                let mut addr = self.register(base);
+                if let Some((index, scale)) = scaled_offset {
+                    let offset = self.register(index).cast(index.data_type(), N64, false);
+                    addr = addr.add(&offset.mul(&ValueSet::constant(scale as i64), N64), N64);
+                }
+                if let Some(disp) = displacement {
+                    addr = addr.add(&ValueSet::constant(disp), N64);
+                }
+                Some(addr)
+            },
+        }
+    }
+
+    fn set_reg(&mut self, reg: Register, value: ValueSet) {
+        self.write((Space::Register, reg.address() as i64), reg.data_type(), value);
+    }
+
+    /// Read the location of the data type. Global memory which was never
+    /// written is read from the image if there is one.
+    fn read(&self, key: (Space, i64), data_type: DataType, image: Option<&MemoryImage>)
+        -> ValueSet
+    {
+        if !is_integer(data_type) {
+            return ValueSet::Top;
+        }
+
+        let (space, offset) = key;
+        let end = offset.saturating_add(data_type.bytes() as i64);
+        let mut overlapping = self.overlapping(key, data_type);
+        match overlapping.next() {
+            // A location of at least the same size at the same offset.
+            Some((&(_, start), &(stored, ref value))) if start == offset
+                && stored.bytes() >= data_type.bytes()
+                && overlapping.next().is_none() =>
+            {
+                if stored == data_type {
+                    value.clone()
+                } else {
+                    value.cast(stored, data_type, false)
+                }
+            },
+            Some(_) => ValueSet::Top,
+            None => match (space, image) {
+                (Space::Memory(MemoryRegion::Global), Some(image))
+                    if !self.havocked && offset >= 0 =>
+                {
+                    image.read(offset as u64, (end - offset) as u64)
+                        .map(|bytes| ValueSet::integer(Integer::from_bytes(&bytes, data_type)))
+                        .unwrap_or(ValueSet::Top)
+                },
+                _ => ValueSet::Top,
+            },
+        }
+    }
+
+    /// Replace the location of the data type. Partially overwritten locations
+    /// lose their values.
+    fn write(&mut self, key: (Space, i64), data_type: DataType, value: ValueSet) {
+        let overlapping: Vec<_> = self.overlapping(key, data_type)
+            .map(|(&key, &(stored, _))| (key, stored))
+            .collect();
+        for (other, stored) in overlapping {
+            self.values.insert(other, (stored, ValueSet::Top));
+        }
+        let value = if is_integer(data_type) { value } else { ValueSet::Top };
+        self.values.insert(key, (data_type, value));
+    }
+
+    /// Join the value into the location of the data type.
+    fn weak_write(
+        &mut self,
+        key: (Space, i64),
+        data_type: DataType,
+        value: ValueSet,
+        image: &MemoryImage,
+    ) {
+        let joined = self.read(key, data_type, Some(image)).join(&value);
+        self.write(key, data_type, joined);
+    }
+
+    /// Forget the values of all memory cells in the regions (or all regions
+    /// for `None`).
+    fn clobber(&mut self, regions: Option<&[MemoryRegion]>) {
+        for (&(space, _), entry) in self.values.iter_mut() {
+            if let Space::Memory(region) = space {
+                if regions.map(|regions| regions.contains(&region)) != Some(false) {
+                    entry.1 = ValueSet::Top;
+                }
+            }
+        }
+        if regions.map(|regions| regions.contains(&MemoryRegion::Global)) != Some(false) {
+            self.havocked = true;
+        }
+    }
+
+    /// Forget the values of all registers.
+    fn clobber_registers(&mut self) {
+        self.values.retain(|&(space, _), _| space != Space::Register);
+    }
+
+    /// The locations overlapping the location of the data type.
+    fn overlapping(&self, key: (Space, i64), data_type: DataType)
+        -> impl Iterator<Item=(&(Space, i64), &(DataType, ValueSet))>
+    {
+        let (space, offset) = key;
+        let end = offset.saturating_add(data_type.bytes() as i64);
+        let range = (Included((space, offset.saturating_sub(32))), Included((space, end - 1)));
+        self.values.range(range)
+            .filter(move |(&(_, start), &(stored, _))| {
+                start.saturating_add(stored.bytes() as i64) > offset
+            })
+    }
+
+    /// Join the states of two paths.
+    fn join(&self, other: &AbstractState) -> AbstractState {
+        self.combine(other, |a, b, _| a.join(b))
+    }
+
+    /// Join the states of two paths, extrapolating growing intervals to the
+    /// bounds of their data types.
+    fn widen(&self, other: &AbstractState) -> AbstractState {
+        self.combine(other, |a, b, data_type| a.widen(b, data_type))
+    }
+
+    /// Combine the values of the locations known in both states. Locations
+    /// known in only one of them can hold anything.
+    fn combine<F>(&self, other: &AbstractState, f: F) -> AbstractState
+    where F: Fn(&ValueSet, &ValueSet, DataType) -> ValueSet {
+        let mut values = BTreeMap::new();
+        for (key, (data_type, value)) in &self.values {
+            let combined = match other.values.get(key) {
+                Some((other_type, other_value)) if other_type == data_type => {
+                    f(value, other_value, *data_type)
+                },
+                _ => ValueSet::Top,
+            };
+            values.insert(*key, (*data_type, combined));
+        }
+        for (key, &(data_type, _)) in &other.values {
+            values.entry(*key).or_insert((data_type, ValueSet::Top));
+        }
+        AbstractState { values, havocked: self.havocked || other.havocked }
+    }
+}
+
+/// Executes the microcode of blocks on abstract states.
+struct Interpreter<'a> {
+    state: AbstractState,
+    image: &'a MemoryImage,
+    temporaries: HashMap<usize, ValueSet>,
+    /// The locations the temporaries were loaded from, for refining them at
+    /// conditional jumps.
+    origins: HashMap<usize, ((Space, i64), DataType)>,
+    /// The address of the last jumping instruction and the target.
+    jump: Option<(u64, ValueSet)>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(state: AbstractState, image: &'a MemoryImage) -> Interpreter<'a> {
+        Interpreter {
+            state,
+            image,
+            temporaries: HashMap::new(),
+            origins: HashMap::new(),
+            jump: None,
+        }
+    }
+
+    /// Execute the block of the node, calling `before` with the state before
+    /// each instruction.
+    fn run<F>(&mut self, cfg: &ControlFlowGraph, node: usize, mut before: F)
+    where F: FnMut(u64, &AbstractState, &Microcode) {
+        let block = &cfg.blocks[&cfg.nodes[node].addr];
+        for (addr, len, _, microcode) in &block.code {
+            before(*addr, &self.state, microcode);
+            self.state.set_reg(Register::RIP, ValueSet::constant((addr + len) as i64));
+            for op in &microcode.ops {
+                self.step(*addr, addr + len, op);
+            }
+        }
+    }
+
+    /// The state the successor is entered with or `None` if the edge cannot be taken.
+    fn successor(&self, cfg: &ControlFlowGraph, node: usize, succ: usize) -> Option<AbstractState> {
+        let mut state = self.state.clone();
+        if !self.refine(&mut state, &cfg.edges[&(node, succ)]) {
+            return None;
+        }
+
+        let rsp = |state: &AbstractState| state.register(Register::RSP)
+            .add(&ValueSet::constant(8), N64);
+        match cfg.edge_kind((node, succ)) {
+            ControlKind::Flow => {},
+            ControlKind::Allocate { function, site, allocator } => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+                if !matches!(allocator, Allocator::Free | Allocator::RustDealloc) {
+                    let result = cfg.config.convention(function).result;
+                    state.set_reg(result, ValueSet::pointer(MemoryRegion::Heap(site), 0));
+                }
+            },
+            // The value of an environment variable is in a heap region like
+            // an allocation, while the read bytes are unknown.
+            ControlKind::Input { function, site, input } => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+                let result = match input {
+                    InputFunction::Getenv => ValueSet::pointer(MemoryRegion::Heap(site), 0),
+                    _ => ValueSet::Top,
+                };
+                state.set_reg(cfg.config.convention(function).result, result);
+            },
+            ControlKind::Exception => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+                state.set_reg(Register::RAX, ValueSet::Top);
+                state.set_reg(Register::RDX, ValueSet::Top);
+            },
+            ControlKind::Recursion { clobbered, .. } | ControlKind::Summary { clobbered, .. } => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+                for reg in clobbered.iter() {
+                    state.set_reg(reg, ValueSet::Top);
+                }
+            },
+            ControlKind::Check { .. } => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+            },
+            ControlKind::Spawn { function } => {
+                let rsp = rsp(&state);
+                state.set_reg(Register::RSP, rsp);
+                state.set_reg(cfg.config.convention(function).result, ValueSet::constant(0));
+            },
+            _ => state.clobber_registers(),
+        }
+
+        Some(state)
+    }
+
+    /// Execute a micro operation.
+    fn step(&mut self, addr: u64, next: u64, operation: &MicroOperation) {
+        use MicroOperation as Op;
+
+        match operation {
+            Op::Mov { dest, src } => {
+                let value = self.load(*src);
+                self.store(*dest, value);
+                if let Location::Temp(temp) = dest {
+                    match self.key(*src) {
+                        Some(key) => { self.origins.insert(temp.1, (key, src.data_type())); },
+                        None => { self.origins.remove(&temp.1); },
+                    }
+                }
+            },
+
+            Op::Const { dest, constant } => self.set_temp(*dest, ValueSet::integer(*constant)),
+            Op::Cast { target, new, signed } => {
+                let value = self.get_temp(*target).cast(target.0, *new, *signed);
+                self.set_temp(Temporary(*new, target.1), value);
+            },
+
+            Op::Add { sum, a, b } => self.binop(*sum, *a, *b, ValueSet::add),
+            Op::Sub { diff, a, b } => self.binop(*diff, *a, *b, ValueSet::sub),
+            Op::Mul { prod, a, b } => self.binop(*prod, *a, *b, ValueSet::mul),
+            Op::And { and, a, b } => self.binop(*and, *a, *b, ValueSet::bitand),
+            Op::Or { or, a, b } => self.binop(*or, *a, *b, |a, b, data_type| {
+                a.exact(b, data_type, Integer::bitor)
+            }),
+            Op::Xor { xor, a, b } if a == b => self.set_temp(*xor, ValueSet::constant(0)),
+            Op::Xor { xor, a, b } => self.binop(*xor, *a, *b, |a, b, data_type| {
+                a.exact(b, data_type, Integer::bitxor)
+            }),
+            Op::Not { not, a } => {
+                let value = self.get_temp(*a).exact(&ValueSet::constant(0), a.0, |a, _| a.bitnot());
+                self.set_temp(*not, value);
+            },
+
+            Op::Bits { op: BitOp::Shl, target, a, b } => self.binop(*target, *a, *b, ValueSet::shl),
+            Op::Bits { op: BitOp::Shr, target, a, b } => {
+                self.binop(*target, *a, *b, |a, b, data_type| {
+                    a.exact(b, data_type, |a, b| a.shr(b, false))
+                })
+            },
+
+            Op::Bits { target, .. } | Op::Packed { target, .. } | Op::Float { target, .. }
+            | Op::Unknown { target } | Op::Query { target, .. } => {
+                self.set_temp(*target, ValueSet::Top)
+            },
+
+            // Bulk operations forget everything in the written regions.
+            Op::Copy { dest, .. } | Op::Fill { dest, .. } => {
+                let regions = self.get_temp(*dest).regions();
+                self.state.clobber(regions.as_deref());
+            },
+
+            Op::Set { target, .. } => {
+                let interval = StridedInterval::new(1, 0, 1);
+                self.set_temp(*target, ValueSet::numbers(interval));
+            },
+            Op::Select { target, a, b, .. } => {
+                let value = self.get_temp(*a).join(&self.get_temp(*b));
+                self.set_temp(*target, value);
+            },
+            Op::Jump { target, relative, .. } => {
+                let mut value = self.get_temp(*target);
+                if *relative {
+                    let offset = value.cast(target.0, N64, true);
+                    value = ValueSet::constant(next as i64).add(&offset, N64);
+                }
+                self.jump = Some((addr, value));
+            },
+
+            Op::Syscall => {
+                for &reg in &[Register::RAX, Register::RCX, Register::R11] {
+                    self.state.set_reg(reg, ValueSet::Top);
+                }
+            },
+        }
+    }
+
+    /// Apply a binary operation to temporaries.
+    fn binop<F>(&mut self, target: Temporary, a: Temporary, b: Temporary, f: F)
+    where F: FnOnce(&ValueSet, &ValueSet, DataType) -> ValueSet {
+        let value = f(&self.get_temp(a), &self.get_temp(b), target.0);
+        self.set_temp(target, value);
+    }
+
+    fn get_temp(&self, temp: Temporary) -> ValueSet {
+        self.temporaries.get(&temp.1).cloned().unwrap_or(ValueSet::Top)
+    }
+
+    fn set_temp(&mut self, temp: Temporary, value: ValueSet) {
+        let value = if is_integer(temp.0) { value } else { ValueSet::Top };
+        self.temporaries.insert(temp.1, value);
+        self.origins.remove(&temp.1);
+    }
+
+    /// The single abstract location a location refers to if there is one.
+    fn key(&self, location: Location) -> Option<(Space, i64)> {
+        match location {
+            Location::Temp(_) => None,
+            Location::Direct(_, 0, addr) => {
+                Some((Space::Memory(MemoryRegion::Global), addr as i64))
+            },
+            Location::Direct(_, _, addr) => Some((Space::Register, addr as i64)),
+            Location::Indirect(_, _, temp) => {
+                let addresses = self.get_temp(temp).addresses(1)?;
+                addresses.first().copied()
+            },
+        }
+    }
+
+    /// Read from a location.
+    fn load(&self, src: Location) -> ValueSet {
+        let data_type = src.data_type();
+        match src {
+            Location::Temp(temp) => self.get_temp(temp),
+            Location::Indirect(_, _, temp) => match self.get_temp(temp).addresses(ACCESS_LIMIT) {
+                Some(keys) => keys.into_iter()
+                    .map(|key| self.state.read(key, data_type, Some(self.image)))
+                    .fold(ValueSet::empty(), |acc, value| acc.join(&value)),
+                None => ValueSet::Top,
+            },
+            direct => {
+                let key = self.key(direct).expect("value-set analysis: location without key");
+                self.state.read(key, data_type, Some(self.image))
+            },
+        }
+    }
+
+    /// Write to a location. Writes to multiple possible addresses are weak.
+    fn store(&mut self, dest: Location, value: ValueSet) {
+        let data_type = dest.data_type();
+        let keys = match dest {
+            Location::Temp(temp) => return self.set_temp(temp, value),
+            Location::Indirect(_, _, temp) => {
+                let addr = self.get_temp(temp);
+                match addr.addresses(ACCESS_LIMIT) {
+                    Some(keys) => keys,
+                    None => return self.state.clobber(addr.regions().as_deref()),
+                }
+            },
+            direct => vec![self.key(direct).expect("value-set analysis: location without key")],
+        };
+
+        // Temporaries loaded from overwritten locations cannot be refined anymore.
+        self.origins.retain(|_, (origin, _)| !keys.iter().any(|key| key.0 == origin.0));
+
+        if let [key] = keys.as_slice() {
+            self.state.write(*key, data_type, value);
+        } else {
+            for key in keys {
+                self.state.weak_write(key, data_type, value.clone(), self.image);
+            }
+        }
+    }
+
+    /// Narrow the locations compared in the condition to the values for which
+    /// it holds. Returns false if it can never hold.
+    fn refine(&self, state: &mut AbstractState, condition: &SymCondition) -> bool {
+        use SymCondition::*;
+        match condition {
+            Bool(value) => *value,
+            And(a, b) => self.refine(state, a) && self.refine(state, b),
+            Not(inner) => match &**inner {
+                Bool(value) => !value,
+                Not(inner) => self.refine(state, inner),
+                LessThan(a, b, s) => self.refine_compare(state, Compare::GreaterEqual, a, b, *s),
+                LessEqual(a, b, s) => self.refine_compare(state, Compare::GreaterThan, a, b, *s),
+                GreaterThan(a, b, s) => self.refine_compare(state, Compare::LessEqual, a, b, *s),
+                GreaterEqual(a, b, s) => self.refine_compare(state, Compare::LessThan, a, b, *s),
+                _ => true,
+            },
+            Equal(a, b) => self.refine_compare(state, Compare::Equal, a, b, false),
+            LessThan(a, b, s) => self.refine_compare(state, Compare::LessThan, a, b, *s),
+            LessEqual(a, b, s) => self.refine_compare(state, Compare::LessEqual, a, b, *s),
+            GreaterThan(a, b, s) => self.refine_compare(state, Compare::GreaterThan, a, b, *s),
+            GreaterEqual(a, b, s) => self.refine_compare(state, Compare::GreaterEqual, a, b, *s),
+            Or(..) => true,
+        }
+    }
+
+    /// Refine the comparison of a temporary loaded from a location with a constant.
+    fn refine_compare(
+        &self,
+        state: &mut AbstractState,
+        compare: Compare,
+        a: &SymExpr,
+        b: &SymExpr,
+        signed: bool,
+    ) -> bool {
+        let (temp, constant, compare) = match (self.temporary(a), self.constant(a)) {
+            (Some(temp), _) => match self.constant(b) {
+                Some(constant) => (temp, constant, compare),
+                None => return true,
+            },
+            (None, Some(constant)) => match self.temporary(b) {
+                Some(temp) => (temp, constant, compare.flipped()),
+                None => return true,
+            },
+            _ => return true,
+        };
+        let (key, data_type) = match self.origins.get(&temp.1) {
+            Some(&origin) => origin,
+            None => return true,
+        };
+
+        let interval = match state.read(key, data_type, Some(self.image)).global() {
+            Some(interval) => interval,
+            None => return true,
+        };
+
+        // Only refine where the order of the values agrees with the comparison.
+        let (min, max) = bounds(data_type);
+        let comparable = if data_type == N64 {
+            signed || (interval.lower >= 0 && constant >= 0)
+        } else {
+            !signed || (interval.upper <= max / 2 && constant <= max / 2)
+        };
+        if !comparable {
+            return true;
+        }
+
+        let (lower, upper) = match compare {
+            Compare::Equal => (constant, constant),
+            Compare::LessThan => match constant.checked_sub(1) {
+                Some(upper) => (min, upper),
+                None => return false,
+            },
+            Compare::LessEqual => (min, constant),
+            Compare::GreaterThan => match constant.checked_add(1) {
+                Some(lower) => (lower, max),
+                None => return false,
+            },
+            Compare::GreaterEqual => (constant, max),
+        };
+
+        match interval.meet(lower, upper) {
+            Some(refined) => {
+                if refined != interval {
+                    state.write(key, data_type, ValueSet::numbers(refined));
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The temporary an expression consists of if any.
+    fn temporary(&self, expr: &SymExpr) -> Option<Temporary> {
+        match expr {
+            SymExpr::Sym(Symbol(data_type, "T", index)) => Some(Temporary(*data_type, *index)),
+            _ => None,
+        }
+    }
+
+    /// The single value of an expression if it is a constant or a temporary
+    /// with a single value.
+    fn constant(&self, expr: &SymExpr) -> Option<i64> {
+        match expr {
+            SymExpr::Int(int) => ValueSet::integer(*int).global()?.single(),
+            expr => self.get_temp(self.temporary(expr)?).global()?.single(),
+        }
+    }
+}
+
+/// The comparisons conditions are refined by.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Compare {
+    Equal,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+}
+
+impl Compare {
+    /// The comparison with swapped operands.
+    fn flipped(self) -> Compare {
+        match self {
+            Compare::Equal => Compare::Equal,
+            Compare::LessThan => Compare::GreaterThan,
+            Compare::LessEqual => Compare::GreaterEqual,
+            Compare::GreaterThan => Compare::LessThan,
+            Compare::GreaterEqual => Compare::LessEqual,
+        }
+    }
+}
+
+impl StridedInterval {
+    /// The interval containing only the value.
+    pub fn singleton(value: i64) -> StridedInterval {
+        StridedInterval { stride: 0, lower: value, upper: value }
+    }
+
+    /// Create an interval, normalizing the stride of single values.
+    pub fn new(stride: u64, lower: i64, upper: i64) -> StridedInterval {
+        assert!(lower <= upper, "strided interval: lower bound above upper bound");
+        if lower == upper {
+            StridedInterval::singleton(lower)
+        } else {
+            StridedInterval { stride: stride.max(1), lower, upper }
+        }
+    }
+
+    /// The interval with all values of the data type.
+    pub fn full(data_type: DataType) -> StridedInterval {
+        let (lower, upper) = bounds(data_type);
+        StridedInterval::new(1, lower, upper)
+    }
+
+    /// The value if this contains exactly one.
+    pub fn single(&self) -> Option<i64> {
+        if self.lower == self.upper { Some(self.lower) } else { None }
+    }
+
+    /// The number of values.
+    pub fn count(&self) -> u64 {
+        match self.stride {
+            0 => 1,
+            stride => {
+                let distance = self.upper as i128 - self.lower as i128;
+                ((distance / stride as i128) as u64).saturating_add(1)
+            },
+        }
+    }
+
+    /// Whether the value is in this interval.
+    pub fn contains(&self, value: i64) -> bool {
+        self.lower <= value && value <= self.upper && match self.stride {
+            0 => true,
+            stride => (value as i128 - self.lower as i128) % stride as i128 == 0,
+        }
+    }
+
+    /// All values if there are at most `limit` of them.
+    pub fn values(&self, limit: u64) -> Option<Vec<i64>> {
+        let len = self.count();
+        if len > limit {
+            return None;
+        }
+        Some((0 .. len).map(|i| self.lower.wrapping_add((i * self.stride) as i64)).collect())
+    }
+
+    /// The smallest interval containing both intervals.
+    pub fn join(&self, other: &StridedInterval) -> StridedInterval {
+        let distance = (self.lower as i128 - other.lower as i128).unsigned_abs() as u64;
+        let stride = gcd(gcd(self.stride, other.stride), distance);
+        StridedInterval::new(stride, self.lower.min(other.lower), self.upper.max(other.upper))
+    }
+
+    /// Join the intervals, moving bounds that grew to the bounds of the data type.
+    pub fn widen(&self, other: &StridedInterval, data_type: DataType) -> StridedInterval {
+        let joined = self.join(other);
+        let (min, max) = bounds(data_type);
+        let lower = if joined.lower < self.lower { min } else { joined.lower };
+        let upper = if joined.upper > self.upper { max } else { joined.upper };
+        if (lower, upper) == (joined.lower, joined.upper) {
+            joined
+        } else {
+            StridedInterval::new(1, lower, upper)
+        }
+    }
+
+    /// The values of this interval between the bounds if there are any.
+    pub fn meet(&self, lower: i64, upper: i64) -> Option<StridedInterval> {
+        let stride = self.stride.max(1) as i128;
+        let align = |value: i64, up: bool| {
+            let rem = (value as i128 - self.lower as i128).rem_euclid(stride);
+            match (rem, up) {
+                (0, _) => value as i128,
+                (rem, true) => value as i128 + stride - rem,
+                (rem, false) => value as i128 - rem,
+            }
+        };
+        let lower = align(lower.max(self.lower), true);
+        let upper = align(upper.min(self.upper), false);
+        if lower > upper {
+            None
+        } else {
+            Some(StridedInterval::new(self.stride, lower as i64, upper as i64))
+        }
+    }
+
+    /// The pairwise sums or `None` if they overflow.
+    pub fn add(&self, other: &StridedInterval) -> Option<StridedInterval> {
+        let lower = self.lower.checked_add(other.lower)?;
+        let upper = self.upper.checked_add(other.upper)?;
+        Some(StridedInterval::new(gcd(self.stride, other.stride), lower, upper))
+    }
+
+    /// The negated values or `None` if they overflow.
+    pub fn neg(&self) -> Option<StridedInterval> {
+        let (lower, upper) = (self.upper.checked_neg()?, self.lower.checked_neg()?);
+        Some(StridedInterval::new(self.stride, lower, upper))
+    }
+
+    /// The values multiplied by a factor or `None` if they overflow.
+    pub fn scale(&self, factor: i64) -> Option<StridedInterval> {
+        let (a, b) = (self.lower.checked_mul(factor)?, self.upper.checked_mul(factor)?);
+        let stride = self.stride.checked_mul(factor.unsigned_abs())?;
+        Some(StridedInterval::new(stride, a.min(b), a.max(b)))
+    }
+
+    /// Whether all values fit into the data type.
+    fn fits(&self, data_type: DataType) -> bool {
+        let (min, max) = bounds(data_type);
+        min <= self.lower && self.upper <= max
+    }
+
+    /// Bring the result of an operation back into the range of the data type by
+    /// wrapping around once if all values are out of range.
+    fn wrapped(self, data_type: DataType) -> Option<StridedInterval> {
+        if self.fits(data_type) {
+            return Some(self);
+        }
+        let modulus = 1i64.checked_shl(data_type.bits() as u32).filter(|_| data_type != N64)?;
+        let shifted = if self.lower > bounds(data_type).1 {
+            StridedInterval::new(self.stride, self.lower - modulus, self.upper - modulus)
+        } else if self.upper < 0 {
+            StridedInterval::new(self.stride, self.lower + modulus, self.upper + modulus)
+        } else {
+            return None;
+        };
+        Some(shifted).filter(|shifted| shifted.fits(data_type))
+    }
+}
+
+impl ValueSet {
+    /// The value set without any values.
+    pub fn empty() -> ValueSet {
+        ValueSet::Values(BTreeMap::new())
+    }
+
+    /// The value set with the single number.
+    pub fn constant(value: i64) -> ValueSet {
+        ValueSet::numbers(StridedInterval::singleton(value))
+    }
+
+    /// The value set of numbers in the interval.
+    pub fn numbers(interval: StridedInterval) -> ValueSet {
+        ValueSet::pointer_set(MemoryRegion::Global, interval)
+    }
+
+    /// The value set with the single pointer at an offset in the region.
+    pub fn pointer(region: MemoryRegion, offset: i64) -> ValueSet {
+        ValueSet::pointer_set(region, StridedInterval::singleton(offset))
+    }
+
+    /// The value set of pointers at the offsets in the region.
+    pub fn pointer_set(region: MemoryRegion, interval: StridedInterval) -> ValueSet {
+        let mut regions = BTreeMap::new();
+        regions.insert(region, interval);
+        ValueSet::Values(regions)
+    }
+
+    /// The value set with the integer.
+    pub fn integer(int: Integer) -> ValueSet {
+        match int.0 {
+            N8 | N16 | N32 | N64 => ValueSet::constant(int.1 as i64),
+            _ => ValueSet::Top,
+        }
+    }
+
+    /// The numbers if this contains nothing but numbers.
+    pub fn global(&self) -> Option<StridedInterval> {
+        match self {
+            ValueSet::Values(regions) if regions.len() == 1 => {
+                regions.get(&MemoryRegion::Global).copied()
+            },
+            _ => None,
+        }
+    }
+
+    /// The regions of the values, or `None` if it could be any.
+    pub fn regions(&self) -> Option<Vec<MemoryRegion>> {
+        match self {
+            ValueSet::Top => None,
+            ValueSet::Values(regions) => Some(regions.keys().copied().collect()),
+        }
+    }
+
+    /// Whether accesses of the sizes at addresses of the value sets can overlap.
+    pub fn may_overlap(&self, size: u64, other: &ValueSet, other_size: u64) -> bool {
+        match (self, other) {
+            (ValueSet::Values(a), ValueSet::Values(b)) => a.iter().any(|(region, x)| {
+                b.get(region).map(|y| {
+                    (x.lower as i128) < y.upper as i128 + other_size as i128
+                        && (y.lower as i128) < x.upper as i128 + size as i128
+                }) == Some(true)
+            }),
+            _ => true,
+        }
+    }
+
+    /// The locations addressed by the values if there are at most `limit`.
+    fn addresses(&self, limit: u64) -> Option<Vec<(Space, i64)>> {
+        let regions = match self {
+            ValueSet::Top => return None,
+            ValueSet::Values(regions) => regions,
+        };
+        let mut keys = Vec::new();
+        for (&region, interval) in regions {
+            for value in interval.values(limit.checked_sub(keys.len() as u64)?)? {
+                keys.push((Space::Memory(region), value));
+            }
+        }
+        Some(keys)
+    }
+
+    /// The union of both value sets.
+    pub fn join(&self, other: &ValueSet) -> ValueSet {
+        self.combine(other, |a, b| a.join(b))
+    }
+
+    /// The union of both value sets, moving bounds that grew to the bounds of
+    /// the data type.
+    pub fn widen(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        self.combine(other, |a, b| a.widen(b, data_type))
+    }
+
+    fn combine<F>(&self, other: &ValueSet, f: F) -> ValueSet
+    where F: Fn(&StridedInterval, &StridedInterval) -> StridedInterval {
+        match (self, other) {
+            (ValueSet::Values(a), ValueSet::Values(b)) => {
+                let mut regions = a.clone();
+                for (region, y) in b {
+                    let combined = match regions.get(region) {
+                        Some(x) => f(x, y),
+                        None => *y,
+                    };
+                    regions.insert(*region, combined);
+                }
+                ValueSet::Values(regions)
+            },
+            _ => ValueSet::Top,
+        }
+    }
+
+    /// Combine all pairs of intervals by region. The function decides the
+    /// region of the result and returns `None` if it can be anything.
+    fn pairwise<F>(&self, other: &ValueSet, data_type: DataType, f: F) -> ValueSet
+    where F: Fn(MemoryRegion, &StridedInterval, MemoryRegion, &StridedInterval)
+        -> Option<(MemoryRegion, StridedInterval)>
+    {
+        let (a, b) = match (self, other) {
+            (ValueSet::Values(a), ValueSet::Values(b)) => (a, b),
+            _ => return ValueSet::Top,
+        };
+        let mut result = ValueSet::empty();
+        for (&ra, x) in a {
+            for (&rb, y) in b {
+                let value = match f(ra, x, rb, y) {
+                    Some((region, interval)) => match interval.wrapped(data_type) {
+                        Some(interval) => ValueSet::pointer_set(region, interval),
+                        None => return ValueSet::Top,
+                    },
+                    None => return ValueSet::Top,
+                };
+                result = result.join(&value);
+            }
+        }
+        result
+    }
+
+    /// The sums of the values, where pointers can be offset by numbers.
+    pub fn add(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        use MemoryRegion::Global;
+        self.pairwise(other, data_type, |ra, x, rb, y| match (ra, rb) {
+            (Global, region) | (region, Global) => Some((region, x.add(y)?)),
+            _ => None,
+        })
+    }
+
+    /// The differences of the values, where the difference of pointers into the
+    /// same region is a number.
+    pub fn sub(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        use MemoryRegion::Global;
+        self.pairwise(other, data_type, |ra, x, rb, y| match (ra, rb) {
+            (region, Global) => Some((region, x.add(&y.neg()?)?)),
+            (a, b) if a == b => Some((Global, x.add(&y.neg()?)?)),
+            _ => None,
+        })
+    }
+
+    /// The products of numbers where one factor is a single value.
+    pub fn mul(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        use MemoryRegion::Global;
+        self.pairwise(other, data_type, |ra, x, rb, y| match (ra, rb, x.single(), y.single()) {
+            (Global, Global, _, Some(factor)) => Some((Global, x.scale(factor)?)),
+            (Global, Global, Some(factor), _) => Some((Global, y.scale(factor)?)),
+            _ => None,
+        })
+    }
+
+    /// The bitwise and of numbers, which is bounded by a non-negative mask.
+    pub fn bitand(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        use MemoryRegion::Global;
+        if let (Some(x), Some(y)) = (self.global(), other.global()) {
+            if let (Some(_), Some(_)) = (x.single(), y.single()) {
+                return self.exact(other, data_type, Integer::bitand);
+            }
+            let mask = y.single().or_else(|| x.single());
+            if let Some(mask) = mask.filter(|&mask| mask >= 0) {
+                return ValueSet::pointer_set(Global, StridedInterval::new(1, 0, mask));
+            }
+        }
+        ValueSet::Top
+    }
+
+    /// The numbers shifted left by a single amount.
+    pub fn shl(&self, other: &ValueSet, data_type: DataType) -> ValueSet {
+        match (self.global(), other.global().and_then(|y| y.single())) {
+            (Some(x), Some(_)) if x.single().is_some() => {
+                self.exact(other, data_type, Integer::shl)
+            },
+            (Some(x), Some(shift)) if (0 .. 63).contains(&shift) => {
+                match x.scale(1 << shift).and_then(|x| x.wrapped(data_type)) {
+                    Some(interval) => ValueSet::numbers(interval),
+                    None => ValueSet::Top,
+                }
+            },
+            _ => ValueSet::Top,
+        }
+    }
+
+    /// Compute an operation on single numbers exactly.
+    fn exact<F>(&self, other: &ValueSet, data_type: DataType, f: F) -> ValueSet
+    where F: FnOnce(Integer, Integer) -> Integer {
+        let single = |set: &ValueSet| set.global().and_then(|x| x.single());
+        match (single(self), single(other)) {
+            (Some(a), Some(b)) => {
+                let (a, b) = (Integer(data_type, a as u64), Integer(data_type, b as u64));
+                ValueSet::integer(f(a.cast(data_type, false), b.cast(data_type, false)))
+            },
+            _ => ValueSet::Top,
+        }
+    }
+
+    /// Convert numbers from the old to the new data type. Pointers keep their
+    /// values only if the type stays the same.
+    pub fn cast(&self, old: DataType, new: DataType, signed: bool) -> ValueSet {
+        if old == new {
+            return self.clone();
+        }
+        if !is_integer(old) || !is_integer(new) {
+            return ValueSet::Top;
+        }
+
+        let interval = match self.global() {
+            Some(interval) => interval,
+            None => return ValueSet::Top,
+        };
+        if let Some(value) = interval.single() {
+            return ValueSet::integer(Integer(old, value as u64).cast(new, signed));
+        }
+
+        let sign_max = bounds(old).1 / 2;
+        let preserved = if new.bits() < old.bits() {
+            interval.fits(new)
+        } else if signed && old != N64 {
+            interval.upper <= sign_max
+        } else {
+            // Zero extension of narrow values keeps them. Zero extension of
+            // 64-bit values cannot happen.
+            old != N64 || interval.lower >= 0
+        };
+
+        if preserved { self.clone() } else { ValueSet::Top }
+    }
+}
+
+/// Whether values of the data type are tracked as integers.
+fn is_integer(data_type: DataType) -> bool {
+    matches!(data_type, N8 | N16 | N32 | N64)
+}
+
+/// The smallest and largest value of the data type.
+fn bounds(data_type: DataType) -> (i64, i64) {
+    match data_type {
+        N64 => (i64::MIN, i64::MAX),
+        _ => (0, ((1u64 << data_type.bits().min(63)) - 1) as i64),
+    }
+}
+
+/// The greatest common divisor.
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl Display for StridedInterval {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.single() {
+            Some(value) => write!(f, "{:#x}", value),
+            None => write!(f, "{}[{:#x}, {:#x}]", self.stride, self.lower, self.upper),
+        }
+    }
+}
+
+impl Display for MemoryRegion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MemoryRegion::Global => write!(f, "global"),
+            MemoryRegion::Stack => write!(f, "stack"),
+            MemoryRegion::Heap(site) => write!(f, "heap {:x}", site),
+        }
+    }
+}
+
+impl Display for ValueSet {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ValueSet::Top => write!(f, "T"),
+            ValueSet::Values(regions) => {
+                write!(f, "{{")?;
+                let mut first = true;
+                for (region, interval) in regions {
+                    if !first { write!(f, ", ")?; } first = false;
+                    write!(f, "{}: {}", region, interval)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::MicroEncoder;
+    use crate::x86_64::Instruction;
+    use super::*;
+
+    #[test]
+    fn intervals() {
+        let a = StridedInterval::new(4, 0, 12);
+        let b = StridedInterval::singleton(18);
+        assert_eq!(a.join(&b), StridedInterval::new(2, 0, 18));
+        assert_eq!(a.count(), 4);
+        assert!(a.contains(8) && !a.contains(6));
+        assert_eq!(a.meet(1, 9), Some(StridedInterval::new(4, 4, 8)));
+        assert_eq!(a.meet(13, 20), None);
+        assert_eq!(a.scale(-2), Some(StridedInterval::new(8, -24, 0)));
+        assert_eq!(a.widen(&StridedInterval::new(4, 0, 16), N32),
+                   StridedInterval::new(1, 0, 0xffff_ffff));
+        assert_eq!(a.to_string(), "4[0x0, 0xc]");
+
+        let stack = ValueSet::pointer(MemoryRegion::Stack, -16);
+        let index = ValueSet::numbers(StridedInterval::new(1, 0, 3));
+        let addr = stack.add(&index.mul(&ValueSet::constant(8), N64), N64);
+        let offsets = StridedInterval::new(8, -16, 8);
+        assert_eq!(addr, ValueSet::pointer_set(MemoryRegion::Stack, offsets));
+        assert_eq!(addr.sub(&stack, N64), ValueSet::numbers(StridedInterval::new(8, 0, 24)));
+        assert_eq!(stack.add(&stack, N64), ValueSet::Top);
+        let wrapped = ValueSet::constant(0xffff_fffe).add(&ValueSet::constant(3), N32);
+        assert_eq!(wrapped, ValueSet::constant(1));
+    }
+
+    #[test]
+    fn interpreter() {
+        // mov eax, 5; lea rbx, [rsp-0x10]; mov dword ptr [rbx], eax; mov ecx, dword ptr [rbx]
+        let bytes: &[&[u8]] = &[
+            &[0xb8, 0x05, 0x00, 0x00, 0x00], &[0x48, 0x8d, 0x5c, 0x24, 0xf0],
+            &[0x89, 0x03], &[0x8b, 0x0b],
+        ];
+
+        let image = MemoryImage::new();
+        let mut interpreter = Interpreter::new(AbstractState::initial(&[0x1000], &[]), &image);
+        let mut encoder = MicroEncoder::new();
+        let mut addr = 0x1000;
+        for bytes in bytes {
+            let instruction = Instruction::decode(bytes).unwrap();
+            let microcode = encoder.encode(&instruction).unwrap();
+            for op in &microcode.ops {
+                interpreter.step(addr, addr + bytes.len() as u64, op);
+            }
+            addr += bytes.len() as u64;
+        }
+
+        let state = &interpreter.state;
+        assert_eq!(state.register(Register::EAX), ValueSet::constant(5));
+        assert_eq!(state.register(Register::RBX), ValueSet::pointer(MemoryRegion::Stack, -16));
+        assert_eq!(state.memory(MemoryRegion::Stack, -16, N32), ValueSet::constant(5));
+        assert_eq!(state.register(Register::ECX), ValueSet::constant(5));
+
+        let local = StorageLocation::Indirect {
+            data_type: N32,
+            base: Register::RBX,
+            scaled_offset: None,
+            displacement: Some(8),
+        };
+        assert_eq!(state.address(local), Some(ValueSet::pointer(MemoryRegion::Stack, -8)));
+    }
+}
@@ -0,0 +1,158 @@
+//! Entry points for fuzzing the loader, the decoder and the exploration.
+//!
+//! Each target accepts arbitrary bytes and swallows the errors of malformed
+//! input, so that any panic is a bug. They are meant to be driven from
+//! cargo-fuzz targets like:
+//!
+//! ```ignore
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| symflow::fuzz::load(data));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::Program;
+use crate::elf::ElfFile;
+use crate::flow::{CancellationToken, ControlFlowGraph, Monitor, Report};
+use crate::ir::MicroEncoder;
+use crate::sym::SymConfig;
+use crate::x86_64::Instruction;
+
+
+/// The number of control flow nodes after which the exploration of fuzzed
+/// code is cancelled.
+pub const NODE_LIMIT: usize = 64;
+
+/// The address at which fuzzed code is placed.
+pub const BASE: u64 = 0x1000;
+
+/// Parse the bytes as an ELF file, read all of its tables and load it as a
+/// program.
+pub fn load(data: &[u8]) {
+    if let Ok(mut file) = ElfFile::from_slice(data) {
+        let _ = file.sections();
+        let _ = file.get_symbols();
+        let _ = file.get_relocations(".rela.dyn");
+    }
+
+    let _ = Program::try_from_bytes(data);
+}
+
+/// Decode the bytes as a stream of instructions and lift each to microcode.
+/// Undecodable bytes are skipped one at a time.
+pub fn decode(data: &[u8]) {
+    let mut encoder = MicroEncoder::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        let rest = &data[index ..];
+        let len = Instruction::length(rest) as usize;
+        let instruction = match rest.get(.. len).map(Instruction::decode) {
+            Some(Ok(instruction)) if len > 0 => instruction,
+            _ => { index += 1; continue; },
+        };
+
+        let _ = instruction.to_string();
+        if let Ok(microcode) = encoder.encode(&instruction) {
+            let _ = microcode.to_string();
+        }
+        index += len;
+    }
+}
+
+/// Place the bytes as code at `BASE`, and explore its control flow for at
+/// most `NODE_LIMIT` nodes if it can be loaded.
+pub fn explore(data: &[u8]) {
+    let program = Program::from_code(BASE, data, HashMap::new());
+    if program.code.is_empty() {
+        return;
+    }
+
+    let token = CancellationToken::new();
+    let limit_token = token.clone();
+    let config = SymConfig {
+        monitor: Monitor {
+            progress: Some(Arc::new(move |report: Report| {
+                if report.done > NODE_LIMIT {
+                    limit_token.cancel();
+                }
+            })),
+            cancellation: token,
+        },
+        .. SymConfig::default()
+    };
+
+    let _ = ControlFlowGraph::try_with_config(&program, config);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn truncated() {
+        let bytes = std::fs::read(fixture("block-1")).unwrap();
+        for len in (0 .. bytes.len()).step_by(97) {
+            load(&bytes[.. len]);
+        }
+
+        assert!(Program::try_from_bytes(&bytes[.. 64]).is_err());
+        assert!(Program::try_from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn huge_sections() {
+        // Blow up the size of `.bss`, which has no contents in the file.
+        let mut bytes = std::fs::read(fixture("jumps")).unwrap();
+        let file = ElfFile::from_slice(&bytes).unwrap();
+        let index = file.section_headers.iter().position(|header| header.name == ".bss").unwrap();
+        let header = file.section_headers[index].clone();
+        let offset = file.header.section_headers_offset as usize
+            + index * file.header.section_header_size as usize + 32;
+        bytes[offset .. offset + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+
+        load(&bytes);
+        let program = Program::try_from_bytes(&bytes).unwrap();
+        assert_eq!(program.image.region(header.addr), Some((header.addr, 1 << 40)));
+        assert!(program.image.is_zeros(header.addr + (1 << 39)));
+    }
+
+    #[test]
+    fn garbage() {
+        let bytes: Vec<u8> = (0 .. 4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        load(&bytes);
+        decode(&bytes);
+
+        // A truncated `mov eax, imm32` is rejected instead of read past the end.
+        assert!(Instruction::decode(&[0xb8, 0x01, 0x02]).is_err());
+        assert!(Instruction::decode(&[0xb8, 0x01, 0x02, 0x03, 0x04]).is_ok());
+    }
+
+    #[test]
+    fn exploration() {
+        // A loop counting `eax` to three followed by the exit system call.
+        explore(&[
+            0xb8, 0x00, 0x00, 0x00, 0x00, 0xff, 0xc0, 0x83, 0xf8, 0x03, 0x7c, 0xf9,
+            0xb8, 0x3c, 0x00, 0x00, 0x00, 0xbf, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x05,
+        ]);
+        explore(&[]);
+
+        // An indirect jump to an unknown target, a system call with an
+        // unknown number, an unsupported one and a read of an unknown number
+        // of bytes stop their paths.
+        let inputs: [&[u8]; 4] = [
+            &[0xff, 0xe0],
+            &[0x0f, 0x05],
+            &[0xb8, 0x27, 0x00, 0x00, 0x00, 0x0f, 0x05],
+            &[0x31, 0xc0, 0x0f, 0x05],
+        ];
+        for &input in &inputs {
+            explore(input);
+            let cfg = ControlFlowGraph::new(&Program::from_code(BASE, input, HashMap::new()));
+            assert_eq!(cfg.stops.len(), 1);
+        }
+    }
+}
@@ -2,16 +2,42 @@
 
 use std::fmt::{self, Display, Formatter};
 
+use crate::flow::{ValueSource, StorageLocation};
+use crate::helper::boxed;
 use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol};
-use crate::x86_64::{Instruction, Mnemoic, Operand, Register};
+use crate::x86_64::{Flag, Instruction, Mnemoic, Operand, Register};
 use Register::*;
 use SymCondition::*;
 
 
-/// A sequence of micro operations.
+/// The lifted representation of an instruction.
+///
+/// Analyses operate only on this representation and never look at the
+/// machine instruction itself, so instruction semantics live in one place.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Microcode {
+    /// The sequence of micro operations executing the instruction.
     pub ops: Vec<MicroOperation>,
+    /// Pairs of (source, sink) describing data dependencies in the instruction.
+    pub flows: Vec<(ValueSource, StorageLocation)>,
+    /// How the instruction transfers control.
+    pub transfer: Transfer,
+    /// Whether the instruction executes atomically. Atomic operations are
+    /// executed like any other, but are marked for concurrency analyses.
+    pub atomic: bool,
+}
+
+/// How an instruction transfers control.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Transfer {
+    /// Execution continues with the next instruction.
+    Next,
+    /// A (possibly conditional) jump.
+    Jump,
+    /// A call that enters a new function context.
+    Call,
+    /// A return that leaves the current function context.
+    Return,
 }
 
 /// A minimal executable action.
@@ -39,11 +65,38 @@ pub enum MicroOperation {
     And { and: Temporary, a: Temporary, b: Temporary },
     /// Store the bitwise OR of `a` and `b` in or. Set flags if active.
     Or { or: Temporary, a: Temporary, b: Temporary },
+    /// Store the bitwise XOR of `a` and `b` in `xor`.
+    Xor { xor: Temporary, a: Temporary, b: Temporary },
     /// Store the bitwise NOT of `a` in `not`.
     Not { not: Temporary, a: Temporary },
 
+    /// Store the result of the bit manipulation operation on `a` and `b` in `target`.
+    Bits { op: BitOp, target: Temporary, a: Temporary, b: Temporary },
+
+    /// Apply the operation independently to each lane of type `lane` of the
+    /// vectors `a` and `b` and store the combined lanes in `target`.
+    Packed { op: PackedOp, lane: DataType, target: Temporary, a: Temporary, b: Temporary },
+
+    /// Store the result of the floating-point operation on `a` and `b` in `target`.
+    /// Floats are modeled coarsely: every result is a fresh unknown value.
+    Float { op: FloatOp, target: Temporary, a: Temporary, b: Temporary },
+
+    /// Copy `count` consecutive values of the data type from the main memory
+    /// starting at address `src` to the main memory starting at address `dest`.
+    Copy { dest: Temporary, src: Temporary, count: Temporary, data_type: DataType },
+    /// Fill `count` consecutive values in main memory starting at address `dest`
+    /// with the value `value`.
+    Fill { dest: Temporary, value: Temporary, count: Temporary },
+    /// Store an unknown value in the temporary `target`.
+    Unknown { target: Temporary },
+    /// Store a value provided by the processor in the temporary `target`. The
+    /// value ends up in the register `register`, which identifies its origin.
+    Query { query: Query, target: Temporary, register: Register },
+
     /// Set the target temporary to one if the condition is true and to zero otherwise.
     Set { target: Temporary, condition: SymCondition },
+    /// Store `a` in the target temporary if the condition is true and `b` otherwise.
+    Select { target: Temporary, condition: SymCondition, a: Temporary, b: Temporary },
     /// Jump to the current address plus the `offset` if `relative` is true,
     /// otherwise directly to the target if the condition specified by `condition`
     /// is fulfilled.
@@ -53,6 +106,65 @@ pub enum MicroOperation {
     Syscall,
 }
 
+/// Bit manipulation operations.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum BitOp {
+    /// Shift `a` left by `b` bits.
+    Shl,
+    /// Shift `a` right by `b` bits (logical).
+    Shr,
+    /// The number of trailing zero bits of `a` (`b` is unused).
+    TrailingZeros,
+    /// The number of leading zero bits of `a` (`b` is unused).
+    LeadingZeros,
+    /// Gather the bits of `a` selected by the mask `b` into the low bits.
+    Extract,
+    /// Scatter the low bits of `a` to the positions selected by the mask `b`.
+    Deposit,
+}
+
+/// Values provided by the processor instead of being computed from the program state.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Query {
+    /// The time stamp counter.
+    Timestamp,
+    /// A hardware random number.
+    Random,
+    /// The result register with the index (eax, ebx, ecx, edx) of the processor
+    /// identification for the `leaf` and `subleaf`.
+    Cpuid { leaf: Temporary, subleaf: Temporary, index: usize },
+}
+
+/// Operations applied lane-wise to vectors.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PackedOp {
+    /// Wrapping addition of the lanes.
+    Add,
+    /// Wrapping subtraction of the lanes.
+    Sub,
+    /// All ones if the lanes are equal and zero otherwise.
+    Equal,
+    /// All ones if the lane of `a` is greater (signed) and zero otherwise.
+    Greater,
+    /// Select the byte of `a` indexed by the lane of `b` within the same 128-bit
+    /// half, or zero if the highest bit of the index is set.
+    Shuffle,
+    /// Collect the highest bit of every lane of `a` into the low bits of
+    /// the (scalar) target.
+    MoveMask,
+}
+
+/// Floating-point operations.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FloatOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// Conversion of `a` to the type of the target (`b` is unused).
+    Convert,
+}
+
 impl Display for Microcode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "Microcode [")?;
@@ -88,10 +200,52 @@ impl Display for MicroOperation {
 
             And { and, a, b } => write!(f, "and {} = {} & {}", and, a, b),
             Or { or, a, b } => write!(f, "or {} = {} | {}", or, a, b),
+            Xor { xor, a, b } => write!(f, "xor {} = {} ^ {}", xor, a, b),
             Not { not, a } => write!(f, "not {} = !{}", not, a),
 
+            Bits { op, target, a, b } => match op {
+                BitOp::Shl => write!(f, "bits {} = {} << {}", target, a, b),
+                BitOp::Shr => write!(f, "bits {} = {} >> {}", target, a, b),
+                BitOp::TrailingZeros => write!(f, "bits {} = trailing zeros {}", target, a),
+                BitOp::LeadingZeros => write!(f, "bits {} = leading zeros {}", target, a),
+                BitOp::Extract => write!(f, "bits {} = extract {} by {}", target, a, b),
+                BitOp::Deposit => write!(f, "bits {} = deposit {} by {}", target, a, b),
+            },
+
+            Packed { op, lane, target, a, b } => match op {
+                PackedOp::Add => write!(f, "packed {} = {} + {} per {}", target, a, b, lane),
+                PackedOp::Sub => write!(f, "packed {} = {} - {} per {}", target, a, b, lane),
+                PackedOp::Equal => write!(f, "packed {} = {} == {} per {}", target, a, b, lane),
+                PackedOp::Greater => write!(f, "packed {} = {} > {} signed per {}",
+                    target, a, b, lane),
+                PackedOp::Shuffle => write!(f, "packed {} = {} shuffled by {} per {}",
+                    target, a, b, lane),
+                PackedOp::MoveMask => write!(f, "packed {} = msb {} per {}", target, a, lane),
+            },
+
+            Float { op, target, a, b } => match op {
+                FloatOp::Add => write!(f, "float {} = {} + {}", target, a, b),
+                FloatOp::Sub => write!(f, "float {} = {} - {}", target, a, b),
+                FloatOp::Mul => write!(f, "float {} = {} * {}", target, a, b),
+                FloatOp::Div => write!(f, "float {} = {} / {}", target, a, b),
+                FloatOp::Convert => write!(f, "float {} = convert {}", target, a),
+            },
+
+            Copy { dest, src, count, data_type } => write!(f, "copy {} x {} from ({}) to ({})",
+                count, data_type, src, dest),
+            Fill { dest, value, count } => write!(f, "fill {} x {} to ({})", count, value, dest),
+            Unknown { target } => write!(f, "unknown {}", target),
+            Query { query, target, register } => match query {
+                self::Query::Timestamp => write!(f, "query {} = timestamp for {}", target, register),
+                self::Query::Random => write!(f, "query {} = random for {}", target, register),
+                self::Query::Cpuid { leaf, subleaf, index } => write!(f,
+                    "query {} = cpuid {} of ({}, {}) for {}", target, index, leaf, subleaf, register),
+            },
+
             Set { target, condition } => write!(f, "set {}{}",
                 target, show_condition(condition)),
+            Select { target, condition, a, b } => write!(f, "select {} = {} if {} else {}",
+                target, a, condition, b),
             Jump { target, condition, relative } => write!(f, "jump {} {}{}",
                 if *relative { "by" } else { "to" }, target, show_condition(condition)),
 
@@ -140,6 +294,7 @@ impl MicroEncoder {
     /// The actual encoding but with a different result type that the public interface.
     fn encode_internal(&mut self, inst: &Instruction) -> EncoderResult<Microcode> {
         use MicroOperation as Op;
+        use DataType::*;
         use Mnemoic::*;
 
         match inst.mnemoic {
@@ -152,10 +307,109 @@ impl MicroEncoder {
                 let (a, b) = self.encode_binop(inst, |diff, a, b| Op::Sub { diff, a, b });
                 self.last_flag_op = Some(a.to_expr().sub(b.to_expr()));
             },
+            Xor => {
+                let (a, b) = self.encode_binop(inst, |xor, a, b| Op::Xor { xor, a, b });
+                let result = a.to_expr().bitxor(b.to_expr());
+                self.last_flag_op = Some(result.clone().bitand(result));
+            },
             Imul => {
                 let (a, b) = self.encode_binop(inst, |prod, a, b| Op::Mul { prod, a, b });
                 self.last_flag_op = Some(a.to_expr().mul(b.to_expr()));
             },
+            Inc | Dec => {
+                let (dest, a) = self.encode_load_operand(inst.operands[0]);
+                let one = self.encode_load_constant(Integer(a.0, 1));
+                if inst.mnemoic == Inc {
+                    self.encode_vector_result(dest, |sum| Op::Add { sum, a, b: one })?;
+                    self.last_flag_op = Some(a.to_expr().add(one.to_expr()));
+                } else {
+                    self.encode_vector_result(dest, |diff| Op::Sub { diff, a, b: one })?;
+                    self.last_flag_op = Some(a.to_expr().sub(one.to_expr()));
+                }
+            },
+
+            // Bit manipulation instructions.
+            Andn => {
+                let (dest, a, b) = self.encode_vector_operands(inst);
+                let inverted = self.encode_temp(a.0);
+                self.ops.push(Op::Not { not: inverted, a });
+                self.encode_vector_result(dest, |and| Op::And { and, a: inverted, b })?;
+                self.last_flag_op = Some(inverted.to_expr().bitand(b.to_expr()));
+            },
+            Blsi | Blsr => {
+                // Isolate (a & -a) or reset (a & (a - 1)) the lowest set bit.
+                let dest = self.encode_get_location(inst.operands[0]);
+                let (_, a) = self.encode_load_operand(inst.operands[1]);
+                let b = self.encode_temp(a.0);
+                if inst.mnemoic == Blsi {
+                    let zero = self.encode_load_constant(Integer(a.0, 0));
+                    self.ops.push(Op::Sub { diff: b, a: zero, b: a });
+                } else {
+                    let one = self.encode_load_constant(Integer(a.0, 1));
+                    self.ops.push(Op::Sub { diff: b, a, b: one });
+                }
+                self.encode_vector_result(dest, |and| Op::And { and, a, b })?;
+                self.last_flag_op = Some(a.to_expr().bitand(b.to_expr()));
+            },
+            Bextr => {
+                // The control operand holds the start bit and the length in its low bytes.
+                let (dest, a, control) = self.encode_vector_operands(inst);
+                let byte = self.encode_load_constant(Integer(a.0, 0xff));
+                let eight = self.encode_load_constant(Integer(a.0, 8));
+                let one = self.encode_load_constant(Integer(a.0, 1));
+                let start = self.encode_temp(a.0);
+                self.ops.push(Op::And { and: start, a: control, b: byte });
+                let len = self.encode_bits(BitOp::Shr, control, eight);
+                self.ops.push(Op::And { and: len, a: len, b: byte });
+
+                let shifted = self.encode_bits(BitOp::Shr, a, start);
+                let mask = self.encode_bits(BitOp::Shl, one, len);
+                self.ops.push(Op::Sub { diff: mask, a: mask, b: one });
+                self.encode_vector_result(dest, |and| Op::And { and, a: shifted, b: mask })?;
+                self.last_flag_op = Some(shifted.to_expr().bitand(mask.to_expr()));
+            },
+            Tzcnt | Lzcnt => {
+                let op = if inst.mnemoic == Tzcnt { BitOp::TrailingZeros } else { BitOp::LeadingZeros };
+                let dest = self.encode_get_location(inst.operands[0]);
+                let (_, a) = self.encode_load_operand(inst.operands[1]);
+                let count = self.encode_bits(op, a, a);
+                self.encode_move(dest, Location::Temp(count))?;
+                self.last_flag_op = Some(count.to_expr().bitand(count.to_expr()));
+            },
+            Pext | Pdep => {
+                let op = if inst.mnemoic == Pext { BitOp::Extract } else { BitOp::Deposit };
+                let (dest, a, mask) = self.encode_vector_operands(inst);
+                let result = self.encode_bits(op, a, mask);
+                self.encode_move(dest, Location::Temp(result))?;
+            },
+
+            // Atomic operations are executed as if there was a single thread.
+            Xchg => {
+                let ((dest, a), (src, b)) = self.encode_load_both(inst);
+                self.encode_move(dest, Location::Temp(b))?;
+                self.encode_move(src, Location::Temp(a))?;
+            },
+            Xadd => {
+                let ((dest, a), (src, b)) = self.encode_load_both(inst);
+                let sum = self.encode_temp(a.0);
+                self.ops.push(Op::Add { sum, a, b });
+                self.encode_move(src, Location::Temp(a))?;
+                self.encode_move(dest, Location::Temp(sum))?;
+                self.last_flag_op = Some(a.to_expr().add(b.to_expr()));
+            },
+            Cmpxchg => {
+                // The destination is replaced if it equals the accumulator. Either
+                // way, the accumulator holds the old destination value afterwards.
+                let ((dest, old), (_, new)) = self.encode_load_both(inst);
+                let accumulator = Operand::Direct(Register::accumulator(old.0));
+                let (acc, expected) = self.encode_load_operand(accumulator);
+                let condition = expected.to_expr().equal(old.to_expr());
+                self.encode_vector_result(dest, |target| {
+                    Op::Select { target, condition, a: new, b: old }
+                })?;
+                self.encode_move(acc, Location::Temp(old))?;
+                self.last_flag_op = Some(expected.to_expr().sub(old.to_expr()));
+            },
 
             Cmp => {
                 let ((_dest, left), (_src, right)) = self.encode_load_both(inst);
@@ -167,7 +421,7 @@ impl MicroEncoder {
             },
 
             // Retrieve both locations and move from source to destination.
-            Mov => {
+            Mov | Movups | Movaps | Movdqa | Movdqu => {
                 let dest = self.encode_get_location(inst.operands[0]);
                 let src = self.encode_get_location(inst.operands[1]);
                 if dest.data_type() != src.data_type() {
@@ -211,16 +465,41 @@ impl MicroEncoder {
 
             // Jump to the first operand under specific conditions.
             Jmp => self.encode_jump(inst, SymCondition::TRUE),
+            Je | Jne | Jbe | Jl | Jle | Jg | Jge | Ja | Jae | Jb
+            if self.compared_floats().is_some() => {
+                let condition = self.encode_condition(inst.mnemoic)?;
+                self.encode_jump(inst, condition);
+            },
             Je  => self.encode_jump(inst, condition!(self;
                 SymExpr::Sub(a, b) => Equal(a, b),
                 SymExpr::BitAnd(a, b) => SymExpr::from_int(a.data_type(), 0).equal(a.bitand(*b))
             )?),
+            Jne => self.encode_jump(inst, condition!(self;
+                SymExpr::Sub(a, b) => Equal(a, b),
+                SymExpr::BitAnd(a, b) => SymExpr::from_int(a.data_type(), 0).equal(a.bitand(*b))
+            )?.not()),
             Jbe => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => LessEqual(a, b, false))?),
             Jl  => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => LessThan(a, b, true))?),
             Jle => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => LessEqual(a, b, true))?),
             Jg  => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => GreaterThan(a, b, true))?),
             Jge => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => GreaterEqual(a, b, true))?),
-            Setl => self.encode_set(inst, condition!(self; SymExpr::Sub(a, b) => LessThan(a, b, true))?)?,
+            Ja  => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => GreaterThan(a, b, false))?),
+            Jae => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => GreaterEqual(a, b, false))?),
+            Jb  => self.encode_jump(inst, condition!(self; SymExpr::Sub(a, b) => LessThan(a, b, false))?),
+
+            // Set a byte or move a value depending on the flags. Both the
+            // old and the new value are kept in an if-then-else.
+            Sete | Setne | Setl | Setle | Setg | Setge | Setb | Setbe | Seta | Setae => {
+                let condition = self.encode_condition(inst.mnemoic)?;
+                self.encode_set(inst, condition)?;
+            },
+            Cmove | Cmovne | Cmovl | Cmovle | Cmovg | Cmovge | Cmovb | Cmovbe | Cmova | Cmovae => {
+                let condition = self.encode_condition(inst.mnemoic)?;
+                let ((dest, old), (_, new)) = self.encode_load_both(inst);
+                self.encode_vector_result(dest, |target| {
+                    Op::Select { target, condition, a: new, b: old }
+                })?;
+            },
 
             // Save the procedure linking information on the stack and jump.
             Call => {
@@ -246,13 +525,203 @@ impl MicroEncoder {
                 self.ops.push(Op::Jump { target, condition: SymCondition::TRUE, relative: false });
             },
 
+            // Jumps back to the address on top of the interrupt frame and restores
+            // the stack pointer saved in it after the code and flags segments.
+            Iret | Iretq => {
+                let size = if inst.mnemoic == Iretq { N64 } else { N32 };
+                let (sp, stack) = self.encode_load_operand(Operand::Direct(Register::RSP));
+                let target = self.encode_temp(size);
+                self.encode_move(Location::Temp(target), Location::Indirect(size, 0, stack))?;
+                let offset = self.encode_load_constant(Integer(N64, 3 * size.bytes() as u64));
+                self.ops.push(Op::Add { sum: stack, a: stack, b: offset });
+                let saved = self.encode_temp(size);
+                self.encode_move(Location::Temp(saved), Location::Indirect(size, 0, stack))?;
+                for &temp in &[target, saved] {
+                    if size != N64 {
+                        self.ops.push(Op::Cast { target: temp, new: N64, signed: false });
+                    }
+                }
+                let (target, saved) = (Temporary(N64, target.1), Temporary(N64, saved.1));
+                self.encode_move(sp, Location::Temp(saved))?;
+                self.ops.push(Op::Jump { target, condition: SymCondition::TRUE, relative: false });
+            },
+
             Syscall => { self.ops.push(Op::Syscall); },
-            Nop => {},
+
+            // Values from the processor are fresh symbols unless configured otherwise
+            // during execution. The counter is split into edx:eax.
+            Rdtsc => {
+                let tsc = self.encode_temp(N64);
+                self.ops.push(Op::Query { query: Query::Timestamp, target: tsc, register: RAX });
+                let thirty_two = self.encode_load_constant(Integer(N64, 32));
+                let high = self.encode_bits(BitOp::Shr, tsc, thirty_two);
+                self.ops.push(Op::Cast { target: tsc, new: N32, signed: false });
+                self.ops.push(Op::Cast { target: high, new: N32, signed: false });
+                let (low, high) = (Temporary(N32, tsc.1), Temporary(N32, high.1));
+                self.encode_move(Location::Direct(N32, 1, EAX.address()), Location::Temp(low))?;
+                self.encode_move(Location::Direct(N32, 1, EDX.address()), Location::Temp(high))?;
+            },
+            Cpuid => {
+                let leaf = self.encode_load_reg(EAX);
+                let subleaf = self.encode_load_reg(ECX);
+                let registers = [EAX, EBX, ECX, EDX];
+                let mut results = vec![];
+                for (index, &register) in registers.iter().enumerate() {
+                    let target = self.encode_temp(N32);
+                    let query = Query::Cpuid { leaf, subleaf, index };
+                    self.ops.push(Op::Query { query, target, register });
+                    results.push(target);
+                }
+                for (register, target) in registers.iter().zip(results) {
+                    let dest = Location::Direct(N32, 1, register.address());
+                    self.encode_move(dest, Location::Temp(target))?;
+                }
+            },
+            Rdrand => {
+                // The instruction always succeeds, that is, it sets the carry flag.
+                let register = match inst.operands[0] {
+                    Operand::Direct(register) => register,
+                    _ => return Err("invalid operand for rdrand".to_string()),
+                };
+                let target = self.encode_temp(register.data_type());
+                self.ops.push(Op::Query { query: Query::Random, target, register });
+                let dest = self.encode_get_location(inst.operands[0]);
+                self.encode_move(dest, Location::Temp(target))?;
+                self.last_flag_op = Some(SymExpr::Sub(
+                    boxed(SymExpr::from_int(N8, 0)),
+                    boxed(SymExpr::from_int(N8, 1)),
+                ));
+            },
+            // Fences only order memory accesses, which are executed in order anyways.
+            Nop | Mfence | Lfence | Sfence | Endbr64 => {},
+
+            // Instructions of plugins come with their microcode.
+            Custom(name) => return Err(format!("custom instruction {} without microcode", name)),
+
+            // Move between general purpose and vector registers, zero-extending
+            // or truncating the value.
+            Movd | Movq => self.encode_move_casted(inst.operands[0], inst.operands[1], false)?,
+
+            // Bitwise operations work on the whole vector.
+            Pxor => self.encode_vector_binop(inst, |xor, a, b| Op::Xor { xor, a, b })?,
+            Pand => self.encode_vector_binop(inst, |and, a, b| Op::And { and, a, b })?,
+            Por => self.encode_vector_binop(inst, |or, a, b| Op::Or { or, a, b })?,
+            Pandn => {
+                let (dest, a, b) = self.encode_vector_operands(inst);
+                self.ops.push(Op::Not { not: a, a });
+                self.encode_vector_result(dest, |and| Op::And { and, a, b })?;
+            },
+
+            // Everything else works lane-wise.
+            Paddb => self.encode_packed(inst, PackedOp::Add, DataType::N8)?,
+            Paddw => self.encode_packed(inst, PackedOp::Add, DataType::N16)?,
+            Paddd => self.encode_packed(inst, PackedOp::Add, DataType::N32)?,
+            Paddq => self.encode_packed(inst, PackedOp::Add, DataType::N64)?,
+            Psubb => self.encode_packed(inst, PackedOp::Sub, DataType::N8)?,
+            Psubw => self.encode_packed(inst, PackedOp::Sub, DataType::N16)?,
+            Psubd => self.encode_packed(inst, PackedOp::Sub, DataType::N32)?,
+            Psubq => self.encode_packed(inst, PackedOp::Sub, DataType::N64)?,
+            Pcmpeqb => self.encode_packed(inst, PackedOp::Equal, DataType::N8)?,
+            Pcmpeqw => self.encode_packed(inst, PackedOp::Equal, DataType::N16)?,
+            Pcmpeqd => self.encode_packed(inst, PackedOp::Equal, DataType::N32)?,
+            Pcmpgtb => self.encode_packed(inst, PackedOp::Greater, DataType::N8)?,
+            Pcmpgtw => self.encode_packed(inst, PackedOp::Greater, DataType::N16)?,
+            Pcmpgtd => self.encode_packed(inst, PackedOp::Greater, DataType::N32)?,
+            Pshufb => self.encode_packed(inst, PackedOp::Shuffle, DataType::N8)?,
+
+            Xorps | Xorpd => self.encode_vector_binop(inst, |xor, a, b| Op::Xor { xor, a, b })?,
+
+            // Scalar floats are moved and computed in the low part of the vector registers.
+            Movss | Movsd => {
+                let data_type = if inst.mnemoic == Movss { F32 } else { F64 };
+                let dest = self.encode_get_float_location(inst.operands[0], data_type);
+                let src = self.encode_get_float_location(inst.operands[1], data_type);
+                self.encode_move(dest, src)?;
+            },
+            Addss => self.encode_float_binop(inst, FloatOp::Add, F32)?,
+            Addsd => self.encode_float_binop(inst, FloatOp::Add, F64)?,
+            Subss => self.encode_float_binop(inst, FloatOp::Sub, F32)?,
+            Subsd => self.encode_float_binop(inst, FloatOp::Sub, F64)?,
+            Mulss => self.encode_float_binop(inst, FloatOp::Mul, F32)?,
+            Mulsd => self.encode_float_binop(inst, FloatOp::Mul, F64)?,
+            Divss => self.encode_float_binop(inst, FloatOp::Div, F32)?,
+            Divsd => self.encode_float_binop(inst, FloatOp::Div, F64)?,
+
+            // The comparison is remembered with its float operands, from which
+            // the conditions derive the flags it sets (see `float_condition`).
+            Ucomiss | Ucomisd | Comiss | Comisd => {
+                let data_type = match inst.mnemoic { Ucomiss | Comiss => F32, _ => F64 };
+                let (_, a) = self.encode_load_float(inst.operands[0], data_type);
+                let (_, b) = self.encode_load_float(inst.operands[1], data_type);
+                self.last_flag_op = Some(SymExpr::Sub(boxed(a.to_expr()), boxed(b.to_expr())));
+            },
+
+            // The types only matter for vector registers, general purpose
+            // registers have their own width.
+            Cvttss2si | Cvttsd2si | Cvtsi2ss | Cvtsi2sd | Cvtss2sd | Cvtsd2ss => {
+                let (to, from) = match inst.mnemoic {
+                    Cvttss2si => (N32, F32),
+                    Cvttsd2si => (N32, F64),
+                    Cvtsi2ss => (F32, N32),
+                    Cvtsi2sd => (F64, N32),
+                    Cvtss2sd => (F64, F32),
+                    _ => (F32, F64),
+                };
+                let dest = self.encode_get_float_location(inst.operands[0], to);
+                let (_, a) = self.encode_load_float(inst.operands[1], from);
+                let converted = self.encode_float_convert(a, dest.data_type());
+                self.encode_move(dest, Location::Temp(converted))?;
+            },
+
+            // The x87 register stack is modeled as just its top register.
+            Fld | Fild => {
+                let st0 = self.encode_get_location(Operand::Direct(ST0));
+                let (_, value) = self.encode_load_operand(inst.operands[0]);
+                let converted = self.encode_float_convert(value, F64);
+                self.encode_move(st0, Location::Temp(converted))?;
+            },
+            Fstp | Fistp => {
+                let dest = self.encode_get_location(inst.operands[0]);
+                let (_, value) = self.encode_load_operand(Operand::Direct(ST0));
+                let converted = self.encode_float_convert(value, dest.data_type());
+                self.encode_move(dest, Location::Temp(converted))?;
+            },
+            Fadd | Fsub | Fmul | Fdiv => {
+                let op = match inst.mnemoic {
+                    Fadd => FloatOp::Add,
+                    Fsub => FloatOp::Sub,
+                    Fmul => FloatOp::Mul,
+                    _ => FloatOp::Div,
+                };
+                let (st0, a) = self.encode_load_operand(Operand::Direct(ST0));
+                let (_, b) = self.encode_load_operand(inst.operands[0]);
+                let b = self.encode_float_convert(b, F64);
+                self.encode_vector_result(st0, |target| Op::Float { op, target, a, b })?;
+            },
+
+            Movs | Stos | Cmps | Scas => self.encode_string(inst)?,
+
+            // Gather the sign bits of the bytes into a general purpose register.
+            Pmovmskb => {
+                let dest = self.encode_get_location(inst.operands[0]);
+                let (_, a) = self.encode_load_operand(inst.operands[1]);
+                self.encode_vector_result(dest, |target| Op::Packed {
+                    op: PackedOp::MoveMask, lane: DataType::N8, target, a, b: a,
+                })?;
+            },
         }
 
         let mut ops = Vec::new();
         std::mem::swap(&mut ops, &mut self.ops);
-        Ok(Microcode { ops })
+
+        let transfer = match inst.mnemoic {
+            Jmp | Je | Jne | Jl | Jle | Jg | Jge | Jbe | Ja | Jae | Jb => Transfer::Jump,
+            Call => Transfer::Call,
+            Ret | Iret | Iretq => Transfer::Return,
+            _ => Transfer::Next,
+        };
+
+        Ok(Microcode { ops, flows: encode_flows(inst), transfer, atomic: inst.is_atomic() })
     }
 
     /// Encode a binary operation like an add or a subtract.
@@ -270,6 +739,188 @@ impl MicroEncoder {
         (left, right)
     }
 
+    /// Encode a vector operation with two operands (the first one being source and
+    /// destination) or three operands (VEX-encoded with a separate destination).
+    fn encode_vector_binop<F>(&mut self, inst: &Instruction, binop: F) -> EncoderResult<()>
+    where F: FnOnce(Temporary, Temporary, Temporary) -> MicroOperation {
+        let (dest, a, b) = self.encode_vector_operands(inst);
+        self.encode_vector_result(dest, |target| binop(target, a, b))
+    }
+
+    /// Encode a lane-wise vector operation.
+    fn encode_packed(&mut self, inst: &Instruction, op: PackedOp, lane: DataType)
+    -> EncoderResult<()> {
+        self.encode_vector_binop(inst, |target, a, b| {
+            MicroOperation::Packed { op, lane, target, a, b }
+        })
+    }
+
+    /// Encode the loading of the two source operands of a vector operation and
+    /// return the destination location and the loaded temporaries.
+    fn encode_vector_operands(&mut self, inst: &Instruction) -> (Location, Temporary, Temporary) {
+        let count = inst.operands.len();
+        let dest = self.encode_get_location(inst.operands[0]);
+        let (_, a) = self.encode_load_operand(inst.operands[count - 2]);
+        let (_, b) = self.encode_load_operand(inst.operands[count - 1]);
+        (dest, a, b)
+    }
+
+    /// Encode an operation into a fresh temporary with the type of the
+    /// destination and the move into the destination.
+    fn encode_vector_result<F>(&mut self, dest: Location, operation: F) -> EncoderResult<()>
+    where F: FnOnce(Temporary) -> MicroOperation {
+        let target = Temporary(dest.data_type(), self.temps);
+        self.temps += 1;
+        self.ops.push(operation(target));
+        self.encode_move(dest, Location::Temp(target))
+    }
+
+    /// Encode a scalar floating-point operation with the destination as first operand.
+    fn encode_float_binop(&mut self, inst: &Instruction, op: FloatOp, data_type: DataType)
+    -> EncoderResult<()> {
+        let (dest, a) = self.encode_load_float(inst.operands[0], data_type);
+        let (_, b) = self.encode_load_float(inst.operands[1], data_type);
+        self.encode_vector_result(dest, |target| MicroOperation::Float { op, target, a, b })
+    }
+
+    /// Encode the loading of a scalar float into a temporary.
+    fn encode_load_float(&mut self, operand: Operand, data_type: DataType) -> (Location, Temporary) {
+        let location = self.encode_get_float_location(operand, data_type);
+        let temp = Temporary(location.data_type(), self.temps);
+        self.ops.push(MicroOperation::Mov { dest: Location::Temp(temp), src: location });
+        self.temps += 1;
+        (location, temp)
+    }
+
+    /// Encode the location of an operand holding a scalar float. For vector registers
+    /// this is the low part with the given type, other operands are unaffected.
+    fn encode_get_float_location(&mut self, operand: Operand, data_type: DataType) -> Location {
+        match operand {
+            Operand::Direct(reg) if reg.vector_index().is_some() => {
+                Location::Direct(data_type, 1, reg.address())
+            },
+            operand => self.encode_get_location(operand),
+        }
+    }
+
+    /// Encode a floating-point conversion of the temporary if its type differs.
+    fn encode_float_convert(&mut self, temp: Temporary, new: DataType) -> Temporary {
+        if temp.0 == new {
+            return temp;
+        }
+
+        let target = Temporary(new, self.temps);
+        self.temps += 1;
+        self.ops.push(MicroOperation::Float { op: FloatOp::Convert, target, a: temp, b: temp });
+        target
+    }
+
+    /// Encode a string instruction. If it is repeated, the implicit loop running
+    /// `rcx` times is summarized by a single bulk operation instead of being unrolled.
+    /// The direction flag is assumed to be clear.
+    fn encode_string(&mut self, inst: &Instruction) -> EncoderResult<()> {
+        use MicroOperation as Op;
+        use Mnemoic::*;
+
+        let data_type = match inst.operands.iter().find_map(|op| match *op {
+            Operand::Indirect { data_type, .. } |
+            Operand::Memory { data_type, .. } => Some(data_type),
+            _ => None,
+        }) {
+            Some(data_type) => data_type,
+            None => return Err("string instruction without memory operand".to_string()),
+        };
+        let compares = inst.mnemoic == Cmps || inst.mnemoic == Scas;
+        let (rdi_loc, rdi) = self.encode_load_operand(Operand::Direct(RDI));
+        let rsi = match inst.mnemoic {
+            Movs | Cmps => Some(self.encode_load_operand(Operand::Direct(RSI))),
+            _ => None,
+        };
+
+        // Determine the number of processed elements and what remains in rcx.
+        // Repeated comparisons stop early at an unknown point.
+        let (count, remaining) = if inst.is_repeated() {
+            let (rcx_loc, total) = self.encode_load_operand(Operand::Direct(RCX));
+            if compares {
+                let remaining = self.encode_temp(DataType::N64);
+                self.ops.push(Op::Unknown { target: remaining });
+                let count = self.encode_temp(DataType::N64);
+                self.ops.push(Op::Sub { diff: count, a: total, b: remaining });
+                (count, Some((rcx_loc, remaining)))
+            } else {
+                let zero = self.encode_load_constant(Integer::from_ptr(0));
+                (total, Some((rcx_loc, zero)))
+            }
+        } else {
+            (self.encode_load_constant(Integer::from_ptr(1)), None)
+        };
+
+        match inst.mnemoic {
+            Movs => if let Some((_, src)) = rsi {
+                self.ops.push(Op::Copy { dest: rdi, src, count, data_type });
+            },
+            Stos => {
+                let (_, value) = self.encode_load_operand(inst.operands[1]);
+                self.ops.push(Op::Fill { dest: rdi, value, count });
+            },
+            _ => {},
+        }
+
+        // Advance the pointers past the processed elements.
+        let size = self.encode_load_constant(Integer::from_ptr(data_type.bytes() as u64));
+        let advance = self.encode_temp(DataType::N64);
+        self.ops.push(Op::Mul { prod: advance, a: count, b: size });
+        self.ops.push(Op::Add { sum: rdi, a: rdi, b: advance });
+        if let Some((_, rsi)) = rsi {
+            self.ops.push(Op::Add { sum: rsi, a: rsi, b: advance });
+        }
+
+        // The flags are set by the comparison of the last processed elements.
+        if compares {
+            let last_rdi = self.encode_load_last(rdi, size, data_type);
+            let left = match rsi {
+                Some((_, rsi)) => self.encode_load_last(rsi, size, data_type),
+                None => self.encode_load_operand(inst.operands[0]).1,
+            };
+            self.last_flag_op = Some(left.to_expr().sub(last_rdi.to_expr()));
+        }
+
+        self.encode_move(rdi_loc, Location::Temp(rdi))?;
+        if let Some((rsi_loc, rsi)) = rsi {
+            self.encode_move(rsi_loc, Location::Temp(rsi))?;
+        }
+        if let Some((rcx_loc, remaining)) = remaining {
+            self.encode_move(rcx_loc, Location::Temp(remaining))?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the loading of the element of the given size right before the pointer.
+    fn encode_load_last(&mut self, pointer: Temporary, size: Temporary, data_type: DataType)
+    -> Temporary {
+        let addr = self.encode_temp(DataType::N64);
+        self.ops.push(MicroOperation::Sub { diff: addr, a: pointer, b: size });
+        let value = self.encode_temp(data_type);
+        let src = Location::Indirect(data_type, 0, addr);
+        self.ops.push(MicroOperation::Mov { dest: Location::Temp(value), src });
+        value
+    }
+
+    /// Encode a bit manipulation operation into a fresh temporary.
+    fn encode_bits(&mut self, op: BitOp, a: Temporary, b: Temporary) -> Temporary {
+        let target = self.encode_temp(a.0);
+        self.ops.push(MicroOperation::Bits { op, target, a, b });
+        target
+    }
+
+    /// Create a fresh temporary.
+    fn encode_temp(&mut self, data_type: DataType) -> Temporary {
+        let temp = Temporary(data_type, self.temps);
+        self.temps += 1;
+        temp
+    }
+
     /// Encode a conditional, relative jump.
     fn encode_jump(&mut self, inst: &Instruction, condition: SymCondition) {
         let operand = inst.operands[0];
@@ -281,6 +932,44 @@ impl MicroEncoder {
         self.ops.push(MicroOperation::Jump { target, condition, relative });
     }
 
+    /// The condition under which a conditional set or move takes effect based
+    /// on the last flag-modifying operation.
+    fn encode_condition(&self, mnemoic: Mnemoic) -> EncoderResult<SymCondition> {
+        use Mnemoic::*;
+
+        if let Some((a, b)) = self.compared_floats() {
+            return float_condition(mnemoic, a, b)
+                .ok_or_else(|| format!("encode_condition: no condition for {}", mnemoic));
+        }
+
+        let equal = || condition!(self;
+            SymExpr::Sub(a, b) => Equal(a, b),
+            SymExpr::BitAnd(a, b) => SymExpr::from_int(a.data_type(), 0).equal(a.bitand(*b))
+        );
+
+        match mnemoic {
+            Sete | Cmove => equal(),
+            Setne | Cmovne => equal().map(SymCondition::not),
+            Setl | Cmovl => condition!(self; SymExpr::Sub(a, b) => LessThan(a, b, true)),
+            Setle | Cmovle => condition!(self; SymExpr::Sub(a, b) => LessEqual(a, b, true)),
+            Setg | Cmovg => condition!(self; SymExpr::Sub(a, b) => GreaterThan(a, b, true)),
+            Setge | Cmovge => condition!(self; SymExpr::Sub(a, b) => GreaterEqual(a, b, true)),
+            Setb | Cmovb => condition!(self; SymExpr::Sub(a, b) => LessThan(a, b, false)),
+            Setbe | Cmovbe => condition!(self; SymExpr::Sub(a, b) => LessEqual(a, b, false)),
+            Seta | Cmova => condition!(self; SymExpr::Sub(a, b) => GreaterThan(a, b, false)),
+            Setae | Cmovae => condition!(self; SymExpr::Sub(a, b) => GreaterEqual(a, b, false)),
+            _ => Err(format!("encode_condition: no condition for {}", mnemoic)),
+        }
+    }
+
+    /// The operands of the last flag-modifying operation if it compared floats.
+    fn compared_floats(&self) -> Option<(&SymExpr, &SymExpr)> {
+        match &self.last_flag_op {
+            Some(SymExpr::Sub(a, b)) if a.data_type().is_float() => Some((a, b)),
+            _ => None,
+        }
+    }
+
     /// Encode a set instruction, which sets a bit based on a condition.
     fn encode_set(&mut self, inst: &Instruction, condition: SymCondition) -> EncoderResult<()> {
         let location = self.encode_get_location(inst.operands[0]);
@@ -394,6 +1083,26 @@ impl MicroEncoder {
                 Indirect(data_type, 0, reg)
             },
 
+            Operand::Memory { data_type, segment, base, scaled_offset, displacement } => {
+                // Start with the displacement and add all present parts to it.
+                let disp = displacement.unwrap_or(0);
+                let addr = self.encode_load_constant(Integer::from_ptr(disp as u64));
+                for reg in segment.into_iter().chain(base) {
+                    let reg = self.encode_load_reg(reg);
+                    self.ops.push(MicroOperation::Add { sum: addr, a: addr, b: reg });
+                }
+
+                if let Some((index, scale)) = scaled_offset {
+                    let index_reg = self.encode_load_reg(index);
+                    let scale = self.encode_load_constant(Integer::from_ptr(scale as u64));
+
+                    self.ops.push(MicroOperation::Mul { prod: index_reg, a: index_reg, b: scale });
+                    self.ops.push(MicroOperation::Add { sum: addr, a: addr, b: index_reg });
+                }
+
+                Indirect(data_type, 0, addr)
+            },
+
             Operand::Immediate(int) => {
                 // Load the immediate into a temporary.
                 Temp(self.encode_load_constant(int))
@@ -448,6 +1157,251 @@ impl MicroEncoder {
     }
 }
 
+/// The condition of a conditional instruction after a floating-point
+/// comparison of `a` with `b`. The comparison sets the zero and the carry flag
+/// like an unsigned one, sets them and the parity flag if the operands are
+/// unordered (one is a NaN) and clears the sign and the overflow flag.
+fn float_condition(mnemoic: Mnemoic, a: &SymExpr, b: &SymExpr) -> Option<SymCondition> {
+    use Mnemoic::*;
+    let data_type = a.data_type();
+    let int = |value| SymExpr::from_int(data_type, value);
+    let sign = 1u64 << (data_type.bits() - 1);
+    let infinity = match data_type {
+        DataType::F32 => 0x7f80_0000,
+        _ => 0x7ff0_0000_0000_0000,
+    };
+
+    // All magnitudes beyond the one of infinity are NaNs.
+    let magnitude = |x: &SymExpr| x.clone().bitand(int(sign - 1));
+    let nan = |x: &SymExpr| magnitude(x).greater_than(int(infinity), false);
+    let unordered = nan(a).or(nan(b));
+
+    // The sign and the magnitude read as a two's complement number order
+    // all other values, with both zeros at zero.
+    let key = |x: &SymExpr| x.clone().less_than(int(0), true)
+        .if_then_else(magnitude(x).bitnot().add(int(1)), magnitude(x));
+    let zero = unordered.clone().or(key(a).equal(key(b)));
+    let carry = unordered.or(key(a).less_than(key(b), true));
+
+    Some(match mnemoic {
+        Je | Sete | Cmove => zero,
+        Jne | Setne | Cmovne => zero.not(),
+        Jb | Setb | Cmovb => carry,
+        Jae | Setae | Cmovae => carry.not(),
+        Jbe | Setbe | Cmovbe => carry.or(zero),
+        Ja | Seta | Cmova => carry.or(zero).not(),
+        Jl | Setl | Cmovl => SymCondition::FALSE,
+        Jge | Setge | Cmovge => SymCondition::TRUE,
+        Jle | Setle | Cmovle => zero,
+        Jg | Setg | Cmovg => zero.not(),
+        _ => return None,
+    })
+}
+
+/// Pairs of (source, sink) describing data dependencies in the instruction.
+fn encode_flows(inst: &Instruction) -> Vec<(ValueSource, StorageLocation)> {
+    let mut flows = encode_operand_flows(inst);
+
+    // The written flags depend on all operands and the conditions of jumps,
+    // sets and moves on the read ones. Clearing a register sets the zero and
+    // parity flags and clears the others.
+    let sources: Vec<StorageLocation> = inst.operands.iter()
+        .filter_map(|&op| StorageLocation::from_operand(op))
+        .collect();
+    for &flag in inst.mnemoic.flags_written() {
+        if inst.is_zeroing() {
+            let set = matches!(flag, Flag::Zero | Flag::Parity);
+            let value = ValueSource::Const(Integer(DataType::N8, set as u64));
+            flows.push((value, StorageLocation::Flag(flag)));
+            continue;
+        }
+        for &source in &sources {
+            flows.push((ValueSource::Storage(source), StorageLocation::Flag(flag)));
+        }
+    }
+
+    let target = match inst.operands.first() {
+        Some(Operand::Offset(_)) => Some(StorageLocation::Direct(Register::RIP)),
+        Some(&op) => StorageLocation::from_operand(op),
+        None => None,
+    };
+    if let Some(target) = target {
+        for &flag in inst.mnemoic.flags_read() {
+            flows.push((ValueSource::Storage(StorageLocation::Flag(flag)), target));
+        }
+    }
+
+    flows
+}
+
+/// The flows between the explicit and implicit operands of the instruction.
+fn encode_operand_flows(inst: &Instruction) -> Vec<(ValueSource, StorageLocation)> {
+    use DataType::*;
+    use Mnemoic::*;
+
+    let loc = StorageLocation::from_operand;
+    let reg = StorageLocation::Direct;
+    let stg = ValueSource::Storage;
+    let src = |op| match op {
+        Operand::Immediate(int) => Some(ValueSource::Const(int)),
+        op => loc(op).map(ValueSource::Storage),
+    };
+
+    macro_rules! get {
+        ($op:expr) => { if let Some(s) = loc($op) { s } else { return vec![] } };
+    }
+
+    fn stack(data_type: DataType, push: bool) -> StorageLocation {
+        StorageLocation::Indirect {
+            data_type,
+            base: RSP,
+            scaled_offset: None,
+            displacement: if push { Some(-(data_type.bytes() as i64)) } else { None },
+        }
+    }
+
+    // The cleared register does not depend on its previous value.
+    if inst.is_zeroing() {
+        let target = get!(inst.operands[0]);
+        return vec![(ValueSource::Const(Integer(target.data_type(), 0)), target)];
+    }
+
+    match inst.mnemoic {
+        Add | Sub | Imul | Xor | Inc | Dec | Andn | Bextr | Pext | Pdep |
+        Pxor | Pand | Por | Pandn |
+        Paddb | Paddw | Paddd | Paddq | Psubb | Psubw | Psubd | Psubq |
+        Pcmpeqb | Pcmpeqw | Pcmpeqd | Pcmpgtb | Pcmpgtw | Pcmpgtd | Pshufb |
+        Xorps | Xorpd | Addss | Addsd | Subss | Subsd | Mulss | Mulsd | Divss | Divsd => {
+            let target = get!(inst.operands[0]);
+            let mut source_iter = inst.operands.iter();
+            if inst.operands.len() > 2 {
+                source_iter.next().unwrap();
+            }
+            source_iter.take(2)
+                .filter_map(|&op| src(op).map(|s| (s, target)))
+                .collect()
+        },
+
+        Mov | Movzx | Movsx | Blsi | Blsr | Tzcnt | Lzcnt |
+        Movups | Movaps | Movdqa | Movdqu | Movd | Movq | Pmovmskb | Movss | Movsd |
+        Cvttss2si | Cvttsd2si | Cvtsi2ss | Cvtsi2sd | Cvtss2sd | Cvtsd2ss => match (src(inst.operands[1]), loc(inst.operands[0])) {
+            (Some(a), Some(b)) => vec![(a, b)],
+            _ => vec![],
+        },
+
+        Lea => {
+            let target = get!(inst.operands[0]);
+            let source = get!(inst.operands[1]);
+            let mut pairs = vec![];
+            if let StorageLocation::Indirect { base, scaled_offset, .. } = source {
+                pairs.push((stg(reg(base)), target));
+                if let Some((offset, _)) = scaled_offset {
+                    pairs.push((stg(reg(offset)), target));
+                }
+            }
+            pairs
+        },
+
+        // The destination keeps its value if the condition does not hold.
+        Cmove | Cmovne | Cmovl | Cmovle | Cmovg | Cmovge | Cmovb | Cmovbe | Cmova | Cmovae => {
+            let target = get!(inst.operands[0]);
+            vec![(stg(target), target), (stg(get!(inst.operands[1])), target)]
+        },
+
+        Xchg => {
+            let (a, b) = (get!(inst.operands[0]), get!(inst.operands[1]));
+            vec![(stg(b), a), (stg(a), b)]
+        },
+        Xadd => {
+            let (a, b) = (get!(inst.operands[0]), get!(inst.operands[1]));
+            vec![(stg(a), a), (stg(b), a), (stg(a), b)]
+        },
+        Cmpxchg => {
+            let (a, b) = (get!(inst.operands[0]), get!(inst.operands[1]));
+            let acc = reg(Register::accumulator(a.data_type()));
+            vec![(stg(a), a), (stg(b), a), (stg(acc), a), (stg(a), acc)]
+        },
+
+        // The results only depend on the requested leaf.
+        Cpuid => [EAX, EBX, ECX, EDX].iter()
+            .flat_map(|&target| vec![(stg(reg(EAX)), reg(target)), (stg(reg(ECX)), reg(target))])
+            .collect(),
+
+        Cwde => vec![(stg(reg(AX)), reg(EAX))],
+        Cdqe => vec![(stg(reg(EAX)), reg(RAX))],
+
+        Push => {
+            let target = get!(inst.operands[0]);
+            vec![(stg(reg(RSP)), reg(RSP)), (stg(target), stack(target.data_type(), true))]
+        },
+        Pop => {
+            let target = get!(inst.operands[0]);
+            vec![(stg(reg(RSP)), reg(RSP)), (stg(stack(target.data_type(), false)), target)]
+        },
+
+        Call => vec![(stg(reg(RIP)), stack(N64, true))],
+        Leave => vec![
+            (stg(reg(RBP)), reg(RSP)),
+            (stg(StorageLocation::indirect_reg(N64, RBP)), reg(RBP))
+        ],
+        Ret => vec![(stg(stack(N64, false)), reg(RIP))],
+        Iret | Iretq => {
+            let size = if inst.mnemoic == Iretq { N64 } else { N32 };
+            let saved = StorageLocation::Indirect {
+                data_type: size,
+                base: RSP,
+                scaled_offset: None,
+                displacement: Some(3 * size.bytes() as i64),
+            };
+            vec![(stg(stack(size, false)), reg(RIP)), (stg(saved), reg(RSP))]
+        },
+
+        Fld | Fild => match src(inst.operands[0]) {
+            Some(source) => vec![(source, reg(ST0))],
+            None => vec![],
+        },
+        Fstp | Fistp => vec![(stg(reg(ST0)), get!(inst.operands[0]))],
+
+        // The pointers are advanced by the number of processed elements,
+        // which depends on rcx if the instruction is repeated.
+        Movs | Stos | Cmps | Scas => {
+            let mut pairs = vec![];
+            let mut pointers = vec![RDI];
+            match inst.mnemoic {
+                Movs | Stos => pairs.push((stg(get!(inst.operands[1])), get!(inst.operands[0]))),
+                _ => {},
+            }
+            if inst.mnemoic == Movs || inst.mnemoic == Cmps {
+                pointers.push(RSI);
+            }
+
+            let repeated = inst.is_repeated();
+            for &pointer in &pointers {
+                pairs.push((stg(reg(pointer)), reg(pointer)));
+                if repeated {
+                    pairs.push((stg(reg(RCX)), reg(pointer)));
+                }
+            }
+
+            // Repeated comparisons stop depending on the compared values.
+            if repeated && (inst.mnemoic == Cmps || inst.mnemoic == Scas) {
+                pairs.push((stg(reg(RCX)), reg(RCX)));
+                for &operand in &inst.operands {
+                    pairs.push((stg(get!(operand)), reg(RCX)));
+                }
+            }
+
+            pairs
+        },
+        Fadd | Fsub | Fmul | Fdiv => match src(inst.operands[0]) {
+            Some(source) => vec![(stg(reg(ST0)), reg(ST0)), (source, reg(ST0))],
+            None => vec![],
+        },
+
+        _ => vec![],
+    }
+}
+
 /// Pinpoints a target in memory or temporaries.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Location {
@@ -527,6 +1481,11 @@ impl MemoryMapped for Register {
             R14 => 0x70,
             R15 => 0x78,
             IP | EIP | RIP => 0x80,
+            ST0 => 0x88,
+            FS => 0x90,
+            GS => 0x98,
+            // The narrower vector registers alias the lower parts of the wide ones.
+            r => 0x100 + 0x20 * r.vector_index().expect("address: unknown register") as u64,
         }
     }
 }
@@ -570,6 +1529,16 @@ mod tests {
         assert_eq!(code.to_string(), display);
     }
 
+    fn test_flows(bytes: &[u8], transfer: Transfer, flows: &[&str]) {
+        let instruction = Instruction::decode(bytes).unwrap();
+        let code = MicroEncoder::new().encode(&instruction).unwrap();
+        let display: Vec<_> = code.flows.iter()
+            .map(|(source, sink)| format!("{} -> {}", source, sink))
+            .collect();
+        assert_eq!(code.transfer, transfer);
+        assert_eq!(display, flows);
+    }
+
     fn codify(code: &str) -> String {
         let mut output = "Microcode [\n".to_string();
         for line in code.lines() {
@@ -694,6 +1663,15 @@ mod tests {
         ");
     }
 
+    #[test]
+    fn absolute() {
+        // Instruction: mov eax, dword ptr [0x601040]
+        test(&[0x8b, 0x04, 0x25, 0x40, 0x10, 0x60, 0x00], "
+            const T0:n64 = 0x601040:n64
+            mov [m1][0x0:n32] = [m0][(T0:n64):n32]
+        ");
+    }
+
     #[test]
     fn compares() {
         // Instruction: cmp eax, dword ptr [rbp-0x8]
@@ -793,4 +1771,302 @@ mod tests {
             jump to T0:n64
         ");
     }
+
+    #[test]
+    fn flows() {
+        // Instruction: add eax, edx
+        test_flows(&[0x01, 0xd0], Transfer::Next, &[
+            "eax -> eax", "edx -> eax", "eax -> cf", "edx -> cf", "eax -> pf", "edx -> pf",
+            "eax -> zf", "edx -> zf", "eax -> sf", "edx -> sf", "eax -> of", "edx -> of",
+        ]);
+
+        // Instruction: mov dword ptr [rbp-0x8], 0xa
+        test_flows(&[0xc7, 0x45, 0xf8, 0x0a, 0x00, 0x00, 0x00], Transfer::Next,
+                   &["0xa:n32 -> [rbp-0x8:n32]"]);
+
+        // Instruction: push rbp
+        test_flows(&[0x55], Transfer::Next, &["rsp -> rsp", "rbp -> [rsp-0x8:n64]"]);
+
+        // Instruction: call -0x76
+        test_flows(&[0xe8, 0x8a, 0xff, 0xff, 0xff], Transfer::Call, &["rip -> [rsp-0x8:n64]"]);
+
+        // Instruction: ret
+        test_flows(&[0xc3], Transfer::Return, &["[rsp:n64] -> rip"]);
+
+        // Instruction: iretq
+        test_flows(&[0x48, 0xcf], Transfer::Return,
+                   &["[rsp:n64] -> rip", "[rsp+0x18:n64] -> rsp"]);
+
+        // Instruction: jmp +0x7
+        test_flows(&[0xeb, 0x07], Transfer::Jump, &[]);
+
+        // Instruction: jle +0x7
+        let flows = encode_flows(&Instruction::decode(&[0x7e, 0x07]).unwrap());
+        let display: Vec<_> = flows.iter().map(|(source, sink)| format!("{} -> {}", source, sink)).collect();
+        assert_eq!(display, ["zf -> rip", "sf -> rip", "of -> rip"]);
+
+        // Instruction: inc ecx
+        test_flows(&[0xff, 0xc1], Transfer::Next, &[
+            "ecx -> ecx", "ecx -> pf", "ecx -> zf", "ecx -> sf", "ecx -> of",
+        ]);
+
+        // Instruction: mov rax, qword ptr fs:[0x28]
+        test_flows(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00], Transfer::Next,
+                   &["[fs+0x28:n64] -> rax"]);
+
+        // Instruction: vpxor ymm0, ymm1, ymm2
+        test_flows(&[0xc5, 0xf5, 0xef, 0xc2], Transfer::Next, &["ymm1 -> ymm0", "ymm2 -> ymm0"]);
+
+        // Cleared registers and their flags are constant.
+        // Instruction: sub rcx, rcx
+        test_flows(&[0x48, 0x29, 0xc9], Transfer::Next, &[
+            "0x0:n64 -> rcx", "0x0:n8 -> cf", "0x1:n8 -> pf", "0x1:n8 -> zf", "0x0:n8 -> sf",
+            "0x0:n8 -> of",
+        ]);
+        // Instruction: vpxor ymm0, ymm1, ymm1
+        test_flows(&[0xc5, 0xf5, 0xef, 0xc1], Transfer::Next, &["0x0:n256 -> ymm0"]);
+    }
+
+    #[test]
+    fn vectors() {
+        // Instruction: movdqu xmm0, xmmword ptr [rsi]
+        test(&[0xf3, 0x0f, 0x6f, 0x06], "
+            mov T0:n64 = [m1][0x30:n64]
+            mov [m1][0x100:n128] = [m0][(T0:n64):n128]
+        ");
+
+        // Instruction: pcmpeqb xmm0, xmm1
+        test(&[0x66, 0x0f, 0x74, 0xc1], "
+            mov T0:n128 = [m1][0x100:n128]
+            mov T1:n128 = [m1][0x120:n128]
+            packed T2:n128 = T0:n128 == T1:n128 per n8
+            mov [m1][0x100:n128] = T2:n128
+        ");
+
+        // Instruction: vpandn ymm0, ymm1, ymm2
+        test(&[0xc5, 0xf5, 0xdf, 0xc2], "
+            mov T0:n256 = [m1][0x120:n256]
+            mov T1:n256 = [m1][0x140:n256]
+            not T0:n256 = !T0:n256
+            and T2:n256 = T0:n256 & T1:n256
+            mov [m1][0x100:n256] = T2:n256
+        ");
+
+        // Instruction: pmovmskb eax, xmm0
+        test(&[0x66, 0x0f, 0xd7, 0xc0], "
+            mov T0:n128 = [m1][0x100:n128]
+            packed T1:n32 = msb T0:n128 per n8
+            mov [m1][0x0:n32] = T1:n32
+        ");
+    }
+
+    #[test]
+    fn floats() {
+        // Instruction: addss xmm0, xmm1
+        test(&[0xf3, 0x0f, 0x58, 0xc1], "
+            mov T0:f32 = [m1][0x100:f32]
+            mov T1:f32 = [m1][0x120:f32]
+            float T2:f32 = T0:f32 + T1:f32
+            mov [m1][0x100:f32] = T2:f32
+        ");
+
+        // Instruction: cvttss2si eax, xmm0
+        test(&[0xf3, 0x0f, 0x2c, 0xc0], "
+            mov T0:f32 = [m1][0x100:f32]
+            float T1:n32 = convert T0:f32
+            mov [m1][0x0:n32] = T1:n32
+        ");
+
+        // Instruction: fld dword ptr [rbp-0x4]
+        test(&[0xd9, 0x45, 0xfc], "
+            mov T0:n64 = [m1][0x28:n64]
+            const T1:n64 = 0xfffffffffffffffc:n64
+            add T0:n64 = T0:n64 + T1:n64
+            mov T2:f32 = [m0][(T0:n64):f32]
+            float T3:f64 = convert T2:f32
+            mov [m1][0x88:f64] = T3:f64
+        ");
+
+        // Instruction: ucomiss xmm0, xmm1 and ja +0x5
+        let mut enc = MicroEncoder::new();
+        test_with_encoder(&mut enc, &[0x0f, 0x2e, 0xc1], "
+            mov T0:f32 = [m1][0x100:f32]
+            mov T1:f32 = [m1][0x120:f32]
+        ");
+
+        // The conditions compare the operands as floats and take the branch
+        // for the unordered flags if one is a NaN.
+        let condition = |jump: &[u8], a: f32, b: f32| {
+            let mut enc = MicroEncoder::new();
+            enc.encode(&Instruction::decode(&[0x0f, 0x2e, 0xc1]).unwrap()).unwrap();
+            let code = enc.encode(&Instruction::decode(jump).unwrap()).unwrap();
+            let condition = code.ops.iter()
+                .find_map(|op| match op {
+                    MicroOperation::Jump { condition, .. } => Some(condition.clone()),
+                    _ => None,
+                })
+                .unwrap();
+            let value = |symbol: Symbol| if symbol.2 == 0 { a } else { b };
+            condition.evaluate(&|symbol| {
+                Some(Integer(DataType::F32, value(symbol).to_bits() as u64))
+            })
+        };
+        let (ja, jb, je) = (&[0x77, 0x05][..], &[0x72, 0x05][..], &[0x74, 0x05][..]);
+        assert!(condition(ja, 2.0, -1.0) && !condition(jb, 2.0, -1.0));
+        assert!(condition(jb, -2.0, -1.0) && !condition(ja, -2.0, -1.0));
+        assert!(condition(jb, -1.0, 0.5) && !condition(je, -1.0, 0.5));
+        assert!(condition(je, -0.0, 0.0) && !condition(ja, -0.0, 0.0) && !condition(jb, -0.0, 0.0));
+        assert!(condition(je, f32::NAN, 1.0) && condition(jb, 1.0, f32::NAN));
+        assert!(!condition(ja, f32::NAN, 1.0) && !condition(ja, 1.0, -f32::NAN));
+
+        // Instruction: fmul qword ptr [rbp-0x10]
+        test_flows(&[0xdc, 0x4d, 0xf0], Transfer::Next, &["st0 -> st0", "[rbp-0x10:f64] -> st0"]);
+    }
+
+    #[test]
+    fn conditionals() {
+        // Instruction: cmp eax, edx and cmovl eax, edx
+        let mut enc = MicroEncoder::new();
+        test_with_encoder(&mut enc, &[0x39, 0xd0], "
+            mov T0:n32 = [m1][0x0:n32]
+            mov T1:n32 = [m1][0x10:n32]
+        ");
+        test_with_encoder(&mut enc, &[0x0f, 0x4c, 0xc2], "
+            mov T2:n32 = [m1][0x0:n32]
+            mov T3:n32 = [m1][0x10:n32]
+            select T4:n32 = T3:n32 if (T0:n32 < T1:n32 signed) else T2:n32
+            mov [m1][0x0:n32] = T4:n32
+        ");
+
+        // Instruction: setne al
+        test_with_encoder(&mut enc, &[0x0f, 0x95, 0xc0], "
+            set T5:n8 if (not (T0:n32 == T1:n32))
+            mov [m1][0x0:n8] = T5:n8
+        ");
+
+        // Instruction: cmovl rax, rdx
+        let flows = encode_flows(&Instruction::decode(&[0x48, 0x0f, 0x4c, 0xc2]).unwrap());
+        let display: Vec<_> = flows.iter().map(|(source, sink)| format!("{} -> {}", source, sink)).collect();
+        assert_eq!(display, ["rax -> rax", "rdx -> rax", "sf -> rax", "of -> rax"]);
+    }
+
+    #[test]
+    fn atomics() {
+        // Instruction: lock xadd dword ptr [rdi], eax
+        test(&[0xf0, 0x0f, 0xc1, 0x07], "
+            mov T0:n64 = [m1][0x38:n64]
+            mov T1:n32 = [m0][(T0:n64):n32]
+            mov T2:n32 = [m1][0x0:n32]
+            add T3:n32 = T1:n32 + T2:n32
+            mov [m1][0x0:n32] = T1:n32
+            mov [m0][(T0:n64):n32] = T3:n32
+        ");
+
+        // Instruction: lock cmpxchg qword ptr [rdi], rdx
+        test(&[0xf0, 0x48, 0x0f, 0xb1, 0x17], "
+            mov T0:n64 = [m1][0x38:n64]
+            mov T1:n64 = [m0][(T0:n64):n64]
+            mov T2:n64 = [m1][0x10:n64]
+            mov T3:n64 = [m1][0x0:n64]
+            select T4:n64 = T2:n64 if (T3:n64 == T1:n64) else T1:n64
+            mov [m0][(T0:n64):n64] = T4:n64
+            mov [m1][0x0:n64] = T1:n64
+        ");
+
+        let atomic = |bytes: &[u8]| {
+            let instruction = Instruction::decode(bytes).unwrap();
+            MicroEncoder::new().encode(&instruction).unwrap().atomic
+        };
+        assert!(atomic(&[0xf0, 0xff, 0x0f]));
+        assert!(atomic(&[0x48, 0x87, 0x06]));
+        assert!(!atomic(&[0x48, 0x87, 0xc6]));
+        assert!(!atomic(&[0x48, 0xff, 0xc0]));
+    }
+
+    #[test]
+    fn bits() {
+        // Instruction: blsr rax, rdi
+        test(&[0xc4, 0xe2, 0xf8, 0xf3, 0xcf], "
+            mov T0:n64 = [m1][0x38:n64]
+            const T2:n64 = 0x1:n64
+            sub T1:n64 = T0:n64 - T2:n64
+            and T3:n64 = T0:n64 & T1:n64
+            mov [m1][0x0:n64] = T3:n64
+        ");
+
+        // Instruction: tzcnt rax, rdi
+        test(&[0xf3, 0x48, 0x0f, 0xbc, 0xc7], "
+            mov T0:n64 = [m1][0x38:n64]
+            bits T1:n64 = trailing zeros T0:n64
+            mov [m1][0x0:n64] = T1:n64
+        ");
+
+        // Instruction: pext rax, rdi, rsi
+        test_flows(&[0xc4, 0xe2, 0xc2, 0xf5, 0xc6], Transfer::Next, &["rdi -> rax", "rsi -> rax"]);
+    }
+
+    #[test]
+    fn environment() {
+        // Instruction: rdtsc
+        test(&[0x0f, 0x31], "
+            query T0:n64 = timestamp for rax
+            const T1:n64 = 0x20:n64
+            bits T2:n64 = T0:n64 >> T1:n64
+            cast T0:n64 to n32
+            cast T2:n64 to n32
+            mov [m1][0x0:n32] = T0:n32
+            mov [m1][0x10:n32] = T2:n32
+        ");
+
+        // Instruction: rdrand rax
+        test(&[0x48, 0x0f, 0xc7, 0xf0], "
+            query T0:n64 = random for rax
+            mov [m1][0x0:n64] = T0:n64
+        ");
+
+        // Instruction: cpuid
+        test_flows(&[0x0f, 0xa2], Transfer::Next, &[
+            "eax -> eax", "ecx -> eax", "eax -> ebx", "ecx -> ebx",
+            "eax -> ecx", "ecx -> ecx", "eax -> edx", "ecx -> edx",
+        ]);
+    }
+
+    #[test]
+    fn strings() {
+        // Instruction: rep movs byte ptr [rdi], byte ptr [rsi]
+        test(&[0xf3, 0xa4], "
+            mov T0:n64 = [m1][0x38:n64]
+            mov T1:n64 = [m1][0x30:n64]
+            mov T2:n64 = [m1][0x8:n64]
+            const T3:n64 = 0x0:n64
+            copy T2:n64 x n8 from (T1:n64) to (T0:n64)
+            const T4:n64 = 0x1:n64
+            mul T5:n64 = T2:n64 * T4:n64
+            add T0:n64 = T0:n64 + T5:n64
+            add T1:n64 = T1:n64 + T5:n64
+            mov [m1][0x38:n64] = T0:n64
+            mov [m1][0x30:n64] = T1:n64
+            mov [m1][0x8:n64] = T3:n64
+        ");
+
+        // Instruction: repne scas al, byte ptr [rdi]
+        test(&[0xf2, 0xae], "
+            mov T0:n64 = [m1][0x38:n64]
+            mov T1:n64 = [m1][0x8:n64]
+            unknown T2:n64
+            sub T3:n64 = T1:n64 - T2:n64
+            const T4:n64 = 0x1:n64
+            mul T5:n64 = T3:n64 * T4:n64
+            add T0:n64 = T0:n64 + T5:n64
+            sub T6:n64 = T0:n64 - T4:n64
+            mov T7:n8 = [m0][(T6:n64):n8]
+            mov T8:n8 = [m1][0x0:n8]
+            mov [m1][0x38:n64] = T0:n64
+            mov [m1][0x8:n64] = T2:n64
+        ");
+
+        // Instruction: rep stos qword ptr [rdi], rax
+        test_flows(&[0xf3, 0x48, 0xab], Transfer::Next,
+                   &["rax -> [rdi:n64]", "rdi -> rdi", "rcx -> rdi"]);
+    }
 }
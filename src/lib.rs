@@ -2,17 +2,28 @@
 
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Seek};
+#[cfg(feature = "fs")]
 use std::path::Path;
 
-use crate::elf::ElfFile;
+use crate::dwarf::{DebugSections, Variable};
+use crate::elf::{ElfError, ElfFile, Relocation, Section, SymbolTableEntry};
 use crate::ir::{Microcode, MicroEncoder};
+use crate::plugin::Decoder;
+use crate::sym::MemoryImage;
+use crate::unwind::{LandingPad, UnwindError};
 use crate::x86_64::Instruction;
 
 
 /// Helper functions and macros that are used across the crate.
 #[macro_use]
 mod helper {
+    use std::collections::HashMap;
     use std::fmt::{self, Formatter};
+    #[cfg(feature = "fs")]
+    use crate::flow::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+    #[cfg(feature = "fs")]
+    use crate::flow::VisualizationStyle;
     use crate::math::DataType;
 
     pub fn write_signed_hex(f: &mut Formatter, value: i64) -> fmt::Result {
@@ -31,6 +42,97 @@ mod helper {
 
     pub fn boxed<T>(value: T) -> Box<T> { Box::new(value) }
 
+    /// Quote and escape a string for use in JSON.
+    pub fn json_string(s: &str) -> String {
+        let mut quoted = String::with_capacity(s.len() + 2);
+        quoted.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    /// Replace the tokens of the text that have a replacement. Tokens are the
+    /// longest runs of the characters that are part of them.
+    pub fn replace_tokens<F>(text: &str, part: F, replacements: &HashMap<String, String>) -> String
+    where F: Fn(char) -> bool {
+        let mut replaced = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c| part(c)) {
+            replaced.push_str(&rest[.. start]);
+            let end = rest[start ..].find(|c| !part(c)).map_or(rest.len(), |end| start + end);
+            let token = &rest[start .. end];
+            replaced.push_str(replacements.get(token).map_or(token, String::as_str));
+            rest = &rest[end ..];
+        }
+        replaced.push_str(rest);
+        replaced
+    }
+
+    /// Replace the occurrences of the value in the text that neither start
+    /// nor end within a token, with tokens like for `replace_tokens`.
+    pub fn replace_whole<F>(text: &str, part: F, value: &str, replacement: &str) -> String
+    where F: Fn(char) -> bool {
+        let joined = |c: Option<char>| c.map_or(false, &part);
+        let (starts, ends) = (value.starts_with(&part), value.ends_with(&part));
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, _) in text.match_indices(value) {
+            let end = start + value.len();
+            if starts && joined(text[.. start].chars().next_back())
+                || ends && joined(text[end ..].chars().next()) {
+                continue;
+            }
+            replaced.push_str(&text[last .. start]);
+            replaced.push_str(replacement);
+            last = end;
+        }
+        replaced.push_str(&text[last ..]);
+        replaced
+    }
+
+    /// The message of a caught panic.
+    #[cfg(feature = "fs")]
+    pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+        match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload.downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "panic".to_string()),
+        }
+    }
+
+    /// Export the graph of the program named `cfg`, `ddg` or `pdg` in the
+    /// format `dot` or `json`, if it can be exported in the format.
+    #[cfg(feature = "fs")]
+    pub fn export_graph(
+        program: &crate::Program,
+        cfg: &ControlFlowGraph,
+        ddg: &DataDependencyGraph,
+        pdg: &ProgramDependenceGraph,
+        graph: &str,
+        format: &str,
+    ) -> Option<std::io::Result<Vec<u8>>> {
+        let mut export = Vec::new();
+        let result = match (graph, format) {
+            ("cfg", "dot") => cfg.visualize(&mut export, program,
+                "Control Flow Graph", VisualizationStyle::Instructions),
+            ("ddg", "dot") => ddg.visualize(&mut export, "Data Dependency Graph"),
+            ("pdg", "dot") => pdg.visualize(&mut export, "Program Dependence Graph"),
+            ("cfg", "json") => cfg.write_angr_json(&mut export),
+            ("pdg", "json") => pdg.write_json(&mut export),
+            _ => return None,
+        };
+        Some(result.map(|_| export))
+    }
+
     /// Make sure operations only happen on same expressions.
     pub fn check_compatible(a: DataType, b: DataType, operation: &str) {
         assert_eq!(a, b, "incompatible data types for {}", operation);
@@ -51,8 +153,27 @@ pub mod flow;
 pub mod math;
 pub mod sym;
 pub mod elf;
+pub mod unwind;
+pub mod dwarf;
 pub mod ir;
 pub mod x86_64;
+pub mod plugin;
+#[cfg(feature = "fs")]
+pub mod batch;
+#[cfg(feature = "fs")]
+pub mod fixtures;
+#[cfg(feature = "fs")]
+pub mod server;
+#[cfg(feature = "fs")]
+pub mod watch;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[doc(hidden)]
+pub mod fuzz;
+
+mod json;
+mod parallel;
+mod trace;
 
 #[cfg(feature = "timings")]
 pub mod timings;
@@ -70,61 +191,457 @@ pub struct Program {
     pub entry: u64,
     pub binary: Vec<u8>,
     pub code: Vec<(u64, u64, Instruction, Microcode)>,
+    /// The runs of bytes in the code that do not decode to instructions as
+    /// (address, bytes). Decoding goes on after each run at the next address
+    /// where an instruction decodes.
+    pub undecoded: Vec<(u64, Vec<u8>)>,
+    /// The decoder of the code, which also decodes the code the control flow
+    /// exploration reaches.
+    pub decoder: Decoder,
     pub symbols: HashMap<u64, String>,
+    pub landing_pads: Vec<LandingPad>,
+    /// Regions which are both writable and executable as (address, size).
+    pub rwx_regions: Vec<(u64, u64)>,
+    /// The sections loaded into memory as (name, address, size).
+    pub sections: Vec<(String, u64, u64)>,
+    /// The functions from `.init_array`, which run before the entry point.
+    pub constructors: Vec<u64>,
+    /// The functions from `.fini_array` in the order they run after the exit.
+    pub destructors: Vec<u64>,
+    /// The interrupt handlers of a bare-metal image, which can run at any
+    /// time (see `flow::BareMetal`).
+    pub handlers: Vec<u64>,
+    /// Call targets mapped to the functions that actually run when called:
+    /// indirect functions and their stubs to the candidate implementations and
+    /// weak functions to the global ones overriding them.
+    pub resolutions: HashMap<u64, Vec<u64>>,
+    /// The initial contents of the data sections.
+    pub image: MemoryImage,
+    /// The variables of the source program if there is debugging information.
+    pub variables: Vec<Variable>,
 }
 
 impl Program {
     /// Create a new program from a 64-bit ELF file.
+    #[cfg(feature = "fs")]
     pub fn new<P: AsRef<Path>>(filename: P) -> Program {
+        Program::try_new(filename).unwrap()
+    }
+
+    /// Create a new program from the bytes of a 64-bit ELF file.
+    pub fn from_bytes(bytes: &[u8]) -> Program {
+        Program::try_from_bytes(bytes).unwrap()
+    }
+
+    /// Create a new program from a 64-bit ELF file unless it is malformed.
+    #[cfg(feature = "fs")]
+    pub fn try_new<P: AsRef<Path>>(filename: P) -> Result<Program, LoadError> {
+        Program::from_elf(ElfFile::new(filename)?)
+    }
+
+    /// Create a new program from the bytes of a 64-bit ELF file unless they
+    /// are malformed.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Program, LoadError> {
+        Program::from_elf(ElfFile::from_slice(bytes)?)
+    }
+
+    /// Create a program from raw code at the base address, which is also the
+    /// entry point. It has the symbols, but neither data nor any of the other
+    /// information of an executable file.
+    pub fn from_code(base: u64, binary: &[u8], symbols: HashMap<u64, String>) -> Program {
+        let decoder = Decoder::default();
+        let (code, undecoded) = decode(base, binary, &decoder);
+        Program {
+            base,
+            entry: base,
+            binary: binary.to_vec(),
+            code,
+            undecoded,
+            decoder,
+            symbols,
+            landing_pads: Vec::new(),
+            rwx_regions: Vec::new(),
+            sections: Vec::new(),
+            constructors: Vec::new(),
+            destructors: Vec::new(),
+            handlers: Vec::new(),
+            resolutions: HashMap::new(),
+            image: MemoryImage::new(),
+            variables: Vec::new(),
+        }
+    }
+
+    fn from_elf<R: Read + Seek>(mut file: ElfFile<R>) -> Result<Program, LoadError> {
         crate::timings::start("program");
+        let _phase = crate::trace::phase("load");
+        let program = Program::load(&mut file);
+        crate::timings::stop();
+        program
+    }
 
-        let mut file = ElfFile::new(filename).unwrap();
-        let text = file.get_section(".text").unwrap();
+    fn load<R: Read + Seek>(file: &mut ElfFile<R>) -> Result<Program, LoadError> {
+        let text = file.get_section(".text")?;
 
         let base = text.header.addr;
         let binary = text.data;
+        let decoder = Decoder::default();
+        let (code, undecoded) = decode(base, &binary, &decoder);
 
-        let mut index = 0;
-        let mut code = Vec::new();
-        let mut encoder = MicroEncoder::new();
+        // Extract the symbol names for functions and other things.
+        let symbol_entries = file.get_symbols().unwrap_or_default();
+        let mut symbols = HashMap::new();
+        for entry in &symbol_entries {
+            if !entry.name.is_empty() {
+                symbols.insert(entry.value, entry.name.clone());
+            }
+        }
 
-        // Decode the whole text section.
-        while index < binary.len() as u64 {
-            let len = Instruction::length(&binary[index as usize ..]);
-            let bytes = &binary[index as usize .. (index + len) as usize];
-            let instruction = Instruction::decode(bytes).unwrap();
-            let microcode = encoder.encode(&instruction).unwrap();
-            code.push((base + index, len, instruction, microcode));
-            index += len;
+        // Find the landing pads for exceptions thrown in calls.
+        let landing_pads = match (file.get_section(".eh_frame"), file.get_section(".gcc_except_table")) {
+            (Ok(eh_frame), Ok(except_table)) => {
+                unwind::parse_landing_pads(&eh_frame, &except_table).unwrap_or_default()
+            },
+            _ => Vec::new(),
+        };
+
+        // Find the constructors and destructors. In position-independent
+        // executables the arrays are only filled by relative relocations.
+        let relocations = file.get_relocations(".rela.dyn").unwrap_or_default();
+        let constructors = parse_pointers(file, ".init_array", &relocations);
+        let mut destructors = parse_pointers(file, ".fini_array", &relocations);
+        destructors.reverse();
+
+        // Find the stubs of indirect functions, whose slots are filled by
+        // calling the resolver, to redirect calls to the implementations.
+        let mut irelative = relocations.clone();
+        irelative.extend(file.get_relocations(".rela.plt").unwrap_or_default());
+        let mut stubs = Vec::new();
+        for name in &[".plt", ".plt.sec", ".iplt"] {
+            if let Ok(section) = file.get_section(name) {
+                stubs.extend(parse_stubs(&section, &irelative));
+            }
         }
+        let resolutions = resolve_calls(&symbol_entries, &stubs);
 
-        // Extract the symbol names for functions and other things.
-        let mut symbols = HashMap::new();
-        if let Ok(symbol_entries) = file.get_symbols() {
-            for entry in symbol_entries {
-                if !entry.name.is_empty() {
-                    symbols.insert(entry.value, entry.name);
+        // Load the initial contents of the data sections. Sections without
+        // contents in the file are zeros, which are not stored.
+        let mut image = MemoryImage::new();
+        let data_headers: Vec<_> = file.section_headers.iter()
+            .filter(|header| header.is_allocated() && !header.is_executable())
+            .cloned()
+            .collect();
+        for header in data_headers {
+            if header.is_nobits() {
+                image.insert_zeros(header.addr, header.size);
+            } else if let Ok(section) = file.get_section(&header.name) {
+                match header.is_writable() {
+                    true => image.insert(header.addr, section.data),
+                    false => image.insert_readonly(header.addr, section.data),
                 }
             }
         }
 
-        crate::timings::stop();
+        // Find the source variables. Debugging information is only an aid to
+        // the output, so unsupported information is left out.
+        let mut debug = |name: &str| {
+            file.get_section(name).map(|section| section.data).unwrap_or_default()
+        };
+        let sections = DebugSections {
+            info: debug(".debug_info"),
+            abbrev: debug(".debug_abbrev"),
+            str: debug(".debug_str"),
+            line_str: debug(".debug_line_str"),
+            addr: debug(".debug_addr"),
+            loc: debug(".debug_loc"),
+            loclists: debug(".debug_loclists"),
+        };
+        let variables = dwarf::parse_variables(&sections).unwrap_or_default();
 
-        Program {
+        // Find the regions where code could be modified at runtime.
+        let rwx_regions = file.section_headers.iter()
+            .filter(|header| header.is_writable() && header.is_executable())
+            .map(|header| (header.addr, header.size))
+            .collect();
+        let sections = file.section_headers.iter()
+            .filter(|header| header.is_allocated())
+            .map(|header| (header.name.clone(), header.addr, header.size))
+            .collect();
+
+        Ok(Program {
             base,
             entry: file.header.entry,
             binary,
             code,
-            symbols
+            undecoded,
+            decoder,
+            symbols,
+            landing_pads,
+            rwx_regions,
+            sections,
+            constructors,
+            destructors,
+            handlers: Vec::new(),
+            resolutions,
+            image,
+            variables,
+        })
+    }
+
+    /// The addresses where execution starts in order: first the constructors,
+    /// then the interrupt handlers and then the entry point.
+    pub fn roots(&self) -> Vec<u64> {
+        let mut roots = self.constructors.clone();
+        roots.extend(&self.handlers);
+        roots.push(self.entry);
+        roots
+    }
+
+    /// Create a copy of the program with bytes of its code replaced by the
+    /// patches given as (address, bytes) and the code lifted again.
+    pub fn patched(&self, patches: &[(u64, Vec<u8>)]) -> Program {
+        let mut binary = self.binary.clone();
+        for (addr, bytes) in patches {
+            for (i, &byte) in bytes.iter().enumerate() {
+                let index = (addr + i as u64).wrapping_sub(self.base) as usize;
+                if let Some(slot) = binary.get_mut(index) {
+                    *slot = byte;
+                }
+            }
+        }
+
+        let (code, undecoded) = decode(self.base, &binary, &self.decoder);
+        Program {
+            code,
+            undecoded,
+            binary,
+            .. self.clone()
+        }
+    }
+
+    /// Create a copy of the program with its code decoded again by the
+    /// decoder, which is kept for the code decoded later.
+    pub fn with_decoder(&self, decoder: Decoder) -> Program {
+        let (code, undecoded) = decode(self.base, &self.binary, &decoder);
+        Program {
+            code,
+            undecoded,
+            decoder,
+            .. self.clone()
         }
     }
 
+    /// Whether the region of `len` bytes starting at the address overlaps the
+    /// code of the program or a region that could contain code.
+    pub fn is_code(&self, addr: u64, len: u64) -> bool {
+        let overlaps = |start: u64, size: u64| addr < start + size && start < addr + len;
+        overlaps(self.base, self.binary.len() as u64)
+            || self.rwx_regions.iter().any(|&(start, size)| overlaps(start, size))
+    }
+
     /// Get the instruction at the given address.
     pub fn get_instruction(&self, addr: u64) -> Option<&Instruction> {
         self.code.iter()
             .find(|entry| entry.0 == addr)
             .map(|entry| &entry.2)
     }
+
+    /// Get the landing pad catching exceptions thrown by the call at the given address.
+    pub fn get_landing_pad(&self, addr: u64) -> Option<u64> {
+        self.landing_pads.iter()
+            .find(|landing_pad| landing_pad.covers(addr))
+            .map(|landing_pad| landing_pad.pad)
+    }
+}
+
+/// Read the function pointers from an array section, leaving out the markers
+/// for empty entries.
+fn parse_pointers<R: Read + Seek>(
+    file: &mut ElfFile<R>,
+    name: &str,
+    relocations: &[Relocation],
+) -> Vec<u64> {
+    let section = match file.get_section(name) {
+        Ok(section) => section,
+        Err(_) => return Vec::new(),
+    };
+
+    section.data.chunks_exact(8)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let addr = section.header.addr + 8 * i as u64;
+            let mut value = [0; 8];
+            value.copy_from_slice(bytes);
+            match u64::from_le_bytes(value) {
+                0 => relocations.iter()
+                    .find(|relocation| relocation.offset == addr && relocation.is_relative())
+                    .map(|relocation| relocation.addend as u64)
+                    .unwrap_or(0),
+                value => value,
+            }
+        })
+        .filter(|&value| value != 0 && value != u64::MAX)
+        .collect()
+}
+
+/// Find the stubs in a procedure linkage table that jump through a slot filled
+/// by an indirect relative relocation and pair them with the slot's resolver.
+fn parse_stubs(section: &Section, relocations: &[Relocation]) -> Vec<(u64, u64)> {
+    let resolvers: HashMap<u64, u64> = relocations.iter()
+        .filter(|relocation| relocation.is_irelative())
+        .map(|relocation| (relocation.offset, relocation.addend as u64))
+        .collect();
+
+    let mut stubs = Vec::new();
+    for (i, entry) in section.data.chunks(16).enumerate() {
+        // The stub contains an indirect jump `jmp [rip+disp]`.
+        let position = match entry.windows(6).position(|bytes| bytes[.. 2] == [0xff, 0x25]) {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let mut disp = [0; 4];
+        disp.copy_from_slice(&entry[position + 2 .. position + 6]);
+        let addr = section.header.addr + 16 * i as u64;
+        let next = addr + position as u64 + 6;
+        let slot = next.wrapping_add(i32::from_le_bytes(disp) as i64 as u64);
+
+        if let Some(&resolver) = resolvers.get(&slot) {
+            stubs.push((addr, resolver));
+        }
+    }
+
+    stubs
+}
+
+/// The first parts of the names of implementations of indirect functions
+/// after the function name, which denote the instruction set extensions or
+/// variants they are written for.
+const IMPLEMENTATION_VARIANTS: &[&str] = &[
+    "sse", "ssse", "avx", "evex", "erms", "fma", "generic", "unaligned", "aligned",
+];
+
+/// Map call targets to the functions that actually run when called.
+///
+/// The implementations of an indirect function are found by the naming
+/// convention of the C library (e.g. `__memcpy_avx_unaligned` for `memcpy`),
+/// where the name continues with a variant, so that `__memcpy_chk_avx` is not
+/// taken for an implementation of `memcpy`.
+fn resolve_calls(entries: &[SymbolTableEntry], stubs: &[(u64, u64)]) -> HashMap<u64, Vec<u64>> {
+    let functions: Vec<&SymbolTableEntry> = entries.iter()
+        .filter(|entry| entry.is_function() && entry.value != 0 && !entry.name.is_empty())
+        .collect();
+
+    let mut implementations: HashMap<u64, Vec<u64>> = HashMap::new();
+    for ifunc in functions.iter().filter(|entry| entry.is_ifunc()) {
+        let prefix = format!("__{}_", ifunc.name.trim_start_matches('_'));
+        let implements = |entry: &&&SymbolTableEntry| !entry.is_ifunc()
+            && entry.name.strip_prefix(&prefix).map_or(false, |variant| {
+                IMPLEMENTATION_VARIANTS.iter().any(|known| variant.starts_with(known))
+            });
+        implementations.entry(ifunc.value).or_default().extend(functions.iter()
+            .filter(implements)
+            .map(|entry| entry.value));
+    }
+
+    for candidates in implementations.values_mut() {
+        candidates.sort_unstable();
+        candidates.dedup();
+    }
+    implementations.retain(|_, candidates| !candidates.is_empty());
+
+    // Stubs jump to whatever their resolver selects.
+    let mut resolutions = implementations.clone();
+    for (stub, resolver) in stubs {
+        if let Some(candidates) = implementations.get(resolver) {
+            resolutions.insert(*stub, candidates.clone());
+        }
+    }
+
+    // Weak functions are overridden by global functions with the same name.
+    for weak in functions.iter().filter(|entry| entry.is_weak()) {
+        let global = functions.iter().find(|entry| {
+            entry.binding() == 1 && entry.name == weak.name && entry.value != weak.value
+        });
+
+        if let Some(global) = global {
+            let candidates = implementations.get(&global.value)
+                .cloned()
+                .unwrap_or_else(|| vec![global.value]);
+            resolutions.entry(weak.value).or_insert(candidates);
+        }
+    }
+
+    resolutions
+}
+
+/// The instructions and the runs of undecodable bytes of a binary.
+type Decoded = (Vec<(u64, u64, Instruction, Microcode)>, Vec<(u64, Vec<u8>)>);
+
+/// Decode the whole binary located at the base address with the decoder.
+/// Bytes that do not decode are collected into runs up to the next plausible
+/// boundary.
+fn decode(base: u64, binary: &[u8], decoder: &Decoder) -> Decoded {
+    let _phase = crate::trace::phase("decode");
+    let mut index = 0;
+    let mut code = Vec::new();
+    let mut undecoded = Vec::new();
+    let mut encoder = MicroEncoder::new();
+
+    while index < binary.len() as u64 {
+        let addr = base.wrapping_add(index);
+        let rest = &binary[index as usize ..];
+        match decoder.decode(rest, &mut encoder) {
+            Some((len, instruction, microcode)) => {
+                code.push((addr, len, instruction, microcode));
+                index += len;
+            },
+            None => {
+                let len = decoder.undecodable(rest);
+                undecoded.push((addr, rest[.. len as usize].to_vec()));
+                index += len;
+            },
+        }
+    }
+
+    (code, undecoded)
+}
+
+/// The error type for loading programs.
+pub enum LoadError {
+    Elf(ElfError),
+    Unwind(UnwindError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LoadError::Elf(err) => write!(f, "{}", err),
+            LoadError::Unwind(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Elf(err) => Some(err),
+            LoadError::Unwind(err) => Some(err),
+        }
+    }
+}
+
+debug_display!(LoadError);
+
+impl From<ElfError> for LoadError {
+    fn from(err: ElfError) -> LoadError {
+        LoadError::Elf(err)
+    }
+}
+
+impl From<UnwindError> for LoadError {
+    fn from(err: UnwindError) -> LoadError {
+        LoadError::Unwind(err)
+    }
 }
 
 impl Display for Program {
@@ -151,8 +668,65 @@ mod tests {
     use super::*;
 
     fn test(filename: &str) {
-        let path = format!("target/bin/{}", filename);
-        Program::new(path);
+        let path = fixtures::fixture(filename);
+        let program = Program::new(&path);
+
+        let loaded = Program::from_bytes(&std::fs::read(&path).unwrap());
+        assert_eq!((loaded.base, loaded.entry), (program.base, program.entry));
+        assert_eq!(loaded.code.len(), program.code.len());
+    }
+
+    #[test]
+    fn resolutions() {
+        let symbol = |name: &str, info, value| SymbolTableEntry {
+            name: name.to_string(),
+            info,
+            other: 0,
+            section_table_index: 1,
+            value,
+            size: 0,
+        };
+
+        let entries = vec![
+            symbol("memcpy", 0x1a, 0x1000),
+            symbol("__memcpy_sse2", 0x12, 0x1100),
+            symbol("__memcpy_avx", 0x12, 0x1200),
+            symbol("__memcpy_chk", 0x1a, 0x1300),
+            symbol("__memcpy_chk_avx_unaligned", 0x12, 0x1320),
+            symbol("__memcpy_chunked", 0x12, 0x1340),
+            symbol("handler", 0x22, 0x1400),
+            symbol("handler", 0x12, 0x1500),
+            symbol("data", 0x11, 0x2000),
+        ];
+
+        // The stub jumps through the slot at 0x3000 filled by the resolver.
+        let header = elf::SectionHeader {
+            name: ".plt".to_string(),
+            name_offset: 0,
+            section_type: 1,
+            flags: 6,
+            addr: 0x1800,
+            offset: 0,
+            size: 16,
+            link: 0,
+            info: 0,
+            addr_align: 16,
+            entry_size: 16,
+        };
+        let mut data = vec![0xff, 0x25, 0xfa, 0x17, 0, 0];
+        data.resize(16, 0x90);
+        let plt = Section { header, data };
+        let relocations = vec![Relocation { offset: 0x3000, info: 37, addend: 0x1000 }];
+
+        let stubs = parse_stubs(&plt, &relocations);
+        assert_eq!(stubs, vec![(0x1800, 0x1000)]);
+
+        let resolutions = resolve_calls(&entries, &stubs);
+        assert_eq!(resolutions.len(), 4);
+        assert_eq!(resolutions[&0x1000], vec![0x1100, 0x1200]);
+        assert_eq!(resolutions[&0x1300], vec![0x1320]);
+        assert_eq!(resolutions[&0x1800], vec![0x1100, 0x1200]);
+        assert_eq!(resolutions[&0x1400], vec![0x1500]);
     }
 
     #[test]
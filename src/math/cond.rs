@@ -99,7 +99,11 @@ impl SymCondition {
 
     pub fn if_then_else(self, a: SymExpr, b: SymExpr) -> SymExpr {
         check_compatible(a.data_type(), b.data_type(), "if-then-else");
-        IfThenElse(boxed(self), boxed(a), boxed(b))
+        match self {
+            Bool(true) => a,
+            Bool(false) => b,
+            c => IfThenElse(boxed(c), boxed(a), boxed(b)),
+        }
     }
 
     /// Convert this condition into an expression, where `true` is represented by
@@ -121,7 +125,7 @@ impl SymCondition {
             GreaterThan(a, b, s) => a.evaluate(symbols).greater_than(b.evaluate(symbols), *s),
             GreaterEqual(a, b, s) => a.evaluate(symbols).greater_equal(b.evaluate(symbols), *s),
             And(a, b) => a.evaluate(symbols) && b.evaluate(symbols),
-            Or(a, b) => a.evaluate(symbols) && b.evaluate(symbols),
+            Or(a, b) => a.evaluate(symbols) || b.evaluate(symbols),
             Not(a) => !a.evaluate(symbols),
         }
     }
@@ -21,7 +21,11 @@ pub enum SymExpr {
     Mul(Box<SymExpr>, Box<SymExpr>),
     BitAnd(Box<SymExpr>, Box<SymExpr>),
     BitOr(Box<SymExpr>, Box<SymExpr>),
+    BitXor(Box<SymExpr>, Box<SymExpr>),
     BitNot(Box<SymExpr>),
+    Shl(Box<SymExpr>, Box<SymExpr>),
+    /// If the bool is true, the shift is arithmetic.
+    Shr(Box<SymExpr>, Box<SymExpr>, bool),
     Cast(Box<SymExpr>, DataType, bool),
     AsExpr(Box<SymCondition>, DataType),
     IfThenElse(Box<SymCondition>, Box<SymExpr>, Box<SymExpr>),
@@ -32,7 +36,7 @@ macro_rules! bin_expr {
         pub fn $func(self, other: SymExpr) -> SymExpr {
             check_compatible(self.data_type(), other.data_type(), "operation");
             match (self, other) {
-                (Int(a), Int(b)) => Int(a.$func(b)),
+                (Int(a), Int(b)) if !a.0.is_wide() => Int(a.$func(b)),
                 (a, b) => $variant(Box::new(a), Box::new(b)),
             }
         }
@@ -79,7 +83,10 @@ macro_rules! forward {
             Mul(a, b) => { a.$func($arg); b.$func($arg); },
             BitAnd(a, b) => { a.$func($arg); b.$func($arg); },
             BitOr(a, b) => { a.$func($arg); b.$func($arg); },
+            BitXor(a, b) => { a.$func($arg); b.$func($arg); },
             BitNot(a) => a.$func($arg),
+            Shl(a, b) => { a.$func($arg); b.$func($arg); },
+            Shr(a, b, _) => { a.$func($arg); b.$func($arg); },
             Cast(a, _, _) => a.$func($arg),
             AsExpr(a, _) => a.$func($arg),
             IfThenElse(c, a, b) => { a.$func($arg); b.$func($arg); c.$func($arg); },
@@ -116,7 +123,11 @@ impl SymExpr {
             Mul(a, b) => z3_binop!(ctx, a, b, bvmul),
             BitAnd(a, b) => z3_binop!(ctx, a, b, bvand),
             BitOr(a, b) => z3_binop!(ctx, a, b, bvor),
+            BitXor(a, b) => z3_binop!(ctx, a, b, bvxor),
             BitNot(a) => a.to_z3_ast(ctx).bvnot(),
+            Shl(a, b) => z3_binop!(ctx, a, b, bvshl),
+            Shr(a, b, false) => z3_binop!(ctx, a, b, bvlshr),
+            Shr(a, b, true) => z3_binop!(ctx, a, b, bvashr),
 
             Cast(x, new, signed) => {
                 let x_ast = x.to_z3_ast(ctx);
@@ -131,7 +142,7 @@ impl SymExpr {
                         x_ast.zero_ext(extra_bits)
                     }
                 } else if src_len > dest_len {
-                    x_ast.extract(dest_len, 0)
+                    x_ast.extract(dest_len - 1, 0)
                 } else {
                     x_ast
                 }
@@ -149,6 +160,7 @@ impl SymExpr {
     // Add and simplify.
     bin_expr_simplifying!(add, a, b, match (a, b) {
         (a, Int(Integer(_, 0))) | (Int(Integer(_, 0)), a) => a,
+        (a, b) if a.data_type().is_wide() => Add(boxed(a), boxed(b)),
         (Int(a), Int(b)) => Int(a.add(b)),
         (Int(a), Add(b, c)) | (Add(b, c), Int(a)) => match (*b, *c) {
             (Int(b), c) | (c, Int(b)) => Add(boxed(c), boxed(Int(a.add(b)))),
@@ -165,6 +177,7 @@ impl SymExpr {
     // Subtract and simplify.
     bin_expr_simplifying!(sub, a, b, match (a, b) {
         (a, Int(Integer(_, 0))) | (Int(Integer(_, 0)), a) => a,
+        (a, b) if a.data_type().is_wide() => Sub(boxed(a), boxed(b)),
         (Int(a), Int(b)) => Int(a.sub(b)),
         (Int(a), Sub(b, c)) => match (*b, *c) {
             (Int(b), c) => add_or_sub(c, a, b),
@@ -191,10 +204,28 @@ impl SymExpr {
     bin_expr!(mul, Mul);
     bin_expr!(bitand, BitAnd);
     bin_expr!(bitor, BitOr);
+    bin_expr!(shl, Shl);
+
+    pub fn bitxor(self, other: SymExpr) -> SymExpr {
+        check_compatible(self.data_type(), other.data_type(), "operation");
+        match (self, other) {
+            (a, b) if a == b => Int(Integer(a.data_type(), 0)),
+            (Int(a), Int(b)) if !a.0.is_wide() => Int(a.bitxor(b)),
+            (a, b) => BitXor(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn shr(self, other: SymExpr, signed: bool) -> SymExpr {
+        check_compatible(self.data_type(), other.data_type(), "operation");
+        match (self, other) {
+            (Int(a), Int(b)) if !a.0.is_wide() => Int(a.shr(b, signed)),
+            (a, b) => Shr(Box::new(a), Box::new(b), signed),
+        }
+    }
 
     pub fn bitnot(self) -> SymExpr {
         match self {
-            Int(x) => Int(x.bitnot()),
+            Int(x) if !x.0.is_wide() => Int(x.bitnot()),
             x => BitNot(Box::new(x)),
         }
     }
@@ -214,7 +245,7 @@ impl SymExpr {
 
     pub fn cast(self, new: DataType, signed: bool) -> SymExpr {
         match self {
-            Int(x) => Int(x.cast(new, signed)),
+            Int(x) if !(signed && new.is_wide()) => Int(x.cast(new, signed)),
             Cast(x, t, false) => {
                 if x.data_type() == new {
                     *x
@@ -228,6 +259,31 @@ impl SymExpr {
         }
     }
 
+    /// Split the expression into lanes of type `lane`, lowest lane first.
+    pub fn lanes(&self, lane: DataType) -> Vec<SymExpr> {
+        let data_type = self.data_type();
+        let count = data_type.bits() / lane.bits();
+        (0 .. count).map(|i| {
+            let shift = SymExpr::from_int(data_type, (i * lane.bits()) as u64);
+            self.clone().shr(shift, false).cast(lane, false)
+        }).collect()
+    }
+
+    /// Combine lanes (lowest lane first) into one expression of the given type.
+    pub fn from_lanes(lanes: Vec<SymExpr>, data_type: DataType) -> SymExpr {
+        let mut combined = SymExpr::from_int(data_type, 0);
+        for (i, lane) in lanes.into_iter().enumerate() {
+            let shift = SymExpr::from_int(data_type, (i * lane.data_type().bits()) as u64);
+            let lane = lane.cast(data_type, false);
+            combined = if i == 0 {
+                lane
+            } else {
+                combined.bitor(lane.shl(shift))
+            };
+        }
+        combined
+    }
+
     /// The data type of the expression.
     pub fn data_type(&self) -> DataType {
         match self {
@@ -238,7 +294,10 @@ impl SymExpr {
             Mul(a, _)    => a.data_type(),
             BitAnd(a, _) => a.data_type(),
             BitOr(a, _)  => a.data_type(),
+            BitXor(a, _) => a.data_type(),
             BitNot(a)    => a.data_type(),
+            Shl(a, _)    => a.data_type(),
+            Shr(a, _, _) => a.data_type(),
             Cast(_, new, _) => *new,
             AsExpr(_, new)  => *new,
             IfThenElse(_, a, _) => a.data_type(),
@@ -257,7 +316,10 @@ impl SymExpr {
             Mul(a, b) => a.evaluate(symbols).mul(b.evaluate(symbols)),
             BitAnd(a, b) => a.evaluate(symbols).bitand(b.evaluate(symbols)),
             BitOr(a, b) => a.evaluate(symbols).bitor(b.evaluate(symbols)),
+            BitXor(a, b) => a.evaluate(symbols).bitxor(b.evaluate(symbols)),
             BitNot(a) => a.evaluate(symbols).bitnot(),
+            Shl(a, b) => a.evaluate(symbols).shl(b.evaluate(symbols)),
+            Shr(a, b, s) => a.evaluate(symbols).shr(b.evaluate(symbols), *s),
             Cast(a, data_type, signed) => a.evaluate(symbols).cast(*data_type, *signed),
             AsExpr(a, data_type) => Integer::from_bool(a.evaluate(symbols), *data_type),
             IfThenElse(c, a, b) => if c.evaluate(symbols) {
@@ -295,7 +357,10 @@ impl Display for SymExpr {
             Mul(a, b) => write!(f, "({} * {})", a, b),
             BitAnd(a, b) => write!(f, "({} & {})", a, b),
             BitOr(a, b) => write!(f, "({} | {})", a, b),
+            BitXor(a, b) => write!(f, "({} ^ {})", a, b),
             BitNot(a) => write!(f, "(!{})", a),
+            Shl(a, b) => write!(f, "({} << {})", a, b),
+            Shr(a, b, s) => write!(f, "({} >> {}{})", a, b, signed_name(*s)),
             Cast(x, new, signed) => write!(f, "({} as {}{})", x, new, signed_name(*signed)),
             AsExpr(c, data_type) => write!(f, "({} as {})", c, data_type),
             IfThenElse(c, a, b) => write!(f, "if {} then {} else {}", c, a, b),
@@ -14,11 +14,17 @@ mod num;
 mod expr;
 mod cond;
 mod smt;
+mod range;
+#[cfg(target_arch = "x86_64")]
+mod native;
 
 pub use num::*;
 pub use expr::*;
 pub use cond::*;
-pub use smt::{Solver, SharedSolver, FromAstError};
+pub use range::*;
+#[cfg(target_arch = "x86_64")]
+pub use native::{NativeOp, Mismatch, check_against_native};
+pub use smt::{Solver, SharedSolver, SolverStats, FromAstError};
 
 
 /// A dynamically typed symbolic value.
@@ -83,6 +89,26 @@ mod tests {
         assert_eq!(y().cast(N8, false), y());
     }
 
+    #[test]
+    fn lanes() {
+        let word = Int(Integer(N32, 0x04030201));
+        let bytes: Vec<_> = (1 ..= 4).map(|x| Int(Integer(N8, x))).collect();
+        assert_eq!(word.lanes(N8), bytes);
+        assert_eq!(SymExpr::from_lanes(bytes, N32), word);
+        assert_eq!(x().bitxor(x()), n(0));
+    }
+
+    #[test]
+    fn conditions() {
+        let symbols = |_: Symbol| Some(Integer(N64, 3));
+        let is = |value| x().equal(n(value));
+        let or = |a: SymCondition, b: SymCondition| SymCondition::Or(boxed(a), boxed(b));
+        assert!(or(is(3), is(4)).evaluate(&symbols));
+        assert!(or(is(4), is(3)).evaluate(&symbols));
+        assert!(!or(is(4), is(5)).evaluate(&symbols));
+        assert!(!SymCondition::And(boxed(is(3)), boxed(is(4))).evaluate(&symbols));
+    }
+
     #[test]
     fn ast() {
         let solver = Solver::new();
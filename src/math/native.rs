@@ -0,0 +1,258 @@
+//! Differential testing of the integer semantics against the processor.
+//!
+//! The flagged operations of `Integer` are executed natively with inline
+//! assembly for pseudo-random operands and the results and the defined flags
+//! are compared. Operands are drawn from a xorshift generator with a fixed
+//! seed, so a reported mismatch can be reproduced, and every few draws an
+//! edge value like zero or the signed minimum is picked instead.
+
+use std::arch::asm;
+use std::fmt::{self, Display, Formatter};
+
+use super::{DataType, Flags, Integer};
+use DataType::*;
+
+
+const ZF: u64 = 1 << 6;
+const SF: u64 = 1 << 7;
+const OF: u64 = 1 << 11;
+
+/// Execute a two-operand instruction on the register parts of the given type
+/// and return the result and the flags register.
+macro_rules! native {
+    ($mnemonic:literal, $data_type:expr, $a:expr, $b:expr) => {
+        match $data_type {
+            N8 => native!(@ $mnemonic, "l", $a, $b),
+            N16 => native!(@ $mnemonic, "x", $a, $b),
+            N32 => native!(@ $mnemonic, "e", $a, $b),
+            N64 => native!(@ $mnemonic, "r", $a, $b),
+            data_type => panic!("cannot execute {} natively", data_type),
+        }
+    };
+    (@ $mnemonic:literal, $part:literal, $a:expr, $b:expr) => {{
+        let mut value: u64 = $a;
+        let rflags: u64;
+        unsafe {
+            asm!(
+                concat!($mnemonic, " {a:", $part, "}, {b:", $part, "}"),
+                "pushfq",
+                "pop {f}",
+                a = inout(reg) value,
+                b = in(reg) $b,
+                f = out(reg) rflags,
+            );
+        }
+        (value, rflags)
+    }};
+}
+
+
+/// An operation whose semantics can be compared against the processor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NativeOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+}
+
+impl NativeOp {
+    /// All operations.
+    pub const ALL: [NativeOp; 5] = [
+        NativeOp::Add, NativeOp::Sub, NativeOp::Mul, NativeOp::And, NativeOp::Or,
+    ];
+
+    /// The types for which the operation can be executed natively.
+    pub const TYPES: [DataType; 4] = [N8, N16, N32, N64];
+
+    /// Apply the operation with the semantics of `Integer`.
+    pub fn emulate(self, a: Integer, b: Integer) -> (Integer, Flags) {
+        match self {
+            NativeOp::Add => a.flagged_add(b),
+            NativeOp::Sub => a.flagged_sub(b),
+            NativeOp::Mul => a.flagged_mul(b),
+            NativeOp::And => a.flagged_and(b),
+            NativeOp::Or => a.flagged_or(b),
+        }
+    }
+
+    /// Execute the operation on the processor.
+    ///
+    /// Panics if the type is not one of `TYPES`.
+    pub fn execute(self, a: Integer, b: Integer) -> (Integer, Flags) {
+        let data_type = a.0;
+        let (value, rflags) = match (self, data_type) {
+            (NativeOp::Mul, N8) => imul8(a.1, b.1),
+            (NativeOp::Add, _) => native!("add", data_type, a.1, b.1),
+            (NativeOp::Sub, _) => native!("sub", data_type, a.1, b.1),
+            (NativeOp::Mul, _) => native!("imul", data_type, a.1, b.1),
+            (NativeOp::And, _) => native!("and", data_type, a.1, b.1),
+            (NativeOp::Or, _) => native!("or", data_type, a.1, b.1),
+        };
+
+        let flags = Flags {
+            zero: rflags & ZF != 0,
+            sign: rflags & SF != 0,
+            overflow: rflags & OF != 0,
+        };
+
+        (Integer(data_type, value).cast(data_type, false), flags)
+    }
+
+    /// The flags which the processor defines after the operation. The zero
+    /// and sign flags are undefined after a multiplication.
+    fn defined(self, flags: Flags) -> Flags {
+        match self {
+            NativeOp::Mul => Flags { zero: false, sign: false, .. flags },
+            _ => flags,
+        }
+    }
+}
+
+impl Display for NativeOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            NativeOp::Add => "add",
+            NativeOp::Sub => "sub",
+            NativeOp::Mul => "imul",
+            NativeOp::And => "and",
+            NativeOp::Or => "or",
+        })
+    }
+}
+
+/// Operands for which emulation and native execution disagree.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Mismatch {
+    pub op: NativeOp,
+    pub a: Integer,
+    pub b: Integer,
+    pub emulated: (Integer, Flags),
+    pub native: (Integer, Flags),
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {}, {}: emulated {} {:?}, native {} {:?}",
+               self.op, self.a, self.b,
+               self.emulated.0, self.emulated.1,
+               self.native.0, self.native.1)
+    }
+}
+
+/// Compare the semantics of an operation on a type against the processor
+/// for `count` operand pairs generated from the seed.
+///
+/// The raw values are compared, so results which are only equal after
+/// truncation to the type also count as mismatches.
+pub fn check_against_native(
+    op: NativeOp,
+    data_type: DataType,
+    seed: u64,
+    count: usize,
+) -> Result<(), Mismatch> {
+    let mut operands = Operands::new(data_type, seed);
+    for _ in 0 .. count {
+        let a = operands.draw();
+        let b = operands.draw();
+
+        let emulated = op.emulate(a, b);
+        let native = op.execute(a, b);
+
+        let emulated_flags = op.defined(emulated.1);
+        let native_flags = op.defined(native.1);
+
+        if emulated.0.1 != native.0.1 || emulated_flags != native_flags {
+            return Err(Mismatch {
+                op,
+                a,
+                b,
+                emulated: (emulated.0, emulated_flags),
+                native: (native.0, native_flags),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Byte multiplications only exist in the one-operand form, which multiplies
+/// with `al` and stores the full product in `ax`.
+fn imul8(a: u64, b: u64) -> (u64, u64) {
+    let mut value: u64 = a;
+    let rflags: u64;
+    unsafe {
+        asm!(
+            "imul {b:l}",
+            "pushfq",
+            "pop {f}",
+            b = in(reg) b,
+            f = out(reg) rflags,
+            inout("rax") value,
+        );
+    }
+    (value, rflags)
+}
+
+/// Generates operands from a xorshift sequence interleaved with edge values.
+struct Operands {
+    data_type: DataType,
+    state: u64,
+    drawn: usize,
+}
+
+impl Operands {
+    fn new(data_type: DataType, seed: u64) -> Operands {
+        // Xorshift gets stuck at zero.
+        Operands { data_type, state: seed | 1, drawn: 0 }
+    }
+
+    fn draw(&mut self) -> Integer {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.drawn += 1;
+
+        let bits = self.data_type.bits() as u32;
+        let sign = 1u64 << (bits - 1);
+        let edges = [0, 1, sign - 1, sign, sign + 1, u64::MAX];
+
+        let value = if self.drawn % 4 == 0 {
+            edges[(self.state % edges.len() as u64) as usize]
+        } else {
+            self.state
+        };
+
+        Integer(self.data_type, value).cast(self.data_type, false)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differential() {
+        for &op in &NativeOp::ALL {
+            for &data_type in &NativeOp::TYPES {
+                if let Err(mismatch) = check_against_native(op, data_type, 0x5eed, 10_000) {
+                    panic!("{}", mismatch);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn native() {
+        assert_eq!(NativeOp::Add.execute(Integer(N8, 100), Integer(N8, 100)),
+            (Integer(N8, 200), Flags { zero: false, sign: true, overflow: true }));
+
+        assert_eq!(NativeOp::Sub.execute(Integer(N32, 3), Integer(N32, 3)),
+            (Integer(N32, 0), Flags { zero: true, sign: false, overflow: false }));
+
+        assert!(NativeOp::Mul.execute(Integer(N8, 16), Integer(N8, 8)).1.overflow);
+        assert!(!NativeOp::Mul.execute(Integer(N8, -2i8 as u64), Integer(N8, 8)).1.overflow);
+    }
+}
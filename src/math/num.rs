@@ -1,7 +1,7 @@
 //! Integers with machine semantics.
 
 use std::fmt::{self, Display, Formatter};
-use std::ops::{BitAnd, BitOr};
+use std::ops::{BitAnd, BitOr, BitXor};
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::helper::check_compatible;
@@ -9,6 +9,9 @@ use DataType::*;
 
 
 /// Variable data type integer with machine semantics.
+///
+/// The value is stored in 64 bits, so integers of the wide vector types
+/// (`N128` and `N256`) can only represent values below `2^64` concretely.
 #[derive(Debug, Copy, Clone, Hash)]
 pub struct Integer(pub DataType, pub u64);
 
@@ -19,6 +22,12 @@ pub enum DataType {
     N16,
     N32,
     N64,
+    N128,
+    N256,
+    /// Single-precision floats, handled as their bit patterns.
+    F32,
+    /// Double-precision floats, handled as their bit patterns.
+    F64,
 }
 
 /// Replicates code for all types.
@@ -28,11 +37,15 @@ macro_rules! typed {
             (N8 , false) => { let $caster = |n| n as u8; $code  }
             (N16, false) => { let $caster = |n| n as u16; $code }
             (N32, false) => { let $caster = |n| n as u32; $code }
-            (N64, false) => { let $caster = |n| n as u64; $code }
+            (N64, false) | (F64, false) => { let $caster = |n| n as u64; $code }
+            (F32, false) => { let $caster = |n| n as u32; $code }
+            (N128, false) | (N256, false) => { let $caster = |n| n as u128; $code }
             (N8 , true)  => { let $caster = |n| n as i8; $code  }
             (N16, true)  => { let $caster = |n| n as i16; $code }
             (N32, true)  => { let $caster = |n| n as i32; $code }
-            (N64, true)  => { let $caster = |n| n as i64; $code }
+            (N64, true) | (F64, true) => { let $caster = |n| n as i64; $code }
+            (F32, true) => { let $caster = |n| n as i32; $code }
+            (N128, true) | (N256, true) => { let $caster = |n| n as i128; $code }
         }
     };
 }
@@ -57,7 +70,7 @@ macro_rules! flagged {
                 let $a = cast(self.1);
                 let $b = cast(other.1);
                 let $target = $a.$op($b);
-                (Integer(self.0, $target as u64), $flags)
+                (Integer(self.0, $target as u64).cast(self.0, false), $flags)
             })
         }
     };
@@ -101,8 +114,8 @@ impl Integer {
         Integer(data_type, match data_type {
             N8  => bytes[0] as u64,
             N16 => LittleEndian::read_u16(bytes) as u64,
-            N32 => LittleEndian::read_u32(bytes) as u64,
-            N64 => LittleEndian::read_u64(bytes) as u64,
+            N32 | F32 => LittleEndian::read_u32(bytes) as u64,
+            N64 | N128 | N256 | F64 => LittleEndian::read_u64(bytes) as u64,
         })
     }
 
@@ -112,8 +125,8 @@ impl Integer {
         match self.0 {
             N8  => buf[0] = self.1 as u8,
             N16 => LittleEndian::write_u16(&mut buf, self.1 as u16),
-            N32 => LittleEndian::write_u32(&mut buf, self.1 as u32),
-            N64 => LittleEndian::write_u64(&mut buf, self.1 as u64),
+            N32 | F32 => LittleEndian::write_u32(&mut buf, self.1 as u32),
+            N64 | N128 | N256 | F64 => LittleEndian::write_u64(&mut buf, self.1 as u64),
         }
         buf
     }
@@ -123,6 +136,32 @@ impl Integer {
     binop!(mul, wrapping_mul);
     binop!(bitand, bitand);
     binop!(bitor, bitor);
+    binop!(bitxor, bitxor);
+
+    /// Shift left by `other` bits (the result is zero if `other` is at least
+    /// the bit width).
+    pub fn shl(self, other: Integer) -> Integer {
+        check_compatible(self.0, other.0, "operation");
+        if other.1 >= self.0.bits() as u64 {
+            return Integer(self.0, 0);
+        }
+        Integer(self.0, typed!(cast => self.0, false, {
+            (cast(self.1) << other.1) as u64
+        }))
+    }
+
+    /// Shift right by `other` bits, filling in the sign bit if `signed`
+    /// is true and zeros otherwise.
+    pub fn shr(self, other: Integer, signed: bool) -> Integer {
+        check_compatible(self.0, other.0, "operation");
+        let bits = self.0.bits() as u64;
+        if other.1 >= bits && !signed {
+            return Integer(self.0, 0);
+        }
+        let shift = other.1.min(bits - 1);
+        let value = typed!(cast => self.0, signed, { (cast(self.1) >> shift) as u64 });
+        Integer(self.0, value).cast(self.0, false)
+    }
 
     pub fn bitnot(self) -> Integer {
         Integer(self.0, typed!(cast => self.0, false, { !cast(self.1) as u64 }))
@@ -186,8 +225,10 @@ impl DataType {
         match self {
             N8 => "byte",
             N16 => "word",
-            N32 => "dword",
-            N64 => "qword",
+            N32 | F32 => "dword",
+            N64 | F64 => "qword",
+            N128 => "xmmword",
+            N256 => "ymmword",
         }
     }
 
@@ -196,8 +237,10 @@ impl DataType {
         match self {
             N8 => 1,
             N16 => 2,
-            N32 => 4,
-            N64 => 8,
+            N32 | F32 => 4,
+            N64 | F64 => 8,
+            N128 => 16,
+            N256 => 32,
         }
     }
 
@@ -205,6 +248,39 @@ impl DataType {
     pub fn bits(&self) -> usize {
         self.bytes() * 8
     }
+
+    /// The data type with the given number of bits if there is one.
+    pub fn from_bits(bits: usize) -> Option<DataType> {
+        match bits {
+            8 => Some(N8),
+            16 => Some(N16),
+            32 => Some(N32),
+            64 => Some(N64),
+            128 => Some(N128),
+            256 => Some(N256),
+            _ => None,
+        }
+    }
+
+    /// The float data type with the given number of bits if there is one.
+    pub fn float_from_bits(bits: usize) -> Option<DataType> {
+        match bits {
+            32 => Some(F32),
+            64 => Some(F64),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a floating-point type.
+    pub fn is_float(&self) -> bool {
+        *self == F32 || *self == F64
+    }
+
+    /// Whether this is one of the vector types wider than 64 bits, which
+    /// cannot be represented concretely in full.
+    pub fn is_wide(&self) -> bool {
+        self.bits() > 64
+    }
 }
 
 impl Display for DataType {
@@ -214,6 +290,10 @@ impl Display for DataType {
             N16 => "n16",
             N32 => "n32",
             N64 => "n64",
+            N128 => "n128",
+            N256 => "n256",
+            F32 => "f32",
+            F64 => "f64",
         })
     }
 }
@@ -250,11 +330,23 @@ mod tests {
 
         assert_eq!(Integer(N8, 130).flagged_sub(Integer(N8, 10)).1,
             Flags { zero: false, sign: false, overflow: true });
+
+        assert_eq!(Integer(N8, 100).flagged_add(Integer(N8, 100)).0.1, 200);
     }
 
     #[test]
     fn bytes() {
         assert_eq!(Integer(N8, 1).to_bytes(), vec![1]);
         assert_eq!(Integer(N16, 0xabef).to_bytes(), vec![0xef, 0xab]);
+        assert_eq!(Integer(N128, 0x1).to_bytes().len(), 16);
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(Integer(N8, 0x81).shl(Integer(N8, 1)), Integer(N8, 0x02));
+        assert_eq!(Integer(N8, 0x81).shr(Integer(N8, 1), false), Integer(N8, 0x40));
+        assert_eq!(Integer(N8, 0x81).shr(Integer(N8, 1), true), Integer(N8, 0xc0));
+        assert_eq!(Integer(N32, 5).shl(Integer(N32, 32)), Integer(N32, 0));
+        assert_eq!(Integer(N128, 0xff).shl(Integer(N128, 8)), Integer(N128, 0xff00));
     }
 }
@@ -0,0 +1,503 @@
+//! Solver-free range and known-bits analysis of symbolic expressions.
+//!
+//! Both analyses overapproximate the values an expression can take, so they
+//! can cheaply decide many conditions before handing them to the solver.
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{SymExpr, SymCondition, DataType, Integer, Symbol};
+use SymExpr::*;
+use SymCondition::*;
+
+
+/// The bits of a value that are known to be zero or one.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct KnownBits {
+    pub data_type: DataType,
+    /// The bits known to be zero.
+    pub zeros: u64,
+    /// The bits known to be one.
+    pub ones: u64,
+}
+
+impl KnownBits {
+    /// No bit of the value is known.
+    pub fn unknown(data_type: DataType) -> KnownBits {
+        KnownBits { data_type, zeros: 0, ones: 0 }
+    }
+
+    /// All bits of the value are known.
+    pub fn constant(int: Integer) -> KnownBits {
+        let value = int.1 & mask(int.0);
+        KnownBits { data_type: int.0, zeros: !value & mask(int.0), ones: value }
+    }
+
+    /// The bits shared by all values of an unsigned interval, which are the
+    /// leading bits its bounds agree on.
+    pub fn from_interval(lower: Integer, upper: Integer) -> KnownBits {
+        let data_type = lower.0;
+        let known = mask(data_type) & !fill_right(lower.1 ^ upper.1);
+        KnownBits { data_type, zeros: !lower.1 & known, ones: lower.1 & known }
+    }
+
+    /// The value if all bits are known.
+    pub fn value(&self) -> Option<Integer> {
+        if self.zeros | self.ones == mask(self.data_type) {
+            Some(Integer(self.data_type, self.ones))
+        } else {
+            None
+        }
+    }
+
+    /// The smallest unsigned interval containing all values with these bits.
+    pub fn interval(&self) -> (Integer, Integer) {
+        let upper = !self.zeros & mask(self.data_type);
+        (Integer(self.data_type, self.ones), Integer(self.data_type, upper))
+    }
+
+    /// Whether no value can have both these bits and the other ones.
+    pub fn conflicts(&self, other: &KnownBits) -> bool {
+        (self.ones & other.zeros) | (self.zeros & other.ones) != 0
+    }
+
+    /// The bits known in both self and the other known bits.
+    pub fn join(&self, other: &KnownBits) -> KnownBits {
+        KnownBits {
+            data_type: self.data_type,
+            zeros: self.zeros & other.zeros,
+            ones: self.ones & other.ones,
+        }
+    }
+
+    /// The bits known in either self or the other known bits.
+    pub fn meet(&self, other: &KnownBits) -> KnownBits {
+        KnownBits {
+            data_type: self.data_type,
+            zeros: self.zeros | other.zeros,
+            ones: self.ones | other.ones,
+        }
+    }
+
+    /// The number of low bits that are known.
+    fn trailing_known(&self) -> u64 {
+        ((self.zeros | self.ones).trailing_ones() as u64).min(bits(self.data_type))
+    }
+}
+
+impl Display for KnownBits {
+    /// Displays the bits from the most significant one with `?` for the
+    /// unknown ones.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for bit in (0 .. bits(self.data_type)).rev() {
+            let symbol = if self.ones >> bit & 1 == 1 {
+                '1'
+            } else if self.zeros >> bit & 1 == 1 {
+                '0'
+            } else {
+                '?'
+            };
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+impl SymExpr {
+    /// Overapproximate the unsigned values of the expression by an inclusive
+    /// interval given the intervals of the symbols. Symbols without an
+    /// interval can take any value.
+    pub fn interval<S>(&self, symbols: &S) -> (Integer, Integer)
+    where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+        let data_type = self.data_type();
+        let (lower, upper) = self.bounds(symbols);
+        (Integer(data_type, lower), Integer(data_type, upper))
+    }
+
+    /// Determine the bits of the expression that are the same for all values
+    /// of the symbols within their intervals.
+    pub fn known_bits<S>(&self, symbols: &S) -> KnownBits
+    where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+        let data_type = self.data_type();
+        let max = mask(data_type);
+        let width = bits(data_type);
+        let from_interval = || {
+            let (lower, upper) = self.interval(symbols);
+            KnownBits::from_interval(lower, upper)
+        };
+
+        match self {
+            Int(int) => KnownBits::constant(*int),
+            Sym(_) => from_interval(),
+
+            // The low bits up to the first unknown one do not depend on any
+            // carries from unknown bits.
+            Add(a, b) | Sub(a, b) => {
+                let (a, b) = (a.known_bits(symbols), b.known_bits(symbols));
+                let low = low_mask(a.trailing_known().min(b.trailing_known()));
+                let value = match self {
+                    Add(..) => a.ones.wrapping_add(b.ones),
+                    _ => a.ones.wrapping_sub(b.ones),
+                } & low;
+                KnownBits { data_type, zeros: !value & low, ones: value }.meet(&from_interval())
+            },
+            Mul(a, b) => {
+                let trailing = |bits: KnownBits| (bits.zeros.trailing_ones() as u64).min(width);
+                let (a, b) = (a.known_bits(symbols), b.known_bits(symbols));
+                let zeros = low_mask((trailing(a) + trailing(b)).min(width));
+                KnownBits { data_type, zeros, ones: 0 }.meet(&from_interval())
+            },
+
+            BitAnd(a, b) => {
+                let (a, b) = (a.known_bits(symbols), b.known_bits(symbols));
+                KnownBits { data_type, zeros: a.zeros | b.zeros, ones: a.ones & b.ones }
+            },
+            BitOr(a, b) => {
+                let (a, b) = (a.known_bits(symbols), b.known_bits(symbols));
+                KnownBits { data_type, zeros: a.zeros & b.zeros, ones: a.ones | b.ones }
+            },
+            BitXor(a, b) => {
+                let (a, b) = (a.known_bits(symbols), b.known_bits(symbols));
+                let known = (a.zeros | a.ones) & (b.zeros | b.ones);
+                let value = a.ones ^ b.ones;
+                KnownBits { data_type, zeros: !value & known, ones: value & known }
+            },
+            BitNot(a) => {
+                let a = a.known_bits(symbols);
+                KnownBits { data_type, zeros: a.ones, ones: a.zeros }
+            },
+
+            // Shifts are only tracked for known shift amounts.
+            Shl(a, b) => match b.known_bits(symbols).value() {
+                Some(shift) if shift.1 >= width => KnownBits::constant(Integer(data_type, 0)),
+                Some(shift) => {
+                    let a = a.known_bits(symbols);
+                    KnownBits {
+                        data_type,
+                        zeros: ((a.zeros << shift.1) | low_mask(shift.1)) & max,
+                        ones: (a.ones << shift.1) & max,
+                    }
+                },
+                None => KnownBits::unknown(data_type),
+            },
+            Shr(a, b, signed) => match b.known_bits(symbols).value() {
+                Some(shift) if shift.1 >= width && !signed => {
+                    KnownBits::constant(Integer(data_type, 0))
+                },
+                Some(shift) => {
+                    let shift = shift.1.min(width - 1);
+                    let a = a.known_bits(symbols);
+                    let top = max & !(max >> shift);
+                    let sign = 1 << (width - 1);
+                    let fill = |sign_known: bool| if sign_known { top } else { 0 };
+                    KnownBits {
+                        data_type,
+                        zeros: (a.zeros >> shift) | fill(!signed || a.zeros & sign != 0),
+                        ones: (a.ones >> shift) | fill(*signed && a.ones & sign != 0),
+                    }
+                },
+                None => KnownBits::unknown(data_type),
+            },
+
+            Cast(a, _, signed) => {
+                let source = a.data_type();
+                let known = a.known_bits(symbols);
+                let (zeros, ones) = (known.zeros & max, known.ones & max);
+                if bits(source) >= width {
+                    return KnownBits { data_type, zeros, ones };
+                }
+
+                // Extended bits are zero or a copy of the sign bit.
+                let high = max & !mask(source);
+                let sign = 1 << (bits(source) - 1);
+                let (zeros, ones) = match signed {
+                    false => (zeros | high, ones),
+                    true if known.zeros & sign != 0 => (zeros | high, ones),
+                    true if known.ones & sign != 0 => (zeros, ones | high),
+                    true => (zeros, ones),
+                };
+                KnownBits { data_type, zeros, ones }
+            },
+            AsExpr(c, _) => match c.decide(symbols) {
+                Some(value) => KnownBits::constant(Integer::from_bool(value, data_type)),
+                None => KnownBits { data_type, zeros: max & !1, ones: 0 },
+            },
+            IfThenElse(c, a, b) => match c.decide(symbols) {
+                Some(true) => a.known_bits(symbols),
+                Some(false) => b.known_bits(symbols),
+                None => a.known_bits(symbols).join(&b.known_bits(symbols)),
+            },
+        }
+    }
+
+    /// The unsigned bounds of the expression as plain numbers.
+    fn bounds<S>(&self, symbols: &S) -> (u64, u64)
+    where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+        let data_type = self.data_type();
+        let max = mask(data_type);
+        let width = bits(data_type);
+        let full = (0, max);
+
+        // Results exceeding the type wrap around and may take any value.
+        let fit = |lower: u128, upper: u128| {
+            if upper <= max as u128 { (lower as u64, upper as u64) } else { full }
+        };
+
+        match self {
+            Int(int) => (int.1 & max, int.1 & max),
+            Sym(sym) => match symbols(*sym) {
+                Some((lower, upper)) if lower.1 <= upper.1 => (lower.1 & max, upper.1 & max),
+                _ => full,
+            },
+
+            Add(a, b) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                fit(al as u128 + bl as u128, ah as u128 + bh as u128)
+            },
+            Sub(a, b) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                if al >= bh { (al - bh, ah - bl) } else { full }
+            },
+            Mul(a, b) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                match (ah as u128).checked_mul(bh as u128) {
+                    Some(upper) => fit(al as u128 * bl as u128, upper),
+                    None => full,
+                }
+            },
+
+            BitAnd(a, b) => {
+                let ((_, ah), (_, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                (0, ah.min(bh))
+            },
+            BitOr(a, b) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                (al.max(bl), fill_right(ah | bh))
+            },
+            BitXor(a, b) => {
+                let ((_, ah), (_, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                (0, fill_right(ah | bh))
+            },
+            BitNot(a) => {
+                let (al, ah) = a.bounds(symbols);
+                (max - ah, max - al)
+            },
+
+            Shl(a, b) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                if bh >= width {
+                    full
+                } else {
+                    fit((al as u128) << bl, (ah as u128) << bh)
+                }
+            },
+            Shr(a, b, signed) => {
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                let shift = |value: u64, by: u64| if by >= width { 0 } else { value >> by };
+                // Arithmetic shifts of non-negative values are logical ones.
+                if *signed && ah > max >> 1 {
+                    full
+                } else {
+                    (shift(al, bh), shift(ah, bl))
+                }
+            },
+
+            Cast(a, _, signed) => {
+                let source = a.data_type();
+                let (al, ah) = a.bounds(symbols);
+                if bits(source) <= width {
+                    let positive = max_signed(source);
+                    if !signed || ah <= positive {
+                        (al, ah)
+                    } else if al > positive {
+                        let extend = |value| Integer(source, value).cast(data_type, true).1 & max;
+                        (extend(al), extend(ah))
+                    } else {
+                        full
+                    }
+                } else if ah - al <= max && al & max <= ah & max {
+                    // The truncated bounds stay ordered if no wrap around occurs.
+                    (al & max, ah & max)
+                } else {
+                    full
+                }
+            },
+            AsExpr(c, _) => match c.decide(symbols) {
+                Some(value) => (value as u64, value as u64),
+                None => (0, 1),
+            },
+            IfThenElse(c, a, b) => match c.decide(symbols) {
+                Some(true) => a.bounds(symbols),
+                Some(false) => b.bounds(symbols),
+                None => {
+                    let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                    (al.min(bl), ah.max(bh))
+                },
+            },
+        }
+    }
+
+    /// The bounds of the expression as signed or unsigned numbers.
+    fn numeric_bounds<S>(&self, symbols: &S, signed: bool) -> (i128, i128)
+    where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+        let data_type = self.data_type();
+        let (lower, upper) = self.bounds(symbols);
+        if !signed {
+            return (lower as i128, upper as i128);
+        }
+
+        let positive = max_signed(data_type);
+        let extend = |value| Integer(data_type, value).cast(DataType::N64, true).1 as i64 as i128;
+        if upper <= positive || lower > positive {
+            (extend(lower), extend(upper))
+        } else {
+            (-(positive as i128) - 1, positive as i128)
+        }
+    }
+}
+
+impl SymCondition {
+    /// Decide the condition without a solver given the intervals of the
+    /// symbols. Returns `None` if the analysis is not precise enough to
+    /// determine its value.
+    pub fn decide<S>(&self, symbols: &S) -> Option<bool>
+    where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+        match self {
+            Bool(value) => Some(*value),
+            And(a, b) => match (a.decide(symbols), b.decide(symbols)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Or(a, b) => match (a.decide(symbols), b.decide(symbols)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Not(a) => a.decide(symbols).map(|value| !value),
+
+            Equal(a, b) => {
+                if a == b {
+                    return Some(true);
+                }
+                let ((al, ah), (bl, bh)) = (a.bounds(symbols), b.bounds(symbols));
+                if ah < bl || bh < al {
+                    Some(false)
+                } else if al == ah && bl == bh {
+                    Some(true)
+                } else if a.known_bits(symbols).conflicts(&b.known_bits(symbols)) {
+                    Some(false)
+                } else {
+                    None
+                }
+            },
+
+            LessThan(a, b, signed) => compare(a, b, *signed, symbols),
+            LessEqual(a, b, signed) => compare(b, a, *signed, symbols).map(|value| !value),
+            GreaterThan(a, b, signed) => compare(b, a, *signed, symbols),
+            GreaterEqual(a, b, signed) => compare(a, b, *signed, symbols).map(|value| !value),
+        }
+    }
+}
+
+/// Decide whether `a` is less than `b`.
+fn compare<S>(a: &SymExpr, b: &SymExpr, signed: bool, symbols: &S) -> Option<bool>
+where S: Fn(Symbol) -> Option<(Integer, Integer)> {
+    let (al, ah) = a.numeric_bounds(symbols, signed);
+    let (bl, bh) = b.numeric_bounds(symbols, signed);
+    if ah < bl {
+        Some(true)
+    } else if al >= bh {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The number of bits tracked for values of the data type. Values of the
+/// wide types are limited to 64 bits like their integers.
+fn bits(data_type: DataType) -> u64 {
+    (data_type.bits() as u64).min(64)
+}
+
+/// The mask of the bits tracked for the data type.
+fn mask(data_type: DataType) -> u64 {
+    low_mask(bits(data_type))
+}
+
+/// The largest positive signed value of the data type.
+fn max_signed(data_type: DataType) -> u64 {
+    mask(data_type) >> 1
+}
+
+/// The mask of the lowest `count` bits.
+fn low_mask(count: u64) -> u64 {
+    if count >= 64 { u64::MAX } else { (1 << count) - 1 }
+}
+
+/// Set all bits below the highest set bit.
+fn fill_right(value: u64) -> u64 {
+    if value == 0 { 0 } else { u64::MAX >> value.leading_zeros() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::DataType::*;
+
+    fn n(x: u64) -> SymExpr { SymExpr::from_int(N64, x) }
+    fn x() -> SymExpr { Sym(Symbol(N64, "stdin", 0)) }
+    fn y() -> SymExpr { Sym(Symbol(N8, "stdin", 1)) }
+
+    fn symbols(symbol: Symbol) -> Option<(Integer, Integer)> {
+        match symbol {
+            Symbol(N64, "stdin", 0) => Some((Integer(N64, 0x10), Integer(N64, 0x1f))),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = |expr: SymExpr| {
+            let (lower, upper) = expr.interval(&symbols);
+            (lower.1, upper.1)
+        };
+
+        assert_eq!(interval(x().add(n(8))), (0x18, 0x27));
+        assert_eq!(interval(x().sub(n(0x10))), (0, 0xf));
+        assert_eq!(interval(n(8).sub(x())), (0, u64::MAX));
+        assert_eq!(interval(x().mul(n(4))), (0x40, 0x7c));
+        assert_eq!(interval(x().bitand(n(0x3))), (0, 0x3));
+        assert_eq!(interval(y().cast(N32, false)), (0, 0xff));
+        assert_eq!(interval(y().cast(N32, true)), (0, 0xffff_ffff));
+        assert_eq!(interval(x().shr(n(4), false)), (1, 1));
+
+        let select = x().less_than(n(0x20), false).if_then_else(n(1), x());
+        assert_eq!(interval(select), (1, 1));
+
+        assert_eq!(x().greater_equal(n(0x10), false).decide(&symbols), Some(true));
+        assert_eq!(x().equal(n(0x30)).decide(&symbols), Some(false));
+        assert_eq!(x().equal(n(0x18)).decide(&symbols), None);
+        assert_eq!(x().less_than(n(0), true).decide(&symbols), Some(false));
+        assert_eq!(y().less_than(SymExpr::from_int(N8, 0), true).decide(&symbols), None);
+    }
+
+    #[test]
+    fn known_bits() {
+        let known = |expr: SymExpr| expr.known_bits(&symbols).to_string();
+
+        let byte = y().bitand(SymExpr::from_int(N8, 0xf0)).bitor(SymExpr::from_int(N8, 0x01));
+        assert_eq!(known(byte.clone()), "????0001");
+        assert_eq!(known(byte.clone().shl(SymExpr::from_int(N8, 2))), "??000100");
+        assert_eq!(known(byte.clone().cast(N16, false)), "00000000????0001");
+        assert_eq!(known(byte.clone().bitnot()), "????1110");
+        assert_eq!(known(byte.clone().add(SymExpr::from_int(N8, 1))), "????0010");
+
+        let scaled = y().cast(N64, false).mul(n(8));
+        assert!(scaled.known_bits(&symbols).to_string().ends_with("000"));
+        assert_eq!(x().known_bits(&symbols).interval(), (Integer(N64, 0x10), Integer(N64, 0x1f)));
+
+        let odd = y().bitor(SymExpr::from_int(N8, 1));
+        assert_eq!(odd.equal(SymExpr::from_int(N8, 0x40)).decide(&symbols), Some(false));
+    }
+}
@@ -2,29 +2,100 @@
 
 use std::collections::HashMap;
 use std::fmt::{self, Display, Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+#[cfg(feature = "fs")]
+use std::time::Instant;
 use z3::Context as Z3Context;
 use z3::ast::Ast;
 
-use super::{SymExpr, SymCondition, SymDynamic, Symbol, Integer, DataType};
+use super::{SymExpr, SymCondition, SymDynamic, Symbol, Integer, DataType, Traversed};
 use SymExpr::*;
 use SymCondition::*;
-use DataType::*;
 
 
 /// Solves and simplifies conditions and expressions using Z3.
 pub struct Solver {
     ctx: Z3Context,
+    /// The random seed of the queries, which decides among the models of
+    /// satisfiable conditions.
+    seed: u32,
 }
 
 /// A reference-counted condition solver.
 pub type SharedSolver = std::rc::Rc<Solver>;
 
+/// The number of queries made by all solvers and the time spent in them.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SolverStats {
+    pub queries: u64,
+    pub time: Duration,
+}
+
+/// The totals of all queries since the start of the process.
+static QUERIES: AtomicU64 = AtomicU64::new(0);
+static QUERY_NANOS: AtomicU64 = AtomicU64::new(0);
+
+impl SolverStats {
+    /// The totals since the start of the process, including queries of other
+    /// analyses running at the same time.
+    pub fn total() -> SolverStats {
+        SolverStats {
+            queries: QUERIES.load(Ordering::Relaxed),
+            time: Duration::from_nanos(QUERY_NANOS.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// The queries made between an earlier total and this one.
+    pub fn since(self, earlier: SolverStats) -> SolverStats {
+        SolverStats {
+            queries: self.queries - earlier.queries,
+            time: self.time - earlier.time,
+        }
+    }
+}
+
+/// Counts and traces a solver query until it is dropped.
+struct Query {
+    _trace: crate::trace::Guard,
+    /// When the query started, which is not measured without the `fs`
+    /// feature, since WebAssembly has no clock without a host.
+    #[cfg(feature = "fs")]
+    started: Instant,
+}
+
+impl Query {
+    fn new(kind: &'static str) -> Query {
+        Query {
+            _trace: crate::trace::query(kind),
+            #[cfg(feature = "fs")]
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        QUERIES.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "fs")]
+        QUERY_NANOS.fetch_add(self.started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
 impl Solver {
     /// Create a new condition solver with it's own Z3 context.
     pub fn new() -> Solver {
+        Solver::with_seed(0)
+    }
+
+    /// Create a new condition solver whose queries use the random seed, so
+    /// that the same seed yields the same models. Z3 only takes 32-bit seeds,
+    /// so the upper half of larger seeds is folded into the lower one and
+    /// different seeds can yield the same models.
+    pub fn with_seed(seed: u64) -> Solver {
         let config = z3::Config::new();
         let ctx = Z3Context::new(&config);
-        Solver { ctx }
+        Solver { ctx, seed: (seed ^ (seed >> 32)) as u32 }
     }
 
     /// Simplify an expression.
@@ -37,6 +108,7 @@ impl Solver {
         }
 
         crate::timings::start("simplify");
+        let _query = Query::new("simplify");
 
         let z3_expr = expr.to_z3_ast(&self.ctx);
         let params = self.params();
@@ -52,15 +124,16 @@ impl Solver {
 
     /// Simplify a condition.
     pub fn simplify_condition(&self, cond: &SymCondition) -> SymCondition {
-        // Just a bool has no need to be simplified.
-        if let Bool(x) = cond {
-            return Bool(*x);
+        // Conditions decided by their ranges need no solver.
+        if let Some(x) = cond.decide(&|_| None) {
+            return Bool(x);
         }
 
         crate::timings::start("simplify");
+        let _query = Query::new("simplify");
 
         let z3_cond = cond.to_z3_ast(&self.ctx);
-        let solver = z3::Solver::new(&self.ctx);
+        let solver = self.solver();
 
         crate::timings::start("check-sat");
         solver.assert(&z3_cond);
@@ -84,17 +157,97 @@ impl Solver {
 
     /// Check whether two expressions are possibly equal.
     pub fn check_equal_sat(&self, a: &SymExpr, b: &SymExpr) -> bool {
+        let equal = SymCondition::Equal(Box::new(a.clone()), Box::new(b.clone()));
+        if let Some(sat) = equal.decide(&|_| None) {
+            return sat;
+        }
+
+        let _query = Query::new("check-equal-sat");
         crate::timings::with("check-equal-sat", || {
         crate::timings::with("check-sat", || {
             let z3_a = a.to_z3_ast(&self.ctx);
             let z3_b = b.to_z3_ast(&self.ctx);
-            let solver = z3::Solver::new(&self.ctx);
+            let solver = self.solver();
             solver.assert(&z3_a._eq(&z3_b));
             solver.check()
         })
         })
     }
 
+    /// Find up to `count` distinct possible values of an expression.
+    pub fn examples(&self, expr: &SymExpr, count: usize) -> Vec<Integer> {
+        self.examples_where(expr, &SymCondition::TRUE, count)
+    }
+
+    /// Find up to `count` distinct values the expression can take while the
+    /// condition holds.
+    pub fn examples_where(&self, expr: &SymExpr, cond: &SymCondition, count: usize)
+    -> Vec<Integer> {
+        let _query = Query::new("examples");
+        crate::timings::with("check-sat", || {
+            let data_type = expr.data_type();
+            let z3_expr = expr.to_z3_ast(&self.ctx);
+            let value = z3::ast::BV::new_const(&self.ctx, "example", data_type.bits() as u32);
+            let solver = self.solver();
+            solver.assert(&z3_expr._eq(&value));
+            solver.assert(&cond.to_z3_ast(&self.ctx));
+
+            let mut examples = Vec::new();
+            while examples.len() < count && solver.check() {
+                let model = solver.get_model();
+                let example = match model.eval(&value).and_then(|value| value.as_u64()) {
+                    Some(example) => example,
+                    None => break,
+                };
+
+                // Exclude this value from the next models.
+                let z3_example = z3::ast::BV::from_u64(&self.ctx, example, data_type.bits() as u32);
+                solver.assert(&value._eq(&z3_example).not());
+                examples.push(Integer(data_type, example));
+            }
+            examples
+        })
+    }
+
+    /// Find values for the symbols of a condition under which it holds or
+    /// `None` if it is unsatisfiable. Symbols the condition does not depend
+    /// on are zero.
+    pub fn model(&self, cond: &SymCondition) -> Option<HashMap<Symbol, Integer>> {
+        let mut symbols = Vec::new();
+        cond.traverse(&mut |node| if let Traversed::Expr(Sym(symbol)) = node {
+            symbols.push(*symbol);
+        });
+
+        let _query = Query::new("model");
+        crate::timings::with("check-sat", || {
+            let solver = self.solver();
+            solver.assert(&cond.to_z3_ast(&self.ctx));
+            if !solver.check() {
+                return None;
+            }
+
+            let model = solver.get_model();
+            let values = symbols.into_iter()
+                .map(|symbol| {
+                    let bits = symbol.0.bits() as u32;
+                    let constant = z3::ast::BV::new_const(&self.ctx, symbol.to_string(), bits);
+                    let value = model.eval(&constant).and_then(|value| value.as_u64()).unwrap_or(0);
+                    (symbol, Integer(symbol.0, value))
+                })
+                .collect();
+            Some(values)
+        })
+    }
+
+    /// A fresh Z3 solver with the random seed.
+    fn solver(&self) -> z3::Solver {
+        let solver = z3::Solver::new(&self.ctx);
+        let mut params = z3::Params::new(&self.ctx);
+        params.set_u32("random_seed", self.seed);
+        solver.set_params(&params);
+        solver
+    }
+
     /// Builds the default simplifaction params.
     fn params(&self) -> z3::Params {
         let mut params = z3::Params::new(&self.ctx);
@@ -144,17 +297,7 @@ impl<'a> Z3Parser<'a> {
         self.skip_white();
         match self.peek() {
             Some('(') => self.parse_bv_func(),
-            Some('#') => {
-                let (bits, value) = self.parse_bv_immediate()?;
-                let data_type = match bits {
-                    8 => N8,
-                    16 => N16,
-                    32 => N32,
-                    64 => N64,
-                    s => return err(format!("invalid bitvec immediate size: {}", s)),
-                };
-                Ok(Int(Integer(data_type, value)))
-            },
+            Some('#') => self.parse_bv_immediate(),
             Some('|') => self.parse_bv_symbol(),
             Some(_) => match self.parse_variable()? {
                 SymDynamic::Expr(expr) => Ok(expr),
@@ -185,6 +328,18 @@ impl<'a> Z3Parser<'a> {
 
                         bit_extend(bits, right, kind == "sign_extend")?
                     },
+                    "extract" => {
+                        self.skip_white();
+                        let high = self.parse_number()?;
+                        self.skip_white();
+                        let low = self.parse_number()?;
+
+                        self.expect(')')?;
+                        self.skip_white();
+                        let right = self.parse_bitvec()?;
+
+                        extract(high, low, right)?
+                    },
                     _ => return err("unknown _ function kind"),
                 }
             },
@@ -194,26 +349,26 @@ impl<'a> Z3Parser<'a> {
             "bvmul" => self.parse_bv_varop(SymExpr::mul)?,
             "bvand" => self.parse_bv_varop(SymExpr::bitand)?,
             "bvor"  => self.parse_bv_varop(SymExpr::bitor)?,
+            "bvxor" => self.parse_bv_varop(SymExpr::bitxor)?,
             "bvnot" => self.parse_bitvec()?.bitnot(),
+            "bvshl" => { let (a, b) = self.parse_bv_pair()?; a.shl(b) },
+            "bvlshr" => { let (a, b) = self.parse_bv_pair()?; a.shr(b, false) },
+            "bvashr" => { let (a, b) = self.parse_bv_pair()?; a.shr(b, true) },
 
             "ite" => {
                 let condition = self.parse_bool()?;
-                condition.if_then_else(self.parse_bitvec()?, self.parse_bitvec()?)
+                let (a, b) = self.parse_bv_pair()?;
+                condition.if_then_else(a, b)
             },
 
             "concat" => {
+                let mut parts = vec![self.parse_bitvec()?];
                 self.skip_white();
-                if self.peek() != Some('#') {
-                    return err("can only use in concat with left-hand zero immediate");
-                }
-
-                let (bits, value) = self.parse_bv_immediate()?;
-                if value != 0 {
-                    return err("unhandled concat: non-zero left-hand value");
+                while self.peek() != Some(')') {
+                    parts.push(self.parse_bitvec()?);
+                    self.skip_white();
                 }
-
-                let right = self.parse_bitvec()?;
-                bit_extend(bits, right, false)?
+                concat(parts)?
             },
 
             _ => return err(format!("unknown bitvec function: {:?}", func)),
@@ -226,19 +381,31 @@ impl<'a> Z3Parser<'a> {
     /// Parse a bitvector function with variable number of arguments.
     fn parse_bv_varop<F>(&mut self, op: F) -> ParseResult<SymExpr>
     where F: Fn(SymExpr, SymExpr) -> SymExpr {
-        let mut expr = op(self.parse_bitvec()?, self.parse_bitvec()?);
+        let (a, b) = self.parse_bv_pair()?;
+        let mut expr = op(a, b);
 
         self.skip_white();
         while self.peek() != Some(')') {
-            expr = op(expr, self.parse_bitvec()?);
+            let (a, b) = unify(expr, self.parse_bitvec()?);
+            expr = op(a, b);
             self.skip_white();
         }
 
         Ok(expr)
     }
 
+    /// Parse two bitvectors which are operands of the same operation.
+    fn parse_bv_pair(&mut self) -> ParseResult<(SymExpr, SymExpr)> {
+        let a = self.parse_bitvec()?;
+        let b = self.parse_bitvec()?;
+        Ok(unify(a, b))
+    }
+
     /// Parse a bitvector immediate value.
-    fn parse_bv_immediate(&mut self) -> ParseResult<(usize, u64)> {
+    ///
+    /// Values of the wide vector types which do not fit into 64 bits are
+    /// assembled from 64-bit chunks.
+    fn parse_bv_immediate(&mut self) -> ParseResult<SymExpr> {
         self.expect('#')?;
 
         enum Kind { Hex, Bin }
@@ -250,15 +417,30 @@ impl<'a> Z3Parser<'a> {
 
         let word = self.parse_word_while(|c| c.is_digit(radix));
 
-        let bits = word.len() * match kind {
+        let digit_bits = match kind {
             Kind::Hex => 4,
             Kind::Bin => 1,
         };
 
-        let value = u64::from_str_radix(word, radix)
-            .map_err(|_| "invalid immediate")?;
+        let bits = word.len() * digit_bits;
+        let data_type = match DataType::from_bits(bits) {
+            Some(data_type) => data_type,
+            None => return err(format!("invalid bitvec immediate size: {}", bits)),
+        };
+
+        let mut expr = Int(Integer(data_type, 0));
+        for (i, chunk) in word.as_bytes().rchunks(64 / digit_bits).enumerate() {
+            let chunk = std::str::from_utf8(chunk).map_err(|_| "invalid immediate")?;
+            let value = u64::from_str_radix(chunk, radix).map_err(|_| "invalid immediate")?;
+            if i == 0 {
+                expr = Int(Integer(data_type, value));
+            } else if value != 0 {
+                let shift = Int(Integer(data_type, 64 * i as u64));
+                expr = expr.bitor(Int(Integer(data_type, value)).shl(shift));
+            }
+        }
 
-        Ok((bits, value))
+        Ok(expr)
     }
 
     /// Parse a bitvector symbol.
@@ -268,6 +450,17 @@ impl<'a> Z3Parser<'a> {
             "mem" => "mem",
             "reg" => "reg",
             "stdin" => "stdin",
+            "float" => "float",
+            "unknown" => "unknown",
+            "tsc" => "tsc",
+            "rand" => "rand",
+            "cpuid" => "cpuid",
+            "heap" => "heap",
+            "env" => "env",
+            "file" => "file",
+            "net" => "net",
+            "netlen" => "netlen",
+            "mmio" => "mmio",
             "T" => "T",
             s => return err(format!("invalid space name for symbol: {:?}", s)),
         };
@@ -275,18 +468,24 @@ impl<'a> Z3Parser<'a> {
         let index = self.parse_number()?;
 
         self.expect(':')?;
-        self.expect('n')?;
-        let data_type = match (self.next(), self.next()) {
-            (Some('8'), Some('|')) => N8,
-            (Some('1'), Some('6')) => N16,
-            (Some('3'), Some('2')) => N32,
-            (Some('6'), Some('4')) => N64,
+        let float = match self.next() {
+            Some('n') => false,
+            Some('f') => true,
             _ => return err("expected data type for symbol"),
         };
+        let bits = self.parse_number()?;
+        let data_type = if float {
+            DataType::float_from_bits(bits)
+        } else {
+            DataType::from_bits(bits)
+        };
 
-        if data_type != N8 {
-            self.expect('|')?;
-        }
+        let data_type = match data_type {
+            Some(data_type) => data_type,
+            None => return err("expected data type for symbol"),
+        };
+
+        self.expect('|')?;
 
         Ok(Sym(Symbol(data_type, space, index)))
     }
@@ -313,12 +512,12 @@ impl<'a> Z3Parser<'a> {
         let cond = match func {
             "let" => { self.parse_let_bindings()?; self.parse_bool()? },
 
-            "=" => self.parse_bitvec()?.equal(self.parse_bitvec()?),
+            "=" => { let (a, b) = self.parse_bv_pair()?; a.equal(b) },
 
-            "bvult" | "bvslt" => self.parse_bitvec()?.less_than(self.parse_bitvec()?, signed()),
-            "bvule" | "bvsle" => self.parse_bitvec()?.less_equal(self.parse_bitvec()?, signed()),
-            "bvugt" | "bvsgt" => self.parse_bitvec()?.greater_than(self.parse_bitvec()?, signed()),
-            "bvuge" | "bvsge" => self.parse_bitvec()?.greater_equal(self.parse_bitvec()?, signed()),
+            "bvult" | "bvslt" => { let (a, b) = self.parse_bv_pair()?; a.less_than(b, signed()) },
+            "bvule" | "bvsle" => { let (a, b) = self.parse_bv_pair()?; a.less_equal(b, signed()) },
+            "bvugt" | "bvsgt" => { let (a, b) = self.parse_bv_pair()?; a.greater_than(b, signed()) },
+            "bvuge" | "bvsge" => { let (a, b) = self.parse_bv_pair()?; a.greater_equal(b, signed()) },
 
             "and" => self.parse_bool()?.and(self.parse_bool()?),
             "or" => self.parse_bool()?.or(self.parse_bool()?),
@@ -449,14 +648,67 @@ type ParseResult<T> = Result<T, String>;
 
 /// Extend `right` by `bits` bits (ones if signed, zeros otherwise).
 fn bit_extend(bits: usize, right: SymExpr, signed: bool) -> ParseResult<SymExpr> {
-    match bits + right.data_type().bits() {
-        16 => Ok(right.cast(N16, signed)),
-        32 => Ok(right.cast(N32, signed)),
-        64 => Ok(right.cast(N64, signed)),
-        s => err(format!("unhandled bit extension: invalid target size {}", s)),
+    match DataType::from_bits(bits + right.data_type().bits()) {
+        Some(data_type) => Ok(right.cast(data_type, signed)),
+        None => err(format!("unhandled bit extension: invalid target size {}",
+            bits + right.data_type().bits())),
     }
 }
 
+/// Z3 does not distinguish between integers and floats of the same width,
+/// so immediates are retyped to match the other operand.
+fn unify(a: SymExpr, b: SymExpr) -> (SymExpr, SymExpr) {
+    match (a, b) {
+        (Int(Integer(t, x)), b) if t != b.data_type() && t.bits() == b.data_type().bits() => {
+            let data_type = b.data_type();
+            (Int(Integer(data_type, x)), b)
+        },
+        (a, Int(Integer(t, x))) if t != a.data_type() && t.bits() == a.data_type().bits() => {
+            let data_type = a.data_type();
+            (a, Int(Integer(data_type, x)))
+        },
+        (a, b) => (a, b),
+    }
+}
+
+/// Extract the bits `low ..= high` from `right`.
+fn extract(high: usize, low: usize, right: SymExpr) -> ParseResult<SymExpr> {
+    let data_type = match DataType::from_bits(high + 1 - low) {
+        Some(data_type) => data_type,
+        None => return err(format!("unhandled extract: invalid target size {}", high + 1 - low)),
+    };
+
+    let shifted = if low == 0 {
+        right
+    } else {
+        let shift = Int(Integer(right.data_type(), low as u64));
+        right.shr(shift, false)
+    };
+
+    Ok(shifted.cast(data_type, false))
+}
+
+/// Concatenate the parts, the first part ending up in the highest bits.
+fn concat(parts: Vec<SymExpr>) -> ParseResult<SymExpr> {
+    let bits = parts.iter().map(|part| part.data_type().bits()).sum();
+    let data_type = match DataType::from_bits(bits) {
+        Some(data_type) => data_type,
+        None => return err(format!("unhandled concat: invalid target size {}", bits)),
+    };
+
+    let mut expr = Int(Integer(data_type, 0));
+    for part in parts {
+        let shift = Int(Integer(data_type, part.data_type().bits() as u64));
+        let part = part.cast(data_type, false);
+        expr = match expr {
+            Int(Integer(_, 0)) => part,
+            expr => expr.shl(shift).bitor(part),
+        };
+    }
+
+    Ok(expr)
+}
+
 /// Fast way to make an error.
 fn err<T, S: Into<String>>(message: S) -> ParseResult<T> {
     Err(message.into())
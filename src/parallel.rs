@@ -0,0 +1,82 @@
+//! Running independent parts of an analysis on multiple threads.
+//!
+//! Without the `parallel` feature everything runs on the calling thread.
+//! Timings of parts that run in parallel may overlap each other.
+
+
+/// The number of threads to use for a configured number (0 for one per core).
+#[cfg(feature = "parallel")]
+pub(crate) fn threads(configured: usize) -> usize {
+    match configured {
+        0 => rayon::current_num_threads(),
+        count => count,
+    }
+}
+
+/// The number of threads to use, which is always one without the `parallel`
+/// feature.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn threads(_configured: usize) -> usize {
+    1
+}
+
+/// Apply the function to all items, on the number of threads with the
+/// `parallel` feature (0 for one per core), and collect the results in order.
+#[cfg(feature = "parallel")]
+pub(crate) fn map<T, R, F>(threads: usize, items: &[T], f: F) -> Vec<R>
+where T: Sync, R: Send, F: Fn(&T) -> R + Sync {
+    use rayon::prelude::*;
+
+    if threads == 1 || items.len() < 2 {
+        return items.iter().map(f).collect();
+    }
+
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(|| items.par_iter().map(&f).collect()),
+        Err(_) => items.iter().map(f).collect(),
+    }
+}
+
+/// Apply the function to all items and collect the results in order.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map<T, R, F>(_threads: usize, items: &[T], f: F) -> Vec<R>
+where F: Fn(&T) -> R {
+    items.iter().map(f).collect()
+}
+
+/// The part of the paths of an exploration taken by one of several explorers.
+///
+/// All explorers follow the same paths up to a number of branches which
+/// yields a few subtrees per partition. The subtrees starting there are dealt
+/// out round-robin in the order they are reached, which has to be the same
+/// for all explorers.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Partition {
+    pub index: usize,
+    pub count: usize,
+    /// The number of subtrees dealt out so far.
+    subtrees: usize,
+}
+
+impl Partition {
+    /// The partitions of the paths among the number of explorers.
+    pub(crate) fn all(count: usize) -> Vec<Partition> {
+        (0 .. count).map(|index| Partition { index, count, subtrees: 0 }).collect()
+    }
+
+    /// The number of branches after which the paths are partitioned, such
+    /// that there are about four subtrees per partition.
+    pub(crate) fn split(&self) -> usize {
+        let mut split = 0;
+        while (1 << split) < 4 * self.count {
+            split += 1;
+        }
+        split
+    }
+
+    /// Deal out the next subtree and tell whether this explorer takes it.
+    pub(crate) fn takes(&mut self) -> bool {
+        self.subtrees += 1;
+        (self.subtrees - 1) % self.count == self.index
+    }
+}
@@ -0,0 +1,229 @@
+//! Decoding and semantics of instructions the crate does not know.
+//!
+//! Firmware tends to contain a few instructions the built-in decoder rejects,
+//! like the ones of custom coprocessors or undocumented opcodes. A decoder
+//! plugin is asked for every instruction the crate cannot decode or lift and
+//! describes it either by its micro operations or, if its semantics do not
+//! matter, by an opaque effect on registers. The decoder of a program (see
+//! `Program::with_decoder`) is used both for its code and for the code the
+//! control flow exploration decodes on the fly.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use crate::flow::{StorageLocation, ValueSource};
+use crate::ir::{Location, MemoryMapped, Microcode, MicroEncoder, MicroOperation};
+use crate::ir::{Temporary, Transfer};
+use crate::x86_64::{Instruction, Mnemoic, Operand, Prefixes, Register};
+
+
+/// Decodes instructions the crate does not know.
+pub trait DecoderPlugin: Send + Sync {
+    /// Decode the instruction at the start of the bytes if the plugin knows
+    /// it. The bytes go on to the end of the code.
+    fn decode(&self, bytes: &[u8]) -> Option<CustomInstruction>;
+}
+
+impl<F> DecoderPlugin for F where F: Fn(&[u8]) -> Option<CustomInstruction> + Send + Sync {
+    fn decode(&self, bytes: &[u8]) -> Option<CustomInstruction> {
+        self(bytes)
+    }
+}
+
+/// An instruction decoded by a plugin.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CustomInstruction {
+    /// The name the instruction is displayed with.
+    pub name: &'static str,
+    /// The number of bytes of the instruction.
+    pub len: u64,
+    /// The operands the instruction is displayed with.
+    pub operands: Vec<Operand>,
+    pub semantics: CustomSemantics,
+}
+
+/// What an instruction decoded by a plugin does.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CustomSemantics {
+    /// The microcode executing the instruction.
+    Microcode(Microcode),
+    /// An effect that is only known by the registers it reads and writes. The
+    /// written registers get unknown values depending on the read ones.
+    Opaque { reads: Vec<Register>, writes: Vec<Register> },
+}
+
+/// The built-in decoder, extended by a plugin if there is one.
+#[derive(Clone, Default)]
+pub struct Decoder {
+    pub plugin: Option<Arc<dyn DecoderPlugin>>,
+}
+
+impl Decoder {
+    /// A decoder asking the plugin for the instructions the crate does not
+    /// know.
+    pub fn with_plugin<P: DecoderPlugin + 'static>(plugin: P) -> Decoder {
+        Decoder { plugin: Some(Arc::new(plugin)) }
+    }
+
+    /// Decode the instruction at the start of the bytes and lift it to
+    /// microcode if the bytes form a valid and supported instruction or one
+    /// the plugin knows. Returns the length with the instruction.
+    pub fn decode(
+        &self,
+        bytes: &[u8],
+        encoder: &mut MicroEncoder,
+    ) -> Option<(u64, Instruction, Microcode)> {
+        let len = Instruction::length(bytes);
+        let builtin = bytes.get(.. len as usize)
+            .filter(|bytes| !bytes.is_empty())
+            .and_then(|bytes| Instruction::decode(bytes).ok())
+            .and_then(|instruction| {
+                let microcode = encoder.encode(&instruction).ok()?;
+                Some((len, instruction, microcode))
+            });
+        builtin.or_else(|| self.decode_custom(bytes))
+    }
+
+    /// The number of bytes up to the next plausible instruction boundary,
+    /// which is the next offset where an instruction decodes or the end of
+    /// the bytes.
+    pub fn undecodable(&self, bytes: &[u8]) -> u64 {
+        let mut encoder = MicroEncoder::new();
+        (1 .. bytes.len())
+            .find(|&offset| self.decode(&bytes[offset ..], &mut encoder).is_some())
+            .unwrap_or(bytes.len()) as u64
+    }
+
+    /// Decode the instruction at the start of the bytes with the plugin.
+    fn decode_custom(&self, bytes: &[u8]) -> Option<(u64, Instruction, Microcode)> {
+        let custom = self.plugin.as_ref()?.decode(bytes)?;
+        let bytes = bytes.get(.. custom.len as usize).filter(|bytes| !bytes.is_empty())?;
+        let instruction = Instruction {
+            bytes: bytes.to_vec(),
+            prefixes: Prefixes::default(),
+            mnemoic: Mnemoic::Custom(custom.name),
+            operands: custom.operands,
+        };
+        let microcode = match custom.semantics {
+            CustomSemantics::Microcode(microcode) => microcode,
+            CustomSemantics::Opaque { reads, writes } => opaque(&reads, &writes),
+        };
+        Some((custom.len, instruction, microcode))
+    }
+}
+
+/// The microcode storing unknown values depending on the read registers in
+/// the written ones.
+fn opaque(reads: &[Register], writes: &[Register]) -> Microcode {
+    let mut ops = Vec::new();
+    let mut flows = Vec::new();
+    for (index, &register) in writes.iter().enumerate() {
+        let data_type = register.data_type();
+        let target = Temporary(data_type, index);
+        ops.push(MicroOperation::Unknown { target });
+        ops.push(MicroOperation::Mov {
+            dest: Location::Direct(data_type, 1, register.address()),
+            src: Location::Temp(target),
+        });
+        for &read in reads {
+            let source = ValueSource::Storage(StorageLocation::Direct(read));
+            flows.push((source, StorageLocation::Direct(register)));
+        }
+    }
+    Microcode { ops, flows, transfer: Transfer::Next, atomic: false }
+}
+
+impl Debug for Decoder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("plugin", &self.plugin.is_some())
+            .finish()
+    }
+}
+
+/// Decoders are equal if they use the same plugin.
+impl PartialEq for Decoder {
+    fn eq(&self, other: &Decoder) -> bool {
+        match (&self.plugin, &other.plugin) {
+            (Some(a), Some(b)) => Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const (),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Eq for Decoder {}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Program;
+    use crate::flow::ControlFlowGraph;
+    use crate::math::{DataType, Integer};
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn plugin() {
+        // coproc rdx, rdi; setone rbx; mov eax, 60; syscall
+        let binary = vec![0x06, 0x07, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05];
+        let program = Program::from_code(0x1000, &binary, HashMap::new());
+        assert_eq!(program.undecoded, vec![(0x1000, vec![0x06, 0x07])]);
+
+        let one = Temporary(DataType::N64, 0);
+        let setone = Microcode {
+            ops: vec![
+                MicroOperation::Const { dest: one, constant: Integer(DataType::N64, 1) },
+                MicroOperation::Mov {
+                    dest: Location::Direct(DataType::N64, 1, Register::RBX.address()),
+                    src: Location::Temp(one),
+                },
+            ],
+            flows: vec![(
+                ValueSource::Const(Integer(DataType::N64, 1)),
+                StorageLocation::Direct(Register::RBX),
+            )],
+            transfer: Transfer::Next,
+            atomic: false,
+        };
+        let decoder = Decoder::with_plugin(move |bytes: &[u8]| match bytes.first()? {
+            0x06 => Some(CustomInstruction {
+                name: "coproc",
+                len: 1,
+                operands: vec![Operand::Direct(Register::RDX), Operand::Direct(Register::RDI)],
+                semantics: CustomSemantics::Opaque {
+                    reads: vec![Register::RDI],
+                    writes: vec![Register::RDX],
+                },
+            }),
+            0x07 => Some(CustomInstruction {
+                name: "setone",
+                len: 1,
+                operands: vec![Operand::Direct(Register::RBX)],
+                semantics: CustomSemantics::Microcode(setone.clone()),
+            }),
+            _ => None,
+        });
+        assert_ne!(decoder, Decoder::default());
+        assert_eq!(decoder, decoder.clone());
+
+        // Both custom instructions are part of the only block, where they
+        // change the registers.
+        let program = program.with_decoder(decoder);
+        assert!(program.undecoded.is_empty());
+        let names: Vec<String> = program.code.iter()
+            .map(|(_, _, instruction, _)| instruction.to_string())
+            .collect();
+        assert_eq!(names[.. 2], ["coproc rdx, rdi", "setone rbx"]);
+        assert_eq!(program.code[0].3.flows, vec![(
+            ValueSource::Storage(StorageLocation::Direct(Register::RDI)),
+            StorageLocation::Direct(Register::RDX),
+        )]);
+
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.nodes.len(), 1);
+        assert_eq!(cfg.blocks[&0x1000].code.len(), 4);
+        let lines = cfg.block_effect(0).lines();
+        assert!(lines.iter().any(|line| line == "rbx = 0x1:n64"));
+        assert!(lines.iter().any(|line| line.starts_with("rdx = ")));
+    }
+}
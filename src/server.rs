@@ -0,0 +1,462 @@
+//! A server keeping analyzed programs resident to answer queries about them.
+//!
+//! Requests and responses are JSON-RPC 2.0 messages with one message per
+//! line. The methods are:
+//! - `load` with a `path`: Load the program and build its graphs, returning
+//!   the `program` handle and the numbers of PDG `nodes` and `edges`.
+//! - `unload` with a `program`: Drop the program and its graphs.
+//! - `nodes` with a `program` and an `addr`: The PDG nodes at the address
+//!   with their `index` and the fields of `ProgramDependenceGraph::write_json`.
+//! - `slice` with a `program`, an `addr` and optionally a `direction` that is
+//!   `backward` (the default) or `forward`: The indices of the PDG nodes in
+//!   the slice.
+//! - `path_condition` with a `program`, an `addr` and optionally the address
+//!   `from` which to start, which defaults to the entry block: The condition
+//!   under which the block at the address depends on the start, or null if it
+//!   does not.
+//! - `report` with the parameters of `slice`, a `format` that is `html` or
+//!   `markdown`, optionally `model`, which defaults to false, and optionally
+//!   a `visual` object configuring how the sub-graph is rendered: The report
+//!   of the slice (see `ProgramDependenceGraph::write_report`) as a string.
+//!   The members of `visual` are those of `VisualConfig` by name, that is
+//!   `rankdir`, `splines`, `font`, `title_size`, `block_shape`,
+//!   `location_shape`, `max_label`, `elision` (`end` or `middle`), `simplify`,
+//!   `tooltips` and `legend`, with `bytes` and `lines` for the listing. The
+//!   `lines` are an object mapping addresses to source lines.
+//! - `export` with a `program`, a `graph` that is `cfg`, `ddg` or `pdg` and a
+//!   `format` that is `dot` or `json`: The graph as a string, with JSON only
+//!   available for the control flow and program dependence graph.
+//! - `equivalence` with a `program`, an `other` program and either the name
+//!   of a `function` in both or its `addr` in the first and `other_addr` in
+//!   the second: Whether the functions are `equivalent`, which is null if
+//!   that could not be decided for the `reason`. Different functions come
+//!   with the distinguishing `inputs` and the differing `outputs` with their
+//!   values in the `first` and the `second` function.
+//! - `impact` with a `program` and the `old` program it is a patched build
+//!   of: The impact of the patch (see `PatchImpact::write_json`).
+//!
+//! Addresses may be numbers or hexadecimal strings. A failing analysis
+//! answers with an error instead of taking the server down.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::Program;
+use crate::flow::{ControlFlowGraph, DataDependencyGraph, ProgramDependenceGraph};
+use crate::flow::{ReportConfig, ReportFormat, Elision};
+use crate::flow::{Equivalence, EquivalenceConfig, check_equivalence, check_equivalence_named};
+use crate::flow::PatchImpact;
+use crate::helper::{json_string, export_graph, panic_message};
+use crate::json::Json;
+
+/// The JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const ANALYSIS_FAILED: i64 = -32000;
+
+
+/// Answers queries about resident programs.
+#[derive(Default)]
+pub struct Server {
+    analyses: HashMap<u64, Analysis>,
+    next: u64,
+}
+
+/// A loaded program with its graphs.
+struct Analysis {
+    program: Program,
+    cfg: ControlFlowGraph,
+    ddg: DataDependencyGraph,
+    pdg: ProgramDependenceGraph,
+}
+
+impl Server {
+    /// Create a server without programs.
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Answer the requests read line by line until the input ends.
+    pub fn serve<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer a request. Notifications, which are requests without an `id`,
+    /// get no response.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let request = match Json::parse(request) {
+            Ok(request) => request,
+            Err(err) => return Some(respond(&Json::Null, Err(RpcError::new(PARSE_ERROR, err)))),
+        };
+
+        let method = match request.get("method").and_then(Json::as_str) {
+            Some(method) => method,
+            None => {
+                let id = request.get("id").unwrap_or(&Json::Null);
+                return Some(respond(id, Err(RpcError::new(INVALID_REQUEST, "missing method"))));
+            }
+        };
+
+        let params = request.get("params").cloned().unwrap_or(Json::Object(Vec::new()));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.call(method, &params)))
+            .unwrap_or_else(|payload| {
+                Err(RpcError::new(ANALYSIS_FAILED, panic_message(&*payload)))
+            });
+
+        request.get("id").map(|id| respond(id, result))
+    }
+
+    /// Run a method and return its result as JSON.
+    fn call(&mut self, method: &str, params: &Json) -> Result<String, RpcError> {
+        match method {
+            "load" => {
+                let path = params.get("path").and_then(Json::as_str)
+                    .ok_or_else(|| RpcError::param("path"))?;
+                let program = Program::new(path);
+                let cfg = ControlFlowGraph::new(&program);
+                let ddg = DataDependencyGraph::new(&cfg);
+                let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+
+                let handle = self.next;
+                self.next += 1;
+                let result = format!("{{\"program\": {}, \"nodes\": {}, \"edges\": {}}}",
+                                     handle, pdg.nodes.len(), pdg.edges.len());
+                self.analyses.insert(handle, Analysis { program, cfg, ddg, pdg });
+                Ok(result)
+            },
+
+            "unload" => {
+                let handle = number(params, "program")?;
+                self.analyses.remove(&handle)
+                    .map(|_| "true".to_string())
+                    .ok_or_else(|| RpcError::unknown_program(handle))
+            },
+
+            "nodes" => {
+                let pdg = &self.analysis(params)?.pdg;
+                let addr = number(params, "addr")?;
+                let nodes: Vec<String> = (0 .. pdg.nodes.len())
+                    .filter(|&index| pdg.nodes[index].addr() == addr)
+                    .map(|index| format!("{{\"index\": {}, {}", index, &pdg.node_json(index)[1 ..]))
+                    .collect();
+                Ok(format!("[{}]", nodes.join(", ")))
+            },
+
+            "slice" => {
+                let pdg = &self.analysis(params)?.pdg;
+                let slice = slice(pdg, params)?;
+                let indices: Vec<String> = slice.iter().map(usize::to_string).collect();
+                Ok(format!("[{}]", indices.join(", ")))
+            },
+
+            "report" => {
+                let analysis = self.analysis(params)?;
+                let slice = slice(&analysis.pdg, params)?;
+                let config = report_config(params)?;
+                let title = format!("{:#x}", number(params, "addr")?);
+                let mut report = Vec::new();
+                analysis.pdg.write_report(&mut report, &title, &slice, &analysis.cfg, &config)
+                    .map_err(|err| RpcError::new(ANALYSIS_FAILED, err))?;
+                Ok(json_string(&String::from_utf8_lossy(&report)))
+            },
+
+            "path_condition" => {
+                let analysis = self.analysis(params)?;
+                let pdg = &analysis.pdg;
+                let addr = number(params, "addr")?;
+                let from = match params.get("from") {
+                    Some(_) => number(params, "from")?,
+                    None => analysis.cfg.nodes[0].addr,
+                };
+
+                let source = pdg.node_at(from).ok_or_else(|| {
+                    RpcError::new(INVALID_PARAMS, format!("no block at {:#x}", from))
+                })?;
+                let condition = pdg.nodes_at(addr).iter()
+                    .filter_map(|&sink| pdg.depends_on(pdg.id(sink), pdg.id(source)))
+                    .fold(None, |all: Option<_>, condition| match all {
+                        Some(all) => Some(condition.or(all)),
+                        None => Some(condition),
+                    });
+                Ok(condition.map_or("null".to_string(), |c| json_string(&c.to_string())))
+            },
+
+            "export" => {
+                let analysis = self.analysis(params)?;
+                let graph = params.get("graph").and_then(Json::as_str)
+                    .ok_or_else(|| RpcError::param("graph"))?;
+                let format = params.get("format").and_then(Json::as_str)
+                    .ok_or_else(|| RpcError::param("format"))?;
+
+                let Analysis { program, cfg, ddg, pdg } = analysis;
+                let export = export_graph(program, cfg, ddg, pdg, graph, format)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS,
+                        format!("no export of graph {} as {}", graph, format)))?
+                    .map_err(|err| RpcError::new(ANALYSIS_FAILED, err))?;
+                Ok(json_string(&String::from_utf8_lossy(&export)))
+            },
+
+            "equivalence" => {
+                let first = &self.analysis(params)?.program;
+                let handle = number(params, "other")?;
+                let second = &self.analyses.get(&handle)
+                    .ok_or_else(|| RpcError::unknown_program(handle))?
+                    .program;
+
+                let config = EquivalenceConfig::default();
+                let equivalence = match params.get("function").map(Json::as_str) {
+                    Some(Some(name)) => check_equivalence_named(first, second, name, &config)
+                        .map_err(|err| RpcError::new(INVALID_PARAMS, err.message))?,
+                    Some(None) => return Err(RpcError::param("function")),
+                    None => {
+                        let addr = number(params, "addr")?;
+                        let other = number(params, "other_addr")?;
+                        check_equivalence(first, addr, second, other, &config)
+                    },
+                };
+
+                Ok(match equivalence {
+                    Equivalence::Equivalent => "{\"equivalent\": true}".to_string(),
+                    Equivalence::Inconclusive(reason) => format!(
+                        "{{\"equivalent\": null, \"reason\": {}}}", json_string(&reason),
+                    ),
+                    Equivalence::Different(counterexample) => {
+                        let inputs: Vec<String> = counterexample.inputs.iter()
+                            .map(|(name, value)| format!(
+                                "{}: {}", json_string(name), json_string(&value.to_string()),
+                            ))
+                            .collect();
+                        let outputs: Vec<String> = counterexample.outputs.iter()
+                            .map(|(name, a, b)| format!(
+                                "{{\"output\": {}, \"first\": {}, \"second\": {}}}",
+                                json_string(name),
+                                json_string(&a.to_string()),
+                                json_string(&b.to_string()),
+                            ))
+                            .collect();
+                        format!("{{\"equivalent\": false, \"inputs\": {{{}}}, \"outputs\": [{}]}}",
+                                inputs.join(", "), outputs.join(", "))
+                    },
+                })
+            },
+
+            "impact" => {
+                let new = self.analysis(params)?;
+                let handle = number(params, "old")?;
+                let old = self.analyses.get(&handle)
+                    .ok_or_else(|| RpcError::unknown_program(handle))?;
+                let impact = PatchImpact::compute(
+                    &old.program, &old.cfg, &new.program, &new.cfg, &new.ddg, &new.pdg,
+                );
+                let mut json = Vec::new();
+                impact.write_json(&mut json).map_err(|err| RpcError::new(ANALYSIS_FAILED, err))?;
+                Ok(String::from_utf8_lossy(&json).trim_end().to_string())
+            },
+
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    /// The analysis of the program given by the parameters.
+    fn analysis(&self, params: &Json) -> Result<&Analysis, RpcError> {
+        let handle = number(params, "program")?;
+        self.analyses.get(&handle).ok_or_else(|| RpcError::unknown_program(handle))
+    }
+}
+
+/// A failed request.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new<S: ToString>(code: i64, message: S) -> RpcError {
+        RpcError { code, message: message.to_string() }
+    }
+
+    fn param(name: &str) -> RpcError {
+        RpcError::new(INVALID_PARAMS, format!("missing or invalid parameter {}", name))
+    }
+
+    fn unknown_program(handle: u64) -> RpcError {
+        RpcError::new(INVALID_PARAMS, format!("unknown program {}", handle))
+    }
+}
+
+/// A numeric parameter.
+fn number(params: &Json, name: &str) -> Result<u64, RpcError> {
+    params.get(name).and_then(Json::as_u64).ok_or_else(|| RpcError::param(name))
+}
+
+/// An optional boolean parameter.
+fn flag(params: &Json, name: &str) -> Result<Option<bool>, RpcError> {
+    match params.get(name) {
+        None => Ok(None),
+        Some(&Json::Bool(value)) => Ok(Some(value)),
+        Some(_) => Err(RpcError::param(name)),
+    }
+}
+
+/// The configuration of a report given by the `format`, `model` and `visual`
+/// parameters.
+fn report_config(params: &Json) -> Result<ReportConfig, RpcError> {
+    let format = match params.get("format").and_then(Json::as_str) {
+        Some("html") => ReportFormat::Html,
+        Some("markdown") => ReportFormat::Markdown,
+        _ => return Err(RpcError::param("format")),
+    };
+    let model = flag(params, "model")?.unwrap_or(false);
+    let mut config = ReportConfig { format, model, .. ReportConfig::default() };
+
+    let visual = match params.get("visual") {
+        None => return Ok(config),
+        Some(visual @ Json::Object(_)) => visual,
+        Some(_) => return Err(RpcError::param("visual")),
+    };
+    let text = |name: &str| visual.get(name)
+        .map(|value| value.as_str().map(str::to_string).ok_or_else(|| RpcError::param(name)))
+        .transpose();
+    let count = |name: &str| visual.get(name)
+        .map(|value| value.as_u64().ok_or_else(|| RpcError::param(name)))
+        .transpose();
+
+    let config_visual = &mut config.visual;
+    config_visual.rankdir = text("rankdir")?;
+    config_visual.splines = text("splines")?;
+    if let Some(font) = text("font")? {
+        config_visual.font = font;
+    }
+    config_visual.title_size = count("title_size")?.map(|size| size as u32);
+    config_visual.block_shape = text("block_shape")?;
+    config_visual.location_shape = text("location_shape")?;
+    config_visual.max_label = count("max_label")?.map(|max| max as usize);
+    config_visual.elision = match text("elision")?.as_deref() {
+        None | Some("end") => Elision::End,
+        Some("middle") => Elision::Middle,
+        Some(_) => return Err(RpcError::param("elision")),
+    };
+    config_visual.simplify = flag(visual, "simplify")?.unwrap_or(false);
+    config_visual.tooltips = flag(visual, "tooltips")?.unwrap_or(false);
+    config_visual.legend = flag(visual, "legend")?.unwrap_or(false);
+    config_visual.listing.bytes = flag(visual, "bytes")?.unwrap_or(false);
+    match visual.get("lines") {
+        None => {},
+        Some(Json::Object(members)) => {
+            for (addr, line) in members {
+                let invalid = || RpcError::param("lines");
+                let addr = Json::String(addr.clone()).as_u64().ok_or_else(invalid)?;
+                let line = line.as_str().ok_or_else(invalid)?;
+                config_visual.listing.lines.insert(addr, line.to_string());
+            }
+        },
+        Some(_) => return Err(RpcError::param("lines")),
+    }
+    Ok(config)
+}
+
+/// The slice at the `addr` in the `direction` given by the parameters.
+fn slice(pdg: &ProgramDependenceGraph, params: &Json) -> Result<BTreeSet<usize>, RpcError> {
+    let addr = number(params, "addr")?;
+    match params.get("direction").map(Json::as_str) {
+        None | Some(Some("backward")) => Ok(pdg.backward_slice(addr)),
+        Some(Some("forward")) => Ok(pdg.forward_slice(addr)),
+        _ => Err(RpcError::param("direction")),
+    }
+}
+
+/// The response to the request with the id.
+fn respond(id: &Json, result: Result<String, RpcError>) -> String {
+    match result {
+        Ok(result) => format!("{{\"jsonrpc\": \"2.0\", \"id\": {}, \"result\": {}}}", id, result),
+        Err(err) => format!(
+            "{{\"jsonrpc\": \"2.0\", \"id\": {}, \"error\": {{\"code\": {}, \"message\": {}}}}}",
+            id, err.code, json_string(&err.message),
+        ),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let mut server = Server::new();
+        let mut error = |request: &str| {
+            let response = Json::parse(&server.handle(request).unwrap()).unwrap();
+            assert_eq!(response.get("jsonrpc").and_then(Json::as_str), Some("2.0"));
+            let code = response.get("error").unwrap().get("code").unwrap();
+            format!("{} {}", response.get("id").unwrap(), code)
+        };
+
+        assert_eq!(error("{"), "null -32700");
+        assert_eq!(error(r#"{"id": 1}"#), "1 -32600");
+        assert_eq!(error(r#"{"id": "a", "method": "fly"}"#), "\"a\" -32601");
+        assert_eq!(error(r#"{"id": 2, "method": "slice", "params": {"program": 7, "addr": 0}}"#),
+                   "2 -32602");
+        assert_eq!(error(r#"{"id": 3, "method": "report", "params": {"program": 7, "addr": 0}}"#),
+                   "3 -32602");
+        assert_eq!(error(r#"{"id": 4, "method": "equivalence", "params": {"program": 7}}"#),
+                   "4 -32602");
+        assert_eq!(server.handle(r#"{"method": "unload", "params": {"program": 7}}"#), None);
+    }
+
+    #[test]
+    fn report() {
+        let params = Json::parse(r#"{"format": "markdown", "visual": {"rankdir": "LR",
+            "max_label": 20, "elision": "middle", "bytes": true, "lines": {"0x1000": "x++;"}}}"#)
+            .unwrap();
+        let config = report_config(&params).unwrap();
+        assert_eq!(config.format, ReportFormat::Markdown);
+        assert_eq!(config.visual.rankdir.as_deref(), Some("LR"));
+        assert_eq!((config.visual.max_label, config.visual.elision), (Some(20), Elision::Middle));
+        assert!(config.visual.listing.bytes && !config.visual.simplify);
+        assert_eq!(config.visual.listing.lines[&0x1000], "x++;");
+
+        let error = |text| report_config(&Json::parse(text).unwrap()).unwrap_err().message;
+        assert_eq!(error(r#"{"format": "html", "visual": {"elision": "start"}}"#),
+                   RpcError::param("elision").message);
+        assert_eq!(error(r#"{"format": "html", "visual": []}"#), RpcError::param("visual").message);
+    }
+
+    #[test]
+    fn session() {
+        let path = json_string(&crate::fixtures::fixture("bufs").display().to_string());
+        let input = format!(concat!(
+            r#"{{"id": 1, "method": "load", "params": {{"path": {}}}}}"#, "\n",
+            r#"{{"id": 2, "method": "export", "params": "#,
+            r#"{{"program": 0, "graph": "pdg", "format": "json"}}}}"#, "\n",
+            r#"{{"id": 3, "method": "unload", "params": {{"program": 0}}}}"#, "\n",
+        ), path);
+        let mut output = Vec::new();
+        Server::new().serve(input.as_bytes(), &mut output).unwrap();
+
+        let responses: Vec<Json> = String::from_utf8(output).unwrap().lines()
+            .map(|line| Json::parse(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        let loaded = responses[0].get("result").unwrap();
+        assert_eq!(loaded.get("program").and_then(Json::as_u64), Some(0));
+
+        let export = responses[1].get("result").and_then(Json::as_str).unwrap();
+        let pdg = Json::parse(export).unwrap();
+        let nodes = pdg.get("nodes").and_then(Json::as_array).unwrap();
+        assert_eq!(Some(nodes.len() as u64), loaded.get("nodes").and_then(Json::as_u64));
+        assert_eq!(responses[2].get("result"), Some(&Json::Bool(true)));
+    }
+}
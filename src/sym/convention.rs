@@ -0,0 +1,126 @@
+//! Calling conventions describing how functions receive arguments and
+//! return values.
+
+use crate::ir::MemoryMapped;
+use crate::math::{SymExpr, DataType};
+use crate::x86_64::Register;
+use super::SymState;
+
+
+/// How functions pass integer arguments and return values and which
+/// registers they preserve. Custom conventions for unusual ABIs can be built
+/// directly from the fields.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CallingConvention {
+    /// The registers holding the first arguments in order. Further
+    /// arguments are passed on the stack.
+    pub arguments: Vec<Register>,
+    /// The register holding the return value.
+    pub result: Register,
+    /// The registers a called function has to preserve.
+    pub preserved: Vec<Register>,
+    /// The stack space the caller reserves for the register arguments above
+    /// the return address.
+    pub shadow_space: u64,
+}
+
+/// Where an argument is located at the entry of a function.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ArgumentSlot {
+    Register(Register),
+    /// An offset from the stack pointer.
+    Stack(u64),
+}
+
+impl CallingConvention {
+    /// The System V AMD64 convention used on Linux and most Unix systems.
+    pub fn sysv() -> CallingConvention {
+        use Register::*;
+        CallingConvention {
+            arguments: vec![RDI, RSI, RDX, RCX, R8, R9],
+            result: RAX,
+            preserved: vec![RBX, RSP, RBP, R12, R13, R14, R15],
+            shadow_space: 0,
+        }
+    }
+
+    /// The Microsoft x64 convention used on Windows.
+    pub fn microsoft() -> CallingConvention {
+        use Register::*;
+        CallingConvention {
+            arguments: vec![RCX, RDX, R8, R9],
+            result: RAX,
+            preserved: vec![RBX, RSP, RBP, RSI, RDI, R12, R13, R14, R15],
+            shadow_space: 32,
+        }
+    }
+
+    /// Where the argument with the index is located at the entry of a function.
+    pub fn argument_slot(&self, index: usize) -> ArgumentSlot {
+        match self.arguments.get(index) {
+            Some(&reg) => ArgumentSlot::Register(reg),
+            None => {
+                let stack_index = (index - self.arguments.len()) as u64;
+                ArgumentSlot::Stack(8 + self.shadow_space + 8 * stack_index)
+            },
+        }
+    }
+
+    /// Read the argument with the index at the entry of a function.
+    pub fn argument(&self, state: &SymState, index: usize, data_type: DataType) -> SymExpr {
+        match self.argument_slot(index) {
+            ArgumentSlot::Register(reg) => state.memory[1].read_direct(reg.address(), data_type),
+            ArgumentSlot::Stack(offset) => {
+                let addr = state.get_reg(Register::RSP).add(SymExpr::from_ptr(offset));
+                state.memory[0].read_expr(addr, data_type)
+            },
+        }
+    }
+
+    /// Set the argument with the index at the entry of a function.
+    pub fn set_argument(&self, state: &mut SymState, index: usize, value: SymExpr) {
+        match self.argument_slot(index) {
+            ArgumentSlot::Register(reg) => state.memory[1].write_direct(reg.address(), value),
+            ArgumentSlot::Stack(offset) => {
+                let addr = state.get_reg(Register::RSP).add(SymExpr::from_ptr(offset));
+                state.memory[0].write_expr(addr, value);
+            },
+        }
+    }
+
+    /// Set the return value of a function.
+    pub fn set_result(&self, state: &mut SymState, value: SymExpr) {
+        state.memory[1].write_direct(self.result.address(), value);
+    }
+
+    /// Whether a called function has to preserve the register.
+    pub fn is_preserved(&self, reg: Register) -> bool {
+        self.preserved.contains(&reg.base())
+    }
+}
+
+impl Default for CallingConvention {
+    fn default() -> CallingConvention {
+        CallingConvention::sysv()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argument_slots() {
+        let sysv = CallingConvention::sysv();
+        assert_eq!(sysv.argument_slot(1), ArgumentSlot::Register(Register::RSI));
+        assert_eq!(sysv.argument_slot(6), ArgumentSlot::Stack(8));
+        assert_eq!(sysv.argument_slot(7), ArgumentSlot::Stack(16));
+        assert!(!sysv.is_preserved(Register::EDI));
+
+        let microsoft = CallingConvention::microsoft();
+        assert_eq!(microsoft.argument_slot(0), ArgumentSlot::Register(Register::RCX));
+        assert_eq!(microsoft.argument_slot(4), ArgumentSlot::Stack(40));
+        assert!(microsoft.is_preserved(Register::EDI));
+    }
+}
@@ -0,0 +1,223 @@
+//! Modeling of heap allocations with one abstract region per allocation site.
+
+use crate::math::{SymExpr, SymCondition, DataType, Symbol, Traversed};
+use crate::x86_64::Register;
+use super::{SymState, TypedMemoryAccess};
+
+
+/// A function managing heap memory whose calls are summarized.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Allocator {
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+    RustAlloc,
+    RustAllocZeroed,
+    RustRealloc,
+    RustDealloc,
+}
+
+/// The region of memory returned by the allocations at a call site.
+///
+/// The base of the region is the symbol `heap` indexed by the address of the
+/// call site, so that the memory of different sites never aliases and their
+/// data dependencies stay distinguishable. Repeated allocations at the same
+/// site share one region.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HeapRegion {
+    /// The address of the call to the allocator.
+    pub site: u64,
+    /// The address of the first byte of the region.
+    pub base: SymExpr,
+    /// The number of bytes requested for the region.
+    pub size: SymExpr,
+    /// Whether the region was released by the last allocator call concerning it.
+    pub freed: bool,
+}
+
+impl Allocator {
+    /// The allocator with the symbol name if there is one.
+    pub fn from_name(name: &str) -> Option<Allocator> {
+        Some(match name {
+            "malloc" => Allocator::Malloc,
+            "calloc" => Allocator::Calloc,
+            "realloc" => Allocator::Realloc,
+            "free" => Allocator::Free,
+            "__rust_alloc" => Allocator::RustAlloc,
+            "__rust_alloc_zeroed" => Allocator::RustAllocZeroed,
+            "__rust_realloc" => Allocator::RustRealloc,
+            "__rust_dealloc" => Allocator::RustDealloc,
+            _ => return None,
+        })
+    }
+
+    /// The symbol name of the allocator.
+    pub fn name(self) -> &'static str {
+        match self {
+            Allocator::Malloc => "malloc",
+            Allocator::Calloc => "calloc",
+            Allocator::Realloc => "realloc",
+            Allocator::Free => "free",
+            Allocator::RustAlloc => "__rust_alloc",
+            Allocator::RustAllocZeroed => "__rust_alloc_zeroed",
+            Allocator::RustRealloc => "__rust_realloc",
+            Allocator::RustDealloc => "__rust_dealloc",
+        }
+    }
+
+    /// Perform a call of the allocator `function` at the site on a state at the
+    /// entry of the function. The arguments are read and the result is set
+    /// according to the calling convention of the function.
+    pub fn call(self, state: &mut SymState, function: u64, site: u64) {
+        let config = state.config.clone();
+        let convention = config.convention(function);
+        let argument = |state: &SymState, index| convention.argument(state, index, DataType::N64);
+
+        let result = match self {
+            Allocator::Malloc | Allocator::RustAlloc => {
+                let size = argument(state, 0);
+                Some(state.allocate(site, size))
+            },
+            Allocator::Calloc | Allocator::RustAllocZeroed => {
+                let size = match self {
+                    Allocator::Calloc => argument(state, 0).mul(argument(state, 1)),
+                    _ => argument(state, 0),
+                };
+                let base = state.allocate(site, size.clone());
+                state.memory[0].write_fill(base.clone(), size, SymExpr::from_int(DataType::N8, 0));
+                Some(base)
+            },
+            // The old contents are copied as far as the new size allows.
+            Allocator::Realloc | Allocator::RustRealloc => {
+                let old = argument(state, 0);
+                let size = argument(state, if self == Allocator::Realloc { 1 } else { 3 });
+                let copied = match self {
+                    Allocator::RustRealloc => {
+                        let old_size = argument(state, 1);
+                        old_size.clone().less_than(size.clone(), false)
+                            .if_then_else(old_size, size.clone())
+                    },
+                    _ => size.clone(),
+                };
+                state.free(&old);
+                let base = state.allocate(site, size);
+                state.memory[0].write_copy(base.clone(), old, copied);
+                Some(base)
+            },
+            Allocator::Free | Allocator::RustDealloc => {
+                let pointer = argument(state, 0);
+                state.free(&pointer);
+                None
+            },
+        };
+
+        if let Some(result) = result {
+            convention.set_result(state, result);
+        }
+
+        // Return to the caller.
+        let rsp = state.get_reg(Register::RSP).add(SymExpr::from_ptr(8));
+        state.set_reg(Register::RSP, rsp);
+        state.trace.pop();
+    }
+}
+
+impl HeapRegion {
+    /// The base address of the allocations at the site.
+    pub fn base_of(site: u64) -> SymExpr {
+        SymExpr::Sym(Symbol(DataType::N64, "heap", site as usize))
+    }
+
+    /// The condition under which the access lies completely within the region.
+    pub fn in_bounds(&self, access: &TypedMemoryAccess) -> SymCondition {
+        let end = access.0.clone().add(SymExpr::from_ptr(access.1.bytes() as u64));
+        let limit = self.base.clone().add(self.size.clone());
+        self.base.clone().less_equal(access.0.clone(), false)
+            .and(end.less_equal(limit, false))
+    }
+}
+
+impl SymState {
+    /// Allocate the region for the site with the size in bytes and return its base.
+    pub fn allocate(&mut self, site: u64, size: SymExpr) -> SymExpr {
+        let base = HeapRegion::base_of(site);
+        let region = HeapRegion { site, base: base.clone(), size, freed: false };
+        match self.heap.iter_mut().find(|region| region.site == site) {
+            Some(existing) => *existing = region,
+            None => self.heap.push(region),
+        }
+        base
+    }
+
+    /// Release the region the pointer points into if it is known.
+    pub fn free(&mut self, pointer: &SymExpr) {
+        let site = self.heap_region(pointer).map(|region| region.site);
+        if let Some(region) = self.heap.iter_mut().find(|region| Some(region.site) == site) {
+            region.freed = true;
+        }
+    }
+
+    /// The heap region an address points into, which is the one whose base
+    /// symbol occurs in the address.
+    pub fn heap_region(&self, addr: &SymExpr) -> Option<&HeapRegion> {
+        let mut site = None;
+        addr.traverse(&mut |node| {
+            if let Traversed::Expr(&SymExpr::Sym(Symbol(_, "heap", index))) = node {
+                site = Some(index as u64);
+            }
+        });
+        let site = site?;
+        self.heap.iter().find(|region| region.site == site)
+    }
+
+    /// The condition under which the access violates the bounds of the heap
+    /// region it points into or touches it after it was freed. Accesses outside
+    /// of the heap are never out of bounds.
+    pub fn out_of_bounds(&self, access: &TypedMemoryAccess) -> SymCondition {
+        match self.heap_region(&access.0) {
+            Some(region) if region.freed => SymCondition::TRUE,
+            Some(region) => region.in_bounds(access).not(),
+            None => SymCondition::FALSE,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::math::{Integer, Solver};
+    use crate::sym::MemoryStrategy;
+    use std::rc::Rc;
+    use super::*;
+
+    #[test]
+    fn allocations() {
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+        state.set_reg(Register::RDI, SymExpr::from_ptr(2));
+        state.set_reg(Register::RSI, SymExpr::from_ptr(8));
+        state.trace.push(0x1000);
+        Allocator::Calloc.call(&mut state, 0x2000, 0x1000);
+
+        let base = state.get_reg(Register::RAX);
+        assert_eq!(base, HeapRegion::base_of(0x1000));
+        assert_eq!(state.get_reg(Register::RSP), SymExpr::from_ptr(0x8008));
+        assert!(state.trace.is_empty());
+
+        let at = |offset| base.clone().add(SymExpr::from_ptr(offset));
+        let access = |offset| TypedMemoryAccess(at(offset), DataType::N64);
+        let symbols = |symbol| match symbol {
+            Symbol(_, "heap", 0x1000) => Some(Integer(DataType::N64, 0x10000)),
+            _ => None,
+        };
+        assert!(!state.out_of_bounds(&access(8)).evaluate(&symbols));
+        assert!(state.out_of_bounds(&access(12)).evaluate(&symbols));
+
+        state.set_reg(Register::RDI, at(0));
+        Allocator::Free.call(&mut state, 0x2008, 0x1010);
+        assert!(state.heap[0].freed);
+        assert_eq!(state.out_of_bounds(&access(0)), SymCondition::TRUE);
+        assert_eq!(Allocator::from_name("__rust_dealloc"), Some(Allocator::RustDealloc));
+    }
+}
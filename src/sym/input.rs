@@ -0,0 +1,559 @@
+//! Modeling of inputs from environment variables, named files and sockets.
+//!
+//! Calls to `getenv`, to the functions opening and reading files and to the
+//! socket receive functions are summarized like the allocators. The bytes
+//! they provide are fresh symbols of the families `env`, `file` and `net`, or
+//! `stdin` for reads from the descriptors of the standard streams, and the
+//! state remembers for each symbol which variable or which byte of
+//! which file or message it stands for. The data dependency graph keeps this
+//! for the locations the bytes are written to, so that everything depending
+//! on them can be described by its origin like `env THEME` or `bytes 4..8 of
+//! config.bin`.
+//!
+//! Only a limited number of bytes of each input get their own symbols. The
+//! rest of a longer input, or of one with an unknown length, is written by a
+//! single symbol at an unknown offset past the limit.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::flow::{AbstractLocation, StorageLocation};
+use crate::math::{SymExpr, DataType, Integer, Symbol};
+use crate::x86_64::Register;
+use super::{SymState, StdioKind, TypedMemoryAccess, CallingConvention, ArgumentSlot};
+
+
+/// A function providing input from outside of the program whose calls are summarized.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum InputFunction {
+    Getenv,
+    Open,
+    Fopen,
+    Read,
+    Fread,
+    Recv,
+    RecvFrom,
+}
+
+/// Where the bytes of an input come from.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum InputSource {
+    /// The value of the environment variable with the name.
+    Env(String),
+    /// The contents of the file with the path.
+    File(String),
+    /// The message received with the index among all messages, from the
+    /// socket with the handle if it is known.
+    Message { socket: Option<u64>, index: usize },
+    /// The registers of the memory-mapped device with the name.
+    Device(String),
+    /// The standard stream read through its file descriptor.
+    Stdio(StdioKind),
+}
+
+/// A byte of an input at an offset from the start of its source.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Input {
+    pub source: InputSource,
+    pub offset: u64,
+}
+
+/// A file opened by the program and the offset the next read starts at.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OpenFile {
+    pub path: String,
+    pub offset: u64,
+}
+
+/// The number of bytes in the values of environment variables, which are
+/// followed by a null byte.
+pub const ENV_LENGTH: u64 = 16;
+
+/// The default number of bytes of a single input that get their own symbols.
+pub const INPUT_LIMIT: u64 = 1024;
+
+/// The handle of the first file opened, following the standard streams.
+const FIRST_HANDLE: u64 = 3;
+
+/// The maximum length of names read from memory.
+const NAME_LIMIT: u64 = 256;
+
+impl InputFunction {
+    /// The input function with the symbol name if there is one.
+    pub fn from_name(name: &str) -> Option<InputFunction> {
+        Some(match name {
+            "getenv" | "secure_getenv" => InputFunction::Getenv,
+            "open" | "open64" => InputFunction::Open,
+            "fopen" | "fopen64" => InputFunction::Fopen,
+            "read" => InputFunction::Read,
+            "fread" => InputFunction::Fread,
+            "recv" => InputFunction::Recv,
+            "recvfrom" => InputFunction::RecvFrom,
+            _ => return None,
+        })
+    }
+
+    /// The symbol name of the input function.
+    pub fn name(self) -> &'static str {
+        match self {
+            InputFunction::Getenv => "getenv",
+            InputFunction::Open => "open",
+            InputFunction::Fopen => "fopen",
+            InputFunction::Read => "read",
+            InputFunction::Fread => "fread",
+            InputFunction::Recv => "recv",
+            InputFunction::RecvFrom => "recvfrom",
+        }
+    }
+
+    /// Perform a call of the input `function` at the site on a state at the
+    /// entry of the function and return the symbols of the bytes written to
+    /// memory with their accesses.
+    ///
+    /// The value of an environment variable is a heap region of the site
+    /// with `ENV_LENGTH` symbolic bytes. Opening a file returns a new small
+    /// handle for both descriptors and streams, which programs only pass on
+    /// to the reads. Reads of a known number of bytes always succeed
+    /// completely. Reads from the descriptors 0 to 2 are reads from the
+    /// standard streams (see `standard_stream`), and reads from other handles
+    /// that were not opened before are named after the handle.
+    ///
+    /// Received messages have a symbolic length `netlen` bounded by the size
+    /// of the buffer, which is returned. Each byte of the buffer holds its
+    /// symbol if it lies within the length and keeps its value otherwise. A
+    /// buffer of symbolic size is filled like one of unlimited size.
+    ///
+    /// Only the first bytes up to the input limit of the configuration get
+    /// their own symbols (see `SymConfig::input_limit`).
+    pub fn call(
+        self,
+        state: &mut SymState,
+        function: u64,
+        site: u64,
+    ) -> Vec<(Symbol, TypedMemoryAccess)> {
+        let config = state.config.clone();
+        let convention = config.convention(function);
+        let argument = |state: &SymState, index| convention.argument(state, index, DataType::N64);
+
+        let arguments: Vec<SymExpr> = (0 .. 4).map(|index| argument(state, index)).collect();
+
+        // Return to the caller first, so that the bytes belong to its context.
+        let rsp = state.get_reg(Register::RSP).add(SymExpr::from_ptr(8));
+        state.set_reg(Register::RSP, rsp);
+        state.trace.pop();
+
+        let (result, inputs) = match self {
+            InputFunction::Getenv => {
+                let name = state.read_name(arguments[0].clone());
+                let base = state.allocate(site, SymExpr::from_ptr(ENV_LENGTH + 1));
+                let end = base.clone().add(SymExpr::from_ptr(ENV_LENGTH));
+                state.memory[0].write_expr(end, SymExpr::from_int(DataType::N8, 0));
+
+                let buffer = (base.clone(), convention.result);
+                let source = InputSource::Env(name);
+                let inputs = state.input(site, source, 0, buffer, Some(ENV_LENGTH), None);
+                (base, inputs)
+            },
+            InputFunction::Open | InputFunction::Fopen => {
+                let path = state.read_name(arguments[0].clone());
+                let handle = state.files.keys().next_back().map_or(FIRST_HANDLE, |&last| last + 1);
+                state.files.insert(handle, OpenFile { path, offset: 0 });
+                (SymExpr::from_int(DataType::N64, handle), vec![])
+            },
+            InputFunction::Read | InputFunction::Fread => {
+                let (handle, buffer, count, returned) = match self {
+                    InputFunction::Read => {
+                        let buffer = (arguments[1].clone(), base_register(convention, 1));
+                        (arguments[0].clone(), buffer, arguments[2].clone(), arguments[2].clone())
+                    },
+                    _ => {
+                        let buffer = (arguments[0].clone(), base_register(convention, 0));
+                        let count = arguments[1].clone().mul(arguments[2].clone());
+                        (arguments[3].clone(), buffer, count, arguments[2].clone())
+                    },
+                };
+
+                match (handle, count) {
+                    (SymExpr::Int(Integer(_, handle)), SymExpr::Int(Integer(_, count))) => {
+                        let file = state.files.entry(handle).or_insert_with(|| OpenFile {
+                            path: format!("handle {}", handle),
+                            offset: 0,
+                        });
+                        let source = match standard_stream(handle) {
+                            Some(kind) => InputSource::Stdio(kind),
+                            None => InputSource::File(file.path.clone()),
+                        };
+                        let offset = file.offset;
+                        file.offset += count;
+                        (returned, state.input(site, source, offset, buffer, Some(count), None))
+                    },
+                    _ => (state.unknown(DataType::N64), vec![]),
+                }
+            },
+            InputFunction::Recv | InputFunction::RecvFrom => {
+                let buffer = (arguments[1].clone(), base_register(convention, 1));
+                let size = arguments[2].clone();
+                let socket = match arguments[0] {
+                    SymExpr::Int(Integer(_, socket)) => Some(socket),
+                    _ => None,
+                };
+                let index = state.messages;
+                state.messages += 1;
+
+                let symbol = Symbol(DataType::N64, "netlen", index);
+                let storage = StorageLocation::Direct(convention.result);
+                let location = AbstractLocation::new(site, state.trace.clone(), storage);
+                state.symbol_map.insert(symbol, location);
+                let length = SymExpr::Sym(symbol).less_than(size.clone(), false)
+                    .if_then_else(SymExpr::Sym(symbol), size.clone());
+
+                let size = match size {
+                    SymExpr::Int(Integer(_, size)) => Some(size),
+                    _ => None,
+                };
+                let source = InputSource::Message { socket, index };
+                let inputs = state.input(site, source, 0, buffer, size, Some(&length));
+                (length, inputs)
+            },
+        };
+
+        convention.set_result(state, result);
+        inputs
+    }
+}
+
+impl Display for InputSource {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InputSource::Env(name) => write!(f, "env {}", name),
+            InputSource::File(path) => write!(f, "file {}", path),
+            InputSource::Message { socket: Some(socket), index } => {
+                write!(f, "message {} from socket {}", index, socket)
+            },
+            InputSource::Message { socket: None, index } => {
+                write!(f, "message {} from an unknown socket", index)
+            },
+            InputSource::Device(name) => write!(f, "device {}", name),
+            InputSource::Stdio(StdioKind::Stdin) => write!(f, "stdin"),
+            InputSource::Stdio(_) => write!(f, "stdout"),
+        }
+    }
+}
+
+/// Describe the inputs by their sources. Environment variables are named as
+/// a whole like `env THEME`, while consecutive bytes of files and messages are
+/// merged into ranges like `bytes 4..8 of config.bin`. Device registers are
+/// named by their offsets like `register 0x4 of uart`.
+pub fn describe_inputs<'a, I>(inputs: I) -> Vec<String> where I: IntoIterator<Item=&'a Input> {
+    let mut offsets: BTreeMap<&InputSource, BTreeSet<u64>> = BTreeMap::new();
+    for input in inputs {
+        offsets.entry(&input.source).or_default().insert(input.offset);
+    }
+
+    let mut descriptions = Vec::new();
+    for (source, offsets) in offsets {
+        let name = match source {
+            InputSource::Env(_) => {
+                descriptions.push(source.to_string());
+                continue;
+            },
+            InputSource::Device(name) => {
+                for offset in offsets {
+                    descriptions.push(format!("register {:#x} of {}", offset, name));
+                }
+                continue;
+            },
+            InputSource::File(path) => path.clone(),
+            InputSource::Message { .. } | InputSource::Stdio(_) => source.to_string(),
+        };
+
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for offset in offsets {
+            match ranges.last_mut() {
+                Some(range) if range.1 == offset => range.1 += 1,
+                _ => ranges.push((offset, offset + 1)),
+            }
+        }
+        for (start, end) in ranges {
+            descriptions.push(match end - start {
+                1 => format!("byte {} of {}", start, name),
+                _ => format!("bytes {}..{} of {}", start, end, name),
+            });
+        }
+    }
+    descriptions
+}
+
+/// The kind of the standard stream with the file descriptor if it is one.
+/// Standard error is an output like stdout.
+fn standard_stream(handle: u64) -> Option<StdioKind> {
+    match handle {
+        0 => Some(StdioKind::Stdin),
+        1 | 2 => Some(StdioKind::Stdout),
+        _ => None,
+    }
+}
+
+/// The register holding the argument with the index, which names the
+/// locations of the bytes read into it. Arguments on the stack are named
+/// after the result register instead.
+fn base_register(convention: &CallingConvention, index: usize) -> Register {
+    match convention.argument_slot(index) {
+        ArgumentSlot::Register(reg) => reg,
+        ArgumentSlot::Stack(_) => convention.result,
+    }
+}
+
+impl SymState {
+    /// Write fresh symbols for the `count` bytes of the source starting at
+    /// the offset into the buffer, given by its address and the register
+    /// holding it, and remember where they come from. With a symbolic
+    /// length, only the bytes within it are overwritten. An unknown count
+    /// is unlimited.
+    ///
+    /// Beyond the input limit, the rest of the bytes is written by a single
+    /// symbol at an unknown offset after the limit, so that reads from there
+    /// may depend on the input.
+    fn input(
+        &mut self,
+        site: u64,
+        source: InputSource,
+        offset: u64,
+        buffer: (SymExpr, Register),
+        count: Option<u64>,
+        length: Option<&SymExpr>,
+    ) -> Vec<(Symbol, TypedMemoryAccess)> {
+        let (addr, base) = buffer;
+        let limit = self.config.input_limit();
+        let mut inputs = Vec::new();
+        for i in 0 .. count.map_or(limit, |count| count.min(limit)) {
+            let at = SymExpr::from_ptr(i);
+            inputs.push(self.input_byte(site, &source, offset + i, (&addr, base), (at, i), length));
+        }
+
+        if count.map_or(true, |count| count > limit) {
+            let at = SymExpr::from_ptr(limit).add(self.unknown(DataType::N64));
+            let tail = offset + limit;
+            inputs.push(self.input_byte(site, &source, tail, (&addr, base), (at, limit), length));
+        }
+        inputs
+    }
+
+    /// Write a fresh symbol for the byte of the source at the offset into the
+    /// buffer, given with its known part.
+    fn input_byte(
+        &mut self,
+        site: u64,
+        source: &InputSource,
+        offset: u64,
+        buffer: (&SymExpr, Register),
+        at: (SymExpr, u64),
+        length: Option<&SymExpr>,
+    ) -> (Symbol, TypedMemoryAccess) {
+        let (addr, base) = buffer;
+        let (at, displacement) = at;
+        let symbol = match source {
+            InputSource::Env(_) => {
+                self.env_symbols += 1;
+                Symbol(DataType::N8, "env", self.env_symbols - 1)
+            },
+            InputSource::File(_) => {
+                self.file_symbols += 1;
+                Symbol(DataType::N8, "file", self.file_symbols - 1)
+            },
+            InputSource::Message { .. } => {
+                self.net_symbols += 1;
+                Symbol(DataType::N8, "net", self.net_symbols - 1)
+            },
+            InputSource::Device(_) => {
+                self.mmio_symbols += 1;
+                Symbol(DataType::N8, "mmio", self.mmio_symbols - 1)
+            },
+            InputSource::Stdio(_) => {
+                self.stdin_symbols += 1;
+                Symbol(DataType::N8, "stdin", self.stdin_symbols - 1)
+            },
+        };
+
+        let target = addr.clone().add(at.clone());
+        let value = match length {
+            Some(length) => {
+                let old = self.memory[0].read_expr(target.clone(), DataType::N8);
+                at.less_than(length.clone(), false)
+                    .if_then_else(SymExpr::Sym(symbol), old)
+            },
+            None => SymExpr::Sym(symbol),
+        };
+        self.memory[0].write_expr(target.clone(), value);
+
+        let storage = StorageLocation::Indirect {
+            data_type: DataType::N8,
+            base,
+            scaled_offset: None,
+            displacement: if displacement > 0 { Some(displacement as i64) } else { None },
+        };
+        let location = AbstractLocation::new(site, self.trace.clone(), storage);
+        self.symbol_map.insert(symbol, location);
+        self.inputs.insert(symbol, Input { source: source.clone(), offset });
+        (symbol, TypedMemoryAccess(target, DataType::N8))
+    }
+
+    /// The null-terminated string at the address, or `?` if it is not fully known.
+    fn read_name(&self, addr: SymExpr) -> String {
+        let mut bytes = Vec::new();
+        for i in 0 .. NAME_LIMIT {
+            let at = addr.clone().add(SymExpr::from_ptr(i));
+            match self.memory[0].read_expr(at, DataType::N8) {
+                SymExpr::Int(Integer(_, 0)) => return String::from_utf8_lossy(&bytes).into_owned(),
+                SymExpr::Int(Integer(_, byte)) => bytes.push(byte as u8),
+                _ => break,
+            }
+        }
+        "?".to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use crate::Program;
+    use crate::fixtures::fixture;
+    use crate::flow::{ControlFlowGraph, ControlKind, DataDependencyGraph, DependencyNode};
+    use crate::flow::{ProgramDependenceGraph, DependenceNode};
+    use crate::math::Solver;
+    use crate::sym::{MemoryStrategy, SymConfig};
+    use super::*;
+
+    #[test]
+    fn inputs() {
+        let program = Program::new(fixture("inputs"));
+        let cfg = ControlFlowGraph::new(&program);
+        let mut names: Vec<&str> = cfg.edges.keys()
+            .filter_map(|&edge| match cfg.edge_kind(edge) {
+                ControlKind::Input { input, .. } => Some(input.name()),
+                _ => None,
+            })
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["getenv", "open", "read"]);
+
+        // The output depends on the first byte of the variable and on the
+        // last four bytes read from the file.
+        let ddg = DataDependencyGraph::new(&cfg);
+        assert_eq!(ddg.inputs.len(), ENV_LENGTH as usize + 8);
+        let output = (0 .. ddg.nodes.len())
+            .find(|&node| matches!(ddg.nodes[node], DependencyNode::Io(StdioKind::Stdout, _)))
+            .unwrap();
+        let written = match &ddg.nodes[ddg.predecessors(output).next().unwrap()] {
+            DependencyNode::Location(location) => location.addr,
+            _ => panic!("output is not written from a location"),
+        };
+
+        let pdg = ProgramDependenceGraph::new(&cfg, &ddg);
+        let slice = pdg.backward_slice(written);
+        let locations = slice.iter().filter_map(|&node| match &pdg.nodes[node] {
+            DependenceNode::DataDependency(location) => Some(location),
+            _ => None,
+        });
+        assert_eq!(ddg.describe_inputs(locations), vec![
+            "env THEME".to_string(),
+            "bytes 4..8 of config.bin".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn received() {
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+        state.set_reg(Register::RDI, SymExpr::from_ptr(5));
+        state.set_reg(Register::RSI, SymExpr::from_ptr(0x4000));
+        state.set_reg(Register::RDX, SymExpr::from_ptr(4));
+        state.memory[0].write_expr(SymExpr::from_ptr(0x4003), SymExpr::from_int(DataType::N8, 7));
+        state.trace.push(0x1000);
+        let inputs = InputFunction::Recv.call(&mut state, 0x2000, 0x1000);
+        assert_eq!(inputs.len(), 4);
+        assert!(state.trace.is_empty());
+
+        // The length is bounded by the size and the bytes beyond it stay.
+        let symbols = |length| move |symbol: Symbol| match symbol {
+            Symbol(_, "netlen", 0) => Some(Integer(DataType::N64, length)),
+            Symbol(_, "net", index) => Some(Integer(DataType::N8, 0x10 + index as u64)),
+            _ => None,
+        };
+        let result = state.get_reg(Register::RAX);
+        assert_eq!(result.evaluate(&symbols(100)).1, 4);
+        assert_eq!(result.evaluate(&symbols(2)).1, 2);
+        let last = state.memory[0].read_expr(SymExpr::from_ptr(0x4003), DataType::N8);
+        assert_eq!(last.evaluate(&symbols(2)).1, 7);
+        assert_eq!(last.evaluate(&symbols(4)).1, 0x13);
+
+        let labels = inputs.iter().map(|(symbol, _)| &state.inputs[symbol]);
+        let description = "bytes 0..4 of message 0 from socket 5".to_string();
+        assert_eq!(describe_inputs(labels), vec![description]);
+    }
+
+    #[test]
+    fn standard_streams() {
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        let read = |state: &mut SymState, count| {
+            state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+            state.set_reg(Register::RDI, SymExpr::from_ptr(0));
+            state.set_reg(Register::RSI, SymExpr::from_ptr(0x4000));
+            state.set_reg(Register::RDX, SymExpr::from_ptr(count));
+            state.trace.push(0x1000);
+            InputFunction::Read.call(state, 0x2000, 0x1000)
+        };
+
+        // Reads from descriptor 0 continue on stdin.
+        let mut inputs = read(&mut state, 2);
+        inputs.extend(read(&mut state, 3));
+        assert!(inputs.iter().all(|(symbol, _)| symbol.1 == "stdin"));
+        let labels = inputs.iter().map(|(symbol, _)| &state.inputs[symbol]);
+        assert_eq!(describe_inputs(labels), vec!["bytes 0..5 of stdin".to_string()]);
+        assert_eq!(standard_stream(2), Some(StdioKind::Stdout));
+        assert_eq!(standard_stream(3), None);
+    }
+
+    #[test]
+    fn limited() {
+        let mut state = SymState::new(MemoryStrategy::ConditionalTrees, Rc::new(Solver::new()));
+        state.config = Arc::new(SymConfig { input_limit: 2, ..SymConfig::default() });
+        state.set_reg(Register::RSP, SymExpr::from_ptr(0x8000));
+        state.set_reg(Register::RDI, SymExpr::from_ptr(5));
+        state.set_reg(Register::RSI, SymExpr::from_ptr(0x4000));
+        state.set_reg(Register::RDX, SymExpr::from_ptr(0x100));
+        state.trace.push(0x1000);
+        let inputs = InputFunction::Recv.call(&mut state, 0x2000, 0x1000);
+        assert_eq!(inputs.len(), 3);
+
+        // The bytes after the limit may come from the tail symbol.
+        let tail = inputs[2].0;
+        assert_eq!(state.inputs[&tail].offset, 2);
+        let byte = state.memory[0].read_expr(SymExpr::from_ptr(0x4010), DataType::N8);
+        let symbols = |symbol: Symbol| match symbol {
+            Symbol(_, "netlen", 0) => Some(Integer(DataType::N64, 0x100)),
+            Symbol(_, "net", index) => Some(Integer(DataType::N8, 0x10 + index as u64)),
+            Symbol(..) => Some(Integer(DataType::N64, 0xe)),
+        };
+        assert_eq!(byte.evaluate(&symbols).1, 0x10 + tail.2 as u64);
+    }
+
+    #[test]
+    fn descriptions() {
+        let input = |source: &InputSource, offset| Input { source: source.clone(), offset };
+        let env = InputSource::Env("THEME".to_string());
+        let file = InputSource::File("config.bin".to_string());
+        let inputs = vec![
+            input(&file, 5), input(&file, 4), input(&env, 2), input(&file, 7),
+            input(&file, 6), input(&file, 0), input(&env, 0),
+        ];
+        assert_eq!(describe_inputs(&inputs), vec![
+            "env THEME".to_string(),
+            "byte 0 of config.bin".to_string(),
+            "bytes 4..8 of config.bin".to_string(),
+        ]);
+        assert_eq!(InputFunction::from_name("fopen64"), Some(InputFunction::Fopen));
+    }
+}
@@ -1,10 +1,13 @@
 //! Symbolic memory models.
 
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
 
-use crate::math::{SymExpr, SymCondition, DataType, Symbol, SharedSolver};
+use crate::helper::boxed;
+use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol, SharedSolver};
 
 
 /// Symbolic memory handling writes and reads involving symbolic
@@ -14,6 +17,7 @@ pub struct SymMemory {
     data: RefCell<MemoryData>,
     solver: SharedSolver,
     strategy: MemoryStrategy,
+    config: MemoryConfig,
 }
 
 /// How the memory handled complex symbolic queries.
@@ -25,6 +29,67 @@ pub enum MemoryStrategy {
     PerfectMatches,
 }
 
+/// Configuration of how never written memory is read.
+#[derive(Debug, Clone)]
+pub struct MemoryConfig {
+    /// The initial contents of memory.
+    pub image: Arc<MemoryImage>,
+    /// The maximum number of addresses a read from a symbolic address into
+    /// a region of the image is resolved to. Larger regions use the policy.
+    pub select_limit: usize,
+    /// How reads from symbolic addresses are handled that neither match a
+    /// previous write for sure nor can be resolved into the image.
+    pub policy: Concretization,
+}
+
+/// How a read from a symbolic address is handled when its value is unknown.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Concretization {
+    /// Produce a fresh symbol for the value (the default).
+    Symbolic,
+    /// Assume one possible value of the address and read from there.
+    Concretize,
+    /// Read from up to the given number of possible values of the address
+    /// and select between them by the address. Other addresses produce a
+    /// fresh symbol.
+    Enumerate(usize),
+    /// Stop the execution and report the read (see `UnknownRead`).
+    Abort,
+}
+
+/// A read from unknown memory at a symbolic address, which stops the
+/// execution under the `Abort` policy.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnknownRead {
+    /// The name of the read memory.
+    pub memory: &'static str,
+    pub addr: SymExpr,
+    pub data_type: DataType,
+}
+
+impl Display for UnknownRead {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "read of {} from unknown {} memory at symbolic address {}",
+               self.data_type, self.memory, self.addr)
+    }
+}
+
+/// The initial contents of memory, like the data sections of a binary.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryImage {
+    regions: BTreeMap<u64, Contents>,
+    /// The starts of the regions whose contents are never written.
+    readonly: BTreeSet<u64>,
+}
+
+/// The contents of a region of the image.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Contents {
+    Bytes(Vec<u8>),
+    /// A number of zeros, which are not stored, like the ones of `.bss`.
+    Zeros(u64),
+}
+
 /// The actual memory data, which is wrapped in an interior mutability type
 /// to make reads on immutable borrows possible while performing some extra work
 /// requiring mutable access.
@@ -34,32 +99,57 @@ struct MemoryData {
     writes: BTreeMap<Epoch, MemoryWrite>,
     symbols: usize,
     epoch: Epoch,
+    /// The read that stopped the execution and was not taken yet.
+    aborted: Option<UnknownRead>,
 }
 
 type Epoch = u32;
 
 /// A piece of data written to memory.
 #[derive(Debug, Clone)]
-struct MemoryWrite {
-    addr: SymExpr,
-    value: SymExpr,
+enum MemoryWrite {
+    /// A single value written to an address.
+    Value { addr: SymExpr, value: SymExpr },
+    /// A region of `len` bytes starting at `addr` filled with repetitions of `value`.
+    Fill { addr: SymExpr, len: SymExpr, value: SymExpr },
+    /// A region of `len` bytes starting at `addr` copied from the region starting at `src`.
+    Copy { addr: SymExpr, len: SymExpr, src: SymExpr },
 }
 
 impl SymMemory {
     /// Create a new blank symbolic memory.
     pub fn new(name: &'static str, strategy: MemoryStrategy, solver: SharedSolver) -> SymMemory {
+        SymMemory::with_config(name, strategy, solver, MemoryConfig::default())
+    }
+
+    /// Create a new symbolic memory, which is initially filled with the
+    /// contents of the configured image.
+    pub fn with_config(
+        name: &'static str,
+        strategy: MemoryStrategy,
+        solver: SharedSolver,
+        config: MemoryConfig,
+    ) -> SymMemory {
         SymMemory {
             data: RefCell::new(MemoryData {
                 name,
                 writes: BTreeMap::new(),
                 symbols: 0,
                 epoch: 1,
+                aborted: None,
             }),
             solver,
             strategy,
+            config,
         }
     }
 
+    /// The read that stopped the execution under the `Abort` policy since
+    /// the last call, if any.
+    pub fn take_unknown_read(&self) -> Option<UnknownRead> {
+        self.data.borrow_mut().aborted.take()
+    }
+
     /// Read from a direct address.
     pub fn read_direct(&self, addr: u64, data_type: DataType) -> SymExpr {
         self.read_expr(SymExpr::from_ptr(addr), data_type)
@@ -73,105 +163,258 @@ impl SymMemory {
     /// Read from a symbolic address.
     pub fn read_expr(&self, addr: SymExpr, data_type: DataType) -> SymExpr {
         crate::timings::with("sym-mem", || {
-            let expr = match self.strategy {
-                MemoryStrategy::PerfectMatches => self.read_perfect(addr, data_type),
-                MemoryStrategy::ConditionalTrees => self.read_conditional(addr, data_type),
-            };
-
-            if expr.data_type() == data_type { expr } else { expr.cast(data_type, false) }
+            let mut data = self.data.borrow_mut();
+            self.read_before(&mut data, addr, data_type, Epoch::MAX)
         })
     }
 
-    /// Read from memory using the perfect matches strategy.
+    /// Read from memory with the strategy of this memory, only considering
+    /// writes before the given epoch.
+    fn read_before(&self, data: &mut MemoryData, addr: SymExpr, data_type: DataType, before: Epoch)
+    -> SymExpr {
+        let expr = match self.strategy {
+            MemoryStrategy::PerfectMatches => self.read_perfect(data, addr, data_type, before),
+            MemoryStrategy::ConditionalTrees => self.read_conditional(data, addr, data_type, before),
+        };
+
+        if expr.data_type() == data_type { expr } else { expr.cast(data_type, false) }
+    }
+
+    /// Read from memory using the perfect matches strategy, only considering
+    /// writes before the given epoch.
     ///
     /// This will only return the value if the address expression match
     /// perfectly. This is not sound but way faster than `read_conditional`.
-    fn read_perfect(&self, addr: SymExpr, data_type: DataType) -> SymExpr {
-        let mut data = self.data.borrow_mut();
+    fn read_perfect(&self, data: &mut MemoryData, addr: SymExpr, data_type: DataType, before: Epoch)
+    -> SymExpr {
+        let mut copied = None;
 
-        for (_, write) in data.writes.iter().rev() {
-            if write.addr == addr {
-                return write.value.clone();
+        for (&epoch, write) in data.writes.range(.. before).rev() {
+            match write {
+                MemoryWrite::Value { addr: start, value } if *start == addr => {
+                    return value.clone();
+                },
+                MemoryWrite::Fill { addr: start, value, .. } if *start == addr => {
+                    return repeat(value.clone(), data_type);
+                },
+                MemoryWrite::Copy { addr: start, src, .. } if *start == addr => {
+                    copied = Some((src.clone(), epoch));
+                    break;
+                },
+                _ => {},
             }
         }
 
-        data.generate_default_symbol(addr, data_type)
+        match copied {
+            Some((src, epoch)) => self.read_perfect(data, src, data_type, epoch),
+            None => self.read_initial(data, addr, data_type, before),
+        }
     }
 
-    /// Read from memory using the conditional trees strategy.
+    /// Read from memory using the conditional trees strategy, only considering
+    /// writes before the given epoch.
     ///
     /// This will go through the write map from newest to oldest and build
     /// an if-then-else chain with all values that possibly match and the
-    /// conditions under which they match.
-    fn read_conditional(&self, addr: SymExpr, data_type: DataType) -> SymExpr {
-        let mut data = self.data.borrow_mut();
-
-        let default = data.get_default_value(data_type);
-        let mut tree = default.clone();
+    /// conditions under which they match. Reads from copied regions continue
+    /// at the source of the copy with the writes that happened before it.
+    fn read_conditional(&self, data: &mut MemoryData, addr: SymExpr, data_type: DataType, before: Epoch)
+    -> SymExpr {
+        // The innermost else-branch is a placeholder until we know whether
+        // a default symbol is needed.
+        let mut tree = data.get_default_value(data_type);
         let mut active = &mut tree;
         let mut used_default_symbol = true;
 
         // We traverse the memory writes from latest to oldest. If we find
         // a write that perfectly matches our read, we can quit early
         // because anything before would have been overwritten for sure.
-        for (_, write) in data.writes.iter().rev() {
-            // If it matches perfectly, we can stop here.
-            if write.addr == addr {
-                *active = write.value.clone();
-                used_default_symbol = false;
-                break;
+        let epochs: Vec<Epoch> = data.writes.range(.. before).rev().map(|(&epoch, _)| epoch).collect();
+        for epoch in epochs {
+            let condition = match &data.writes[&epoch] {
+                MemoryWrite::Value { addr: start, value } => {
+                    // If it matches perfectly, we can stop here.
+                    if *start == addr {
+                        *active = value.clone();
+                        used_default_symbol = false;
+                        break;
+                    }
+
+                    if !self.solver.check_equal_sat(start, &addr) {
+                        continue;
+                    }
+
+                    start.clone().equal(addr.clone())
+                },
+                MemoryWrite::Fill { addr: start, len, .. } |
+                MemoryWrite::Copy { addr: start, len, .. } => {
+                    in_region(start, len, &addr, data_type)
+                },
+            };
+
+            let simplified = self.solver.simplify_condition(&condition);
+            if simplified == SymCondition::FALSE {
+                continue;
             }
 
-            if self.solver.check_equal_sat(&write.addr, &addr) {
-                let condition = write.addr.clone().equal(addr.clone());
-                let simplified = self.solver.simplify_condition(&condition);
+            let value = match data.writes[&epoch].clone() {
+                MemoryWrite::Value { value, .. } => value,
+                MemoryWrite::Fill { value, .. } => repeat(value, data_type),
+                MemoryWrite::Copy { addr: start, src, .. } => {
+                    let offset = addr.clone().sub(start);
+                    self.read_conditional(data, src.add(offset), data_type, epoch)
+                },
+            };
 
-                // If it didn't match perfectly but still always is the same thing
-                // we can also stop here.
-                if simplified == SymCondition::TRUE {
-                    *active = write.value.clone();
-                    used_default_symbol = false;
-                    break;
+            // If it didn't match perfectly but still always is the same thing
+            // we can also stop here.
+            if simplified == SymCondition::TRUE {
+                *active = value;
+                used_default_symbol = false;
+                break;
 
-                } else {
-                    *active = simplified.if_then_else(write.value.clone(), default.clone());
-                    active = match active {
-                        SymExpr::IfThenElse(_, _, ref mut b) => b,
-                        _ => panic!("read_conditional: expected if-then-else"),
-                    };
-                }
+            } else {
+                *active = SymExpr::IfThenElse(
+                    boxed(simplified),
+                    boxed(value),
+                    boxed(data.get_default_value(data_type)),
+                );
+                active = match active {
+                    SymExpr::IfThenElse(_, _, ref mut b) => b,
+                    _ => panic!("read_conditional: expected if-then-else"),
+                };
             }
         }
 
         if used_default_symbol {
-            data.generate_default_symbol(addr, data_type);
+            *active = self.read_initial(data, addr, data_type, before);
         }
 
         tree
     }
 
+    /// Read never written memory. Concrete addresses read from the image
+    /// and symbolic ones select from the addresses they can take in a region
+    /// of it. Everything else is handled by the concretization policy, whose
+    /// concrete addresses are read again considering the writes before the epoch.
+    fn read_initial(&self, data: &mut MemoryData, addr: SymExpr, data_type: DataType, before: Epoch)
+    -> SymExpr {
+        let len = data_type.bytes() as u64;
+        if len > 8 {
+            return data.generate_default_symbol(addr, data_type);
+        }
+
+        let image = &self.config.image;
+        if let SymExpr::Int(Integer(_, ptr)) = addr {
+            return match image.read(ptr, len) {
+                Some(bytes) => SymExpr::Int(Integer::from_bytes(&bytes, data_type)),
+                None => data.generate_default_symbol(addr, data_type),
+            };
+        }
+
+        // Find the region the constant part of the address points into.
+        let anchored = constant_part(&addr).and_then(|anchor| image.region(anchor));
+
+        // Regions of zeros read as zero no matter where in them the address
+        // points, so they are not limited in size.
+        if let Some((start, size)) = anchored.filter(|&(start, _)| image.is_zeros(start)) {
+            let (start, size) = (SymExpr::from_ptr(start), SymExpr::from_ptr(size));
+            let inside = in_region(&start, &size, &addr, data_type);
+            let outside = data.generate_default_symbol(addr.clone(), data_type);
+            let value = inside.if_then_else(SymExpr::from_int(data_type, 0), outside);
+            data.writes.insert(0, MemoryWrite::Value { addr, value: value.clone() });
+            return value;
+        }
+
+        // Find all addresses in the region the read could access.
+        let region = anchored
+            .filter(|&(_, size)| size >= len && (size - len) < self.config.select_limit as u64);
+
+        if let Some((start, size)) = region {
+            let candidates: Vec<u64> = (start ..= start + size - len)
+                .filter(|&ptr| self.solver.check_equal_sat(&addr, &SymExpr::from_ptr(ptr)))
+                .collect();
+
+            // The address could still point outside of the region.
+            let mut value = data.generate_default_symbol(addr.clone(), data_type);
+            for &ptr in candidates.iter().rev() {
+                let bytes = image.read(ptr, len).expect("read_initial: expected bytes in region");
+                let selected = SymExpr::Int(Integer::from_bytes(&bytes, data_type));
+                let condition = addr.clone().equal(SymExpr::from_ptr(ptr));
+                value = condition.if_then_else(selected, value);
+            }
+
+            // Later reads from the same address select the same value.
+            data.writes.insert(0, MemoryWrite::Value { addr, value: value.clone() });
+            return value;
+        }
+
+        match self.config.policy {
+            Concretization::Symbolic => data.generate_default_symbol(addr, data_type),
+            Concretization::Concretize => match self.solver.examples(&addr, 1).first() {
+                Some(&ptr) => self.read_before(data, SymExpr::Int(ptr), data_type, before),
+                None => data.generate_default_symbol(addr, data_type),
+            },
+            Concretization::Enumerate(count) => {
+                let models = self.solver.examples(&addr, count);
+                let mut value = data.generate_default_symbol(addr.clone(), data_type);
+                for &ptr in models.iter().rev() {
+                    let selected = self.read_before(data, SymExpr::Int(ptr), data_type, before);
+                    let condition = addr.clone().equal(SymExpr::Int(ptr));
+                    value = condition.if_then_else(selected, value);
+                }
+                data.writes.insert(0, MemoryWrite::Value { addr, value: value.clone() });
+                value
+            },
+            // The read still produces a value, but the execution stops.
+            Concretization::Abort => {
+                let read = UnknownRead { memory: data.name, addr: addr.clone(), data_type };
+                data.aborted.get_or_insert(read);
+                data.generate_default_symbol(addr, data_type)
+            },
+        }
+    }
+
     /// Write a value to a symbolic address.
     pub fn write_expr(&mut self, addr: SymExpr, value: SymExpr) {
         crate::timings::with("sym-mem", || {
             let mut data = self.data.borrow_mut();
 
-            let new_write = MemoryWrite { addr, value };
-
+            // Overwrite an older write to the same address, but only if no
+            // region write could have read it in between.
             for (_, write) in data.writes.iter_mut().rev() {
-                if write.addr == new_write.addr {
-                    *write = new_write;
-                    return;
+                match write {
+                    MemoryWrite::Value { addr: start, value: old } => if *start == addr {
+                        *old = value;
+                        return;
+                    },
+                    _ => break,
                 }
             }
 
-            let epoch = data.epoch;
-            data.writes.insert(epoch, new_write);
-            data.epoch += 1;
+            data.insert(MemoryWrite::Value { addr, value });
         })
     }
+
+    /// Fill the `len` bytes starting at `addr` with repetitions of `value`.
+    pub fn write_fill(&mut self, addr: SymExpr, len: SymExpr, value: SymExpr) {
+        self.data.borrow_mut().insert(MemoryWrite::Fill { addr, len, value });
+    }
+
+    /// Copy the `len` bytes starting at `src` to the region starting at `addr`.
+    pub fn write_copy(&mut self, addr: SymExpr, src: SymExpr, len: SymExpr) {
+        self.data.borrow_mut().insert(MemoryWrite::Copy { addr, len, src });
+    }
 }
 
 impl MemoryData {
+    /// Insert a write with a new epoch.
+    fn insert(&mut self, write: MemoryWrite) {
+        let epoch = self.epoch;
+        self.writes.insert(epoch, write);
+        self.epoch += 1;
+    }
+
     /// Get the value for the next default symbol that would be generated.
     fn get_default_value(&self, data_type: DataType) -> SymExpr {
         SymExpr::Sym(Symbol(data_type, self.name, self.symbols))
@@ -180,7 +423,7 @@ impl MemoryData {
     /// Generate a default symbol for uninitialized memory.
     fn generate_default_symbol(&mut self, addr: SymExpr, data_type: DataType) -> SymExpr {
         let value = self.get_default_value(data_type);
-        self.writes.insert(0, MemoryWrite {
+        self.writes.insert(0, MemoryWrite::Value {
             addr,
             value: value.clone(),
         });
@@ -189,14 +432,204 @@ impl MemoryData {
     }
 }
 
+impl Default for MemoryConfig {
+    fn default() -> MemoryConfig {
+        MemoryConfig {
+            image: Arc::new(MemoryImage::default()),
+            select_limit: 256,
+            policy: Concretization::Symbolic,
+        }
+    }
+}
+
+impl MemoryImage {
+    /// Create an empty image.
+    pub fn new() -> MemoryImage {
+        MemoryImage::default()
+    }
+
+    /// Whether the image has no contents.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Add a region with its contents starting at the address.
+    pub fn insert(&mut self, addr: u64, bytes: Vec<u8>) {
+        if !bytes.is_empty() {
+            self.regions.insert(addr, Contents::Bytes(bytes));
+        }
+    }
+
+    /// Add a region of `size` zeros starting at the address like the one of
+    /// `.bss`, whose contents take no space no matter its size.
+    pub fn insert_zeros(&mut self, addr: u64, size: u64) {
+        if size > 0 {
+            self.regions.insert(addr, Contents::Zeros(size));
+        }
+    }
+
+    /// Add a region like `insert` whose contents are never written, like
+    /// the ones of `.rodata`.
+    pub fn insert_readonly(&mut self, addr: u64, bytes: Vec<u8>) {
+        if !bytes.is_empty() {
+            self.readonly.insert(addr);
+        }
+        self.insert(addr, bytes);
+    }
+
+    /// Whether the address is in a region whose contents are never written.
+    pub fn is_readonly(&self, addr: u64) -> bool {
+        self.region(addr).map_or(false, |(start, _)| self.readonly.contains(&start))
+    }
+
+    /// Whether the address is in a region added by `insert_zeros`.
+    pub fn is_zeros(&self, addr: u64) -> bool {
+        self.region(addr).map_or(false, |(start, _)| {
+            matches!(self.regions[&start], Contents::Zeros(_))
+        })
+    }
+
+    /// The start and size of the region containing the address.
+    pub fn region(&self, addr: u64) -> Option<(u64, u64)> {
+        let (&start, contents) = self.regions.range(..= addr).next_back()?;
+        let size = match contents {
+            Contents::Bytes(bytes) => bytes.len() as u64,
+            Contents::Zeros(size) => *size,
+        };
+        if addr - start < size { Some((start, size)) } else { None }
+    }
+
+    /// The `len` bytes at the address if they are all in one region.
+    pub fn read(&self, addr: u64, len: u64) -> Option<Cow<'_, [u8]>> {
+        let (start, size) = self.region(addr)?;
+        let offset = addr - start;
+        if offset + len > size {
+            return None;
+        }
+        match &self.regions[&start] {
+            Contents::Bytes(bytes) => {
+                Some(Cow::Borrowed(&bytes[offset as usize .. (offset + len) as usize]))
+            },
+            Contents::Zeros(_) => Some(Cow::Owned(vec![0; len as usize])),
+        }
+    }
+}
+
+/// The constant summand of an address expression.
+fn constant_part(addr: &SymExpr) -> Option<u64> {
+    match addr {
+        SymExpr::Add(a, b) => match (&**a, &**b) {
+            (_, SymExpr::Int(Integer(_, value))) | (SymExpr::Int(Integer(_, value)), _) => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The condition under which a read of the data type at `addr` lies
+/// completely in the region of `len` bytes at `start`.
+fn in_region(start: &SymExpr, len: &SymExpr, addr: &SymExpr, data_type: DataType) -> SymCondition {
+    let size = SymExpr::from_ptr(data_type.bytes() as u64);
+    let after = addr.clone().add(size);
+    let end = start.clone().add(len.clone());
+    start.clone().less_equal(addr.clone(), false).and(after.less_equal(end, false))
+}
+
+/// Repeat a fill value to match the data type of a read.
+fn repeat(value: SymExpr, data_type: DataType) -> SymExpr {
+    let bits = value.data_type().bits();
+    if value.data_type() == data_type || bits > data_type.bits() {
+        value
+    } else {
+        SymExpr::from_lanes(vec![value; data_type.bits() / bits], data_type)
+    }
+}
+
 impl Display for SymMemory {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SymMemory [")?;
         let data = self.data.borrow();
         if !data.writes.is_empty() { writeln!(f)?; }
         for (epoch, write) in &data.writes {
-            writeln!(f, "    [{}] {} => {}", epoch, write.addr, write.value)?;
+            match write {
+                MemoryWrite::Value { addr, value } => {
+                    writeln!(f, "    [{}] {} => {}", epoch, addr, value)?;
+                },
+                MemoryWrite::Fill { addr, len, value } => {
+                    writeln!(f, "    [{}] {} (+{}) => fill {}", epoch, addr, len, value)?;
+                },
+                MemoryWrite::Copy { addr, len, src } => {
+                    writeln!(f, "    [{}] {} (+{}) => copy {}", epoch, addr, len, src)?;
+                },
+            }
         }
         writeln!(f, "]")
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::math::Solver;
+    use super::*;
+
+    #[test]
+    fn image() {
+        let mut image = MemoryImage::new();
+        image.insert(0x1000, vec![1, 2, 3, 4]);
+        image.insert(0x2000, vec![5; 16]);
+
+        assert_eq!(image.region(0x1003), Some((0x1000, 4)));
+        assert_eq!(image.region(0x1004), None);
+        assert_eq!(image.read(0x1001, 2).as_deref(), Some(&[2, 3][..]));
+        assert_eq!(image.read(0x1002, 4), None);
+        assert_eq!(image.read(0x200c, 4).as_deref(), Some(&[5; 4][..]));
+
+        let addr = SymExpr::Sym(Symbol(DataType::N64, "stdin", 0)).add(SymExpr::from_ptr(0x2000));
+        assert_eq!(constant_part(&addr), Some(0x2000));
+    }
+
+    #[test]
+    fn initial_reads() {
+        let mut image = MemoryImage::new();
+        image.insert(0x1000, vec![1, 2, 3, 4]);
+        image.insert_zeros(0x2000, 1 << 40);
+        assert_eq!(image.region(0x2000 + (1 << 39)), Some((0x2000, 1 << 40)));
+        assert_eq!(image.read(0x2000 + (1 << 39), 8).as_deref(), Some(&[0; 8][..]));
+        assert!(image.is_zeros(0x2000) && !image.is_zeros(0x1000));
+
+        let config = MemoryConfig { image: Arc::new(image), .. MemoryConfig::default() };
+        let solver = Rc::new(Solver::new());
+        let memory = SymMemory::with_config("mem", MemoryStrategy::ConditionalTrees, solver, config);
+        let stdin = Symbol(DataType::N64, "stdin", 0);
+        let at = |offset| SymExpr::Sym(stdin).add(SymExpr::from_ptr(offset));
+        let value = |expr: &SymExpr, index| expr.evaluate(&|symbol: Symbol| match symbol == stdin {
+            true => Some(Integer::from_ptr(index)),
+            false => Some(Integer(symbol.0, 0xff)),
+        }).1;
+
+        // A symbolic address into a small region selects between its bytes.
+        let small = memory.read_expr(at(0x1000), DataType::N8);
+        assert_eq!((value(&small, 2), value(&small, 4)), (3, 0xff));
+
+        // One into a region of zeros reads zero anywhere in it, however large it is.
+        let huge = memory.read_expr(at(0x2000), DataType::N32);
+        assert_eq!((value(&huge, 1 << 39), value(&huge, 1 << 40)), (0, 0xff));
+    }
+
+    #[test]
+    fn abort() {
+        let config = MemoryConfig { policy: Concretization::Abort, .. MemoryConfig::default() };
+        let solver = Rc::new(Solver::new());
+        let memory = SymMemory::with_config("mem", MemoryStrategy::ConditionalTrees, solver, config);
+        let addr = SymExpr::Sym(Symbol(DataType::N64, "stdin", 0));
+        memory.read_expr(addr.clone(), DataType::N8);
+
+        let read = memory.take_unknown_read().unwrap();
+        assert_eq!(read, UnknownRead { memory: "mem", addr, data_type: DataType::N8 });
+        let report = "read of n8 from unknown mem memory at symbolic address stdin0:n64";
+        assert_eq!(read.to_string(), report);
+        assert_eq!(memory.take_unknown_read(), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! Modeling of memory-mapped I/O.
+//!
+//! Firmware talks to devices through registers mapped at fixed addresses,
+//! whose values change outside of the program and whose writes have effects
+//! outside of it. Accesses to concrete addresses in the configured regions
+//! are therefore no ordinary memory accesses: every read produces a fresh
+//! symbol of the family `mmio`, which the state remembers as an input from
+//! the register like `register 0x4 of uart`, and every write is an output to
+//! the device. Both are reported as events, for which the data dependency
+//! graph adds I/O nodes like for the standard streams.
+
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+
+use crate::ir::Location;
+use crate::math::{SymExpr, DataType, Integer, Symbol};
+use super::{SymState, Event, StdioKind, TypedMemoryAccess, Input, InputSource};
+
+
+/// A range of addresses where the registers of a device are mapped.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MmioRegion {
+    /// The name of the device, which describes the inputs from it.
+    pub name: String,
+    pub range: Range<u64>,
+}
+
+impl MmioRegion {
+    /// Create a region for the device with the name.
+    pub fn new(name: &str, range: Range<u64>) -> MmioRegion {
+        MmioRegion { name: name.to_string(), range }
+    }
+}
+
+impl Display for MmioRegion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {:#x}..{:#x}", self.name, self.range.start, self.range.end)
+    }
+}
+
+impl SymState {
+    /// The concrete address if it lies in a configured region of memory-mapped
+    /// I/O.
+    pub fn device(&self, addr: &SymExpr) -> Option<u64> {
+        match addr {
+            SymExpr::Int(Integer(_, addr)) if self.config.mmio_region(*addr).is_some() => {
+                Some(*addr)
+            },
+            _ => None,
+        }
+    }
+
+    /// The address of the location if it is memory-mapped I/O.
+    pub(super) fn device_location(&self, location: Location) -> Option<u64> {
+        if self.config.mmio.is_empty() {
+            return None;
+        }
+        match location {
+            Location::Direct(_, 0, addr) => self.device(&SymExpr::from_ptr(addr)),
+            Location::Indirect(_, 0, temp) => self.device(&self.get_temp(temp)),
+            _ => None,
+        }
+    }
+
+    /// Access the device register at the address with a fresh symbol, which
+    /// stands for the value read or names the write. Returns the symbol and
+    /// the event reporting the access.
+    pub(super) fn access_device(
+        &mut self,
+        kind: StdioKind,
+        addr: u64,
+        data_type: DataType,
+    ) -> (Symbol, Event) {
+        let symbol = Symbol(data_type, "mmio", self.mmio_symbols);
+        self.mmio_symbols += 1;
+
+        if kind == StdioKind::MmioRead {
+            let region = self.config.mmio_region(addr).expect("access_device: no region");
+            let source = InputSource::Device(region.name.clone());
+            let input = Input { source, offset: addr - region.range.start };
+            self.inputs.insert(symbol, input);
+        }
+
+        let access = TypedMemoryAccess(SymExpr::from_ptr(addr), data_type);
+        (symbol, Event::Device(kind, symbol, access))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use crate::ir::{MicroOperation, Temporary};
+    use crate::math::Solver;
+    use crate::sym::{MemoryStrategy, SymConfig, describe_inputs};
+    use super::*;
+
+    #[test]
+    fn mmio() {
+        let config = SymConfig {
+            mmio: vec![MmioRegion::new("uart", 0x4000_0000 .. 0x4000_1000)],
+            .. SymConfig::default()
+        };
+        let solver = Rc::new(Solver::new());
+        let mut state = SymState::with_config(
+            MemoryStrategy::ConditionalTrees, solver, Arc::new(config));
+
+        let addr = Temporary(DataType::N64, 0);
+        let value = Temporary(DataType::N32, 1);
+        let status = SymExpr::from_ptr(0x4000_0004);
+        state.set_temp(addr, status.clone());
+        state.set_temp(value, SymExpr::from_int(DataType::N32, 1));
+
+        // Writes are outputs, but do not determine what is read back.
+        let write = MicroOperation::Mov {
+            dest: Location::Indirect(DataType::N32, 0, addr),
+            src: Location::Temp(value),
+        };
+        let read = MicroOperation::Mov {
+            dest: Location::Temp(value),
+            src: Location::Indirect(DataType::N32, 0, addr),
+        };
+        let written = TypedMemoryAccess(status.clone(), DataType::N32);
+        let symbol = Symbol(DataType::N32, "mmio", 0);
+        assert_eq!(state.step(0x1000, &write),
+                   Some(Event::Device(StdioKind::MmioWrite, symbol, written.clone())));
+        for index in 1 .. 3 {
+            let symbol = Symbol(DataType::N32, "mmio", index);
+            assert_eq!(state.step(0x1004, &read),
+                       Some(Event::Device(StdioKind::MmioRead, symbol, written.clone())));
+            assert_eq!(state.get_temp(value), SymExpr::Sym(symbol));
+        }
+        assert!(!state.inputs.contains_key(&Symbol(DataType::N32, "mmio", 0)));
+        assert_eq!(describe_inputs(state.inputs.values()), vec!["register 0x4 of uart"]);
+
+        // Other memory reads back what was written.
+        state.set_temp(addr, SymExpr::from_ptr(0x4000_1000));
+        assert_eq!(state.step(0x1008, &write), None);
+        state.set_temp(value, SymExpr::from_int(DataType::N32, 1));
+        assert_eq!(state.step(0x100c, &read), None);
+        assert_eq!(state.get_temp(value), SymExpr::Sym(Symbol(DataType::N32, "mmio", 2)));
+    }
+}
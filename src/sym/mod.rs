@@ -1,16 +1,26 @@
 //! Symbolic microcode execution.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
 
-use crate::flow::{AbstractLocation, StorageLocation};
-use crate::ir::{MicroOperation, Location, Temporary, MemoryMapped};
+use crate::flow::{AbstractLocation, StorageLocation, Monitor, Granularity, ContextPolicy};
+use crate::flow::{RecursionConfig, Spawner, Summaries, Profile, AnalysisScope, RegisterSet};
+use crate::ir::{Microcode, MicroOperation, BitOp, PackedOp, Query, Location, Temporary, Transfer, MemoryMapped};
 use crate::math::{SymExpr, SymCondition, Integer, DataType, Symbol, SharedSolver, Traversed};
-use crate::x86_64::{Instruction, Mnemoic, Register};
+use crate::x86_64::Register;
 use DataType::*;
 
 mod mem;
+mod convention;
+mod heap;
+mod input;
+mod mmio;
 pub use mem::*;
+pub use convention::*;
+pub use heap::*;
+pub use input::*;
+pub use mmio::*;
 
 
 /// The symbolic execution state.
@@ -30,9 +40,158 @@ pub struct SymState {
     pub ip: u64,
     /// The shared SMT solver.
     pub solver: SharedSolver,
+    /// The user configuration of the execution environment.
+    pub config: Arc<SymConfig>,
+    /// The heap regions allocated so far.
+    pub heap: Vec<HeapRegion>,
+    /// The inputs from the environment and from files the symbols stand for.
+    pub inputs: HashMap<Symbol, Input>,
+    /// The files opened so far by their handles.
+    pub files: BTreeMap<u64, OpenFile>,
     /// The number of used symbols.
     stdin_symbols: usize,
     stdout_symbols: usize,
+    env_symbols: usize,
+    file_symbols: usize,
+    net_symbols: usize,
+    messages: usize,
+    float_symbols: usize,
+    unknown_symbols: usize,
+    query_symbols: usize,
+    mmio_symbols: usize,
+}
+
+/// Configuration of the environment the program is executed in.
+#[derive(Debug, Clone, Default)]
+pub struct SymConfig {
+    /// Fixed results (eax, ebx, ecx, edx) of `cpuid` by leaf and subleaf. A
+    /// subleaf of `None` matches every subleaf. Leaves that are not configured
+    /// or queried with a symbolic leaf produce fresh symbols.
+    pub cpuid: HashMap<(u32, Option<u32>), [u32; 4]>,
+    /// Whether registered signal handlers can be entered. Handlers are entered
+    /// right after their registration and return to the registering code.
+    pub signals: bool,
+    /// Addresses of functions saving an execution context like `setjmp`, in
+    /// addition to the ones recognized by their symbol names.
+    pub setjmp: HashSet<u64>,
+    /// Addresses of functions restoring an execution context like `longjmp`.
+    pub longjmp: HashSet<u64>,
+    /// The calling convention of all functions without an override.
+    pub convention: CallingConvention,
+    /// The calling conventions of individual functions by address.
+    pub conventions: HashMap<u64, CallingConvention>,
+    /// How main memory is initialized and read from symbolic addresses.
+    pub memory: MemoryConfig,
+    /// Addresses of functions managing heap memory, in addition to the ones
+    /// recognized by their symbol names like `malloc` and `free`.
+    pub allocators: HashMap<u64, Allocator>,
+    /// Addresses of functions providing input from the environment, from
+    /// files or from sockets, in addition to the ones recognized by their
+    /// symbol names like `getenv`, `read` and `recv`.
+    pub inputs: HashMap<u64, InputFunction>,
+    /// The number of bytes of a single input that get their own symbols, or
+    /// 0 for `INPUT_LIMIT`. Any bytes after them are written by one symbol at
+    /// an unknown offset into the rest of the buffer.
+    pub input_limit: u64,
+    /// Addresses of functions creating threads, in addition to the ones
+    /// recognized by their symbol names like `pthread_create`.
+    pub spawners: HashMap<u64, Spawner>,
+    /// The address ranges where the registers of devices are mapped. Reads
+    /// from them produce fresh symbols standing for inputs from the device
+    /// and writes to them are outputs to it.
+    pub mmio: Vec<MmioRegion>,
+    /// Whether the data flow analysis marks the edges between threads that
+    /// link atomic or fenced accesses as synchronizations, through which the
+    /// write happens before the reads observing it.
+    pub happens_before: bool,
+    /// Whether the data flow analysis and the visualizations leave out the
+    /// instrumentation inserted by the compiler for hardening, like the
+    /// copies of the stack canary, which otherwise link all protected
+    /// functions through the canary.
+    pub strip_instrumentation: bool,
+    /// Whether the data flow analysis leaves out the flows into definitions
+    /// that are dead by the liveness analysis and flow nowhere (see
+    /// `DataDependencyGraph::pruned`).
+    pub prune_dead: bool,
+    /// Whether the program runs without an operating system, so that system
+    /// calls are not emulated as the ones of Linux and stop the execution.
+    pub bare_metal: bool,
+    /// The number of threads for the parts of the analysis that run in
+    /// parallel with the `parallel` feature, or 0 for one per core. The
+    /// paths of the control and data flow explorations are split among the
+    /// threads, each with its own solver, except for searches of a target
+    /// address, profiled explorations and the cloning context policy.
+    pub threads: usize,
+    /// An address to reach instead of exploring exhaustively. The exploration
+    /// continues with the block closest to it in the static control flow and
+    /// stops once a block containing it was explored.
+    pub target: Option<u64>,
+    /// Whether the data flow analysis summarizes the natural loops whose
+    /// paths it follows instead of going around them. Without it, the
+    /// analysis does not terminate for programs with loops.
+    pub summarize_loops: bool,
+    /// How finely the data flow analysis divides memory into locations.
+    pub granularity: Granularity,
+    /// Whether the data flow analysis runs a value-set analysis first and
+    /// assumes that memory operands whose value sets cannot overlap do not
+    /// alias, which skips the solver for them.
+    pub value_sets: bool,
+    /// How the nodes and locations of functions are distinguished by the
+    /// calls leading to them.
+    pub context: ContextPolicy,
+    /// How deep recursive calls are followed and what happens beyond.
+    pub recursion: RecursionConfig,
+    /// Dependence summaries of functions by symbol name, like the ones
+    /// computed in an earlier run or published for a library. Calls of the
+    /// summarized functions are not entered.
+    pub summaries: Summaries,
+    /// Execution counts from real runs, which weight the edges of the control
+    /// flow graph. The exploration continues with the block that ran most
+    /// often unless there is a target address.
+    pub profile: Profile,
+    /// The functions the control flow exploration enters. Calls of functions
+    /// outside of it return with unknown values in the registers the calling
+    /// convention does not preserve.
+    pub scope: AnalysisScope,
+    /// The initial values of registers at the first root, like the stack
+    /// pointer of a bare-metal image. The other registers start with unknown
+    /// values.
+    pub registers: HashMap<Register, u64>,
+    /// The seed of the random choices of the analysis, like the values the
+    /// solver picks for symbolic addresses and jump targets. The same seed
+    /// reproduces the same results. Seeds are folded into 32 bits for the
+    /// solver (see `Solver::with_seed`).
+    pub seed: u64,
+    /// The progress callback and cancellation token of the construction of
+    /// the flow graphs in this environment.
+    pub monitor: Monitor,
+}
+
+impl SymConfig {
+    /// The configured `cpuid` results for the leaf and subleaf if there are any.
+    pub fn cpuid(&self, leaf: u32, subleaf: Option<u32>) -> Option<[u32; 4]> {
+        subleaf.and_then(|subleaf| self.cpuid.get(&(leaf, Some(subleaf))))
+            .or_else(|| self.cpuid.get(&(leaf, None)))
+            .copied()
+    }
+
+    /// The calling convention of the function at the address.
+    pub fn convention(&self, function: u64) -> &CallingConvention {
+        self.conventions.get(&function).unwrap_or(&self.convention)
+    }
+
+    /// The number of bytes of a single input that get their own symbols.
+    pub fn input_limit(&self) -> u64 {
+        match self.input_limit {
+            0 => INPUT_LIMIT,
+            limit => limit,
+        }
+    }
+
+    /// The region of memory-mapped I/O containing the address if there is one.
+    pub fn mmio_region(&self, addr: u64) -> Option<&MmioRegion> {
+        self.mmio.iter().find(|region| region.range.contains(&addr))
+    }
 }
 
 /// When and where to find the symbolic values in memory in a real execution.
@@ -43,32 +202,80 @@ pub type SymbolMap = HashMap<Symbol, AbstractLocation>;
 pub enum Event {
     Jump { target: SymExpr, condition: SymCondition, relative: bool },
     Stdio(StdioKind, Vec<(Symbol, TypedMemoryAccess)>),
+    Signal { signal: u64, handler: u64 },
+    Device(StdioKind, Symbol, TypedMemoryAccess),
+    /// The processor provided a value that is not configured as a fresh symbol.
+    Query(StdioKind, Symbol),
     Exit,
+    /// A read stopped the execution under the `Abort` concretization policy.
+    Abort(UnknownRead),
+    /// A system call that cannot be emulated stopped the execution.
+    Unsupported(UnsupportedSyscall),
+}
+
+/// Why a system call cannot be emulated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum UnsupportedSyscall {
+    /// The number of the system call is symbolic.
+    SymbolicNumber,
+    /// The system call with the number is not emulated.
+    Number(u64),
+    /// A read or write of a symbolic number of bytes.
+    SymbolicCount,
+    /// There is no operating system to handle system calls.
+    NoSystem,
 }
 
-/// Kinds of standard interfaces (stdin or stdout).
+/// Kinds of I/O interfaces (stdin, stdout, memory-mapped device registers or
+/// values provided by the processor).
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum StdioKind {
     Stdin,
     Stdout,
+    MmioRead,
+    MmioWrite,
+    Timestamp,
+    Random,
+    Cpuid,
 }
 
 impl SymState {
     /// Create a blank symbolic state that will use the given solver and strategy for
     /// main memory.
     pub fn new(mem_strategy: MemoryStrategy, solver: SharedSolver) -> SymState {
+        SymState::with_config(mem_strategy, solver, Arc::new(SymConfig::default()))
+    }
+
+    /// Create a new blank symbolic state in the configured environment.
+    pub fn with_config(
+        mem_strategy: MemoryStrategy,
+        solver: SharedSolver,
+        config: Arc<SymConfig>,
+    ) -> SymState {
         SymState {
             temporaries: HashMap::new(),
             memory: [
-                SymMemory::new("mem", mem_strategy, solver.clone()),
+                SymMemory::with_config("mem", mem_strategy, solver.clone(), config.memory.clone()),
                 SymMemory::new("reg", MemoryStrategy::PerfectMatches, solver.clone())
             ],
             symbol_map: SymbolMap::new(),
             trace: Vec::new(),
             ip: 0,
+            heap: Vec::new(),
+            inputs: HashMap::new(),
+            files: BTreeMap::new(),
             stdin_symbols: 0,
             stdout_symbols: 0,
-            solver
+            env_symbols: 0,
+            file_symbols: 0,
+            net_symbols: 0,
+            messages: 0,
+            float_symbols: 0,
+            unknown_symbols: 0,
+            query_symbols: 0,
+            mmio_symbols: 0,
+            solver,
+            config,
         }
     }
 
@@ -82,7 +289,12 @@ impl SymState {
         self.ip = addr;
 
         match operation {
-            Op::Mov { dest, src } => self.do_move(*dest, *src),
+            Op::Mov { dest, src } => {
+                if let Some(event) = self.do_move(*dest, *src) {
+                    crate::timings::stop();
+                    return Some(event);
+                }
+            },
 
             Op::Const { dest, constant } => self.set_temp(*dest, SymExpr::Int(*constant)),
             Op::Cast { target, new, signed } => {
@@ -96,11 +308,61 @@ impl SymState {
 
             Op::And { and, a, b } => self.do_binop(*and, *a, *b, SymExpr::bitand),
             Op::Or { or, a, b } => self.do_binop(*or, *a, *b, SymExpr::bitor),
+            Op::Xor { xor, a, b } => self.do_binop(*xor, *a, *b, SymExpr::bitxor),
             Op::Not { not, a } => self.set_temp(*not, self.get_temp(*a).bitnot()),
 
+            Op::Bits { op, target, a, b } => {
+                self.set_temp(*target, bits(*op, self.get_temp(*a), self.get_temp(*b)));
+            },
+
+            Op::Packed { op, lane, target, a, b } => {
+                let value = packed(*op, *lane, target.0, self.get_temp(*a), self.get_temp(*b));
+                self.set_temp(*target, value);
+            },
+
+            // Floating-point results are not computed, but represented by fresh symbols.
+            Op::Float { target, .. } => {
+                let symbol = Symbol(target.0, "float", self.float_symbols);
+                self.float_symbols += 1;
+                self.set_temp(*target, SymExpr::Sym(symbol));
+            },
+
+            // Bulk operations are recorded as whole regions in main memory.
+            Op::Copy { dest, src, count, data_type } => {
+                let len = self.region_length(*count, *data_type);
+                let (dest, src) = (self.get_temp(*dest), self.get_temp(*src));
+                self.memory[0].write_copy(dest, src, len);
+            },
+            Op::Fill { dest, value, count } => {
+                let len = self.region_length(*count, value.0);
+                let (dest, value) = (self.get_temp(*dest), self.get_temp(*value));
+                self.memory[0].write_fill(dest, len, value);
+            },
+            Op::Unknown { target } => {
+                let value = self.unknown(target.0);
+                self.set_temp(*target, value);
+            },
+            Op::Query { query, target, register } => {
+                let value = self.do_query(*query, *target, *register);
+                self.set_temp(*target, value.clone());
+                if let SymExpr::Sym(symbol) = value {
+                    let kind = match query {
+                        Query::Timestamp => StdioKind::Timestamp,
+                        Query::Random => StdioKind::Random,
+                        Query::Cpuid { .. } => StdioKind::Cpuid,
+                    };
+                    crate::timings::stop();
+                    return Some(Event::Query(kind, symbol));
+                }
+            },
+
             Op::Set { target, condition } => {
                 self.set_temp(*target, self.evaluate_condition(&condition).as_expr(target.0));
             },
+            Op::Select { target, condition, a, b } => {
+                let condition = self.evaluate_condition(condition);
+                self.set_temp(*target, condition.if_then_else(self.get_temp(*a), self.get_temp(*b)));
+            },
             Op::Jump { target, condition, relative } => {
                 crate::timings::stop();
                 return Some(Event::Jump {
@@ -111,27 +373,32 @@ impl SymState {
             },
 
             Op::Syscall => {
+                if self.config.bare_metal {
+                    crate::timings::stop();
+                    return Some(Event::Unsupported(UnsupportedSyscall::NoSystem));
+                }
                 if let SymExpr::Int(int) = self.get_reg(Register::RAX) {
                     if let Some(event) = self.do_syscall(int.1) {
                         crate::timings::stop();
                         return Some(event);
                     }
                 } else {
-                    panic!("step: unhandled symbolic syscall number");
+                    crate::timings::stop();
+                    return Some(Event::Unsupported(UnsupportedSyscall::SymbolicNumber));
                 }
             },
         }
 
         crate::timings::stop();
-        None
+        self.memory.iter().find_map(SymMemory::take_unknown_read).map(Event::Abort)
     }
 
-    /// Adjust the trace based on the instruction.
-    pub fn track(&mut self, instruction: &Instruction, addr: u64) {
+    /// Adjust the trace based on the control transfer of the lifted instruction.
+    pub fn track(&mut self, microcode: &Microcode, addr: u64) {
         // Adjust the trace.
-        match instruction.mnemoic {
-            Mnemoic::Call => self.trace.push(addr),
-            Mnemoic::Ret => { self.trace.pop(); },
+        match microcode.transfer {
+            Transfer::Call => self.trace.push(addr),
+            Transfer::Return => { self.trace.pop(); },
             _ => {},
         };
     }
@@ -164,7 +431,7 @@ impl SymState {
     pub fn get_access_for_storage(&self, location: StorageLocation) -> Option<TypedMemoryAccess> {
         use StorageLocation::*;
         match location {
-            Direct(_) => None,
+            Direct(_) | Flag(_) => None,
             Indirect { data_type, base, scaled_offset, displacement } => Some({
                 let mut addr = self.get_reg(base);
 
@@ -239,20 +506,90 @@ impl SymState {
         self.memory[1].write_direct(reg.address(), value);
     }
 
+    /// Create a fresh symbol for a value that cannot be determined.
+    pub fn unknown(&mut self, data_type: DataType) -> SymExpr {
+        let symbol = Symbol(data_type, "unknown", self.unknown_symbols);
+        self.unknown_symbols += 1;
+        SymExpr::Sym(symbol)
+    }
+
+    /// Write an unknown value at an unknown offset from each of the pointers
+    /// in the registers, for memory changed by a call at the site that is
+    /// not followed, and return where it is written.
+    pub fn havoc(&mut self, site: u64, pointers: RegisterSet) -> Vec<(Symbol, TypedMemoryAccess)> {
+        let mut writes = Vec::new();
+        for reg in pointers.iter() {
+            let offset = self.unknown(N64);
+            let target = self.get_reg(reg).add(offset);
+            let symbol = Symbol(N64, "unknown", self.unknown_symbols);
+            self.unknown_symbols += 1;
+            self.memory[0].write_expr(target.clone(), SymExpr::Sym(symbol));
+
+            let storage = StorageLocation::indirect_reg(N64, reg);
+            let location = AbstractLocation::new(site, self.trace.clone(), storage);
+            self.symbol_map.insert(symbol, location);
+            writes.push((symbol, TypedMemoryAccess(target, N64)));
+        }
+        writes
+    }
+
     /// Do a binary operation.
     fn do_binop<F>(&mut self, target: Temporary, a: Temporary, b: Temporary, binop: F)
     where F: FnOnce(SymExpr, SymExpr) -> SymExpr {
         self.set_temp(target, binop(self.get_temp(a), self.get_temp(b)));
     }
 
-    /// Move a value from a location to another location.
-    fn do_move(&mut self, dest: Location, src: Location) {
+    /// The byte length of a region of `count` values of the data type.
+    fn region_length(&self, count: Temporary, data_type: DataType) -> SymExpr {
+        let count = self.get_temp(count).cast(N64, false);
+        count.mul(SymExpr::from_ptr(data_type.bytes() as u64))
+    }
+
+    /// Move a value from a location to another location. Accesses to
+    /// memory-mapped I/O are reported as events.
+    fn do_move(&mut self, dest: Location, src: Location) -> Option<Event> {
         assert_eq!(dest.data_type(), src.data_type(), "do_move: incompatible data types for move");
+        if let Some(addr) = self.device_location(src) {
+            let (symbol, event) = self.access_device(StdioKind::MmioRead, addr, src.data_type());
+            self.write_location(dest, SymExpr::Sym(symbol));
+            return Some(event);
+        }
+
         let value = self.read_location(src);
         self.write_location(dest, value);
+        let addr = self.device_location(dest)?;
+        Some(self.access_device(StdioKind::MmioWrite, addr, dest.data_type()).1)
     }
 
-    /// Emulate a Linux syscall.
+    /// Retrieve a value from the processor. Values which are not configured are
+    /// fresh symbols found in the register after the current instruction.
+    fn do_query(&mut self, query: Query, target: Temporary, register: Register) -> SymExpr {
+        let space = match query {
+            Query::Timestamp => "tsc",
+            Query::Random => "rand",
+            Query::Cpuid { leaf, subleaf, index } => {
+                let subleaf = match self.get_temp(subleaf) {
+                    SymExpr::Int(Integer(_, subleaf)) => Some(subleaf as u32),
+                    _ => None,
+                };
+                if let SymExpr::Int(Integer(_, leaf)) = self.get_temp(leaf) {
+                    if let Some(results) = self.config.cpuid(leaf as u32, subleaf) {
+                        return SymExpr::from_int(target.0, results[index] as u64);
+                    }
+                }
+                "cpuid"
+            },
+        };
+
+        let symbol = Symbol(target.0, space, self.query_symbols);
+        self.query_symbols += 1;
+
+        let storage = StorageLocation::Direct(register);
+        self.symbol_map.insert(symbol, AbstractLocation::new(self.ip, self.trace.clone(), storage));
+        SymExpr::Sym(symbol)
+    }
+
+    /// Emulate a Linux syscall. Calls that cannot be emulated end the path.
     fn do_syscall(&mut self, num: u64) -> Option<Event> {
         match num {
             // Read from or write to a file descriptor.
@@ -264,7 +601,7 @@ impl SymState {
                 let count = self.get_reg(Register::RDX);
                 let byte_count = match count {
                     SymExpr::Int(Integer(N64, bytes)) => bytes,
-                    _ => panic!("do_syscall: read: unknown byte count"),
+                    _ => return Some(Event::Unsupported(UnsupportedSyscall::SymbolicCount)),
                 };
 
                 let mut locs = vec![];
@@ -304,13 +641,119 @@ impl SymState {
                 Some(Event::Stdio(kind, locs))
             },
 
+            // Register a signal handler. The handler is the first field of
+            // the kernel's signal action structure. Only actual functions and
+            // not the default or ignoring dispositions are reported.
+            13 => {
+                let signal = self.get_reg(Register::RDI);
+                let action = self.get_reg(Register::RSI);
+                self.set_reg(Register::RAX, SymExpr::from_int(N64, 0));
+
+                if let SymExpr::Int(Integer(_, 0)) = action {
+                    return None;
+                }
+
+                match (signal, self.memory[0].read_expr(action, N64)) {
+                    (SymExpr::Int(Integer(_, signal)), SymExpr::Int(Integer(_, handler)))
+                        if handler > 1 => Some(Event::Signal { signal, handler }),
+                    _ => None,
+                }
+            },
+
             // System exit
             60 => Some(Event::Exit),
-            s => panic!("do_syscall: unimplemented syscall number {}", s),
+            num => Some(Event::Unsupported(UnsupportedSyscall::Number(num))),
         }
     }
 }
 
+/// Apply a bit manipulation operation to `a` and `b`.
+fn bits(op: BitOp, a: SymExpr, b: SymExpr) -> SymExpr {
+    let data_type = a.data_type();
+    let width = data_type.bits() as u64;
+    let int = |value| SymExpr::from_int(data_type, value);
+    let bit = |expr: &SymExpr, index| expr.clone().shr(int(index), false).bitand(int(1));
+    let is_set = |expr: &SymExpr, index| bit(expr, index).equal(int(1));
+
+    match op {
+        BitOp::Shl => a.shl(b),
+        BitOp::Shr => a.shr(b, false),
+
+        // Build an if-then-else chain checking the bits from the counted end.
+        BitOp::TrailingZeros => (0 .. width).rev()
+            .fold(int(width), |count, i| is_set(&a, i).if_then_else(int(i), count)),
+        BitOp::LeadingZeros => (0 .. width)
+            .fold(int(width), |count, i| is_set(&a, i).if_then_else(int(width - 1 - i), count)),
+
+        // The k-th selected bit of the mask corresponds to the k-th low bit of
+        // the result, where k is the number of set mask bits below.
+        BitOp::Extract | BitOp::Deposit => {
+            let mut result = int(0);
+            let mut k = int(0);
+            for i in 0 .. width {
+                let moved = if op == BitOp::Extract {
+                    bit(&a, i).shl(k.clone())
+                } else {
+                    bit(&a.clone().shr(k.clone(), false), 0).shl(int(i))
+                };
+                result = result.bitor(is_set(&b, i).if_then_else(moved, int(0)));
+                k = k.add(bit(&b, i));
+            }
+            result
+        },
+    }
+}
+
+/// Apply a packed operation to the lanes of `a` and `b`.
+fn packed(op: PackedOp, lane: DataType, data_type: DataType, a: SymExpr, b: SymExpr) -> SymExpr {
+    let int = |data_type, value| SymExpr::from_int(data_type, value);
+    let ones = int(lane, 0).bitnot();
+    let sign_shift = int(lane, lane.bits() as u64 - 1);
+
+    let a_lanes = a.lanes(lane);
+    let b_lanes = b.lanes(lane);
+    let pairs = a_lanes.iter().cloned().zip(b_lanes);
+
+    let lanes: Vec<SymExpr> = match op {
+        PackedOp::Add => pairs.map(|(a, b)| a.add(b)).collect(),
+        PackedOp::Sub => pairs.map(|(a, b)| a.sub(b)).collect(),
+        PackedOp::Equal => pairs
+            .map(|(a, b)| a.equal(b).if_then_else(ones.clone(), int(lane, 0)))
+            .collect(),
+        PackedOp::Greater => pairs
+            .map(|(a, b)| a.greater_than(b, true).if_then_else(ones.clone(), int(lane, 0)))
+            .collect(),
+
+        // Every byte selects a byte from the same 128-bit half by its low four bits.
+        PackedOp::Shuffle => pairs.enumerate().map(|(i, (_, index))| {
+            let half = &a_lanes[i / 16 * 16 .. i / 16 * 16 + 16];
+            let low = index.clone().bitand(int(N8, 0x0f));
+
+            let mut selected = half[15].clone();
+            for j in (0 .. 15).rev() {
+                selected = low.clone().equal(int(N8, j as u64))
+                    .if_then_else(half[j].clone(), selected);
+            }
+
+            let zeroed = index.bitand(int(N8, 0x80)).equal(int(N8, 0x80));
+            zeroed.if_then_else(int(N8, 0), selected)
+        }).collect(),
+
+        // The mask is scalar, so we can return directly.
+        PackedOp::MoveMask => {
+            let mut mask = int(data_type, 0);
+            for (i, a) in a_lanes.into_iter().enumerate() {
+                let bit = a.shr(sign_shift.clone(), false).cast(data_type, false);
+                let bit = bit.shl(int(data_type, i as u64));
+                mask = if i == 0 { bit } else { mask.bitor(bit) };
+            }
+            return mask;
+        },
+    };
+
+    SymExpr::from_lanes(lanes, data_type)
+}
+
 /// A typed symbolic memory access.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TypedMemoryAccess(pub SymExpr, pub DataType);
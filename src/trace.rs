@@ -0,0 +1,76 @@
+//! Structured logging of the analysis with `tracing` spans and events.
+//!
+//! The phases (load, decode, cfg, ddg, pdg, render) are logged as info spans
+//! ending with an event carrying their duration, solver queries as trace
+//! spans, and the functions and blocks entered during the explorations as
+//! debug and trace events carrying their addresses. Without the `tracing`
+//! feature all of this compiles to nothing.
+
+
+/// A span that is entered until this guard is dropped, which then logs how
+/// long it was entered.
+#[cfg(feature = "tracing")]
+pub(crate) struct Guard {
+    _span: tracing::span::EnteredSpan,
+    name: &'static str,
+    level: tracing::Level,
+    started: std::time::Instant,
+}
+
+/// A guard that does nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Guard;
+
+/// Enter the span of a phase of the analysis.
+#[cfg(feature = "tracing")]
+pub(crate) fn phase(name: &'static str) -> Guard {
+    Guard {
+        _span: tracing::info_span!("phase", name).entered(),
+        name,
+        level: tracing::Level::INFO,
+        started: std::time::Instant::now(),
+    }
+}
+
+/// Enter the span of a solver query of a kind.
+#[cfg(feature = "tracing")]
+pub(crate) fn query(kind: &'static str) -> Guard {
+    Guard {
+        _span: tracing::trace_span!("query", kind).entered(),
+        name: kind,
+        level: tracing::Level::TRACE,
+        started: std::time::Instant::now(),
+    }
+}
+
+/// Log that an exploration entered a function.
+#[cfg(feature = "tracing")]
+pub(crate) fn function(addr: u64, name: Option<&str>) {
+    tracing::debug!(addr = %format_args!("{:#x}", addr), name, "entering function");
+}
+
+/// Log that an exploration reached a block.
+#[cfg(feature = "tracing")]
+pub(crate) fn block(addr: u64, function: Option<&str>) {
+    tracing::trace!(addr = %format_args!("{:#x}", addr), function, "exploring block");
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let micros = self.started.elapsed().as_micros() as u64;
+        match self.level {
+            tracing::Level::INFO => tracing::info!(name = self.name, micros, "finished"),
+            _ => tracing::trace!(name = self.name, micros, "finished"),
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn phase(_name: &'static str) -> Guard { Guard }
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn query(_kind: &'static str) -> Guard { Guard }
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn function(_addr: u64, _name: Option<&str>) {}
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn block(_addr: u64, _function: Option<&str>) {}
@@ -0,0 +1,337 @@
+//! Parsing of the unwinding information for exceptions (`.eh_frame` and
+//! the language-specific data areas in `.gcc_except_table`).
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, LE};
+
+use crate::elf::Section;
+
+
+/// A range of call sites whose exceptions are caught by a landing pad.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct LandingPad {
+    /// The address of the first call site.
+    pub start: u64,
+    /// The address after the last call site.
+    pub end: u64,
+    /// The address of the landing pad.
+    pub pad: u64,
+}
+
+impl LandingPad {
+    /// Whether the address belongs to a call site of this landing pad.
+    pub fn covers(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+/// Find all landing pads of the frame descriptions in the `.eh_frame` section
+/// with language-specific data in the `.gcc_except_table` section.
+pub fn parse_landing_pads(eh_frame: &Section, except_table: &Section) -> UnwindResult<Vec<LandingPad>> {
+    let data = eh_frame.data.as_slice();
+    let base = eh_frame.header.addr;
+
+    let mut pads = Vec::new();
+    let mut entries = HashMap::new();
+    let mut cursor = Cursor::new(data);
+
+    while cursor.position() < data.len() as u64 {
+        let start = cursor.position();
+        let mut length = cursor.read_u32::<LE>()? as u64;
+        if length == 0 {
+            break;
+        } else if length == 0xffff_ffff {
+            length = cursor.read_u64::<LE>()?;
+        }
+
+        // The length and the CIE pointer are measured from the end of the length.
+        let id_position = cursor.position();
+        let id = cursor.read_u32::<LE>()? as u64;
+
+        if id == 0 {
+            entries.insert(start, parse_common_entry(&mut cursor)?);
+        } else {
+            let entry = entries.get(&id_position.wrapping_sub(id))
+                .ok_or(UnwindError::Invalid("frame description without common entry"))?;
+
+            let function = read_pointer(&mut cursor, entry.encoding, base)?
+                .ok_or(UnwindError::Invalid("frame description without function"))?;
+            read_pointer(&mut cursor, entry.encoding & 0x0f, 0)?;
+
+            if entry.sized {
+                read_uleb(&mut cursor)?;
+                if let Some(encoding) = entry.lsda_encoding {
+                    if let Some(lsda) = read_pointer(&mut cursor, encoding, base)? {
+                        if lsda != 0 {
+                            parse_lsda(except_table, lsda, function, &mut pads)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let next = id_position.checked_add(length)
+            .ok_or(UnwindError::Invalid("entry length out of range"))?;
+        cursor.seek(SeekFrom::Start(next))?;
+    }
+
+    Ok(pads)
+}
+
+/// The encodings used by the frame descriptions of a common information entry.
+struct CommonEntry {
+    encoding: u8,
+    lsda_encoding: Option<u8>,
+    sized: bool,
+}
+
+/// Parse the parts of a common information entry following its identifier.
+fn parse_common_entry(cursor: &mut Cursor<&[u8]>) -> UnwindResult<CommonEntry> {
+    let version = cursor.read_u8()?;
+
+    let mut augmentation = Vec::new();
+    loop {
+        match cursor.read_u8()? {
+            0 => break,
+            byte => augmentation.push(byte),
+        }
+    }
+
+    if augmentation.starts_with(b"eh") {
+        return Err(UnwindError::Unsupported("eh augmentation data".to_string()));
+    }
+
+    // Code and data alignment factors and the return address register.
+    read_uleb(cursor)?;
+    read_sleb(cursor)?;
+    if version == 1 { cursor.read_u8()?; } else { read_uleb(cursor)?; }
+
+    let mut entry = CommonEntry { encoding: 0, lsda_encoding: None, sized: false };
+    if augmentation.first() == Some(&b'z') {
+        entry.sized = true;
+        read_uleb(cursor)?;
+        for &kind in &augmentation[1 ..] {
+            match kind {
+                b'L' => entry.lsda_encoding = Some(cursor.read_u8()?),
+                b'R' => entry.encoding = cursor.read_u8()?,
+                b'P' => {
+                    let encoding = cursor.read_u8()?;
+                    read_pointer(cursor, encoding, 0)?;
+                },
+                b'S' | b'B' => {},
+                _ => return Err(UnwindError::Unsupported(
+                    format!("augmentation {:?}", kind as char))),
+            }
+        }
+    }
+
+    Ok(entry)
+}
+
+/// Parse the call site table of the language-specific data area at the
+/// address and add the landing pads of the function.
+fn parse_lsda(
+    table: &Section,
+    addr: u64,
+    function: u64,
+    pads: &mut Vec<LandingPad>,
+) -> UnwindResult<()> {
+    let base = table.header.addr;
+    let offset = addr.checked_sub(base)
+        .filter(|&offset| offset < table.data.len() as u64)
+        .ok_or(UnwindError::Invalid("data area outside of the exception table"))?;
+
+    let mut cursor = Cursor::new(table.data.as_slice());
+    cursor.seek(SeekFrom::Start(offset))?;
+
+    // Landing pads are relative to the function unless specified otherwise.
+    let encoding = cursor.read_u8()?;
+    let landing_base = read_pointer(&mut cursor, encoding, base)?.unwrap_or(function);
+
+    // The type table is only needed for matching the exception types.
+    if cursor.read_u8()? != OMIT {
+        read_uleb(&mut cursor)?;
+    }
+
+    let encoding = cursor.read_u8()?;
+    let length = read_uleb(&mut cursor)?;
+    let end = cursor.position().saturating_add(length);
+
+    while cursor.position() < end {
+        let mut read = || read_pointer(&mut cursor, encoding, base)
+            .and_then(|value| value.ok_or(UnwindError::Invalid("omitted call site value")));
+        let (start, len, pad) = (read()?, read()?, read()?);
+        read_uleb(&mut cursor)?;
+
+        if pad != 0 {
+            pads.push(LandingPad {
+                start: function.wrapping_add(start),
+                end: function.wrapping_add(start).wrapping_add(len),
+                pad: landing_base.wrapping_add(pad),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The pointer encoding for omitted values.
+const OMIT: u8 = 0xff;
+
+/// Read a pointer in the given encoding. Program-counter relative pointers are
+/// resolved with the address of the section the cursor reads from.
+fn read_pointer(cursor: &mut Cursor<&[u8]>, encoding: u8, base: u64) -> UnwindResult<Option<u64>> {
+    if encoding == OMIT {
+        return Ok(None);
+    }
+
+    let position = base.wrapping_add(cursor.position());
+    let value = match encoding & 0x0f {
+        0x00 | 0x04 => cursor.read_u64::<LE>()?,
+        0x01 => read_uleb(cursor)?,
+        0x02 => cursor.read_u16::<LE>()? as u64,
+        0x03 => cursor.read_u32::<LE>()? as u64,
+        0x09 => read_sleb(cursor)? as u64,
+        0x0a => cursor.read_i16::<LE>()? as i64 as u64,
+        0x0b => cursor.read_i32::<LE>()? as i64 as u64,
+        0x0c => cursor.read_i64::<LE>()? as u64,
+        format => return Err(UnwindError::Unsupported(format!("pointer format {:#x}", format))),
+    };
+
+    Ok(Some(match encoding & 0x70 {
+        0x10 => position.wrapping_add(value),
+        _ => value,
+    }))
+}
+
+/// Read an unsigned LEB128 number.
+pub(crate) fn read_uleb<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// Read a signed LEB128 number.
+pub(crate) fn read_sleb<R: Read>(reader: &mut R) -> io::Result<i64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as i64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1 << shift;
+            }
+            return Ok(value);
+        }
+    }
+}
+
+
+/// The error type for parsing unwinding information.
+pub enum UnwindError {
+    Invalid(&'static str),
+    Unsupported(String),
+    Io(io::Error),
+}
+
+pub(in super) type UnwindResult<T> = Result<T, UnwindError>;
+
+impl Display for UnwindError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnwindError::Invalid(message) => write!(f, "Invalid unwinding information: {}", message),
+            UnwindError::Unsupported(what) => write!(f, "Unsupported unwinding information: {}", what),
+            UnwindError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for UnwindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UnwindError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+debug_display!(UnwindError);
+
+impl From<io::Error> for UnwindError {
+    fn from(err: io::Error) -> UnwindError {
+        UnwindError::Io(err)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::elf::SectionHeader;
+    use super::*;
+
+    fn section(addr: u64, data: Vec<u8>) -> Section {
+        let header = SectionHeader {
+            name: String::new(),
+            name_offset: 0,
+            section_type: 0,
+            flags: 0,
+            addr,
+            offset: 0,
+            size: data.len() as u64,
+            link: 0,
+            info: 0,
+            addr_align: 0,
+            entry_size: 0,
+        };
+        Section { header, data }
+    }
+
+    #[test]
+    fn landing_pads() {
+        let eh_frame = section(0x2000, vec![
+            // Common entry with augmentation "zLR", pc-relative sdata4 pointers.
+            0x14, 0, 0, 0, 0, 0, 0, 0,
+            1, b'z', b'L', b'R', 0, 1, 0x78, 0x10, 2, 0x1b, 0x1b, 0, 0, 0, 0, 0,
+            // Frame description for 0x1000 .. 0x1040 with data area at 0x3000.
+            0x14, 0, 0, 0, 0x1c, 0, 0, 0,
+            0xe0, 0xef, 0xff, 0xff, 0x40, 0, 0, 0,
+            4, 0xd7, 0x0f, 0, 0, 0, 0, 0,
+            // Terminator.
+            0, 0, 0, 0,
+        ]);
+
+        let except_table = section(0x3000, vec![
+            // Relative to the function, no type table, uleb128 call sites.
+            0xff, 0xff, 0x01, 8,
+            // Call sites 0x1010 .. 0x1015 land at 0x1030, 0x1020 .. 0x1025 nowhere.
+            0x10, 0x05, 0x30, 0x00,
+            0x20, 0x05, 0x00, 0x00,
+        ]);
+
+        let pads = parse_landing_pads(&eh_frame, &except_table).unwrap();
+        assert_eq!(pads, vec![LandingPad { start: 0x1010, end: 0x1015, pad: 0x1030 }]);
+        assert!(pads[0].covers(0x1010));
+        assert!(!pads[0].covers(0x1015));
+    }
+
+    #[test]
+    fn leb128() {
+        assert_eq!(read_uleb(&mut Cursor::new(&[0xe5, 0x8e, 0x26][..])).unwrap(), 624485);
+        assert_eq!(read_sleb(&mut Cursor::new(&[0xc0, 0xbb, 0x78][..])).unwrap(), -123456);
+    }
+}
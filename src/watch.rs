@@ -0,0 +1,131 @@
+//! Re-analysis of a binary whenever it changes on disk.
+//!
+//! The file is polled for changes of its modification time and size, which
+//! needs no support from the operating system. Since compilers and linkers
+//! write their output in several steps, a change is only analyzed once the
+//! file stayed the same for one more poll. The graphs are kept in an
+//! `AnalysisCache`, so rebuilding an identical binary analyzes nothing and a
+//! changed one only the changed functions and their callers.
+
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::Program;
+use crate::flow::{AnalysisCache, FunctionChanges};
+
+
+/// Watches a binary and keeps its graphs up to date.
+#[derive(Debug)]
+pub struct Watcher {
+    path: PathBuf,
+    /// The time between polls.
+    pub interval: Duration,
+    cache: Option<AnalysisCache>,
+    /// The state of the file at the last poll.
+    seen: Option<Stamp>,
+    /// The state of the file that was last analyzed or failed to load.
+    analyzed: Option<Stamp>,
+}
+
+/// The modification time and size of a file.
+type Stamp = (SystemTime, u64);
+
+impl Watcher {
+    /// Watch the binary at the path, polling twice per second.
+    pub fn new<P: AsRef<Path>>(path: P) -> Watcher {
+        Watcher {
+            path: path.as_ref().to_path_buf(),
+            interval: Duration::from_millis(500),
+            cache: None,
+            seen: None,
+            analyzed: None,
+        }
+    }
+
+    /// The graphs of the last build that was analyzed.
+    pub fn cache(&self) -> Option<&AnalysisCache> {
+        self.cache.as_ref()
+    }
+
+    /// Check the file once and bring the graphs up to date if it changed
+    /// and settled. Returns the function changes if the graphs were updated,
+    /// where the first analysis reports all functions as added.
+    ///
+    /// Fails if the file cannot be read or is no valid program, which is
+    /// reported once per change of the file.
+    pub fn poll(&mut self) -> io::Result<Option<FunctionChanges>> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // The file may be replaced by removing and recreating it.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.seen = None;
+                return Ok(None);
+            },
+            Err(err) => return Err(err),
+        };
+        let stamp = (metadata.modified()?, metadata.len());
+        let settled = self.seen.replace(stamp) == Some(stamp);
+        if !settled || self.analyzed == Some(stamp) {
+            return Ok(None);
+        }
+        self.analyzed = Some(stamp);
+
+        let bytes = fs::read(&self.path)?;
+        let program = Program::try_from_bytes(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        let changes = panic::catch_unwind(AssertUnwindSafe(|| match &mut self.cache {
+            Some(cache) => cache.update(&program),
+            None => {
+                let cache = AnalysisCache::new(&program);
+                let changes = FunctionChanges {
+                    added: cache.fingerprints.keys().cloned().collect(),
+                    .. FunctionChanges::default()
+                };
+                self.cache = Some(cache);
+                changes
+            },
+        }));
+        changes.map(Some)
+            .map_err(|_| io::Error::other("analysis failed"))
+    }
+
+    /// Poll the file forever and call the function after every update of
+    /// the graphs or with the error of a failed one.
+    pub fn watch<F>(&mut self, mut f: F) -> !
+    where F: FnMut(Result<(&AnalysisCache, &FunctionChanges), io::Error>) {
+        loop {
+            match self.poll() {
+                Ok(Some(changes)) => f(Ok((self.cache.as_ref().unwrap(), &changes))),
+                Ok(None) => {},
+                Err(err) => f(Err(err)),
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settle() {
+        let path = std::env::temp_dir().join(format!("symflow-watch-{}", std::process::id()));
+        let mut watcher = Watcher::new(&path);
+        assert!(watcher.poll().unwrap().is_none());
+
+        // A broken binary is reported once it settled and only once.
+        fs::write(&path, b"\x7fELF\x02\x01").unwrap();
+        assert!(watcher.poll().unwrap().is_none());
+        assert_eq!(watcher.poll().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(watcher.poll().unwrap().is_none());
+        assert!(watcher.cache().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
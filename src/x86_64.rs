@@ -3,8 +3,7 @@
 use std::fmt::{self, Display, Formatter};
 use byteorder::{ByteOrder, LittleEndian};
 
-use crate::flow::{ValueSource, StorageLocation};
-use crate::math::{Integer, DataType};
+use crate::math::{Integer, DataType, SymExpr, Symbol};
 use DataType::*;
 
 
@@ -12,23 +11,62 @@ use DataType::*;
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Instruction {
     pub bytes: Vec<u8>,
+    pub prefixes: Prefixes,
     pub mnemoic: Mnemoic,
     pub operands: Vec<Operand>,
 }
 
+/// The legacy and VEX prefixes of an instruction.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Prefixes {
+    /// The operand-size override (0x66).
+    pub operand_size: bool,
+    /// The REP / REPE prefix (0xf3).
+    pub rep: bool,
+    /// The REPNE prefix (0xf2).
+    pub repne: bool,
+    /// The LOCK prefix (0xf0).
+    pub lock: bool,
+    /// The segment override (0x64 for fs and 0x65 for gs).
+    pub segment: Option<Register>,
+    /// Whether the instruction is VEX-encoded.
+    pub vex: bool,
+}
+
 /// Identifies an instruction.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Mnemoic {
-    Add, Sub, Imul,
+    Add, Sub, Imul, Inc, Dec, Xor,
     Mov, Movzx, Movsx, Lea,
     Cwde, Cdqe,
     Push, Pop,
-    Jmp, Je, Jl, Jle, Jg, Jge, Jbe,
-    Call, Leave, Ret,
+    Jmp, Je, Jne, Jl, Jle, Jg, Jge, Jbe, Ja, Jae, Jb,
+    Call, Leave, Ret, Iret, Iretq,
     Cmp, Test,
-    Setl,
+    Sete, Setne, Setl, Setle, Setg, Setge, Setb, Setbe, Seta, Setae,
+    Cmove, Cmovne, Cmovl, Cmovle, Cmovg, Cmovge, Cmovb, Cmovbe, Cmova, Cmovae,
     Syscall,
     Nop,
+    Movups, Movaps, Movdqa, Movdqu, Movd, Movq,
+    Pxor, Pand, Por, Pandn,
+    Paddb, Paddw, Paddd, Paddq,
+    Psubb, Psubw, Psubd, Psubq,
+    Pcmpeqb, Pcmpeqw, Pcmpeqd,
+    Pcmpgtb, Pcmpgtw, Pcmpgtd,
+    Pshufb, Pmovmskb,
+    Movss, Movsd, Xorps, Xorpd,
+    Addss, Addsd, Subss, Subsd, Mulss, Mulsd, Divss, Divsd,
+    Ucomiss, Ucomisd, Comiss, Comisd,
+    Cvttss2si, Cvttsd2si, Cvtsi2ss, Cvtsi2sd, Cvtss2sd, Cvtsd2ss,
+    Fld, Fild, Fstp, Fistp, Fadd, Fsub, Fmul, Fdiv,
+    Movs, Stos, Cmps, Scas,
+    Xchg, Xadd, Cmpxchg,
+    Andn, Bextr, Blsi, Blsr, Tzcnt, Lzcnt, Pext, Pdep,
+    Rdtsc, Cpuid, Rdrand,
+    Mfence, Lfence, Sfence,
+    Endbr64,
+    /// An instruction decoded by a plugin with its name.
+    Custom(&'static str),
 }
 
 /// An operand in an instruction.
@@ -45,6 +83,15 @@ pub enum Operand {
         scaled_offset: Option<(Register, u8)>,
         displacement: Option<i64>,
     },
+    /// The value at the sum of the optional parts that `Indirect` cannot hold
+    /// together, like the absolute `[0x601040]` or `fs:[rax+rbx*8]`.
+    Memory {
+        data_type: DataType,
+        segment: Option<Register>,
+        base: Option<Register>,
+        scaled_offset: Option<(Register, u8)>,
+        displacement: Option<i64>,
+    },
     /// A direct immediate value.
     Immediate(Integer),
     /// A direct offset.
@@ -54,121 +101,107 @@ pub enum Operand {
 impl Instruction {
     /// Tries to decode an instruction from raw bytes.
     pub fn decode(bytes: &[u8]) -> DecodeResult<Instruction> {
-        Decoder::new(bytes).decode()
+        // The decoder reads the fields of the instruction without checking
+        // the bounds, so it works on a copy with enough zeros appended and
+        // instructions extending into them are rejected.
+        let mut padded = bytes.to_vec();
+        padded.resize(bytes.len() + PADDING, 0);
+        Decoder::new(&padded, bytes.len()).decode()
     }
 
-    /// The byte length of the first instruction in the given slice.
-    pub fn length(bytes: &[u8]) -> u64 {
-        lde::X64.ld(bytes) as u64
+    /// Whether the instruction executes atomically, which is the case for
+    /// LOCK-prefixed instructions and exchanges with memory.
+    pub fn is_atomic(&self) -> bool {
+        let memory = self.operands.iter().any(Operand::accesses_memory);
+        self.prefixes.lock || (self.mnemoic == Mnemoic::Xchg && memory)
     }
 
-    /// Pairs of (source, sink) describing data dependencies in the instruction.
-    pub fn flows(&self) -> Vec<(ValueSource, StorageLocation)> {
+    /// Whether the instruction clears a register by combining it with itself,
+    /// like `xor eax, eax` or `sub eax, eax`, so that its result and flags
+    /// are constant no matter the value of the register.
+    pub fn is_zeroing(&self) -> bool {
         use Mnemoic::*;
-        use Register::*;
-
-        let loc = StorageLocation::from_operand;
-        let reg = StorageLocation::Direct;
-        let stg = ValueSource::Storage;
-        let src = |op| match op {
-            Operand::Immediate(int) => Some(ValueSource::Const(int)),
-            op => loc(op).map(|s| ValueSource::Storage(s)),
-        };
+        let idiom = matches!(self.mnemoic,
+            Xor | Sub | Pxor | Xorps | Xorpd | Psubb | Psubw | Psubd | Psubq);
+        let sources = &self.operands[self.operands.len().saturating_sub(2) ..];
+        idiom && matches!(sources, [Operand::Direct(a), Operand::Direct(b)] if a == b)
+    }
 
-        macro_rules! get {
-            ($op:expr) => { if let Some(s) = loc($op) { s } else { return vec![] } };
-        }
+    /// Whether the instruction is a fence ordering the memory accesses
+    /// before it against those after it.
+    pub fn is_fence(&self) -> bool {
+        matches!(self.mnemoic, Mnemoic::Mfence | Mnemoic::Lfence | Mnemoic::Sfence)
+    }
 
-        fn stack(data_type: DataType, push: bool) -> StorageLocation {
-            StorageLocation::Indirect {
-                data_type,
-                base: RSP,
-                scaled_offset: None,
-                displacement: if push { Some(-(data_type.bytes() as i64)) } else { None },
-            }
-        }
+    /// Whether this is a string instruction that is repeated `rcx` times.
+    pub fn is_repeated(&self) -> bool {
+        self.repeat_prefix().is_some()
+    }
 
+    /// The name of the repeat prefix if this is a repeated string instruction.
+    fn repeat_prefix(&self) -> Option<&'static str> {
         match self.mnemoic {
-            Add | Sub | Imul => {
-                let target = get!(self.operands[0]);
-                let mut source_iter = self.operands.iter();
-                if self.operands.len() > 2 {
-                    source_iter.next().unwrap();
-                }
-                source_iter.take(2)
-                    .filter_map(|&op| src(op).map(|s| (s, target)))
-                    .collect()
-            },
-
-            Mov | Movzx | Movsx => match (src(self.operands[1]), loc(self.operands[0])) {
-                (Some(a), Some(b)) => vec![(a, b)],
-                _ => vec![],
-            },
-
-            Lea => {
-                let target = get!(self.operands[0]);
-                let source = get!(self.operands[1]);
-                let mut pairs = vec![];
-                if let StorageLocation::Indirect { base, scaled_offset, .. } = source {
-                    pairs.push((stg(reg(base)), target));
-                    if let Some((offset, _)) = scaled_offset {
-                        pairs.push((stg(reg(offset)), target));
-                    }
-                }
-                pairs
-            },
-
-            Cwde => vec![(stg(reg(AX)), reg(EAX))],
-            Cdqe => vec![(stg(reg(EAX)), reg(RAX))],
-
-            Push => {
-                let target = get!(self.operands[0]);
-                vec![(stg(reg(RSP)), reg(RSP)), (stg(target), stack(target.data_type(), true))]
-            },
-            Pop => {
-                let target = get!(self.operands[0]);
-                vec![(stg(reg(RSP)), reg(RSP)), (stg(stack(target.data_type(), false)), target)]
-            },
-
-            Call => vec![(stg(reg(RIP)), stack(N64, true))],
-            Leave => vec![
-                (stg(reg(RBP)), reg(RSP)),
-                (stg(StorageLocation::indirect_reg(N64, RBP)), reg(RBP))
-            ],
-            Ret => vec![(stg(stack(N64, false)), reg(RIP))],
-
-            _ => vec![],
+            Mnemoic::Movs | Mnemoic::Stos if self.prefixes.rep => Some("rep"),
+            Mnemoic::Cmps | Mnemoic::Scas if self.prefixes.rep => Some("repe"),
+            Mnemoic::Cmps | Mnemoic::Scas if self.prefixes.repne => Some("repne"),
+            _ => None,
         }
     }
+
+    /// The byte length of the first instruction in the given slice.
+    pub fn length(bytes: &[u8]) -> u64 {
+        lde::X64.ld(bytes) as u64
+    }
 }
 
+/// The number of zeros appended to the bytes of an instruction, which covers
+/// everything that can follow the prefixes.
+const PADDING: usize = 32;
+
 /// Decodes an instruction.
 #[derive(Debug, Clone)]
 struct Decoder<'a> {
     bytes: &'a [u8],
+    /// The number of bytes before the padding.
+    len: usize,
     index: usize,
+    segment: Option<Register>,
 }
 
 impl<'a> Decoder<'a> {
     /// Create a new decoder.
-    fn new(bytes: &'a [u8]) -> Decoder<'a> {
-        Decoder { bytes, index: 0 }
+    fn new(bytes: &'a [u8], len: usize) -> Decoder<'a> {
+        Decoder { bytes, len, index: 0, segment: None }
+    }
+
+    /// The error for the bytes without the padding.
+    fn error(&self) -> DecodingError {
+        DecodingError::new(self.bytes[.. self.len].to_vec())
     }
 
     /// Decodes the bytes into an instruction.
     fn decode(mut self) -> DecodeResult<Instruction> {
         use OperandLayout::*;
 
-        // Parse the optional REX prefix.
-        let rex = self.decode_rex();
+        // Parse the legacy prefixes and then either a VEX or an optional REX prefix.
+        let mut prefixes = self.decode_prefixes();
+        self.segment = prefixes.segment;
+        let vex = self.decode_vex();
+        let rex = match vex {
+            Some(vex) => { prefixes.vex = true; vex.rex },
+            None => self.decode_rex(),
+        };
 
         // Parse the opcode.
-        let (opcode, operation) = self.decode_opcode(rex);
-        let (mnemoic, op) = operation.ok_or_else(|| DecodingError::new(self.bytes.to_vec()))?;
+        let (opcode, operation) = self.decode_opcode(prefixes, rex, vex);
+        let (mnemoic, op) = operation.ok_or_else(|| self.error())?;
 
         // Construct the operands.
         let mut operands = Vec::new();
-        if let Plus(base, width) = op {
+        if let Ext = op {
+            self.index += 1;
+
+        } else if let Plus(base, width) = op {
             // Decode the register from the opcode.
             let reg = Register::from_bits(false, opcode[0] - base, width);
             operands.push(Operand::Direct(reg));
@@ -183,11 +216,11 @@ impl<'a> Decoder<'a> {
 
         } else if let Rm(rm_w) = op {
             // Parse just the R/M part and add a direct operand with it.
-            let (_, rm) = self.decode_modrm_operands(rex, N64, rm_w);
+            let (_, rm) = self.decode_modrm_operands(rex, N64, rm_w)?;
             operands.push(rm);
 
         } else if let RegRm(reg_w, rm_w, ordered) = op {
-            let (reg, rm) = self.decode_modrm_operands(rex, reg_w, rm_w);
+            let (reg, rm) = self.decode_modrm_operands(rex, reg_w, rm_w)?;
 
             // Insert them in the ordered denoted by the opcode.
             if ordered {
@@ -197,7 +230,7 @@ impl<'a> Decoder<'a> {
             }
 
         } else if let RmIm(rm_w, im_w) = op {
-            let (_, rm) = self.decode_modrm_operands(rex, N64, rm_w);
+            let (_, rm) = self.decode_modrm_operands(rex, N64, rm_w)?;
             let immediate = self.decode_immediate(im_w);
 
             // Construct and insert the operands.
@@ -214,15 +247,124 @@ impl<'a> Decoder<'a> {
         } else if let Rel(width) = op {
             let offset = self.decode_offset(width);
             operands.push(offset);
+
+        } else if let SimdRegRm(reg_w, rm_w, ordered) = op {
+            let (reg, rm) = self.decode_modrm_operands_of(rex, reg_w, rm_w)?;
+
+            if ordered {
+                operands.push(reg); operands.push(rm);
+            } else {
+                operands.push(rm); operands.push(reg);
+            }
+
+        } else if let SimdVex(width) = op {
+            // The first source register is encoded in the VEX prefix.
+            let vvvv = vex.map(|vex| vex.vvvv).unwrap_or(0);
+            let (reg, rm) = self.decode_modrm_operands_of(
+                rex, RegWidth::Vector(width), RegWidth::Vector(width))?;
+
+            operands.push(reg);
+            operands.push(Operand::Direct(Register::vector(vvvv, width)));
+            operands.push(rm);
+
+        } else if let Str(width) = op {
+            // String instructions have implicit operands addressed by rsi and rdi.
+            let string = |base| Operand::Indirect {
+                data_type: width,
+                base,
+                scaled_offset: None,
+                displacement: None,
+            };
+            let accumulator = Operand::Direct(Register::accumulator(width));
+
+            let (left, right) = match mnemoic {
+                Mnemoic::Movs => (string(Register::RDI), string(Register::RSI)),
+                Mnemoic::Stos => (string(Register::RDI), accumulator),
+                Mnemoic::Cmps => (string(Register::RSI), string(Register::RDI)),
+                _ => (accumulator, string(Register::RDI)),
+            };
+            operands.push(left);
+            operands.push(right);
+
+        } else if let GeneralVex(width) = op {
+            // The additional general purpose register is encoded in the VEX prefix.
+            let vvvv = vex.map(|vex| vex.vvvv).unwrap_or(0);
+            let extra = Operand::Direct(Register::from_bits(vvvv >= 8, vvvv & 0b111, width));
+            let (reg, rm) = self.decode_modrm_operands(rex, width, width)?;
+
+            match mnemoic {
+                Mnemoic::Bextr => operands.extend(&[reg, rm, extra]),
+                Mnemoic::Blsi | Mnemoic::Blsr => operands.extend(&[extra, rm]),
+                _ => operands.extend(&[reg, extra, rm]),
+            }
+        }
+
+        if self.index > self.len {
+            return Err(self.error());
         }
 
         Ok(Instruction {
-            bytes: self.bytes.to_vec(),
+            bytes: self.bytes[.. self.len].to_vec(),
+            prefixes,
             mnemoic,
             operands,
         })
     }
 
+    /// Decodes the legacy prefixes that are relevant for decoding.
+    fn decode_prefixes(&mut self) -> Prefixes {
+        let mut prefixes = Prefixes::default();
+        loop {
+            match self.bytes.get(self.index) {
+                Some(0x66) => prefixes.operand_size = true,
+                Some(0xf3) => prefixes.rep = true,
+                Some(0xf2) => prefixes.repne = true,
+                Some(0xf0) => prefixes.lock = true,
+                Some(0x64) => prefixes.segment = Some(Register::FS),
+                Some(0x65) => prefixes.segment = Some(Register::GS),
+                // The other segment overrides have no effect in 64-bit mode.
+                Some(0x26) | Some(0x2e) | Some(0x36) | Some(0x3e) => {},
+                _ => return prefixes,
+            }
+            self.index += 1;
+        }
+    }
+
+    /// Decodes the two- or three-byte VEX prefix if there is one.
+    fn decode_vex(&mut self) -> Option<VexPrefix> {
+        let bytes = &self.bytes[self.index ..];
+        let (rex, map, second) = match bytes {
+            &[0xc5, byte, ..] => {
+                self.index += 2;
+                (RexPrefix { r: byte & 0x80 == 0, .. RexPrefix::default() }, 1, byte)
+            },
+            &[0xc4, first, second, ..] => {
+                self.index += 3;
+                let rex = RexPrefix {
+                    w: second & 0x80 > 0,
+                    r: first & 0x80 == 0,
+                    x: first & 0x40 == 0,
+                    b: first & 0x20 == 0,
+                };
+                (rex, first & 0b00011111, second)
+            },
+            _ => return None,
+        };
+
+        Some(VexPrefix {
+            rex,
+            map,
+            vvvv: !(second >> 3) & 0b1111,
+            long: second & 0b100 > 0,
+            simd: match second & 0b11 {
+                0b01 => SimdPrefix::P66,
+                0b10 => SimdPrefix::PF3,
+                0b11 => SimdPrefix::PF2,
+                _ => SimdPrefix::None,
+            },
+        })
+    }
+
     /// Decodes the REX prefix.
     fn decode_rex(&mut self) -> RexPrefix {
         let byte = self.bytes[self.index];
@@ -240,39 +382,85 @@ impl<'a> Decoder<'a> {
     }
 
     /// Decodes the opcode.
-    fn decode_opcode(&mut self, rex: RexPrefix) -> (&'a [u8], Option<(Mnemoic, OperandLayout)>) {
+    fn decode_opcode(&mut self, prefixes: Prefixes, rex: RexPrefix, vex: Option<VexPrefix>)
+    -> (Vec<u8>, Option<(Mnemoic, OperandLayout)>) {
         use OperandLayout::*;
-
-        // Find out the length of the opcode and adjust the index.
+        use RegWidth::*;
+
+        // Find out the length of the opcode and adjust the index. VEX prefixes
+        // imply the escape bytes, so we prepend them to get a uniform opcode.
+        let mut opcode = match vex.map(|vex| vex.map) {
+            Some(1) => vec![0x0f],
+            Some(2) => vec![0x0f, 0x38],
+            Some(3) => vec![0x0f, 0x3a],
+            Some(_) => return (vec![], None),
+            None => vec![],
+        };
         let mut len = 1;
-        if self.bytes[self.index] == 0x0f {
+        if vex.is_none() && self.bytes[self.index] == 0x0f {
             len += 1;
             if self.bytes[self.index + 1] == 0x38 || self.bytes[self.index + 1] == 0x3a {
                 len += 1;
             }
         }
-        let opcode = &self.bytes[self.index .. self.index + len];
-        self.index += opcode.len();
-
-        // The default widths for reg und r/m depends on the rex prefix.
-        let scaled = if rex.w { N64 } else { N32 };
+        opcode.extend_from_slice(&self.bytes[self.index .. self.index + len]);
+        self.index += len;
+
+        // The default widths for reg und r/m depends on the rex and
+        // operand-size prefixes.
+        let scaled = if rex.w { N64 } else if prefixes.operand_size { N16 } else { N32 };
+        let imm = if prefixes.operand_size { N16 } else { N32 };
+
+        // Vector instructions use the prefixes to select the operation and always
+        // use 32- or 64-bit general purpose registers.
+        let simd = match vex {
+            Some(vex) => vex.simd,
+            None if prefixes.repne => SimdPrefix::PF2,
+            None if prefixes.rep => SimdPrefix::PF3,
+            None if prefixes.operand_size => SimdPrefix::P66,
+            None => SimdPrefix::None,
+        };
+        let general = if rex.w { N64 } else { N32 };
+        let vector = if vex.map(|vex| vex.long).unwrap_or(false) { N256 } else { N128 };
+        let load = SimdRegRm(Vector(vector), Vector(vector), true);
+        let store = SimdRegRm(Vector(vector), Vector(vector), false);
+        let binop = if vex.is_some() { SimdVex(vector) } else { load };
 
         // The instruction extension (0 - 7), uses the reg field of ModR/M.
         let ext = self.bytes[self.index ..].get(0).map(|byte| {
             (byte & 0b00111000) >> 3
         });
 
+        // The whole ModR/M byte for opcodes selected by it.
+        let modrm = self.bytes.get(self.index).copied();
+
+        // Whether the ModR/M byte addresses memory.
+        let memory = self.bytes.get(self.index).map(|byte| byte >> 6 != 0b11) == Some(true);
+
+        // Scalar floats live in the low part of the vector registers.
+        let legacy = vex.is_none();
+        let scalar = |data_type| SimdRegRm(Vector(N128), Vector(data_type), true);
+        let scalar_store = |data_type| SimdRegRm(Vector(N128), Vector(data_type), false);
+
         // Handle all the opcodes.
-        (opcode, Some(match opcode {
+        let operation = match opcode.as_slice() {
             &[0x00] => (Mnemoic::Add, RegRm(N8, N8, false)),
             &[0x01] => (Mnemoic::Add, RegRm(scaled, scaled, false)),
             &[0x03] => (Mnemoic::Add, RegRm(scaled, scaled, true)),
-            &[0x05] => (Mnemoic::Add, FixIm(Operand::Direct(Register::EAX), N32)),
-            &[0x81] if ext == Some(0) => (Mnemoic::Add, RmIm(scaled, N32)),
+            &[0x05] => (Mnemoic::Add, FixIm(Operand::Direct(Register::from_bits(false, 0, scaled)), imm)),
+            &[0x81] if ext == Some(0) => (Mnemoic::Add, RmIm(scaled, imm)),
             &[0x83] if ext == Some(0) => (Mnemoic::Add, RmIm(scaled, N8)),
-            &[0x81] if ext == Some(5) => (Mnemoic::Sub, RmIm(scaled, N32)),
+            &[0x81] if ext == Some(5) => (Mnemoic::Sub, RmIm(scaled, imm)),
             &[0x83] if ext == Some(5) => (Mnemoic::Sub, RmIm(scaled, N8)),
+            &[0x29] => (Mnemoic::Sub, RegRm(scaled, scaled, false)),
+            &[0x2b] => (Mnemoic::Sub, RegRm(scaled, scaled, true)),
+            &[0x31] => (Mnemoic::Xor, RegRm(scaled, scaled, false)),
+            &[0x33] => (Mnemoic::Xor, RegRm(scaled, scaled, true)),
             &[0x0f, 0xaf] => (Mnemoic::Imul, RegRm(scaled, scaled, true)),
+            &[0xfe] if ext == Some(0) => (Mnemoic::Inc, Rm(N8)),
+            &[0xff] if ext == Some(0) => (Mnemoic::Inc, Rm(scaled)),
+            &[0xfe] if ext == Some(1) => (Mnemoic::Dec, Rm(N8)),
+            &[0xff] if ext == Some(1) => (Mnemoic::Dec, Rm(scaled)),
 
             &[x] if 0x50 <= x && x < 0x58 => (Mnemoic::Push, Plus(0x50, N64)),
             &[x] if 0x58 <= x && x < 0x60 => (Mnemoic::Pop, Plus(0x58, N64)),
@@ -281,77 +469,229 @@ impl<'a> Decoder<'a> {
             &[0x89] => (Mnemoic::Mov, RegRm(scaled, scaled, false)),
             &[0x8b] => (Mnemoic::Mov, RegRm(scaled, scaled, true)),
             &[0xc6] if ext == Some(0) => (Mnemoic::Mov, RmIm(N8, N8)),
-            &[0xc7] => (Mnemoic::Mov, RmIm(scaled, N32)),
+            &[0xc7] => (Mnemoic::Mov, RmIm(scaled, imm)),
             &[x] if 0xb8 <= x && x < 0xc0 => (Mnemoic::Mov, PlusIm(0xb8, scaled, scaled)),
             &[0x0f, 0xb6] => (Mnemoic::Movzx, RegRm(scaled, N8, true)),
             &[0x0f, 0xbe] => (Mnemoic::Movsx, RegRm(scaled, N8, true)),
 
             &[0x8d] => (Mnemoic::Lea, RegRm(scaled, scaled, true)),
 
+            &[0x86] => (Mnemoic::Xchg, RegRm(N8, N8, false)),
+            &[0x87] => (Mnemoic::Xchg, RegRm(scaled, scaled, false)),
+            &[0x0f, 0xc0] => (Mnemoic::Xadd, RegRm(N8, N8, false)),
+            &[0x0f, 0xc1] => (Mnemoic::Xadd, RegRm(scaled, scaled, false)),
+            &[0x0f, 0xb0] => (Mnemoic::Cmpxchg, RegRm(N8, N8, false)),
+            &[0x0f, 0xb1] => (Mnemoic::Cmpxchg, RegRm(scaled, scaled, false)),
+
             &[0x80] if ext == Some(7) => (Mnemoic::Cmp, RmIm(N8, N8)),
             &[0x83] if ext == Some(7) => (Mnemoic::Cmp, RmIm(scaled, N8)),
             &[0x3c] => (Mnemoic::Cmp, FixIm(Operand::Direct(Register::AL), N8)),
             &[0x39] => (Mnemoic::Cmp, RegRm(scaled, scaled, false)),
             &[0x3b] => (Mnemoic::Cmp, RegRm(scaled, scaled, true)),
             &[0x85] => (Mnemoic::Test, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x94] => (Mnemoic::Sete, Rm(N8)),
+            &[0x0f, 0x95] => (Mnemoic::Setne, Rm(N8)),
             &[0x0f, 0x9c] => (Mnemoic::Setl, Rm(N8)),
+            &[0x0f, 0x9e] => (Mnemoic::Setle, Rm(N8)),
+            &[0x0f, 0x9f] => (Mnemoic::Setg, Rm(N8)),
+            &[0x0f, 0x9d] => (Mnemoic::Setge, Rm(N8)),
+            &[0x0f, 0x92] => (Mnemoic::Setb, Rm(N8)),
+            &[0x0f, 0x96] => (Mnemoic::Setbe, Rm(N8)),
+            &[0x0f, 0x97] => (Mnemoic::Seta, Rm(N8)),
+            &[0x0f, 0x93] => (Mnemoic::Setae, Rm(N8)),
+
+            &[0x0f, 0x44] => (Mnemoic::Cmove, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x45] => (Mnemoic::Cmovne, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x4c] => (Mnemoic::Cmovl, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x4e] => (Mnemoic::Cmovle, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x4f] => (Mnemoic::Cmovg, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x4d] => (Mnemoic::Cmovge, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x42] => (Mnemoic::Cmovb, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x46] => (Mnemoic::Cmovbe, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x47] => (Mnemoic::Cmova, RegRm(scaled, scaled, true)),
+            &[0x0f, 0x43] => (Mnemoic::Cmovae, RegRm(scaled, scaled, true)),
 
             &[0x74] =>(Mnemoic::Je, Rel(N8)),
+            &[0x75] =>(Mnemoic::Jne, Rel(N8)),
             &[0x7c] =>(Mnemoic::Jl, Rel(N8)),
             &[0x7e] =>(Mnemoic::Jle, Rel(N8)),
             &[0x76] =>(Mnemoic::Jbe, Rel(N8)),
             &[0x7f] =>(Mnemoic::Jg, Rel(N8)),
             &[0x7d] =>(Mnemoic::Jge, Rel(N8)),
+            &[0x77] =>(Mnemoic::Ja, Rel(N8)),
+            &[0x73] =>(Mnemoic::Jae, Rel(N8)),
+            &[0x72] =>(Mnemoic::Jb, Rel(N8)),
             &[0xeb] =>(Mnemoic::Jmp, Rel(N8)),
             &[0xe8] =>(Mnemoic::Call, Rel(N16)),
             &[0xff] if ext == Some(2) =>(Mnemoic::Call, Rm(N64)),
+            &[0xff] if ext == Some(4) =>(Mnemoic::Jmp, Rm(N64)),
 
             &[0x90] => (Mnemoic::Nop, Free),
             &[0x98] => (if rex.w { Mnemoic::Cdqe } else { Mnemoic::Cwde }, Free),
             &[0xc9] => (Mnemoic::Leave, Free),
             &[0xc3] => (Mnemoic::Ret, Free),
+            &[0xcf] if scaled != N16 => (if rex.w { Mnemoic::Iretq } else { Mnemoic::Iret }, Free),
             &[0x0f, 0x05] => (Mnemoic::Syscall, Free),
+            &[0x0f, 0x31] => (Mnemoic::Rdtsc, Free),
+            &[0x0f, 0xa2] => (Mnemoic::Cpuid, Free),
+            &[0x0f, 0xc7] if !memory && ext == Some(6) => (Mnemoic::Rdrand, Rm(scaled)),
+            &[0x0f, 0xae] if !memory && ext == Some(5) => (Mnemoic::Lfence, Ext),
+            &[0x0f, 0xae] if !memory && ext == Some(6) => (Mnemoic::Mfence, Ext),
+            &[0x0f, 0xae] if !memory && ext == Some(7) => (Mnemoic::Sfence, Ext),
+            &[0x0f, 0x1e] if simd == SimdPrefix::PF3 && modrm == Some(0xfa) => (Mnemoic::Endbr64, Ext),
+
+            &[0xa4] => (Mnemoic::Movs, Str(N8)),
+            &[0xa5] => (Mnemoic::Movs, Str(scaled)),
+            &[0xaa] => (Mnemoic::Stos, Str(N8)),
+            &[0xab] => (Mnemoic::Stos, Str(scaled)),
+            &[0xa6] => (Mnemoic::Cmps, Str(N8)),
+            &[0xa7] => (Mnemoic::Cmps, Str(scaled)),
+            &[0xae] => (Mnemoic::Scas, Str(N8)),
+            &[0xaf] => (Mnemoic::Scas, Str(scaled)),
+
+            &[0x0f, 0x10] if simd == SimdPrefix::None => (Mnemoic::Movups, load),
+            &[0x0f, 0x11] if simd == SimdPrefix::None => (Mnemoic::Movups, store),
+            &[0x0f, 0x28] if simd == SimdPrefix::None => (Mnemoic::Movaps, load),
+            &[0x0f, 0x29] if simd == SimdPrefix::None => (Mnemoic::Movaps, store),
+            &[0x0f, 0x6f] if simd == SimdPrefix::P66 => (Mnemoic::Movdqa, load),
+            &[0x0f, 0x7f] if simd == SimdPrefix::P66 => (Mnemoic::Movdqa, store),
+            &[0x0f, 0x6f] if simd == SimdPrefix::PF3 => (Mnemoic::Movdqu, load),
+            &[0x0f, 0x7f] if simd == SimdPrefix::PF3 => (Mnemoic::Movdqu, store),
+            &[0x0f, 0x6e] if simd == SimdPrefix::P66 => (
+                if rex.w { Mnemoic::Movq } else { Mnemoic::Movd },
+                SimdRegRm(Vector(N128), General(general), true),
+            ),
+            &[0x0f, 0x7e] if simd == SimdPrefix::P66 => (
+                if rex.w { Mnemoic::Movq } else { Mnemoic::Movd },
+                SimdRegRm(Vector(N128), General(general), false),
+            ),
+            &[0x0f, 0xd7] if simd == SimdPrefix::P66 => {
+                (Mnemoic::Pmovmskb, SimdRegRm(General(general), Vector(vector), true))
+            },
+
+            &[0x0f, 0xef] if simd == SimdPrefix::P66 => (Mnemoic::Pxor, binop),
+            &[0x0f, 0xdb] if simd == SimdPrefix::P66 => (Mnemoic::Pand, binop),
+            &[0x0f, 0xeb] if simd == SimdPrefix::P66 => (Mnemoic::Por, binop),
+            &[0x0f, 0xdf] if simd == SimdPrefix::P66 => (Mnemoic::Pandn, binop),
+            &[0x0f, 0xfc] if simd == SimdPrefix::P66 => (Mnemoic::Paddb, binop),
+            &[0x0f, 0xfd] if simd == SimdPrefix::P66 => (Mnemoic::Paddw, binop),
+            &[0x0f, 0xfe] if simd == SimdPrefix::P66 => (Mnemoic::Paddd, binop),
+            &[0x0f, 0xd4] if simd == SimdPrefix::P66 => (Mnemoic::Paddq, binop),
+            &[0x0f, 0xf8] if simd == SimdPrefix::P66 => (Mnemoic::Psubb, binop),
+            &[0x0f, 0xf9] if simd == SimdPrefix::P66 => (Mnemoic::Psubw, binop),
+            &[0x0f, 0xfa] if simd == SimdPrefix::P66 => (Mnemoic::Psubd, binop),
+            &[0x0f, 0xfb] if simd == SimdPrefix::P66 => (Mnemoic::Psubq, binop),
+            &[0x0f, 0x74] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpeqb, binop),
+            &[0x0f, 0x75] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpeqw, binop),
+            &[0x0f, 0x76] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpeqd, binop),
+            &[0x0f, 0x64] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpgtb, binop),
+            &[0x0f, 0x65] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpgtw, binop),
+            &[0x0f, 0x66] if simd == SimdPrefix::P66 => (Mnemoic::Pcmpgtd, binop),
+            &[0x0f, 0x38, 0x00] if simd == SimdPrefix::P66 => (Mnemoic::Pshufb, binop),
+
+            &[0x0f, 0x57] if simd == SimdPrefix::None => (Mnemoic::Xorps, binop),
+            &[0x0f, 0x57] if simd == SimdPrefix::P66 => (Mnemoic::Xorpd, binop),
+            &[0x0f, 0x10] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Movss, scalar(F32)),
+            &[0x0f, 0x10] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Movsd, scalar(F64)),
+            &[0x0f, 0x11] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Movss, scalar_store(F32)),
+            &[0x0f, 0x11] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Movsd, scalar_store(F64)),
+            &[0x0f, 0x58] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Addss, scalar(F32)),
+            &[0x0f, 0x58] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Addsd, scalar(F64)),
+            &[0x0f, 0x5c] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Subss, scalar(F32)),
+            &[0x0f, 0x5c] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Subsd, scalar(F64)),
+            &[0x0f, 0x59] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Mulss, scalar(F32)),
+            &[0x0f, 0x59] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Mulsd, scalar(F64)),
+            &[0x0f, 0x5e] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Divss, scalar(F32)),
+            &[0x0f, 0x5e] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Divsd, scalar(F64)),
+            &[0x0f, 0x2e] if legacy && simd == SimdPrefix::None => (Mnemoic::Ucomiss, scalar(F32)),
+            &[0x0f, 0x2e] if legacy && simd == SimdPrefix::P66 => (Mnemoic::Ucomisd, scalar(F64)),
+            &[0x0f, 0x2f] if legacy && simd == SimdPrefix::None => (Mnemoic::Comiss, scalar(F32)),
+            &[0x0f, 0x2f] if legacy && simd == SimdPrefix::P66 => (Mnemoic::Comisd, scalar(F64)),
+            &[0x0f, 0x2c] if legacy && simd == SimdPrefix::PF3 => {
+                (Mnemoic::Cvttss2si, SimdRegRm(General(general), Vector(F32), true))
+            },
+            &[0x0f, 0x2c] if legacy && simd == SimdPrefix::PF2 => {
+                (Mnemoic::Cvttsd2si, SimdRegRm(General(general), Vector(F64), true))
+            },
+            &[0x0f, 0x2a] if legacy && simd == SimdPrefix::PF3 => {
+                (Mnemoic::Cvtsi2ss, SimdRegRm(Vector(N128), General(general), true))
+            },
+            &[0x0f, 0x2a] if legacy && simd == SimdPrefix::PF2 => {
+                (Mnemoic::Cvtsi2sd, SimdRegRm(Vector(N128), General(general), true))
+            },
+            &[0x0f, 0x5a] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Cvtss2sd, scalar(F32)),
+            &[0x0f, 0x5a] if legacy && simd == SimdPrefix::PF2 => (Mnemoic::Cvtsd2ss, scalar(F64)),
+
+            // Only the memory forms of the basic x87 instructions are supported.
+            &[0xd9] if memory && ext == Some(0) => (Mnemoic::Fld, Rm(F32)),
+            &[0xdd] if memory && ext == Some(0) => (Mnemoic::Fld, Rm(F64)),
+            &[0xdb] if memory && ext == Some(0) => (Mnemoic::Fild, Rm(N32)),
+            &[0xd9] if memory && ext == Some(3) => (Mnemoic::Fstp, Rm(F32)),
+            &[0xdd] if memory && ext == Some(3) => (Mnemoic::Fstp, Rm(F64)),
+            &[0xdb] if memory && ext == Some(3) => (Mnemoic::Fistp, Rm(N32)),
+            &[0xd8] if memory && ext == Some(0) => (Mnemoic::Fadd, Rm(F32)),
+            &[0xdc] if memory && ext == Some(0) => (Mnemoic::Fadd, Rm(F64)),
+            &[0xd8] if memory && ext == Some(1) => (Mnemoic::Fmul, Rm(F32)),
+            &[0xdc] if memory && ext == Some(1) => (Mnemoic::Fmul, Rm(F64)),
+            &[0xd8] if memory && ext == Some(4) => (Mnemoic::Fsub, Rm(F32)),
+            &[0xdc] if memory && ext == Some(4) => (Mnemoic::Fsub, Rm(F64)),
+            &[0xd8] if memory && ext == Some(6) => (Mnemoic::Fdiv, Rm(F32)),
+            &[0xdc] if memory && ext == Some(6) => (Mnemoic::Fdiv, Rm(F64)),
+
+            // Bit manipulation (BMI1 and BMI2).
+            &[0x0f, 0x38, 0xf2] if !legacy && simd == SimdPrefix::None => (Mnemoic::Andn, GeneralVex(general)),
+            &[0x0f, 0x38, 0xf7] if !legacy && simd == SimdPrefix::None => (Mnemoic::Bextr, GeneralVex(general)),
+            &[0x0f, 0x38, 0xf3] if !legacy && ext == Some(3) => (Mnemoic::Blsi, GeneralVex(general)),
+            &[0x0f, 0x38, 0xf3] if !legacy && ext == Some(1) => (Mnemoic::Blsr, GeneralVex(general)),
+            &[0x0f, 0x38, 0xf5] if !legacy && simd == SimdPrefix::PF3 => (Mnemoic::Pext, GeneralVex(general)),
+            &[0x0f, 0x38, 0xf5] if !legacy && simd == SimdPrefix::PF2 => (Mnemoic::Pdep, GeneralVex(general)),
+            &[0x0f, 0xbc] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Tzcnt, RegRm(scaled, scaled, true)),
+            &[0x0f, 0xbd] if legacy && simd == SimdPrefix::PF3 => (Mnemoic::Lzcnt, RegRm(scaled, scaled, true)),
+
+            _ => return (opcode, None),
+        };
 
+        // Only vector and bit manipulation instructions can be VEX-encoded.
+        match (vex, operation.1) {
+            (None, _) | (Some(_), SimdRegRm(..)) | (Some(_), SimdVex(_)) | (Some(_), GeneralVex(_)) => {},
             _ => return (opcode, None),
-        }))
+        }
+
+        (opcode, Some(operation))
     }
 
     /// Decodes the ModR/M byte and displacement.
     fn decode_modrm_operands(&mut self, rex: RexPrefix, reg_w: DataType, rm_w: DataType)
-    -> (Operand, Operand) {
+    -> DecodeResult<(Operand, Operand)> {
+        self.decode_modrm_operands_of(rex, RegWidth::General(reg_w), RegWidth::General(rm_w))
+    }
+
+    /// Decodes the ModR/M byte and displacement where the operands may be
+    /// vector registers.
+    fn decode_modrm_operands_of(&mut self, rex: RexPrefix, reg_w: RegWidth, rm_w: RegWidth)
+    -> DecodeResult<(Operand, Operand)> {
         let (modus, reg, rm) = self.decode_modrm();
 
-        let reg_op = Operand::Direct(Register::from_bits(rex.r, reg, reg_w));
+        let reg_op = Operand::Direct(reg_w.register(rex.r, reg));
+        let rm_class = rm_w;
+        let rm_w = rm_w.data_type();
 
         let rm_op = match modus {
             0b00 => {
                 // Check if we use SIB, RIP-relative or R/M.
                 if rm == 0b100 {
-                    let (scale, index, base) = self.decode_sib(rex);
-                    Operand::Indirect {
-                        data_type: rm_w,
-                        base,
-                        scaled_offset: Some((index, scale)),
-                        displacement: None,
-                    }
+                    // Without a base, there is a 32-bit displacement.
+                    let (scale, index, base) = self.decode_sib(rex, modus);
+                    let disp = if base.is_none() { Some(self.decode_signed_value(N32)) } else { None };
+                    self.memory_operand(rm_w, base, index.map(|index| (index, scale)), disp)?
 
                 } else if rm == 0b101 {
                     let disp = self.decode_signed_value(N32);
-                    Operand::Indirect {
-                        data_type: rm_w,
-                        base: Register::RIP,
-                        scaled_offset: None,
-                        displacement: Some(disp),
-                    }
+                    self.memory_operand(rm_w, Some(Register::RIP), None, Some(disp))?
 
                 } else {
                     let base = Register::from_bits(rex.b, rm, N64);
-                    Operand::Indirect {
-                        data_type: rm_w,
-                        base,
-                        scaled_offset: None,
-                        displacement: None,
-                    }
+                    self.memory_operand(rm_w, Some(base), None, None)?
                 }
             },
             0b01 | 0b10 => {
@@ -359,31 +699,46 @@ impl<'a> Decoder<'a> {
 
                 // Check if we use SIB or just R/M.
                 if rm == 0b100 {
-                    let (scale, index, base) = self.decode_sib(rex);
+                    let (scale, index, base) = self.decode_sib(rex, modus);
                     let disp = self.decode_signed_value(displace_width);
-                    Operand::Indirect {
-                        data_type: rm_w,
-                        base,
-                        scaled_offset: Some((index, scale)),
-                        displacement: Some(disp),
-                    }
+                    self.memory_operand(rm_w, base, index.map(|index| (index, scale)), Some(disp))?
 
                 } else {
                     let base = Register::from_bits(rex.b, rm, N64);
                     let disp = self.decode_signed_value(displace_width);
-                    Operand::Indirect {
-                        data_type: rm_w,
-                        base,
-                        scaled_offset: None,
-                        displacement: Some(disp),
-                    }
+                    self.memory_operand(rm_w, Some(base), None, Some(disp))?
                 }
             },
-            0b11 => Operand::Direct(Register::from_bits(rex.b, rm, rm_w)),
+            0b11 => Operand::Direct(rm_class.register(rex.b, rm)),
             _ => panic!("decode_modrm_operands: invalid modus"),
         };
 
-        (reg_op, rm_op)
+        Ok((reg_op, rm_op))
+    }
+
+    /// Builds a memory operand, adding the segment base if there is a segment
+    /// override. The segment base takes the place of the base register, which
+    /// then becomes an unscaled offset. Without both, the scaled index takes
+    /// the place of the base and is scaled by one less.
+    fn memory_operand(
+        &self,
+        data_type: DataType,
+        base: Option<Register>,
+        scaled_offset: Option<(Register, u8)>,
+        displacement: Option<i64>,
+    ) -> DecodeResult<Operand> {
+        let (base, scaled_offset) = match (self.segment, base, scaled_offset) {
+            (None, Some(base), scaled_offset) => (base, scaled_offset),
+            (Some(segment), None, scaled_offset) => (segment, scaled_offset),
+            (Some(segment), Some(base), None) => (segment, Some((base, 1))),
+            (None, None, Some((index, 1))) => (index, None),
+            (None, None, Some((index, scale))) => (index, Some((index, scale - 1))),
+            (segment, base, scaled_offset) => return Ok(Operand::Memory {
+                data_type, segment, base, scaled_offset, displacement,
+            }),
+        };
+
+        Ok(Operand::Indirect { data_type, base, scaled_offset, displacement })
     }
 
     /// Decodes the ModR/M byte and returns a (modus, reg, rm) triple.
@@ -397,12 +752,26 @@ impl<'a> Decoder<'a> {
     }
 
     /// Decodes the SIB byte and returns a (scale, index, base) triple.
-    fn decode_sib(&mut self, rex: RexPrefix) -> (u8, Register, Register) {
+    /// The index is missing if it is encoded as `rsp` and the base is missing
+    /// if it is encoded as `rbp` without a displacement.
+    fn decode_sib(&mut self, rex: RexPrefix, modus: u8) -> (u8, Option<Register>, Option<Register>) {
         let byte = self.bytes[self.index];
         let scale = 2u8.pow((byte >> 6) as u32);
-        let index = Register::from_bits(rex.x, (byte & 0b00111000) >> 3, N64);
-        let base = Register::from_bits(rex.b, byte & 0b00000111, N64);
+        let index_bits = (byte & 0b00111000) >> 3;
+        let base_bits = byte & 0b00000111;
         self.index += 1;
+
+        let index = if index_bits == 0b100 && !rex.x {
+            None
+        } else {
+            Some(Register::from_bits(rex.x, index_bits, N64))
+        };
+        let base = if base_bits == 0b101 && modus == 0b00 {
+            None
+        } else {
+            Some(Register::from_bits(rex.b, base_bits, N64))
+        };
+
         (scale, index, base)
     }
 
@@ -424,6 +793,7 @@ impl<'a> Decoder<'a> {
             N16 => (LittleEndian::read_u16(bytes) as u64, 2),
             N32 => (LittleEndian::read_u32(bytes) as u64, 4),
             N64 => (LittleEndian::read_u64(bytes), 8),
+            _ => panic!("decode_unsigned_value: invalid width"),
         };
         self.index += off;
         value
@@ -437,6 +807,7 @@ impl<'a> Decoder<'a> {
             N16 => (LittleEndian::read_i16(bytes) as i64, 2),
             N32 => (LittleEndian::read_i32(bytes) as i64, 4),
             N64 => (LittleEndian::read_i64(bytes), 8),
+            _ => panic!("decode_signed_value: invalid width"),
         };
         self.index += off;
         value
@@ -452,10 +823,62 @@ struct RexPrefix {
     b: bool,
 }
 
+/// A VEX prefix.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct VexPrefix {
+    /// The (inverted) REX bits contained in the prefix.
+    rex: RexPrefix,
+    /// The implied opcode escape (1 = 0x0f, 2 = 0x0f38, 3 = 0x0f3a).
+    map: u8,
+    /// The additional source register.
+    vvvv: u8,
+    /// Whether 256-bit vectors are used.
+    long: bool,
+    /// The implied mandatory prefix.
+    simd: SimdPrefix,
+}
+
+/// The mandatory prefix selecting between vector instructions with
+/// the same opcode.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum SimdPrefix {
+    None,
+    P66,
+    PF3,
+    PF2,
+}
+
+/// The width of a register operand in either the general purpose or
+/// the vector register file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum RegWidth {
+    General(DataType),
+    Vector(DataType),
+}
+
+impl RegWidth {
+    /// The width of the register.
+    fn data_type(self) -> DataType {
+        match self {
+            RegWidth::General(width) | RegWidth::Vector(width) => width,
+        }
+    }
+
+    /// Decodes the register from the bit pattern in the instruction.
+    fn register(self, alt: bool, reg: u8) -> Register {
+        match self {
+            RegWidth::General(width) => Register::from_bits(alt, reg, width),
+            RegWidth::Vector(width) => Register::vector(reg + if alt { 8 } else { 0 }, width),
+        }
+    }
+}
+
 /// Describes the operand layout of the instruction.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum OperandLayout {
     Free,
+    /// No operands, but a ModR/M byte with an opcode extension.
+    Ext,
     Plus(u8, DataType),
     PlusIm(u8, DataType, DataType),
     Rm(DataType),
@@ -463,10 +886,27 @@ enum OperandLayout {
     RmIm(DataType, DataType),
     FixIm(Operand, DataType),
     Rel(DataType),
+    SimdRegRm(RegWidth, RegWidth, bool),
+    SimdVex(DataType),
+    Str(DataType),
+    GeneralVex(DataType),
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.prefixes.lock {
+            write!(f, "lock ")?;
+        }
+        // Only the vector instructions are named differently when VEX-encoded.
+        let general = matches!(self.mnemoic,
+            Mnemoic::Andn | Mnemoic::Bextr | Mnemoic::Blsi |
+            Mnemoic::Blsr | Mnemoic::Pext | Mnemoic::Pdep);
+        if self.prefixes.vex && !general {
+            write!(f, "v")?;
+        }
+        if let Some(prefix) = self.repeat_prefix() {
+            write!(f, "{} ", prefix)?;
+        }
         write!(f, "{}", self.mnemoic)?;
         let mut first = true;
         for operand in &self.operands {
@@ -477,9 +917,49 @@ impl Display for Instruction {
     }
 }
 
+impl Mnemoic {
+    /// The status flags the condition of the instruction depends on.
+    pub fn flags_read(self) -> &'static [Flag] {
+        use Flag::*;
+        use Mnemoic::*;
+        match self {
+            Je | Jne | Sete | Setne | Cmove | Cmovne => &[Zero],
+            Jl | Jge | Setl | Setge | Cmovl | Cmovge => &[Sign, Overflow],
+            Jle | Jg | Setle | Setg | Cmovle | Cmovg => &[Zero, Sign, Overflow],
+            Jb | Jae | Setb | Setae | Cmovb | Cmovae => &[Carry],
+            Jbe | Ja | Setbe | Seta | Cmovbe | Cmova => &[Carry, Zero],
+            _ => &[],
+        }
+    }
+
+    /// The status flags the instruction sets, clears or leaves undefined.
+    pub fn flags_written(self) -> &'static [Flag] {
+        use Flag::*;
+        use Mnemoic::*;
+        match self {
+            // Increments and decrements keep the carry flag.
+            Inc | Dec => &[Parity, Zero, Sign, Overflow],
+            Add | Sub | Imul | Xor | Cmp | Test | Xadd | Cmpxchg | Cmps | Scas |
+            Ucomiss | Ucomisd | Comiss | Comisd |
+            Andn | Bextr | Blsi | Blsr | Tzcnt | Lzcnt | Rdrand => Flag::ALL,
+            _ => &[],
+        }
+    }
+}
+
 impl Display for Mnemoic {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", format!("{:?}", self).to_lowercase())
+        match self {
+            Mnemoic::Custom(name) => write!(f, "{}", name),
+            _ => write!(f, "{}", format!("{:?}", self).to_lowercase()),
+        }
+    }
+}
+
+impl Operand {
+    /// Whether the operand is the value at some address.
+    pub fn accesses_memory(&self) -> bool {
+        matches!(self, Operand::Indirect { .. } | Operand::Memory { .. })
     }
 }
 
@@ -491,12 +971,46 @@ impl Display for Operand {
         match *self {
             Direct(reg) => write!(f, "{}", reg),
             Indirect { data_type, base, scaled_offset, displacement } => {
-                write!(f, "{} ptr [{}", data_type.name(), base)?;
+                // Segment bases are written as a prefix like in `fs:[0x28]`.
+                let mut empty = base.is_segment();
+                if empty {
+                    write!(f, "{} ptr {}:[", data_type.name(), base)?;
+                } else {
+                    write!(f, "{} ptr [{}", data_type.name(), base)?;
+                }
+                if let Some((index, scale)) = scaled_offset {
+                    if !empty { write!(f, "+")?; }
+                    write!(f, "{}*{}", index, scale)?;
+                    empty = false;
+                }
+                match displacement {
+                    Some(disp) if empty && disp >= 0 => write!(f, "{:#x}", disp)?,
+                    Some(disp) => write_signed_hex(f, disp)?,
+                    None => {},
+                }
+                write!(f, "]")
+            },
+            Memory { data_type, segment, base, scaled_offset, displacement } => {
+                write!(f, "{} ptr ", data_type.name())?;
+                if let Some(segment) = segment {
+                    write!(f, "{}:", segment)?;
+                }
+                write!(f, "[")?;
+                let mut empty = true;
+                if let Some(base) = base {
+                    write!(f, "{}", base)?;
+                    empty = false;
+                }
                 if let Some((index, scale)) = scaled_offset {
-                    write!(f, "+{}*{}", index, scale)?;
+                    if !empty { write!(f, "+")?; }
+                    write!(f, "{}*{}", index, scale)?;
+                    empty = false;
                 }
-                if let Some(disp) = displacement {
-                    write_signed_hex(f, disp)?;
+                match displacement {
+                    Some(disp) if empty && disp >= 0 => write!(f, "{:#x}", disp)?,
+                    Some(disp) => write_signed_hex(f, disp)?,
+                    None if empty => write!(f, "0x0")?,
+                    None => {},
                 }
                 write!(f, "]")
             },
@@ -515,8 +1029,28 @@ pub enum Register {
     AL, CL, DL, BL, AH, CH, DH, BH,
     R8, R9, R10, R11, R12, R13, R14, R15,
     IP, EIP, RIP,
+    /// The base addresses of the fs and gs segments.
+    FS, GS,
+    /// The top of the x87 register stack.
+    ST0,
+    XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7,
+    XMM8, XMM9, XMM10, XMM11, XMM12, XMM13, XMM14, XMM15,
+    YMM0, YMM1, YMM2, YMM3, YMM4, YMM5, YMM6, YMM7,
+    YMM8, YMM9, YMM10, YMM11, YMM12, YMM13, YMM14, YMM15,
 }
 
+const XMM: [Register; 16] = {
+    use Register::*;
+    [XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7,
+     XMM8, XMM9, XMM10, XMM11, XMM12, XMM13, XMM14, XMM15]
+};
+
+const YMM: [Register; 16] = {
+    use Register::*;
+    [YMM0, YMM1, YMM2, YMM3, YMM4, YMM5, YMM6, YMM7,
+     YMM8, YMM9, YMM10, YMM11, YMM12, YMM13, YMM14, YMM15]
+};
+
 impl Display for Register {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}", format!("{:?}", self).to_lowercase())
@@ -529,10 +1063,13 @@ impl Register {
         use Register::*;
         match self {
             RAX | RCX | RDX | RBX | RSP | RBP | RSI | RDI |
-            R8 | R9 | R10 | R11 | R12 | R13 | R14 | R15 | RIP => N64,
+            R8 | R9 | R10 | R11 | R12 | R13 | R14 | R15 | RIP | FS | GS => N64,
             EAX | ECX | EDX | EBX | ESP | EBP | ESI | EDI | EIP => N32,
             AX | CX | DX | BX | SP | BP | SI | DI | IP => N16,
             AL | CL | DL | BL | AH | CH | DH | BH => N8,
+            ST0 => F64,
+            r if XMM.contains(r) => N128,
+            _ => N256,
         }
     }
 
@@ -548,7 +1085,34 @@ impl Register {
             CH | BP | EBP => RBP,
             DH | SI | ESI => RSI,
             BH | DI | EDI => RDI,
-            r => r
+            r => match r.vector_index() {
+                Some(index) => YMM[index],
+                None => r,
+            }
+        }
+    }
+
+    /// Whether this is the base of a segment.
+    pub fn is_segment(self) -> bool {
+        self == Register::FS || self == Register::GS
+    }
+
+    /// The index of a vector register (`xmm3` and `ymm3` both have index 3).
+    pub fn vector_index(self) -> Option<usize> {
+        XMM.iter().position(|&r| r == self)
+            .or_else(|| YMM.iter().position(|&r| r == self))
+    }
+
+    /// The accumulator register (`al`, `ax`, `eax` or `rax`) with the given width.
+    pub fn accumulator(width: DataType) -> Register {
+        Register::from_bits(false, 0, width)
+    }
+
+    /// The vector register with the given index and width.
+    fn vector(index: u8, width: DataType) -> Register {
+        match width {
+            N256 => YMM[index as usize],
+            _ => XMM[index as usize],
         }
     }
 
@@ -577,6 +1141,73 @@ impl Register {
     }
 }
 
+/// The general purpose registers and the names of their initial symbols.
+pub(crate) const REGISTERS: [(Register, &str); 16] = [
+    (Register::RAX, "rax"), (Register::RCX, "rcx"), (Register::RDX, "rdx"),
+    (Register::RBX, "rbx"), (Register::RSP, "rsp"), (Register::RBP, "rbp"),
+    (Register::RSI, "rsi"), (Register::RDI, "rdi"), (Register::R8, "r8"),
+    (Register::R9, "r9"), (Register::R10, "r10"), (Register::R11, "r11"),
+    (Register::R12, "r12"), (Register::R13, "r13"), (Register::R14, "r14"),
+    (Register::R15, "r15"),
+];
+
+
+/// The symbol a general purpose register holds at the start of an analysis
+/// that does not know the initial values of the registers.
+pub(crate) fn initial(register: Register) -> SymExpr {
+    let &(_, name) = REGISTERS.iter()
+        .find(|&&(other, _)| other == register)
+        .expect("initial: not a general purpose register");
+    SymExpr::Sym(Symbol(DataType::N64, name, 0))
+}
+
+/// The constant the expression adds to the initial value of the register if
+/// it is one.
+pub(crate) fn offset_from(expr: &SymExpr, register: Register) -> Option<i64> {
+    let base = initial(register);
+    match expr {
+        expr if *expr == base => Some(0),
+        SymExpr::Add(a, b) if **a == base => match **b {
+            SymExpr::Int(int) => Some(int.1 as i64),
+            _ => None,
+        },
+        SymExpr::Sub(a, b) if **a == base => match **b {
+            SymExpr::Int(int) => Some((int.1 as i64).wrapping_neg()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Identifies a status flag in the flags register.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Flag {
+    Carry,
+    Parity,
+    Zero,
+    Sign,
+    Overflow,
+}
+
+impl Flag {
+    /// All tracked status flags.
+    pub const ALL: &'static [Flag] = &[
+        Flag::Carry, Flag::Parity, Flag::Zero, Flag::Sign, Flag::Overflow,
+    ];
+}
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Flag::Carry => "cf",
+            Flag::Parity => "pf",
+            Flag::Zero => "zf",
+            Flag::Sign => "sf",
+            Flag::Overflow => "of",
+        })
+    }
+}
+
 
 /// The error type for instruction decoding.
 pub struct DecodingError(Vec<u8>);
@@ -584,7 +1215,7 @@ pub(in super) type DecodeResult<T> = Result<T, DecodingError>;
 
 impl DecodingError {
     /// Create a new decoding error from bytes.
-    fn new(bytes: Vec<u8>) -> DecodingError {
+    pub(crate) fn new(bytes: Vec<u8>) -> DecodingError {
         DecodingError(bytes)
     }
 }
@@ -618,6 +1249,8 @@ mod tests {
         test(&[0x48, 0x81, 0xc4, 0x20, 0x04, 0x00, 0x00], "add rsp, 0x420");
         test(&[0x48, 0x81, 0xec, 0x20, 0x04, 0x00, 0x00], "sub rsp, 0x420");
         test(&[0x0f, 0xaf, 0x45, 0xfc], "imul eax, dword ptr [rbp-0x4]");
+        test(&[0x31, 0xc0], "xor eax, eax");
+        test(&[0x48, 0x2b, 0x45, 0xf8], "sub rax, qword ptr [rbp-0x8]");
 
         // Comparisons
         test(&[0x80, 0x7d, 0xff, 0x60], "cmp byte ptr [rbp-0x1], 0x60");
@@ -631,10 +1264,111 @@ mod tests {
         test(&[0x0f, 0xbe, 0xc0], "movsx eax, al");
         test(&[0x48, 0x8d, 0x05, 0xcb, 0xff, 0xff, 0xff], "lea rax, qword ptr [rip-0x35]");
         test(&[0x48, 0x8d, 0x1c, 0x02], "lea rbx, qword ptr [rdx+rax*1]");
+        test(&[0x48, 0x8d, 0x14, 0x85, 0x00, 0x00, 0x00, 0x00], "lea rdx, qword ptr [rax+rax*3]");
+
+        // Atomics
+        test(&[0xf0, 0x0f, 0xc1, 0x07], "lock xadd dword ptr [rdi], eax");
+        test(&[0xf0, 0x48, 0x0f, 0xb1, 0x17], "lock cmpxchg qword ptr [rdi], rdx");
+        test(&[0xf0, 0xff, 0x0f], "lock dec dword ptr [rdi]");
+        test(&[0x48, 0x87, 0x06], "xchg qword ptr [rsi], rax");
+        test(&[0x48, 0xff, 0xc0], "inc rax");
+        test(&[0x0f, 0xae, 0xf0], "mfence");
+        test(&[0x0f, 0xae, 0xe8], "lfence");
+        test(&[0x0f, 0xae, 0xf8], "sfence");
+
+        // Segments
+        test(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00], "mov rax, qword ptr fs:[0x28]");
+        test(&[0x64, 0x48, 0x8b, 0x00], "mov rax, qword ptr fs:[rax*1]");
+        test(&[0x64, 0x8b, 0x40, 0xfc], "mov eax, dword ptr fs:[rax*1-0x4]");
+        test(&[0x64, 0x48, 0x2b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00], "sub rax, qword ptr fs:[0x28]");
+        test(&[0x8b, 0x04, 0x25, 0x40, 0x10, 0x60, 0x00], "mov eax, dword ptr [0x601040]");
+        test(&[0x64, 0x48, 0x8b, 0x04, 0xc8], "mov rax, qword ptr fs:[rax+rcx*8]");
+        test(&[0x65, 0x48, 0x89, 0x1c, 0x25, 0x10, 0x00, 0x00, 0x00],
+             "mov qword ptr gs:[0x10], rbx");
+        test(&[0x8b, 0x04, 0x24], "mov eax, dword ptr [rsp]");
+        test(&[0x8b, 0x44, 0x8c, 0x08], "mov eax, dword ptr [rsp+rcx*4+0x8]");
+
+        // Conditional moves and sets
+        test(&[0x0f, 0x94, 0xc0], "sete al");
+        test(&[0x48, 0x0f, 0x4c, 0xc2], "cmovl rax, rdx");
+        test(&[0x0f, 0x47, 0x45, 0xfc], "cmova eax, dword ptr [rbp-0x4]");
 
         // Jumps
         test(&[0x7e, 0x19], "jle +0x19");
+        test(&[0x75, 0x05], "jne +0x5");
         test(&[0xff, 0xd2], "call rdx");
+        test(&[0xff, 0xe0], "jmp rax");
+
+        // Prefixes
+        test(&[0x66, 0x89, 0xc8], "mov ax, cx");
+        test(&[0xf3, 0xc3], "ret");
+        test(&[0xf3, 0x0f, 0x1e, 0xfa], "endbr64");
+    }
+
+    #[test]
+    fn decode_bits() {
+        test(&[0xc4, 0xe2, 0x78, 0xf2, 0xc2], "andn eax, eax, edx");
+        test(&[0xc4, 0xe2, 0xe8, 0xf7, 0xc7], "bextr rax, rdi, rdx");
+        test(&[0xc4, 0xe2, 0x78, 0xf3, 0xdf], "blsi eax, edi");
+        test(&[0xc4, 0xe2, 0xf8, 0xf3, 0xcf], "blsr rax, rdi");
+        test(&[0xf3, 0x48, 0x0f, 0xbc, 0xc7], "tzcnt rax, rdi");
+        test(&[0xf3, 0x0f, 0xbd, 0xc7], "lzcnt eax, edi");
+        test(&[0xc4, 0xe2, 0xc2, 0xf5, 0xc6], "pext rax, rdi, rsi");
+        test(&[0xc4, 0xe2, 0xc3, 0xf5, 0xc6], "pdep rax, rdi, rsi");
+    }
+
+    #[test]
+    fn decode_environment() {
+        test(&[0x0f, 0x31], "rdtsc");
+        test(&[0x0f, 0xa2], "cpuid");
+        test(&[0x48, 0x0f, 0xc7, 0xf0], "rdrand rax");
+        test(&[0x0f, 0xc7, 0xf1], "rdrand ecx");
+    }
+
+    #[test]
+    fn decode_string() {
+        test(&[0xf3, 0xa4], "rep movs byte ptr [rdi], byte ptr [rsi]");
+        test(&[0xf3, 0x48, 0xab], "rep stos qword ptr [rdi], rax");
+        test(&[0xf3, 0xa6], "repe cmps byte ptr [rsi], byte ptr [rdi]");
+        test(&[0xf2, 0xae], "repne scas al, byte ptr [rdi]");
+        test(&[0xa5], "movs dword ptr [rdi], dword ptr [rsi]");
+    }
+
+    #[test]
+    fn decode_vector() {
+        test(&[0x66, 0x0f, 0xef, 0xc0], "pxor xmm0, xmm0");
+        test(&[0xf3, 0x0f, 0x6f, 0x06], "movdqu xmm0, xmmword ptr [rsi]");
+        test(&[0x0f, 0x29, 0x45, 0xf0], "movaps xmmword ptr [rbp-0x10], xmm0");
+        test(&[0x66, 0x45, 0x0f, 0xfe, 0xc1], "paddd xmm8, xmm9");
+        test(&[0x66, 0x0f, 0x74, 0xc1], "pcmpeqb xmm0, xmm1");
+        test(&[0x66, 0x0f, 0xd7, 0xc0], "pmovmskb eax, xmm0");
+        test(&[0x66, 0x0f, 0x6e, 0xc7], "movd xmm0, edi");
+        test(&[0x66, 0x0f, 0x38, 0x00, 0xc1], "pshufb xmm0, xmm1");
+
+        // VEX-encoded
+        test(&[0xc5, 0xf9, 0x6f, 0x07], "vmovdqa xmm0, xmmword ptr [rdi]");
+        test(&[0xc5, 0xfd, 0x74, 0x0f], "vpcmpeqb ymm1, ymm0, ymmword ptr [rdi]");
+        test(&[0xc5, 0xf5, 0xef, 0xc2], "vpxor ymm0, ymm1, ymm2");
+        test(&[0xc4, 0xe2, 0x7d, 0x00, 0xc1], "vpshufb ymm0, ymm0, ymm1");
+    }
+
+    #[test]
+    fn decode_float() {
+        test(&[0xf3, 0x0f, 0x10, 0x45, 0xfc], "movss xmm0, dword ptr [rbp-0x4]");
+        test(&[0xf2, 0x0f, 0x11, 0x45, 0xf8], "movsd qword ptr [rbp-0x8], xmm0");
+        test(&[0xf3, 0x0f, 0x58, 0xc1], "addss xmm0, xmm1");
+        test(&[0xf2, 0x0f, 0x59, 0x45, 0xf8], "mulsd xmm0, qword ptr [rbp-0x8]");
+        test(&[0x0f, 0x2e, 0xc1], "ucomiss xmm0, xmm1");
+        test(&[0xf3, 0x0f, 0x2c, 0xc0], "cvttss2si eax, xmm0");
+        test(&[0xf2, 0x48, 0x0f, 0x2a, 0xc7], "cvtsi2sd xmm0, rdi");
+        test(&[0x0f, 0x57, 0xc0], "xorps xmm0, xmm0");
+        test(&[0x77, 0x05], "ja +0x5");
+
+        // x87
+        test(&[0xd9, 0x45, 0xfc], "fld dword ptr [rbp-0x4]");
+        test(&[0xdc, 0x4d, 0xf0], "fmul qword ptr [rbp-0x10]");
+        test(&[0xdd, 0x5d, 0xf8], "fstp qword ptr [rbp-0x8]");
+        assert!(Instruction::decode(&[0xd9, 0xc0]).is_err());
     }
 
     #[test]
@@ -649,6 +1383,8 @@ mod tests {
         test(&[0x0f, 0xb6, 0xc0], "movzx eax, al");
         test(&[0x5d], "pop rbp");
         test(&[0xc3], "ret");
+        test(&[0x48, 0xcf], "iretq");
+        test(&[0xcf], "iret");
 
         test(&[0x48, 0x89, 0x7d, 0xf8], "mov qword ptr [rbp-0x8], rdi");
         test(&[0x48, 0x8b, 0x45, 0xf8], "mov rax, qword ptr [rbp-0x8]");
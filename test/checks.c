@@ -0,0 +1,26 @@
+#define LIB_IO
+#include "lib.h"
+
+// Stand in for the library function and the handlers of failed runtime
+// checks, which are recognized by their names.
+char* getenv(const char* name) {
+    return 0;
+}
+
+void __stack_chk_fail() {
+    while (1) {}
+}
+
+void __ubsan_handle_add_overflow(void* data, void* lhs, void* rhs) {}
+
+// Calls the handlers like the checks inserted by the compiler do.
+void main() {
+    char* level = getenv("LEVEL");
+    if (level[0] > 'x') {
+        __ubsan_handle_add_overflow(0, 0, 0);
+    }
+    if (level[1] > 'y') {
+        __stack_chk_fail();
+    }
+    write_one_byte(level[0] + 1);
+}
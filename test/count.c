@@ -0,0 +1,8 @@
+#define LIB_IO
+#include "lib.h"
+
+void main() {
+    for (int i = 0; i < 3; i++) {
+        write_one_byte('a');
+    }
+}
@@ -0,0 +1,14 @@
+#define LIB_IO
+#include "lib.h"
+
+// Stands in for the handler of a changed canary, which is recognized by its
+// name.
+void __stack_chk_fail() {
+    while (1) {}
+}
+
+void main() {
+    char buffer[8];
+    buffer[0] = read_one_byte();
+    write_one_byte(buffer[0]);
+}
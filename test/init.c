@@ -0,0 +1,23 @@
+#define LIB_IO
+#include "lib.h"
+
+// Constructors run by ascending and destructors by descending priority.
+__attribute__((constructor(101))) void first() {
+    write_one_byte('a');
+}
+
+__attribute__((constructor(102))) void second() {
+    write_one_byte('b');
+}
+
+__attribute__((destructor(101))) void last() {
+    write_one_byte('z');
+}
+
+__attribute__((destructor(102))) void early() {
+    write_one_byte('y');
+}
+
+void main() {
+    write_one_byte('m');
+}
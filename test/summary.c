@@ -0,0 +1,20 @@
+#define LIB_IO
+#include "lib.h"
+
+int total;
+
+// Adds positive values to the total and returns the doubled value, while
+// the weight is not used at all.
+int accumulate(int value, int weight) {
+    if (value > 0) {
+        total += value;
+    }
+    return value * 2;
+}
+
+void main() {
+    char value = read_one_byte();
+    char weight = read_one_byte();
+    int doubled = accumulate(value, weight);
+    write_one_byte(doubled + total);
+}
@@ -0,0 +1,16 @@
+#define LIB_IO
+#include "lib.h"
+
+// Compiles into a jump through a table in which 'd' leads to the default.
+void main() {
+    char result;
+    switch (read_one_byte()) {
+        case 'a': result = '1'; break;
+        case 'b': result = '7'; break;
+        case 'c': result = '3'; break;
+        case 'e': result = '9'; break;
+        case 'f': result = '4'; break;
+        default: result = '0'; break;
+    }
+    write_one_byte(result);
+}
@@ -0,0 +1,26 @@
+#define LIB_IO
+#include "lib.h"
+
+char shared;
+int ready;
+
+// Stands in for the library function, which is recognized by its name.
+int pthread_create(void* thread, void* attr, void* (*routine)(void*), void* arg) {
+    return 0;
+}
+
+void* worker(void* arg) {
+    if (__atomic_fetch_add(&ready, 0, __ATOMIC_SEQ_CST)) {
+        write_one_byte(shared);
+    }
+    return 0;
+}
+
+void main() {
+    long thread;
+    shared = read_one_byte();
+    pthread_create(&thread, 0, worker, 0);
+    shared = read_one_byte();
+    asm("mfence");
+    __atomic_store_n(&ready, 1, __ATOMIC_SEQ_CST);
+}
@@ -0,0 +1,38 @@
+#define LIB_IO
+#include "lib.h"
+
+// Stand in for the unwinder, which is not linked without the standard library.
+void _Unwind_Resume(void* exception) {}
+int __gcc_personality_v0() { return 0; }
+
+// Not analyzed across calls, so calling it may throw.
+__attribute__((noipa)) void emit(char x) {
+    write_one_byte(x);
+}
+
+void release(char* x) {
+    write_one_byte(*x);
+}
+
+void handler(int signal) {
+    write_one_byte('s');
+}
+
+// Registers the handler with the kernel's signal action structure.
+void install() {
+    void* action[4] = { handler, 0, 0, 0 };
+    asm("movq $10, %%rdi;"
+        "movq %0, %%rsi;"
+        "movq $0, %%rdx;"
+        "movq $8, %%r10;"
+        "movq $13, %%rax;"
+        "syscall;"
+        :: "r"(action) : "rdi", "rsi", "rdx", "r10", "rax"
+    );
+}
+
+void main() {
+    install();
+    char x __attribute__((cleanup(release))) = read_one_byte();
+    emit(x + 1);
+}
@@ -0,0 +1,13 @@
+#define LIB_IO
+#include "lib.h"
+
+// Compiled with debugging information, so that the conditions can name the
+// variable instead of the byte read into it.
+void main() {
+    char len = read_one_byte();
+    if (len < 64) {
+        write_one_byte('s');
+    } else {
+        write_one_byte('l');
+    }
+}